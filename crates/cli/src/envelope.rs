@@ -0,0 +1,394 @@
+//! `envelope`: decoding wire captures for debugging framing
+//!
+//! Bytes are read leniently, so a damaged capture still shows as much as
+//! can be made of it: the header field by field, whether the checksum
+//! holds, the payload as JSON and, for error envelopes, the error in
+//! whichever of the forms guests and hosts send it.
+
+use aingle_wasmer_codec::compute_checksum_with;
+use aingle_wasmer_common::{
+    ChecksumKind, Compatibility, EnvelopeFlags, EnvelopeHeader, ProtocolCompat, WasmError,
+    WasmErrorInner, FIXED_ERROR_MARKER, MAGIC,
+};
+use aingle_wasmer_host::{set_redacted_fields, transcode_msgpack_to_json, MemoryDump};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use clap::Args;
+use std::fmt::{self, Write};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Bytes shown of payloads and inputs that are dumped as hex
+const HEXDUMP_LIMIT: usize = 256;
+
+#[derive(Args)]
+pub struct EnvelopeArgs {
+    #[command(flatten)]
+    input: Input,
+
+    /// Show the values of fields like `password` or `secret`, which are
+    /// redacted by default
+    #[arg(long)]
+    no_redact: bool,
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct Input {
+    /// Wire bytes in hex; whitespace, `:`, `,` and `0x` prefixes are ignored
+    #[arg(long, value_name = "HEX")]
+    hex: Option<String>,
+    /// Wire bytes in base64
+    #[arg(long, value_name = "BASE64")]
+    base64: Option<String>,
+    /// File holding the raw wire bytes, `-` for stdin
+    #[arg(long, value_name = "PATH")]
+    file: Option<PathBuf>,
+}
+
+impl Input {
+    fn read(&self) -> Result<Vec<u8>, String> {
+        match (&self.hex, &self.base64, &self.file) {
+            (Some(hex), _, _) => parse_hex(hex),
+            (_, Some(text), _) => STANDARD
+                .decode(text.trim())
+                .map_err(|e| format!("invalid base64: {}", e)),
+            (_, _, Some(path)) if path == Path::new("-") => {
+                let mut bytes = Vec::new();
+                std::io::stdin()
+                    .read_to_end(&mut bytes)
+                    .map_err(|e| format!("Failed to read stdin: {}", e))?;
+                Ok(bytes)
+            }
+            (_, _, Some(path)) => {
+                std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+            }
+            (None, None, None) => unreachable!("clap requires an input"),
+        }
+    }
+}
+
+pub fn run(args: EnvelopeArgs) -> Result<ExitCode, String> {
+    let bytes = args.input.read()?;
+    if args.no_redact {
+        set_redacted_fields(Vec::<String>::new());
+    }
+
+    let inspection = inspect(&bytes);
+    print!("{}", inspection.report);
+    Ok(if inspection.valid {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// What was made of some wire bytes
+pub struct Inspection {
+    /// One `label  value` line per finding, followed by decoded values
+    pub report: String,
+    /// Whether the bytes start with a supported envelope whose checksum
+    /// holds
+    pub valid: bool,
+}
+
+/// Inspect `bytes` as captured off the wire
+pub fn inspect(bytes: &[u8]) -> Inspection {
+    let mut report = Report::default();
+    let valid = report.envelope(bytes);
+    Inspection {
+        report: report.0,
+        valid,
+    }
+}
+
+#[derive(Default)]
+struct Report(String);
+
+impl Report {
+    fn line(&mut self, label: &str, value: impl fmt::Display) {
+        let _ = writeln!(self.0, "{:<12} {}", label, value);
+    }
+
+    fn text(&mut self, text: &str) {
+        self.0.push_str(text);
+        if !text.ends_with('\n') {
+            self.0.push('\n');
+        }
+    }
+
+    fn hexdump(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        let shown = &bytes[..bytes.len().min(HEXDUMP_LIMIT)];
+        self.text(&MemoryDump::new(0, shown.to_vec()).hexdump());
+        if bytes.len() > shown.len() {
+            self.text(&format!("... {} more bytes", bytes.len() - shown.len()));
+        }
+    }
+
+    fn envelope(&mut self, bytes: &[u8]) -> bool {
+        self.line("input", format_args!("{} bytes", bytes.len()));
+
+        let Some(header) = bytes.get(..EnvelopeHeader::SIZE) else {
+            let reason = format!("shorter than the {} byte header", EnvelopeHeader::SIZE);
+            return self.not_envelope(bytes, &reason);
+        };
+        let header = EnvelopeHeader::from_bytes(header.try_into().expect("header sized"));
+        let info = header.info();
+        if info.magic != MAGIC {
+            let reason = format!("magic {:#06x}, expected {:#06x}", info.magic, MAGIC);
+            return self.not_envelope(bytes, &reason);
+        }
+
+        let mut valid = true;
+        self.line("magic", format_args!("{:#06x}", info.magic));
+        let compatibility = match ProtocolCompat::check_current(info.version) {
+            Compatibility::Full => "current",
+            Compatibility::ReadOnly => "older, still read",
+            Compatibility::Incompatible => {
+                valid = false;
+                "UNSUPPORTED"
+            }
+        };
+        self.line(
+            "version",
+            format_args!("{} ({})", info.version, compatibility),
+        );
+        self.line(
+            "flags",
+            format_args!("{:#04x} {}", info.flags.bits(), info.flags),
+        );
+        self.line("payload_len", info.payload_len);
+
+        let available = bytes.len() - EnvelopeHeader::SIZE;
+        let payload_end = match header.envelope_len() {
+            Some(end) if end <= bytes.len() => end,
+            _ => {
+                self.line(
+                    "checksum",
+                    format_args!(
+                        "{:#010x}, NOT VERIFIED: only {} payload bytes follow the header",
+                        info.checksum, available
+                    ),
+                );
+                self.line("payload", "truncated");
+                self.hexdump(&bytes[EnvelopeHeader::SIZE..]);
+                return false;
+            }
+        };
+
+        // The header does not name the algorithm, so both are tried
+        let payload = &bytes[EnvelopeHeader::SIZE..payload_end];
+        let crc32 = compute_checksum_with(ChecksumKind::Crc32, payload);
+        let crc32c = compute_checksum_with(ChecksumKind::Crc32c, payload);
+        if crc32 == info.checksum {
+            self.line(
+                "checksum",
+                format_args!("{:#010x} crc32, ok", info.checksum),
+            );
+        } else if crc32c == info.checksum {
+            self.line(
+                "checksum",
+                format_args!("{:#010x} crc32c, ok", info.checksum),
+            );
+        } else {
+            valid = false;
+            self.line(
+                "checksum",
+                format_args!(
+                    "{:#010x}, MISMATCH: the payload has {:#010x} as crc32, {:#010x} as crc32c",
+                    info.checksum, crc32, crc32c
+                ),
+            );
+        }
+        if payload_end < bytes.len() {
+            self.line(
+                "trailing",
+                format_args!(
+                    "{} bytes after the envelope, ignored by decoders",
+                    bytes.len() - payload_end
+                ),
+            );
+        }
+
+        self.payload(&header, payload);
+        if header.is_error() {
+            self.error(payload);
+        }
+        valid
+    }
+
+    fn not_envelope(&mut self, bytes: &[u8], reason: &str) -> bool {
+        if let Some(json) = msgpack_json(bytes) {
+            self.text("not an envelope: no magic, but the input is raw MessagePack");
+            self.text(&json);
+        } else if let Some(inner) = fixed_error(bytes) {
+            self.text("not an envelope: the input is a fixed-layout error");
+            self.line("error", WasmError::GuestStructured(inner));
+        } else {
+            self.text(&format!("not an envelope: {}", reason));
+            self.hexdump(bytes);
+        }
+        false
+    }
+
+    fn payload(&mut self, header: &EnvelopeHeader, payload: &[u8]) {
+        let flags = header.flags_set();
+        if payload.is_empty() {
+            self.line("payload", "empty");
+        } else if flags.contains(EnvelopeFlags::Compressed) {
+            self.line("payload", "compressed, not decoded");
+            self.hexdump(payload);
+        } else if flags.contains(EnvelopeFlags::Encrypted) {
+            self.line("payload", "encrypted, not decoded");
+            self.hexdump(payload);
+        } else if let Some(json) = msgpack_json(payload) {
+            self.line("payload", "MessagePack");
+            self.text(&json);
+        } else if let Ok(text) = std::str::from_utf8(payload) {
+            self.line("payload", "UTF-8 text, not MessagePack");
+            self.text(&format!("{:?}", text));
+        } else {
+            self.line("payload", "binary, not MessagePack");
+            self.hexdump(payload);
+        }
+    }
+
+    /// Decode an error payload in the forms [`guest_error_message`] reads,
+    /// and the `WasmError` host functions send
+    ///
+    /// [`guest_error_message`]: aingle_wasmer_host::guest_error_message
+    fn error(&mut self, payload: &[u8]) {
+        #[derive(serde::Deserialize)]
+        struct SerializableError {
+            error_type: String,
+            message: String,
+        }
+
+        if let Some(inner) = fixed_error(payload) {
+            self.line(
+                "error",
+                format_args!("fixed layout, {}", WasmError::GuestStructured(inner)),
+            );
+        } else if let Ok(error) = rmp_serde::from_slice::<WasmError>(payload) {
+            self.line("error", format_args!("WasmError, {}", error));
+        } else if let Ok(error) = rmp_serde::from_slice::<SerializableError>(payload) {
+            self.line(
+                "error",
+                format_args!(
+                    "error_type {}, message {:?}",
+                    error.error_type, error.message
+                ),
+            );
+        } else if let Ok(text) = std::str::from_utf8(payload) {
+            self.line("error", format_args!("plain text, {:?}", text));
+        } else {
+            self.line("error", "UNDECODABLE");
+        }
+    }
+}
+
+/// `bytes` as JSON if they hold exactly one MessagePack value
+fn msgpack_json(bytes: &[u8]) -> Option<String> {
+    match bytes.is_empty() {
+        true => None,
+        false => transcode_msgpack_to_json(bytes).ok(),
+    }
+}
+
+fn fixed_error(bytes: &[u8]) -> Option<WasmErrorInner> {
+    match bytes.first() {
+        Some(&FIXED_ERROR_MARKER) => WasmErrorInner::decode_fixed(bytes),
+        _ => None,
+    }
+}
+
+fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let mut digits = String::with_capacity(text.len());
+    for token in text.split(|c: char| c.is_whitespace() || c == ':' || c == ',') {
+        digits.push_str(token.strip_prefix("0x").unwrap_or(token));
+    }
+    if !digits.len().is_multiple_of(2) || !digits.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!(
+            "invalid hex: expected pairs of hex digits, got {:?}",
+            text
+        ));
+    }
+
+    Ok(digits
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).expect("ascii");
+            u8::from_str_radix(pair, 16).expect("hex digits")
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aingle_wasmer_codec::encode_with_envelope;
+
+    fn envelope(payload: &[u8], flags: u8) -> Vec<u8> {
+        let mut buffer = vec![0u8; EnvelopeHeader::SIZE + payload.len()];
+        encode_with_envelope(payload, flags, &mut buffer).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex("4149 01"), Ok(vec![0x41, 0x49, 0x01]));
+        assert_eq!(parse_hex("0x41:0x49,\n0A"), Ok(vec![0x41, 0x49, 0x0a]));
+        assert_eq!(parse_hex("0x4149"), Ok(vec![0x41, 0x49]));
+        assert_eq!(parse_hex(""), Ok(vec![]));
+
+        assert!(parse_hex("414").is_err());
+        assert!(parse_hex("4g").is_err());
+        assert!(parse_hex("+1").is_err());
+    }
+
+    #[test]
+    fn test_inspect_valid_envelope() {
+        let payload = rmp_serde::to_vec_named(&("hello", 7)).unwrap();
+        let inspection = inspect(&envelope(&payload, 0));
+
+        assert!(inspection.valid, "{}", inspection.report);
+        assert!(inspection.report.contains("crc32, ok"));
+        assert!(inspection.report.contains("[\"hello\",7]"));
+    }
+
+    #[test]
+    fn test_inspect_damage() {
+        let mut wire = envelope(b"\x91\x01", 0);
+        *wire.last_mut().unwrap() ^= 1;
+        let inspection = inspect(&wire);
+        assert!(!inspection.valid);
+        assert!(inspection.report.contains(", MISMATCH: the payload has"));
+
+        let inspection = inspect(&envelope(b"\x91\x01", 0)[..13]);
+        assert!(!inspection.valid);
+        assert!(inspection.report.contains("only 1 payload bytes"));
+
+        let inspection = inspect(&[]);
+        assert!(!inspection.valid);
+        assert!(inspection
+            .report
+            .contains("shorter than the 12 byte header"));
+    }
+
+    #[test]
+    fn test_inspect_recognizes_bare_payloads() {
+        let inspection = inspect(&rmp_serde::to_vec_named(&[1, 2, 3]).unwrap());
+        assert!(!inspection.valid);
+        assert!(inspection.report.contains("raw MessagePack\n[1,2,3]\n"));
+
+        let error = WasmErrorInner::new(aingle_wasmer_common::ErrorKind::NotFound, "gone");
+        let inspection = inspect(&error.encode_fixed());
+        assert!(inspection.report.contains("fixed-layout error"));
+        assert!(inspection.report.contains("[NotFound] gone"));
+    }
+}
@@ -0,0 +1,416 @@
+//! # AIngle WASM CLI
+//!
+//! Inspects and pre-populates the on-disk module cache of a conductor, so
+//! deployments can warm it before starting and operators can look into it
+//! without writing code, and decodes envelopes captured off the wire.
+//!
+//! ```text
+//! aingle-wasmer-cli --cache-dir <DIR> cache ls
+//! aingle-wasmer-cli --cache-dir <DIR> cache verify
+//! aingle-wasmer-cli --cache-dir <DIR> cache add <WASM>... [--key <HEX>]
+//! aingle-wasmer-cli --cache-dir <DIR> cache rm <KEY>...
+//! aingle-wasmer-cli --cache-dir <DIR> cache gc [--max-age <AGE>] [--max-bytes <SIZE>]
+//! aingle-wasmer-cli envelope (--hex <HEX> | --base64 <BASE64> | --file <PATH>)
+//! ```
+//!
+//! The cache directory can also be given as `AINGLE_WASMER_CACHE_DIR`. The
+//! `envelope` subcommand comes with the default `envelope` feature.
+
+#[cfg(not(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod")))]
+compile_error!("aingle-wasmer-cli needs the wasmer_sys_dev or wasmer_sys_prod feature");
+
+#[cfg(feature = "envelope")]
+mod envelope;
+
+use aingle_wasmer_host::{EngineConfig, HostError, WasmEngine};
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime};
+
+#[derive(Parser)]
+#[command(
+    name = "aingle-wasmer-cli",
+    version,
+    about = "Inspect the AIngle WASM module cache and wire format"
+)]
+struct Cli {
+    /// Module cache directory, as set in `EngineConfig::cache_path`;
+    /// required by `cache`
+    #[arg(long, env = "AINGLE_WASMER_CACHE_DIR", value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Manage the on-disk module cache
+    #[command(subcommand)]
+    Cache(CacheCommand),
+    /// Decode an envelope captured off the wire
+    ///
+    /// Prints the header, whether the checksum holds, the payload as JSON
+    /// and the error of error envelopes. Exits with 1 unless the input is
+    /// a supported envelope with a matching checksum.
+    #[cfg(feature = "envelope")]
+    Envelope(envelope::EnvelopeArgs),
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// List cached modules with their size, last use and compiler
+    Ls,
+    /// Check that every cached module loads with this build
+    Verify,
+    /// Compile modules into the cache
+    Add {
+        /// `.wasm` files, or directories whose `.wasm` files are added
+        #[arg(required = true, value_name = "WASM")]
+        paths: Vec<PathBuf>,
+        /// Key to cache a single module under, by default the BLAKE3 hash
+        /// of its bytes as in the C ABI
+        #[arg(long, value_name = "HEX", value_parser = parse_key)]
+        key: Option<[u8; 32]>,
+    },
+    /// Remove modules from the cache
+    Rm {
+        /// Keys of the modules, in hex as listed by `cache ls`
+        #[arg(required = true, value_name = "KEY", value_parser = parse_key)]
+        keys: Vec<[u8; 32]>,
+    },
+    /// Delete modules unused for too long, then the least recently used
+    /// ones until the cache fits its size budget
+    Gc(GcArgs),
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = true)]
+struct GcArgs {
+    /// Delete modules unused for longer than this, as in `90s`, `30m`,
+    /// `12h` or `7d`
+    #[arg(long, value_name = "AGE", value_parser = parse_age)]
+    max_age: Option<Duration>,
+    /// Size budget of the cache, as in `500000`, `64K`, `512M` or `2G`
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_bytes: Option<u64>,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode, Box<dyn Error>> {
+    match cli.command {
+        Command::Cache(command) => {
+            let Some(cache_dir) = cli.cache_dir else {
+                Cli::command()
+                    .error(
+                        ErrorKind::MissingRequiredArgument,
+                        "cache commands need --cache-dir <DIR> or AINGLE_WASMER_CACHE_DIR",
+                    )
+                    .exit()
+            };
+            Ok(cache(cache_dir, command)?)
+        }
+        #[cfg(feature = "envelope")]
+        Command::Envelope(args) => Ok(envelope::run(args)?),
+    }
+}
+
+fn cache(cache_dir: PathBuf, command: CacheCommand) -> Result<ExitCode, HostError> {
+    let engine = WasmEngine::new(EngineConfig {
+        cache_path: Some(cache_dir),
+        ..Default::default()
+    })?;
+
+    match command {
+        CacheCommand::Ls => list(&engine),
+        CacheCommand::Verify => verify(&engine),
+        CacheCommand::Add { paths, key } => add(&engine, &paths, key),
+        CacheCommand::Rm { keys } => remove(&engine, &keys),
+        CacheCommand::Gc(args) => gc(&engine, args),
+    }
+}
+
+fn list(engine: &WasmEngine) -> Result<ExitCode, HostError> {
+    let entries = engine.module_cache().disk_entries()?;
+    let now = SystemTime::now();
+
+    println!(
+        "{:<64}  {:>10}  {:>9}  COMPILER",
+        "KEY", "SIZE", "LAST USED"
+    );
+    for entry in &entries {
+        let age = now.duration_since(entry.last_used).unwrap_or_default();
+        println!(
+            "{:<64}  {:>10}  {:>9}  {}",
+            hex(&entry.key),
+            entry.size,
+            format!("{} ago", format_age(age)),
+            entry.compiler.as_deref().unwrap_or("unrecorded")
+        );
+    }
+
+    let total: u64 = entries.iter().map(|entry| entry.size).sum();
+    println!("{} modules, {} bytes", entries.len(), total);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn verify(engine: &WasmEngine) -> Result<ExitCode, HostError> {
+    let cache = engine.module_cache();
+    let mut failed = 0;
+
+    let entries = cache.disk_entries()?;
+    for entry in &entries {
+        match cache.verify_artifact(entry) {
+            Ok(()) => println!("ok    {}", hex(&entry.key)),
+            Err(e) => {
+                failed += 1;
+                println!("FAIL  {}: {}", hex(&entry.key), e);
+            }
+        }
+    }
+
+    println!(
+        "{} ok, {} failed, this cache uses {}",
+        entries.len() - failed,
+        failed,
+        cache.compiler()
+    );
+    Ok(if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn add(
+    engine: &WasmEngine,
+    paths: &[PathBuf],
+    key: Option<[u8; 32]>,
+) -> Result<ExitCode, HostError> {
+    let mut files = Vec::new();
+    for path in paths {
+        files.extend(wasm_files(path)?);
+    }
+    if key.is_some() && files.len() != 1 {
+        return Err(HostError::Cache(format!(
+            "--key needs exactly one module, got {}",
+            files.len()
+        )));
+    }
+
+    let cache = engine.module_cache();
+    for file in &files {
+        let wasm = std::fs::read(file)
+            .map_err(|e| HostError::Cache(format!("Failed to read {}: {}", file.display(), e)))?;
+        let key = key.unwrap_or_else(|| *blake3::hash(&wasm).as_bytes());
+        let artifact = cache
+            .module_cache_file_path(&key)
+            .expect("the cache has a path");
+
+        let was_cached = artifact.exists();
+        engine.compile_cached(key, &wasm)?;
+        // Writing the artifact is best effort for the engine, not here
+        if !artifact.exists() {
+            return Err(HostError::Cache(format!(
+                "Compiled {} but could not write {}",
+                file.display(),
+                artifact.display()
+            )));
+        }
+
+        let status = if was_cached { "cached" } else { "compiled" };
+        println!("{}  {:<8}  {}", hex(&key), status, file.display());
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn remove(engine: &WasmEngine, keys: &[[u8; 32]]) -> Result<ExitCode, HostError> {
+    let mut missing = 0;
+    for key in keys {
+        if engine.module_cache().remove(key)? {
+            println!("removed     {}", hex(key));
+        } else {
+            missing += 1;
+            println!("not cached  {}", hex(key));
+        }
+    }
+
+    Ok(if missing == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn gc(engine: &WasmEngine, args: GcArgs) -> Result<ExitCode, HostError> {
+    let report = engine.module_cache().gc_disk(
+        args.max_age.unwrap_or(Duration::MAX),
+        args.max_bytes.unwrap_or(u64::MAX),
+    )?;
+    println!(
+        "removed {} files, {} bytes",
+        report.files_removed, report.bytes_removed
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `path` itself, or the `.wasm` files directly inside it, in name order
+fn wasm_files(path: &Path) -> Result<Vec<PathBuf>, HostError> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let read_error =
+        |e: std::io::Error| HostError::Cache(format!("Failed to read {}: {}", path.display(), e));
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path).map_err(read_error)? {
+        let file = entry.map_err(read_error)?.path();
+        if file.is_file() && file.extension().is_some_and(|ext| ext == "wasm") {
+            files.push(file);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_key(text: &str) -> Result<[u8; 32], String> {
+    let invalid = || format!("expected 64 hex digits, got {:?}", text);
+    if text.len() != 64 || !text.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
+    let mut key = [0u8; 32];
+    for (byte, pair) in key.iter_mut().zip(text.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+    }
+    Ok(key)
+}
+
+/// Split `text` into a number and the suffix after it
+fn split_number(text: &str) -> Result<(u64, &str), String> {
+    let digits = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let number = text[..digits]
+        .parse()
+        .map_err(|_| format!("expected a number, got {:?}", text))?;
+    Ok((number, &text[digits..]))
+}
+
+fn parse_age(text: &str) -> Result<Duration, String> {
+    let (number, unit) = split_number(text)?;
+    let seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("unknown unit {:?}, expected s, m, h or d", unit)),
+    };
+    number
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("{:?} is too long", text))
+}
+
+fn parse_size(text: &str) -> Result<u64, String> {
+    let (number, unit) = split_number(text)?;
+    let shift = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" | "KB" | "KIB" => 10,
+        "M" | "MB" | "MIB" => 20,
+        "G" | "GB" | "GIB" => 30,
+        _ => return Err(format!("unknown unit {:?}, expected K, M or G", unit)),
+    };
+    number
+        .checked_mul(1 << shift)
+        .ok_or_else(|| format!("{:?} is too large", text))
+}
+
+fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m", seconds / 60),
+        3600..=86399 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_key() {
+        let text = "00ff".repeat(16);
+        let key = parse_key(&text).unwrap();
+        assert_eq!(key[0], 0x00);
+        assert_eq!(key[1], 0xff);
+        assert_eq!(hex(&key), text);
+        assert_eq!(parse_key(&"AB".repeat(32)).unwrap(), [0xab; 32]);
+
+        assert!(parse_key(&"00".repeat(31)).is_err());
+        assert!(parse_key(&"0g".repeat(32)).is_err());
+        assert!(parse_key(&format!("+{}", "0".repeat(63))).is_err());
+        assert!(parse_key(&"é".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_age("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_age("30m"), Ok(Duration::from_secs(1800)));
+        assert_eq!(parse_age("12h"), Ok(Duration::from_secs(43200)));
+        assert_eq!(parse_age("7d"), Ok(Duration::from_secs(604800)));
+
+        assert!(parse_age("").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("7w").is_err());
+        assert!(parse_age("-1s").is_err());
+        assert!(parse_age(&format!("{}d", u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(parse_size("500000"), Ok(500000));
+        assert_eq!(parse_size("64K"), Ok(64 << 10));
+        assert_eq!(parse_size("512m"), Ok(512 << 20));
+        assert_eq!(parse_size("2GiB"), Ok(2 << 30));
+
+        assert!(parse_size("1T").is_err());
+        assert!(parse_size("1.5G").is_err());
+        assert!(parse_size(&format!("{}G", u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(Duration::from_secs(59)), "59s");
+        assert_eq!(format_age(Duration::from_secs(60)), "1m");
+        assert_eq!(format_age(Duration::from_secs(7200)), "2h");
+        assert_eq!(format_age(Duration::from_secs(3 * 86400 + 5)), "3d");
+    }
+}
@@ -0,0 +1,213 @@
+//! Drives `aingle-wasmer-cli cache` against a temporary cache directory
+
+#![cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::path::Path;
+
+fn cli(cache_dir: &Path) -> Command {
+    let mut command = Command::cargo_bin("aingle-wasmer-cli").unwrap();
+    command
+        .env_remove("AINGLE_WASMER_CACHE_DIR")
+        .arg("--cache-dir")
+        .arg(cache_dir);
+    command
+}
+
+/// A module returning `value`, distinct per value
+fn write_module(path: &Path, value: i32) {
+    let wasm = wat::parse_str(format!(
+        r#"(module (func (export "answer") (result i32) i32.const {}))"#,
+        value
+    ))
+    .unwrap();
+    std::fs::write(path, wasm).unwrap();
+}
+
+fn key_of(path: &Path) -> String {
+    blake3::hash(&std::fs::read(path).unwrap())
+        .to_hex()
+        .to_string()
+}
+
+#[test]
+fn test_add_then_list() {
+    let cache = tempfile::tempdir().unwrap();
+    let modules = tempfile::tempdir().unwrap();
+    let wasm = modules.path().join("answer.wasm");
+    write_module(&wasm, 42);
+    let key = key_of(&wasm);
+
+    cli(cache.path())
+        .args(["cache", "add"])
+        .arg(&wasm)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("{}  compiled", key)));
+    cli(cache.path())
+        .args(["cache", "add"])
+        .arg(&wasm)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("{}  cached", key)));
+
+    cli(cache.path())
+        .args(["cache", "ls"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(&key))
+        .stdout(predicate::str::contains(
+            aingle_wasmer_host::ARTIFACT_COMPILER,
+        ))
+        .stdout(predicate::str::contains("1 modules"));
+}
+
+#[test]
+fn test_add_directory() {
+    let cache = tempfile::tempdir().unwrap();
+    let modules = tempfile::tempdir().unwrap();
+    for value in 0..3 {
+        write_module(&modules.path().join(format!("m{}.wasm", value)), value);
+    }
+    std::fs::write(modules.path().join("notes.txt"), "not a module").unwrap();
+
+    cli(cache.path())
+        .args(["cache", "add"])
+        .arg(modules.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("compiled").count(3));
+    cli(cache.path())
+        .args(["cache", "ls"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3 modules"));
+
+    // A key names one module only
+    cli(cache.path())
+        .args(["cache", "add", "--key", &"11".repeat(32)])
+        .arg(modules.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("exactly one module"));
+}
+
+#[test]
+fn test_add_with_key_then_remove() {
+    let cache = tempfile::tempdir().unwrap();
+    let modules = tempfile::tempdir().unwrap();
+    let wasm = modules.path().join("answer.wasm");
+    write_module(&wasm, 7);
+    let key = "ab".repeat(32);
+
+    cli(cache.path())
+        .args(["cache", "add", "--key", &key])
+        .arg(&wasm)
+        .assert()
+        .success();
+    assert!(cache.path().join("ab").join("ab".repeat(31)).exists());
+
+    cli(cache.path())
+        .args(["cache", "rm", &key])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("removed     {}", key)));
+    cli(cache.path())
+        .args(["cache", "rm", &key])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("not cached"));
+    cli(cache.path())
+        .args(["cache", "ls"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("0 modules"));
+}
+
+#[test]
+fn test_verify_reports_damaged_artifacts() {
+    let cache = tempfile::tempdir().unwrap();
+    let modules = tempfile::tempdir().unwrap();
+    let wasm = modules.path().join("answer.wasm");
+    write_module(&wasm, 1);
+    let key = key_of(&wasm);
+
+    cli(cache.path())
+        .args(["cache", "add"])
+        .arg(&wasm)
+        .assert()
+        .success();
+    cli(cache.path())
+        .args(["cache", "verify"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("ok    {}", key)));
+
+    let artifact = cache.path().join(&key[..2]).join(&key[2..]);
+    let mut bytes = std::fs::read(&artifact).unwrap();
+    bytes.truncate(bytes.len() / 2);
+    std::fs::write(&artifact, bytes).unwrap();
+
+    cli(cache.path())
+        .args(["cache", "verify"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(format!("FAIL  {}", key)))
+        .stdout(predicate::str::contains("0 ok, 1 failed"));
+}
+
+#[test]
+fn test_gc_size_budget() {
+    let cache = tempfile::tempdir().unwrap();
+    let modules = tempfile::tempdir().unwrap();
+    for value in 0..2 {
+        write_module(&modules.path().join(format!("m{}.wasm", value)), value);
+    }
+    cli(cache.path())
+        .args(["cache", "add"])
+        .arg(modules.path())
+        .assert()
+        .success();
+
+    cli(cache.path())
+        .args(["cache", "gc", "--max-age", "7d"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("removed 0 files"));
+    cli(cache.path())
+        .args(["cache", "gc", "--max-bytes", "0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("removed 2 files"));
+    cli(cache.path())
+        .args(["cache", "ls"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("0 modules"));
+
+    // Without a limit there is nothing to collect
+    cli(cache.path()).args(["cache", "gc"]).assert().failure();
+}
+
+#[test]
+fn test_rejects_bad_arguments() {
+    let cache = tempfile::tempdir().unwrap();
+
+    cli(cache.path())
+        .args(["cache", "rm", "abc"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("64 hex digits"));
+    cli(cache.path())
+        .args(["cache", "gc", "--max-age", "7w"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown unit"));
+    cli(cache.path())
+        .args(["cache", "add"])
+        .arg(cache.path().join("missing.wasm"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("missing.wasm"));
+}
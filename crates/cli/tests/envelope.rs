@@ -0,0 +1,173 @@
+//! Snapshots of `aingle-wasmer-cli envelope` over the golden wire vectors
+//!
+//! Each `tests/vectors/<name>.hex` holds a capture in hex after `#` lines
+//! saying what it is, and `<name>.txt` pins what the inspector prints for
+//! it along with the exit status. Run with `SNAPSHOTS=overwrite` to accept
+//! changed output.
+
+#![cfg(all(
+    feature = "envelope",
+    any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod")
+))]
+
+use assert_cmd::Command;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use predicates::prelude::*;
+use std::path::{Path, PathBuf};
+
+fn cli() -> Command {
+    let mut command = Command::cargo_bin("aingle-wasmer-cli").unwrap();
+    command.arg("envelope");
+    command
+}
+
+fn vectors() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/vectors");
+    let mut vectors: Vec<PathBuf> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "hex"))
+        .collect();
+    vectors.sort();
+    vectors
+}
+
+/// The hex of a vector, without its comments
+fn read_hex(vector: &Path) -> String {
+    std::fs::read_to_string(vector)
+        .unwrap()
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn read_bytes(vector: &Path) -> Vec<u8> {
+    read_hex(vector)
+        .split_whitespace()
+        .map(|byte| u8::from_str_radix(byte, 16).unwrap())
+        .collect()
+}
+
+/// What the inspector prints for `vector`, as pinned in its snapshot
+fn inspect(vector: &Path) -> String {
+    let output = cli().arg("--hex").arg(read_hex(vector)).output().unwrap();
+    assert!(output.stderr.is_empty(), "{:?}", output);
+    format!(
+        "{}exit status: {}\n",
+        String::from_utf8(output.stdout).unwrap(),
+        output.status.code().unwrap()
+    )
+}
+
+#[test]
+fn test_golden_vectors() {
+    let overwrite = std::env::var("SNAPSHOTS").is_ok_and(|value| value == "overwrite");
+    let vectors = vectors();
+    assert!(vectors.len() >= 10, "missing golden vectors");
+
+    let mut mismatches = Vec::new();
+    for vector in &vectors {
+        let actual = inspect(vector);
+        let snapshot = vector.with_extension("txt");
+        if overwrite {
+            std::fs::write(&snapshot, &actual).unwrap();
+            continue;
+        }
+
+        let expected = std::fs::read_to_string(&snapshot).unwrap_or_default();
+        if expected != actual {
+            mismatches.push(format!(
+                "{}\n--- expected\n{}--- actual\n{}",
+                snapshot.display(),
+                expected,
+                actual
+            ));
+        }
+    }
+    assert!(
+        mismatches.is_empty(),
+        "{}\nrun with SNAPSHOTS=overwrite to accept the new output",
+        mismatches.join("\n")
+    );
+}
+
+#[test]
+fn test_inputs_agree() {
+    let vector = vectors()
+        .into_iter()
+        .find(|vector| vector.ends_with("error_serialized.hex"))
+        .unwrap();
+    let bytes = read_bytes(&vector);
+    let expected = cli()
+        .arg("--hex")
+        .arg(read_hex(&vector))
+        .output()
+        .unwrap()
+        .stdout;
+    assert!(!expected.is_empty());
+
+    cli()
+        .args(["--base64", &STANDARD.encode(&bytes)])
+        .assert()
+        .success()
+        .stdout(expected.clone());
+
+    let capture = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(capture.path(), &bytes).unwrap();
+    cli()
+        .arg("--file")
+        .arg(capture.path())
+        .assert()
+        .success()
+        .stdout(expected.clone());
+    cli()
+        .args(["--file", "-"])
+        .write_stdin(bytes)
+        .assert()
+        .success()
+        .stdout(expected);
+}
+
+#[test]
+fn test_redaction() {
+    let vector = vectors()
+        .into_iter()
+        .find(|vector| vector.ends_with("msgpack_named.hex"))
+        .unwrap();
+
+    cli()
+        .arg("--hex")
+        .arg(read_hex(&vector))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""password":"[redacted]""#));
+    cli()
+        .arg("--hex")
+        .arg(read_hex(&vector))
+        .arg("--no-redact")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""password":"hunter2""#));
+}
+
+#[test]
+fn test_rejects_bad_input() {
+    cli()
+        .args(["--hex", "414"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid hex"));
+    cli()
+        .args(["--base64", "!!"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid base64"));
+    cli()
+        .args(["--hex", "4149", "--base64", "QUk="])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    cli().assert().failure();
+}
@@ -0,0 +1,24 @@
+//! Benchmark comparing the envelope checksum algorithms
+
+use aingle_wasmer_codec::{compute_checksum_with, ChecksumKind};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+/// CRC32 against CRC32C on a typical large payload
+fn bench_checksum(c: &mut Criterion) {
+    let mut group = c.benchmark_group("checksum");
+    let payload: Vec<u8> = (0..64 * 1024).map(|i| (i * 31 % 251) as u8).collect();
+    group.throughput(Throughput::Bytes(payload.len() as u64));
+
+    for kind in [ChecksumKind::Crc32, ChecksumKind::Crc32c] {
+        group.bench_with_input(
+            BenchmarkId::new(format!("{:?}", kind), payload.len()),
+            &payload,
+            |b, payload| b.iter(|| compute_checksum_with(kind, payload)),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_checksum);
+criterion_main!(benches);
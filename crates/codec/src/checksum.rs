@@ -1,15 +1,107 @@
 //! Checksum computation for data integrity
 
+use aingle_wasmer_common::ChecksumKind;
+
 /// Compute CRC32 checksum of data
 pub fn compute_checksum(data: &[u8]) -> u32 {
     crc32fast::hash(data)
 }
 
+/// Compute the checksum of data with the given algorithm
+pub fn compute_checksum_with(kind: ChecksumKind, data: &[u8]) -> u32 {
+    match kind {
+        ChecksumKind::Crc32 => crc32fast::hash(data),
+        #[cfg(feature = "std")]
+        ChecksumKind::Crc32c => crc32c::crc32c(data),
+        #[cfg(not(feature = "std"))]
+        ChecksumKind::Crc32c => crc32c_table(data),
+    }
+}
+
 /// Verify checksum matches expected value
 pub fn verify_checksum(data: &[u8], expected: u32) -> bool {
     compute_checksum(data) == expected
 }
 
+/// Checksum of data fed in pieces, equal to [`compute_checksum_with`] over
+/// their concatenation
+///
+/// Lets payloads be verified chunk by chunk as they are copied, without
+/// holding them whole.
+#[derive(Clone)]
+pub struct ChecksumHasher(HasherState);
+
+#[derive(Clone)]
+enum HasherState {
+    Crc32(crc32fast::Hasher),
+    Crc32c(u32),
+}
+
+impl ChecksumHasher {
+    /// Start a checksum with the given algorithm
+    pub fn new(kind: ChecksumKind) -> Self {
+        Self(match kind {
+            ChecksumKind::Crc32 => HasherState::Crc32(crc32fast::Hasher::new()),
+            ChecksumKind::Crc32c => HasherState::Crc32c(0),
+        })
+    }
+
+    /// Feed the next piece of data
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.0 {
+            HasherState::Crc32(hasher) => hasher.update(data),
+            #[cfg(feature = "std")]
+            HasherState::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, data),
+            #[cfg(not(feature = "std"))]
+            HasherState::Crc32c(crc) => *crc = crc32c_table_append(*crc, data),
+        }
+    }
+
+    /// The checksum of all the data fed
+    pub fn finalize(self) -> u32 {
+        match self.0 {
+            HasherState::Crc32(hasher) => hasher.finalize(),
+            HasherState::Crc32c(crc) => crc,
+        }
+    }
+}
+
+/// Lookup table of the reflected CRC32C polynomial
+#[cfg(any(not(feature = "std"), test))]
+const CRC32C_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x82f6_3b78
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC32C without hardware support, for `no_std` builds such as guests
+#[cfg(any(not(feature = "std"), test))]
+fn crc32c_table(data: &[u8]) -> u32 {
+    crc32c_table_append(0, data)
+}
+
+/// [`crc32c_table`] of the data `crc` covers followed by `data`
+#[cfg(any(not(feature = "std"), test))]
+fn crc32c_table_append(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |crc, &byte| {
+        CRC32C_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -28,4 +120,53 @@ mod tests {
         let checksum = compute_checksum(&[]);
         assert!(verify_checksum(&[], checksum));
     }
+
+    #[test]
+    fn test_checksum_kinds() {
+        // Check values of the two algorithms
+        let data = b"123456789";
+        assert_eq!(
+            compute_checksum_with(ChecksumKind::Crc32, data),
+            0xcbf4_3926
+        );
+        assert_eq!(
+            compute_checksum_with(ChecksumKind::Crc32c, data),
+            0xe306_9283
+        );
+        assert_eq!(crc32c_table(data), 0xe306_9283);
+
+        let long: [u8; 4099] = core::array::from_fn(|i| i as u8);
+        assert_eq!(
+            crc32c_table(&long),
+            compute_checksum_with(ChecksumKind::Crc32c, &long)
+        );
+        assert_eq!(compute_checksum_with(ChecksumKind::Crc32c, &[]), 0);
+    }
+
+    #[test]
+    fn test_checksum_hasher_matches_one_shot() {
+        let data: [u8; 4099] = core::array::from_fn(|i| (i * 7) as u8);
+        for kind in [ChecksumKind::Crc32, ChecksumKind::Crc32c] {
+            for chunk in [1, 64, 1000, data.len()] {
+                let mut hasher = ChecksumHasher::new(kind);
+                data.chunks(chunk).for_each(|piece| hasher.update(piece));
+                assert_eq!(
+                    hasher.finalize(),
+                    compute_checksum_with(kind, &data),
+                    "{:?} in chunks of {}",
+                    kind,
+                    chunk
+                );
+            }
+            assert_eq!(
+                ChecksumHasher::new(kind).finalize(),
+                compute_checksum_with(kind, &[])
+            );
+        }
+        let (head, tail) = data.split_at(100);
+        assert_eq!(
+            crc32c_table_append(crc32c_table(head), tail),
+            crc32c_table(&data)
+        );
+    }
 }
@@ -1,7 +1,9 @@
 //! Decoding functionality
 
-use crate::checksum::compute_checksum;
-use aingle_wasmer_common::{DeserializeError, EnvelopeError, EnvelopeHeader, WasmError};
+use crate::checksum::compute_checksum_with;
+use aingle_wasmer_common::{
+    ChecksumKind, DeserializeError, EnvelopeError, EnvelopeHeader, EnvelopeHeaderInfo, WasmError,
+};
 
 /// Decoder for WASM messages
 pub struct Decoder<'a> {
@@ -76,10 +78,40 @@ pub struct DecodedEnvelope<'a> {
     pub header: EnvelopeHeader,
     /// The payload bytes (zero-copy reference)
     pub payload: &'a [u8],
+    /// Algorithm the payload checksum was verified with
+    pub checksum: ChecksumKind,
+}
+
+impl DecodedEnvelope<'_> {
+    /// Aligned copy of the header, for logging and diagnostics
+    pub fn header_info(&self) -> EnvelopeHeaderInfo {
+        self.header.info()
+    }
 }
 
 /// Decode an envelope from a buffer
+///
+/// The header does not record the checksum algorithm, so the checksum is
+/// verified as CRC32 and then as CRC32C; a payload matching neither is
+/// reported as [`DeserializeError::ChecksumMismatch`] against its CRC32.
+/// Peers that agreed on an algorithm use [`decode_envelope_with`].
 pub fn decode_envelope(buffer: &[u8]) -> Result<DecodedEnvelope<'_>, WasmError> {
+    decode_envelope_with(ChecksumKind::Crc32, buffer).or_else(|e| match e {
+        WasmError::Deserialize(DeserializeError::ChecksumMismatch { .. }) => {
+            decode_envelope_with(ChecksumKind::Crc32c, buffer).map_err(|_| e)
+        }
+        e => Err(e),
+    })
+}
+
+/// Decode an envelope from a buffer, its checksum verified with `kind`
+///
+/// An envelope checksummed otherwise, by a peer that did not agree on
+/// `kind`, is reported as [`DeserializeError::ChecksumMismatch`].
+pub fn decode_envelope_with(
+    kind: ChecksumKind,
+    buffer: &[u8],
+) -> Result<DecodedEnvelope<'_>, WasmError> {
     if buffer.len() < EnvelopeHeader::SIZE {
         return Err(WasmError::Deserialize(DeserializeError::UnexpectedEof));
     }
@@ -91,7 +123,7 @@ pub fn decode_envelope(buffer: &[u8]) -> Result<DecodedEnvelope<'_>, WasmError>
     let header = EnvelopeHeader::from_bytes(&header_bytes);
 
     // Validate header
-    header.validate().map_err(|e| {
+    header.validate(None).map_err(|e| {
         WasmError::Deserialize(match e {
             EnvelopeError::InvalidMagic(_) => DeserializeError::InvalidFormat,
             EnvelopeError::UnsupportedVersion(_) => DeserializeError::InvalidFormat,
@@ -99,22 +131,28 @@ pub fn decode_envelope(buffer: &[u8]) -> Result<DecodedEnvelope<'_>, WasmError>
         })
     })?;
 
-    let payload_start = EnvelopeHeader::SIZE;
-    let payload_end = payload_start + header.payload_len as usize;
+    // A length that does not even fit `usize` cannot fit the buffer either
+    let payload_end = match header.envelope_len() {
+        Some(end) if end <= buffer.len() => end,
+        _ => return Err(WasmError::Deserialize(DeserializeError::UnexpectedEof)),
+    };
 
-    if buffer.len() < payload_end {
-        return Err(WasmError::Deserialize(DeserializeError::UnexpectedEof));
-    }
-
-    let payload = &buffer[payload_start..payload_end];
+    let payload = &buffer[EnvelopeHeader::SIZE..payload_end];
 
     // Verify checksum
-    let actual_checksum = compute_checksum(payload);
-    if actual_checksum != header.checksum {
-        return Err(WasmError::Deserialize(DeserializeError::InvalidFormat));
+    let actual = compute_checksum_with(kind, payload);
+    if actual != header.checksum {
+        return Err(WasmError::Deserialize(DeserializeError::ChecksumMismatch {
+            expected: header.checksum,
+            actual,
+        }));
     }
 
-    Ok(DecodedEnvelope { header, payload })
+    Ok(DecodedEnvelope {
+        header,
+        payload,
+        checksum: kind,
+    })
 }
 
 /// Decode payload directly (without envelope) - for compatibility
@@ -125,7 +163,9 @@ pub fn decode_raw(buffer: &[u8]) -> &[u8] {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::encode::encode_with_envelope;
+    use crate::checksum::compute_checksum;
+    use crate::encode::{encode_envelope_with, encode_with_envelope};
+    use aingle_wasmer_common::{EnvelopeFlagSet, EnvelopeFlags};
 
     #[test]
     fn test_decoder_basic() {
@@ -148,6 +188,11 @@ mod tests {
 
         assert_eq!(decoded.payload, payload);
         assert!(!decoded.header.is_error());
+
+        let info = decoded.header_info();
+        assert_eq!(info.payload_len as usize, payload.len());
+        assert_eq!(info.checksum, compute_checksum(payload));
+        assert!(info.flags.is_empty());
     }
 
     #[test]
@@ -161,6 +206,93 @@ mod tests {
         buffer[EnvelopeHeader::SIZE] ^= 0xFF;
 
         let result = decode_envelope(&buffer[..len]);
-        assert!(result.is_err());
+        assert!(matches!(
+            result,
+            Err(WasmError::Deserialize(
+                DeserializeError::ChecksumMismatch { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_crc32c_envelopes() {
+        let mut buffer = [0u8; 64];
+        let flags = EnvelopeFlags::IsError as u8;
+        let len =
+            encode_envelope_with(ChecksumKind::Crc32c, b"castagnoli", flags, &mut buffer).unwrap();
+
+        let decoded = decode_envelope_with(ChecksumKind::Crc32c, &buffer[..len]).unwrap();
+        assert_eq!(decoded.checksum, ChecksumKind::Crc32c);
+        assert_eq!(
+            decoded.header_info().checksum,
+            compute_checksum_with(ChecksumKind::Crc32c, b"castagnoli")
+        );
+        assert_eq!(decoded.header.flags, flags);
+        assert_eq!(decoded.payload, b"castagnoli");
+
+        // Without an agreed algorithm either one is accepted
+        let decoded = decode_envelope(&buffer[..len]).unwrap();
+        assert_eq!(decoded.checksum, ChecksumKind::Crc32c);
+        assert_eq!(decoded.payload, b"castagnoli");
+
+        // A peer checksumming with another algorithm than agreed
+        assert!(matches!(
+            decode_envelope_with(ChecksumKind::Crc32, &buffer[..len]),
+            Err(WasmError::Deserialize(
+                DeserializeError::ChecksumMismatch { .. }
+            ))
+        ));
+        let len = encode_with_envelope(b"ieee", 0, &mut buffer).unwrap();
+        assert!(matches!(
+            decode_envelope_with(ChecksumKind::Crc32c, &buffer[..len]),
+            Err(WasmError::Deserialize(
+                DeserializeError::ChecksumMismatch { .. }
+            ))
+        ));
+        assert_eq!(
+            decode_envelope(&buffer[..len]).unwrap().checksum,
+            ChecksumKind::Crc32
+        );
+    }
+
+    #[test]
+    fn test_user_flags_survive() {
+        let flags = EnvelopeFlagSet::from(EnvelopeFlags::IsError as u8).with_user_flag(0, true);
+        let mut buffer = [0u8; 64];
+        let len = encode_with_envelope(b"user", flags.bits(), &mut buffer).unwrap();
+
+        let decoded = decode_envelope(&buffer[..len]).unwrap();
+        assert_eq!(decoded.header.flags_set(), flags);
+        assert!(decoded.header.flags_set().user_flag(0));
+        assert!(decoded.header.is_error());
+        assert_eq!(decoded.payload, b"user");
+
+        // Every user bit, next to every core flag
+        assert_eq!(EnvelopeFlags::USER_MASK, 0b1111_0000);
+        assert_eq!(EnvelopeFlagSet::USER_FLAGS, 4);
+        let core = EnvelopeFlags::combine(&EnvelopeFlags::ALL);
+        for n in 0..EnvelopeFlagSet::USER_FLAGS {
+            let flags = EnvelopeFlagSet::from(core).with_user_flag(n, true);
+            let len = encode_with_envelope(b"user", flags.bits(), &mut buffer).unwrap();
+
+            let decoded = decode_envelope(&buffer[..len]).unwrap();
+            assert_eq!(decoded.header.flags_set(), flags, "user flag {}", n);
+            assert_eq!(decoded.header.flags_set().user_bits(), 1 << (4 + n));
+        }
+    }
+
+    #[test]
+    fn test_payload_len_overflow() {
+        // A header claiming u32::MAX bytes, followed by a few
+        let mut buffer = [0u8; 64];
+        buffer[..EnvelopeHeader::SIZE]
+            .copy_from_slice(&EnvelopeHeader::new(u32::MAX, 0, 0).to_bytes());
+
+        // Too long for the buffer on 64-bit targets, too long for usize on
+        // 32-bit ones; either way it is not read past the buffer
+        assert!(matches!(
+            decode_envelope(&buffer),
+            Err(WasmError::Deserialize(DeserializeError::UnexpectedEof))
+        ));
     }
 }
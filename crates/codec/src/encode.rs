@@ -1,7 +1,7 @@
 //! Encoding functionality
 
-use crate::checksum::compute_checksum;
-use aingle_wasmer_common::{EnvelopeHeader, WasmError, WasmSlice};
+use crate::checksum::compute_checksum_with;
+use aingle_wasmer_common::{ChecksumKind, EnvelopeHeader, WasmError, WasmSlice};
 
 /// Encoder for WASM messages
 pub struct Encoder<'a> {
@@ -70,10 +70,25 @@ impl<'a> Encoder<'a> {
 }
 
 /// Encode a payload with envelope header
+///
+/// The checksum is CRC32, see [`encode_envelope_with`] for CRC32C.
 pub fn encode_with_envelope(
     payload: &[u8],
     flags: u8,
     output: &mut [u8],
+) -> Result<usize, WasmError> {
+    encode_envelope_with(ChecksumKind::Crc32, payload, flags, output)
+}
+
+/// Encode a payload with envelope header, checksummed with `kind`
+///
+/// The header does not record `kind`; the peer has to expect it, as host
+/// and guest do once they agreed on it through the guest ABI handshake.
+pub fn encode_envelope_with(
+    kind: ChecksumKind,
+    payload: &[u8],
+    flags: u8,
+    output: &mut [u8],
 ) -> Result<usize, WasmError> {
     let total_size = EnvelopeHeader::SIZE + payload.len();
 
@@ -86,7 +101,7 @@ pub fn encode_with_envelope(
         ));
     }
 
-    let checksum = compute_checksum(payload);
+    let checksum = compute_checksum_with(kind, payload);
     let header = EnvelopeHeader::new(payload.len() as u32, checksum, flags);
 
     let mut encoder = Encoder::new(output);
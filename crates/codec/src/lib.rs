@@ -8,16 +8,22 @@
 //! - Checksum validation
 //! - Streaming support
 
+#![cfg_attr(not(feature = "std"), no_std)]
 #![warn(missing_docs)]
 
 mod checksum;
 mod decode;
 mod encode;
+#[cfg(feature = "middleware_bytes")]
+mod serialized_bytes;
 
 pub use checksum::*;
 pub use decode::*;
 pub use encode::*;
+#[cfg(feature = "middleware_bytes")]
+pub use serialized_bytes::*;
 
 pub use aingle_wasmer_common::{
-    EnvelopeFlags, EnvelopeHeader, WasmDecode, WasmEncode, WasmError, WasmResult, WasmSlice,
+    ChecksumKind, EnvelopeFlagSet, EnvelopeFlags, EnvelopeHeader, EnvelopeHeaderInfo, WasmDecode,
+    WasmEncode, WasmError, WasmResult, WasmSlice,
 };
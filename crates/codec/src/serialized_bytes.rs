@@ -0,0 +1,109 @@
+//! Envelopes around [`SerializedBytes`]
+//!
+//! Payloads already serialized by `aingle_middleware_bytes` go into an
+//! envelope as they are, without another round through the serializer.
+
+use crate::{encode_with_envelope, DecodedEnvelope};
+use aingle_middleware_bytes::{SerializedBytes, UnsafeBytes};
+use aingle_wasmer_common::{WasmError, WasmErrorInner};
+use std::collections::BTreeMap;
+
+/// Encode `sb` with an envelope into `output`
+///
+/// Same as [`encode_with_envelope`] over the serialized bytes; returns the
+/// number of bytes written.
+pub fn encode_serialized_bytes(
+    sb: &SerializedBytes,
+    flags: u8,
+    output: &mut [u8],
+) -> Result<usize, WasmError> {
+    encode_with_envelope(sb.bytes(), flags, output)
+}
+
+/// The payload of an envelope, copied out
+///
+/// Envelopes flagged as errors convert to the guest error they carry: a
+/// fixed-layout error as [`WasmError::GuestStructured`], anything else as
+/// [`WasmError::Guest`] with its message.
+impl TryFrom<DecodedEnvelope<'_>> for SerializedBytes {
+    type Error = WasmError;
+
+    fn try_from(envelope: DecodedEnvelope<'_>) -> Result<Self, Self::Error> {
+        if envelope.header.is_error() {
+            return Err(guest_error(envelope.payload));
+        }
+        Ok(UnsafeBytes::from(envelope.payload.to_vec()).into())
+    }
+}
+
+/// Error returned by a guest, fixed layout or a serialized
+/// `{ error_type, message }` struct
+fn guest_error(payload: &[u8]) -> WasmError {
+    if let Some(inner) = WasmErrorInner::decode_fixed(payload) {
+        return WasmError::GuestStructured(inner);
+    }
+    // A struct of strings reads as a map, so no serde derive is needed here
+    let message = aingle_middleware_bytes::decode::<_, BTreeMap<String, String>>(payload)
+        .ok()
+        .and_then(|mut fields| fields.remove("message"))
+        .unwrap_or_else(|| String::from_utf8_lossy(payload).into_owned());
+    WasmError::Guest(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode_envelope;
+    use aingle_wasmer_common::{EnvelopeFlags, EnvelopeHeader, ErrorKind};
+
+    fn roundtrip(sb: &SerializedBytes, flags: u8) -> Result<SerializedBytes, WasmError> {
+        let mut buffer = vec![0u8; EnvelopeHeader::SIZE + sb.bytes().len()];
+        let len = encode_serialized_bytes(sb, flags, &mut buffer).unwrap();
+        assert_eq!(len, buffer.len());
+        SerializedBytes::try_from(decode_envelope(&buffer).unwrap())
+    }
+
+    #[test]
+    fn test_serialized_bytes_roundtrip() {
+        let sb = SerializedBytes::from(UnsafeBytes::from(
+            aingle_middleware_bytes::encode(&(7u32, "seven")).unwrap(),
+        ));
+        assert_eq!(roundtrip(&sb, 0).unwrap(), sb);
+        assert_eq!(roundtrip(&sb, EnvelopeFlags::USER_MASK).unwrap(), sb);
+
+        let empty = SerializedBytes::from(UnsafeBytes::from(Vec::new()));
+        assert_eq!(roundtrip(&empty, 0).unwrap(), empty);
+
+        let mut small = [0u8; EnvelopeHeader::SIZE];
+        assert!(encode_serialized_bytes(&sb, 0, &mut small).is_err());
+    }
+
+    #[test]
+    fn test_serialized_bytes_error_flag() {
+        let is_error = EnvelopeFlags::IsError as u8;
+
+        let fixed = WasmErrorInner::new(ErrorKind::Validation, "bad input");
+        let sb = SerializedBytes::from(UnsafeBytes::from(fixed.encode_fixed()));
+        assert_eq!(
+            roundtrip(&sb, is_error),
+            Err(WasmError::GuestStructured(fixed))
+        );
+
+        let mut fields = BTreeMap::new();
+        fields.insert("error_type", "Guest");
+        fields.insert("message", "no greeting today");
+        let sb = SerializedBytes::from(UnsafeBytes::from(
+            aingle_middleware_bytes::encode(&fields).unwrap(),
+        ));
+        assert_eq!(
+            roundtrip(&sb, is_error),
+            Err(WasmError::Guest("no greeting today".into()))
+        );
+
+        let sb = SerializedBytes::from(UnsafeBytes::from(b"plain".to_vec()));
+        assert_eq!(
+            roundtrip(&sb, is_error),
+            Err(WasmError::Guest("plain".into()))
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Property tests for envelope encoding and decoding
+//!
+//! Runs 256 cases per property; set `PROPTEST_CASES` for more, as in
+//! `PROPTEST_CASES=100000 cargo test -p aingle_wasmer_codec --test properties`.
+
+use aingle_wasmer_codec::{
+    compute_checksum_with, decode_envelope, decode_envelope_with, encode_envelope_with,
+    encode_with_envelope, Decoder,
+};
+use aingle_wasmer_common::{ChecksumKind, EnvelopeHeader, MAGIC, PROTOCOL_VERSION};
+use proptest::prelude::*;
+
+fn payload() -> impl Strategy<Value = Vec<u8>> {
+    proptest::collection::vec(any::<u8>(), 0..2048)
+}
+
+fn checksum_kind() -> impl Strategy<Value = ChecksumKind> {
+    prop_oneof![Just(ChecksumKind::Crc32), Just(ChecksumKind::Crc32c)]
+}
+
+fn encode(payload: &[u8], flags: u8) -> Vec<u8> {
+    encode_checksummed(ChecksumKind::Crc32, payload, flags)
+}
+
+fn encode_checksummed(kind: ChecksumKind, payload: &[u8], flags: u8) -> Vec<u8> {
+    let mut buffer = vec![0u8; EnvelopeHeader::SIZE + payload.len()];
+    let len = encode_envelope_with(kind, payload, flags, &mut buffer).unwrap();
+    assert_eq!(len, buffer.len());
+    buffer
+}
+
+/// Damage done to a valid envelope
+#[derive(Clone, Debug)]
+enum Damage {
+    /// XOR a byte with a non-zero mask
+    Flip(usize, u8),
+    /// Keep only the first bytes
+    Truncate(usize),
+    /// Overwrite the payload length in the header
+    Length(u32),
+    /// Append bytes
+    Extend(Vec<u8>),
+}
+
+fn damage() -> impl Strategy<Value = Damage> {
+    prop_oneof![
+        (any::<usize>(), 1..=u8::MAX).prop_map(|(at, mask)| Damage::Flip(at, mask)),
+        any::<usize>().prop_map(Damage::Truncate),
+        prop_oneof![
+            any::<u32>(),
+            0..4096u32,
+            (0..16u32).prop_map(|n| u32::MAX - n)
+        ]
+        .prop_map(Damage::Length),
+        proptest::collection::vec(any::<u8>(), 1..64).prop_map(Damage::Extend),
+    ]
+}
+
+impl Damage {
+    fn apply(&self, envelope: &mut Vec<u8>) {
+        match self {
+            Damage::Flip(at, mask) => {
+                let at = at % envelope.len();
+                envelope[at] ^= mask;
+            }
+            Damage::Truncate(len) => envelope.truncate(len % envelope.len()),
+            Damage::Length(len) => {
+                if let Some(field) = envelope.get_mut(4..8) {
+                    field.copy_from_slice(&len.to_le_bytes());
+                }
+            }
+            Damage::Extend(bytes) => envelope.extend_from_slice(bytes),
+        }
+    }
+}
+
+/// Reading `buffer` is safe whatever it holds, and an envelope read from it
+/// lies within it as its header says
+fn check_decode(buffer: &[u8]) -> Result<(), TestCaseError> {
+    if let Ok(decoded) = decode_envelope(buffer) {
+        let info = decoded.header_info();
+        prop_assert_eq!(info.magic, MAGIC);
+        prop_assert_eq!(decoded.payload.len(), info.payload_len as usize);
+        prop_assert_eq!(
+            decoded.payload,
+            &buffer[EnvelopeHeader::SIZE..EnvelopeHeader::SIZE + decoded.payload.len()]
+        );
+        prop_assert_eq!(
+            compute_checksum_with(decoded.checksum, decoded.payload),
+            info.checksum
+        );
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn envelope_roundtrip(
+        payload in payload(),
+        flags in any::<u8>(),
+        kind in checksum_kind(),
+    ) {
+        let envelope = encode_checksummed(kind, &payload, flags);
+        let decoded = decode_envelope_with(kind, &envelope).unwrap();
+
+        prop_assert_eq!(decoded.payload, &payload[..]);
+        let info = decoded.header_info();
+        prop_assert_eq!(info.flags.bits(), flags);
+        prop_assert_eq!(info.version, PROTOCOL_VERSION);
+        prop_assert_eq!(info.payload_len as usize, payload.len());
+        prop_assert_eq!(decoded.checksum, kind);
+        prop_assert_eq!(decode_envelope(&envelope).unwrap().payload, &payload[..]);
+    }
+
+    #[test]
+    fn envelope_ignores_trailing_bytes(payload in payload(), trailing in payload()) {
+        let mut envelope = encode(&payload, 0);
+        envelope.extend_from_slice(&trailing);
+        prop_assert_eq!(decode_envelope(&envelope).unwrap().payload, &payload[..]);
+    }
+
+    #[test]
+    fn encode_refuses_short_buffers(payload in payload(), short in 0..EnvelopeHeader::SIZE) {
+        let mut buffer = vec![0u8; EnvelopeHeader::SIZE + payload.len() - 1 - short];
+        prop_assert!(encode_with_envelope(&payload, 0, &mut buffer).is_err());
+    }
+
+    #[test]
+    fn decode_survives_byte_soup(buffer in proptest::collection::vec(any::<u8>(), 0..256)) {
+        check_decode(&buffer)?;
+    }
+
+    #[test]
+    fn decode_survives_damage(
+        payload in payload(),
+        flags in any::<u8>(),
+        damage in proptest::collection::vec(damage(), 1..4),
+    ) {
+        let mut envelope = encode(&payload, flags);
+        for damage in &damage {
+            if envelope.is_empty() {
+                break;
+            }
+            damage.apply(&mut envelope);
+        }
+        check_decode(&envelope)?;
+    }
+
+    #[test]
+    fn decoder_reads_within_buffer(
+        buffer in proptest::collection::vec(any::<u8>(), 0..64),
+        reads in proptest::collection::vec(0..5usize, 0..32),
+    ) {
+        let mut decoder = Decoder::new(&buffer);
+        for read in reads {
+            let before = decoder.remaining();
+            let width = [1, 2, 4, 8, before / 2][read];
+            let result = match read {
+                0 => decoder.read_u8().map(drop),
+                1 => decoder.read_u16().map(drop),
+                2 => decoder.read_u32().map(drop),
+                3 => decoder.read_u64().map(drop),
+                _ => decoder.read_bytes(width).map(drop),
+            };
+            prop_assert_eq!(result.is_ok(), width <= before);
+            let consumed = if result.is_ok() { width } else { 0 };
+            prop_assert_eq!(decoder.remaining(), before - consumed);
+            prop_assert_eq!(decoder.position() + decoder.remaining(), buffer.len());
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! Generates the C header of the `capi` module
+
+fn main() {
+    #[cfg(feature = "capi")]
+    capi::generate();
+}
+
+#[cfg(feature = "capi")]
+mod capi {
+    use std::path::PathBuf;
+
+    pub fn generate() {
+        println!("cargo:rerun-if-changed=src/capi.rs");
+
+        let out = PathBuf::from(std::env::var_os("OUT_DIR").unwrap()).join("aingle_wasmer.h");
+        // Only freestanding headers, so guests build without a libc
+        cbindgen::Builder::new()
+            .with_src("src/capi.rs")
+            .with_language(cbindgen::Language::C)
+            .with_include_guard("AINGLE_WASMER_H")
+            .with_header("/* Generated from aingle_wasmer_common's capi module, do not edit */")
+            .with_no_includes()
+            .with_sys_include("stdbool.h")
+            .with_sys_include("stdint.h")
+            .generate()
+            .expect("failed to generate the C header")
+            .write_to_file(out);
+    }
+}
@@ -0,0 +1,159 @@
+//! Guest ABI handshake
+//!
+//! Guests built with the guest crate export the conventions they follow, so
+//! the host can adapt to guests built against older protocol versions.
+
+use crate::{Compatibility, ProtocolCompat, PROTOCOL_VERSION};
+
+/// Guest export returning the packed [`GuestAbi`]: `() -> i64`
+pub const GUEST_ABI_EXPORT: &str = "__aingle_guest_abi";
+
+/// Guest export selecting the [`ChecksumKind`](crate::ChecksumKind) of the
+/// envelopes the guest sends, by its value: `(i32) -> ()`
+pub const GUEST_SET_CHECKSUM_EXPORT: &str = "__aingle_guest_set_checksum";
+
+/// Guest export the host calls with an empty payload right after
+/// instantiation, for guests to initialize lazy state ahead of their first
+/// call: `(ptr: i32, len: i32) -> i64`
+pub const GUEST_WARMUP_EXPORT: &str = "__aingle_warmup";
+
+/// Conventions a guest supports
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuestCapability {
+    /// Inputs and results may be wrapped in envelopes
+    EnvelopeIo = 1 << 0,
+    /// The arena is reset by the host after each call
+    ArenaReset = 1 << 1,
+    /// Panics are published as errors before the guest traps
+    PanicHook = 1 << 2,
+    /// Inputs may be streamed in chunks
+    Streaming = 1 << 3,
+    /// Envelopes may be checksummed with CRC32C, and the guest sends them
+    /// so once selected through [`GUEST_SET_CHECKSUM_EXPORT`]
+    Crc32c = 1 << 4,
+    /// Typed inputs may be MessagePack with structs as positional arrays
+    /// rather than maps keyed by field name
+    CompactEncoding = 1 << 5,
+}
+
+impl GuestCapability {
+    /// Check if the capability is set in a bit set
+    #[inline]
+    pub fn is_set(self, capabilities: u32) -> bool {
+        capabilities & (self as u32) != 0
+    }
+}
+
+/// Protocol version, guest crate version and capabilities of a guest
+///
+/// Packed into an `i64` as `protocol_version | crate_major << 8 |
+/// crate_minor << 16 | capabilities << 32`. Guests without the export are
+/// treated as [`GuestAbi::legacy`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GuestAbi {
+    /// Envelope protocol version, 0 for legacy guests
+    pub protocol_version: u8,
+    /// Major version of the guest crate
+    pub crate_major: u8,
+    /// Minor version of the guest crate
+    pub crate_minor: u8,
+    /// Combination of [`GuestCapability`] flags
+    pub capabilities: u32,
+}
+
+impl GuestAbi {
+    /// ABI of guests predating the handshake: raw MessagePack, no capabilities
+    pub const fn legacy() -> Self {
+        Self {
+            protocol_version: 0,
+            crate_major: 0,
+            crate_minor: 0,
+            capabilities: 0,
+        }
+    }
+
+    /// ABI speaking the current protocol version with the given capabilities
+    pub const fn current(crate_major: u8, crate_minor: u8, capabilities: u32) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            crate_major,
+            crate_minor,
+            capabilities,
+        }
+    }
+
+    /// Unpack from the raw value returned by the guest export
+    pub const fn from_raw(raw: u64) -> Self {
+        Self {
+            protocol_version: raw as u8,
+            crate_major: (raw >> 8) as u8,
+            crate_minor: (raw >> 16) as u8,
+            capabilities: (raw >> 32) as u32,
+        }
+    }
+
+    /// Pack into the raw value returned by the guest export
+    pub const fn into_raw(self) -> u64 {
+        self.protocol_version as u64
+            | (self.crate_major as u64) << 8
+            | (self.crate_minor as u64) << 16
+            | (self.capabilities as u64) << 32
+    }
+
+    /// Check if the guest supports a capability
+    #[inline]
+    pub fn has(&self, capability: GuestCapability) -> bool {
+        capability.is_set(self.capabilities)
+    }
+
+    /// Whether the guest predates the handshake
+    pub fn is_legacy(&self) -> bool {
+        self.protocol_version == 0
+    }
+
+    /// Compatibility of this build with the guest
+    ///
+    /// Legacy guests use no envelopes, and hosts fall back to raw framing
+    /// for them, so they count as older but supported.
+    pub const fn compatibility(&self) -> Compatibility {
+        match self.protocol_version {
+            0 => Compatibility::ReadOnly,
+            version => ProtocolCompat::check_current(version),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guest_abi_roundtrip() {
+        let abi = GuestAbi::current(
+            1,
+            4,
+            GuestCapability::EnvelopeIo as u32 | GuestCapability::Streaming as u32,
+        );
+        assert_eq!(GuestAbi::from_raw(abi.into_raw()), abi);
+        assert_eq!(abi.into_raw() & 0xff, PROTOCOL_VERSION as u64);
+        assert!(abi.has(GuestCapability::EnvelopeIo));
+        assert!(abi.has(GuestCapability::Streaming));
+        assert!(!abi.has(GuestCapability::PanicHook));
+        assert!(!abi.is_legacy());
+
+        assert_eq!(GuestAbi::from_raw(0), GuestAbi::legacy());
+        assert!(GuestAbi::legacy().is_legacy());
+    }
+
+    #[test]
+    fn test_guest_abi_compatibility() {
+        let abi = |protocol_version| GuestAbi {
+            protocol_version,
+            ..GuestAbi::current(1, 0, 0)
+        };
+        assert_eq!(abi(0).compatibility(), Compatibility::ReadOnly);
+        assert_eq!(abi(1).compatibility(), Compatibility::Full);
+        assert_eq!(abi(2).compatibility(), Compatibility::Incompatible);
+    }
+}
@@ -0,0 +1,229 @@
+//! C interface to the wire format
+//!
+//! The envelope layout, flag values and result packing as constants and
+//! `extern "C"` functions, for guests and hosts written in C. The build
+//! generates the matching header with cbindgen, see [`HEADER`]; the guest
+//! support layer in `examples/c-guest` includes it and implements the same
+//! functions in C, so guests build without a Rust toolchain.
+//!
+//! The values are literals for the header's sake, and checked against the
+//! crate's own at compile time.
+
+use crate::{EnvelopeFlags, EnvelopeHeader, WasmResult, WasmSlice, MAGIC, PROTOCOL_VERSION};
+
+/// `aingle_wasmer.h`, generated from this module
+///
+/// cbindgen:ignore
+pub const HEADER: &str = include_str!(concat!(env!("OUT_DIR"), "/aingle_wasmer.h"));
+
+/// Magic bytes opening every envelope: "AI"
+pub const AINGLE_MAGIC: u16 = 0x4149;
+
+/// Protocol version written into envelope headers
+pub const AINGLE_PROTOCOL_VERSION: u8 = 1;
+
+/// Size of the envelope header in bytes; the payload follows it
+pub const AINGLE_ENVELOPE_HEADER_SIZE: u32 = 12;
+
+/// Offset of the magic bytes in the header, a little-endian u16
+pub const AINGLE_ENVELOPE_MAGIC_OFFSET: u32 = 0;
+
+/// Offset of the protocol version in the header
+pub const AINGLE_ENVELOPE_VERSION_OFFSET: u32 = 2;
+
+/// Offset of the flags byte in the header
+pub const AINGLE_ENVELOPE_FLAGS_OFFSET: u32 = 3;
+
+/// Offset of the payload length in the header, a little-endian u32
+pub const AINGLE_ENVELOPE_PAYLOAD_LEN_OFFSET: u32 = 4;
+
+/// Offset of the payload checksum in the header, a little-endian u32
+pub const AINGLE_ENVELOPE_CHECKSUM_OFFSET: u32 = 8;
+
+/// Payload is compressed
+pub const AINGLE_FLAG_COMPRESSED: u8 = 1 << 0;
+
+/// Payload is encrypted
+pub const AINGLE_FLAG_ENCRYPTED: u8 = 1 << 1;
+
+/// Response expected
+pub const AINGLE_FLAG_EXPECTS_RESPONSE: u8 = 1 << 2;
+
+/// The payload is an error
+pub const AINGLE_FLAG_IS_ERROR: u8 = 1 << 3;
+
+/// Bits of the flags byte reserved for the core protocol
+pub const AINGLE_FLAG_CORE_MASK: u8 = 0x0f;
+
+/// Bit of a packed result marking it as an error
+pub const AINGLE_RESULT_ERROR_BIT: u64 = 1u64 << 63;
+
+const _: () = {
+    assert!(AINGLE_MAGIC == MAGIC);
+    assert!(AINGLE_PROTOCOL_VERSION == PROTOCOL_VERSION);
+    assert!(AINGLE_ENVELOPE_HEADER_SIZE as usize == EnvelopeHeader::SIZE);
+    assert!(AINGLE_FLAG_COMPRESSED == EnvelopeFlags::Compressed as u8);
+    assert!(AINGLE_FLAG_ENCRYPTED == EnvelopeFlags::Encrypted as u8);
+    assert!(AINGLE_FLAG_EXPECTS_RESPONSE == EnvelopeFlags::ExpectsResponse as u8);
+    assert!(AINGLE_FLAG_IS_ERROR == EnvelopeFlags::IsError as u8);
+    assert!(AINGLE_FLAG_CORE_MASK == EnvelopeFlags::CORE_MASK);
+    assert!(WasmResult::err(WasmSlice::empty()).into_raw() == AINGLE_RESULT_ERROR_BIT);
+};
+
+/// Envelope header with its fields aligned, as read by
+/// [`aingle_envelope_header_decode`]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AingleEnvelopeHeader {
+    /// Magic bytes, [`AINGLE_MAGIC`]
+    pub magic: u16,
+    /// Protocol version
+    pub version: u8,
+    /// Flags, `AINGLE_FLAG_*`
+    pub flags: u8,
+    /// Length of the payload in bytes
+    pub payload_len: u32,
+    /// Checksum of the payload
+    pub checksum: u32,
+}
+
+/// Pack a successful result pointing at `len` bytes at `ptr`
+#[no_mangle]
+pub extern "C" fn aingle_result_ok(ptr: u32, len: u32) -> u64 {
+    WasmResult::ok(WasmSlice::new(ptr, len)).into_raw()
+}
+
+/// Pack an error result pointing at `len` bytes at `ptr`
+#[no_mangle]
+pub extern "C" fn aingle_result_err(ptr: u32, len: u32) -> u64 {
+    WasmResult::err(WasmSlice::new(ptr, len)).into_raw()
+}
+
+/// Whether a packed result is an error
+#[no_mangle]
+pub extern "C" fn aingle_result_is_err(result: u64) -> bool {
+    WasmResult::from_raw(result).is_err()
+}
+
+/// Pointer of the slice a packed result points at
+#[no_mangle]
+pub extern "C" fn aingle_result_ptr(result: u64) -> u32 {
+    WasmResult::from_raw(result).slice().ptr
+}
+
+/// Length of the slice a packed result points at
+#[no_mangle]
+pub extern "C" fn aingle_result_len(result: u64) -> u32 {
+    WasmResult::from_raw(result).slice().len
+}
+
+/// Write the header of an envelope around a `payload_len` byte payload
+/// into the [`AINGLE_ENVELOPE_HEADER_SIZE`] bytes at `out`
+///
+/// # Safety
+///
+/// `out` must be valid for writes of [`AINGLE_ENVELOPE_HEADER_SIZE`] bytes.
+#[no_mangle]
+pub unsafe extern "C" fn aingle_envelope_header_encode(
+    payload_len: u32,
+    checksum: u32,
+    flags: u8,
+    out: *mut u8,
+) {
+    let bytes = EnvelopeHeader::new(payload_len, checksum, flags).to_bytes();
+    // SAFETY: the caller provides room for a header
+    unsafe { core::ptr::copy_nonoverlapping(bytes.as_ptr(), out, bytes.len()) };
+}
+
+/// Read the header at the start of the `len` bytes at `bytes` into `out`
+///
+/// Returns false if the bytes are too short for a header, or do not start
+/// with a header of a supported protocol version. The payload length and
+/// checksum are not checked against the bytes.
+///
+/// # Safety
+///
+/// `bytes` must be valid for reads of `len` bytes and `out` for a write.
+#[no_mangle]
+pub unsafe extern "C" fn aingle_envelope_header_decode(
+    bytes: *const u8,
+    len: u32,
+    out: *mut AingleEnvelopeHeader,
+) -> bool {
+    if (len as usize) < EnvelopeHeader::SIZE {
+        return false;
+    }
+    // SAFETY: the caller provides `len` readable bytes, at least a header
+    let bytes = unsafe { &*bytes.cast::<[u8; EnvelopeHeader::SIZE]>() };
+    let header = EnvelopeHeader::from_bytes(bytes);
+    if header.validate(None).is_err() {
+        return false;
+    }
+    let info = header.info();
+    let decoded = AingleEnvelopeHeader {
+        magic: info.magic,
+        version: info.version,
+        flags: info.flags.bits(),
+        payload_len: info.payload_len,
+        checksum: info.checksum,
+    };
+    // SAFETY: the caller provides a writable header
+    unsafe { out.write(decoded) };
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capi_result_packing() {
+        let ok = aingle_result_ok(64, 5);
+        assert_eq!(ok, WasmResult::ok(WasmSlice::new(64, 5)).into_raw());
+        assert!(!aingle_result_is_err(ok));
+        assert_eq!((aingle_result_ptr(ok), aingle_result_len(ok)), (64, 5));
+
+        let err = aingle_result_err(64, 5);
+        assert!(aingle_result_is_err(err));
+        assert_eq!(err & !AINGLE_RESULT_ERROR_BIT, ok);
+    }
+
+    #[test]
+    fn test_capi_envelope_header() {
+        let mut bytes = [0u8; AINGLE_ENVELOPE_HEADER_SIZE as usize];
+        unsafe {
+            aingle_envelope_header_encode(5, 0xdead_beef, AINGLE_FLAG_IS_ERROR, bytes.as_mut_ptr())
+        };
+        assert_eq!(
+            bytes,
+            EnvelopeHeader::new(5, 0xdead_beef, AINGLE_FLAG_IS_ERROR).to_bytes()
+        );
+        let offset = AINGLE_ENVELOPE_FLAGS_OFFSET as usize;
+        assert_eq!(bytes[offset], AINGLE_FLAG_IS_ERROR);
+
+        let mut header = AingleEnvelopeHeader::default();
+        assert!(unsafe { aingle_envelope_header_decode(bytes.as_ptr(), 12, &mut header) });
+        assert_eq!(
+            header,
+            AingleEnvelopeHeader {
+                magic: AINGLE_MAGIC,
+                version: AINGLE_PROTOCOL_VERSION,
+                flags: AINGLE_FLAG_IS_ERROR,
+                payload_len: 5,
+                checksum: 0xdead_beef,
+            }
+        );
+
+        assert!(!unsafe { aingle_envelope_header_decode(bytes.as_ptr(), 11, &mut header) });
+        bytes[0] ^= 1;
+        assert!(!unsafe { aingle_envelope_header_decode(bytes.as_ptr(), 12, &mut header) });
+    }
+
+    #[test]
+    fn test_capi_header() {
+        assert!(HEADER.contains("#define AINGLE_ENVELOPE_HEADER_SIZE 12"));
+        assert!(HEADER.contains("uint64_t aingle_result_ok(uint32_t ptr, uint32_t len);"));
+        assert!(HEADER.contains("AingleEnvelopeHeader"));
+        assert!(!HEADER.contains("#define HEADER"));
+    }
+}
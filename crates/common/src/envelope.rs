@@ -3,9 +3,28 @@
 //! The envelope provides a versioned, checksummed wire format for
 //! host↔guest communication that supports future protocol evolution.
 
-use crate::{MAGIC, PROTOCOL_VERSION};
+use crate::{ProtocolCompat, VersionPolicy, MAGIC, PROTOCOL_VERSION};
+use core::fmt;
+use serde::{Deserialize, Serialize};
 
 /// Flags for envelope options
+///
+/// The flags byte is split in two ranges:
+///
+/// | Bits | Range | Use                                            |
+/// |------|-------|------------------------------------------------|
+/// | 0–3  | core  | these flags                                    |
+/// | 4–7  | user  | protocols built on the envelope, see [`EnvelopeFlagSet::user_flag`] |
+///
+/// Neither the checksum algorithm nor a streamed result takes a flag: the
+/// first is agreed through the guest ABI handshake, see [`ChecksumKind`],
+/// and the second shows from the guest's calls to the output chunk import.
+///
+/// Core code never sets user bits and carries them through decoding
+/// untouched. Decoders ignore core bits they do not know, so a newer
+/// version may add flags without breaking older peers. A version adding a
+/// flag that must not be ignored also adds a `MustUnderstand` flag, and
+/// decoders reject envelopes with unknown core bits only when it is set.
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum EnvelopeFlags {
@@ -33,6 +52,184 @@ impl EnvelopeFlags {
     pub fn combine(flags: &[EnvelopeFlags]) -> u8 {
         flags.iter().fold(0u8, |acc, f| acc | (*f as u8))
     }
+
+    /// Every flag with a bit of its own, in bit order
+    pub const ALL: [EnvelopeFlags; 4] = [
+        EnvelopeFlags::Compressed,
+        EnvelopeFlags::Encrypted,
+        EnvelopeFlags::ExpectsResponse,
+        EnvelopeFlags::IsError,
+    ];
+
+    /// Bits of the flags byte reserved for the core protocol
+    pub const CORE_MASK: u8 = 0b0000_1111;
+
+    /// Bits of the flags byte left to protocols built on the envelope
+    pub const USER_MASK: u8 = !Self::CORE_MASK;
+}
+
+// Core flags stay within the core range
+const _: () = {
+    let mut i = 0;
+    while i < EnvelopeFlags::ALL.len() {
+        assert!(EnvelopeFlags::ALL[i] as u8 & EnvelopeFlags::USER_MASK == 0);
+        i += 1;
+    }
+};
+
+/// Algorithm of an envelope's payload checksum
+///
+/// The header does not record it: host and guest agree on it through the
+/// guest ABI handshake, and only guests advertising
+/// [`GuestCapability::Crc32c`](crate::GuestCapability::Crc32c) are sent
+/// CRC32C envelopes, which peers that predate it read as corrupt.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ChecksumKind {
+    /// IEEE CRC32
+    #[default]
+    Crc32 = 0,
+    /// CRC32C (Castagnoli), computed with dedicated instructions on x86
+    /// SSE4.2 and ARMv8
+    Crc32c = 1,
+}
+
+impl ChecksumKind {
+    /// Parse the value passed to [`GUEST_SET_CHECKSUM_EXPORT`](crate::GUEST_SET_CHECKSUM_EXPORT)
+    #[inline]
+    pub const fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(ChecksumKind::Crc32),
+            1 => Some(ChecksumKind::Crc32c),
+            _ => None,
+        }
+    }
+}
+
+/// The flags byte of an envelope, parsed
+///
+/// Serializes as the plain byte. Bits no [`EnvelopeFlags`] stands for are
+/// kept, so a header from a newer protocol version survives a round trip.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EnvelopeFlagSet(u8);
+
+impl EnvelopeFlagSet {
+    /// Parse a flags byte
+    #[inline]
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    /// The flags byte
+    #[inline]
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Whether `flag` is set
+    #[inline]
+    pub fn contains(self, flag: EnvelopeFlags) -> bool {
+        flag.is_set(self.0)
+    }
+
+    /// Whether no bit is set
+    #[inline]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Bits set that no [`EnvelopeFlags`] stands for
+    #[inline]
+    pub fn unknown_bits(self) -> u8 {
+        self.0 & !EnvelopeFlags::combine(&EnvelopeFlags::ALL)
+    }
+
+    /// Number of user flags
+    pub const USER_FLAGS: u8 = EnvelopeFlags::USER_MASK.count_ones() as u8;
+
+    /// Whether user flag `n` is set
+    ///
+    /// # Panics
+    ///
+    /// If `n` is not below [`EnvelopeFlagSet::USER_FLAGS`].
+    #[inline]
+    pub const fn user_flag(self, n: u8) -> bool {
+        self.0 & Self::user_bit(n) != 0
+    }
+
+    /// Copy with user flag `n` set to `value`
+    ///
+    /// # Panics
+    ///
+    /// If `n` is not below [`EnvelopeFlagSet::USER_FLAGS`].
+    #[inline]
+    pub const fn with_user_flag(self, n: u8, value: bool) -> Self {
+        match value {
+            true => Self(self.0 | Self::user_bit(n)),
+            false => Self(self.0 & !Self::user_bit(n)),
+        }
+    }
+
+    /// The bits of the user range
+    #[inline]
+    pub const fn user_bits(self) -> u8 {
+        self.0 & EnvelopeFlags::USER_MASK
+    }
+
+    /// Bits of the core range that no [`EnvelopeFlags`] stands for
+    #[inline]
+    pub fn unknown_core_bits(self) -> u8 {
+        self.unknown_bits() & EnvelopeFlags::CORE_MASK
+    }
+
+    const fn user_bit(n: u8) -> u8 {
+        assert!(n < Self::USER_FLAGS, "user flag out of range");
+        1 << (EnvelopeFlags::USER_MASK.trailing_zeros() as u8 + n)
+    }
+
+    /// The known flags that are set, in bit order
+    pub fn iter(self) -> impl Iterator<Item = EnvelopeFlags> {
+        EnvelopeFlags::ALL
+            .into_iter()
+            .filter(move |flag| self.contains(*flag))
+    }
+}
+
+impl From<u8> for EnvelopeFlagSet {
+    fn from(bits: u8) -> Self {
+        Self(bits)
+    }
+}
+
+impl From<EnvelopeFlagSet> for u8 {
+    fn from(flags: EnvelopeFlagSet) -> Self {
+        flags.0
+    }
+}
+
+/// `Compressed | IsError`, `None` when empty, unknown bits in hex
+impl fmt::Display for EnvelopeFlagSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("None");
+        }
+        let mut separator = "";
+        for flag in self.iter() {
+            write!(f, "{}{:?}", separator, flag)?;
+            separator = " | ";
+        }
+        if self.unknown_bits() != 0 {
+            write!(f, "{}{:#04x}", separator, self.unknown_bits())?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for EnvelopeFlagSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EnvelopeFlagSet({})", self)
+    }
 }
 
 /// Header for WASM envelope messages
@@ -83,12 +280,16 @@ impl EnvelopeHeader {
     }
 
     /// Validate the header
+    ///
+    /// The version must be one [`ProtocolCompat`] finds compatible and
+    /// `policy` accepts, [`VersionPolicy::AcceptOlder`] when `None`.
     #[inline]
-    pub fn validate(&self) -> Result<(), EnvelopeError> {
+    pub fn validate(&self, policy: Option<VersionPolicy>) -> Result<(), EnvelopeError> {
         if self.magic != MAGIC {
             return Err(EnvelopeError::InvalidMagic(self.magic));
         }
-        if self.version > PROTOCOL_VERSION {
+        let compatibility = ProtocolCompat::check_current(self.version);
+        if !policy.unwrap_or_default().accepts(compatibility) {
             return Err(EnvelopeError::UnsupportedVersion(self.version));
         }
         Ok(())
@@ -118,6 +319,17 @@ impl EnvelopeHeader {
         }
     }
 
+    /// Size of the envelope, header included
+    ///
+    /// `None` when it does not fit `usize`, as with a `payload_len` near
+    /// `u32::MAX` on 32-bit targets such as wasm32.
+    #[inline]
+    pub fn envelope_len(&self) -> Option<usize> {
+        usize::try_from(self.payload_len)
+            .ok()?
+            .checked_add(Self::SIZE)
+    }
+
     /// Check if error flag is set
     #[inline]
     pub fn is_error(&self) -> bool {
@@ -129,6 +341,90 @@ impl EnvelopeHeader {
     pub fn is_compressed(&self) -> bool {
         EnvelopeFlags::Compressed.is_set(self.flags)
     }
+
+    /// The parsed flags
+    #[inline]
+    pub fn flags_set(&self) -> EnvelopeFlagSet {
+        EnvelopeFlagSet::from_bits(self.flags)
+    }
+
+    /// Copy of the header that can be borrowed, printed and serialized
+    #[inline]
+    pub fn info(&self) -> EnvelopeHeaderInfo {
+        EnvelopeHeaderInfo::from(*self)
+    }
+}
+
+impl fmt::Debug for EnvelopeHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Fields are read through the aligned copy, as references into a
+        // packed struct may be unaligned
+        let info = self.info();
+        f.debug_struct("EnvelopeHeader")
+            .field("magic", &format_args!("{:#06x}", info.magic))
+            .field("version", &info.version)
+            .field("flags", &info.flags)
+            .field("payload_len", &info.payload_len)
+            .field("checksum", &format_args!("{:#010x}", info.checksum))
+            .finish()
+    }
+}
+
+/// Aligned copy of an [`EnvelopeHeader`]
+///
+/// The header itself is packed, so its fields cannot be borrowed; this
+/// mirror can, and is what diagnostics log and serialize.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EnvelopeHeaderInfo {
+    /// Magic bytes
+    pub magic: u16,
+    /// Protocol version
+    pub version: u8,
+    /// Flags
+    pub flags: EnvelopeFlagSet,
+    /// Length of payload in bytes
+    pub payload_len: u32,
+    /// CRC32 checksum of payload
+    pub checksum: u32,
+}
+
+impl From<EnvelopeHeader> for EnvelopeHeaderInfo {
+    fn from(header: EnvelopeHeader) -> Self {
+        Self {
+            magic: header.magic,
+            version: header.version,
+            flags: header.flags_set(),
+            payload_len: header.payload_len,
+            checksum: header.checksum,
+        }
+    }
+}
+
+impl From<EnvelopeHeaderInfo> for EnvelopeHeader {
+    fn from(info: EnvelopeHeaderInfo) -> Self {
+        Self {
+            magic: info.magic,
+            version: info.version,
+            flags: info.flags.bits(),
+            payload_len: info.payload_len,
+            checksum: info.checksum,
+        }
+    }
+}
+
+/// `v1 [Compressed] 1024 bytes, checksum 0xdeadbeef`
+impl fmt::Display for EnvelopeHeaderInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "v{} [{}] {} bytes, checksum {:#010x}",
+            self.version, self.flags, self.payload_len, self.checksum
+        )?;
+        if self.magic != MAGIC {
+            write!(f, ", bad magic {:#06x}", self.magic)?;
+        }
+        Ok(())
+    }
 }
 
 /// Errors that can occur when parsing envelopes
@@ -139,16 +435,60 @@ pub enum EnvelopeError {
     /// Unsupported protocol version
     UnsupportedVersion(u8),
     /// Checksum mismatch
-    ChecksumMismatch { expected: u32, actual: u32 },
+    ChecksumMismatch {
+        /// Checksum in the header
+        expected: u32,
+        /// Checksum of the payload
+        actual: u32,
+    },
     /// Buffer too small
-    BufferTooSmall { needed: usize, available: usize },
+    BufferTooSmall {
+        /// Bytes the envelope needs
+        needed: usize,
+        /// Bytes the buffer has
+        available: usize,
+    },
     /// Payload too large
     PayloadTooLarge(u32),
 }
 
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvelopeError::InvalidMagic(magic) => write!(
+                f,
+                "invalid envelope magic {:#06x}, expected {:#06x}",
+                magic, MAGIC
+            ),
+            EnvelopeError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported envelope version {}, newest supported is {}",
+                version, PROTOCOL_VERSION
+            ),
+            EnvelopeError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "envelope checksum mismatch: header says {:#010x} but payload has {:#010x}",
+                expected, actual
+            ),
+            EnvelopeError::BufferTooSmall { needed, available } => write!(
+                f,
+                "envelope buffer too small: needed {} bytes but only {} are available",
+                needed, available
+            ),
+            EnvelopeError::PayloadTooLarge(len) => {
+                write!(f, "envelope payload of {} bytes is too large", len)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EnvelopeError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::string::ToString;
 
     #[test]
     fn test_header_roundtrip() {
@@ -171,7 +511,7 @@ mod tests {
     #[test]
     fn test_header_validation() {
         let valid = EnvelopeHeader::new(100, 0, 0);
-        assert!(valid.validate().is_ok());
+        assert!(valid.validate(None).is_ok());
 
         let invalid = EnvelopeHeader {
             magic: 0xFFFF,
@@ -181,11 +521,56 @@ mod tests {
             checksum: 0,
         };
         assert!(matches!(
-            invalid.validate(),
+            invalid.validate(None),
             Err(EnvelopeError::InvalidMagic(0xFFFF))
         ));
     }
 
+    #[test]
+    fn test_envelope_len() {
+        assert_eq!(EnvelopeHeader::new(100, 0, 0).envelope_len(), Some(112));
+
+        let huge = EnvelopeHeader::new(u32::MAX, 0, 0);
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(
+            huge.envelope_len(),
+            Some(u32::MAX as usize + EnvelopeHeader::SIZE)
+        );
+        #[cfg(target_pointer_width = "32")]
+        assert_eq!(huge.envelope_len(), None);
+    }
+
+    #[test]
+    fn test_header_version_policy() {
+        let policies = [
+            None,
+            Some(VersionPolicy::AcceptOlder),
+            Some(VersionPolicy::RequireCurrent),
+        ];
+        for version in 0..=PROTOCOL_VERSION + 1 {
+            let mut header = EnvelopeHeader::new(0, 0, 0);
+            header.version = version;
+            let accepted =
+                version >= crate::MIN_SUPPORTED_PROTOCOL_VERSION && version <= PROTOCOL_VERSION;
+            for policy in policies {
+                let strict = policy == Some(VersionPolicy::RequireCurrent);
+                assert_eq!(
+                    header.validate(policy).is_ok(),
+                    accepted && (!strict || version == PROTOCOL_VERSION),
+                    "version {} policy {:?}",
+                    version,
+                    policy
+                );
+            }
+            if !accepted {
+                assert_eq!(
+                    header.validate(None),
+                    Err(EnvelopeError::UnsupportedVersion(version))
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_flags() {
         let flags = EnvelopeFlags::combine(&[EnvelopeFlags::Compressed, EnvelopeFlags::IsError]);
@@ -193,5 +578,128 @@ mod tests {
         assert!(EnvelopeFlags::Compressed.is_set(flags));
         assert!(EnvelopeFlags::IsError.is_set(flags));
         assert!(!EnvelopeFlags::Encrypted.is_set(flags));
+        assert!(!EnvelopeFlags::ExpectsResponse.is_set(flags));
+    }
+
+    #[test]
+    fn test_flag_set() {
+        let flags = EnvelopeHeader::new(0, 0, 0b1010_1001).flags_set();
+        assert!(flags.contains(EnvelopeFlags::Compressed));
+        assert!(flags.contains(EnvelopeFlags::IsError));
+        assert!(!flags.contains(EnvelopeFlags::Encrypted));
+        assert_eq!(
+            flags.iter().collect::<alloc::vec::Vec<_>>(),
+            [EnvelopeFlags::Compressed, EnvelopeFlags::IsError]
+        );
+        assert_eq!(flags.unknown_bits(), 0b1010_0000);
+        assert_eq!(flags.to_string(), "Compressed | IsError | 0xa0");
+        assert_eq!(EnvelopeFlagSet::default().to_string(), "None");
+    }
+
+    #[test]
+    fn test_user_flags() {
+        assert_eq!(EnvelopeFlagSet::USER_FLAGS, 4);
+
+        let flags = EnvelopeFlagSet::from(EnvelopeFlags::IsError as u8).with_user_flag(1, true);
+        assert_eq!(flags.bits(), 0b0010_1000);
+        assert!(flags.user_flag(1));
+        assert!(!flags.user_flag(0));
+        assert_eq!(flags.user_bits(), 0b0010_0000);
+        assert_eq!(flags.unknown_core_bits(), 0);
+        assert!(flags.contains(EnvelopeFlags::IsError));
+        assert_eq!(
+            flags.with_user_flag(1, false).bits(),
+            EnvelopeFlags::IsError as u8
+        );
+
+        // Every core bit is known to this version
+        assert_eq!(EnvelopeFlagSet::from_bits(u8::MAX).unknown_core_bits(), 0);
+
+        // User bits survive the header's byte round trip
+        let header = EnvelopeHeader::new(4, 0, flags.bits());
+        assert_eq!(
+            EnvelopeHeader::from_bytes(&header.to_bytes()).flags_set(),
+            flags
+        );
+        assert!(header.validate(None).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "user flag out of range")]
+    fn test_user_flag_out_of_range() {
+        EnvelopeFlagSet::default().user_flag(EnvelopeFlagSet::USER_FLAGS);
+    }
+
+    #[test]
+    fn test_checksum_kind() {
+        for kind in [ChecksumKind::Crc32, ChecksumKind::Crc32c] {
+            assert_eq!(ChecksumKind::from_u32(kind as u32), Some(kind));
+        }
+        assert_eq!(ChecksumKind::default(), ChecksumKind::Crc32);
+        assert_eq!(ChecksumKind::from_u32(2), None);
+    }
+
+    #[test]
+    fn test_header_debug() {
+        let header = EnvelopeHeader::new(1024, 0xDEADBEEF, EnvelopeFlags::IsError as u8);
+        let debug = alloc::format!("{:?}", header);
+        for field in [
+            "magic: 0x4149",
+            "version: 1",
+            "flags: EnvelopeFlagSet(IsError)",
+            "payload_len: 1024",
+            "checksum: 0xdeadbeef",
+        ] {
+            assert!(debug.contains(field), "{} in {}", field, debug);
+        }
+        assert_eq!(
+            header.info().to_string(),
+            "v1 [IsError] 1024 bytes, checksum 0xdeadbeef"
+        );
+    }
+
+    #[test]
+    fn test_envelope_error_display() {
+        let cases = [
+            (
+                EnvelopeError::InvalidMagic(0xFFFF),
+                "invalid envelope magic 0xffff, expected 0x4149",
+            ),
+            (
+                EnvelopeError::UnsupportedVersion(9),
+                "unsupported envelope version 9, newest supported is 1",
+            ),
+            (
+                EnvelopeError::ChecksumMismatch {
+                    expected: 0xDEADBEEF,
+                    actual: 1,
+                },
+                "envelope checksum mismatch: header says 0xdeadbeef but payload has 0x00000001",
+            ),
+            (
+                EnvelopeError::BufferTooSmall {
+                    needed: 12,
+                    available: 4,
+                },
+                "envelope buffer too small: needed 12 bytes but only 4 are available",
+            ),
+            (
+                EnvelopeError::PayloadTooLarge(70000),
+                "envelope payload of 70000 bytes is too large",
+            ),
+        ];
+        for (error, expected) in cases {
+            assert_eq!(error.to_string(), expected);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "msgpack")]
+    fn test_header_info_serde() {
+        let header = EnvelopeHeader::new(7, 42, 0b1001_0001);
+        let bytes = rmp_serde::to_vec_named(&header.info()).unwrap();
+        let decoded: EnvelopeHeaderInfo = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(decoded, header.info());
+        assert_eq!(EnvelopeHeader::from(decoded).to_bytes(), header.to_bytes());
     }
 }
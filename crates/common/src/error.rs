@@ -1,7 +1,13 @@
 //! Error types for AIngle WASM runtime
 
+use crate::{WasmDecode, WasmEncode};
+use alloc::boxed::Box;
 use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
 use core::fmt;
+use core::panic::Location;
+use core::sync::atomic::{AtomicPtr, Ordering};
 use serde::{Deserialize, Serialize};
 
 /// Primary error type for WASM operations
@@ -30,6 +36,41 @@ impl WasmError {
     pub fn guest<S: Into<String>>(msg: S) -> Self {
         WasmError::Guest(msg.into())
     }
+
+    /// Create a structured guest error located at the caller
+    #[track_caller]
+    pub fn guest_here(msg: &str) -> Self {
+        WasmError::GuestStructured(WasmErrorInner::here(ErrorKind::Unknown, msg))
+    }
+
+    /// Error for a payload-less error result, from the kind it carries
+    ///
+    /// See [`WasmResult::kind`](crate::WasmResult::kind).
+    pub fn without_payload(kind: ErrorKind) -> Self {
+        WasmError::GuestStructured(WasmErrorInner::new(kind, "error without payload"))
+    }
+
+    /// Kind of the error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            WasmError::Serialize(_) => ErrorKind::Serialization,
+            WasmError::Deserialize(_) => ErrorKind::Deserialization,
+            WasmError::Memory(_) => ErrorKind::Memory,
+            WasmError::HostCall(_) | WasmError::Host(_) => ErrorKind::HostCall,
+            WasmError::GuestCall(_) => ErrorKind::GuestCall,
+            WasmError::Guest(_) => ErrorKind::Unknown,
+            WasmError::GuestStructured(inner) => inner.kind,
+        }
+    }
+
+    /// Message carried by the error, empty for the typed variants
+    pub fn message(&self) -> &str {
+        match self {
+            WasmError::Guest(msg) | WasmError::Host(msg) => msg,
+            WasmError::GuestStructured(inner) => inner.message(),
+            _ => "",
+        }
+    }
 }
 
 impl From<String> for WasmError {
@@ -59,6 +100,75 @@ impl From<aingle_middleware_bytes::SerializedBytesError> for WasmError {
     }
 }
 
+/// Convert MessagePack encoding errors when the msgpack feature is enabled
+#[cfg(feature = "msgpack")]
+impl From<rmp_serde::encode::Error> for WasmError {
+    #[track_caller]
+    fn from(e: rmp_serde::encode::Error) -> Self {
+        located(ErrorKind::Serialization, e)
+    }
+}
+
+/// Convert MessagePack decoding errors when the msgpack feature is enabled
+#[cfg(feature = "msgpack")]
+impl From<rmp_serde::decode::Error> for WasmError {
+    #[track_caller]
+    fn from(e: rmp_serde::decode::Error) -> Self {
+        located(ErrorKind::Deserialization, e)
+    }
+}
+
+impl From<core::str::Utf8Error> for WasmError {
+    #[track_caller]
+    fn from(e: core::str::Utf8Error) -> Self {
+        located(ErrorKind::Deserialization, e)
+    }
+}
+
+impl From<alloc::string::FromUtf8Error> for WasmError {
+    #[track_caller]
+    fn from(e: alloc::string::FromUtf8Error) -> Self {
+        located(ErrorKind::Deserialization, e)
+    }
+}
+
+impl From<core::num::ParseIntError> for WasmError {
+    #[track_caller]
+    fn from(e: core::num::ParseIntError) -> Self {
+        located(ErrorKind::Validation, e)
+    }
+}
+
+impl From<core::num::ParseFloatError> for WasmError {
+    #[track_caller]
+    fn from(e: core::num::ParseFloatError) -> Self {
+        located(ErrorKind::Validation, e)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for WasmError {
+    #[track_caller]
+    fn from(e: std::io::Error) -> Self {
+        use std::io::ErrorKind as IoKind;
+        let kind = match e.kind() {
+            IoKind::PermissionDenied => ErrorKind::PermissionDenied,
+            IoKind::TimedOut | IoKind::WouldBlock => ErrorKind::Timeout,
+            IoKind::InvalidInput | IoKind::InvalidData => ErrorKind::Validation,
+            IoKind::UnexpectedEof => ErrorKind::Deserialization,
+            IoKind::OutOfMemory => ErrorKind::Memory,
+            _ => ErrorKind::Unknown,
+        };
+        located(kind, e)
+    }
+}
+
+/// Structured error of `kind` with the message of `e`, located at the caller
+#[track_caller]
+fn located(kind: ErrorKind, e: impl fmt::Display) -> WasmError {
+    WasmError::GuestStructured(WasmErrorInner::here(kind, &e.to_string()))
+}
+
 /// Inner error with optional context
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct WasmErrorInner {
@@ -83,6 +193,16 @@ impl WasmErrorInner {
         }
     }
 
+    /// Create a new error located at the caller
+    ///
+    /// The file is trimmed to its path tail, see [`set_location_prefix`].
+    #[track_caller]
+    pub fn here(kind: ErrorKind, message: &str) -> Self {
+        let location = Location::caller();
+        let file = trim_location(location.file(), location_prefix());
+        Self::new(kind, message).with_location(file, location.line())
+    }
+
     /// Create a new error with message (const-compatible, truncates at 128 chars)
     pub const fn new_const(kind: ErrorKind, message: &'static str) -> WasmErrorInnerBuilder {
         WasmErrorInnerBuilder { kind, message }
@@ -101,6 +221,202 @@ impl WasmErrorInner {
     }
 }
 
+/// First byte of the fixed-layout error encoding
+///
+/// The byte is never used by MessagePack, so fixed-layout errors cannot be
+/// mistaken for serialized ones.
+pub const FIXED_ERROR_MARKER: u8 = 0xc1;
+
+/// Maximum size of a fixed-layout error, marker included
+///
+/// Longer files and messages are cut to fit, the message first.
+pub const FIXED_ERROR_MAX_LEN: usize = 1024;
+
+/// Size of the fixed-layout error header
+const FIXED_ERROR_HEADER: usize = 8;
+
+/// Size of the message length following the file
+const FIXED_ERROR_MSG_LEN: usize = 2;
+
+/// Parts of a fixed-layout error, cut to [`FIXED_ERROR_MAX_LEN`]
+fn fixed_parts<'a>(
+    location: Option<(&'a str, u32)>,
+    message: &'a [u8],
+) -> (&'a [u8], u32, &'a [u8]) {
+    let (file, line) = location.unwrap_or_default();
+    let room = FIXED_ERROR_MAX_LEN - FIXED_ERROR_HEADER - FIXED_ERROR_MSG_LEN;
+    let file = &file.as_bytes()[..file.len().min(room)];
+    let message = &message[..message.len().min(room - file.len())];
+    (file, line, message)
+}
+
+/// Write the parts of a fixed-layout error to `buf`, returning the size
+fn write_fixed(
+    buf: &mut [u8],
+    kind: ErrorKind,
+    (file, line, message): (&[u8], u32, &[u8]),
+) -> Result<usize, WasmError> {
+    let file_end = FIXED_ERROR_HEADER + file.len();
+    let size = file_end + FIXED_ERROR_MSG_LEN + message.len();
+    let available = buf.len();
+    let Some(out) = buf.get_mut(..size) else {
+        return Err(WasmError::Serialize(SerializeError::BufferTooSmall {
+            needed: size,
+            available,
+        }));
+    };
+    out[0] = FIXED_ERROR_MARKER;
+    out[1] = kind as u8;
+    out[2..6].copy_from_slice(&line.to_le_bytes());
+    out[6..8].copy_from_slice(&(file.len() as u16).to_le_bytes());
+    out[FIXED_ERROR_HEADER..file_end].copy_from_slice(file);
+    out[file_end..file_end + FIXED_ERROR_MSG_LEN]
+        .copy_from_slice(&(message.len() as u16).to_le_bytes());
+    out[file_end + FIXED_ERROR_MSG_LEN..].copy_from_slice(message);
+    Ok(size)
+}
+
+/// Split `len` bytes off `rest`, or all that is left when `lenient`
+fn split_fixed(rest: &[u8], len: usize, lenient: bool) -> Result<(&[u8], &[u8]), DeserializeError> {
+    match rest.len() >= len {
+        true => Ok(rest.split_at(len)),
+        false if lenient => Ok((rest, &[])),
+        false => Err(DeserializeError::UnexpectedEof),
+    }
+}
+
+/// Read a fixed-layout error
+///
+/// Truncated errors are read up to where they end when `lenient`, and
+/// rejected otherwise.
+fn read_fixed(bytes: &[u8], lenient: bool) -> Result<WasmErrorInner, DeserializeError> {
+    if bytes.first() != Some(&FIXED_ERROR_MARKER) {
+        return Err(DeserializeError::InvalidFormat);
+    }
+    if bytes.len() < FIXED_ERROR_HEADER {
+        return Err(DeserializeError::UnexpectedEof);
+    }
+    let kind = ErrorKind::from_u8(bytes[1]);
+    let line = u32::from_le_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]);
+    let file_len = u16::from_le_bytes([bytes[6], bytes[7]]) as usize;
+
+    let take = |rest, len| split_fixed(rest, len, lenient);
+    let (file, rest) = take(&bytes[FIXED_ERROR_HEADER..], file_len)?;
+    let (message_len, rest) = take(rest, FIXED_ERROR_MSG_LEN)?;
+    let message_len = match *message_len {
+        [low, high] => u16::from_le_bytes([low, high]) as usize,
+        _ => 0,
+    };
+    let (message, rest) = take(rest, message_len)?;
+    if !rest.is_empty() && !lenient {
+        return Err(DeserializeError::InvalidFormat);
+    }
+
+    let inner = WasmErrorInner::new(kind, &String::from_utf8_lossy(message));
+    Ok(match file_len {
+        0 => inner,
+        _ => inner.with_location(&String::from_utf8_lossy(file), line),
+    })
+}
+
+impl WasmErrorInner {
+    /// Encode in the fixed layout, see [`encode_fixed_error`]
+    pub fn encode_fixed(&self) -> Vec<u8> {
+        let location = self.file.as_deref().zip(self.line);
+        encode_fixed_error(self.kind, location, self.message.as_bytes())
+    }
+
+    /// Decode an error encoded in the fixed layout
+    ///
+    /// Returns `None` if `bytes` do not start with [`FIXED_ERROR_MARKER`].
+    /// Truncated errors keep what is left of the file and message; use
+    /// [`WasmDecode::decode_from`] to reject them instead.
+    pub fn decode_fixed(bytes: &[u8]) -> Option<Self> {
+        read_fixed(bytes, true).ok()
+    }
+
+    fn fixed_parts(&self) -> (&[u8], u32, &[u8]) {
+        let location = self.file.as_deref().zip(self.line);
+        fixed_parts(location, self.message.as_bytes())
+    }
+}
+
+/// The fixed layout of [`encode_fixed_error`], usable without a serializer
+impl WasmEncode for WasmErrorInner {
+    fn encoded_size(&self) -> usize {
+        let (file, _, message) = self.fixed_parts();
+        FIXED_ERROR_HEADER + file.len() + FIXED_ERROR_MSG_LEN + message.len()
+    }
+
+    fn encode_to(&self, buf: &mut [u8]) -> Result<usize, WasmError> {
+        write_fixed(buf, self.kind, self.fixed_parts())
+    }
+}
+
+/// Decoding takes exactly one fixed-layout error
+impl WasmDecode for WasmErrorInner {
+    fn decode_from(buf: &[u8]) -> Result<Self, WasmError> {
+        read_fixed(buf, false).map_err(WasmError::Deserialize)
+    }
+}
+
+/// Encode an error without a serializer or formatting code
+///
+/// The layout is `[FIXED_ERROR_MARKER, kind, line: u32 LE, file_len: u16 LE,
+/// file, message_len: u16 LE, message]`, with line and file length 0 for
+/// errors without location, and at most [`FIXED_ERROR_MAX_LEN`] bytes long.
+/// Guests built with the guest crate's `min-size` feature return errors this
+/// way; [`WasmErrorInner::decode_fixed`] reads them back.
+pub fn encode_fixed_error(
+    kind: ErrorKind,
+    location: Option<(&str, u32)>,
+    message: &[u8],
+) -> Vec<u8> {
+    let parts = fixed_parts(location, message);
+    let mut bytes =
+        vec![0; FIXED_ERROR_HEADER + parts.0.len() + FIXED_ERROR_MSG_LEN + parts.2.len()];
+    let _ = write_fixed(&mut bytes, kind, parts);
+    bytes
+}
+
+static LOCATION_PREFIX: AtomicPtr<&'static str> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Strip `prefix` from the file paths recorded by [`WasmErrorInner::here`]
+///
+/// Paths outside the prefix are trimmed to their path tail as usual.
+pub fn set_location_prefix(prefix: &'static str) {
+    let prefix = Box::leak(Box::new(prefix));
+    LOCATION_PREFIX.store(prefix, Ordering::Release);
+}
+
+fn location_prefix() -> Option<&'static str> {
+    let prefix = LOCATION_PREFIX.load(Ordering::Acquire);
+    // SAFETY: only ever set to a leaked `Box<&'static str>`
+    unsafe { prefix.as_ref().copied() }
+}
+
+/// Trim a source path to its tail
+///
+/// With a matching `prefix` the rest of the path is kept. Otherwise the path
+/// is cut before the directory holding the last `src` component, so
+/// `/home/me/project/crates/guest/src/lib.rs` becomes `guest/src/lib.rs`.
+fn trim_location(file: &'static str, prefix: Option<&str>) -> &'static str {
+    let is_separator = |c: char| c == '/' || c == '\\';
+    if let Some(rest) = prefix.and_then(|prefix| file.strip_prefix(prefix)) {
+        return rest.trim_start_matches(is_separator);
+    }
+
+    let mut end = file.len();
+    while let Some(separator) = file[..end].rfind(is_separator) {
+        if &file[separator + 1..end] == "src" {
+            let start = file[..separator].rfind(is_separator).map_or(0, |i| i + 1);
+            return &file[start..];
+        }
+        end = separator;
+    }
+    file
+}
+
 /// Builder for const context - converts to WasmErrorInner at runtime
 pub struct WasmErrorInnerBuilder {
     kind: ErrorKind,
@@ -187,13 +503,137 @@ pub enum ErrorKind {
     Timeout = 7,
     /// Permission denied
     PermissionDenied = 8,
+    /// Input rejected before any processing
+    InvalidInput = 9,
+    /// A limit or quota ran out
+    ResourceExhausted = 10,
+    /// The requested item does not exist
+    NotFound = 11,
+    /// The operation was cancelled
+    Cancelled = 12,
+}
+
+impl ErrorKind {
+    /// Every kind, in order of discriminant
+    ///
+    /// Discriminants travel on the wire and are never reused; new kinds take
+    /// the next free value.
+    pub const ALL: &'static [ErrorKind] = &[
+        ErrorKind::Unknown,
+        ErrorKind::Serialization,
+        ErrorKind::Deserialization,
+        ErrorKind::Memory,
+        ErrorKind::HostCall,
+        ErrorKind::GuestCall,
+        ErrorKind::Validation,
+        ErrorKind::Timeout,
+        ErrorKind::PermissionDenied,
+        ErrorKind::InvalidInput,
+        ErrorKind::ResourceExhausted,
+        ErrorKind::NotFound,
+        ErrorKind::Cancelled,
+    ];
+
+    /// Name of the kind, without going through `Debug`
+    pub const fn name(self) -> &'static str {
+        match self {
+            ErrorKind::Unknown => "Unknown",
+            ErrorKind::Serialization => "Serialization",
+            ErrorKind::Deserialization => "Deserialization",
+            ErrorKind::Memory => "Memory",
+            ErrorKind::HostCall => "HostCall",
+            ErrorKind::GuestCall => "GuestCall",
+            ErrorKind::Validation => "Validation",
+            ErrorKind::Timeout => "Timeout",
+            ErrorKind::PermissionDenied => "PermissionDenied",
+            ErrorKind::InvalidInput => "InvalidInput",
+            ErrorKind::ResourceExhausted => "ResourceExhausted",
+            ErrorKind::NotFound => "NotFound",
+            ErrorKind::Cancelled => "Cancelled",
+        }
+    }
+
+    /// Human-readable description of the kind, as shown by `Display`
+    pub const fn description(self) -> &'static str {
+        match self {
+            ErrorKind::Unknown => "unknown error",
+            ErrorKind::Serialization => "serialization error",
+            ErrorKind::Deserialization => "deserialization error",
+            ErrorKind::Memory => "memory error",
+            ErrorKind::HostCall => "host call error",
+            ErrorKind::GuestCall => "guest call error",
+            ErrorKind::Validation => "validation error",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::PermissionDenied => "permission denied",
+            ErrorKind::InvalidInput => "invalid input",
+            ErrorKind::ResourceExhausted => "resource exhausted",
+            ErrorKind::NotFound => "not found",
+            ErrorKind::Cancelled => "cancelled",
+        }
+    }
+
+    /// Kind with the given discriminant, [`ErrorKind::Unknown`] if there is
+    /// none
+    pub const fn from_u8(value: u8) -> Self {
+        match value {
+            1 => ErrorKind::Serialization,
+            2 => ErrorKind::Deserialization,
+            3 => ErrorKind::Memory,
+            4 => ErrorKind::HostCall,
+            5 => ErrorKind::GuestCall,
+            6 => ErrorKind::Validation,
+            7 => ErrorKind::Timeout,
+            8 => ErrorKind::PermissionDenied,
+            9 => ErrorKind::InvalidInput,
+            10 => ErrorKind::ResourceExhausted,
+            11 => ErrorKind::NotFound,
+            12 => ErrorKind::Cancelled,
+            _ => ErrorKind::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+/// Fails with [`UnknownVariant`] for values no kind was assigned
+impl TryFrom<u8> for ErrorKind {
+    type Error = UnknownVariant;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match ErrorKind::from_u8(value) {
+            ErrorKind::Unknown if value != ErrorKind::Unknown as u8 => Err(UnknownVariant(value)),
+            kind => Ok(kind),
+        }
+    }
+}
+
+/// A wire value no [`ErrorKind`] was assigned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnknownVariant(pub u8);
+
+impl fmt::Display for UnknownVariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown error kind {}", self.0)
+    }
 }
 
+#[cfg(feature = "std")]
+impl std::error::Error for UnknownVariant {}
+
 /// Serialization errors
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SerializeError {
     /// Buffer too small for serialization
-    BufferTooSmall { needed: usize, available: usize },
+    BufferTooSmall {
+        /// Bytes the serialized value needs
+        needed: usize,
+        /// Bytes the buffer has
+        available: usize,
+    },
     /// Type cannot be serialized
     UnsupportedType,
     /// Nesting too deep
@@ -211,21 +651,39 @@ pub enum DeserializeError {
     TypeMismatch,
     /// Unknown variant
     UnknownVariant(u32),
+    /// The payload does not match the checksum in its envelope header
+    ChecksumMismatch {
+        /// Checksum recorded in the envelope header
+        expected: u32,
+        /// Checksum computed over the payload as received
+        actual: u32,
+    },
 }
 
 /// Memory errors
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MemoryError {
     /// Allocation failed
-    AllocationFailed { requested: usize },
+    AllocationFailed {
+        /// Bytes asked for
+        requested: usize,
+    },
     /// Out of bounds access
     OutOfBounds {
+        /// Start of the access
         offset: usize,
+        /// Length of the access
         len: usize,
+        /// Size of the memory accessed
         max: usize,
     },
     /// Alignment error
-    Alignment { addr: usize, required: usize },
+    Alignment {
+        /// Address accessed
+        addr: usize,
+        /// Alignment the access needs
+        required: usize,
+    },
     /// Arena exhausted
     ArenaExhausted,
 }
@@ -241,6 +699,8 @@ pub enum HostCallError {
     HostError(u32),
     /// Call timed out
     Timeout,
+    /// Host returned a malformed result
+    InvalidReturn,
 }
 
 /// Guest call errors
@@ -256,14 +716,118 @@ pub enum GuestCallError {
     MeteringExceeded,
 }
 
+impl fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerializeError::BufferTooSmall { needed, available } => write!(
+                f,
+                "serialization buffer too small: needed {} bytes but only {} are available",
+                needed, available
+            ),
+            SerializeError::UnsupportedType => {
+                f.write_str("serialization failed: unsupported type")
+            }
+            SerializeError::NestingTooDeep => f.write_str("serialization failed: nesting too deep"),
+        }
+    }
+}
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeserializeError::UnexpectedEof => {
+                f.write_str("deserialization failed: unexpected end of input")
+            }
+            DeserializeError::InvalidFormat => {
+                f.write_str("deserialization failed: invalid data format")
+            }
+            DeserializeError::TypeMismatch => f.write_str("deserialization failed: type mismatch"),
+            DeserializeError::UnknownVariant(variant) => {
+                write!(f, "deserialization failed: unknown variant {}", variant)
+            }
+            DeserializeError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch: header says {:#010x} but payload has {:#010x}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemoryError::AllocationFailed { requested } => {
+                write!(f, "memory allocation of {} bytes failed", requested)
+            }
+            MemoryError::OutOfBounds { offset, len, max } => write!(
+                f,
+                "memory access out of bounds: tried to read {} bytes at offset {} but memory is \
+                 {} bytes",
+                len, offset, max
+            ),
+            MemoryError::Alignment { addr, required } => write!(
+                f,
+                "misaligned memory access: address {:#x} is not aligned to {} bytes",
+                addr, required
+            ),
+            MemoryError::ArenaExhausted => f.write_str("guest arena exhausted"),
+        }
+    }
+}
+
+impl fmt::Display for HostCallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostCallError::FunctionNotFound => f.write_str("host call failed: function not found"),
+            HostCallError::InvalidArguments => f.write_str("host call failed: invalid arguments"),
+            HostCallError::HostError(code) => {
+                write!(f, "host call failed with error code {}", code)
+            }
+            HostCallError::Timeout => f.write_str("host call timed out"),
+            HostCallError::InvalidReturn => f.write_str("host call failed: invalid return value"),
+        }
+    }
+}
+
+impl fmt::Display for GuestCallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GuestCallError::FunctionNotExported => {
+                f.write_str("guest call failed: function not exported")
+            }
+            GuestCallError::InvalidReturn => f.write_str("guest call failed: invalid return value"),
+            GuestCallError::Panic => f.write_str("guest panicked"),
+            GuestCallError::MeteringExceeded => {
+                f.write_str("guest call exceeded its metering limit")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SerializeError {}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DeserializeError {}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MemoryError {}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HostCallError {}
+
+#[cfg(feature = "std")]
+impl std::error::Error for GuestCallError {}
+
 impl fmt::Display for WasmError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            WasmError::Serialize(e) => write!(f, "serialization error: {:?}", e),
-            WasmError::Deserialize(e) => write!(f, "deserialization error: {:?}", e),
-            WasmError::Memory(e) => write!(f, "memory error: {:?}", e),
-            WasmError::HostCall(e) => write!(f, "host call error: {:?}", e),
-            WasmError::GuestCall(e) => write!(f, "guest call error: {:?}", e),
+            WasmError::Serialize(e) => e.fmt(f),
+            WasmError::Deserialize(e) => e.fmt(f),
+            WasmError::Memory(e) => e.fmt(f),
+            WasmError::HostCall(e) => e.fmt(f),
+            WasmError::GuestCall(e) => e.fmt(f),
             WasmError::Guest(msg) => write!(f, "guest error: {}", msg),
             WasmError::Host(msg) => write!(f, "host error: {}", msg),
             WasmError::GuestStructured(inner) => {
@@ -284,9 +848,7 @@ impl std::error::Error for WasmError {}
 #[macro_export]
 macro_rules! wasm_error {
     ($kind:expr, $msg:literal) => {
-        $crate::WasmError::GuestStructured(
-            $crate::WasmErrorInner::new($kind, $msg).with_location(file!(), line!()),
-        )
+        $crate::WasmError::GuestStructured($crate::WasmErrorInner::here($kind, $msg))
     };
     ($msg:literal) => {
         $crate::wasm_error!($crate::ErrorKind::Unknown, $msg)
@@ -296,6 +858,7 @@ macro_rules! wasm_error {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::format;
 
     #[test]
     fn test_wasm_error_inner() {
@@ -308,6 +871,337 @@ mod tests {
         assert_eq!(err.message(), "invalid input");
     }
 
+    #[test]
+    fn test_fixed_error_roundtrip() {
+        let err = WasmErrorInner::new(ErrorKind::PermissionDenied, "not granted")
+            .with_location("zome.rs", 12);
+        let bytes = err.encode_fixed();
+        assert_eq!(bytes[0], FIXED_ERROR_MARKER);
+        assert_eq!(WasmErrorInner::decode_fixed(&bytes), Some(err.clone()));
+
+        let bare = WasmErrorInner::new(ErrorKind::Validation, "bad input");
+        assert_eq!(
+            WasmErrorInner::decode_fixed(&bare.encode_fixed()),
+            Some(bare)
+        );
+
+        // Through the codec traits, into a buffer of exactly the encoded size
+        let mut buf = vec![0u8; err.encoded_size()];
+        assert_eq!(err.encode_to(&mut buf).unwrap(), bytes.len());
+        assert_eq!(buf, bytes);
+        assert_eq!(WasmErrorInner::decode_from(&buf).unwrap(), err);
+        assert!(matches!(
+            err.encode_to(&mut buf[..4]),
+            Err(WasmError::Serialize(SerializeError::BufferTooSmall {
+                needed,
+                available: 4
+            })) if needed == bytes.len()
+        ));
+
+        // Truncated by the guest's fallback buffer
+        let bytes = encode_fixed_error(ErrorKind::Memory, Some(("lib.rs", 3)), b"arena full");
+        let truncated = WasmErrorInner::decode_fixed(&bytes[..bytes.len() - 5]).unwrap();
+        assert_eq!(truncated.message(), "arena");
+        assert_eq!(truncated.file.as_deref(), Some("lib.rs"));
+        let cut_in_file = WasmErrorInner::decode_fixed(&bytes[..10]).unwrap();
+        assert_eq!(cut_in_file.file.as_deref(), Some("li"));
+        assert_eq!(cut_in_file.message(), "");
+
+        // Strict decoding rejects every truncation and trailing bytes
+        for len in 1..bytes.len() {
+            assert!(matches!(
+                WasmErrorInner::decode_from(&bytes[..len]),
+                Err(WasmError::Deserialize(DeserializeError::UnexpectedEof))
+            ));
+        }
+        let mut padded = bytes.clone();
+        padded.push(0);
+        assert!(matches!(
+            WasmErrorInner::decode_from(&padded),
+            Err(WasmError::Deserialize(DeserializeError::InvalidFormat))
+        ));
+
+        // MessagePack never starts with the marker
+        let msgpack = [0x81, 0xa5, b'G', b'u', b'e', b's', b't'];
+        assert_eq!(WasmErrorInner::decode_fixed(&msgpack), None);
+        assert_eq!(WasmErrorInner::decode_fixed(&[FIXED_ERROR_MARKER]), None);
+        assert!(matches!(
+            WasmErrorInner::decode_from(&msgpack),
+            Err(WasmError::Deserialize(DeserializeError::InvalidFormat))
+        ));
+    }
+
+    #[test]
+    fn test_fixed_error_size_cap() {
+        let file = "f".repeat(300);
+        let message = "m".repeat(2000);
+        let err = WasmErrorInner::new(ErrorKind::Unknown, &message).with_location(&file, 1);
+        let bytes = err.encode_fixed();
+        assert_eq!(bytes.len(), FIXED_ERROR_MAX_LEN);
+        assert_eq!(err.encoded_size(), FIXED_ERROR_MAX_LEN);
+
+        // The file is kept whole, the message cut to the room left
+        let decoded = WasmErrorInner::decode_from(&bytes).unwrap();
+        assert_eq!(decoded.file, Some(file));
+        assert_eq!(decoded.message().len(), FIXED_ERROR_MAX_LEN - 10 - 300);
+        assert!(message.starts_with(decoded.message()));
+
+        let huge_file = "f".repeat(70_000);
+        assert_eq!(
+            encode_fixed_error(ErrorKind::Unknown, Some((&huge_file, 1)), b"lost").len(),
+            FIXED_ERROR_MAX_LEN
+        );
+    }
+
+    #[test]
+    fn test_error_kind_names() {
+        for value in 0..=12 {
+            let kind = ErrorKind::from_u8(value);
+            assert_eq!(kind as u8, value);
+            assert_eq!(kind.name(), format!("{:?}", kind));
+        }
+        assert_eq!(ErrorKind::from_u8(200), ErrorKind::Unknown);
+        assert_eq!(ErrorKind::PermissionDenied.to_string(), "permission denied");
+        assert_eq!(
+            ErrorKind::ResourceExhausted.to_string(),
+            "resource exhausted"
+        );
+
+        let err = WasmError::GuestStructured(WasmErrorInner::new(ErrorKind::Timeout, "slow"));
+        assert_eq!((err.kind(), err.message()), (ErrorKind::Timeout, "slow"));
+        let err = WasmError::Memory(MemoryError::ArenaExhausted);
+        assert_eq!((err.kind(), err.message()), (ErrorKind::Memory, ""));
+    }
+
+    #[test]
+    fn test_error_display() {
+        let cases = [
+            (
+                WasmError::Serialize(SerializeError::BufferTooSmall {
+                    needed: 64,
+                    available: 16,
+                }),
+                "serialization buffer too small: needed 64 bytes but only 16 are available",
+            ),
+            (
+                WasmError::Serialize(SerializeError::UnsupportedType),
+                "serialization failed: unsupported type",
+            ),
+            (
+                WasmError::Serialize(SerializeError::NestingTooDeep),
+                "serialization failed: nesting too deep",
+            ),
+            (
+                WasmError::Deserialize(DeserializeError::UnexpectedEof),
+                "deserialization failed: unexpected end of input",
+            ),
+            (
+                WasmError::Deserialize(DeserializeError::InvalidFormat),
+                "deserialization failed: invalid data format",
+            ),
+            (
+                WasmError::Deserialize(DeserializeError::TypeMismatch),
+                "deserialization failed: type mismatch",
+            ),
+            (
+                WasmError::Deserialize(DeserializeError::UnknownVariant(7)),
+                "deserialization failed: unknown variant 7",
+            ),
+            (
+                WasmError::Deserialize(DeserializeError::ChecksumMismatch {
+                    expected: 0xDEADBEEF,
+                    actual: 1,
+                }),
+                "checksum mismatch: header says 0xdeadbeef but payload has 0x00000001",
+            ),
+            (
+                WasmError::Memory(MemoryError::AllocationFailed { requested: 4096 }),
+                "memory allocation of 4096 bytes failed",
+            ),
+            (
+                WasmError::Memory(MemoryError::OutOfBounds {
+                    offset: 1024,
+                    len: 4096,
+                    max: 2048,
+                }),
+                "memory access out of bounds: tried to read 4096 bytes at offset 1024 but \
+                 memory is 2048 bytes",
+            ),
+            (
+                WasmError::Memory(MemoryError::Alignment {
+                    addr: 0x1003,
+                    required: 8,
+                }),
+                "misaligned memory access: address 0x1003 is not aligned to 8 bytes",
+            ),
+            (
+                WasmError::Memory(MemoryError::ArenaExhausted),
+                "guest arena exhausted",
+            ),
+            (
+                WasmError::HostCall(HostCallError::FunctionNotFound),
+                "host call failed: function not found",
+            ),
+            (
+                WasmError::HostCall(HostCallError::InvalidArguments),
+                "host call failed: invalid arguments",
+            ),
+            (
+                WasmError::HostCall(HostCallError::HostError(3)),
+                "host call failed with error code 3",
+            ),
+            (
+                WasmError::HostCall(HostCallError::Timeout),
+                "host call timed out",
+            ),
+            (
+                WasmError::HostCall(HostCallError::InvalidReturn),
+                "host call failed: invalid return value",
+            ),
+            (
+                WasmError::GuestCall(GuestCallError::FunctionNotExported),
+                "guest call failed: function not exported",
+            ),
+            (
+                WasmError::GuestCall(GuestCallError::InvalidReturn),
+                "guest call failed: invalid return value",
+            ),
+            (
+                WasmError::GuestCall(GuestCallError::Panic),
+                "guest panicked",
+            ),
+            (
+                WasmError::GuestCall(GuestCallError::MeteringExceeded),
+                "guest call exceeded its metering limit",
+            ),
+        ];
+        for (error, expected) in cases {
+            assert_eq!(error.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_here_captures_caller() {
+        let (err, line) = (WasmErrorInner::here(ErrorKind::Memory, "oom"), line!());
+        assert_eq!(err.kind, ErrorKind::Memory);
+        assert_eq!(err.line, Some(line));
+        assert!(err
+            .file
+            .as_deref()
+            .is_some_and(|file| file.ends_with("src/error.rs")));
+
+        let (err, line) = (WasmError::guest_here("boom"), line!());
+        let WasmError::GuestStructured(inner) = err else {
+            panic!("expected a structured error");
+        };
+        assert_eq!(inner.kind, ErrorKind::Unknown);
+        assert_eq!(inner.line, Some(line));
+
+        let (err, line) = (wasm_error!(ErrorKind::Validation, "bad"), line!());
+        assert!(matches!(err, WasmError::GuestStructured(inner) if inner.line == Some(line)));
+    }
+
+    #[test]
+    fn test_trim_location() {
+        assert_eq!(
+            trim_location("/home/me/project/crates/guest/src/lib.rs", None),
+            "guest/src/lib.rs"
+        );
+        assert_eq!(trim_location("src/lib.rs", None), "src/lib.rs");
+        assert_eq!(
+            trim_location(r"C:\work\zome\src\api\mod.rs", None),
+            r"zome\src\api\mod.rs"
+        );
+        assert_eq!(trim_location("build.rs", None), "build.rs");
+        assert_eq!(
+            trim_location("/home/me/project/zome/src/lib.rs", Some("/home/me/project")),
+            "zome/src/lib.rs"
+        );
+        assert_eq!(
+            trim_location("/elsewhere/zome/src/lib.rs", Some("/home/me")),
+            "zome/src/lib.rs"
+        );
+    }
+
+    fn kind_of(err: WasmError) -> ErrorKind {
+        match err {
+            WasmError::GuestStructured(inner) => inner.kind,
+            other => panic!("expected a structured error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_from_parse_and_utf8_errors() {
+        let parse = |input: &str| -> Result<u32, WasmError> { Ok(input.parse::<u32>()?) };
+        let line = line!() - 1;
+
+        let WasmError::GuestStructured(inner) = parse("ten").unwrap_err() else {
+            panic!("expected a structured error");
+        };
+        assert_eq!(inner.kind, ErrorKind::Validation);
+        assert_eq!(inner.message(), "invalid digit found in string");
+        assert_eq!(inner.line, Some(line));
+
+        let err: WasmError = "1.x".parse::<f64>().unwrap_err().into();
+        assert_eq!(kind_of(err), ErrorKind::Validation);
+
+        let invalid = alloc::vec![0xffu8, 0xfe];
+        let err: WasmError = core::str::from_utf8(&invalid).unwrap_err().into();
+        assert_eq!(kind_of(err), ErrorKind::Deserialization);
+
+        let err: WasmError = String::from_utf8(invalid).unwrap_err().into();
+        assert_eq!(kind_of(err), ErrorKind::Deserialization);
+    }
+
+    #[test]
+    fn test_error_kind_discriminants() {
+        for (index, &kind) in ErrorKind::ALL.iter().enumerate() {
+            assert_eq!(kind as usize, index);
+            assert_eq!(ErrorKind::try_from(kind as u8), Ok(kind));
+            assert_eq!(ErrorKind::from_u8(kind as u8), kind);
+        }
+
+        // Every other value is reserved
+        let assigned = ErrorKind::ALL.len() as u8;
+        for value in assigned..=u8::MAX {
+            assert_eq!(ErrorKind::try_from(value), Err(UnknownVariant(value)));
+            assert_eq!(ErrorKind::from_u8(value), ErrorKind::Unknown);
+        }
+        assert_eq!(UnknownVariant(200).to_string(), "unknown error kind 200");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_from_io_error() {
+        use std::io;
+
+        let cases = [
+            (io::ErrorKind::PermissionDenied, ErrorKind::PermissionDenied),
+            (io::ErrorKind::TimedOut, ErrorKind::Timeout),
+            (io::ErrorKind::InvalidData, ErrorKind::Validation),
+            (io::ErrorKind::UnexpectedEof, ErrorKind::Deserialization),
+            (io::ErrorKind::NotFound, ErrorKind::Unknown),
+        ];
+        for (io_kind, kind) in cases {
+            let err: WasmError = io::Error::new(io_kind, "io failed").into();
+            assert_eq!(kind_of(err), kind);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "msgpack")]
+    fn test_from_msgpack_errors() {
+        let err: WasmError = rmp_serde::from_slice::<String>(&[0xc1]).unwrap_err().into();
+        assert_eq!(kind_of(err), ErrorKind::Deserialization);
+
+        let err = WasmError::from(rmp_serde::encode::Error::Syntax("bad map".to_string()));
+        let WasmError::GuestStructured(inner) = err else {
+            panic!("expected a structured error");
+        };
+        assert_eq!(inner.kind, ErrorKind::Serialization);
+        assert!(inner.message().contains("bad map"));
+    }
+
     #[test]
     fn test_wasm_error_display() {
         let err = WasmError::Guest("test error".to_string());
@@ -0,0 +1,58 @@
+//! Extern registry exported by guests
+//!
+//! Guests that register their externs export a table describing them, so the
+//! host can learn more than the export names carry.
+
+use alloc::string::String;
+use serde::{Deserialize, Serialize};
+
+/// Guest export returning the registered extern table: `() -> i64`
+///
+/// The result is an envelope whose payload is a MessagePack encoded
+/// `Vec<ExternRecord>`.
+pub const GUEST_LIST_EXTERNS_EXPORT: &str = "__aingle_list_externs";
+
+/// Flags describing a registered extern
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExternFlags {
+    /// No special flags
+    None = 0,
+    /// Called back by the host rather than invoked by users
+    Callback = 1 << 0,
+    /// Does not modify guest or host state
+    ReadOnly = 1 << 1,
+}
+
+impl ExternFlags {
+    /// Check if a flag is set
+    #[inline]
+    pub fn is_set(self, flags: u32) -> bool {
+        flags & (self as u32) != 0
+    }
+}
+
+/// A guest extern as registered in the guest's extern table
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExternRecord {
+    /// Export name of the extern
+    pub name: String,
+    /// Identifier of the input schema, defined by the application
+    pub schema_id: u32,
+    /// Combination of [`ExternFlags`]
+    pub flags: u32,
+}
+
+impl ExternRecord {
+    /// Check if a flag is set
+    #[inline]
+    pub fn has_flag(&self, flag: ExternFlags) -> bool {
+        flag.is_set(self.flags)
+    }
+
+    /// Check if the extern is a callback
+    #[inline]
+    pub fn is_callback(&self) -> bool {
+        self.has_flag(ExternFlags::Callback)
+    }
+}
@@ -0,0 +1,110 @@
+//! Standard holochain host functions
+//!
+//! Zomes built on the ADK import the host API as `env.__hc__<name>_<version>`
+//! functions, each taking the `(ptr, len)` of its serialized input and
+//! returning a packed [`WasmResult`](crate::WasmResult). [`hc_extern_table!`]
+//! is the one list of them: the guest crate declares the imports from it,
+//! and hosts check their imports against [`HC_EXTERN_NAMES`].
+
+/// Version suffix of the host functions in [`hc_extern_table!`]
+pub const HC_EXTERNS_VERSION: u32 = 1;
+
+/// Invoke `$callback!` with the identifiers of the standard holochain host
+/// functions, comma separated
+///
+/// Adding a host function to this table adds it to the guest declarations
+/// and to [`HC_EXTERN_NAMES`]. Every function has the signature
+/// `(ptr: u32, len: u32) -> u64`; functions without input are called with
+/// an empty slice.
+///
+/// ```ignore
+/// macro_rules! count {
+///     ($($name:ident),* $(,)?) => { [$(stringify!($name)),*].len() };
+/// }
+/// let total = aingle_wasmer_common::hc_extern_table!(count);
+/// ```
+#[macro_export]
+macro_rules! hc_extern_table {
+    ($callback:ident) => {
+        $callback! {
+            __hc__accept_countersigning_preflight_request_1,
+            __hc__agent_info_1,
+            __hc__block_agent_1,
+            __hc__call_1,
+            __hc__call_info_1,
+            __hc__call_remote_1,
+            __hc__capability_claims_1,
+            __hc__capability_grants_1,
+            __hc__capability_info_1,
+            __hc__close_chain_1,
+            __hc__count_links_1,
+            __hc__create_1,
+            __hc__create_link_1,
+            __hc__create_x25519_keypair_1,
+            __hc__delete_1,
+            __hc__delete_link_1,
+            __hc__dna_info_1,
+            __hc__emit_signal_1,
+            __hc__get_1,
+            __hc__get_agent_activity_1,
+            __hc__get_details_1,
+            __hc__get_link_details_1,
+            __hc__get_links_1,
+            __hc__hash_1,
+            __hc__must_get_action_1,
+            __hc__must_get_agent_activity_1,
+            __hc__must_get_entry_1,
+            __hc__must_get_valid_record_1,
+            __hc__open_chain_1,
+            __hc__query_1,
+            __hc__random_bytes_1,
+            __hc__remote_signal_1,
+            __hc__schedule_1,
+            __hc__sign_1,
+            __hc__sign_ephemeral_1,
+            __hc__sleep_1,
+            __hc__sys_time_1,
+            __hc__trace_1,
+            __hc__unblock_agent_1,
+            __hc__update_1,
+            __hc__verify_signature_1,
+            __hc__x_25519_x_salsa20_poly1305_decrypt_1,
+            __hc__x_25519_x_salsa20_poly1305_encrypt_1,
+            __hc__x_salsa20_poly1305_decrypt_1,
+            __hc__x_salsa20_poly1305_encrypt_1,
+            __hc__x_salsa20_poly1305_shared_secret_create_random_1,
+            __hc__x_salsa20_poly1305_shared_secret_export_1,
+            __hc__x_salsa20_poly1305_shared_secret_ingest_1,
+            __hc__zome_info_1,
+        }
+    };
+}
+
+macro_rules! hc_extern_names {
+    ($($name:ident),* $(,)?) => {
+        /// Import names of the standard holochain host functions, in the
+        /// `env` namespace
+        pub const HC_EXTERN_NAMES: &[&str] = &[$(stringify!($name)),*];
+    };
+}
+
+hc_extern_table!(hc_extern_names);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    #[test]
+    fn test_hc_extern_names() {
+        let suffix = format!("_{}", HC_EXTERNS_VERSION);
+        for name in HC_EXTERN_NAMES {
+            assert!(name.starts_with("__hc__"), "{}", name);
+            assert!(name.ends_with(&suffix), "{}", name);
+        }
+        // Sorted, hence without duplicates
+        assert!(HC_EXTERN_NAMES.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(HC_EXTERN_NAMES.contains(&"__hc__agent_info_1"));
+        assert!(HC_EXTERN_NAMES.contains(&"__hc__call_remote_1"));
+    }
+}
@@ -16,18 +16,41 @@ extern crate alloc;
 #[cfg(feature = "std")]
 extern crate std;
 
+mod abi;
+#[cfg(feature = "capi")]
+pub mod capi;
 mod envelope;
 mod error;
+mod externs;
+mod hc_externs;
+mod message;
+mod protocol;
+mod random;
 mod slice;
+mod stats;
+mod stream;
 mod traits;
+mod unit;
 
+pub use abi::*;
 pub use envelope::*;
 pub use error::*;
+pub use externs::*;
+pub use hc_externs::*;
+pub use message::*;
+pub use protocol::*;
+pub use random::*;
 pub use slice::*;
+pub use stats::*;
+pub use stream::*;
 pub use traits::*;
+pub use unit::*;
 
 /// Protocol version for the AIngle WASM envelope format
 pub const PROTOCOL_VERSION: u8 = 1;
 
+/// Oldest protocol version still read; see [`ProtocolCompat`]
+pub const MIN_SUPPORTED_PROTOCOL_VERSION: u8 = 1;
+
 /// Magic bytes identifying AIngle WASM messages: "AI" (0x4149)
 pub const MAGIC: u16 = 0x4149;
@@ -0,0 +1,226 @@
+//! Fixed-capacity error messages
+//!
+//! Guests built without `std` format errors into an [`ErrorMessage`] on the
+//! stack instead of allocating a `String`.
+
+use core::convert::Infallible;
+use core::fmt;
+use core::str::FromStr;
+
+/// Default capacity of an [`ErrorMessage`] in bytes
+pub const ERROR_MESSAGE_LEN: usize = 128;
+
+/// Error message formatted into a fixed buffer
+///
+/// Implements [`fmt::Write`]; text past the capacity is dropped at a
+/// character boundary and [`ErrorMessage::is_truncated`] is set.
+#[derive(Clone)]
+pub struct ErrorMessage<const N: usize = ERROR_MESSAGE_LEN> {
+    buffer: [u8; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<const N: usize> ErrorMessage<N> {
+    /// Create an empty message
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; N],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    /// Create a message from `text`, truncated in a const context
+    ///
+    /// Text past the capacity is dropped at the last complete character,
+    /// like writing it would.
+    ///
+    /// ```
+    /// use aingle_wasmer_common::ErrorMessage;
+    ///
+    /// const FULL: ErrorMessage<4> = ErrorMessage::from_static("abcé");
+    /// assert_eq!(FULL.as_str(), "abc");
+    /// assert!(FULL.is_truncated());
+    /// ```
+    pub const fn from_static(text: &'static str) -> Self {
+        let bytes = text.as_bytes();
+        let mut len = if bytes.len() < N { bytes.len() } else { N };
+        // Back off over the continuation bytes of a split character
+        while len < bytes.len() && bytes[len] & 0xc0 == 0x80 {
+            len -= 1;
+        }
+
+        let mut buffer = [0; N];
+        let mut i = 0;
+        while i < len {
+            buffer[i] = bytes[i];
+            i += 1;
+        }
+        Self {
+            buffer,
+            len,
+            truncated: len < bytes.len(),
+        }
+    }
+
+    /// Format `args` into a new message
+    ///
+    /// ```
+    /// use aingle_wasmer_common::ErrorMessage;
+    ///
+    /// let message: ErrorMessage = ErrorMessage::format(format_args!("code {}", 7));
+    /// assert_eq!(message.as_str(), "code 7");
+    /// ```
+    pub fn format(args: fmt::Arguments<'_>) -> Self {
+        let mut message = Self::new();
+        // Writing never fails, overflow is recorded as truncation
+        let _ = fmt::Write::write_fmt(&mut message, args);
+        message
+    }
+
+    /// The message text
+    pub fn as_str(&self) -> &str {
+        let text = core::str::from_utf8(self.as_bytes());
+        // Only whole characters are ever copied in
+        debug_assert!(text.is_ok(), "error message split a character");
+        text.unwrap_or_default()
+    }
+
+    /// The message as UTF-8 bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+
+    /// Length of the message in bytes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the message is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether text was dropped because the buffer was full
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl<const N: usize> Default for ErrorMessage<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Truncates like [`ErrorMessage::from_static`], and never fails
+impl<const N: usize> FromStr for ErrorMessage<N> {
+    type Err = Infallible;
+
+    fn from_str(text: &str) -> Result<Self, Infallible> {
+        Ok(Self::from(text))
+    }
+}
+
+impl<const N: usize> From<&str> for ErrorMessage<N> {
+    fn from(text: &str) -> Self {
+        let mut message = Self::new();
+        let _ = fmt::Write::write_str(&mut message, text);
+        message
+    }
+}
+
+impl<const N: usize> fmt::Write for ErrorMessage<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let available = N - self.len;
+        let mut take = s.len().min(available);
+        while !s.is_char_boundary(take) {
+            take -= 1;
+        }
+        self.buffer[self.len..self.len + take].copy_from_slice(&s.as_bytes()[..take]);
+        self.len += take;
+        self.truncated |= take < s.len();
+        Ok(())
+    }
+}
+
+impl<const N: usize> fmt::Display for ErrorMessage<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<const N: usize> fmt::Debug for ErrorMessage<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for ErrorMessage<N> {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl<const N: usize> PartialEq for ErrorMessage<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<const N: usize> Eq for ErrorMessage<N> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+
+    #[test]
+    fn test_error_message_truncates_at_char_boundary() {
+        let message: ErrorMessage<8> = ErrorMessage::format(format_args!("{}-{}", "ab", 12));
+        assert_eq!(message.as_str(), "ab-12");
+        assert!(!message.is_truncated());
+
+        let mut message: ErrorMessage<8> = ErrorMessage::new();
+        write!(message, "héllo wörld").unwrap();
+        // "héllo w" is 8 bytes, "ö" would straddle the end
+        assert_eq!(message.as_str(), "héllo w");
+        assert_eq!(message.len(), 8);
+        assert!(message.is_truncated());
+
+        let mut message: ErrorMessage<4> = ErrorMessage::new();
+        write!(message, "abcé").unwrap();
+        assert_eq!(message.as_str(), "abc");
+        assert!(message.is_truncated());
+    }
+
+    #[test]
+    fn test_emoji_straddling_the_capacity() {
+        // The 4-byte emoji starts two bytes before the end of the buffer
+        const TEXT: &str = concat!(
+            "0123456789012345678901234567890123456789012345678901234567890123",
+            "01234567890123456789012345678901234567890123456789012345678901",
+            "\u{1f980} overflow"
+        );
+        const STATIC: ErrorMessage = ErrorMessage::from_static(TEXT);
+        let expected = &TEXT[..ERROR_MESSAGE_LEN - 2];
+
+        assert_eq!(STATIC.as_str(), expected);
+        assert_eq!(STATIC.len(), ERROR_MESSAGE_LEN - 2);
+        assert!(STATIC.is_truncated());
+
+        let parsed: ErrorMessage = TEXT.parse().unwrap();
+        assert_eq!(parsed, STATIC);
+        assert!(parsed.is_truncated());
+
+        let fits: ErrorMessage = ErrorMessage::from_static("short");
+        assert_eq!((fits.as_str(), fits.is_truncated()), ("short", false));
+        assert_eq!(
+            ErrorMessage::<8>::from("exactly8"),
+            ErrorMessage::from_static("exactly8")
+        );
+        assert!(!ErrorMessage::<8>::from("exactly8").is_truncated());
+        assert_eq!(ErrorMessage::<0>::from_static("é").as_str(), "");
+    }
+}
@@ -0,0 +1,112 @@
+//! Compatibility between protocol versions
+//!
+//! Every side speaks [`PROTOCOL_VERSION`] and can still read messages of
+//! versions down to [`MIN_SUPPORTED_PROTOCOL_VERSION`]. [`ProtocolCompat`]
+//! is the one place deciding what a pairing of versions allows, and a
+//! [`VersionPolicy`] whether a side accepts peers on older versions.
+
+use crate::{MIN_SUPPORTED_PROTOCOL_VERSION, PROTOCOL_VERSION};
+
+/// What a side can do with a peer on another protocol version
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compatibility {
+    /// Same version: everything works
+    Full,
+    /// The peer is older but still supported: its messages can be read, but
+    /// anything sent to it must stay within its version
+    ReadOnly,
+    /// The peer is newer, or older than the oldest supported version
+    Incompatible,
+}
+
+/// Compatibility rules between protocol versions
+///
+/// | local \ remote | 0            | 1            | 2            |
+/// |----------------|--------------|--------------|--------------|
+/// | 1              | Incompatible | Full         | Incompatible |
+/// | 2              | Incompatible | ReadOnly     | Full         |
+#[derive(Clone, Copy, Debug)]
+pub struct ProtocolCompat;
+
+impl ProtocolCompat {
+    /// Compatibility of a side speaking `local` with a peer speaking `remote`
+    pub const fn check(local: u8, remote: u8) -> Compatibility {
+        if remote > local || remote < MIN_SUPPORTED_PROTOCOL_VERSION {
+            Compatibility::Incompatible
+        } else if remote == local {
+            Compatibility::Full
+        } else {
+            Compatibility::ReadOnly
+        }
+    }
+
+    /// Compatibility of this build with a peer speaking `remote`
+    pub const fn check_current(remote: u8) -> Compatibility {
+        Self::check(PROTOCOL_VERSION, remote)
+    }
+}
+
+/// Which peers a side accepts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VersionPolicy {
+    /// Accept older peers that are still supported
+    #[default]
+    AcceptOlder,
+    /// Accept only peers on the same version
+    RequireCurrent,
+}
+
+impl VersionPolicy {
+    /// Whether a peer with `compatibility` is accepted
+    pub const fn accepts(self, compatibility: Compatibility) -> bool {
+        match compatibility {
+            Compatibility::Full => true,
+            Compatibility::ReadOnly => matches!(self, VersionPolicy::AcceptOlder),
+            Compatibility::Incompatible => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compatibility_matrix() {
+        use Compatibility::*;
+
+        let expected = [
+            // local 0
+            [Incompatible, Incompatible, Incompatible],
+            // local 1
+            [Incompatible, Full, Incompatible],
+            // local 2
+            [Incompatible, ReadOnly, Full],
+        ];
+        for (local, row) in expected.iter().enumerate() {
+            for (remote, compatibility) in row.iter().enumerate() {
+                assert_eq!(
+                    ProtocolCompat::check(local as u8, remote as u8),
+                    *compatibility,
+                    "local {} remote {}",
+                    local,
+                    remote
+                );
+            }
+        }
+        assert_eq!(ProtocolCompat::check_current(PROTOCOL_VERSION), Full);
+    }
+
+    #[test]
+    fn test_version_policy() {
+        let policy = VersionPolicy::default();
+        assert!(policy.accepts(Compatibility::Full));
+        assert!(policy.accepts(Compatibility::ReadOnly));
+        assert!(!policy.accepts(Compatibility::Incompatible));
+
+        let strict = VersionPolicy::RequireCurrent;
+        assert!(strict.accepts(Compatibility::Full));
+        assert!(!strict.accepts(Compatibility::ReadOnly));
+        assert!(!strict.accepts(Compatibility::Incompatible));
+    }
+}
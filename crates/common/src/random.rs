@@ -0,0 +1,62 @@
+//! Randomness provided by the host
+//!
+//! Guests cannot reach a source of randomness on `wasm32-unknown-unknown`;
+//! they ask the host through the `env.__aingle_random_bytes` import instead.
+//! Hosts decide where the bytes come from, so replays can use a seeded
+//! stream.
+
+/// Host import filling guest memory with random bytes, in the `env`
+/// namespace: `(ptr: i32, len: i32) -> i32`
+///
+/// Returns a [`RandomStatus`] code.
+pub const RANDOM_BYTES_IMPORT: &str = "__aingle_random_bytes";
+
+/// Result of asking the host for random bytes
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RandomStatus {
+    /// The requested bytes were written
+    Ok = 0,
+    /// The host provides no randomness
+    Unavailable = 1,
+    /// The requested range lies outside guest memory
+    OutOfBounds = 2,
+}
+
+impl RandomStatus {
+    /// Parse a status code returned by the host
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            0 => Some(Self::Ok),
+            1 => Some(Self::Unavailable),
+            2 => Some(Self::OutOfBounds),
+            _ => None,
+        }
+    }
+
+    /// Short human readable description
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Unavailable => "the host provides no randomness",
+            Self::OutOfBounds => "destination outside guest memory",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_codes() {
+        for status in [
+            RandomStatus::Ok,
+            RandomStatus::Unavailable,
+            RandomStatus::OutOfBounds,
+        ] {
+            assert_eq!(RandomStatus::from_code(status as i32), Some(status));
+        }
+        assert_eq!(RandomStatus::from_code(3), None);
+    }
+}
@@ -1,5 +1,6 @@
 //! WASM memory slice types for zero-copy operations
 
+use crate::{ErrorKind, MemoryError, WasmDecode, WasmEncode, WasmError, WasmSafe};
 use core::marker::PhantomData;
 
 /// A slice of WASM memory represented as pointer + length
@@ -51,22 +52,180 @@ impl WasmSlice {
         }
     }
 
-    /// Get the end offset (ptr + len)
+    /// Get the end offset (ptr + len), or `None` if it overflows
+    #[inline]
+    pub const fn checked_end(&self) -> Option<u32> {
+        self.ptr.checked_add(self.len)
+    }
+
+    /// Get the end offset (ptr + len), saturating at `u32::MAX`
+    ///
+    /// Use [`WasmSlice::checked_end`] for slices coming from guests.
     #[inline]
     pub const fn end(&self) -> u32 {
         self.ptr.saturating_add(self.len)
     }
 
+    /// The exact end offset, which may lie past `u32::MAX`
+    #[inline]
+    const fn wide_end(&self) -> u64 {
+        self.ptr as u64 + self.len as u64
+    }
+
     /// Check if this slice overlaps with another
     #[inline]
     pub const fn overlaps(&self, other: &WasmSlice) -> bool {
-        self.ptr < other.end() && other.ptr < self.end()
+        (self.ptr as u64) < other.wide_end() && (other.ptr as u64) < self.wide_end()
     }
 
     /// Check if an offset is within this slice
     #[inline]
     pub const fn contains(&self, offset: u32) -> bool {
-        offset >= self.ptr && offset < self.end()
+        offset >= self.ptr && (offset as u64) < self.wide_end()
+    }
+
+    /// Check that the end of the slice fits in a `u32`
+    ///
+    /// Guests can return any pointer and length, so host code validates
+    /// their slices before doing arithmetic on them.
+    pub const fn validate(&self) -> Result<(), MemoryError> {
+        match self.checked_end() {
+            Some(_) => Ok(()),
+            None => Err(MemoryError::OutOfBounds {
+                offset: self.ptr as usize,
+                len: self.len as usize,
+                max: u32::MAX as usize,
+            }),
+        }
+    }
+
+    /// The `len` bytes starting `offset` bytes into this slice
+    ///
+    /// Fails with [`MemoryError::OutOfBounds`], relative to this slice, if
+    /// they do not lie within it or their start does not fit in a `u32`.
+    pub const fn subslice(&self, offset: u32, len: u32) -> Result<WasmSlice, MemoryError> {
+        match self.ptr.checked_add(offset) {
+            Some(ptr) if offset as u64 + len as u64 <= self.len as u64 => Ok(Self::new(ptr, len)),
+            _ => Err(MemoryError::OutOfBounds {
+                offset: offset as usize,
+                len: len as usize,
+                max: self.len as usize,
+            }),
+        }
+    }
+
+    /// Split the slice into the first `mid` bytes and the rest
+    ///
+    /// Fails like [`WasmSlice::subslice`] if `mid` lies past the end.
+    pub const fn split_at(&self, mid: u32) -> Result<(WasmSlice, WasmSlice), MemoryError> {
+        match self.subslice(mid, self.len.saturating_sub(mid)) {
+            Ok(tail) => Ok((Self::new(self.ptr, mid), tail)),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// A slice of 64-bit WASM memory, as used by memory64 guests
+///
+/// The 32-bit [`WasmSlice`] remains the default ABI. Guests with 64-bit
+/// memory take the slice as two `i64` parameters and return a
+/// [`WasmResult64`] as two `i64` results.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WasmSlice64 {
+    /// Pointer to the start of the data in WASM linear memory
+    pub ptr: u64,
+    /// Length of the data in bytes
+    pub len: u64,
+}
+
+impl WasmSlice64 {
+    /// Create a new 64-bit WASM slice
+    #[inline]
+    pub const fn new(ptr: u64, len: u64) -> Self {
+        Self { ptr, len }
+    }
+
+    /// Create an empty slice
+    #[inline]
+    pub const fn empty() -> Self {
+        Self { ptr: 0, len: 0 }
+    }
+
+    /// Check if the slice is empty
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Pack into a single u128
+    ///
+    /// Format: high 64 bits = ptr, low 64 bits = len
+    #[inline]
+    pub const fn pack(&self) -> u128 {
+        ((self.ptr as u128) << 64) | (self.len as u128)
+    }
+
+    /// Unpack from a u128
+    #[inline]
+    pub const fn unpack(packed: u128) -> Self {
+        Self {
+            ptr: (packed >> 64) as u64,
+            len: packed as u64,
+        }
+    }
+
+    /// Get the end offset (ptr + len), or `None` if it overflows
+    #[inline]
+    pub const fn checked_end(&self) -> Option<u64> {
+        self.ptr.checked_add(self.len)
+    }
+
+    /// Get the end offset (ptr + len), saturating at `u64::MAX`
+    ///
+    /// Use [`WasmSlice64::checked_end`] for slices coming from guests.
+    #[inline]
+    pub const fn end(&self) -> u64 {
+        self.ptr.saturating_add(self.len)
+    }
+
+    /// The exact end offset, which may lie past `u64::MAX`
+    #[inline]
+    const fn wide_end(&self) -> u128 {
+        self.ptr as u128 + self.len as u128
+    }
+
+    /// Check if this slice overlaps with another
+    #[inline]
+    pub const fn overlaps(&self, other: &WasmSlice64) -> bool {
+        (self.ptr as u128) < other.wide_end() && (other.ptr as u128) < self.wide_end()
+    }
+
+    /// Check if an offset is within this slice
+    #[inline]
+    pub const fn contains(&self, offset: u64) -> bool {
+        offset >= self.ptr && (offset as u128) < self.wide_end()
+    }
+}
+
+impl From<WasmSlice> for WasmSlice64 {
+    fn from(slice: WasmSlice) -> Self {
+        Self::new(slice.ptr as u64, slice.len as u64)
+    }
+}
+
+/// Fails unless the whole slice lies within the first 4 GiB
+impl TryFrom<WasmSlice64> for WasmSlice {
+    type Error = core::num::TryFromIntError;
+
+    fn try_from(slice: WasmSlice64) -> Result<Self, Self::Error> {
+        let ptr = u32::try_from(slice.ptr)?;
+        let len = u32::try_from(slice.len)?;
+        if len > 0 {
+            // The last byte must lie below 4 GiB too
+            u32::try_from(slice.ptr + slice.len - 1)?;
+        }
+        Ok(Self::new(ptr, len))
     }
 }
 
@@ -114,6 +273,86 @@ impl<T> WasmRef<T> {
     pub const fn is_empty(&self) -> bool {
         self.slice.is_empty()
     }
+
+    /// The bytes of `mem` the reference covers
+    ///
+    /// Fails with [`MemoryError::OutOfBounds`] naming the offset and length
+    /// if they reach past the end of `mem`.
+    pub fn bytes<'m>(&self, mem: &'m [u8]) -> Result<&'m [u8], WasmError> {
+        let range = self.range(mem.len())?;
+        Ok(&mem[range])
+    }
+
+    /// The bytes of `mem` the reference covers, mutably
+    pub fn bytes_mut<'m>(&self, mem: &'m mut [u8]) -> Result<&'m mut [u8], WasmError> {
+        let range = self.range(mem.len())?;
+        Ok(&mut mem[range])
+    }
+
+    fn range(&self, max: usize) -> Result<core::ops::Range<usize>, WasmError> {
+        let offset = self.slice.ptr as usize;
+        let len = self.slice.len as usize;
+        match offset.checked_add(len) {
+            Some(end) if end <= max => Ok(offset..end),
+            _ => Err(WasmError::Memory(MemoryError::OutOfBounds {
+                offset,
+                len,
+                max,
+            })),
+        }
+    }
+}
+
+impl<T: WasmDecode> WasmRef<T> {
+    /// Decode the value the reference points at in `mem`
+    pub fn read(&self, mem: &[u8]) -> Result<T, WasmError> {
+        T::decode_from(self.bytes(mem)?)
+    }
+}
+
+impl<T: WasmEncode> WasmRef<T> {
+    /// Encode `value` into the bytes the reference covers in `mem`
+    ///
+    /// Returns the number of bytes written, which may be fewer than the
+    /// reference covers.
+    pub fn write(&self, mem: &mut [u8], value: &T) -> Result<usize, WasmError> {
+        value.encode_to(self.bytes_mut(mem)?)
+    }
+}
+
+impl<T: WasmSafe> WasmRef<T> {
+    /// View the bytes the reference covers as an array of `T`, without
+    /// copying
+    ///
+    /// The length must be a multiple of the size of `T` and the bytes
+    /// aligned for it, or this fails with [`MemoryError::Alignment`]. WASM
+    /// memory is little-endian, so multi-byte values only read correctly on
+    /// little-endian hosts.
+    ///
+    /// # Safety
+    /// Every bit pattern in the covered bytes must be a valid `T`, which
+    /// does not hold for `bool` nor for types with padding.
+    pub unsafe fn cast_slice<'m>(&self, mem: &'m [u8]) -> Result<&'m [T], WasmError> {
+        let bytes = self.bytes(mem)?;
+        let size = core::mem::size_of::<T>();
+        let align = core::mem::align_of::<T>();
+        if size == 0 || !bytes.len().is_multiple_of(size) {
+            return Err(WasmError::Memory(MemoryError::Alignment {
+                addr: self.slice.ptr as usize + bytes.len(),
+                required: size.max(1),
+            }));
+        }
+        if !bytes.as_ptr().cast::<T>().is_aligned() {
+            return Err(WasmError::Memory(MemoryError::Alignment {
+                addr: self.slice.ptr as usize,
+                required: align,
+            }));
+        }
+        Ok(core::slice::from_raw_parts(
+            bytes.as_ptr().cast::<T>(),
+            bytes.len() / size,
+        ))
+    }
 }
 
 /// Result type for WASM operations, packed for return
@@ -121,12 +360,41 @@ impl<T> WasmRef<T> {
 /// Uses a single u64 where:
 /// - Bit 63 (high bit): 0 = Ok, 1 = Err
 /// - Bits 0-62: payload (WasmSlice packed)
+///
+/// The error bit is the pointer's top bit, so results only carry pointers
+/// below 2 GiB.
+///
+/// An error without a payload, whose slice length is zero, has no use for
+/// its pointer, and may instead carry an [`ErrorKind`] in bits 56-62, the
+/// pointer's bits 24-30, as the kind's discriminant plus one. Zero there
+/// means no kind, so results packed before the code existed read as
+/// payload-less errors of unknown kind. Ok results and errors with a
+/// payload never carry a code, and their pointers are used as they are.
+///
+/// # Empty results
+///
+/// Data never starts at offset 0, which is the null pointer in guest
+/// memory, so a zero pointer tells "nothing" apart from "data":
+///
+/// | result | pointer  | length | meaning                                |
+/// |--------|----------|--------|----------------------------------------|
+/// | Ok     | 0        | 0      | no value, [`WasmResult::ok_empty`]     |
+/// | Ok     | non-zero | > 0    | the value                              |
+/// | Err    | any      | 0      | no payload, see [`WasmResult::kind`]   |
+/// | Err    | non-zero | > 0    | the error                              |
+///
+/// Anything else, an Ok of zero length at a non-zero pointer or data at
+/// offset 0, is malformed; see [`WasmResult::is_well_formed`]. Unit
+/// results are returned with [`WasmResult::ok_empty`], which packs to
+/// the same zero as `WasmResult::ok(WasmSlice::empty())`.
 #[repr(transparent)]
 #[derive(Clone, Copy, Debug)]
 pub struct WasmResult(u64);
 
 impl WasmResult {
     const ERROR_BIT: u64 = 1 << 63;
+    const KIND_SHIFT: u32 = 56;
+    const KIND_MASK: u64 = 0x7f << Self::KIND_SHIFT;
 
     /// Create a successful result
     #[inline]
@@ -134,18 +402,82 @@ impl WasmResult {
         Self(slice.pack())
     }
 
+    /// Create a successful result without a value
+    #[inline]
+    pub const fn ok_empty() -> Self {
+        Self(0)
+    }
+
     /// Create an error result
     #[inline]
     pub const fn err(slice: WasmSlice) -> Self {
         Self(slice.pack() | Self::ERROR_BIT)
     }
 
+    /// Create an error result, recording `kind` if the slice is empty
+    ///
+    /// Errors with a payload describe themselves, and are created exactly
+    /// like [`WasmResult::err`].
+    #[inline]
+    pub const fn err_with_kind(slice: WasmSlice, kind: ErrorKind) -> Self {
+        let result = Self::err(slice);
+        if slice.len != 0 {
+            return result;
+        }
+        let code = (kind as u64 + 1) << Self::KIND_SHIFT;
+        Self((result.0 & !Self::KIND_MASK) | code)
+    }
+
     /// Check if this is an error
     #[inline]
     pub const fn is_err(&self) -> bool {
         self.0 & Self::ERROR_BIT != 0
     }
 
+    /// Whether this is an error without a payload
+    #[inline]
+    const fn is_payloadless_err(&self) -> bool {
+        self.is_err() && self.0 as u32 == 0
+    }
+
+    /// Whether this is a successful result without a value
+    #[inline]
+    pub const fn is_empty_ok(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether this is an error without a payload
+    #[inline]
+    pub const fn is_empty_err(&self) -> bool {
+        self.is_payloadless_err()
+    }
+
+    /// Whether the result follows the contract on empty results
+    ///
+    /// Malformed results are an Ok of zero length at a non-zero pointer,
+    /// and data at offset 0.
+    #[inline]
+    pub const fn is_well_formed(&self) -> bool {
+        let slice = self.slice();
+        match (self.is_ok(), slice.len) {
+            (true, 0) => slice.ptr == 0,
+            (false, 0) => true,
+            (_, _) => slice.ptr != 0,
+        }
+    }
+
+    /// Kind of a payload-less error, if it carries one
+    #[inline]
+    pub const fn kind(&self) -> Option<ErrorKind> {
+        if !self.is_payloadless_err() {
+            return None;
+        }
+        match ((self.0 & Self::KIND_MASK) >> Self::KIND_SHIFT) as u8 {
+            0 => None,
+            code => Some(ErrorKind::from_u8(code - 1)),
+        }
+    }
+
     /// Check if this is ok
     #[inline]
     pub const fn is_ok(&self) -> bool {
@@ -155,7 +487,11 @@ impl WasmResult {
     /// Get the underlying slice
     #[inline]
     pub const fn slice(&self) -> WasmSlice {
-        WasmSlice::unpack(self.0 & !Self::ERROR_BIT)
+        let mut raw = self.0 & !Self::ERROR_BIT;
+        if self.is_payloadless_err() {
+            raw &= !Self::KIND_MASK;
+        }
+        WasmSlice::unpack(raw)
     }
 
     /// Convert to raw u64
@@ -171,7 +507,221 @@ impl WasmResult {
     }
 }
 
+/// Result type for 64-bit WASM operations, packed for return
+///
+/// Uses a u128 where:
+/// - Bit 127 (high bit): 0 = Ok, 1 = Err
+/// - Bits 0-126: payload ([`WasmSlice64`] packed)
+///
+/// As with [`WasmResult`], the error bit is the pointer's top bit.
+///
+/// Guests return it as two `i64` results, see [`WasmResult64::into_parts`].
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug)]
+pub struct WasmResult64(u128);
+
+impl WasmResult64 {
+    const ERROR_BIT: u128 = 1 << 127;
+
+    /// Create a successful result
+    #[inline]
+    pub const fn ok(slice: WasmSlice64) -> Self {
+        Self(slice.pack())
+    }
+
+    /// Create an error result
+    #[inline]
+    pub const fn err(slice: WasmSlice64) -> Self {
+        Self(slice.pack() | Self::ERROR_BIT)
+    }
+
+    /// Check if this is an error
+    #[inline]
+    pub const fn is_err(&self) -> bool {
+        self.0 & Self::ERROR_BIT != 0
+    }
+
+    /// Check if this is ok
+    #[inline]
+    pub const fn is_ok(&self) -> bool {
+        !self.is_err()
+    }
+
+    /// Get the underlying slice
+    #[inline]
+    pub const fn slice(&self) -> WasmSlice64 {
+        WasmSlice64::unpack(self.0 & !Self::ERROR_BIT)
+    }
+
+    /// Convert to raw u128
+    #[inline]
+    pub const fn into_raw(self) -> u128 {
+        self.0
+    }
+
+    /// Create from raw u128
+    #[inline]
+    pub const fn from_raw(raw: u128) -> Self {
+        Self(raw)
+    }
+
+    /// Split into the two `i64` results of a guest export: the pointer with
+    /// the error bit, and the length
+    #[inline]
+    pub const fn into_parts(self) -> (u64, u64) {
+        ((self.0 >> 64) as u64, self.0 as u64)
+    }
+
+    /// Join the two `i64` results of a guest export
+    #[inline]
+    pub const fn from_parts(high: u64, low: u64) -> Self {
+        Self(((high as u128) << 64) | low as u128)
+    }
+}
+
+/// Guest export allocating memory in a 64-bit guest: `(i64) -> i64`
+///
+/// Returns 0 if the allocation fails, like the 32-bit allocator.
+pub const GUEST_ALLOCATE64_EXPORT: &str = "__aingle_guest_allocate64";
+
+/// Value a guest function returns to the host: a packed [`WasmResult`]
+///
+/// Wraps the `u64` the call convention hands across, so a packed result is
+/// not mistaken for a packed [`WasmSlice`] or a plain length. Being
+/// `#[repr(transparent)]`, it is a bare `u64` to `extern "C"` functions.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GuestReturn(u64);
+
+impl GuestReturn {
+    /// Return `result`
+    #[inline]
+    pub const fn from_result(result: WasmResult) -> Self {
+        Self(result.into_raw())
+    }
+
+    /// Return `slice` as a successful result
+    #[inline]
+    pub const fn from_slice(slice: WasmSlice) -> Self {
+        Self::from_result(WasmResult::ok(slice))
+    }
+
+    /// Return success without a value
+    #[inline]
+    pub const fn empty() -> Self {
+        Self::from_result(WasmResult::ok_empty())
+    }
+
+    /// The result returned
+    #[inline]
+    pub const fn as_result(&self) -> WasmResult {
+        WasmResult::from_raw(self.0)
+    }
+
+    /// The slice returned, whether it holds a value or an error
+    #[inline]
+    pub const fn as_slice(&self) -> WasmSlice {
+        self.as_result().slice()
+    }
+
+    /// Convert to the raw u64 of the call convention
+    #[inline]
+    pub const fn into_raw(self) -> u64 {
+        self.0
+    }
+
+    /// Create from the raw u64 of the call convention
+    #[inline]
+    pub const fn from_raw(raw: u64) -> Self {
+        Self(raw)
+    }
+}
+
+impl From<WasmResult> for GuestReturn {
+    fn from(result: WasmResult) -> Self {
+        Self::from_result(result)
+    }
+}
+
+impl From<GuestReturn> for WasmResult {
+    fn from(value: GuestReturn) -> Self {
+        value.as_result()
+    }
+}
+
+impl From<u64> for GuestReturn {
+    fn from(raw: u64) -> Self {
+        Self::from_raw(raw)
+    }
+}
+
+impl From<GuestReturn> for u64 {
+    fn from(value: GuestReturn) -> Self {
+        value.into_raw()
+    }
+}
+
+/// Order of the pointer and the length in a packed [`WasmSlice`]
+///
+/// [`WasmSlice::pack`] puts the pointer in the high half. Guests built
+/// against releases predating [`GuestReturn`] packed their `DoubleUSize`
+/// results the other way round, which hosts undo for them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SliceOrder {
+    /// Pointer in the high 32 bits, as [`WasmSlice::pack`] does
+    #[default]
+    PtrLen,
+    /// Length in the high 32 bits, as legacy guests return their results
+    LenPtr,
+}
+
+impl SliceOrder {
+    /// Pack `slice` in this order
+    #[inline]
+    pub const fn pack(self, slice: WasmSlice) -> u64 {
+        match self {
+            Self::PtrLen => slice.pack(),
+            Self::LenPtr => ((slice.len as u64) << 32) | (slice.ptr as u64),
+        }
+    }
+
+    /// Unpack a slice packed in this order
+    #[inline]
+    pub const fn unpack(self, packed: u64) -> WasmSlice {
+        match self {
+            Self::PtrLen => WasmSlice::unpack(packed),
+            Self::LenPtr => WasmSlice::new(packed as u32, (packed >> 32) as u32),
+        }
+    }
+
+    /// The order `packed` was packed in, judging by which reading fits
+    ///
+    /// A reading fits if its slice is not empty, ends within the first
+    /// `memory_len` bytes of memory and `plausible` accepts it. Returns
+    /// `None` if both or neither reading fits, or both are the same slice,
+    /// as the value tells nothing about the order then.
+    pub fn detect(
+        packed: u64,
+        memory_len: u64,
+        mut plausible: impl FnMut(WasmSlice) -> bool,
+    ) -> Option<Self> {
+        let (ptr_len, len_ptr) = (Self::PtrLen.unpack(packed), Self::LenPtr.unpack(packed));
+        if ptr_len == len_ptr {
+            return None;
+        }
+        let mut fits = |slice: WasmSlice| {
+            !slice.is_empty() && slice.wide_end() <= memory_len && plausible(slice)
+        };
+        match (fits(ptr_len), fits(len_ptr)) {
+            (true, false) => Some(Self::PtrLen),
+            (false, true) => Some(Self::LenPtr),
+            _ => None,
+        }
+    }
+}
+
 /// Double usize for guest function returns (compatibility type)
+#[deprecated(note = "use `GuestReturn`, which converts from and into `u64`")]
 pub type DoubleUSize = u64;
 
 #[cfg(test)]
@@ -188,6 +738,41 @@ mod tests {
         assert_eq!(unpacked.len, 0x1000);
     }
 
+    #[test]
+    fn test_slice_order() {
+        let slice = WasmSlice::new(64, 5);
+        for order in [SliceOrder::PtrLen, SliceOrder::LenPtr] {
+            assert_eq!(order.unpack(order.pack(slice)), slice);
+        }
+        assert_eq!(SliceOrder::PtrLen.pack(slice), slice.pack());
+        assert_eq!(SliceOrder::LenPtr.pack(slice), 5 << 32 | 64);
+
+        // Only the legacy reading points at the payload
+        let at_payload = |s: WasmSlice| s == slice;
+        let packed = SliceOrder::LenPtr.pack(slice);
+        assert_eq!(
+            SliceOrder::detect(packed, 1024, at_payload),
+            Some(SliceOrder::LenPtr)
+        );
+        assert_eq!(
+            SliceOrder::detect(slice.pack(), 1024, at_payload),
+            Some(SliceOrder::PtrLen)
+        );
+
+        // Both readings end at the same offset, so only emptiness and the
+        // payload tell them apart
+        assert_eq!(
+            SliceOrder::detect(5, 1024, |_| true),
+            Some(SliceOrder::PtrLen)
+        );
+        assert_eq!(SliceOrder::detect(packed, 68, at_payload), None);
+
+        // Ambiguous or uninformative values decide nothing
+        assert_eq!(SliceOrder::detect(packed, 1024, |_| true), None);
+        assert_eq!(SliceOrder::detect(7 << 32 | 7, 1024, |_| true), None);
+        assert_eq!(SliceOrder::detect(0, 1024, |_| true), None);
+    }
+
     #[test]
     fn test_slice_overlap() {
         let a = WasmSlice::new(100, 50); // 100-150
@@ -200,6 +785,151 @@ mod tests {
         assert!(!c.overlaps(&a));
     }
 
+    /// Offsets around the ends of the 32-bit address space
+    const EDGES: [u32; 7] = [0, 1, 2, 1 << 31, u32::MAX - 2, u32::MAX - 1, u32::MAX];
+
+    #[test]
+    fn test_slice_checked_arithmetic() {
+        for ptr in EDGES {
+            for len in EDGES {
+                let slice = WasmSlice::new(ptr, len);
+                let end = ptr as u64 + len as u64;
+                let fits = end <= u32::MAX as u64;
+                assert_eq!(slice.checked_end(), fits.then_some(end as u32));
+                assert_eq!(slice.validate().is_ok(), fits);
+                assert_eq!(slice.end() as u64, end.min(u32::MAX as u64));
+
+                for offset in EDGES {
+                    let inside = offset >= ptr && (offset as u64) < end;
+                    assert_eq!(slice.contains(offset), inside, "{:?} {}", slice, offset);
+                    let other = WasmSlice::new(offset, 1);
+                    let overlap = len > 0 && inside;
+                    assert_eq!(slice.overlaps(&other), overlap, "{:?} {}", slice, offset);
+                }
+
+                for mid in EDGES {
+                    let starts = ptr as u64 + mid as u64 <= u32::MAX as u64;
+                    match slice.split_at(mid) {
+                        Ok((head, tail)) => {
+                            assert!(mid <= len && starts);
+                            assert_eq!(head, WasmSlice::new(ptr, mid));
+                            assert_eq!(tail.len, len - mid);
+                            assert_eq!(tail.ptr as u64, ptr as u64 + mid as u64);
+                        }
+                        Err(e) => {
+                            assert!(mid > len || !starts);
+                            assert!(matches!(e, MemoryError::OutOfBounds { .. }));
+                        }
+                    }
+                    for sub_len in EDGES {
+                        let fits = starts && mid as u64 + sub_len as u64 <= len as u64;
+                        let sub = slice.subslice(mid, sub_len);
+                        assert_eq!(sub.is_ok(), fits);
+                        if let Ok(sub) = sub {
+                            assert_eq!((sub.ptr - ptr, sub.len), (mid, sub_len));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_slice_overflow() {
+        // Saturating ends no longer make a wrapped slice look huge
+        let top = WasmSlice::new(u32::MAX - 4, 16);
+        assert_eq!(top.checked_end(), None);
+        assert_eq!(
+            top.validate(),
+            Err(MemoryError::OutOfBounds {
+                offset: (u32::MAX - 4) as usize,
+                len: 16,
+                max: u32::MAX as usize,
+            })
+        );
+        assert!(!top.contains(3));
+        assert!(top.contains(u32::MAX));
+
+        let slice = WasmSlice::new(100, 10);
+        assert_eq!(slice.subslice(4, 6), Ok(WasmSlice::new(104, 6)));
+        assert_eq!(
+            slice.subslice(4, 7),
+            Err(MemoryError::OutOfBounds {
+                offset: 4,
+                len: 7,
+                max: 10
+            })
+        );
+        assert_eq!(slice.split_at(10), Ok((slice, WasmSlice::new(110, 0))));
+    }
+
+    #[test]
+    fn test_slice64_pack_unpack() {
+        let slice = WasmSlice64::new(0x1_2345_6789, 0x2_0000_0000);
+        assert_eq!(WasmSlice64::unpack(slice.pack()), slice);
+        assert_eq!(slice.end(), 0x3_2345_6789);
+        assert!(slice.contains(0x1_2345_6789));
+        assert!(!slice.contains(slice.end()));
+    }
+
+    #[test]
+    fn test_slice64_4gib_boundary() {
+        const GIB4: u64 = 1 << 32;
+
+        // The last byte below 4 GiB still fits a 32-bit slice
+        let last = WasmSlice64::new(GIB4 - 1, 1);
+        assert_eq!(last.end(), GIB4);
+        assert_eq!(
+            WasmSlice::try_from(last).unwrap(),
+            WasmSlice::new(u32::MAX, 1)
+        );
+        assert!(WasmSlice::try_from(WasmSlice64::new(GIB4 - 1, 0)).is_ok());
+
+        // Slices reaching past 4 GiB do not
+        assert!(WasmSlice::try_from(WasmSlice64::new(GIB4 - 1, 2)).is_err());
+        assert!(WasmSlice::try_from(WasmSlice64::new(GIB4, 0)).is_err());
+        assert!(WasmSlice::try_from(WasmSlice64::new(0, GIB4)).is_err());
+        assert_eq!(
+            WasmSlice64::from(WasmSlice::new(u32::MAX, u32::MAX)).end(),
+            2 * GIB4 - 2
+        );
+
+        // A slice across the boundary overlaps both sides of it
+        let across = WasmSlice64::new(GIB4 - 8, 16);
+        assert!(across.overlaps(&WasmSlice64::new(0, GIB4)));
+        assert!(across.overlaps(&WasmSlice64::new(GIB4, 8)));
+        assert!(!WasmSlice64::new(0, GIB4).overlaps(&WasmSlice64::new(GIB4, 8)));
+    }
+
+    #[test]
+    fn test_slice64_overflow() {
+        let top = WasmSlice64::new(u64::MAX - 4, 16);
+        assert_eq!(top.checked_end(), None);
+        assert_eq!(top.end(), u64::MAX);
+        assert!(top.overlaps(&WasmSlice64::new(u64::MAX - 1, 1)));
+        assert!(!top.overlaps(&WasmSlice64::new(0, u64::MAX - 4)));
+        assert!(WasmSlice::try_from(top).is_err());
+    }
+
+    #[test]
+    fn test_wasm_result64() {
+        let slice = WasmSlice64::new(5 << 32, 1 << 33);
+        let ok = WasmResult64::ok(slice);
+        assert!(ok.is_ok());
+        assert_eq!(ok.slice(), slice);
+
+        let err = WasmResult64::err(slice);
+        assert!(err.is_err());
+        assert_eq!(err.slice(), slice);
+
+        let (high, low) = err.into_parts();
+        assert_eq!(high, (5 << 32) | 1 << 63);
+        assert_eq!(low, 1 << 33);
+        let joined = WasmResult64::from_parts(high, low);
+        assert!(joined.is_err());
+        assert_eq!(joined.into_raw(), err.into_raw());
+    }
+
     #[test]
     fn test_wasm_result() {
         let ok = WasmResult::ok(WasmSlice::new(100, 50));
@@ -212,4 +942,237 @@ mod tests {
         assert!(!err.is_ok());
         assert_eq!(err.slice().ptr, 200);
     }
+
+    #[test]
+    fn test_empty_results() {
+        // Ok and Err, each empty and with data
+        let empty_ok = WasmResult::ok_empty();
+        assert!(empty_ok.is_ok() && empty_ok.is_empty_ok() && !empty_ok.is_empty_err());
+        assert!(empty_ok.is_well_formed());
+        assert_eq!(empty_ok.slice(), WasmSlice::empty());
+        assert_eq!(
+            empty_ok.into_raw(),
+            WasmResult::ok(WasmSlice::empty()).into_raw()
+        );
+
+        let ok = WasmResult::ok(WasmSlice::new(64, 8));
+        assert!(ok.is_ok() && !ok.is_empty_ok() && !ok.is_empty_err());
+        assert!(ok.is_well_formed());
+
+        let empty_err = WasmResult::err_with_kind(WasmSlice::empty(), ErrorKind::Memory);
+        assert!(empty_err.is_err() && empty_err.is_empty_err() && !empty_err.is_empty_ok());
+        assert!(empty_err.is_well_formed());
+        assert!(WasmResult::err(WasmSlice::empty()).is_empty_err());
+
+        let err = WasmResult::err(WasmSlice::new(64, 8));
+        assert!(err.is_err() && !err.is_empty_err() && !err.is_empty_ok());
+        assert!(err.is_well_formed());
+
+        // Zero-length data somewhere, and data at offset 0
+        assert!(!WasmResult::ok(WasmSlice::new(64, 0)).is_empty_ok());
+        assert!(!WasmResult::ok(WasmSlice::new(64, 0)).is_well_formed());
+        assert!(!WasmResult::ok(WasmSlice::new(0, 8)).is_well_formed());
+        assert!(!WasmResult::err(WasmSlice::new(0, 8)).is_well_formed());
+
+        // Through the raw value crossing the boundary
+        for result in [empty_ok, ok, empty_err, err] {
+            let raw = WasmResult::from_raw(GuestReturn::from(result).into_raw());
+            assert_eq!(
+                (raw.is_empty_ok(), raw.is_empty_err(), raw.slice()),
+                (result.is_empty_ok(), result.is_empty_err(), result.slice())
+            );
+        }
+    }
+
+    #[test]
+    fn test_wasm_ref_read_write() {
+        let mut mem = [0u8; 16];
+        let counter = WasmRef::<u32>::new(WasmSlice::new(8, 4));
+        assert_eq!(counter.write(&mut mem, &0xdead_beef), Ok(4));
+        assert_eq!(&mem[8..12], &0xdead_beefu32.to_le_bytes());
+        assert_eq!(counter.read(&mem), Ok(0xdead_beef));
+
+        // The last bytes of memory are in bounds
+        let last = WasmRef::<u32>::new(WasmSlice::new(12, 4));
+        assert_eq!(last.write(&mut mem, &7), Ok(4));
+        assert_eq!(last.read(&mem), Ok(7));
+    }
+
+    #[test]
+    fn test_wasm_ref_failures() {
+        let mut mem = [0u8; 16];
+        let out_of_bounds = |offset, len| {
+            WasmError::Memory(MemoryError::OutOfBounds {
+                offset,
+                len,
+                max: 16,
+            })
+        };
+
+        let past_end = WasmRef::<u32>::new(WasmSlice::new(14, 4));
+        assert_eq!(past_end.read(&mem), Err(out_of_bounds(14, 4)));
+        assert_eq!(past_end.write(&mut mem, &1), Err(out_of_bounds(14, 4)));
+        let beyond = WasmRef::<u32>::new(WasmSlice::new(u32::MAX, u32::MAX));
+        assert_eq!(
+            beyond.read(&mem),
+            Err(out_of_bounds(u32::MAX as usize, u32::MAX as usize))
+        );
+        assert_eq!(mem, [0; 16]);
+
+        // Errors of the value's own encoding pass through
+        let short = WasmRef::<u32>::new(WasmSlice::new(0, 2));
+        assert_eq!(
+            short.read(&mem),
+            Err(WasmError::Deserialize(
+                crate::DeserializeError::UnexpectedEof
+            ))
+        );
+        assert!(matches!(
+            short.write(&mut mem, &1),
+            Err(WasmError::Serialize(_))
+        ));
+    }
+
+    #[test]
+    fn test_wasm_ref_cast_slice() {
+        #[repr(align(4))]
+        struct Aligned([u8; 16]);
+
+        let mut mem = Aligned([0; 16]);
+        for (i, chunk) in mem.0.chunks_exact_mut(4).enumerate() {
+            chunk.copy_from_slice(&(i as f32 * 0.5).to_le_bytes());
+        }
+
+        let tensor = WasmRef::<f32>::new(WasmSlice::new(4, 12));
+        assert_eq!(
+            unsafe { tensor.cast_slice(&mem.0) },
+            Ok(&[0.5f32, 1.0, 1.5][..])
+        );
+        let empty = WasmRef::<f32>::new(WasmSlice::new(16, 0));
+        assert_eq!(unsafe { empty.cast_slice(&mem.0) }, Ok(&[][..]));
+
+        let misaligned = WasmRef::<f32>::new(WasmSlice::new(2, 8));
+        assert_eq!(
+            unsafe { misaligned.cast_slice(&mem.0) },
+            Err(WasmError::Memory(MemoryError::Alignment {
+                addr: 2,
+                required: 4
+            }))
+        );
+        let partial = WasmRef::<f32>::new(WasmSlice::new(4, 6));
+        assert_eq!(
+            unsafe { partial.cast_slice(&mem.0) },
+            Err(WasmError::Memory(MemoryError::Alignment {
+                addr: 10,
+                required: 4
+            }))
+        );
+        let past_end = WasmRef::<f32>::new(WasmSlice::new(8, 12));
+        assert!(matches!(
+            unsafe { past_end.cast_slice(&mem.0) },
+            Err(WasmError::Memory(MemoryError::OutOfBounds {
+                offset: 8,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_guest_return() {
+        assert_eq!(
+            core::mem::size_of::<GuestReturn>(),
+            core::mem::size_of::<u64>()
+        );
+        assert_eq!(
+            core::mem::align_of::<GuestReturn>(),
+            core::mem::align_of::<u64>()
+        );
+
+        let slice = WasmSlice::new(0x1000, 24);
+        let ok = GuestReturn::from_slice(slice);
+        assert_eq!(ok.into_raw(), WasmResult::ok(slice).into_raw());
+        assert!(ok.as_result().is_ok());
+        assert_eq!(ok.as_slice(), slice);
+
+        let err = GuestReturn::from_result(WasmResult::err(slice));
+        assert!(err.as_result().is_err());
+        assert_eq!(err.as_slice(), slice);
+        assert_eq!(u64::from(err), err.into_raw());
+        assert_eq!(GuestReturn::from(err.into_raw()), err);
+
+        // The extern "C" ABI sees a bare u64
+        extern "C" fn returns(raw: u64) -> GuestReturn {
+            GuestReturn::from_raw(raw)
+        }
+        let as_u64: extern "C" fn(u64) -> u64 =
+            unsafe { core::mem::transmute(returns as extern "C" fn(u64) -> GuestReturn) };
+        assert_eq!(as_u64(err.into_raw()), err.into_raw());
+    }
+
+    /// Pointers around the bits a kind code occupies
+    const PTRS: [u32; 8] = [
+        0,
+        1,
+        (1 << 24) - 1,
+        1 << 24,
+        0x7f << 24,
+        (1 << 31) - 1,
+        0x5555_5555,
+        0x2aaa_aaaa,
+    ];
+
+    const LENS: [u32; 6] = [0, 1, 2, 0xff, 1 << 31, u32::MAX];
+
+    #[test]
+    fn test_wasm_result_kind_layout() {
+        for &kind in ErrorKind::ALL {
+            for ptr in PTRS {
+                // Payload-less errors carry the kind and read as empty
+                let err = WasmResult::err_with_kind(WasmSlice::new(ptr, 0), kind);
+                assert!(err.is_err());
+                assert_eq!(err.kind(), Some(kind));
+                assert_eq!(err.slice().len, 0);
+                assert_eq!(err.slice().ptr, ptr & !(0x7f << 24));
+                assert_eq!((err.into_raw() >> 56) & 0x7f, kind as u64 + 1);
+                let raw = WasmResult::from_raw(err.into_raw());
+                assert_eq!((raw.kind(), raw.slice()), (Some(kind), err.slice()));
+
+                for len in LENS.into_iter().filter(|&len| len != 0) {
+                    // Errors with a payload are left untouched
+                    let slice = WasmSlice::new(ptr, len);
+                    let err = WasmResult::err_with_kind(slice, kind);
+                    assert_eq!(err.into_raw(), WasmResult::err(slice).into_raw());
+                    assert_eq!((err.kind(), err.slice()), (None, slice));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_wasm_result_without_kind() {
+        for ptr in PTRS {
+            for len in LENS {
+                let slice = WasmSlice::new(ptr, len);
+                let ok = WasmResult::ok(slice);
+                assert_eq!((ok.is_ok(), ok.kind(), ok.slice()), (true, None, slice));
+                assert_eq!(
+                    WasmResult::from_raw(ok.into_raw()).into_raw(),
+                    ok.into_raw()
+                );
+
+                if ptr >> 24 == 0 || len != 0 {
+                    let err = WasmResult::err(slice);
+                    assert_eq!((err.is_err(), err.kind(), err.slice()), (true, None, slice));
+                }
+            }
+        }
+
+        // Every code decodes, unassigned ones to the unknown kind
+        for code in 1..=0x7fu64 {
+            let err = WasmResult::from_raw(1 << 63 | code << 56);
+            let expected = ErrorKind::from_u8(code as u8 - 1);
+            assert_eq!(err.kind(), Some(expected));
+            assert_eq!(err.slice(), WasmSlice::empty());
+        }
+    }
 }
@@ -0,0 +1,40 @@
+//! Guest arena statistics and controls exported to the host
+//!
+//! Guests built with the guest crate export these functions; the host reads
+//! the statistics after each call when present.
+
+/// Guest export returning the bytes currently held by the arena: `() -> i32`
+pub const GUEST_ALLOCATED_BYTES_EXPORT: &str = "__aingle_guest_allocated_bytes";
+
+/// Guest export returning the arena high-water mark: `() -> i32`
+///
+/// The mark survives arena resets, so it covers every call since the guest
+/// last restarted tracking.
+pub const GUEST_HIGH_WATER_EXPORT: &str = "__aingle_guest_high_water";
+
+/// Guest export limiting the bytes the arena may hold, 0 for no limit:
+/// `(i32) -> ()`
+pub const GUEST_SET_ARENA_LIMIT_EXPORT: &str = "__aingle_guest_set_arena_limit";
+
+/// Guest export making room for the given bytes in the arena ahead of the
+/// first call: `(i32) -> ()`
+pub const GUEST_PREALLOC_EXPORT: &str = "__aingle_guest_prealloc";
+
+/// Guest export reserving a buffer of at least the given bytes outside the
+/// arena, returning its address or 0: `(i32) -> i32`
+///
+/// The buffer lives as long as the instance. Hosts write data that fits to
+/// it instead of allocating for each write.
+pub const GUEST_SCRATCH_EXPORT: &str = "__aingle_guest_scratch";
+
+/// Guest export setting the arena aside for a call nested in a host call,
+/// returning the number of frames pushed: `() -> i32`
+pub const GUEST_ARENA_PUSH_EXPORT: &str = "__aingle_arena_push";
+
+/// Guest export dropping the arena of a nested call and restoring the one
+/// set aside, returning the number of frames left or -1: `() -> i32`
+pub const GUEST_ARENA_POP_EXPORT: &str = "__aingle_arena_pop";
+
+/// Guest export returning the error result published by a panic that
+/// aborted the call, or 0: `() -> i64`
+pub const GUEST_LAST_PANIC_EXPORT: &str = "__aingle_guest_last_panic";
@@ -0,0 +1,96 @@
+//! Chunked streaming protocol
+//!
+//! Large inputs are fed to the guest in chunks instead of a single
+//! contiguous allocation:
+//!
+//! 1. `__aingle_stream_begin(total_len) -> handle` opens a stream
+//! 2. `__aingle_stream_write(handle, ptr, len) -> status` hands over each chunk
+//! 3. the target function is called with `(handle, total_len)`
+//!
+//! Large outputs travel the other way through a host import:
+//!
+//! 1. the guest calls `env.__aingle_output_chunk(ptr, len) -> status` for
+//!    each chunk, which the host appends to the call's output; a guest
+//!    streaming nothing still calls it once, with an empty chunk
+//! 2. the function returns an envelope whose payload is the total length
+//!    as a little-endian `u64`, read as such because chunks were sent
+//! 3. the host returns the collected chunks as the result of the call
+
+/// Guest export opening a stream: `(total_len: i32) -> i32`
+///
+/// Returns a positive handle, or zero if the stream cannot be opened.
+pub const STREAM_BEGIN_EXPORT: &str = "__aingle_stream_begin";
+
+/// Guest export receiving a chunk: `(handle: i32, ptr: i32, len: i32) -> i32`
+///
+/// Returns a [`StreamStatus`] code.
+pub const STREAM_WRITE_EXPORT: &str = "__aingle_stream_write";
+
+/// Host import receiving a chunk of streamed output, in the `env` namespace:
+/// `(ptr: i32, len: i32) -> i32`
+///
+/// Returns a [`StreamStatus`] code.
+pub const OUTPUT_CHUNK_IMPORT: &str = "__aingle_output_chunk";
+
+/// Host import recording a step the guest reports in the trace of the call,
+/// in the `env` namespace: `(ptr: i32, len: i32) -> ()`
+///
+/// The bytes are a UTF-8 message. Hosts not explaining the call ignore it.
+pub const TRACE_IMPORT: &str = "__aingle_trace";
+
+/// Result of handing a chunk to the guest, or a chunk of output to the host
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamStatus {
+    /// Chunk accepted
+    Ok = 0,
+    /// The handle was never opened or its stream was already consumed, or
+    /// no call is collecting output
+    OutOfOrder = 1,
+    /// The chunk would exceed the announced total length, or the host's
+    /// output limit
+    OverLength = 2,
+    /// The chunk lies outside guest memory
+    OutOfBounds = 3,
+}
+
+impl StreamStatus {
+    /// Parse a status code returned by the guest
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            0 => Some(Self::Ok),
+            1 => Some(Self::OutOfOrder),
+            2 => Some(Self::OverLength),
+            3 => Some(Self::OutOfBounds),
+            _ => None,
+        }
+    }
+
+    /// Short human readable description
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::OutOfOrder => "write to a stream that is not open",
+            Self::OverLength => "write past the announced stream length",
+            Self::OutOfBounds => "chunk outside guest memory",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_codes() {
+        for status in [
+            StreamStatus::Ok,
+            StreamStatus::OutOfOrder,
+            StreamStatus::OverLength,
+            StreamStatus::OutOfBounds,
+        ] {
+            assert_eq!(StreamStatus::from_code(status as i32), Some(status));
+        }
+        assert_eq!(StreamStatus::from_code(-1), None);
+    }
+}
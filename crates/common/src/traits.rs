@@ -1,6 +1,8 @@
 //! Traits for WASM serialization and guest/host communication
 
-use crate::{WasmError, WasmSlice};
+use crate::{DeserializeError, SerializeError, WasmError, WasmSlice};
+use core::fmt;
+use serde::{de::DeserializeOwned, Serialize};
 
 /// Trait for types that can be encoded to WASM memory
 pub trait WasmEncode {
@@ -22,6 +24,20 @@ pub trait WasmDecode: Sized {
 /// Trait for types that can be passed to/from WASM as a single value
 ///
 /// This is for primitive types that fit in a WASM value (i32, i64, f32, f64).
+/// Integers narrower than 32 bits travel as `i32`: unsigned ones
+/// zero-extended, signed ones sign-extended, and `from_wasm` keeps the low
+/// bits like WASM's narrowing stores. `char` travels as its `u32` scalar
+/// value.
+///
+/// `usize` has no impl on purpose: it is 32 bits in the guest and usually
+/// 64 on the host, so a value could not make the round trip. Pick `u32` or
+/// `u64` explicitly.
+///
+/// ```compile_fail
+/// use aingle_wasmer_common::WasmPrimitive;
+///
+/// let _ = 1usize.to_wasm();
+/// ```
 pub trait WasmPrimitive: Copy {
     /// The WASM type this maps to
     type WasmType: Copy;
@@ -34,6 +50,9 @@ pub trait WasmPrimitive: Copy {
 }
 
 /// Trait for host functions that can be imported into WASM
+///
+/// The host crate's `HostFunctionRegistry` turns implementations into
+/// `env.NAME` imports that guests call with `host_call_enveloped`.
 pub trait HostFunction<Args, Ret> {
     /// The name of the function
     const NAME: &'static str;
@@ -43,19 +62,127 @@ pub trait HostFunction<Args, Ret> {
 }
 
 /// Trait for guest functions that can be called from the host
+///
+/// Implemented by marker types declared with [`guest_fn!`](crate::guest_fn),
+/// naming an export together with the types it exchanges, so a guest and
+/// its hosts can share one definition from an interface crate.
 pub trait GuestFunction {
     /// The name of the function
     const NAME: &'static str;
 
     /// The input type
-    type Input: WasmEncode;
+    type Input: Serialize + DeserializeOwned + fmt::Debug;
 
     /// The output type
-    type Output: WasmDecode;
+    type Output: Serialize + DeserializeOwned + fmt::Debug;
+}
+
+/// Declare guest functions, or export one from a guest
+///
+/// The declaring form defines a marker type implementing [`GuestFunction`]
+/// per function, typically in an interface crate shared by the guest and
+/// its hosts:
+///
+/// ```
+/// use aingle_wasmer_common::{guest_fn, GuestFunction};
+///
+/// guest_fn! {
+///     /// Greet someone by name
+///     pub struct Greet => fn greet(String) -> String;
+///     /// Add up numbers
+///     pub struct Sum => fn sum(Vec<u32>) -> u64;
+/// }
+///
+/// assert_eq!(Greet::NAME, "greet");
+/// ```
+///
+/// The exporting form, used in a guest, exports `inner_fn` under the
+/// marker's name like `map_extern!`. `inner_fn` takes the marker's input
+/// and returns `Result<Output, E>` with `WasmError: From<E>`; an export
+/// name or types differing from the declaration fail the build:
+///
+/// ```ignore
+/// fn greet_by_name(name: String) -> Result<String, WasmError> {
+///     Ok(format!("hello {}", name))
+/// }
+///
+/// guest_fn!(impl interface::Greet as greet => greet_by_name);
+/// ```
+#[macro_export]
+macro_rules! guest_fn {
+    (impl $marker:path as $extern_name:ident => $inner_fn:ident) => {
+        mod $extern_name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            const _: () = assert!(
+                $crate::__names_match(
+                    <$marker as $crate::GuestFunction>::NAME,
+                    stringify!($extern_name),
+                ),
+                concat!(
+                    "`", stringify!($extern_name), "` is not the name declared by `",
+                    stringify!($marker), "`",
+                ),
+            );
+
+            #[no_mangle]
+            pub extern "C" fn $extern_name(
+                guest_ptr: u32,
+                len: u32,
+            ) -> ::aingle_wasmer_guest::GuestReturn {
+                ::aingle_wasmer_guest::__entry(
+                    guest_ptr,
+                    len,
+                    |input: <$marker as $crate::GuestFunction>::Input| -> ::core::result::Result<
+                        <$marker as $crate::GuestFunction>::Output,
+                        $crate::WasmError,
+                    > { super::$inner_fn(input).map_err($crate::WasmError::from) },
+                )
+            }
+        }
+    };
+    ($(
+        $(#[$meta:meta])*
+        $vis:vis struct $marker:ident => fn $name:ident($input:ty) -> $output:ty;
+    )*) => {
+        $(
+            $(#[$meta])*
+            #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+            $vis struct $marker;
+
+            impl $crate::GuestFunction for $marker {
+                const NAME: &'static str = stringify!($name);
+                type Input = $input;
+                type Output = $output;
+            }
+        )*
+    };
+}
+
+/// Whether two names are equal, usable in constants
+#[doc(hidden)]
+pub const fn __names_match(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
 }
 
 /// Marker trait for types safe to share between host and guest
 ///
+/// Structs get it from `#[derive(WasmSafe)]` in the guest crate, which
+/// checks the conditions below; check manual impls with
+/// [`static_assert_wasm_safe!`](crate::static_assert_wasm_safe).
+///
 /// # Safety
 /// Only implement this for types that:
 /// - Have a stable memory layout
@@ -63,6 +190,54 @@ pub trait GuestFunction {
 /// - Are serializable/deserializable deterministically
 pub unsafe trait WasmSafe {}
 
+/// Check at compile time that a type and its fields are [`WasmSafe`], and
+/// that the fields fill the type without padding
+///
+/// Takes the type followed by the types of all its fields, for pairing with
+/// a manual `unsafe impl WasmSafe`:
+///
+/// ```
+/// use aingle_wasmer_common::{static_assert_wasm_safe, WasmSafe};
+///
+/// #[repr(C)]
+/// struct Header {
+///     len: u32,
+///     flags: u32,
+/// }
+///
+/// unsafe impl WasmSafe for Header {}
+/// static_assert_wasm_safe!(Header, u32, u32);
+/// ```
+///
+/// Padding fails the build:
+///
+/// ```compile_fail
+/// use aingle_wasmer_common::{static_assert_wasm_safe, WasmSafe};
+///
+/// #[repr(C)]
+/// struct Header {
+///     tag: u8,
+///     len: u32,
+/// }
+///
+/// unsafe impl WasmSafe for Header {}
+/// static_assert_wasm_safe!(Header, u8, u32);
+/// ```
+#[macro_export]
+macro_rules! static_assert_wasm_safe {
+    ($ty:ty $(, $field:ty)* $(,)?) => {
+        const _: () = {
+            const fn assert_wasm_safe<T: $crate::WasmSafe>() {}
+            assert_wasm_safe::<$ty>();
+            $(assert_wasm_safe::<$field>();)*
+            assert!(
+                ::core::mem::size_of::<$ty>() == 0 $(+ ::core::mem::size_of::<$field>())*,
+                concat!("`", stringify!($ty), "` has padding or fields not listed"),
+            );
+        };
+    };
+}
+
 // Implement WasmPrimitive for common types
 impl WasmPrimitive for u32 {
     type WasmType = u32;
@@ -162,6 +337,47 @@ impl WasmPrimitive for bool {
     }
 }
 
+/// Narrow integers widen to `i32` through `as`, which zero-extends unsigned
+/// and sign-extends signed types, and narrow back through `as`, which keeps
+/// the low bits
+macro_rules! impl_small_int_primitive {
+    ($($ty:ty),*) => {
+        $(
+            impl WasmPrimitive for $ty {
+                type WasmType = i32;
+
+                #[inline]
+                fn to_wasm(self) -> i32 {
+                    self as i32
+                }
+
+                #[inline]
+                fn from_wasm(wasm: i32) -> Self {
+                    wasm as $ty
+                }
+            }
+        )*
+    };
+}
+
+impl_small_int_primitive!(u8, u16, i8, i16);
+
+impl WasmPrimitive for char {
+    type WasmType = u32;
+
+    #[inline]
+    fn to_wasm(self) -> u32 {
+        self as u32
+    }
+
+    /// Surrogates and values past `char::MAX` become
+    /// [`char::REPLACEMENT_CHARACTER`]
+    #[inline]
+    fn from_wasm(wasm: u32) -> Self {
+        char::from_u32(wasm).unwrap_or(char::REPLACEMENT_CHARACTER)
+    }
+}
+
 impl WasmPrimitive for WasmSlice {
     type WasmType = u64;
 
@@ -176,6 +392,49 @@ impl WasmPrimitive for WasmSlice {
     }
 }
 
+/// Fixed-size primitives are encoded little-endian, as WASM memory holds them
+///
+/// Decoding takes exactly the size of the type.
+macro_rules! impl_le_codec {
+    ($($ty:ty),*) => {
+        $(
+            impl WasmEncode for $ty {
+                #[inline]
+                fn encoded_size(&self) -> usize {
+                    core::mem::size_of::<$ty>()
+                }
+
+                fn encode_to(&self, buf: &mut [u8]) -> Result<usize, WasmError> {
+                    let bytes = self.to_le_bytes();
+                    let available = buf.len();
+                    let Some(out) = buf.get_mut(..bytes.len()) else {
+                        return Err(WasmError::Serialize(SerializeError::BufferTooSmall {
+                            needed: bytes.len(),
+                            available,
+                        }));
+                    };
+                    out.copy_from_slice(&bytes);
+                    Ok(bytes.len())
+                }
+            }
+
+            impl WasmDecode for $ty {
+                fn decode_from(buf: &[u8]) -> Result<Self, WasmError> {
+                    match buf.try_into() {
+                        Ok(bytes) => Ok(<$ty>::from_le_bytes(bytes)),
+                        Err(_) if buf.len() < core::mem::size_of::<$ty>() => {
+                            Err(WasmError::Deserialize(DeserializeError::UnexpectedEof))
+                        }
+                        Err(_) => Err(WasmError::Deserialize(DeserializeError::InvalidFormat)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_le_codec!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
 // Safety: Primitive types are safe to share
 unsafe impl WasmSafe for u8 {}
 unsafe impl WasmSafe for u16 {}
@@ -189,18 +448,108 @@ unsafe impl WasmSafe for f32 {}
 unsafe impl WasmSafe for f64 {}
 unsafe impl WasmSafe for bool {}
 unsafe impl WasmSafe for WasmSlice {}
+unsafe impl<T: WasmSafe, const N: usize> WasmSafe for [T; N] {}
+
+static_assert_wasm_safe!(WasmSlice, u32, u32);
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::string::String;
+
+    guest_fn! {
+        struct Greet => fn greet(String) -> String;
+        struct Ping => fn ping(()) -> ();
+    }
+
+    #[test]
+    fn test_guest_fn_declarations() {
+        assert_eq!(Greet::NAME, "greet");
+        assert_eq!(<Ping as GuestFunction>::NAME, "ping");
+        let _: <Greet as GuestFunction>::Input = String::new();
+
+        assert!(__names_match("greet", Greet::NAME));
+        assert!(!__names_match("greet", "greed"));
+        assert!(!__names_match("greet", "greeting"));
+    }
 
     #[test]
     fn test_bool_primitive() {
         assert_eq!(true.to_wasm(), 1i32);
         assert_eq!(false.to_wasm(), 0i32);
-        assert_eq!(bool::from_wasm(1), true);
-        assert_eq!(bool::from_wasm(0), false);
-        assert_eq!(bool::from_wasm(42), true);
+        assert!(bool::from_wasm(1));
+        assert!(!bool::from_wasm(0));
+        assert!(bool::from_wasm(42));
+    }
+
+    #[test]
+    fn test_small_int_primitives() {
+        for value in u8::MIN..=u8::MAX {
+            assert_eq!(value.to_wasm(), i32::from(value));
+            assert_eq!(u8::from_wasm(value.to_wasm()), value);
+        }
+        for value in i8::MIN..=i8::MAX {
+            assert_eq!(value.to_wasm(), i32::from(value));
+            assert_eq!(i8::from_wasm(value.to_wasm()), value);
+        }
+        for value in u16::MIN..=u16::MAX {
+            assert_eq!(value.to_wasm(), i32::from(value));
+            assert_eq!(u16::from_wasm(value.to_wasm()), value);
+        }
+        for value in i16::MIN..=i16::MAX {
+            assert_eq!(value.to_wasm(), i32::from(value));
+            assert_eq!(i16::from_wasm(value.to_wasm()), value);
+        }
+
+        // Signed values sign-extend, unsigned ones zero-extend
+        assert_eq!((-1i8).to_wasm(), -1);
+        assert_eq!(0xffu8.to_wasm(), 0xff);
+        assert_eq!(i16::MIN.to_wasm(), -0x8000);
+
+        // Out of range values keep their low bits
+        assert_eq!(u8::from_wasm(0x1ff), 0xff);
+        assert_eq!(i8::from_wasm(0x180), i8::MIN);
+        assert_eq!(u16::from_wasm(-1), u16::MAX);
+    }
+
+    #[test]
+    fn test_char_primitive() {
+        for value in ['\0', 'a', 'é', '€', '🦀', char::MAX] {
+            assert_eq!(char::from_wasm(value.to_wasm()), value);
+        }
+        assert_eq!('a'.to_wasm(), 0x61);
+
+        assert_eq!(char::from_wasm(0xd800), char::REPLACEMENT_CHARACTER);
+        assert_eq!(char::from_wasm(0xdfff), char::REPLACEMENT_CHARACTER);
+        assert_eq!(char::from_wasm(0x11_0000), char::REPLACEMENT_CHARACTER);
+        assert_eq!(char::from_wasm(u32::MAX), char::REPLACEMENT_CHARACTER);
+    }
+
+    #[test]
+    fn test_le_codec() {
+        let mut buf = [0u8; 8];
+        assert_eq!(0x0102_0304u32.encode_to(&mut buf), Ok(4));
+        assert_eq!(buf[..4], [4, 3, 2, 1]);
+        assert_eq!(u32::decode_from(&buf[..4]), Ok(0x0102_0304));
+        assert_eq!(1.5f64.encoded_size(), 8);
+        1.5f64.encode_to(&mut buf).unwrap();
+        assert_eq!(f64::decode_from(&buf), Ok(1.5));
+
+        assert_eq!(
+            7u64.encode_to(&mut buf[..4]),
+            Err(WasmError::Serialize(SerializeError::BufferTooSmall {
+                needed: 8,
+                available: 4
+            }))
+        );
+        assert_eq!(
+            u32::decode_from(&buf[..3]),
+            Err(WasmError::Deserialize(DeserializeError::UnexpectedEof))
+        );
+        assert_eq!(
+            u32::decode_from(&buf[..5]),
+            Err(WasmError::Deserialize(DeserializeError::InvalidFormat))
+        );
     }
 
     #[test]
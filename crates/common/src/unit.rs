@@ -0,0 +1,348 @@
+//! Zero-length payload convention
+//!
+//! A zero-length payload means "unit": `()` and unit structs are sent as no
+//! bytes at all instead of a serialized nil, and an empty payload decodes to
+//! them without a deserializer. Typed calls on both sides check
+//! [`is_unit`] before serializing and use [`decode_empty`] for empty
+//! payloads, so functions without input or output never agree on an
+//! encoding of nothing.
+
+use crate::{DeserializeError, ErrorKind, WasmError, WasmErrorInner};
+use alloc::string::ToString;
+use core::any::type_name;
+use core::fmt;
+use serde::de::{self, Deserialize, Visitor};
+use serde::ser::{self, Impossible, Serialize};
+
+/// Whether `value` is unit and travels as a zero-length payload
+///
+/// ```
+/// use aingle_wasmer_common::is_unit;
+///
+/// assert!(is_unit(&()));
+/// assert!(!is_unit(&0u8));
+/// assert!(!is_unit(&None::<()>));
+/// ```
+pub fn is_unit<T: Serialize + ?Sized>(value: &T) -> bool {
+    value.serialize(UnitProbe).is_ok()
+}
+
+/// Decode a zero-length payload
+///
+/// Unit types decode from it; every other type fails with [`EmptyPayload`].
+pub fn decode_empty<'de, T: Deserialize<'de>>() -> Result<T, EmptyPayload> {
+    T::deserialize(EmptyInput).map_err(|NotUnit| EmptyPayload {
+        expected: type_name::<T>(),
+    })
+}
+
+/// A type other than unit was decoded from a zero-length payload
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EmptyPayload {
+    /// Name of the type that was expected
+    pub expected: &'static str,
+}
+
+impl EmptyPayload {
+    /// The deserialization error this corresponds to
+    pub fn error(&self) -> DeserializeError {
+        DeserializeError::UnexpectedEof
+    }
+}
+
+impl fmt::Display for EmptyPayload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?}: expected `{}` but the payload is empty",
+            self.error(),
+            self.expected
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EmptyPayload {}
+
+impl From<EmptyPayload> for WasmError {
+    fn from(e: EmptyPayload) -> Self {
+        WasmError::GuestStructured(WasmErrorInner::new(
+            ErrorKind::Deserialization,
+            &e.to_string(),
+        ))
+    }
+}
+
+/// Anything but unit, for [`UnitProbe`] and [`EmptyInput`]
+#[derive(Debug)]
+struct NotUnit;
+
+impl fmt::Display for NotUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("not unit")
+    }
+}
+
+impl ser::StdError for NotUnit {}
+
+impl ser::Error for NotUnit {
+    fn custom<T: fmt::Display>(_msg: T) -> Self {
+        NotUnit
+    }
+}
+
+impl de::Error for NotUnit {
+    fn custom<T: fmt::Display>(_msg: T) -> Self {
+        NotUnit
+    }
+}
+
+/// Serializer accepting only unit
+struct UnitProbe;
+
+impl ser::Serializer for UnitProbe {
+    type Ok = ();
+    type Error = NotUnit;
+    type SerializeSeq = Impossible<(), NotUnit>;
+    type SerializeTuple = Impossible<(), NotUnit>;
+    type SerializeTupleStruct = Impossible<(), NotUnit>;
+    type SerializeTupleVariant = Impossible<(), NotUnit>;
+    type SerializeMap = Impossible<(), NotUnit>;
+    type SerializeStruct = Impossible<(), NotUnit>;
+    type SerializeStructVariant = Impossible<(), NotUnit>;
+
+    fn serialize_unit(self) -> Result<(), NotUnit> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), NotUnit> {
+        Ok(())
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_i128(self, _v: i128) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_u128(self, _v: u128) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_char(self, _v: char) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_none(self) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+    ) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _value: &T,
+    ) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn collect_str<T: fmt::Display + ?Sized>(self, _value: &T) -> Result<(), NotUnit> {
+        Err(NotUnit)
+    }
+}
+
+/// Deserializer over an empty payload, producing only unit
+struct EmptyInput;
+
+impl<'de> de::Deserializer<'de> for EmptyInput {
+    type Error = NotUnit;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, NotUnit> {
+        Err(NotUnit)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NotUnit> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, NotUnit> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option newtype_struct seq tuple tuple_struct map struct
+        enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Marker;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Point {
+        x: i32,
+    }
+
+    #[test]
+    fn test_is_unit() {
+        assert!(is_unit(&()));
+        assert!(is_unit(&Marker));
+
+        assert!(!is_unit(&Point { x: 1 }));
+        assert!(!is_unit(&Some(())));
+        assert!(!is_unit(&Vec::<()>::new()));
+        assert!(!is_unit(""));
+        assert!(!is_unit(&((), ())));
+    }
+
+    #[test]
+    fn test_decode_empty() {
+        decode_empty::<()>().unwrap();
+        assert_eq!(decode_empty::<Marker>().unwrap(), Marker);
+
+        let error = decode_empty::<Point>().unwrap_err();
+        assert_eq!(error.error(), DeserializeError::UnexpectedEof);
+        assert!(error.expected.ends_with("Point"));
+        assert!(decode_empty::<Option<u32>>().is_err());
+        assert!(decode_empty::<String>().is_err());
+
+        let error = WasmError::from(decode_empty::<u32>().unwrap_err());
+        assert_eq!(error.kind(), ErrorKind::Deserialization);
+        assert_eq!(
+            error.message(),
+            "UnexpectedEof: expected `u32` but the payload is empty"
+        );
+    }
+}
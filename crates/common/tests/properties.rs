@@ -0,0 +1,279 @@
+//! Property tests for the bit packing of slices, results and envelope
+//! headers
+//!
+//! Runs 256 cases per property; set `PROPTEST_CASES` for more, as in
+//! `PROPTEST_CASES=100000 cargo test -p aingle_wasmer_common --test properties`.
+
+use aingle_wasmer_common::{
+    EnvelopeFlagSet, EnvelopeFlags, EnvelopeHeader, EnvelopeHeaderInfo, ErrorKind, GuestReturn,
+    SliceOrder, WasmResult, WasmResult64, WasmSlice, WasmSlice64,
+};
+use proptest::prelude::*;
+
+/// Offsets biased towards the ends of the address space, where the
+/// arithmetic overflows
+fn offset32() -> impl Strategy<Value = u32> {
+    prop_oneof![
+        any::<u32>(),
+        0..16u32,
+        (0..16u32).prop_map(|n| u32::MAX - n),
+        (0..16u32).prop_map(|n| (1 << 31) - 8 + n),
+    ]
+}
+
+fn offset64() -> impl Strategy<Value = u64> {
+    prop_oneof![
+        any::<u64>(),
+        0..16u64,
+        (0..16u64).prop_map(|n| u64::MAX - n),
+        (0..16u64).prop_map(|n| (1 << 63) - 8 + n),
+    ]
+}
+
+/// Pointers a result can carry, which leave the error bit clear
+fn result_ptr() -> impl Strategy<Value = u32> {
+    offset32().prop_map(|ptr| ptr & !(1 << 31))
+}
+
+fn error_kind() -> impl Strategy<Value = ErrorKind> {
+    proptest::sample::select(ErrorKind::ALL)
+}
+
+proptest! {
+    #[test]
+    fn slice_pack_roundtrip(ptr in offset32(), len in offset32()) {
+        let slice = WasmSlice::new(ptr, len);
+        prop_assert_eq!(WasmSlice::unpack(slice.pack()), slice);
+        prop_assert_eq!(slice.pack() >> 32, ptr as u64);
+    }
+
+    #[test]
+    fn slice_unpack_roundtrip(packed in any::<u64>()) {
+        prop_assert_eq!(WasmSlice::unpack(packed).pack(), packed);
+    }
+
+    #[test]
+    fn slice_order_roundtrip(ptr in offset32(), len in offset32()) {
+        let slice = WasmSlice::new(ptr, len);
+        for order in [SliceOrder::PtrLen, SliceOrder::LenPtr] {
+            prop_assert_eq!(order.unpack(order.pack(slice)), slice);
+        }
+        prop_assert_eq!(
+            SliceOrder::LenPtr.pack(slice),
+            SliceOrder::PtrLen.pack(WasmSlice::new(len, ptr))
+        );
+    }
+
+    #[test]
+    fn slice_arithmetic_matches_wide(
+        ptr in offset32(),
+        len in offset32(),
+        offset in offset32(),
+        other_len in offset32(),
+    ) {
+        let slice = WasmSlice::new(ptr, len);
+        let end = ptr as u64 + len as u64;
+        let fits = end <= u32::MAX as u64;
+
+        prop_assert_eq!(slice.checked_end(), fits.then_some(end as u32));
+        prop_assert_eq!(slice.validate().is_ok(), fits);
+        prop_assert_eq!(slice.end() as u64, end.min(u32::MAX as u64));
+        prop_assert_eq!(
+            slice.contains(offset),
+            offset >= ptr && (offset as u64) < end
+        );
+
+        let other = WasmSlice::new(offset, other_len);
+        let other_end = offset as u64 + other_len as u64;
+        let overlaps = (ptr as u64) < other_end && (offset as u64) < end;
+        prop_assert_eq!(slice.overlaps(&other), overlaps);
+        prop_assert_eq!(other.overlaps(&slice), overlaps);
+    }
+
+    #[test]
+    fn subslice_stays_within(
+        ptr in offset32(),
+        len in offset32(),
+        offset in offset32(),
+        sub_len in offset32(),
+    ) {
+        let slice = WasmSlice::new(ptr, len);
+        match slice.subslice(offset, sub_len) {
+            Ok(sub) => {
+                prop_assert_eq!(sub.ptr as u64, ptr as u64 + offset as u64);
+                prop_assert_eq!(sub.len, sub_len);
+                prop_assert!(offset as u64 + sub_len as u64 <= len as u64);
+            }
+            Err(_) => prop_assert!(
+                offset as u64 + sub_len as u64 > len as u64
+                    || ptr as u64 + offset as u64 > u32::MAX as u64
+            ),
+        }
+    }
+
+    #[test]
+    fn slice64_pack_roundtrip(ptr in offset64(), len in offset64()) {
+        let slice = WasmSlice64::new(ptr, len);
+        prop_assert_eq!(WasmSlice64::unpack(slice.pack()), slice);
+    }
+
+    #[test]
+    fn slice64_arithmetic_matches_wide(
+        ptr in offset64(),
+        len in offset64(),
+        offset in offset64(),
+        other_len in offset64(),
+    ) {
+        let slice = WasmSlice64::new(ptr, len);
+        let end = ptr as u128 + len as u128;
+
+        prop_assert_eq!(
+            slice.checked_end().map(u128::from),
+            (end <= u64::MAX as u128).then_some(end)
+        );
+        prop_assert_eq!(slice.end() as u128, end.min(u64::MAX as u128));
+        prop_assert_eq!(
+            slice.contains(offset),
+            offset >= ptr && (offset as u128) < end
+        );
+
+        let other = WasmSlice64::new(offset, other_len);
+        let other_end = offset as u128 + other_len as u128;
+        let overlaps = (ptr as u128) < other_end && (offset as u128) < end;
+        prop_assert_eq!(slice.overlaps(&other), overlaps);
+        prop_assert_eq!(other.overlaps(&slice), overlaps);
+    }
+
+    #[test]
+    fn slice64_narrows_within_4gib(ptr in offset64(), len in offset64()) {
+        let slice = WasmSlice64::new(ptr, len);
+        let last = (ptr as u128 + len as u128).saturating_sub(1).max(ptr as u128);
+        let fits = last <= u32::MAX as u128 && len <= u32::MAX as u64;
+        match WasmSlice::try_from(slice) {
+            Ok(narrow) => {
+                prop_assert!(fits);
+                prop_assert_eq!(WasmSlice64::from(narrow), slice);
+            }
+            Err(_) => prop_assert!(!fits),
+        }
+    }
+
+    #[test]
+    fn result_roundtrip(ptr in result_ptr(), len in offset32()) {
+        let slice = WasmSlice::new(ptr, len);
+
+        let ok = WasmResult::from_raw(WasmResult::ok(slice).into_raw());
+        prop_assert!(ok.is_ok());
+        prop_assert_eq!(ok.slice(), slice);
+        prop_assert_eq!(ok.kind(), None);
+        prop_assert_eq!(GuestReturn::from_slice(slice).as_slice(), slice);
+
+        let err = WasmResult::from_raw(WasmResult::err(slice).into_raw());
+        prop_assert!(err.is_err());
+        prop_assert_eq!(err.into_raw() & !(1 << 63), slice.pack());
+        if len != 0 {
+            prop_assert_eq!(err.slice(), slice);
+            prop_assert_eq!(err.kind(), None);
+        }
+    }
+
+    #[test]
+    fn result_carries_kind(ptr in result_ptr(), kind in error_kind()) {
+        let err = WasmResult::err_with_kind(WasmSlice::new(ptr, 0), kind);
+        prop_assert!(err.is_err());
+        prop_assert!(err.is_empty_err());
+        prop_assert_eq!(err.kind(), Some(kind));
+        prop_assert_eq!(err.slice().len, 0);
+        prop_assert!(err.is_well_formed());
+    }
+
+    #[test]
+    fn result_reads_any_raw(raw in any::<u64>()) {
+        let result = WasmResult::from_raw(raw);
+        prop_assert_eq!(result.into_raw(), raw);
+        prop_assert_eq!(result.is_err(), raw >> 63 == 1);
+        prop_assert_eq!(result.is_ok(), !result.is_err());
+
+        // Reading never panics, and only payload-less errors lose bits
+        let slice = result.slice();
+        let _ = (result.kind(), result.is_well_formed());
+        if !result.is_empty_err() {
+            prop_assert_eq!(slice.pack(), raw & !(1 << 63));
+            prop_assert_eq!(result.kind(), None);
+        }
+    }
+
+    #[test]
+    fn result64_roundtrip(
+        ptr in offset64(),
+        len in offset64(),
+        high in any::<u64>(),
+        low in any::<u64>(),
+    ) {
+        let slice = WasmSlice64::new(ptr & !(1 << 63), len);
+        let ok = WasmResult64::ok(slice);
+        prop_assert!(ok.is_ok());
+        prop_assert_eq!(ok.slice(), slice);
+        let err = WasmResult64::err(slice);
+        prop_assert!(err.is_err());
+        prop_assert_eq!(err.slice(), slice);
+
+        let (high_part, low_part) = err.into_parts();
+        prop_assert_eq!(WasmResult64::from_parts(high_part, low_part).into_raw(), err.into_raw());
+        prop_assert_eq!(WasmResult64::from_parts(high, low).into_parts(), (high, low));
+    }
+
+    #[test]
+    fn header_roundtrip(
+        magic in any::<u16>(),
+        version in any::<u8>(),
+        flags in any::<u8>(),
+        payload_len in offset32(),
+        checksum in any::<u32>(),
+    ) {
+        let info = EnvelopeHeaderInfo {
+            magic,
+            version,
+            flags: EnvelopeFlagSet::from_bits(flags),
+            payload_len,
+            checksum,
+        };
+        let header = EnvelopeHeader::from(info);
+        let bytes = header.to_bytes();
+
+        prop_assert_eq!(EnvelopeHeader::from_bytes(&bytes).info(), info);
+        prop_assert_eq!(&bytes[0..2], &magic.to_le_bytes()[..]);
+        prop_assert_eq!(bytes[2], version);
+        prop_assert_eq!(bytes[3], flags);
+        prop_assert_eq!(&bytes[4..8], &payload_len.to_le_bytes()[..]);
+        prop_assert_eq!(&bytes[8..12], &checksum.to_le_bytes()[..]);
+        prop_assert_eq!(
+            header.envelope_len(),
+            Some(payload_len as usize + EnvelopeHeader::SIZE)
+        );
+    }
+
+    #[test]
+    fn header_bytes_roundtrip(bytes in any::<[u8; EnvelopeHeader::SIZE]>()) {
+        let header = EnvelopeHeader::from_bytes(&bytes);
+        prop_assert_eq!(header.to_bytes(), bytes);
+        prop_assert_eq!(EnvelopeHeader::from(header.info()).to_bytes(), bytes);
+    }
+
+    #[test]
+    fn flag_set_keeps_bits(
+        bits in any::<u8>(),
+        n in 0..EnvelopeFlagSet::USER_FLAGS,
+        value in any::<bool>(),
+    ) {
+        let flags = EnvelopeFlagSet::from_bits(bits);
+        let known = flags.iter().fold(0, |acc, flag| acc | flag as u8);
+        prop_assert_eq!(known | flags.unknown_bits(), bits);
+        prop_assert_eq!(flags.user_bits() & EnvelopeFlags::CORE_MASK, 0);
+
+        let changed = flags.with_user_flag(n, value);
+        prop_assert_eq!(changed.user_flag(n), value);
+        prop_assert_eq!(changed.bits() & EnvelopeFlags::CORE_MASK, bits & EnvelopeFlags::CORE_MASK);
+        prop_assert_eq!(changed.with_user_flag(n, flags.user_flag(n)), flags);
+    }
+}
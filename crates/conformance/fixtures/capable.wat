@@ -0,0 +1,93 @@
+;; Guest speaking the current protocol, shaped like one built with the guest
+;; crate: a bump arena in the upper half of its memory serving the host
+;; allocator, arena statistics and controls, a published panic, and the ABI
+;; handshake advertising envelopes, arena resets, the panic hook and CRC32C.
+(module
+    (memory (export "memory") 1)
+
+    ;; Published by the "panic" entry point
+    (data (i32.const 16) "conformance panic")
+
+    ;; Bytes held by the arena, its high-water mark and limit (0 for none)
+    (global $used (mut i32) (i32.const 0))
+    (global $high (mut i32) (i32.const 0))
+    (global $limit (mut i32) (i32.const 0))
+    ;; Whether a panic is waiting to be read
+    (global $panicked (mut i32) (i32.const 0))
+    ;; Checksum the host selected
+    (global $checksum (mut i32) (i32.const 0))
+
+    ;; Take `len` bytes from the arena at 0x8000, or 0 past the limit or the
+    ;; end of memory
+    (func $alloc (param $len i32) (result i32)
+        (local $end i32)
+        (local.set $end (i32.add (global.get $used) (local.get $len)))
+        (if (i32.gt_u (local.get $end) (i32.const 0x8000))
+            (then (return (i32.const 0))))
+        (if (i32.and
+                (i32.ne (global.get $limit) (i32.const 0))
+                (i32.gt_u (local.get $end) (global.get $limit)))
+            (then (return (i32.const 0))))
+        (global.set $used (local.get $end))
+        (if (i32.gt_u (global.get $used) (global.get $high))
+            (then (global.set $high (global.get $used))))
+        (i32.sub (i32.add (i32.const 0x8000) (global.get $used)) (local.get $len)))
+
+    (func (export "__hc__allocate_1") (param $len i32) (result i32)
+        (call $alloc (local.get $len)))
+
+    (func (export "__hc__deallocate_1") (param i32 i32))
+
+    (func (export "__aingle_guest_allocated_bytes") (result i32)
+        (global.get $used))
+
+    (func (export "__aingle_guest_high_water") (result i32)
+        (global.get $high))
+
+    (func (export "__aingle_guest_reset_arena") (result i32)
+        (global.set $used (i32.const 0))
+        (global.get $high))
+
+    (func (export "__aingle_guest_set_arena_limit") (param $bytes i32)
+        (global.set $limit (local.get $bytes)))
+
+    (func (export "__aingle_guest_last_panic") (result i64)
+        (if (i32.eqz (global.get $panicked))
+            (then (return (i64.const 0))))
+        (global.set $panicked (i32.const 0))
+        ;; Error bit, pointer 16, length 17
+        (i64.const 0x8000001000000011))
+
+    ;; Protocol 1, crate 0.0, capabilities 0x17
+    (func (export "__aingle_guest_abi") (result i64)
+        (i64.const 0x0000001700000001))
+
+    (func (export "__aingle_guest_set_checksum") (param $kind i32)
+        (global.set $checksum (local.get $kind)))
+
+    (func (export "selected_checksum") (result i32)
+        (global.get $checksum))
+
+    ;; The input envelope, returned as it is
+    (func (export "echo") (param $ptr i32) (param $len i32) (result i64)
+        (i64.or
+            (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+            (i64.extend_i32_u (local.get $len))))
+
+    ;; The first of two inputs, returned as it is
+    (func (export "first")
+        (param $ptr1 i32) (param $len1 i32) (param i32 i32) (result i64)
+        (i64.or
+            (i64.shl (i64.extend_i32_u (local.get $ptr1)) (i64.const 32))
+            (i64.extend_i32_u (local.get $len1))))
+
+    ;; Hold 256 more bytes in the arena, failing without payload once it is
+    ;; exhausted
+    (func (export "alloc") (param i32 i32) (result i64)
+        (if (i32.eqz (call $alloc (i32.const 256)))
+            (then (return (i64.const 0x8000000000000000))))
+        (i64.const 0))
+
+    (func (export "panic") (param i32 i32) (result i64)
+        (global.set $panicked (i32.const 1))
+        unreachable))
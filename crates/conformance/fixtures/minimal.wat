@@ -0,0 +1,33 @@
+;; Guest predating the ABI handshake: no allocator, statistics or ABI
+;; exports, only entry points following the (ptr, len) -> i64 convention.
+;; Results pack the pointer in the high and the length in the low 32 bits,
+;; with bit 63 marking errors.
+(module
+    (memory (export "memory") 1)
+
+    ;; The input envelope, returned as it is
+    (func (export "echo") (param $ptr i32) (param $len i32) (result i64)
+        (i64.or
+            (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+            (i64.extend_i32_u (local.get $len))))
+
+    ;; The input envelope, returned as an error
+    (func (export "fail") (param $ptr i32) (param $len i32) (result i64)
+        (i64.or
+            (i64.const 0x8000000000000000)
+            (i64.or
+                (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+                (i64.extend_i32_u (local.get $len)))))
+
+    ;; An error without payload or kind
+    (func (export "fail_empty") (param i32 i32) (result i64)
+        (i64.const 0x8000000000000000))
+
+    (func (export "trap") (param i32 i32) (result i64)
+        unreachable)
+
+    ;; Never returns unless metering stops it
+    (func (export "spin") (param i32 i32) (result i64)
+        (loop $forever
+            (br $forever))
+        (i64.const 0)))
@@ -0,0 +1,361 @@
+//! The checks of the suite
+//!
+//! Each check instantiates the fixtures it needs afresh, so checks do not
+//! depend on one another or on their order.
+
+use crate::{ConformanceHost, Fixture};
+use aingle_wasmer_host::{ChecksumKind, EngineConfig, HostError, TrapKind, WasmInstance};
+
+/// Why a check stopped short of passing
+#[derive(Debug)]
+pub(crate) enum Stop {
+    Failed(String),
+    Skipped(String),
+}
+
+impl From<HostError> for Stop {
+    fn from(error: HostError) -> Self {
+        Stop::Failed(format!("unexpected error: {}", error))
+    }
+}
+
+pub(crate) type Checked = Result<(), Stop>;
+
+type Check = fn(&dyn ConformanceHost) -> Checked;
+
+/// Every check in suite order
+pub(crate) const CHECKS: &[(&str, Check)] = &[
+    ("envelope.roundtrip", envelope_roundtrip),
+    ("envelope.two_inputs", envelope_two_inputs),
+    ("errors.guest_error", errors_guest_error),
+    ("errors.payloadless", errors_payloadless),
+    ("errors.trap", errors_trap),
+    ("errors.panic", errors_panic),
+    ("errors.missing_function", errors_missing_function),
+    ("metering.exhaustion", metering_exhaustion),
+    ("metering.points", metering_points),
+    ("arena.statistics", arena_statistics),
+    ("arena.limit", arena_limit),
+    ("allocator.allocate", allocator_allocate),
+    (
+        "allocator.allocate64_fallback",
+        allocator_allocate64_fallback,
+    ),
+    ("allocator.refusal", allocator_refusal),
+    ("abi.checksum_negotiation", abi_checksum_negotiation),
+    ("abi.engine_checksum", abi_engine_checksum),
+];
+
+/// Guest export resetting the arena of guests built with the guest crate,
+/// returning the high-water mark
+const RESET_ARENA_EXPORT: &str = "__aingle_guest_reset_arena";
+
+macro_rules! ensure {
+    ($condition:expr, $($diagnostics:tt)+) => {
+        if !$condition {
+            return Err(Stop::Failed(format!($($diagnostics)+)));
+        }
+    };
+}
+
+macro_rules! ensure_matches {
+    ($value:expr, $pattern:pat $(if $guard:expr)?) => {
+        match $value {
+            $pattern $(if $guard)? => {}
+            other => {
+                return Err(Stop::Failed(format!(
+                    "expected {}, got {:?}",
+                    stringify!($pattern $(if $guard)?),
+                    other
+                )))
+            }
+        }
+    };
+}
+
+fn instantiate(host: &dyn ConformanceHost, fixture: Fixture) -> Result<WasmInstance, Stop> {
+    Ok(host.instantiate(fixture, EngineConfig::default())?)
+}
+
+fn require_metering(host: &dyn ConformanceHost) -> Checked {
+    match host.meters_guests() {
+        true => Ok(()),
+        false => Err(Stop::Skipped(
+            "the backend does not meter guests".to_string(),
+        )),
+    }
+}
+
+/// Payloads of any size come back from a guest returning its input
+/// envelope
+fn envelope_roundtrip(host: &dyn ConformanceHost) -> Checked {
+    let mut instance = instantiate(host, Fixture::Minimal)?;
+    let large: Vec<u8> = (0..16 * 1024).map(|i| i as u8).collect();
+    for payload in [&b""[..], b"x", b"conformance", &large] {
+        let output = instance.call_raw("echo", payload)?;
+        ensure!(
+            output == payload,
+            "a {}-byte payload came back as {} different bytes",
+            payload.len(),
+            output.len()
+        );
+    }
+    Ok(())
+}
+
+/// Both inputs of a two-input call reach the guest through its allocator
+fn envelope_two_inputs(host: &dyn ConformanceHost) -> Checked {
+    let mut instance = instantiate(host, Fixture::Capable)?;
+    let output = instance.call_raw2("first", b"left", b"right")?;
+    ensure!(
+        output == b"left",
+        "the first input came back as {:?}",
+        output
+    );
+    ensure!(
+        instance.outstanding_allocations() == 2,
+        "{} host-made allocations tracked instead of 2",
+        instance.outstanding_allocations()
+    );
+    Ok(())
+}
+
+/// An error result carries the guest's message
+fn errors_guest_error(host: &dyn ConformanceHost) -> Checked {
+    let mut instance = instantiate(host, Fixture::Minimal)?;
+    ensure_matches!(
+        instance.call_raw("fail", b"conformance failure"),
+        Err(HostError::GuestError(message)) if message == "conformance failure"
+    );
+    Ok(())
+}
+
+/// An error result without payload is still an error
+fn errors_payloadless(host: &dyn ConformanceHost) -> Checked {
+    let mut instance = instantiate(host, Fixture::Minimal)?;
+    ensure_matches!(
+        instance.call_raw("fail_empty", b"x"),
+        Err(HostError::GuestError(message)) if message == "empty error"
+    );
+    Ok(())
+}
+
+/// Traps are reported with their kind
+fn errors_trap(host: &dyn ConformanceHost) -> Checked {
+    let mut instance = instantiate(host, Fixture::Minimal)?;
+    ensure_matches!(
+        instance.call_raw("trap", b"x"),
+        Err(HostError::Trap {
+            kind: TrapKind::UnreachableCodeReached,
+            ..
+        })
+    );
+    Ok(())
+}
+
+/// A panic the guest published replaces the trap it ended in, and the
+/// instance stays usable
+fn errors_panic(host: &dyn ConformanceHost) -> Checked {
+    let mut instance = instantiate(host, Fixture::Capable)?;
+    ensure_matches!(
+        instance.call_raw("panic", b"x"),
+        Err(HostError::GuestError(message)) if message == "conformance panic"
+    );
+    let output = instance.call_raw("echo", b"after")?;
+    ensure!(
+        output == b"after",
+        "the call after the panic returned {:?}",
+        output
+    );
+    Ok(())
+}
+
+fn errors_missing_function(host: &dyn ConformanceHost) -> Checked {
+    let mut instance = instantiate(host, Fixture::Minimal)?;
+    ensure_matches!(
+        instance.call_raw("missing", b"x"),
+        Err(HostError::FunctionNotFound(name)) if name == "missing"
+    );
+    Ok(())
+}
+
+/// A guest running out of points stops with [`HostError::MeteringExceeded`]
+/// and runs again once given more
+fn metering_exhaustion(host: &dyn ConformanceHost) -> Checked {
+    require_metering(host)?;
+    let config = EngineConfig {
+        metering_limit: 100_000,
+        ..EngineConfig::default()
+    };
+    let mut instance = host.instantiate(Fixture::Minimal, config)?;
+    ensure_matches!(
+        instance.call_raw("spin", b"x"),
+        Err(HostError::MeteringExceeded)
+    );
+    ensure_matches!(instance.backend_instance().remaining_points(), None);
+
+    instance.backend_instance().set_remaining_points(100_000);
+    let output = instance.call_raw("echo", b"refuelled")?;
+    ensure!(
+        output == b"refuelled",
+        "the call after refuelling returned {:?}",
+        output
+    );
+    Ok(())
+}
+
+/// Calls consume points from the configured limit
+fn metering_points(host: &dyn ConformanceHost) -> Checked {
+    require_metering(host)?;
+    let config = EngineConfig {
+        metering_limit: 1_000_000,
+        ..EngineConfig::default()
+    };
+    let mut instance = host.instantiate(Fixture::Minimal, config)?;
+    ensure_matches!(
+        instance.backend_instance().remaining_points(),
+        Some(1_000_000)
+    );
+    instance.call_raw("echo", b"x")?;
+    ensure_matches!(
+        instance.backend_instance().remaining_points(),
+        Some(points) if points > 0 && points < 1_000_000
+    );
+    Ok(())
+}
+
+/// Arena statistics are read after every call, and the high-water mark
+/// survives resets
+fn arena_statistics(host: &dyn ConformanceHost) -> Checked {
+    fn statistics(instance: &WasmInstance) -> Option<(Option<u32>, Option<u32>)> {
+        instance
+            .last_call_report()
+            .map(|report| (report.guest_allocated_bytes, report.guest_high_water))
+    }
+
+    let mut instance = instantiate(host, Fixture::Capable)?;
+    instance.call_raw("alloc", b"")?;
+    ensure_matches!(statistics(&instance), Some((Some(256), Some(256))));
+    instance.call_raw("alloc", b"")?;
+    ensure_matches!(statistics(&instance), Some((Some(512), Some(512))));
+
+    ensure_matches!(
+        instance.backend_instance().call_stat(RESET_ARENA_EXPORT),
+        Ok(Some(512))
+    );
+    instance.call_raw("alloc", b"")?;
+    ensure_matches!(statistics(&instance), Some((Some(256), Some(512))));
+
+    let mut minimal = instantiate(host, Fixture::Minimal)?;
+    minimal.call_raw("echo", b"x")?;
+    ensure_matches!(statistics(&minimal), Some((None, None)));
+    Ok(())
+}
+
+/// The arena limit reaches guests exporting the control, and only them
+fn arena_limit(host: &dyn ConformanceHost) -> Checked {
+    let mut instance = instantiate(host, Fixture::Capable)?;
+    ensure_matches!(instance.set_guest_arena_limit(Some(300)), Ok(true));
+    instance.call_raw("alloc", b"")?;
+    ensure_matches!(
+        instance.call_raw("alloc", b""),
+        Err(HostError::GuestError(_))
+    );
+    ensure_matches!(instance.set_guest_arena_limit(None), Ok(true));
+    instance.call_raw("alloc", b"")?;
+
+    let mut minimal = instantiate(host, Fixture::Minimal)?;
+    ensure_matches!(minimal.set_guest_arena_limit(Some(300)), Ok(false));
+    Ok(())
+}
+
+/// Memory from the guest allocator can be written and read back
+fn allocator_allocate(host: &dyn ConformanceHost) -> Checked {
+    let mut instance = instantiate(host, Fixture::Capable)?;
+    let guest = instance.backend_instance();
+    let ptr = guest.allocate(64)?;
+    ensure!(ptr != 0, "the allocator returned the null pointer");
+
+    let data: Vec<u8> = (0..64).collect();
+    guest.write_memory(u64::from(ptr), &data)?;
+    let mut read = vec![0; 64];
+    guest.read_memory(u64::from(ptr), &mut read)?;
+    ensure!(read == data, "memory at {:#x} read back as {:?}", ptr, read);
+    Ok(())
+}
+
+/// Guests without a 64-bit allocator are served by their 32-bit one
+fn allocator_allocate64_fallback(host: &dyn ConformanceHost) -> Checked {
+    let mut instance = instantiate(host, Fixture::Capable)?;
+    let guest = instance.backend_instance();
+    let ptr = guest.allocate(64)?;
+    let ptr64 = guest.allocate64(64)?;
+    ensure!(
+        ptr64 != 0 && ptr64 <= u64::from(u32::MAX) && ptr64 != u64::from(ptr),
+        "the 64-bit allocation after one at {:#x} returned {:#x}",
+        ptr,
+        ptr64
+    );
+    ensure_matches!(
+        guest.allocate64(u64::from(u32::MAX) + 1),
+        Err(HostError::MemoryAccess(_))
+    );
+    Ok(())
+}
+
+/// Allocations the guest refuses or cannot make are errors
+fn allocator_refusal(host: &dyn ConformanceHost) -> Checked {
+    let mut instance = instantiate(host, Fixture::Capable)?;
+    ensure_matches!(instance.set_guest_arena_limit(Some(128)), Ok(true));
+    ensure_matches!(
+        instance.backend_instance().allocate(256),
+        Err(HostError::MemoryAccess(_))
+    );
+
+    let mut minimal = instantiate(host, Fixture::Minimal)?;
+    ensure_matches!(
+        minimal.backend_instance().allocate(16),
+        Err(HostError::FunctionNotFound(_))
+    );
+    Ok(())
+}
+
+/// CRC32C is selected for guests advertising it, which are told through
+/// their export, and envelopes checksummed with it round-trip
+fn abi_checksum_negotiation(host: &dyn ConformanceHost) -> Checked {
+    let mut instance = instantiate(host, Fixture::Capable)?;
+    ensure_matches!(
+        instance.set_checksum(ChecksumKind::Crc32c),
+        Ok(ChecksumKind::Crc32c)
+    );
+    ensure_matches!(
+        instance.backend_instance().call_stat("selected_checksum"),
+        Ok(Some(kind)) if kind == ChecksumKind::Crc32c as u32
+    );
+    let output = instance.call_raw("echo", b"castagnoli")?;
+    ensure!(
+        output == b"castagnoli",
+        "a CRC32C envelope came back as {:?}",
+        output
+    );
+
+    let mut minimal = instantiate(host, Fixture::Minimal)?;
+    ensure_matches!(
+        minimal.set_checksum(ChecksumKind::Crc32c),
+        Ok(ChecksumKind::Crc32)
+    );
+    Ok(())
+}
+
+/// The engine's checksum is negotiated on instantiation
+fn abi_engine_checksum(host: &dyn ConformanceHost) -> Checked {
+    let config = EngineConfig {
+        checksum: ChecksumKind::Crc32c,
+        ..EngineConfig::default()
+    };
+    let capable = host.instantiate(Fixture::Capable, config.clone())?;
+    ensure_matches!(capable.checksum(), ChecksumKind::Crc32c);
+    let minimal = host.instantiate(Fixture::Minimal, config)?;
+    ensure_matches!(minimal.checksum(), ChecksumKind::Crc32);
+    Ok(())
+}
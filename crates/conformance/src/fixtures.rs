@@ -0,0 +1,47 @@
+//! Canonical guests the checks run on
+
+use aingle_wasmer_host::HostError;
+
+/// A guest of the suite
+///
+/// Kept as WAT so the exact exports and results each check relies on can
+/// be read next to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fixture {
+    /// Guest predating the ABI handshake, without allocator or statistics
+    Minimal,
+    /// Guest speaking the current protocol with a bump arena, statistics,
+    /// a published panic and CRC32C support
+    Capable,
+}
+
+impl Fixture {
+    /// Every fixture
+    pub const ALL: [Fixture; 2] = [Fixture::Minimal, Fixture::Capable];
+
+    /// WAT source of the guest
+    pub fn wat(self) -> &'static str {
+        match self {
+            Fixture::Minimal => include_str!("../fixtures/minimal.wat"),
+            Fixture::Capable => include_str!("../fixtures/capable.wat"),
+        }
+    }
+
+    /// The guest assembled to WASM bytes
+    pub fn wasm(self) -> Result<Vec<u8>, HostError> {
+        wat::parse_str(self.wat()).map_err(|e| HostError::Compilation(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixtures_assemble() {
+        for fixture in Fixture::ALL {
+            let wasm = fixture.wasm().unwrap();
+            assert_eq!(&wasm[..4], b"\0asm", "{:?}", fixture);
+        }
+    }
+}
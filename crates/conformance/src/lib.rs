@@ -0,0 +1,260 @@
+//! # AIngle WASM Conformance
+//!
+//! One suite pinning the behavior of the host crate that guests and
+//! conductors observe, run against any execution backend: envelope
+//! round-trips, error propagation, metering exhaustion, arena statistics
+//! and resets, the guest allocator and the ABI handshake.
+//!
+//! Embedders implement [`ConformanceHost`] over their engine setup and call
+//! [`run_conformance`]. Every check runs on fresh instances of the
+//! [`Fixture`] guests and reports an [`Outcome`] with diagnostics; a failing
+//! or panicking check never stops the rest of the suite.
+//!
+//! ```ignore
+//! use aingle_wasmer_conformance::{run_conformance, ConformanceHost};
+//! use aingle_wasmer_host::{EngineConfig, HostError, WasmEngine};
+//! use std::sync::Arc;
+//!
+//! struct MyHost;
+//!
+//! impl ConformanceHost for MyHost {
+//!     fn name(&self) -> &str {
+//!         "my backend"
+//!     }
+//!
+//!     fn engine(&self, config: EngineConfig) -> Result<WasmEngine, HostError> {
+//!         Ok(WasmEngine::with_backend(config, Arc::new(MyBackend::new())))
+//!     }
+//! }
+//!
+//! let report = run_conformance(&MyHost);
+//! assert!(report.is_conformant(), "{}", report);
+//! ```
+//!
+//! The wasmer sys backend passes every check, see `tests/wasmer_sys.rs`.
+
+#![warn(missing_docs)]
+
+mod checks;
+mod fixtures;
+
+pub use fixtures::Fixture;
+
+use aingle_wasmer_host::{EngineConfig, HostError, WasmEngine, WasmInstance};
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+/// Engine setup of the backend under test
+pub trait ConformanceHost {
+    /// Name of the backend in reports
+    fn name(&self) -> &str;
+
+    /// A fresh engine on the backend under test, configured with `config`
+    fn engine(&self, config: EngineConfig) -> Result<WasmEngine, HostError>;
+
+    /// Whether the backend meters guests
+    ///
+    /// Metering checks are skipped for backends that cannot, like
+    /// `wasmer_js`.
+    fn meters_guests(&self) -> bool {
+        true
+    }
+
+    /// Instantiate `fixture` on an engine configured with `config`
+    fn instantiate(
+        &self,
+        fixture: Fixture,
+        config: EngineConfig,
+    ) -> Result<WasmInstance, HostError> {
+        let engine = self.engine(config)?;
+        let module = engine.compile(&fixture.wasm()?)?;
+        WasmInstance::new(&engine, &module)
+    }
+}
+
+/// Outcome of a single check
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The backend behaved as specified
+    Passed,
+    /// The backend deviated, with diagnostics
+    Failed(String),
+    /// The check does not apply to the backend, with the reason
+    Skipped(String),
+}
+
+/// A check of the suite and its outcome
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckResult {
+    /// Name of the check, prefixed with the behavior it covers
+    pub name: &'static str,
+    /// Outcome of the check
+    pub outcome: Outcome,
+}
+
+/// Outcomes of the whole suite for one backend
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConformanceReport {
+    /// Name of the backend, see [`ConformanceHost::name`]
+    pub backend: String,
+    /// Every check in suite order
+    pub checks: Vec<CheckResult>,
+}
+
+impl ConformanceReport {
+    /// Number of checks passed
+    pub fn passed(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Passed))
+    }
+
+    /// Number of checks failed
+    pub fn failed(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Failed(_)))
+    }
+
+    /// Number of checks skipped
+    pub fn skipped(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Skipped(_)))
+    }
+
+    /// Whether no check failed; skipped checks are allowed
+    pub fn is_conformant(&self) -> bool {
+        self.failed() == 0
+    }
+
+    /// The checks that failed
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.checks
+            .iter()
+            .filter(|check| matches!(check.outcome, Outcome::Failed(_)))
+    }
+
+    fn count(&self, filter: impl Fn(&Outcome) -> bool) -> usize {
+        self.checks
+            .iter()
+            .filter(|check| filter(&check.outcome))
+            .count()
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "conformance of {}: {} passed, {} failed, {} skipped",
+            self.backend,
+            self.passed(),
+            self.failed(),
+            self.skipped()
+        )?;
+        for check in &self.checks {
+            match &check.outcome {
+                Outcome::Passed => writeln!(f, "  ok   {}", check.name)?,
+                Outcome::Failed(diagnostics) => {
+                    writeln!(f, "  FAIL {}: {}", check.name, diagnostics)?
+                }
+                Outcome::Skipped(reason) => writeln!(f, "  skip {}: {}", check.name, reason)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run every check of the suite against `host`
+pub fn run_conformance(host: &impl ConformanceHost) -> ConformanceReport {
+    let checks = checks::CHECKS
+        .iter()
+        .map(|&(name, check)| CheckResult {
+            name,
+            outcome: run_check(|| check(host)),
+        })
+        .collect();
+
+    ConformanceReport {
+        backend: host.name().to_string(),
+        checks,
+    }
+}
+
+/// Run one check, turning a panic into a failure
+fn run_check(check: impl FnOnce() -> checks::Checked) -> Outcome {
+    match panic::catch_unwind(AssertUnwindSafe(check)) {
+        Ok(Ok(())) => Outcome::Passed,
+        Ok(Err(checks::Stop::Failed(diagnostics))) => Outcome::Failed(diagnostics),
+        Ok(Err(checks::Stop::Skipped(reason))) => Outcome::Skipped(reason),
+        Err(panic) => Outcome::Failed(format!("panicked: {}", panic_message(&*panic))),
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+        (Some(message), _) => message,
+        (None, Some(message)) => message,
+        (None, None) => "non-string panic payload",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use checks::Stop;
+
+    #[test]
+    fn test_run_check_outcomes() {
+        assert_eq!(run_check(|| Ok(())), Outcome::Passed);
+        assert_eq!(
+            run_check(|| Err(Stop::Failed("wrong".to_string()))),
+            Outcome::Failed("wrong".to_string())
+        );
+        assert_eq!(
+            run_check(|| Err(Stop::Skipped("n/a".to_string()))),
+            Outcome::Skipped("n/a".to_string())
+        );
+        assert_eq!(
+            run_check(|| panic!("check {} blew up", 7)),
+            Outcome::Failed("panicked: check 7 blew up".to_string())
+        );
+    }
+
+    #[test]
+    fn test_report_counts_and_rendering() {
+        let report = ConformanceReport {
+            backend: "test".to_string(),
+            checks: vec![
+                CheckResult {
+                    name: "envelope.roundtrip",
+                    outcome: Outcome::Passed,
+                },
+                CheckResult {
+                    name: "errors.trap",
+                    outcome: Outcome::Failed("expected a trap".to_string()),
+                },
+                CheckResult {
+                    name: "metering.exhaustion",
+                    outcome: Outcome::Skipped("unmetered".to_string()),
+                },
+            ],
+        };
+
+        assert_eq!(
+            (report.passed(), report.failed(), report.skipped()),
+            (1, 1, 1)
+        );
+        assert!(!report.is_conformant());
+        assert_eq!(
+            report
+                .failures()
+                .map(|check| check.name)
+                .collect::<Vec<_>>(),
+            ["errors.trap"]
+        );
+        assert_eq!(
+            report.to_string(),
+            "conformance of test: 1 passed, 1 failed, 1 skipped\n\
+             \x20 ok   envelope.roundtrip\n\
+             \x20 FAIL errors.trap: expected a trap\n\
+             \x20 skip metering.exhaustion: unmetered\n"
+        );
+    }
+}
@@ -0,0 +1,24 @@
+//! The wasmer sys backend is the baseline other backends are held to
+
+#![cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+
+use aingle_wasmer_conformance::{run_conformance, ConformanceHost};
+use aingle_wasmer_host::{EngineConfig, HostError, WasmEngine};
+
+struct WasmerSys;
+
+impl ConformanceHost for WasmerSys {
+    fn name(&self) -> &str {
+        "wasmer sys"
+    }
+
+    fn engine(&self, config: EngineConfig) -> Result<WasmEngine, HostError> {
+        WasmEngine::new(config)
+    }
+}
+
+#[test]
+fn test_wasmer_sys_passes_every_check() {
+    let report = run_conformance(&WasmerSys);
+    assert_eq!(report.passed(), report.checks.len(), "{}", report);
+}
@@ -0,0 +1,133 @@
+//! # AIngle WASM Example Guest
+//!
+//! Minimal guest built on `core` and `alloc` alone, keeping the guest crate's
+//! `alloc`-only mode building for wasm32:
+//!
+//! ```text
+//! cargo build -p aingle_wasmer_example_guest --target wasm32-unknown-unknown \
+//!     --no-default-features --features alloc
+//! ```
+//!
+//! Without `std` the guest provides its own panic handler, and the
+//! `arena-global-alloc` feature of the guest crate the global allocator.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+#![warn(missing_docs)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+use aingle_wasmer_example_interface as interface;
+#[cfg(feature = "std")]
+use aingle_wasmer_guest::{
+    guest_fn, host_call_enveloped, prelude::wasm_error, return_err_ptr, ErrorKind, WasmError,
+};
+use aingle_wasmer_guest::{host_args_envelope, return_ok, try_result_raw};
+#[cfg(feature = "std")]
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Return the input payload reversed
+#[no_mangle]
+pub extern "C" fn reverse(ptr: u32, len: u32) -> u64 {
+    let input = try_result_raw!(host_args_envelope(ptr, len));
+    let output: Vec<u8> = input.iter().rev().copied().collect();
+    return_ok(&output)
+}
+
+/// Parse the input as a decimal count, returned as little-endian bytes
+///
+/// Invalid input is reported as a structured error.
+#[cfg(feature = "std")]
+#[no_mangle]
+pub extern "C" fn count(ptr: u32, len: u32) -> u64 {
+    let input = try_result_raw!(host_args_envelope(ptr, len));
+    match parse_count(input) {
+        Ok(count) => return_ok(&count.to_le_bytes()),
+        Err(error) => return_err_ptr(error).into(),
+    }
+}
+
+#[cfg(feature = "std")]
+fn parse_count(input: &[u8]) -> Result<u32, WasmError> {
+    core::str::from_utf8(input)
+        .ok()
+        .and_then(|text| text.trim().parse().ok())
+        .ok_or_else(|| wasm_error!(ErrorKind::Validation, "count must be a decimal number"))
+}
+
+// With mock-host, native builds answer from a MockHost, see the tests below
+#[cfg(all(feature = "std", any(target_arch = "wasm32", feature = "mock-host")))]
+aingle_wasmer_guest::host_externs!(greeting_prefix);
+
+/// Native builds have no host to ask
+#[cfg(all(
+    feature = "std",
+    not(target_arch = "wasm32"),
+    not(feature = "mock-host")
+))]
+unsafe extern "C" fn greeting_prefix(_ptr: u32, _len: u32) -> u64 {
+    aingle_wasmer_guest::WasmResult::err(aingle_wasmer_guest::WasmSlice::empty()).into_raw()
+}
+
+#[cfg(feature = "std")]
+fn greet(name: String) -> Result<String, WasmError> {
+    let prefix: String = host_call_enveloped(greeting_prefix, ())?;
+    Ok(prefix + &name)
+}
+
+#[cfg(feature = "std")]
+fn sum(numbers: Vec<u32>) -> Result<u64, WasmError> {
+    Ok(numbers.into_iter().map(u64::from).sum())
+}
+
+#[cfg(feature = "std")]
+guest_fn!(impl interface::Greet as greet => greet);
+#[cfg(feature = "std")]
+guest_fn!(impl interface::Sum as sum => sum);
+
+/// Return a random v4 UUID, drawing on the host's randomness
+#[cfg(feature = "random")]
+#[no_mangle]
+pub extern "C" fn uuid(_ptr: u32, _len: u32) -> u64 {
+    return_ok(uuid::Uuid::new_v4().as_bytes())
+}
+
+#[cfg(all(not(feature = "std"), target_arch = "wasm32"))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    core::arch::wasm32::unreachable()
+}
+
+#[cfg(all(test, feature = "mock-host"))]
+mod tests {
+    use super::*;
+    use aingle_wasmer_guest::MockHost;
+
+    #[test]
+    fn test_greet_uses_host_prefix() {
+        let host = MockHost::new();
+        host.register_typed("greeting_prefix", |()| {
+            Ok::<_, WasmError>("Hello, ".to_string())
+        });
+        let _guard = host.install();
+
+        assert_eq!(greet("Ada".into()).unwrap(), "Hello, Ada");
+        assert_eq!(host.call_count("greeting_prefix"), 1);
+    }
+
+    #[test]
+    fn test_greet_host_error() {
+        let host = MockHost::new();
+        host.register("greeting_prefix", |_| {
+            Err(wasm_error!(
+                ErrorKind::PermissionDenied,
+                "no greetings today"
+            ))
+        });
+        let _guard = host.install();
+
+        let error = greet("Ada".into()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+    }
+}
@@ -0,0 +1,120 @@
+//! The example guest loaded and called through the `holochain_compat`
+//! adapters of the host crate, as code written against
+//! `holochain_wasmer_host` would
+//!
+//! Builds the guest for wasm32-unknown-unknown, so it is ignored by default:
+//!
+//! ```text
+//! cargo test -p aingle_wasmer_example_guest --test holochain_compat -- --ignored
+//! ```
+
+use aingle_wasmer_example_interface::{Greet, Sum, GREETING_PREFIX};
+use aingle_wasmer_host::holochain_compat::{
+    guest, ModuleCacheCompat, SerializedBytesError, WasmError, WasmErrorInner,
+};
+use aingle_wasmer_host::{GuestFunction, HostFunction, HostFunctionRegistry, ImportBuilder};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use wasmer::{AsStoreMut, Instance, Module, RuntimeError, Store};
+
+const TARGET: &str = "wasm32-unknown-unknown";
+
+/// Build the guest, returning the module bytes
+///
+/// Shares the target directory of `tests/interface.rs`, which builds the
+/// guest the same way.
+fn build() -> Vec<u8> {
+    let target_dir = std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("../../target"))
+        .join("guest-interface");
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "-p", env!("CARGO_PKG_NAME"), "--release"])
+        .args(["--target", TARGET])
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "building the guest failed");
+
+    let wasm = target_dir
+        .join(TARGET)
+        .join("release")
+        .join("aingle_wasmer_example_guest.wasm");
+    std::fs::read(wasm).unwrap()
+}
+
+/// Host side of `GREETING_PREFIX`
+struct GreetingPrefix(Option<&'static str>);
+
+impl HostFunction<(), String> for GreetingPrefix {
+    const NAME: &'static str = GREETING_PREFIX;
+
+    fn call(&self, _args: ()) -> Result<String, aingle_wasmer_host::WasmError> {
+        self.0
+            .map(str::to_string)
+            .ok_or_else(|| aingle_wasmer_host::WasmError::Host("no greeting today".to_string()))
+    }
+}
+
+fn instantiate(store: &mut Store, module: &Module, prefix: Option<&'static str>) -> Arc<Instance> {
+    let registry = HostFunctionRegistry::new().register(GreetingPrefix(prefix));
+    let instance = ImportBuilder::new(store)
+        .functions(store, &registry)
+        .instantiate(store, module)
+        .unwrap();
+    Arc::new(instance)
+}
+
+fn compat_error(error: RuntimeError) -> WasmErrorInner {
+    error.downcast::<WasmError>().unwrap().error
+}
+
+#[test]
+#[ignore = "builds the example guest for wasm32"]
+fn test_holochain_compat_adapters() {
+    let wasm = build();
+    let key = [7u8; 32];
+
+    // ModuleCacheCompat
+    let cache = ModuleCacheCompat::new(None);
+    let module = cache.get(key, &wasm).unwrap();
+    assert!(Arc::ptr_eq(&module, &cache.get(key, &wasm).unwrap()));
+    assert!(matches!(
+        compat_error(cache.get([8u8; 32], b"not wasm").unwrap_err()),
+        WasmErrorInner::Compile(_)
+    ));
+
+    // guest::call, typed both ways
+    let mut store = Store::new(cache.inner().engine().clone());
+    let instance = instantiate(&mut store, &module, Some("hello "));
+    let mut store = store.as_store_mut();
+    let greeting: String = guest::call(
+        &mut store,
+        instance.clone(),
+        Greet::NAME,
+        "wasm".to_string(),
+    )
+    .unwrap();
+    assert_eq!(greeting, "hello wasm");
+    let total: u64 = guest::call(&mut store, instance.clone(), Sum::NAME, vec![1u32, 2]).unwrap();
+    assert_eq!(total, 3);
+
+    // Errors in holochain's taxonomy
+    let error = guest::call::<_, Vec<String>>(&mut store, instance, Sum::NAME, vec![1u32]);
+    assert!(matches!(
+        compat_error(error.unwrap_err()),
+        WasmErrorInner::Serialize(SerializedBytesError::Deserialize(_))
+    ));
+
+    let mut store = Store::new(cache.inner().engine().clone());
+    let instance = instantiate(&mut store, &module, None);
+    let error = guest::call::<_, String>(&mut store.as_store_mut(), instance, Greet::NAME, "wasm");
+    match compat_error(error.unwrap_err()) {
+        WasmErrorInner::Guest(message) => {
+            assert!(message.contains("no greeting today"), "{}", message)
+        }
+        error => panic!("expected a guest error, got {:?}", error),
+    }
+}
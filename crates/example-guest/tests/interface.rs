@@ -0,0 +1,88 @@
+//! The example guest's interface, called through the markers of the shared
+//! interface crate, with the guest's host function served by a
+//! `HostFunctionRegistry`
+//!
+//! Builds the guest for wasm32-unknown-unknown, so it is ignored by default:
+//!
+//! ```text
+//! cargo test -p aingle_wasmer_example_guest --test interface -- --ignored
+//! ```
+
+use aingle_wasmer_example_interface::{Greet, Sum, GREETING_PREFIX};
+use aingle_wasmer_host::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use wasmer::{AsStoreMut, Engine, Instance, Module, Store};
+
+const TARGET: &str = "wasm32-unknown-unknown";
+
+/// Build the guest, returning the module bytes
+///
+/// Uses its own target directory, as the outer `cargo test` holds the lock
+/// on the regular one.
+fn build() -> Vec<u8> {
+    let target_dir = std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("../../target"))
+        .join("guest-interface");
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "-p", env!("CARGO_PKG_NAME"), "--release"])
+        .args(["--target", TARGET])
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "building the guest failed");
+
+    let wasm = target_dir
+        .join(TARGET)
+        .join("release")
+        .join("aingle_wasmer_example_guest.wasm");
+    std::fs::read(wasm).unwrap()
+}
+
+/// Host side of `GREETING_PREFIX`
+struct GreetingPrefix(Option<&'static str>);
+
+impl HostFunction<(), String> for GreetingPrefix {
+    const NAME: &'static str = GREETING_PREFIX;
+
+    fn call(&self, _args: ()) -> Result<String, WasmError> {
+        self.0
+            .map(str::to_string)
+            .ok_or_else(|| WasmError::Host("no greeting today".to_string()))
+    }
+}
+
+fn instantiate(store: &mut Store, module: &Module, prefix: Option<&'static str>) -> Arc<Instance> {
+    let registry = HostFunctionRegistry::new().register(GreetingPrefix(prefix));
+    let instance = ImportBuilder::new(store)
+        .functions(store, &registry)
+        .instantiate(store, module)
+        .unwrap();
+    Arc::new(instance)
+}
+
+#[test]
+#[ignore = "builds the example guest for wasm32"]
+fn test_interface_from_host_and_guest() {
+    let engine = Engine::default();
+    let module = Module::new(&engine, build()).unwrap();
+
+    let mut store = Store::new(engine.clone());
+    let instance = instantiate(&mut store, &module, Some("hello "));
+    let mut store = store.as_store_mut();
+
+    let greeting = call_fn::<Greet>(&mut store, instance.clone(), &"wasm".to_string()).unwrap();
+    assert_eq!(greeting, "hello wasm");
+    let total = call_fn::<Sum>(&mut store, instance, &vec![1, 2, u32::MAX]).unwrap();
+    assert_eq!(total, 3 + u64::from(u32::MAX));
+
+    // Errors of the host function reach the host through the guest
+    let mut store = Store::new(engine.clone());
+    let instance = instantiate(&mut store, &module, None);
+    let error =
+        call_fn::<Greet>(&mut store.as_store_mut(), instance, &"wasm".to_string()).unwrap_err();
+    assert!(error.to_string().contains("no greeting today"), "{}", error);
+}
@@ -0,0 +1,80 @@
+//! UUIDs generated inside the example guest with randomness from the host
+//!
+//! Builds the guest with the `random` feature for wasm32-unknown-unknown, so
+//! it is ignored by default:
+//!
+//! ```text
+//! cargo test -p aingle_wasmer_example_guest --test random -- --ignored
+//! ```
+
+use aingle_wasmer_host::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use wasmer::{AsStoreMut, Engine, Module, Store};
+
+const TARGET: &str = "wasm32-unknown-unknown";
+
+/// Build the guest with the `random` feature, returning the module bytes
+///
+/// Uses its own target directory, as the outer `cargo test` holds the lock
+/// on the regular one.
+fn build() -> Vec<u8> {
+    let target_dir = std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("../../target"))
+        .join("guest-random");
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "-p", env!("CARGO_PKG_NAME"), "--release"])
+        .args(["--target", TARGET, "--features", "random"])
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .env("RUSTFLAGS", r#"--cfg getrandom_backend="custom""#)
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "building the random guest failed");
+
+    let wasm = target_dir
+        .join(TARGET)
+        .join("release")
+        .join("aingle_wasmer_example_guest.wasm");
+    std::fs::read(wasm).unwrap()
+}
+
+/// Call the guest's `uuid` export on a fresh instance
+fn uuid(
+    engine: &Engine,
+    module: &Module,
+    random: Option<RandomSource>,
+) -> Result<Vec<u8>, wasmer::RuntimeError> {
+    let mut store = Store::new(engine.clone());
+    let mut imports = ImportBuilder::new(&mut store);
+    if let Some(source) = random {
+        imports = imports.random(source);
+    }
+    let instance = imports.instantiate(&mut store, module).unwrap();
+    call(&mut store.as_store_mut(), Arc::new(instance), "uuid", b"x")
+}
+
+#[test]
+#[ignore = "builds the example guest for wasm32"]
+fn test_uuid_new_v4_in_guest() {
+    let engine = Engine::default();
+    let module = Module::new(&engine, build()).unwrap();
+
+    let first = uuid(&engine, &module, Some(RandomSource::Os)).unwrap();
+    let second = uuid(&engine, &module, Some(RandomSource::Os)).unwrap();
+    assert_eq!(first.len(), 16);
+    assert_eq!(first[6] >> 4, 4, "not a v4 UUID");
+    assert_ne!(first, second);
+
+    // Seeded hosts hand out the same UUIDs on every run
+    let seeded = uuid(&engine, &module, Some(RandomSource::Seeded(7))).unwrap();
+    assert_eq!(
+        seeded,
+        uuid(&engine, &module, Some(RandomSource::Seeded(7))).unwrap()
+    );
+
+    // Without a source the guest gets an error instead of blocking
+    assert!(uuid(&engine, &module, None).is_err());
+}
@@ -0,0 +1,65 @@
+//! Size of the example guest with and without the guest crate's `min-size`
+//! feature
+//!
+//! Builds the guest for wasm32-unknown-unknown in release mode, so it is
+//! ignored by default:
+//!
+//! ```text
+//! cargo test -p aingle_wasmer_example_guest --test size -- --ignored
+//! ```
+//!
+//! The sizes are recorded in `target/guest-size.txt`.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const TARGET: &str = "wasm32-unknown-unknown";
+
+fn target_dir() -> PathBuf {
+    std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("../../target"))
+}
+
+/// Build the example guest with `features`, returning its size in bytes
+///
+/// Uses its own target directory, as the outer `cargo test` holds the lock
+/// on the regular one.
+fn build(features: &str) -> u64 {
+    let target_dir = target_dir().join("guest-size");
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "-p", env!("CARGO_PKG_NAME"), "--release"])
+        .args([
+            "--target",
+            TARGET,
+            "--no-default-features",
+            "--features",
+            features,
+        ])
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "building with `{}` failed", features);
+
+    let wasm = target_dir
+        .join(TARGET)
+        .join("release")
+        .join("aingle_wasmer_example_guest.wasm");
+    std::fs::metadata(wasm).unwrap().len()
+}
+
+#[test]
+#[ignore = "builds the example guest for wasm32"]
+fn test_min_size_shrinks_guest() {
+    let mut report = String::new();
+    for base in ["std", "alloc"] {
+        let full = build(base);
+        let min = build(&format!("{},min-size", base));
+        report += &format!("{}: {} bytes, with min-size: {} bytes\n", base, full, min);
+        assert!(min <= full, "min-size grew the {} guest", base);
+    }
+
+    std::fs::write(target_dir().join("guest-size.txt"), &report).unwrap();
+    print!("{}", report);
+}
@@ -0,0 +1,28 @@
+//! # AIngle WASM Example Interface
+//!
+//! The functions of the example guest, declared once for the guest that
+//! exports them and the hosts that call them. The guest exports each
+//! marker with `guest_fn!(impl ..)`, and hosts call it with `call_fn`, so
+//! a name or type out of step with this crate fails to build on either
+//! side.
+
+#![no_std]
+#![warn(missing_docs)]
+
+extern crate alloc;
+
+use aingle_wasmer_common::guest_fn;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+guest_fn! {
+    /// Greet someone by name, with the prefix from the host's
+    /// [`GREETING_PREFIX`] function
+    pub struct Greet => fn greet(String) -> String;
+    /// Add up numbers
+    pub struct Sum => fn sum(Vec<u32>) -> u64;
+}
+
+/// Host function the guest asks for the prefix of its greetings, taking
+/// no input and returning a `String`
+pub const GREETING_PREFIX: &str = "greeting_prefix";
@@ -0,0 +1,74 @@
+//! Benchmark for the guest arena and returning results, run natively
+//!
+//! Each iteration resets the arena like the host does after a call, so the
+//! figures include the reset.
+
+use aingle_wasmer_guest::{arena_reset, return_ok, GuestArena};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const SIZES: [usize; 3] = [64, 64 * 1024, 4 * 1024 * 1024];
+
+/// A call's worth of small allocations
+fn bench_arena_alloc(c: &mut Criterion) {
+    let arena = GuestArena::new();
+    let mut group = c.benchmark_group("arena_alloc");
+
+    for count in [16, 256] {
+        group.throughput(Throughput::Elements(count));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, &count| {
+            b.iter(|| {
+                for i in 0..count {
+                    black_box(arena.alloc(24 + (i % 64) as usize));
+                }
+                arena.reset()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+/// Copying a payload into the arena, as for host input
+fn bench_arena_copy(c: &mut Criterion) {
+    let arena = GuestArena::new();
+    let mut group = c.benchmark_group("arena_copy");
+
+    for size in SIZES {
+        let data = vec![0xa5u8; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &data, |b, data| {
+            b.iter(|| {
+                black_box(arena.try_alloc_copy(data).unwrap());
+                arena.reset()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+/// Enveloping a result into the global arena
+fn bench_return_ok(c: &mut Criterion) {
+    let mut group = c.benchmark_group("return_ok");
+
+    for size in SIZES {
+        let data = vec![0xa5u8; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &data, |b, data| {
+            b.iter(|| {
+                black_box(return_ok(data));
+                arena_reset()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_arena_alloc,
+    bench_arena_copy,
+    bench_return_ok
+);
+criterion_main!(benches);
@@ -0,0 +1,64 @@
+//! Benchmark for the arena global allocator
+//!
+//! Replays the allocations of a typical entry call: a growing output buffer,
+//! a handful of short-lived strings and a decoded record list. Compare with
+//! `scripts/guest-size.sh` for the effect on the wasm binary size.
+
+use aingle_wasmer_guest::ArenaGlobalAlloc;
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::alloc::{GlobalAlloc, Layout, System};
+
+/// Pages taken from the system allocator and never returned
+fn leak_pages(pages: usize) -> Option<usize> {
+    let layout = Layout::from_size_align(pages * 64 * 1024, 64 * 1024).ok()?;
+    let ptr = unsafe { System.alloc(layout) };
+    (!ptr.is_null()).then_some(ptr as usize)
+}
+
+/// One entry call's worth of allocations, freed before returning
+fn call_workload(alloc: &dyn GlobalAlloc) {
+    unsafe {
+        // Output buffer growing like a Vec<u8>
+        let mut layout = Layout::from_size_align(8, 1).unwrap();
+        let mut buffer = alloc.alloc(layout);
+        while layout.size() < 4096 {
+            let new_size = layout.size() * 2;
+            buffer = alloc.realloc(buffer, layout, new_size);
+            layout = Layout::from_size_align(new_size, 1).unwrap();
+        }
+
+        let strings: Vec<_> = (0..16)
+            .map(|i| {
+                let layout = Layout::from_size_align(24 + i * 4, 1).unwrap();
+                (alloc.alloc(layout), layout)
+            })
+            .collect();
+        let records = Layout::from_size_align(64 * 48, 8).unwrap();
+        let list = alloc.alloc(records);
+
+        alloc.dealloc(list, records);
+        for (ptr, layout) in strings.into_iter().rev() {
+            alloc.dealloc(ptr, layout);
+        }
+        alloc.dealloc(buffer, layout);
+    }
+}
+
+fn bench_call_allocations(c: &mut Criterion) {
+    let mut group = c.benchmark_group("call_allocations");
+
+    group.bench_function("system", |b| b.iter(|| call_workload(&System)));
+
+    let arena = ArenaGlobalAlloc::with_grow(leak_pages);
+    group.bench_function("arena_global_alloc", |b| {
+        b.iter(|| {
+            call_workload(&arena);
+            arena.reset()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_call_allocations);
+criterion_main!(benches);
@@ -2,33 +2,278 @@
 //!
 //! Uses bumpalo for fast, sequential allocation with bulk deallocation.
 
+use aingle_wasmer_common::MemoryError;
 use bumpalo::Bump;
-use core::cell::RefCell;
+use core::alloc::Layout;
+use core::cell::{Cell, RefCell};
 
 thread_local! {
     /// The global arena for this WASM instance
     pub static ARENA: GuestArena = GuestArena::new();
 }
 
+/// Default cap on a single allocation requested by the host
+///
+/// Lengths up to `i32::MAX` keep `ptr + len` within the 32-bit offsets the
+/// host computes with.
+pub const DEFAULT_MAX_HOST_ALLOCATION: usize = i32::MAX as usize;
+
+/// Regions of a [`GuestArena`]
+///
+/// Results must survive until the host has read them, while host call
+/// buffers are dead once the call returns. Keeping them apart lets the
+/// scratch region be reclaimed after each host call without touching data
+/// the guest is about to return.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArenaRegion {
+    /// Call inputs and results, reclaimed when the host resets the arena
+    Output,
+    /// Host call arguments and responses, reclaimed when the outermost host
+    /// call returns
+    Scratch,
+}
+
+/// Allocation statistics of one arena region
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RegionStats {
+    /// Bytes currently held by the region
+    pub allocated_bytes: usize,
+    /// Largest `allocated_bytes` seen since the mark was last reset
+    pub high_water_mark: usize,
+}
+
+/// One bump region with its high-water mark
+struct Region {
+    bump: RefCell<Bump>,
+    high_water: Cell<usize>,
+}
+
+impl Region {
+    fn new() -> Self {
+        Self {
+            bump: RefCell::new(Bump::new()),
+            high_water: Cell::new(0),
+        }
+    }
+
+    fn try_alloc_layout(&self, layout: Layout) -> Result<*mut u8, MemoryError> {
+        let bump = self.bump.borrow();
+        let ptr = bump
+            .try_alloc_layout(layout)
+            .map_err(|_| MemoryError::ArenaExhausted)?
+            .as_ptr();
+        self.high_water
+            .set(self.high_water.get().max(bump.allocated_bytes()));
+        Ok(ptr)
+    }
+
+    fn reserve(&self, bytes: usize) -> Result<(), MemoryError> {
+        let bump = self.bump.borrow();
+        if bump.chunk_capacity() >= bytes {
+            return Ok(());
+        }
+        // A fresh chunk is allocated for the buffer, whose bytes are
+        // returned to the chunk when it is dropped as the last allocation
+        let mut buffer = bumpalo::collections::Vec::<u8>::new_in(&bump);
+        buffer
+            .try_reserve_exact(bytes)
+            .map_err(|_| MemoryError::ArenaExhausted)?;
+        drop(buffer);
+        self.high_water
+            .set(self.high_water.get().max(bump.allocated_bytes()));
+        Ok(())
+    }
+
+    fn reset(&self) {
+        let mut bump = self.bump.borrow_mut();
+        // Bump keeps its last chunk on reset; with the arena global
+        // allocator the chunk is released so the region can rewind
+        #[cfg(all(feature = "arena-global-alloc", target_arch = "wasm32"))]
+        {
+            let limit = bump.allocation_limit();
+            *bump = Bump::new();
+            bump.set_allocation_limit(limit);
+        }
+        #[cfg(not(all(feature = "arena-global-alloc", target_arch = "wasm32")))]
+        bump.reset();
+    }
+
+    /// Set the bump aside for a fresh one with the same limit
+    fn take(&self) -> Bump {
+        let mut bump = self.bump.borrow_mut();
+        let fresh = Bump::new();
+        fresh.set_allocation_limit(bump.allocation_limit());
+        core::mem::replace(&mut *bump, fresh)
+    }
+
+    /// Drop the bump for one set aside by [`Region::take`]
+    fn restore(&self, bump: Bump) {
+        *self.bump.borrow_mut() = bump;
+    }
+
+    fn allocated_bytes(&self) -> usize {
+        self.bump.borrow().allocated_bytes()
+    }
+
+    fn stats(&self) -> RegionStats {
+        RegionStats {
+            allocated_bytes: self.allocated_bytes(),
+            high_water_mark: self.high_water.get(),
+        }
+    }
+}
+
+/// Regions of a [`GuestArena`] set aside for a nested call
+struct Frame {
+    output: Bump,
+    scratch: Bump,
+    host_calls: usize,
+}
+
 /// Arena allocator for WASM guest memory
+///
+/// Allocations go to the [`ArenaRegion::Output`] region unless stated
+/// otherwise; host call buffers use [`ArenaRegion::Scratch`].
 pub struct GuestArena {
-    bump: RefCell<Bump>,
+    output: Region,
+    scratch: Region,
+    /// Number of host calls in progress
+    host_calls: Cell<usize>,
+    max_host_allocation: Cell<usize>,
+    /// Number of resets so far
+    generation: Cell<u64>,
+    high_water: Cell<usize>,
+    /// Regions of the calls the current one is nested in, innermost last
+    frames: RefCell<Vec<Frame>>,
 }
 
 impl GuestArena {
     /// Create a new arena
     pub fn new() -> Self {
         Self {
-            bump: RefCell::new(Bump::new()),
+            output: Region::new(),
+            scratch: Region::new(),
+            host_calls: Cell::new(0),
+            max_host_allocation: Cell::new(DEFAULT_MAX_HOST_ALLOCATION),
+            generation: Cell::new(0),
+            high_water: Cell::new(0),
+            frames: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Create an arena whose regions hold at most `bytes` each
+    pub fn with_capacity_limit(bytes: usize) -> Self {
+        let arena = Self::new();
+        arena.set_limit(Some(bytes));
+        arena
+    }
+
+    /// Limit the bytes held by each region, or lift the limit with `None`
+    ///
+    /// Allocations past the limit fail with [`MemoryError::ArenaExhausted`]
+    /// instead of growing guest memory. The limit survives resets.
+    pub fn set_limit(&self, bytes: Option<usize>) {
+        self.output.bump.borrow().set_allocation_limit(bytes);
+        self.scratch.bump.borrow().set_allocation_limit(bytes);
+    }
+
+    /// Get the capacity limit, if any
+    pub fn limit(&self) -> Option<usize> {
+        self.output.bump.borrow().allocation_limit()
+    }
+
+    /// Cap a single allocation requested by the host at `bytes`, or restore
+    /// [`DEFAULT_MAX_HOST_ALLOCATION`] with `None`
+    ///
+    /// Larger requests are refused by [`GuestArena::alloc_for_host`]. The
+    /// cap never exceeds the default.
+    pub fn set_max_host_allocation(&self, bytes: Option<usize>) {
+        let bytes = bytes.map_or(DEFAULT_MAX_HOST_ALLOCATION, |bytes| {
+            bytes.min(DEFAULT_MAX_HOST_ALLOCATION)
+        });
+        self.max_host_allocation.set(bytes);
+    }
+
+    /// Largest single allocation the host may request
+    pub fn max_host_allocation(&self) -> usize {
+        self.max_host_allocation.get()
+    }
+
+    fn region(&self, region: ArenaRegion) -> &Region {
+        match region {
+            ArenaRegion::Output => &self.output,
+            ArenaRegion::Scratch => &self.scratch,
         }
     }
 
     /// Allocate bytes from the arena
+    ///
+    /// # Panics
+    /// Panics if the arena is exhausted; see [`GuestArena::try_alloc`].
     pub fn alloc(&self, len: usize) -> *mut u8 {
-        self.bump
-            .borrow()
-            .alloc_layout(core::alloc::Layout::from_size_align(len, 1).unwrap())
-            .as_ptr()
+        self.alloc_layout(Layout::from_size_align(len, 1).unwrap())
+    }
+
+    /// Allocate bytes from the arena aligned to `align`
+    ///
+    /// `align` must be a non-zero power of two, otherwise
+    /// [`MemoryError::Alignment`] is returned.
+    pub fn alloc_aligned(&self, len: usize, align: usize) -> Result<*mut u8, MemoryError> {
+        self.try_alloc_layout(ArenaRegion::Output, aligned_layout(len, align)?)
+    }
+
+    /// Allocate a region matching `layout` from the arena
+    ///
+    /// # Panics
+    /// Panics if the arena is exhausted.
+    pub fn alloc_layout(&self, layout: Layout) -> *mut u8 {
+        self.try_alloc_layout(ArenaRegion::Output, layout)
+            .expect("guest arena exhausted")
+    }
+
+    /// Allocate bytes from the arena
+    ///
+    /// Fails with [`MemoryError::ArenaExhausted`] past the capacity limit or
+    /// when guest memory cannot grow.
+    pub fn try_alloc(&self, len: usize) -> Result<*mut u8, MemoryError> {
+        self.try_alloc_in(ArenaRegion::Output, len)
+    }
+
+    /// Allocate bytes from `region`, failing like [`GuestArena::try_alloc`]
+    pub fn try_alloc_in(&self, region: ArenaRegion, len: usize) -> Result<*mut u8, MemoryError> {
+        let layout = Layout::from_size_align(len, 1)
+            .map_err(|_| MemoryError::AllocationFailed { requested: len })?;
+        self.try_alloc_layout(region, layout)
+    }
+
+    fn try_alloc_layout(
+        &self,
+        region: ArenaRegion,
+        layout: Layout,
+    ) -> Result<*mut u8, MemoryError> {
+        let ptr = self.region(region).try_alloc_layout(layout)?;
+        self.high_water
+            .set(self.high_water.get().max(self.allocated_bytes()));
+        Ok(ptr)
+    }
+
+    /// Allocate memory the host asked for
+    ///
+    /// During a host call this is the host's response and comes from the
+    /// scratch region; otherwise it is the input of a guest call and comes
+    /// from the output region. Lengths above
+    /// [`GuestArena::max_host_allocation`] fail with
+    /// [`MemoryError::AllocationFailed`], exhaustion like
+    /// [`GuestArena::try_alloc`].
+    pub fn alloc_for_host(&self, len: usize) -> Result<*mut u8, MemoryError> {
+        if len > self.max_host_allocation.get() {
+            return Err(MemoryError::AllocationFailed { requested: len });
+        }
+        let region = match self.host_calls.get() {
+            0 => ArenaRegion::Output,
+            _ => ArenaRegion::Scratch,
+        };
+        self.try_alloc_in(region, len)
     }
 
     /// Allocate and copy bytes
@@ -40,14 +285,150 @@ impl GuestArena {
         ptr
     }
 
+    /// Allocate and copy bytes, failing like [`GuestArena::try_alloc`]
+    pub fn try_alloc_copy(&self, data: &[u8]) -> Result<*mut u8, MemoryError> {
+        self.try_alloc_copy_in(ArenaRegion::Output, data)
+    }
+
+    /// Allocate and copy bytes to `region`, failing like
+    /// [`GuestArena::try_alloc`]
+    pub fn try_alloc_copy_in(
+        &self,
+        region: ArenaRegion,
+        data: &[u8],
+    ) -> Result<*mut u8, MemoryError> {
+        let ptr = self.try_alloc_in(region, data.len())?;
+        unsafe {
+            core::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+        }
+        Ok(ptr)
+    }
+
+    /// Allocate and copy bytes to a region aligned to `align`
+    pub fn alloc_copy_aligned(&self, data: &[u8], align: usize) -> Result<*mut u8, MemoryError> {
+        let ptr = self.alloc_aligned(data.len(), align)?;
+        unsafe {
+            core::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+        }
+        Ok(ptr)
+    }
+
+    /// Mark the start of a host call
+    ///
+    /// Until the matching [`GuestArena::end_host_call`], memory the host
+    /// allocates comes from the scratch region.
+    pub fn begin_host_call(&self) {
+        self.host_calls.set(self.host_calls.get() + 1);
+    }
+
+    /// Mark the end of a host call, resetting the scratch region when no
+    /// other host call is in progress
+    ///
+    /// Anything borrowed from the scratch region must have been copied or
+    /// dropped by then.
+    pub fn end_host_call(&self) {
+        let host_calls = self.host_calls.get().saturating_sub(1);
+        self.host_calls.set(host_calls);
+        if host_calls == 0 {
+            self.scratch.reset();
+        }
+    }
+
+    /// Set the regions aside for a call nested in a host call, returning
+    /// the number of frames pushed
+    ///
+    /// The nested call starts on empty regions with no host call in
+    /// progress; memory allocated before stays valid, and is not reclaimed
+    /// by host calls of the nested call.
+    pub fn push_frame(&self) -> usize {
+        let frame = Frame {
+            output: self.output.take(),
+            scratch: self.scratch.take(),
+            host_calls: self.host_calls.replace(0),
+        };
+        let mut frames = self.frames.borrow_mut();
+        frames.push(frame);
+        frames.len()
+    }
+
+    /// Drop the regions of a nested call and restore those set aside by
+    /// the matching [`GuestArena::push_frame`], returning the number of
+    /// frames left, or `None` if none was pushed
+    ///
+    /// The result of the nested call must have been read by then.
+    pub fn pop_frame(&self) -> Option<usize> {
+        let mut frames = self.frames.borrow_mut();
+        let frame = frames.pop()?;
+        self.output.restore(frame.output);
+        self.scratch.restore(frame.scratch);
+        self.host_calls.set(frame.host_calls);
+        Some(frames.len())
+    }
+
+    /// Number of frames pushed and not popped yet
+    pub fn frame_depth(&self) -> usize {
+        self.frames.borrow().len()
+    }
+
+    /// Make room for `bytes` in the output region without growing memory
+    ///
+    /// Does nothing if the current chunk already has room, and counts
+    /// against the capacity limit otherwise. With the `arena-global-alloc`
+    /// feature resets release the chunk, so the room only lasts until the
+    /// next reset.
+    pub fn reserve(&self, bytes: usize) -> Result<(), MemoryError> {
+        self.reserve_in(ArenaRegion::Output, bytes)
+    }
+
+    /// Make room for `bytes` in `region`; see [`GuestArena::reserve`]
+    pub fn reserve_in(&self, region: ArenaRegion, bytes: usize) -> Result<(), MemoryError> {
+        self.region(region).reserve(bytes)?;
+        self.high_water
+            .set(self.high_water.get().max(self.allocated_bytes()));
+        Ok(())
+    }
+
     /// Reset the arena, deallocating all memory
+    ///
+    /// Frames pushed are dropped with it. The high-water mark is kept; see
+    /// [`GuestArena::reset_high_water_mark`].
     pub fn reset(&self) {
-        self.bump.borrow_mut().reset();
+        self.frames.borrow_mut().clear();
+        self.output.reset();
+        self.scratch.reset();
+        self.host_calls.set(0);
+        self.generation.set(self.generation.get() + 1);
+    }
+
+    /// Number of resets so far
+    ///
+    /// Memory allocated from the arena is invalid once this has changed.
+    pub fn generation(&self) -> u64 {
+        self.generation.get()
     }
 
     /// Get allocated bytes count
     pub fn allocated_bytes(&self) -> usize {
-        self.bump.borrow().allocated_bytes()
+        self.output.allocated_bytes() + self.scratch.allocated_bytes()
+    }
+
+    /// Statistics of one region
+    pub fn region_stats(&self, region: ArenaRegion) -> RegionStats {
+        self.region(region).stats()
+    }
+
+    /// Largest [`GuestArena::allocated_bytes`] seen since the mark was last reset
+    pub fn high_water_mark(&self) -> usize {
+        self.high_water.get()
+    }
+
+    /// Restart high-water tracking from the current allocation, returning
+    /// the previous mark
+    pub fn reset_high_water_mark(&self) -> usize {
+        for region in [&self.output, &self.scratch] {
+            region.high_water.set(region.allocated_bytes());
+        }
+        self.high_water.replace(self.allocated_bytes())
     }
 }
 
@@ -57,14 +438,48 @@ impl Default for GuestArena {
     }
 }
 
+/// Statistics of one region of the global arena
+pub fn arena_region_stats(region: ArenaRegion) -> RegionStats {
+    ARENA.with(|arena| arena.region_stats(region))
+}
+
 /// Allocate from the global arena
 pub fn arena_alloc(len: usize) -> *mut u8 {
     ARENA.with(|arena| arena.alloc(len))
 }
 
-/// Allocate and copy from the global arena
-pub fn arena_alloc_copy(data: &[u8]) -> *mut u8 {
-    ARENA.with(|arena| arena.alloc_copy(data))
+/// Allocate from the global arena, failing if it is exhausted
+pub fn arena_try_alloc(len: usize) -> Result<*mut u8, MemoryError> {
+    ARENA.with(|arena| arena.try_alloc(len))
+}
+
+/// Allocate from the global arena aligned to `align`
+pub fn arena_alloc_aligned(len: usize, align: usize) -> Result<*mut u8, MemoryError> {
+    ARENA.with(|arena| arena.alloc_aligned(len, align))
+}
+
+/// Allocate and copy from the global arena, optionally aligned to `align`
+pub fn arena_alloc_copy(data: &[u8], align: Option<usize>) -> Result<*mut u8, MemoryError> {
+    ARENA.with(|arena| match align {
+        Some(align) => arena.alloc_copy_aligned(data, align),
+        None => arena.try_alloc_copy(data),
+    })
+}
+
+/// Limit the bytes held by the global arena; see [`GuestArena::set_limit`]
+pub fn arena_set_limit(bytes: Option<usize>) {
+    ARENA.with(|arena| arena.set_limit(bytes));
+}
+
+/// Cap single host allocations from the global arena; see
+/// [`GuestArena::set_max_host_allocation`]
+pub fn arena_set_max_host_allocation(bytes: Option<usize>) {
+    ARENA.with(|arena| arena.set_max_host_allocation(bytes));
+}
+
+/// Make room in the global arena; see [`GuestArena::reserve`]
+pub fn arena_reserve(bytes: usize) -> Result<(), MemoryError> {
+    ARENA.with(|arena| arena.reserve(bytes))
 }
 
 /// Reset the global arena
@@ -72,6 +487,23 @@ pub fn arena_reset() {
     ARENA.with(|arena| arena.reset());
 }
 
+/// High-water mark of the global arena
+pub fn arena_high_water_mark() -> usize {
+    ARENA.with(|arena| arena.high_water_mark())
+}
+
+/// Build a layout, rejecting alignments that are not a power of two
+fn aligned_layout(len: usize, align: usize) -> Result<Layout, MemoryError> {
+    if !align.is_power_of_two() {
+        return Err(MemoryError::Alignment {
+            addr: 0,
+            required: align,
+        });
+    }
+    Layout::from_size_align(len, align)
+        .map_err(|_| MemoryError::AllocationFailed { requested: len })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -109,5 +541,235 @@ mod tests {
 
         arena.reset();
         // After reset, new allocations start fresh
+        assert_eq!(arena.generation(), 1);
+    }
+
+    #[test]
+    fn test_try_alloc() {
+        let arena = GuestArena::new();
+        assert!(arena.try_alloc(64).is_ok());
+        assert!(arena.try_alloc(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_capacity_limit() {
+        let arena = GuestArena::with_capacity_limit(16 * 1024);
+        assert_eq!(arena.limit(), Some(16 * 1024));
+
+        let mut allocated = 0;
+        let error = loop {
+            match arena.try_alloc_copy(&[0xab; 1024]) {
+                Ok(_) => allocated += 1024,
+                Err(e) => break e,
+            }
+            assert!(allocated <= 16 * 1024);
+        };
+        assert_eq!(error, MemoryError::ArenaExhausted);
+        assert_eq!(
+            arena.alloc_aligned(1024, 8),
+            Err(MemoryError::ArenaExhausted)
+        );
+
+        // The limit survives a reset and can be lifted
+        arena.reset();
+        assert_eq!(arena.limit(), Some(16 * 1024));
+        arena.set_limit(None);
+        assert!(arena.try_alloc(64 * 1024).is_ok());
+    }
+
+    #[test]
+    fn test_reserve() {
+        let arena = GuestArena::new();
+        arena.reserve(64 * 1024).unwrap();
+        let reserved = arena.allocated_bytes();
+        assert!(reserved >= 64 * 1024);
+
+        // Enough room already
+        arena.reserve(1024).unwrap();
+        assert_eq!(arena.allocated_bytes(), reserved);
+
+        // Allocations within the reservation do not grow the arena, before
+        // or after a reset
+        for _ in 0..2 {
+            for _ in 0..64 {
+                arena.try_alloc(1000).unwrap();
+            }
+            assert_eq!(arena.allocated_bytes(), reserved);
+            arena.reset();
+        }
+
+        let arena = GuestArena::with_capacity_limit(16 * 1024);
+        assert_eq!(arena.reserve(32 * 1024), Err(MemoryError::ArenaExhausted));
+        arena.reserve(8 * 1024).unwrap();
+        assert!(arena.try_alloc(16 * 1024).is_err());
+    }
+
+    #[test]
+    fn test_high_water_mark() {
+        let arena = GuestArena::new();
+        assert_eq!(arena.high_water_mark(), 0);
+
+        arena.alloc(100_000);
+        let peak = arena.high_water_mark();
+        assert!(peak >= 100_000);
+        assert_eq!(peak, arena.allocated_bytes());
+
+        // Survives an arena reset and smaller allocations afterwards
+        arena.reset();
+        arena.alloc(10);
+        assert_eq!(arena.high_water_mark(), peak);
+
+        assert_eq!(arena.reset_high_water_mark(), peak);
+        assert_eq!(arena.high_water_mark(), arena.allocated_bytes());
+    }
+
+    #[test]
+    fn test_regions() {
+        let arena = GuestArena::new();
+        arena.alloc(100);
+        arena.try_alloc_in(ArenaRegion::Scratch, 5000).unwrap();
+
+        let output = arena.region_stats(ArenaRegion::Output);
+        let scratch = arena.region_stats(ArenaRegion::Scratch);
+        assert!(output.allocated_bytes >= 100);
+        assert!(scratch.high_water_mark >= 5000);
+        assert_eq!(
+            arena.allocated_bytes(),
+            output.allocated_bytes + scratch.allocated_bytes
+        );
+
+        // Host allocations go to scratch only during host calls, and
+        // scratch is reset when the outermost call ends
+        arena.begin_host_call();
+        arena.begin_host_call();
+        arena.alloc_for_host(100_000).unwrap();
+        assert_eq!(arena.region_stats(ArenaRegion::Output), output);
+        arena.end_host_call();
+        let nested = arena.region_stats(ArenaRegion::Scratch);
+        assert!(nested.allocated_bytes >= 105_000);
+        arena.end_host_call();
+        let reset = arena.region_stats(ArenaRegion::Scratch);
+        assert!(reset.allocated_bytes < nested.allocated_bytes);
+        assert_eq!(reset.high_water_mark, nested.high_water_mark);
+
+        arena.alloc_for_host(64).unwrap();
+        assert!(arena.region_stats(ArenaRegion::Output).allocated_bytes >= 164);
+    }
+
+    #[test]
+    fn test_frames() {
+        let arena = GuestArena::with_capacity_limit(64 * 1024);
+        let outer = arena.alloc_copy(b"outer result");
+        arena.begin_host_call();
+        arena.alloc_for_host(100).unwrap();
+        let before = arena.region_stats(ArenaRegion::Output);
+        assert_eq!(arena.pop_frame(), None);
+
+        // A call nested in the host call starts on empty regions
+        assert_eq!(arena.push_frame(), 1);
+        assert_eq!(arena.frame_depth(), 1);
+        assert_eq!(arena.region_stats(ArenaRegion::Output).allocated_bytes, 0);
+        assert_eq!(arena.limit(), Some(64 * 1024));
+        let input = arena.alloc_for_host(32).unwrap();
+        assert!(arena.region_stats(ArenaRegion::Output).allocated_bytes >= 32);
+        arena.begin_host_call();
+        arena.end_host_call();
+        assert_ne!(input, outer);
+
+        // Popping restores the regions of the outer call
+        assert_eq!(arena.pop_frame(), Some(0));
+        assert_eq!(arena.region_stats(ArenaRegion::Output), before);
+        let copied = unsafe { core::slice::from_raw_parts(outer, 12) };
+        assert_eq!(copied, b"outer result");
+        assert!(arena.region_stats(ArenaRegion::Scratch).allocated_bytes >= 100);
+        // The outer host call is still in progress
+        arena.alloc_for_host(64).unwrap();
+        assert_eq!(arena.region_stats(ArenaRegion::Output), before);
+        arena.end_host_call();
+
+        // Resets drop frames left pushed
+        arena.push_frame();
+        arena.reset();
+        assert_eq!(arena.frame_depth(), 0);
+    }
+
+    #[test]
+    fn test_host_allocation_cap() {
+        let arena = GuestArena::new();
+        assert_eq!(arena.max_host_allocation(), DEFAULT_MAX_HOST_ALLOCATION);
+        assert_eq!(
+            arena.alloc_for_host(usize::MAX),
+            Err(MemoryError::AllocationFailed {
+                requested: usize::MAX
+            })
+        );
+
+        arena.set_max_host_allocation(Some(1024));
+        assert!(arena.alloc_for_host(1024).is_ok());
+        assert!(arena.alloc_for_host(1025).is_err());
+
+        arena.set_max_host_allocation(Some(usize::MAX));
+        assert_eq!(arena.max_host_allocation(), DEFAULT_MAX_HOST_ALLOCATION);
+        arena.set_max_host_allocation(None);
+        assert!(arena.alloc_for_host(1025).is_ok());
+    }
+
+    #[test]
+    fn test_allocate_exports_return_zero_on_failure() {
+        assert_eq!(crate::__hc__allocate_1(-1), 0);
+        assert_eq!(crate::__hc__allocate_1(i32::MIN), 0);
+        assert_eq!(crate::__aingle_guest_allocate(u32::MAX), 0);
+
+        arena_set_max_host_allocation(Some(64));
+        assert_eq!(crate::__aingle_guest_allocate(65), 0);
+        assert_eq!(crate::__hc__allocate_1(65), 0);
+        assert_ne!(crate::__aingle_guest_allocate(64), 0);
+
+        arena_set_limit(Some(1024));
+        arena_set_max_host_allocation(None);
+        assert_eq!(crate::__hc__allocate_1(4096), 0);
+    }
+
+    #[test]
+    fn test_alloc_aligned() {
+        let arena = GuestArena::new();
+
+        for align in [8, 64] {
+            // Misalign the bump pointer first
+            arena.alloc(3);
+            let ptr = arena.alloc_aligned(24, align).unwrap();
+            assert_eq!(ptr as usize % align, 0);
+        }
+
+        let ptr = arena.alloc_layout(Layout::new::<[f32; 4]>());
+        assert_eq!(ptr as usize % core::mem::align_of::<f32>(), 0);
+
+        assert_eq!(
+            arena.alloc_aligned(8, 3),
+            Err(MemoryError::Alignment {
+                addr: 0,
+                required: 3
+            })
+        );
+        assert!(matches!(
+            arena.alloc_aligned(8, 0),
+            Err(MemoryError::Alignment { .. })
+        ));
+    }
+
+    #[test]
+    fn test_arena_alloc_copy_aligned() {
+        let data = [1u8, 2, 3, 4, 5];
+        arena_alloc(1);
+
+        let ptr = arena_alloc_copy(&data, Some(8)).unwrap();
+        assert_eq!(ptr as usize % 8, 0);
+        assert_eq!(
+            unsafe { core::slice::from_raw_parts(ptr, data.len()) },
+            data
+        );
+
+        assert!(arena_alloc_copy(&data, None).is_ok());
+        assert!(arena_alloc_copy(&data, Some(12)).is_err());
     }
 }
@@ -0,0 +1,298 @@
+//! Memoization of idempotent host calls
+//!
+//! Validation callbacks often ask the host for the same thing several times
+//! during one call. [`cached_host_call`] and [`cached_host_call_raw`] keep
+//! the responses of such calls for the rest of the guest call, so repeats
+//! with the same host function and serialized input skip the host. Inputs
+//! and responses live in the arena's output region, and the cache forgets
+//! them when the host resets the arena.
+//!
+//! Only use the cache for host functions without side effects whose
+//! answers cannot change during a guest call.
+
+use crate::arena::{ArenaRegion, GuestArena, ARENA};
+#[cfg(feature = "std")]
+use crate::compat::decode_input;
+#[cfg(feature = "std")]
+use crate::host_call::encode_host_args;
+use crate::host_call::host_call_raw;
+use aingle_wasmer_codec::compute_checksum;
+use aingle_wasmer_common::WasmError;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+#[cfg(feature = "std")]
+use serde::{de::DeserializeOwned, Serialize};
+
+thread_local! {
+    /// The host call cache of this WASM instance
+    pub static HOST_CALL_CACHE: HostCallCache = HostCallCache::new();
+}
+
+/// Host function signature accepted by the cache
+pub type HostFn = unsafe extern "C" fn(u32, u32) -> u64;
+
+/// Bounds on what a [`HostCallCache`] keeps during one guest call
+///
+/// Responses that do not fit are returned uncached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheLimits {
+    /// Maximum number of cached calls
+    pub max_entries: usize,
+    /// Maximum bytes of inputs and responses held
+    pub max_bytes: usize,
+}
+
+impl Default for CacheLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: 32,
+            max_bytes: 64 * 1024,
+        }
+    }
+}
+
+/// Statistics of a [`HostCallCache`] since the arena was last reset
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Cached calls
+    pub entries: usize,
+    /// Bytes of inputs and responses held
+    pub bytes: usize,
+    /// Calls answered from the cache
+    pub hits: usize,
+    /// Calls passed on to the host
+    pub misses: usize,
+}
+
+struct Entry {
+    host_fn: usize,
+    checksum: u32,
+    input: &'static [u8],
+    response: &'static [u8],
+}
+
+/// Responses of host calls made during the current guest call
+pub struct HostCallCache {
+    entries: RefCell<Vec<Entry>>,
+    limits: Cell<CacheLimits>,
+    stats: Cell<CacheStats>,
+    /// Arena generation the entries were allocated in
+    generation: Cell<u64>,
+}
+
+impl HostCallCache {
+    /// Create an empty cache with the default limits
+    pub fn new() -> Self {
+        Self {
+            entries: RefCell::new(Vec::new()),
+            limits: Cell::new(CacheLimits::default()),
+            stats: Cell::new(CacheStats::default()),
+            generation: Cell::new(0),
+        }
+    }
+
+    /// Set the limits, taking effect for calls cached from now on
+    pub fn set_limits(&self, limits: CacheLimits) {
+        self.limits.set(limits);
+    }
+
+    /// Current limits
+    pub fn limits(&self) -> CacheLimits {
+        self.limits.get()
+    }
+
+    /// Statistics since the arena was last reset
+    pub fn stats(&self) -> CacheStats {
+        self.sync(ARENA.with(GuestArena::generation));
+        self.stats.get()
+    }
+
+    /// Call `host_fn` over the envelope protocol like [`host_call_raw`],
+    /// answering repeats of earlier calls from the cache
+    ///
+    /// Errors are never cached.
+    pub fn call_raw(&self, host_fn: HostFn, args: &[u8]) -> Result<&'static [u8], WasmError> {
+        self.sync(ARENA.with(GuestArena::generation));
+        let key = host_fn as usize;
+        let checksum = compute_checksum(args);
+
+        let cached = self.entries.borrow().iter().find_map(|entry| {
+            (entry.host_fn == key && entry.checksum == checksum && entry.input == args)
+                .then_some(entry.response)
+        });
+        let mut stats = self.stats.get();
+        if let Some(response) = cached {
+            stats.hits += 1;
+            self.stats.set(stats);
+            return Ok(response);
+        }
+        stats.misses += 1;
+        self.stats.set(stats);
+
+        let response = host_call_raw(host_fn, args)?;
+        self.insert(key, checksum, args, response);
+        Ok(response)
+    }
+
+    fn insert(&self, host_fn: usize, checksum: u32, args: &[u8], response: &'static [u8]) {
+        let limits = self.limits.get();
+        let mut stats = self.stats.get();
+        let bytes = args.len() + response.len();
+        if stats.entries >= limits.max_entries || stats.bytes + bytes > limits.max_bytes {
+            return;
+        }
+        // The response already lives in the output region, the input is
+        // copied there
+        let Ok(ptr) = ARENA.with(|arena| arena.try_alloc_copy_in(ArenaRegion::Output, args)) else {
+            return;
+        };
+        let input = unsafe { core::slice::from_raw_parts(ptr, args.len()) };
+
+        self.entries.borrow_mut().push(Entry {
+            host_fn,
+            checksum,
+            input,
+            response,
+        });
+        stats.entries += 1;
+        stats.bytes += bytes;
+        self.stats.set(stats);
+    }
+
+    /// Forget everything if the arena was reset since the entries were made
+    fn sync(&self, generation: u64) {
+        if self.generation.replace(generation) != generation {
+            self.entries.borrow_mut().clear();
+            self.stats.set(CacheStats::default());
+        }
+    }
+}
+
+impl Default for HostCallCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Call a host function with raw arguments through the instance's cache
+///
+/// See [`HostCallCache::call_raw`].
+pub fn cached_host_call_raw(host_fn: HostFn, args: &[u8]) -> Result<&'static [u8], WasmError> {
+    HOST_CALL_CACHE.with(|cache| cache.call_raw(host_fn, args))
+}
+
+/// Call a host function with typed arguments through the instance's cache
+///
+/// Behaves like [`host_call_enveloped`](crate::host_call_enveloped), except
+/// that repeats with the same host function and serialized input during one
+/// guest call return the cached response.
+#[cfg(feature = "std")]
+pub fn cached_host_call<I, O>(host_fn: HostFn, input: I) -> Result<O, WasmError>
+where
+    I: Serialize + core::fmt::Debug,
+    O: DeserializeOwned + core::fmt::Debug,
+{
+    let args = encode_host_args(input)?;
+    decode_input(cached_host_call_raw(host_fn, &args)?)
+}
+
+/// Limit the instance's host call cache; see [`HostCallCache::set_limits`]
+pub fn host_call_cache_set_limits(limits: CacheLimits) {
+    HOST_CALL_CACHE.with(|cache| cache.set_limits(limits));
+}
+
+/// Statistics of the instance's host call cache
+pub fn host_call_cache_stats() -> CacheStats {
+    HOST_CALL_CACHE.with(HostCallCache::stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arena::arena_reset;
+    use aingle_wasmer_common::{WasmResult, WasmSlice};
+
+    thread_local! {
+        static CALLS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe extern "C" fn counting_host(_ptr: u32, _len: u32) -> u64 {
+        CALLS.with(|calls| calls.set(calls.get() + 1));
+        WasmResult::ok_empty().into_raw()
+    }
+
+    unsafe extern "C" fn other_host(_ptr: u32, _len: u32) -> u64 {
+        CALLS.with(|calls| calls.set(calls.get() + 1));
+        WasmResult::ok_empty().into_raw()
+    }
+
+    unsafe extern "C" fn failing_host(_ptr: u32, _len: u32) -> u64 {
+        CALLS.with(|calls| calls.set(calls.get() + 1));
+        WasmResult::err(WasmSlice::empty()).into_raw()
+    }
+
+    fn calls() -> usize {
+        CALLS.with(|calls| calls.replace(0))
+    }
+
+    #[test]
+    fn test_identical_calls_hit_the_cache() {
+        for _ in 0..3 {
+            cached_host_call_raw(counting_host, b"entry-hash").unwrap();
+        }
+        assert_eq!(calls(), 1);
+
+        // Only the differing input reaches the host
+        cached_host_call_raw(counting_host, b"entry-hash").unwrap();
+        cached_host_call_raw(counting_host, b"other-hash").unwrap();
+        assert_eq!(calls(), 1);
+
+        // Same input to another host function
+        cached_host_call_raw(other_host, b"entry-hash").unwrap();
+        assert_eq!(calls(), 1);
+
+        let stats = host_call_cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (3, 3, 3));
+        assert_eq!(stats.bytes, 3 * b"entry-hash".len());
+
+        // Entries do not survive an arena reset
+        arena_reset();
+        cached_host_call_raw(counting_host, b"entry-hash").unwrap();
+        assert_eq!(calls(), 1);
+        assert_eq!(host_call_cache_stats().entries, 1);
+    }
+
+    #[test]
+    fn test_limits_and_errors() {
+        host_call_cache_set_limits(CacheLimits {
+            max_entries: 1,
+            max_bytes: 8,
+        });
+
+        // Too large for the byte budget
+        cached_host_call_raw(counting_host, b"a long input").unwrap();
+        cached_host_call_raw(counting_host, b"a long input").unwrap();
+        assert_eq!(calls(), 2);
+
+        cached_host_call_raw(counting_host, b"a").unwrap();
+        cached_host_call_raw(counting_host, b"b").unwrap();
+        cached_host_call_raw(counting_host, b"a").unwrap();
+        cached_host_call_raw(counting_host, b"b").unwrap();
+        assert_eq!(calls(), 3);
+
+        assert!(cached_host_call_raw(failing_host, b"c").is_err());
+        assert!(cached_host_call_raw(failing_host, b"c").is_err());
+        assert_eq!(calls(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_cached_host_call_typed() {
+        for _ in 0..3 {
+            cached_host_call::<_, ()>(counting_host, "entry-hash").unwrap();
+        }
+        cached_host_call::<_, ()>(counting_host, "other-hash").unwrap();
+        cached_host_call::<(), ()>(counting_host, ()).unwrap();
+        assert_eq!(calls(), 3);
+    }
+}
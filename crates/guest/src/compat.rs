@@ -6,11 +6,17 @@
 //! - `return_err_ptr` - Return a serialized error
 //! - `host_call` - Call a host function with typed serialization
 
-use crate::arena::arena_alloc_copy;
+use crate::arena::{arena_alloc_copy, ArenaRegion, ARENA};
+use crate::host_call::{call_host_fn, decode_host_error, response_bytes, HostCallScope};
+use crate::memory::{fallback_err, read_bytes};
 use aingle_wasmer_common::{
-    DeserializeError, DoubleUSize, HostCallError, SerializeError, WasmError, WasmResult, WasmSlice,
+    decode_empty, is_unit, DeserializeError, GuestReturn, HostCallError, SerializeError, WasmError,
+    WasmResult, WasmSlice,
 };
-use serde::{de::DeserializeOwned, Serialize};
+#[cfg(feature = "min-size")]
+use aingle_wasmer_common::{encode_fixed_error, ErrorKind, WasmErrorInner};
+use core::any::type_name;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 /// Guest pointer type (memory offset)
 pub type GuestPtr = u32;
@@ -19,6 +25,12 @@ pub type GuestPtr = u32;
 pub type Len = u32;
 
 /// Wrapper for serialized bytes (compatible with ExternIO)
+///
+/// Interchangeable with [`aingle_middleware_bytes::SerializedBytes`]: both
+/// hold MessagePack bytes and serialize as the same binary value, and they
+/// convert into each other without copying. Types listed in
+/// [`compat_serial!`](crate::compat_serial) convert to and from it with
+/// `try_into()`.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, serde::Deserialize)]
 #[serde(transparent)]
 #[repr(transparent)]
@@ -40,10 +52,23 @@ impl SerializedBytes {
         Ok(Self(bytes))
     }
 
+    /// Encode a value with structs as positional arrays
+    ///
+    /// Smaller than [`SerializedBytes::encode`] by the field names, and
+    /// read back by [`SerializedBytes::decode`] alike. Fields skipped with
+    /// `skip_serializing_if` shift the ones after them, so types using it
+    /// must be encoded with names.
+    pub fn encode_compact<T: Serialize + std::fmt::Debug>(value: &T) -> Result<Self, WasmError> {
+        let bytes = rmp_serde::to_vec(value)
+            .map_err(|_| WasmError::Serialize(SerializeError::UnsupportedType))?;
+        Ok(Self(bytes))
+    }
+
     /// Decode from serialized bytes
     ///
     /// Uses aingle_middleware_bytes for consistent deserialization format
-    /// with the host and rest of the system.
+    /// with the host and rest of the system. Structs are read from either
+    /// encoding.
     pub fn decode<T: DeserializeOwned + std::fmt::Debug>(&self) -> Result<T, WasmError> {
         aingle_middleware_bytes::decode(&self.0)
             .map_err(|_| WasmError::Deserialize(DeserializeError::InvalidFormat))
@@ -78,6 +103,74 @@ impl From<SerializedBytes> for Vec<u8> {
     }
 }
 
+impl core::ops::Deref for SerializedBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<aingle_middleware_bytes::SerializedBytes> for SerializedBytes {
+    fn from(sb: aingle_middleware_bytes::SerializedBytes) -> Self {
+        Self(aingle_middleware_bytes::UnsafeBytes::from(sb).into())
+    }
+}
+
+impl From<SerializedBytes> for aingle_middleware_bytes::SerializedBytes {
+    fn from(sb: SerializedBytes) -> Self {
+        aingle_middleware_bytes::UnsafeBytes::from(sb.0).into()
+    }
+}
+
+/// Implement conversions between types and [`SerializedBytes`]
+///
+/// Generates `TryFrom<&T>` and `TryFrom<T>` for [`SerializedBytes`] and
+/// `TryFrom<SerializedBytes>` for `T`, so `try_into()` works both ways like
+/// with `aingle_middleware_bytes`. Blanket impls would conflict with the
+/// standard library's, hence the macro.
+///
+/// ```ignore
+/// #[derive(Debug, Serialize, Deserialize)]
+/// struct Post {
+///     title: String,
+/// }
+/// aingle_wasmer_guest::compat_serial!(Post);
+///
+/// let bytes: SerializedBytes = (&post).try_into()?;
+/// let post: Post = bytes.try_into()?;
+/// ```
+#[macro_export]
+macro_rules! compat_serial {
+    ($($t:ty),* $(,)?) => {$(
+        impl ::core::convert::TryFrom<&$t> for $crate::compat::SerializedBytes {
+            type Error = $crate::WasmError;
+
+            fn try_from(value: &$t) -> ::core::result::Result<Self, $crate::WasmError> {
+                Self::encode(value)
+            }
+        }
+
+        impl ::core::convert::TryFrom<$t> for $crate::compat::SerializedBytes {
+            type Error = $crate::WasmError;
+
+            fn try_from(value: $t) -> ::core::result::Result<Self, $crate::WasmError> {
+                Self::encode(&value)
+            }
+        }
+
+        impl ::core::convert::TryFrom<$crate::compat::SerializedBytes> for $t {
+            type Error = $crate::WasmError;
+
+            fn try_from(
+                bytes: $crate::compat::SerializedBytes,
+            ) -> ::core::result::Result<Self, $crate::WasmError> {
+                bytes.decode()
+            }
+        }
+    )*};
+}
+
 /// Read input arguments from the host
 ///
 /// This function reads bytes from guest memory at the given pointer/length
@@ -90,21 +183,58 @@ impl From<SerializedBytes> for Vec<u8> {
 ///
 /// # Returns
 /// * `Ok(Vec<u8>)` - The raw input bytes
-/// * `Err(DoubleUSize)` - Error pointer if reading fails
-pub fn host_args(guest_ptr: GuestPtr, len: Len) -> Result<Vec<u8>, DoubleUSize> {
-    if len == 0 {
-        return Ok(Vec::new());
-    }
+/// * `Err(GuestReturn)` - Error result if the range is null or outside
+///   guest memory
+pub fn host_args(guest_ptr: GuestPtr, len: Len) -> Result<Vec<u8>, GuestReturn> {
+    host_args_borrowed(guest_ptr, len).map(<[u8]>::to_vec)
+}
 
-    let bytes = unsafe { core::slice::from_raw_parts(guest_ptr as *const u8, len as usize) };
+/// Borrow input arguments from guest memory without copying
+///
+/// The slice points at the region the host wrote the input to, which stays
+/// valid until the arena is reset at the end of the call. Use [`host_args`]
+/// for input that must outlive the call.
+pub fn host_args_borrowed(guest_ptr: GuestPtr, len: Len) -> Result<&'static [u8], GuestReturn> {
+    read_bytes(guest_ptr, len).map_err(return_err_ptr)
+}
 
-    Ok(bytes.to_vec())
+/// Deserialize input arguments straight from guest memory
+///
+/// An empty input decodes only as unit, see
+/// [`decode_empty`](aingle_wasmer_common::decode_empty). Borrowed fields such as `&str` or `#[serde(with = "serde_bytes")] &[u8]`
+/// point into the input region, with the lifetime caveat of
+/// [`host_args_borrowed`].
+pub fn host_args_decode<'a, T>(guest_ptr: GuestPtr, len: Len) -> Result<T, GuestReturn>
+where
+    T: Deserialize<'a> + std::fmt::Debug,
+{
+    decode_borrowed(host_args_borrowed(guest_ptr, len)?)
+}
+
+fn decode_borrowed<'a, T>(bytes: &'a [u8]) -> Result<T, GuestReturn>
+where
+    T: Deserialize<'a> + std::fmt::Debug,
+{
+    decode_input(bytes).map_err(return_err_ptr)
+}
+
+/// Decode an input, with an empty one standing for unit
+pub(crate) fn decode_input<'a, T>(bytes: &'a [u8]) -> Result<T, WasmError>
+where
+    T: Deserialize<'a> + std::fmt::Debug,
+{
+    if bytes.is_empty() {
+        return decode_empty().map_err(WasmError::from);
+    }
+    aingle_middleware_bytes::decode(bytes)
+        .map_err(|_| WasmError::Deserialize(DeserializeError::InvalidFormat))
 }
 
 /// Return a serialized success value to the host
 ///
 /// Serializes the value and copies it to the arena, returning a pointer
-/// that the host can use to read the result.
+/// that the host can use to read the result. Unit values are returned as an
+/// empty result without serializing them.
 ///
 /// # Type Parameters
 /// * `T` - The type to serialize (must implement Serialize)
@@ -113,22 +243,35 @@ pub fn host_args(guest_ptr: GuestPtr, len: Len) -> Result<Vec<u8>, DoubleUSize>
 /// * `value` - The value to return
 ///
 /// # Returns
-/// A DoubleUSize encoding the pointer and length
-pub fn return_ptr<T: Serialize + std::fmt::Debug>(value: T) -> DoubleUSize {
-    match SerializedBytes::encode(&value) {
-        Ok(sb) => {
-            let bytes = sb.0;
-            let len = bytes.len() as u32;
-            let ptr = arena_alloc_copy(&bytes) as u32;
-            WasmResult::ok(WasmSlice::new(ptr, len)).into_raw()
-        }
-        Err(_) => {
-            // Return empty error on serialization failure
-            WasmResult::err(WasmSlice::empty()).into_raw()
-        }
+/// A GuestReturn encoding the pointer and length
+pub fn return_ptr<T: Serialize + std::fmt::Debug>(value: T) -> GuestReturn {
+    if is_unit(&value) {
+        return GuestReturn::empty();
+    }
+    match aingle_middleware_bytes::encode(&value) {
+        Ok(bytes) => match arena_alloc_copy(&bytes, None) {
+            Ok(ptr) => GuestReturn::from_slice(WasmSlice::new(ptr as u32, bytes.len() as u32)),
+            Err(e) => return_err_ptr(WasmError::Memory(e)),
+        },
+        Err(e) => unserializable::<T>(e),
     }
 }
 
+/// Report a value that could not be serialized
+#[cfg(not(feature = "min-size"))]
+fn unserializable<T>(e: impl core::fmt::Display) -> GuestReturn {
+    let error = WasmError::Serialize(SerializeError::UnsupportedType);
+    let message = format!("{} while returning {}: {}", error, type_name::<T>(), e);
+    error_slice(&error, message)
+}
+
+/// Report a value that could not be serialized, naming only its type
+#[cfg(feature = "min-size")]
+fn unserializable<T>(_e: impl core::fmt::Display) -> GuestReturn {
+    let inner = WasmErrorInner::new(ErrorKind::Serialization, type_name::<T>());
+    return_err_ptr(WasmError::GuestStructured(inner))
+}
+
 /// Return a serialized error to the host
 ///
 /// Converts the WasmError to a serializable format and copies it to the arena,
@@ -138,31 +281,83 @@ pub fn return_ptr<T: Serialize + std::fmt::Debug>(value: T) -> DoubleUSize {
 /// * `error` - The WasmError to return
 ///
 /// # Returns
-/// A DoubleUSize encoding the error pointer and length
-pub fn return_err_ptr(error: WasmError) -> DoubleUSize {
+/// A GuestReturn encoding the error pointer and length
+#[cfg(not(feature = "min-size"))]
+pub fn return_err_ptr(error: WasmError) -> GuestReturn {
+    let message = format!("{}", error);
+    error_slice(&error, message)
+}
+
+/// Return an error to the host in the fixed layout
+///
+/// With the `min-size` feature errors are encoded with
+/// [`encode_fixed_error`] instead of being formatted and serialized, keeping
+/// their kind, raw message and the basename of their file.
+#[cfg(feature = "min-size")]
+pub fn return_err_ptr(error: WasmError) -> GuestReturn {
+    let bytes = error_payload(&error);
+    match arena_alloc_copy(&bytes, None) {
+        Ok(ptr) => WasmResult::err(WasmSlice::new(ptr as u32, bytes.len() as u32)).into(),
+        Err(_) => fallback_err(&bytes, false, error.kind()).into(),
+    }
+}
+
+/// Serialize `error` in the fixed layout, for the host's `guest_error_message`
+#[cfg(feature = "min-size")]
+pub(crate) fn error_payload(error: &WasmError) -> Vec<u8> {
+    let location = match error {
+        WasmError::GuestStructured(inner) => inner.file.as_deref().map(basename).zip(inner.line),
+        _ => None,
+    };
+    encode_fixed_error(error.kind(), location, error.message().as_bytes())
+}
+
+/// Last component of a source path
+#[cfg(feature = "min-size")]
+fn basename(file: &str) -> &str {
+    file.rsplit(['/', '\\']).next().unwrap_or(file)
+}
+
+/// Copy a serialized error to the arena and return it as an error slice
+///
+/// Falls back to the plain UTF-8 message if the arena is exhausted, so the
+/// host never receives an empty error.
+#[cfg(not(feature = "min-size"))]
+fn error_slice(error: &WasmError, message: String) -> GuestReturn {
+    let bytes = serialize_error(error, &message);
+    let Ok(ptr) = arena_alloc_copy(&bytes, None) else {
+        return fallback_err(message.as_bytes(), false, error.kind()).into();
+    };
+    WasmResult::err(WasmSlice::new(ptr as u32, bytes.len() as u32)).into()
+}
+
+/// Serialize `error` for the host's `guest_error_message`
+#[cfg(not(feature = "min-size"))]
+pub(crate) fn error_payload(error: &WasmError) -> Vec<u8> {
+    serialize_error(error, &format!("{}", error))
+}
+
+/// Serialize `error` with `message` as a `{ error_type, message }` struct
+///
+/// Falls back to the plain UTF-8 message if the error itself cannot be
+/// serialized.
+#[cfg(not(feature = "min-size"))]
+fn serialize_error(error: &WasmError, message: &str) -> Vec<u8> {
     // Convert WasmError to a serializable error struct
     #[derive(Debug, Serialize)]
-    struct SerializableError {
+    struct SerializableError<'a> {
         error_type: String,
-        message: String,
+        message: &'a str,
     }
 
     let serializable = SerializableError {
-        error_type: format!("{:?}", core::mem::discriminant(&error)),
-        message: format!("{}", error),
+        error_type: format!("{:?}", core::mem::discriminant(error)),
+        message,
     };
 
-    match SerializedBytes::encode(&serializable) {
-        Ok(sb) => {
-            let bytes = sb.0;
-            let len = bytes.len() as u32;
-            let ptr = arena_alloc_copy(&bytes) as u32;
-            WasmResult::err(WasmSlice::new(ptr, len)).into_raw()
-        }
-        Err(_) => {
-            // Return empty error if we can't serialize
-            WasmResult::err(WasmSlice::empty()).into_raw()
-        }
+    match aingle_middleware_bytes::encode(&serializable) {
+        Ok(bytes) => bytes,
+        Err(_) => message.as_bytes().to_vec(),
     }
 }
 
@@ -174,6 +369,13 @@ pub fn return_err_ptr(error: WasmError) -> DoubleUSize {
 /// 3. Calls the host function
 /// 4. Deserializes and returns the result
 ///
+/// Unit inputs are sent as an empty slice, and an empty response decodes
+/// only as unit; other output types fail with
+/// [`EmptyPayload`](aingle_wasmer_common::EmptyPayload) naming the type.
+///
+/// Prefer [`host_call_enveloped`](crate::host_call_enveloped), which adds
+/// checksumming and structured host errors.
+///
 /// # Type Parameters
 /// * `I` - Input type (must implement Serialize)
 /// * `O` - Output type (must implement DeserializeOwned)
@@ -194,35 +396,37 @@ where
     O: DeserializeOwned + std::fmt::Debug,
 {
     // Serialize input using aingle_middleware_bytes for consistency
-    let input_bytes = SerializedBytes::encode(&input)?;
-    let bytes = input_bytes.0;
-    let len = bytes.len() as u32;
-
-    // Copy to arena for host access
-    let ptr = arena_alloc_copy(&bytes) as u32;
+    let bytes = match is_unit(&input) {
+        true => Vec::new(),
+        false => SerializedBytes::encode(&input)?.0,
+    };
+    // Copy to scratch memory for host access, reclaimed once the response
+    // is decoded
+    let _scope = HostCallScope::begin();
+    let ptr = ARENA
+        .with(|arena| arena.try_alloc_copy_in(ArenaRegion::Scratch, &bytes))
+        .map_err(WasmError::Memory)?;
+    let args = unsafe { core::slice::from_raw_parts(ptr, bytes.len()) };
 
     // Call the host
-    let result = unsafe { host_fn(ptr, len) };
-
-    // Parse result
-    let wasm_result = WasmResult::from_raw(result);
-    let slice = wasm_result.slice();
+    let wasm_result = unsafe { call_host_fn(host_fn, args, false) };
+    if !wasm_result.is_well_formed() {
+        return Err(WasmError::HostCall(HostCallError::InvalidReturn));
+    }
+    let response_bytes = unsafe { response_bytes(wasm_result.slice()) };
 
     if wasm_result.is_err() {
-        // Return host call error - we can't deserialize WasmError directly
-        return Err(WasmError::HostCall(HostCallError::HostError(0)));
+        if response_bytes.is_empty() {
+            return Err(WasmError::HostCall(HostCallError::HostError(0)));
+        }
+        return Err(decode_host_error(response_bytes));
     }
 
     // Deserialize success response using aingle_middleware_bytes for consistency
-    if slice.is_empty() {
-        // Try to decode empty/unit type
-        return aingle_middleware_bytes::decode(&[])
-            .map_err(|_| WasmError::Deserialize(DeserializeError::InvalidFormat));
+    if response_bytes.is_empty() {
+        return decode_empty().map_err(WasmError::from);
     }
 
-    let response_bytes =
-        unsafe { core::slice::from_raw_parts(slice.ptr as *const u8, slice.len as usize) };
-
     aingle_middleware_bytes::decode(response_bytes)
         .map_err(|_| WasmError::Deserialize(DeserializeError::InvalidFormat))
 }
@@ -252,9 +456,211 @@ mod tests {
         assert_eq!(original, decoded);
     }
 
+    #[test]
+    fn test_serialized_bytes_compact() {
+        #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+        struct Reading {
+            id: u16,
+            temp: i16,
+            humidity: u8,
+            pressure: u16,
+            battery: u8,
+            rssi: i8,
+            seq: u32,
+            ts: u32,
+            ok: bool,
+            unit: char,
+        }
+
+        let reading = Reading {
+            id: 7,
+            temp: -4,
+            humidity: 61,
+            pressure: 1013,
+            battery: 88,
+            rssi: -70,
+            seq: 1,
+            ts: 1_700_000_000,
+            ok: true,
+            unit: 'C',
+        };
+        let named = SerializedBytes::encode(&reading).unwrap();
+        let compact = SerializedBytes::encode_compact(&reading).unwrap();
+        assert_eq!(compact.len(), 19);
+        assert_eq!(named.len(), 73);
+        assert!(compact.len() * 10 <= named.len() * 6);
+
+        // Either encoding decodes, here and in the middleware
+        assert_eq!(named.decode::<Reading>().unwrap(), reading);
+        assert_eq!(compact.decode::<Reading>().unwrap(), reading);
+        assert_eq!(decode_input::<Reading>(&compact).unwrap(), reading);
+        let middleware: Reading = aingle_middleware_bytes::decode(compact.as_bytes()).unwrap();
+        assert_eq!(middleware, reading);
+    }
+
+    #[test]
+    fn test_serialized_bytes_interop() {
+        #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+        struct TestData {
+            value: u32,
+            name: String,
+        }
+        crate::compat_serial!(TestData);
+
+        let original = TestData {
+            value: 7,
+            name: "interop".to_string(),
+        };
+        let compat = SerializedBytes::try_from(&original).unwrap();
+        assert_eq!(compat, SerializedBytes::encode(&original).unwrap());
+        let middleware = aingle_middleware_bytes::SerializedBytes::from(compat.clone());
+        assert_eq!(middleware.bytes().as_slice(), &compat[..]);
+        assert_eq!(SerializedBytes::from(middleware.clone()), compat);
+
+        // Both wrap the payload the same way when serialized themselves
+        assert_eq!(
+            aingle_middleware_bytes::encode(&compat).unwrap(),
+            aingle_middleware_bytes::encode(&middleware).unwrap()
+        );
+        let decoded: aingle_middleware_bytes::SerializedBytes =
+            aingle_middleware_bytes::decode(&aingle_middleware_bytes::encode(&compat).unwrap())
+                .unwrap();
+        assert_eq!(decoded, middleware);
+        let roundtrip: TestData = SerializedBytes::from(decoded).try_into().unwrap();
+        assert_eq!(roundtrip, original);
+    }
+
+    #[test]
+    fn test_return_ptr_reports_serialization_failure() {
+        #[derive(Debug)]
+        struct Unserializable;
+
+        impl Serialize for Unserializable {
+            fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+                Err(serde::ser::Error::custom(
+                    "field `secret` cannot be serialized",
+                ))
+            }
+        }
+
+        let result = return_ptr(Unserializable).as_result();
+        assert!(result.is_err());
+        assert!(!result.slice().is_empty());
+
+        let result = return_err_ptr(WasmError::guest("boom")).as_result();
+        assert!(result.is_err());
+        assert!(!result.slice().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "min-size")]
+    fn test_min_size_errors_use_fixed_layout() {
+        let error = WasmError::GuestStructured(
+            WasmErrorInner::new(ErrorKind::Validation, "bad input")
+                .with_location("crates/guest/src/lib.rs", 9),
+        );
+        let expected = encode_fixed_error(ErrorKind::Validation, Some(("lib.rs", 9)), b"bad input");
+        let result = return_err_ptr(error).as_result();
+        assert!(result.is_err());
+        assert_eq!(result.slice().len as usize, expected.len());
+
+        assert_eq!(basename("C:\\guest\\src\\lib.rs"), "lib.rs");
+        assert_eq!(basename("lib.rs"), "lib.rs");
+    }
+
     #[test]
     fn test_host_args_empty() {
         let result = host_args(0, 0).unwrap();
         assert!(result.is_empty());
+        assert!(host_args_borrowed(0, 0).unwrap().is_empty());
+        assert!(host_args_decode::<u32>(0, 0).is_err());
+
+        let result = host_args(0, 8).unwrap_err().as_result();
+        assert!(result.is_err());
+        assert!(!result.slice().is_empty());
+    }
+
+    thread_local! {
+        static RECEIVED_LEN: core::cell::Cell<Option<u32>> = const { core::cell::Cell::new(None) };
+    }
+
+    unsafe extern "C" fn empty_host(_ptr: GuestPtr, len: Len) -> u64 {
+        RECEIVED_LEN.with(|received| received.set(Some(len)));
+        WasmResult::ok_empty().into_raw()
+    }
+
+    fn received_len() -> Option<u32> {
+        RECEIVED_LEN.with(|received| received.take())
+    }
+
+    #[test]
+    fn test_host_call_unit() {
+        // unit -> unit
+        assert_eq!(host_call::<(), ()>(empty_host, ()), Ok(()));
+        assert_eq!(received_len(), Some(0));
+
+        // T -> unit
+        assert_eq!(host_call::<u32, ()>(empty_host, 7), Ok(()));
+        assert_eq!(received_len(), Some(1));
+
+        // unit -> T, with nothing to decode
+        let error = host_call::<(), u32>(empty_host, ()).unwrap_err();
+        assert_eq!(received_len(), Some(0));
+        assert_eq!(
+            error.kind(),
+            aingle_wasmer_common::ErrorKind::Deserialization
+        );
+        assert_eq!(
+            error.message(),
+            "UnexpectedEof: expected `u32` but the payload is empty"
+        );
+    }
+
+    #[test]
+    fn test_unit_inputs_and_results() {
+        let result = return_ptr(()).as_result();
+        assert!(result.is_ok());
+        assert!(result.slice().is_empty());
+
+        assert_eq!(decode_input::<()>(&[]), Ok(()));
+        assert!(decode_input::<u32>(&[]).is_err());
+        // Hosts serializing unit as nil still decode
+        assert_eq!(decode_input::<()>(&[0xc0]), Ok(()));
+    }
+
+    #[test]
+    fn test_decode_borrows_from_input() {
+        #[derive(Debug, Serialize, serde::Deserialize)]
+        struct Borrowed<'a> {
+            #[serde(with = "serde_bytes")]
+            data: &'a [u8],
+            name: &'a str,
+        }
+
+        let payload = vec![0x5au8; 4096];
+        let input = aingle_middleware_bytes::encode(&Borrowed {
+            data: &payload,
+            name: "blob",
+        })
+        .unwrap();
+
+        let decoded: Borrowed<'_> = decode_borrowed(&input).unwrap();
+        assert_eq!(decoded.data, &payload[..]);
+        assert_eq!(decoded.name, "blob");
+
+        let range = input.as_ptr_range();
+        assert!(range.contains(&decoded.data.as_ptr()));
+        assert!(range.contains(&decoded.name.as_ptr()));
+    }
+
+    #[test]
+    #[cfg(target_arch = "wasm32")]
+    fn test_host_args_borrowed_points_at_input() {
+        let input = vec![1u8, 2, 3, 4];
+        let ptr = input.as_ptr() as GuestPtr;
+
+        let borrowed = host_args_borrowed(ptr, input.len() as Len).unwrap();
+        assert_eq!(borrowed.as_ptr() as GuestPtr, ptr);
+        assert_eq!(borrowed, &input[..]);
     }
 }
@@ -0,0 +1,330 @@
+//! Runtime support for `#[aingle_entry]`, `map_extern!`,
+//! `register_externs!` and `register_warmup!`
+//!
+//! The macros expand to calls into these functions; they are public only so
+//! the generated code can reach them.
+
+use crate::compat::{decode_input, GuestPtr, Len};
+use crate::memory::{host_args_auto, return_err, return_err_typed, return_ok, return_ok_typed};
+use aingle_wasmer_common::{ExternRecord, GuestReturn, WasmError};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Decode the input, run an entry function and return its result
+///
+/// The input may come enveloped or raw, see
+/// [`host_args_auto`](crate::host_args_auto). An empty input decodes as unit
+/// and a unit result is returned empty, see
+/// [`is_unit`](aingle_wasmer_common::is_unit).
+#[doc(hidden)]
+pub fn __entry<I, O, F>(guest_ptr: GuestPtr, len: Len, f: F) -> GuestReturn
+where
+    I: DeserializeOwned + std::fmt::Debug,
+    O: Serialize + std::fmt::Debug,
+    F: FnOnce(I) -> Result<O, WasmError>,
+{
+    guard(move || {
+        let input = match host_args_auto(guest_ptr, len) {
+            Ok(input) => input,
+            Err(error) => return return_err_typed(&error).into(),
+        };
+        match decode_input(input.payload) {
+            Ok(input) => finish(f(input)),
+            Err(error) => return_err_typed(&error).into(),
+        }
+    })
+}
+
+/// Run an entry function without input and return its result
+#[doc(hidden)]
+pub fn __entry_unit<O, F>(f: F) -> GuestReturn
+where
+    O: Serialize + std::fmt::Debug,
+    F: FnOnce() -> Result<O, WasmError>,
+{
+    guard(move || finish(f()))
+}
+
+fn finish<O: Serialize + std::fmt::Debug>(result: Result<O, WasmError>) -> GuestReturn {
+    let raw = match result {
+        Ok(output) => return_ok_typed(&output),
+        Err(error) => return_err_typed(&error),
+    };
+    raw.into()
+}
+
+/// Export `$inner_fn` as the guest entry point `$extern_name`
+///
+/// Declarative counterpart of `#[aingle_entry]` for guests avoiding
+/// proc-macro dependencies, shaped like holochain's `map_extern!`:
+/// `$inner_fn` takes `$input` and returns `Result<$output, E>` with
+/// `WasmError: From<E>`.
+///
+/// ```ignore
+/// fn greet(name: String) -> Result<String, WasmError> {
+///     Ok(format!("hello {}", name))
+/// }
+///
+/// map_extern!(greet, greet, String, String);
+/// ```
+#[macro_export]
+macro_rules! map_extern {
+    ($extern_name:ident, $inner_fn:ident, $input:ty, $output:ty) => {
+        mod $extern_name {
+            #[no_mangle]
+            pub extern "C" fn $extern_name(
+                guest_ptr: $crate::GuestPtr,
+                len: $crate::Len,
+            ) -> $crate::GuestReturn {
+                $crate::__entry(
+                    guest_ptr,
+                    len,
+                    |input: $input| -> ::core::result::Result<$output, $crate::WasmError> {
+                        super::$inner_fn(input).map_err($crate::WasmError::from)
+                    },
+                )
+            }
+        }
+    };
+}
+
+/// Return an extern table of `(name, schema_id, flags)` entries to the host
+#[doc(hidden)]
+pub fn __list_externs(table: &[(&str, u32, u32)]) -> GuestReturn {
+    let raw = match aingle_middleware_bytes::encode(&extern_records(table)) {
+        Ok(bytes) => return_ok(&bytes),
+        Err(e) => return_err(format!("extern table: {}", e).as_bytes()),
+    };
+    raw.into()
+}
+
+fn extern_records(table: &[(&str, u32, u32)]) -> Vec<ExternRecord> {
+    table
+        .iter()
+        .map(|&(name, schema_id, flags)| ExternRecord {
+            name: name.to_string(),
+            schema_id,
+            flags,
+        })
+        .collect()
+}
+
+/// Export the extern table the host reads with `ModuleIntrospection`
+///
+/// Each entry names an extern, the application-defined id of its input
+/// schema and any [`ExternFlags`](crate::ExternFlags). Invoke it once per
+/// guest, as it defines the `__aingle_list_externs` export.
+///
+/// ```ignore
+/// register_externs! {
+///     greet => (1),
+///     on_event => (2, Callback, ReadOnly),
+/// }
+/// ```
+#[macro_export]
+macro_rules! register_externs {
+    ($($name:ident => ($schema_id:expr $(, $flag:ident)* $(,)?)),* $(,)?) => {
+        #[no_mangle]
+        pub extern "C" fn __aingle_list_externs() -> $crate::GuestReturn {
+            $crate::__list_externs(&[$((
+                stringify!($name),
+                $schema_id,
+                0 $(| $crate::ExternFlags::$flag as u32)*,
+            )),*])
+        }
+    };
+}
+
+/// Export `$warmup_fn` as the warm-up the host runs right after
+/// instantiation
+///
+/// `$warmup_fn` takes nothing and returns `Result<(), E>` with
+/// `WasmError: From<E>`; it is the place to initialize lazy statics, so the
+/// first call does not pay for them. Errors fail the instantiation on the
+/// host. Invoke it once per guest, as it defines the `__aingle_warmup`
+/// export.
+///
+/// ```ignore
+/// static TABLE: OnceLock<Vec<u64>> = OnceLock::new();
+///
+/// fn warm_up() -> Result<(), WasmError> {
+///     TABLE.get_or_init(build_table);
+///     Ok(())
+/// }
+///
+/// register_warmup!(warm_up);
+/// ```
+#[macro_export]
+macro_rules! register_warmup {
+    ($warmup_fn:path) => {
+        #[no_mangle]
+        pub extern "C" fn __aingle_warmup(
+            _guest_ptr: $crate::GuestPtr,
+            _len: $crate::Len,
+        ) -> $crate::GuestReturn {
+            $crate::__entry_unit(|| $warmup_fn().map_err($crate::WasmError::from))
+        }
+    };
+}
+
+#[cfg(feature = "panic-hook")]
+fn guard(f: impl FnOnce() -> GuestReturn) -> GuestReturn {
+    crate::set_panic_hook();
+    crate::catch_panic(std::panic::AssertUnwindSafe(f))
+}
+
+#[cfg(not(feature = "panic-hook"))]
+fn guard(f: impl FnOnce() -> GuestReturn) -> GuestReturn {
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    // Inputs and results live at 32-bit guest addresses, so natively only
+    // unit-input entries and the result lengths can be checked
+    use crate::prelude::*;
+
+    #[cfg(feature = "macros")]
+    #[aingle_entry]
+    fn entry_test_plain() -> u32 {
+        7
+    }
+
+    #[cfg(feature = "macros")]
+    #[aingle_entry]
+    fn entry_test_ok() -> Result<String, WasmError> {
+        Ok("done".to_string())
+    }
+
+    #[cfg(feature = "macros")]
+    #[aingle_entry]
+    fn entry_test_err() -> Result<(), WasmError> {
+        Err(WasmError::guest("refused"))
+    }
+
+    #[cfg(feature = "macros")]
+    #[aingle_entry]
+    fn entry_test_input(input: u32) -> u32 {
+        input + 1
+    }
+
+    #[cfg(feature = "macros")]
+    #[aingle_entry]
+    fn entry_test_unit(_input: ()) {}
+
+    #[cfg(feature = "macros")]
+    #[aingle_entry]
+    fn entry_test_from_unit(_input: ()) -> u32 {
+        7
+    }
+
+    fn mapped_increment(input: u32) -> Result<u32, WasmError> {
+        Ok(input + 1)
+    }
+
+    crate::map_extern!(map_extern_test, mapped_increment, u32, u32);
+
+    crate::guest_fn! {
+        struct Increment => fn guest_fn_test(u32) -> u32;
+        struct Version => fn guest_fn_test_unit(()) -> u32;
+    }
+
+    fn version(_input: ()) -> Result<u32, WasmError> {
+        Ok(7)
+    }
+
+    crate::guest_fn!(impl Increment as guest_fn_test => mapped_increment);
+    crate::guest_fn!(impl Version as guest_fn_test_unit => version);
+
+    crate::register_externs! {
+        entry_test_input => (1, ReadOnly),
+        map_extern_test => (2, Callback, ReadOnly),
+    }
+
+    static WARMED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+    fn warm_up() -> Result<(), WasmError> {
+        WARMED.store(true, std::sync::atomic::Ordering::SeqCst);
+        Ok(())
+    }
+
+    crate::register_warmup!(warm_up);
+
+    /// Length of the result slice; the error bit is unreliable natively, as
+    /// 64-bit arena pointers are truncated into it
+    fn result_len(result: impl Into<GuestReturn>) -> u32 {
+        result.into().as_slice().len
+    }
+
+    fn empty_input_len() -> u32 {
+        let error = decode_empty::<u32>().unwrap_err();
+        result_len(return_err_typed(&error.into()))
+    }
+
+    #[test]
+    #[cfg(feature = "macros")]
+    fn test_generated_entries() {
+        fn encoded_len<T: Serialize + std::fmt::Debug>(value: T) -> u32 {
+            let payload_len = aingle_middleware_bytes::encode(&value).unwrap().len();
+            (EnvelopeHeader::SIZE + payload_len) as u32
+        }
+
+        assert_eq!(result_len(entry_test_plain(0, 0)), encoded_len(7u32));
+        assert_eq!(result_len(entry_test_ok(0, 0)), encoded_len("done"));
+
+        let refused = return_err_typed(&WasmError::guest("refused"));
+        assert_eq!(result_len(entry_test_err(0, 0)), result_len(refused));
+
+        // An empty input stands for unit, and does not decode as a u32
+        assert_eq!(result_len(entry_test_input(0, 0)), empty_input_len());
+        assert_eq!(result_len(entry_test_from_unit(0, 0)), encoded_len(7u32));
+
+        let unit = entry_test_unit(0, 0).as_result();
+        assert!(unit.is_ok());
+        assert!(unit.slice().is_empty());
+    }
+
+    #[test]
+    fn test_register_externs() {
+        let table = super::extern_records(&[("greet", 1, 0), ("on_event", 2, 3)]);
+        assert_eq!(table[0].name, "greet");
+        assert!(!table[0].is_callback());
+        assert!(table[1].is_callback());
+        assert!(table[1].has_flag(ExternFlags::ReadOnly));
+
+        let expected = super::extern_records(&[
+            ("entry_test_input", 1, ExternFlags::ReadOnly as u32),
+            ("map_extern_test", 2, 3),
+        ]);
+        let payload_len = aingle_middleware_bytes::encode(&expected).unwrap().len();
+        assert_eq!(
+            result_len(__aingle_list_externs()) as usize,
+            EnvelopeHeader::SIZE + payload_len
+        );
+    }
+
+    #[test]
+    fn test_register_warmup() {
+        let result = __aingle_warmup(0, 0).as_result();
+        assert!(result.is_ok());
+        assert!(result.slice().is_empty());
+        assert!(WARMED.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_map_extern() {
+        let result = map_extern_test::map_extern_test(0, 0);
+        assert_eq!(result_len(result), empty_input_len());
+    }
+
+    #[test]
+    fn test_guest_fn_exports() {
+        let result = guest_fn_test::guest_fn_test(0, 0);
+        assert_eq!(result_len(result), empty_input_len());
+
+        let payload_len = aingle_middleware_bytes::encode(&7u32).unwrap().len();
+        assert_eq!(
+            result_len(guest_fn_test_unit::guest_fn_test_unit(0, 0)) as usize,
+            EnvelopeHeader::SIZE + payload_len
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Global allocator for call-scoped guest allocations
+//!
+//! With the `arena-global-alloc` feature on wasm32, `Vec`, `String` and
+//! other heap allocations are bump allocated from pages this allocator grows
+//! itself, instead of going through dlmalloc. Sizes are rounded up to a power
+//! of two and freed blocks are kept on per-size free lists for reuse. When
+//! every allocation has been freed, the host's arena reset rewinds the whole
+//! region, so allocations made by an entry function vanish with the call.
+//!
+//! The feature is opt-in and meant for guests whose allocations do not
+//! outlive a call. Memory held across calls stays valid, but it keeps the
+//! region from rewinding: from then on memory is only reused through the
+//! free lists, and the power-of-two rounding can double the footprint of
+//! long-lived data. Guests holding state across calls should keep the
+//! default allocator.
+
+#![cfg_attr(
+    not(all(feature = "arena-global-alloc", target_arch = "wasm32")),
+    allow(dead_code)
+)]
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+
+/// Size of a WASM memory page
+const PAGE: usize = 64 * 1024;
+
+/// Smallest block, large enough to hold a free-list link
+const MIN_BLOCK: usize = core::mem::size_of::<usize>();
+
+/// Bump region with per-size free lists
+struct Region {
+    start: usize,
+    next: usize,
+    end: usize,
+    live: usize,
+    /// Head of the free list for blocks of `1 << class` bytes, 0 if empty
+    free: [usize; usize::BITS as usize],
+}
+
+impl Region {
+    const fn new() -> Self {
+        Self {
+            start: 0,
+            next: 0,
+            end: 0,
+            live: 0,
+            free: [0; usize::BITS as usize],
+        }
+    }
+
+    unsafe fn alloc(&mut self, layout: Layout, grow: fn(usize) -> Option<usize>) -> *mut u8 {
+        let Some(class) = class_of(layout.size()) else {
+            return core::ptr::null_mut();
+        };
+        let align = layout.align();
+
+        let head = self.free[class];
+        if head != 0 && head & (align - 1) == 0 {
+            self.free[class] = (head as *const usize).read_unaligned();
+            self.live += 1;
+            return head as *mut u8;
+        }
+
+        let size = 1usize << class;
+        let Some(ptr) = self.bump(size, align).or_else(|| {
+            self.grow(size + align, grow)?;
+            self.bump(size, align)
+        }) else {
+            return core::ptr::null_mut();
+        };
+        self.live += 1;
+        ptr as *mut u8
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let Some(class) = class_of(layout.size()) else {
+            return;
+        };
+        self.live -= 1;
+
+        let addr = ptr as usize;
+        if addr + (1 << class) == self.next {
+            self.next = addr;
+        } else {
+            (ptr as *mut usize).write_unaligned(self.free[class]);
+            self.free[class] = addr;
+        }
+    }
+
+    fn bump(&mut self, size: usize, align: usize) -> Option<usize> {
+        let ptr = self.next.checked_add(align - 1)? & !(align - 1);
+        let end = ptr.checked_add(size)?;
+        if end > self.end {
+            return None;
+        }
+        self.next = end;
+        Some(ptr)
+    }
+
+    /// Grow by at least `bytes`, restarting the region if the new pages do
+    /// not follow the current ones
+    fn grow(&mut self, bytes: usize, grow: fn(usize) -> Option<usize>) -> Option<()> {
+        let pages = bytes.div_ceil(PAGE);
+        let base = grow(pages)?;
+        if base != self.end {
+            self.start = base;
+            self.next = base;
+        }
+        self.end = base + pages * PAGE;
+        Some(())
+    }
+
+    /// Rewind the region if nothing is allocated
+    fn reset(&mut self) -> bool {
+        if self.live != 0 {
+            return false;
+        }
+        self.next = self.start;
+        self.free = [0; usize::BITS as usize];
+        true
+    }
+}
+
+/// Free-list class of an allocation of `size` bytes
+fn class_of(size: usize) -> Option<usize> {
+    let block = size.max(MIN_BLOCK).checked_next_power_of_two()?;
+    Some(block.trailing_zeros() as usize)
+}
+
+/// Global allocator bump allocating from pages it grows itself
+///
+/// Installed automatically by the `arena-global-alloc` feature on wasm32;
+/// see the [module documentation](self).
+pub struct ArenaGlobalAlloc {
+    region: UnsafeCell<Region>,
+    grow: fn(usize) -> Option<usize>,
+}
+
+// SAFETY: only installed on wasm32 without the atomics feature, where the
+// guest runs on a single thread
+unsafe impl Sync for ArenaGlobalAlloc {}
+
+impl ArenaGlobalAlloc {
+    /// Create an allocator growing WASM memory
+    #[cfg(target_arch = "wasm32")]
+    pub const fn new() -> Self {
+        Self::with_grow(grow_memory)
+    }
+
+    /// Create an allocator obtaining pages from `grow`, which returns the
+    /// address of the new pages or `None` when out of memory
+    #[doc(hidden)]
+    pub const fn with_grow(grow: fn(usize) -> Option<usize>) -> Self {
+        Self {
+            region: UnsafeCell::new(Region::new()),
+            grow,
+        }
+    }
+
+    /// Rewind the region if every allocation has been freed
+    ///
+    /// Called when the host resets the arena; returns whether it rewound.
+    pub fn reset(&self) -> bool {
+        unsafe { (*self.region.get()).reset() }
+    }
+
+    /// Number of allocations not freed yet
+    pub fn live_allocations(&self) -> usize {
+        unsafe { (*self.region.get()).live }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Default for ArenaGlobalAlloc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl GlobalAlloc for ArenaGlobalAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        (*self.region.get()).alloc(layout, self.grow)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        (*self.region.get()).dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // Blocks are rounded to a power of two, so growing within the class
+        // keeps the block
+        if class_of(new_size).is_some() && class_of(new_size) == class_of(layout.size()) {
+            return ptr;
+        }
+
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            core::ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn grow_memory(pages: usize) -> Option<usize> {
+    let previous = core::arch::wasm32::memory_grow(0, pages);
+    (previous != usize::MAX).then(|| previous * PAGE)
+}
+
+#[cfg(all(feature = "arena-global-alloc", target_arch = "wasm32"))]
+#[cfg(target_feature = "atomics")]
+compile_error!("arena-global-alloc is single-threaded and cannot be used with wasm atomics");
+
+/// The installed allocator
+#[cfg(all(feature = "arena-global-alloc", target_arch = "wasm32"))]
+#[global_allocator]
+pub(crate) static GLOBAL: ArenaGlobalAlloc = ArenaGlobalAlloc::new();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pages leaked from the system allocator, never contiguous
+    fn leak_pages(pages: usize) -> Option<usize> {
+        let layout = Layout::from_size_align(pages * PAGE, PAGE).ok()?;
+        let ptr = unsafe { std::alloc::System.alloc(layout) };
+        (!ptr.is_null()).then_some(ptr as usize)
+    }
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    #[test]
+    fn test_freed_blocks_are_reused() {
+        let alloc = ArenaGlobalAlloc::with_grow(leak_pages);
+        unsafe {
+            let a = alloc.alloc(layout(24, 8));
+            let b = alloc.alloc(layout(100, 8));
+            assert_eq!(alloc.live_allocations(), 2);
+
+            // Same class as `a`, handed out again from the free list
+            alloc.dealloc(a, layout(24, 8));
+            assert_eq!(alloc.alloc(layout(20, 4)), a);
+
+            // Freeing the last block rolls the bump pointer back
+            alloc.dealloc(b, layout(100, 8));
+            assert_eq!(alloc.alloc(layout(128, 8)), b);
+        }
+    }
+
+    #[test]
+    fn test_reset_rewinds_only_when_empty() {
+        let alloc = ArenaGlobalAlloc::with_grow(leak_pages);
+        unsafe {
+            let first = alloc.alloc(layout(64, 8));
+            let second = alloc.alloc(layout(64, 8));
+            alloc.dealloc(first, layout(64, 8));
+            assert!(!alloc.reset());
+
+            alloc.dealloc(second, layout(64, 8));
+            assert!(alloc.reset());
+            assert_eq!(alloc.live_allocations(), 0);
+            assert_eq!(alloc.alloc(layout(256, 8)), first);
+        }
+    }
+
+    #[test]
+    fn test_alignment_and_growth() {
+        let alloc = ArenaGlobalAlloc::with_grow(leak_pages);
+        unsafe {
+            let small = alloc.alloc(layout(8, 1));
+            alloc.dealloc(small, layout(8, 1));
+            let _pin = alloc.alloc(layout(1, 1));
+
+            let aligned = alloc.alloc(layout(8, 64));
+            assert_eq!(aligned as usize % 64, 0);
+
+            // Larger than a page, taken from newly grown pages
+            let large = alloc.alloc(layout(3 * PAGE, 8));
+            assert!(!large.is_null());
+            large.write_bytes(0xa5, 3 * PAGE);
+
+            let grown = alloc.realloc(aligned, layout(8, 64), 6);
+            assert_eq!(grown, aligned);
+            let moved = alloc.realloc(aligned, layout(8, 64), 40);
+            assert_ne!(moved, aligned);
+            assert_eq!(moved as usize % 64, 0);
+        }
+    }
+
+    #[test]
+    fn test_out_of_memory_returns_null() {
+        let alloc = ArenaGlobalAlloc::with_grow(|_| None);
+        unsafe {
+            assert!(alloc.alloc(layout(16, 8)).is_null());
+        }
+        assert_eq!(alloc.live_allocations(), 0);
+    }
+}
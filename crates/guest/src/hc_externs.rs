@@ -0,0 +1,45 @@
+//! Declarations of the standard holochain host functions
+//!
+//! The complete set of `__hc__` imports ADK zomes expect, declared from
+//! [`hc_extern_table!`](aingle_wasmer_common::hc_extern_table) so ADK crates
+//! need not list them with [`host_externs!`](crate::host_externs). Only the
+//! functions a guest calls end up among its imports.
+//!
+//! ```ignore
+//! use aingle_wasmer_guest::hc_externs::__hc__agent_info_1;
+//!
+//! let info: AgentInfo = host_call_enveloped(__hc__agent_info_1, ())?;
+//! ```
+
+pub use aingle_wasmer_common::{HC_EXTERNS_VERSION, HC_EXTERN_NAMES};
+
+#[cfg(not(all(feature = "mock-host", not(target_arch = "wasm32"))))]
+macro_rules! declare_hc_externs {
+    ($($name:ident),* $(,)?) => {
+        extern "C" {
+            $(
+                #[doc = concat!("Host function `env.", stringify!($name), "`")]
+                pub fn $name(ptr: u32, len: u32) -> u64;
+            )*
+        }
+    };
+}
+
+/// Native builds with `mock-host` answer from the installed
+/// [`MockHost`](crate::MockHost) instead
+#[cfg(all(feature = "mock-host", not(target_arch = "wasm32")))]
+macro_rules! declare_hc_externs {
+    ($($name:ident),* $(,)?) => {
+        $(
+            #[doc = concat!("Host function `env.", stringify!($name), "`, answered by the installed `MockHost`")]
+            ///
+            /// # Safety
+            /// Only call through the host call functions.
+            pub unsafe extern "C" fn $name(_ptr: u32, _len: u32) -> u64 {
+                crate::mock::__dispatch(stringify!($name))
+            }
+        )*
+    };
+}
+
+aingle_wasmer_common::hc_extern_table!(declare_hc_externs);
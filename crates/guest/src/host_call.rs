@@ -1,11 +1,76 @@
 //! Host function calling utilities
 
-use crate::arena::arena_alloc_copy;
-use aingle_wasmer_codec::{decode_envelope, encode_with_envelope};
-use aingle_wasmer_common::{HostCallError, WasmError, WasmResult};
+use crate::arena::{ArenaRegion, GuestArena, ARENA};
+use crate::cache::HostFn;
+#[cfg(feature = "std")]
+use crate::compat::decode_input;
+use crate::memory::encode_in_arena;
+use aingle_wasmer_codec::decode_envelope;
+#[cfg(feature = "std")]
+use aingle_wasmer_common::{is_unit, DeserializeError, EnvelopeFlags, SerializeError};
+use aingle_wasmer_common::{HostCallError, WasmError, WasmResult, WasmSlice};
+use alloc::string::String;
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Host call in progress, reclaiming the scratch region when dropped
+pub(crate) struct HostCallScope;
+
+impl HostCallScope {
+    pub(crate) fn begin() -> Self {
+        ARENA.with(GuestArena::begin_host_call);
+        Self
+    }
+}
+
+impl Drop for HostCallScope {
+    fn drop(&mut self) {
+        ARENA.with(GuestArena::end_host_call);
+    }
+}
+
+/// Call `host_fn` with `args`, which travel in an envelope if `enveloped`
+///
+/// # Safety
+/// `host_fn` must follow the host call convention, and `args` must be in
+/// memory the host can read.
+pub(crate) unsafe fn call_host_fn(host_fn: HostFn, args: &[u8], enveloped: bool) -> WasmResult {
+    #[cfg(all(feature = "mock-host", not(target_arch = "wasm32")))]
+    crate::mock::stage_call(args, enveloped);
+    #[cfg(not(all(feature = "mock-host", not(target_arch = "wasm32"))))]
+    let _ = enveloped;
+
+    let result = WasmResult::from_raw(host_fn(args.as_ptr() as u32, args.len() as u32));
+    #[cfg(all(feature = "mock-host", not(target_arch = "wasm32")))]
+    crate::mock::finish_call();
+    result
+}
+
+/// Bytes of the response `slice` of the host function just called
+///
+/// # Safety
+/// `slice` must be part of a well-formed result of the last host call, and
+/// the bytes must not be used after its scratch memory is reclaimed.
+pub(crate) unsafe fn response_bytes<'a>(slice: WasmSlice) -> &'a [u8] {
+    #[cfg(all(feature = "mock-host", not(target_arch = "wasm32")))]
+    if let Some(response) = crate::mock::take_response() {
+        return response;
+    }
+    if slice.is_empty() {
+        return &[];
+    }
+    core::slice::from_raw_parts(slice.ptr as *const u8, slice.len as usize)
+}
 
 /// Call a host function with encoded arguments
 ///
+/// The arguments are encoded straight into a scratch allocation of the exact
+/// envelope size, so their size is only bounded by guest memory. Scratch
+/// memory is reclaimed when the call returns, so the response payload is
+/// copied to the output region.
+///
 /// # Arguments
 /// * `host_fn` - The extern host function to call
 /// * `args` - The serialized arguments
@@ -16,53 +81,167 @@ pub fn host_call_raw(
     host_fn: unsafe extern "C" fn(u32, u32) -> u64,
     args: &[u8],
 ) -> Result<&'static [u8], WasmError> {
-    // Encode args with envelope
-    let mut buffer = [0u8; 4096];
-    let len = encode_with_envelope(args, 0, &mut buffer)?;
+    let _scope = HostCallScope::begin();
 
-    // Copy to arena so host can read
-    let ptr = arena_alloc_copy(&buffer[..len]);
+    // Encode args with envelope into the arena so host can read
+    let envelope = encode_in_arena(args, 0, ArenaRegion::Scratch)?;
 
     // Call the host
-    let result = unsafe { host_fn(ptr as u32, len as u32) };
-
-    // Parse result
-    let wasm_result = WasmResult::from_raw(result);
-    let slice = wasm_result.slice();
+    let wasm_result = unsafe { call_host_fn(host_fn, envelope, true) };
+    if !wasm_result.is_well_formed() {
+        return Err(WasmError::HostCall(HostCallError::InvalidReturn));
+    }
 
-    if slice.is_empty() {
+    // Read response from host
+    let response_bytes = unsafe { response_bytes(wasm_result.slice()) };
+    if response_bytes.is_empty() {
         if wasm_result.is_err() {
-            return Err(WasmError::HostCall(HostCallError::HostError(0)));
+            return Err(payloadless_error(wasm_result));
         }
         return Ok(&[]);
     }
 
-    // Read response from host
-    let response_bytes =
-        unsafe { core::slice::from_raw_parts(slice.ptr as *const u8, slice.len as usize) };
-
     // Decode envelope
     let envelope = decode_envelope(response_bytes)?;
 
     if wasm_result.is_err() || envelope.header.is_error() {
-        // Parse error code from payload if available
-        let code = if envelope.payload.len() >= 4 {
-            u32::from_le_bytes([
-                envelope.payload[0],
-                envelope.payload[1],
-                envelope.payload[2],
-                envelope.payload[3],
-            ])
-        } else {
-            0
-        };
-        return Err(WasmError::HostCall(HostCallError::HostError(code)));
-    }
-
-    Ok(envelope.payload)
+        return Err(decode_host_error(envelope.payload));
+    }
+
+    let payload = envelope.payload;
+    let ptr = ARENA
+        .with(|arena| arena.try_alloc_copy_in(ArenaRegion::Output, payload))
+        .map_err(WasmError::Memory)?;
+    Ok(unsafe { core::slice::from_raw_parts(ptr, payload.len()) })
+}
+
+/// Recover the error a host function reported
+///
+/// Hosts send a serialized [`WasmError`]; a bare 4-byte payload is read as a
+/// legacy [`HostCallError::HostError`] code, and anything else is kept as a
+/// [`WasmError::Host`] message. Without `std` serialized errors cannot be
+/// decoded and are kept as messages too.
+pub(crate) fn decode_host_error(payload: &[u8]) -> WasmError {
+    #[cfg(feature = "std")]
+    if let Ok(error) = aingle_middleware_bytes::decode::<_, WasmError>(payload) {
+        return error;
+    }
+    match <[u8; 4]>::try_from(payload) {
+        Ok(code) => WasmError::HostCall(HostCallError::HostError(u32::from_le_bytes(code))),
+        Err(_) => WasmError::Host(String::from_utf8_lossy(payload).into_owned()),
+    }
+}
+
+/// Call a host function with typed arguments over the envelope protocol
+///
+/// This is the recommended way to call the host: the input is serialized and
+/// sent in a checksummed envelope, and the response envelope is validated
+/// before its payload is deserialized. Error responses carrying a serialized
+/// [`WasmError`] are returned as that error. Unit inputs and outputs travel
+/// as empty payloads, see [`is_unit`].
+///
+/// # Arguments
+/// * `host_fn` - The extern host function to call
+/// * `input` - The input value to serialize and send
+///
+/// # Returns
+/// * `Ok(O)` - The deserialized output
+/// * `Err(WasmError)` - If serialization, the call, or deserialization fails
+#[cfg(feature = "std")]
+pub fn host_call_enveloped<I, O>(
+    host_fn: unsafe extern "C" fn(u32, u32) -> u64,
+    input: I,
+) -> Result<O, WasmError>
+where
+    I: Serialize + core::fmt::Debug,
+    O: DeserializeOwned + core::fmt::Debug,
+{
+    let args = encode_host_args(input)?;
+
+    // Arguments and response live in scratch memory until the output is
+    // decoded
+    let _scope = HostCallScope::begin();
+    let envelope = encode_in_arena(&args, 0, ArenaRegion::Scratch)?;
+
+    // Call the host
+    let wasm_result = unsafe { call_host_fn(host_fn, envelope, true) };
+    if !wasm_result.is_well_formed() {
+        return Err(WasmError::HostCall(HostCallError::InvalidReturn));
+    }
+    let response = unsafe { response_bytes(wasm_result.slice()) };
+    enveloped_response(wasm_result, response)
+}
+
+/// Serialize the input of a host call, with unit as an empty payload
+#[cfg(feature = "std")]
+pub(crate) fn encode_host_args<I>(input: I) -> Result<Vec<u8>, WasmError>
+where
+    I: Serialize + core::fmt::Debug,
+{
+    match is_unit(&input) {
+        true => Ok(Vec::new()),
+        false => aingle_middleware_bytes::encode(&input)
+            .map_err(|_| WasmError::Serialize(SerializeError::UnsupportedType)),
+    }
+}
+
+/// Decode the response of a well-formed `wasm_result` to an enveloped call
+#[cfg(feature = "std")]
+pub(crate) fn enveloped_response<O>(
+    wasm_result: WasmResult,
+    response: &[u8],
+) -> Result<O, WasmError>
+where
+    O: DeserializeOwned + core::fmt::Debug,
+{
+    if wasm_result.is_err() && response.is_empty() {
+        return Err(payloadless_error(wasm_result));
+    }
+    decode_host_response(wasm_result.is_err(), response)
+}
+
+/// Error for a host error result without a payload, from the kind it
+/// carries if any
+fn payloadless_error(result: WasmResult) -> WasmError {
+    match result.kind() {
+        Some(kind) => WasmError::without_payload(kind),
+        None => WasmError::HostCall(HostCallError::HostError(0)),
+    }
+}
+
+/// Validate a host response envelope and deserialize its payload
+#[cfg(feature = "std")]
+fn decode_host_response<O>(is_err: bool, response: &[u8]) -> Result<O, WasmError>
+where
+    O: DeserializeOwned + core::fmt::Debug,
+{
+    if response.is_empty() {
+        if is_err {
+            return Err(WasmError::HostCall(HostCallError::HostError(0)));
+        }
+        return decode_input(&[]);
+    }
+
+    let envelope = decode_envelope(response)?;
+
+    // The guest has no codec for compressed or encrypted payloads
+    let flags = envelope.header.flags;
+    if EnvelopeFlags::Compressed.is_set(flags) || EnvelopeFlags::Encrypted.is_set(flags) {
+        return Err(WasmError::Deserialize(DeserializeError::InvalidFormat));
+    }
+
+    if is_err || envelope.header.is_error() {
+        return Err(decode_host_error(envelope.payload));
+    }
+
+    decode_input(envelope.payload)
 }
 
 /// Macro for defining host extern functions
+///
+/// With the `mock-host` feature, native builds define the functions instead,
+/// answering from the installed [`MockHost`](crate::MockHost).
+#[cfg(not(all(feature = "mock-host", not(target_arch = "wasm32"))))]
 #[macro_export]
 macro_rules! host_externs {
     ($($name:ident),* $(,)?) => {
@@ -74,6 +253,26 @@ macro_rules! host_externs {
     };
 }
 
+/// Macro for defining host extern functions
+///
+/// With the `mock-host` feature, native builds define the functions instead,
+/// answering from the installed [`MockHost`](crate::MockHost).
+#[cfg(all(feature = "mock-host", not(target_arch = "wasm32")))]
+#[macro_export]
+macro_rules! host_externs {
+    ($($name:ident),* $(,)?) => {
+        $(
+            /// Answered by the installed `MockHost`
+            ///
+            /// # Safety
+            /// Only call through the host call functions.
+            pub unsafe extern "C" fn $name(_ptr: u32, _len: u32) -> u64 {
+                $crate::mock::__dispatch(stringify!($name))
+            }
+        )*
+    };
+}
+
 /// Macro for calling a host function with automatic serialization
 #[macro_export]
 macro_rules! call_host {
@@ -84,7 +283,171 @@ macro_rules! call_host {
     }};
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
-    // Host call tests require actual WASM environment
+    // Host calls returning data require an actual WASM environment, as the
+    // host hands back 32-bit guest pointers
+    use super::*;
+    use aingle_wasmer_codec::encode_with_envelope;
+    use aingle_wasmer_common::{EnvelopeHeader, ErrorKind, WasmErrorInner, WasmSlice};
+    use core::cell::Cell;
+
+    thread_local! {
+        static RECEIVED_LEN: Cell<u32> = const { Cell::new(0) };
+    }
+
+    unsafe extern "C" fn accept_host(_ptr: u32, len: u32) -> u64 {
+        RECEIVED_LEN.with(|received| received.set(len));
+        WasmResult::ok_empty().into_raw()
+    }
+
+    unsafe extern "C" fn reject_host(_ptr: u32, _len: u32) -> u64 {
+        WasmResult::err(WasmSlice::empty()).into_raw()
+    }
+
+    unsafe extern "C" fn deny_host(_ptr: u32, _len: u32) -> u64 {
+        WasmResult::err_with_kind(WasmSlice::empty(), ErrorKind::PermissionDenied).into_raw()
+    }
+
+    /// Wrap `payload` in an envelope the way an echoing host would
+    fn echo(payload: &[u8], flags: u8) -> Vec<u8> {
+        let mut buffer = vec![0u8; EnvelopeHeader::SIZE + payload.len()];
+        let len = encode_with_envelope(payload, flags, &mut buffer).unwrap();
+        buffer.truncate(len);
+        buffer
+    }
+
+    #[test]
+    fn test_host_call_enveloped_responses() {
+        let input = aingle_middleware_bytes::encode(&(7u32, "seven".to_string())).unwrap();
+        let decoded: (u32, String) = decode_host_response(false, &echo(&input, 0)).unwrap();
+        assert_eq!(decoded, (7, "seven".to_string()));
+
+        let error = WasmError::guest("not allowed");
+        let payload = aingle_middleware_bytes::encode(&error).unwrap();
+        let response = echo(&payload, EnvelopeFlags::IsError as u8);
+        assert_eq!(decode_host_response::<()>(false, &response), Err(error));
+
+        // Corrupted checksum
+        let mut response = echo(&input, 0);
+        response[EnvelopeHeader::SIZE] ^= 0xff;
+        assert!(decode_host_response::<(u32, String)>(false, &response).is_err());
+
+        // Unit results arrive as an empty payload
+        decode_host_response::<()>(false, &echo(&[], 0)).unwrap();
+        decode_host_response::<()>(false, &[]).unwrap();
+        assert!(decode_host_response::<u32>(false, &echo(&[], 0)).is_err());
+
+        let response = echo(&input, EnvelopeFlags::Compressed as u8);
+        assert_eq!(
+            decode_host_response::<(u32, String)>(false, &response),
+            Err(WasmError::Deserialize(DeserializeError::InvalidFormat))
+        );
+    }
+
+    #[test]
+    fn test_host_errors_arrive_intact() {
+        // What a host reports when denying a call
+        let denied = WasmError::GuestStructured(
+            WasmErrorInner::new(ErrorKind::PermissionDenied, "capability not granted")
+                .with_location("host/src/zome.rs", 42),
+        );
+        let payload = aingle_middleware_bytes::encode(&denied).unwrap();
+        assert_eq!(decode_host_error(&payload), denied);
+
+        let response = echo(&payload, EnvelopeFlags::IsError as u8);
+        assert_eq!(decode_host_response::<()>(true, &response), Err(denied));
+
+        assert_eq!(
+            decode_host_error(&7u32.to_le_bytes()),
+            WasmError::HostCall(HostCallError::HostError(7))
+        );
+        assert_eq!(
+            decode_host_error(b"database unavailable"),
+            WasmError::Host("database unavailable".to_string())
+        );
+    }
+
+    #[test]
+    fn test_host_call_enveloped_empty_error() {
+        assert_eq!(
+            host_call_enveloped::<_, ()>(reject_host, 1u8),
+            Err(WasmError::HostCall(HostCallError::HostError(0)))
+        );
+
+        let error = host_call_enveloped::<_, ()>(deny_host, 1u8).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+        let error = host_call_raw(deny_host, b"args").unwrap_err();
+        assert_eq!(
+            error,
+            WasmError::without_payload(ErrorKind::PermissionDenied)
+        );
+    }
+
+    unsafe extern "C" fn empty_payload_host(_ptr: u32, _len: u32) -> u64 {
+        WasmResult::ok(WasmSlice::new(64, 0)).into_raw()
+    }
+
+    unsafe extern "C" fn null_data_host(_ptr: u32, _len: u32) -> u64 {
+        WasmResult::ok(WasmSlice::new(0, 8)).into_raw()
+    }
+
+    #[test]
+    fn test_host_call_rejects_malformed_results() {
+        let invalid = WasmError::HostCall(HostCallError::InvalidReturn);
+        for host_fn in [empty_payload_host, null_data_host] {
+            assert_eq!(host_call_raw(host_fn, b"args"), Err(invalid.clone()));
+            assert_eq!(
+                host_call_enveloped::<_, ()>(host_fn, 1u8),
+                Err(invalid.clone())
+            );
+        }
+
+        // Empty results stay unit
+        assert_eq!(host_call_raw(accept_host, b"args"), Ok(&[][..]));
+        host_call_enveloped::<_, ()>(accept_host, 1u8).unwrap();
+    }
+
+    unsafe extern "C" fn respond_host(_ptr: u32, len: u32) -> u64 {
+        // Allocate a response the way the host does, from within the call
+        crate::__aingle_guest_allocate(len);
+        WasmResult::ok_empty().into_raw()
+    }
+
+    #[test]
+    fn test_host_call_chain_stays_bounded() {
+        use crate::arena::{arena_region_stats, RegionStats};
+
+        let args = vec![0x5au8; 16 * 1024];
+        let per_call = 2 * (EnvelopeHeader::SIZE + args.len());
+        let output = arena_region_stats(ArenaRegion::Output);
+
+        for _ in 0..100 {
+            host_call_raw(respond_host, &args).unwrap();
+        }
+
+        // Arguments and responses never reach the output region, and scratch
+        // memory is reclaimed after every call
+        assert_eq!(arena_region_stats(ArenaRegion::Output), output);
+        let RegionStats {
+            allocated_bytes,
+            high_water_mark,
+        } = arena_region_stats(ArenaRegion::Scratch);
+        assert!(high_water_mark >= per_call);
+        assert!(high_water_mark < 4 * per_call);
+        assert!(allocated_bytes < 4 * per_call);
+        assert!(ARENA.with(GuestArena::high_water_mark) < 4 * per_call + output.high_water_mark);
+    }
+
+    #[test]
+    fn test_host_call_large_args() {
+        let args = vec![0x5au8; 1024 * 1024];
+
+        let response = host_call_raw(accept_host, &args).unwrap();
+        assert!(response.is_empty());
+        assert_eq!(
+            RECEIVED_LEN.with(Cell::get) as usize,
+            EnvelopeHeader::SIZE + args.len()
+        );
+    }
 }
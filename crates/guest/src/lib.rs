@@ -7,6 +7,13 @@
 //! - Ergonomic macros for defining entry points
 //! - Automatic serialization/deserialization
 //! - Zero-copy data passing where possible
+//! - `no_std` support: with `default-features = false` and the `alloc`
+//!   feature the arena, memory, envelope and streaming paths build on
+//!   `core` and `alloc` alone
+//! - Optional getrandom backends drawing randomness from the host
+//!   (`getrandom-shim`)
+//! - A scripted mock host for unit testing guest logic natively
+//!   (`mock-host`)
 //!
 //! ## Example
 //!
@@ -20,27 +27,97 @@
 //! }
 //! ```
 
+#![cfg_attr(not(feature = "std"), no_std)]
 #![warn(missing_docs)]
 
+// Lets code generated by `#[aingle_entry]` name this crate from within it
+extern crate self as aingle_wasmer_guest;
+
+extern crate alloc;
+
+// Native builds without `std` still link it for its panic runtime, allocator
+// and thread locals; on wasm32 the guest binary provides the first two
+#[cfg(all(not(feature = "std"), not(target_arch = "wasm32")))]
+extern crate std;
+
+#[cfg(not(any(feature = "std", feature = "alloc")))]
+compile_error!("aingle_wasmer_guest needs either the `std` or the `alloc` feature");
+
+#[cfg(not(feature = "std"))]
+#[macro_use]
+mod local;
+
 mod arena;
-mod compat;
+mod cache;
+#[cfg(feature = "std")]
+pub mod compat;
+#[cfg(feature = "std")]
+mod entry;
+#[cfg(any(test, feature = "arena-global-alloc"))]
+mod global_alloc;
+#[cfg(feature = "hc-externs")]
+pub mod hc_externs;
 mod host_call;
 mod memory;
+#[cfg(all(feature = "mock-host", not(target_arch = "wasm32")))]
+pub mod mock;
+#[cfg(feature = "std")]
+mod panic;
+#[cfg(feature = "getrandom-shim")]
+mod random;
+mod stream;
+mod trace;
 
 pub mod prelude;
 
+#[cfg(feature = "macros")]
+pub use aingle_wasmer_macros::{aingle_entry, WasmSafe};
 pub use arena::*;
+pub use cache::*;
+#[cfg(feature = "std")]
+pub use entry::{__entry, __entry_unit, __list_externs};
+#[cfg(feature = "arena-global-alloc")]
+pub use global_alloc::ArenaGlobalAlloc;
 pub use host_call::*;
-pub use memory::{host_args_envelope, read_bytes, return_err, return_ok};
-// Export compat functions but NOT SerializedBytes (conflicts with aingle_zome_types)
-pub use compat::{host_args, host_call, return_err_ptr, return_ptr, GuestPtr, Len};
+#[cfg(all(not(feature = "std"), target_arch = "wasm32"))]
+pub use local::Local;
+pub use memory::{
+    __debug_text, checksum_kind, host_args_auto, host_args_envelope, host_args_pair, read_bytes,
+    reserve_scratch, return_err, return_ok, set_checksum_kind, HostInput,
+};
+#[cfg(feature = "std")]
+pub use memory::{return_err_typed, return_ok_typed};
+#[cfg(all(feature = "mock-host", not(target_arch = "wasm32")))]
+pub use mock::{host_call_with, MockHost, MockHostGuard};
+#[cfg(feature = "std")]
+pub use panic::{catch_panic, set_panic_hook, take_last_panic};
+#[cfg(feature = "getrandom-shim")]
+pub use random::host_random_bytes;
+pub use stream::{
+    __aingle_stream_begin, __aingle_stream_write, stream_begin, stream_write, OutputStream,
+    StreamReader, DEFAULT_OUTPUT_CHUNK_LEN,
+};
+pub use trace::trace_step;
+// Export compat functions but NOT SerializedBytes (conflicts with aingle_zome_types),
+// which stays under `compat`
+#[cfg(feature = "std")]
+pub use compat::{
+    host_args, host_args_borrowed, host_args_decode, host_call, return_err_ptr, return_ptr,
+    GuestPtr, Len,
+};
 
+#[allow(deprecated)]
+pub use aingle_wasmer_common::DoubleUSize;
 pub use aingle_wasmer_common::{
-    DeserializeError, DoubleUSize, GuestCallError, HostCallError, SerializeError, WasmDecode,
-    WasmEncode, WasmError, WasmErrorInner, WasmPrimitive, WasmResult, WasmSlice,
+    decode_empty, guest_fn, is_unit, static_assert_wasm_safe, ChecksumKind, DeserializeError,
+    EmptyPayload, ErrorKind, ErrorMessage, ExternFlags, ExternRecord, GuestCallError,
+    GuestFunction, GuestReturn, HostCallError, SerializeError, WasmDecode, WasmEncode, WasmError,
+    WasmErrorInner, WasmPrimitive, WasmResult, WasmSafe, WasmSlice,
 };
 
-pub use aingle_wasmer_codec::{decode_envelope, encode_with_envelope};
+pub use aingle_wasmer_codec::{
+    decode_envelope, decode_envelope_with, encode_envelope_with, encode_with_envelope,
+};
 
 // Re-export serde for convenience
 pub use serde;
@@ -55,15 +132,28 @@ extern "C" {
 }
 
 /// Allocate memory for use by the host (new naming)
+///
+/// Returns 0 if the length exceeds the arena's maximum host allocation or
+/// the arena is exhausted.
 #[no_mangle]
 pub extern "C" fn __aingle_guest_allocate(len: u32) -> u32 {
-    ARENA.with(|arena| arena.alloc(len as usize) as u32)
+    allocate_for_host(len as usize).map_or(0, |ptr| ptr as u32)
 }
 
 /// Allocate memory for use by the host (holochain-compatible naming)
+///
+/// Returns 0 for negative lengths and otherwise fails like
+/// [`__aingle_guest_allocate`].
 #[no_mangle]
 pub extern "C" fn __hc__allocate_1(len: i32) -> i32 {
-    ARENA.with(|arena| arena.alloc(len as usize) as i32)
+    usize::try_from(len)
+        .ok()
+        .and_then(allocate_for_host)
+        .map_or(0, |ptr| ptr as i32)
+}
+
+fn allocate_for_host(len: usize) -> Option<*mut u8> {
+    ARENA.with(|arena| arena.alloc_for_host(len).ok())
 }
 
 /// Deallocate memory (no-op with arena, cleared on call end)
@@ -81,10 +171,136 @@ pub extern "C" fn __hc__deallocate_1(_ptr: i32, _len: i32) {
 }
 
 /// Reset the arena (called by host at end of each call)
+///
+/// Returns the high-water mark, which is kept across the reset.
+#[no_mangle]
+pub extern "C" fn __aingle_guest_reset_arena() -> u32 {
+    let high_water = ARENA.with(|arena| {
+        arena.reset();
+        arena.high_water_mark() as u32
+    });
+    #[cfg(all(feature = "arena-global-alloc", target_arch = "wasm32"))]
+    global_alloc::GLOBAL.reset();
+    high_water
+}
+
+/// Set the arena aside before the host calls back into the guest from a
+/// host call, returning the number of frames pushed
+///
+/// See [`GuestArena::push_frame`].
+#[no_mangle]
+pub extern "C" fn __aingle_arena_push() -> i32 {
+    ARENA.with(|arena| arena.push_frame() as i32)
+}
+
+/// Drop the arena of a nested call once the host has read its result,
+/// returning the number of frames left, or -1 if none was pushed
+#[no_mangle]
+pub extern "C" fn __aingle_arena_pop() -> i32 {
+    ARENA.with(|arena| arena.pop_frame().map_or(-1, |depth| depth as i32))
+}
+
+/// Limit the bytes held by the arena, with 0 lifting the limit
+///
+/// Called by the host after instantiation on memory-constrained devices.
+#[no_mangle]
+pub extern "C" fn __aingle_guest_set_arena_limit(bytes: u32) {
+    arena_set_limit((bytes != 0).then_some(bytes as usize));
+}
+
+/// Make room for `bytes` in the arena, so the first call does not grow
+/// memory
+///
+/// Called by the host after instantiation; fails silently past the arena
+/// limit.
 #[no_mangle]
-pub extern "C" fn __aingle_guest_reset_arena() {
-    ARENA.with(|arena| arena.reset());
+pub extern "C" fn __aingle_guest_prealloc(bytes: u32) {
+    let _ = arena_reserve(bytes as usize);
+}
+
+/// Reserve a buffer of `bytes` the host writes to instead of allocating,
+/// returning its address or 0
+///
+/// Called by the host after instantiation, next to
+/// `__aingle_guest_prealloc`; see [`reserve_scratch`].
+#[no_mangle]
+pub extern "C" fn __aingle_guest_scratch(bytes: u32) -> u32 {
+    reserve_scratch(bytes as usize).map_or(0, |ptr| ptr as u32)
+}
+
+/// Bytes currently held by the arena (read by the host after each call)
+#[no_mangle]
+pub extern "C" fn __aingle_guest_allocated_bytes() -> u32 {
+    ARENA.with(|arena| arena.allocated_bytes() as u32)
+}
+
+/// Arena high-water mark (read by the host after each call)
+#[no_mangle]
+pub extern "C" fn __aingle_guest_high_water() -> u32 {
+    ARENA.with(|arena| arena.high_water_mark() as u32)
+}
+
+/// Error result published by a panic that aborted the call, or 0
+///
+/// Read by the host after an unreachable trap; reading clears it.
+#[cfg(feature = "std")]
+#[no_mangle]
+pub extern "C" fn __aingle_guest_last_panic() -> u64 {
+    panic::take_panic_result()
+}
+
+/// Select the checksum of the envelopes this guest sends, ignoring unknown
+/// values
+///
+/// Called by the host after instantiation; see
+/// [`ChecksumKind`].
+#[no_mangle]
+pub extern "C" fn __aingle_guest_set_checksum(kind: u32) {
+    if let Some(kind) = ChecksumKind::from_u32(kind) {
+        set_checksum_kind(kind);
+    }
+}
+
+/// Protocol version, crate version and capabilities of this guest
+///
+/// Read by the host to adapt its framing; see [`GuestAbi`](aingle_wasmer_common::GuestAbi).
+#[no_mangle]
+pub extern "C" fn __aingle_guest_abi() -> u64 {
+    GUEST_ABI.into_raw()
+}
+
+const GUEST_ABI: aingle_wasmer_common::GuestAbi = {
+    use aingle_wasmer_common::{GuestAbi, GuestCapability};
+    GuestAbi::current(
+        parse_version(env!("CARGO_PKG_VERSION_MAJOR")),
+        parse_version(env!("CARGO_PKG_VERSION_MINOR")),
+        GuestCapability::EnvelopeIo as u32
+            | GuestCapability::ArenaReset as u32
+            | if cfg!(feature = "std") {
+                GuestCapability::PanicHook as u32 | GuestCapability::CompactEncoding as u32
+            } else {
+                0
+            }
+            | GuestCapability::Streaming as u32
+            | GuestCapability::Crc32c as u32,
+    )
+};
+
+/// Parse a crate version component, saturating at `u8::MAX`
+const fn parse_version(component: &str) -> u8 {
+    let bytes = component.as_bytes();
+    let mut value: u32 = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        value = value * 10 + (bytes[i] - b'0') as u32;
+        if value > u8::MAX as u32 {
+            return u8::MAX;
+        }
+        i += 1;
+    }
+    value as u8
 }
 
 // Re-export middleware_bytes types for aingle compatibility
+#[cfg(feature = "std")]
 pub use aingle_middleware_bytes;
@@ -0,0 +1,71 @@
+//! Per-instance statics without `std`
+//!
+//! `thread_local!` is not available without `std`. WASM guests run on a single
+//! thread, so on wasm32 this module provides a `thread_local!` of its own
+//! declaring plain statics with the same `with` interface. Native builds link
+//! `std` anyway and keep real thread locals, as tests run on several threads.
+
+#[cfg(target_arch = "wasm32")]
+use core::cell::{OnceCell, RefCell};
+
+/// Lazily initialized static standing in for `std::thread::LocalKey`
+#[cfg(target_arch = "wasm32")]
+pub struct Local<T> {
+    init: fn() -> T,
+    value: OnceCell<T>,
+}
+
+// SAFETY: wasm32 guests without `std` run on a single thread
+#[cfg(target_arch = "wasm32")]
+unsafe impl<T> Sync for Local<T> {}
+
+#[cfg(target_arch = "wasm32")]
+impl<T> Local<T> {
+    #[doc(hidden)]
+    pub const fn new(init: fn() -> T) -> Self {
+        Self {
+            init,
+            value: OnceCell::new(),
+        }
+    }
+
+    /// Run `f` with a reference to the value, initializing it on first use
+    pub fn with<R>(&'static self, f: impl FnOnce(&T) -> R) -> R {
+        f(self.value.get_or_init(self.init))
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl<T> Local<RefCell<T>> {
+    /// Run `f` with a shared borrow of the value
+    pub fn with_borrow<R>(&'static self, f: impl FnOnce(&T) -> R) -> R {
+        self.with(|cell| f(&cell.borrow()))
+    }
+
+    /// Run `f` with a mutable borrow of the value
+    pub fn with_borrow_mut<R>(&'static self, f: impl FnOnce(&mut T) -> R) -> R {
+        self.with(|cell| f(&mut cell.borrow_mut()))
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "atomics"))]
+compile_error!("aingle_wasmer_guest needs the `std` feature with wasm atomics");
+
+/// Declare [`Local`] statics with the syntax of `std::thread_local!`
+#[cfg(target_arch = "wasm32")]
+macro_rules! thread_local {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $(const)? $init:expr;)*) => {
+        $(
+            $(#[$attr])*
+            $vis static $name: $crate::local::Local<$ty> = $crate::local::Local::new(|| $init);
+        )*
+    };
+}
+
+/// Forward to `std::thread_local!`, linked on native targets
+#[cfg(not(target_arch = "wasm32"))]
+macro_rules! thread_local {
+    ($($tokens:tt)*) => {
+        std::thread_local! { $($tokens)* }
+    };
+}
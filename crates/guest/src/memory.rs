@@ -1,76 +1,454 @@
 //! Memory management utilities for WASM guests
 
-use crate::arena::arena_alloc_copy;
-use aingle_wasmer_codec::{decode_envelope, encode_with_envelope};
-use aingle_wasmer_common::{WasmError, WasmResult, WasmSlice};
+use crate::arena::{ArenaRegion, ARENA};
+use aingle_wasmer_codec::{decode_envelope, encode_envelope_with, DecodedEnvelope};
+#[cfg(all(not(feature = "std"), not(feature = "min-size")))]
+use aingle_wasmer_common::ErrorMessage;
+#[cfg(feature = "std")]
+use aingle_wasmer_common::{is_unit, WasmErrorInner};
+use aingle_wasmer_common::{
+    ChecksumKind, EnvelopeFlags, EnvelopeHeader, ErrorKind, MemoryError, WasmError, WasmResult,
+    WasmSlice,
+};
+#[cfg(feature = "min-size")]
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+#[cfg(feature = "std")]
+use serde::Serialize;
+
+/// Size of the buffer errors are written to when the arena is exhausted
+const FALLBACK_ERROR_LEN: usize = 128;
+
+thread_local! {
+    static FALLBACK_ERROR: RefCell<[u8; FALLBACK_ERROR_LEN]> =
+        const { RefCell::new([0; FALLBACK_ERROR_LEN]) };
+
+    /// Checksum of the envelopes this guest sends, selected by the host
+    static CHECKSUM: Cell<ChecksumKind> = const { Cell::new(ChecksumKind::Crc32) };
+
+    /// Buffer the host writes to instead of allocating, see
+    /// [`reserve_scratch`]
+    static SCRATCH: RefCell<alloc::vec::Vec<u8>> = const { RefCell::new(alloc::vec::Vec::new()) };
+}
+
+/// Checksum algorithm of the envelopes this guest sends
+pub fn checksum_kind() -> ChecksumKind {
+    CHECKSUM.with(Cell::get)
+}
+
+/// Checksum the envelopes this guest sends with `kind`
+///
+/// The host selects CRC32C through `__aingle_guest_set_checksum` once the
+/// guest advertised support for it. Envelopes received are accepted with
+/// either algorithm, see [`decode_envelope`].
+pub fn set_checksum_kind(kind: ChecksumKind) {
+    CHECKSUM.with(|checksum| checksum.set(kind));
+}
+
+/// Reserve a buffer of at least `bytes` outside the arena, returning its
+/// address
+///
+/// The host asks for it through `__aingle_guest_scratch` after
+/// instantiation and writes data that fits to it in place of allocating, so
+/// the buffer is kept across arena resets. Asking for more than the buffer
+/// holds replaces it. Fails for 0 bytes and when the memory cannot grow.
+pub fn reserve_scratch(bytes: usize) -> Option<*mut u8> {
+    if bytes == 0 {
+        return None;
+    }
+    SCRATCH.with(|scratch| {
+        let mut scratch = scratch.borrow_mut();
+        if scratch.len() < bytes {
+            let mut buffer = alloc::vec::Vec::new();
+            buffer.try_reserve_exact(bytes).ok()?;
+            buffer.resize(bytes, 0);
+            *scratch = buffer;
+        }
+        Some(scratch.as_mut_ptr())
+    })
+}
 
 /// Read input arguments from the host (raw envelope version)
 ///
 /// Decodes the envelope and returns the payload bytes.
 /// This is the internal version that uses our envelope protocol.
 /// For aingle compatibility, use the `host_args` function from `compat` module.
+///
+/// `len` must cover exactly one envelope within guest memory, otherwise
+/// [`MemoryError::OutOfBounds`] is returned.
 pub fn host_args_envelope(ptr: u32, len: u32) -> Result<&'static [u8], WasmError> {
     if len == 0 {
         return Ok(&[]);
     }
 
-    let bytes = unsafe { core::slice::from_raw_parts(ptr as *const u8, len as usize) };
-
-    let envelope = decode_envelope(bytes)?;
+    let envelope = decode_input_envelope(read_bytes(ptr, len)?)?;
 
     // Return a reference to the payload (zero-copy)
     Ok(envelope.payload)
 }
 
+/// Decode an input envelope, rejecting lengths that do not match its header
+fn decode_input_envelope(bytes: &[u8]) -> Result<DecodedEnvelope<'_>, WasmError> {
+    let envelope = decode_envelope(bytes)?;
+    let expected = EnvelopeHeader::SIZE + envelope.payload.len();
+    if bytes.len() != expected {
+        return Err(WasmError::Memory(MemoryError::OutOfBounds {
+            offset: 0,
+            len: bytes.len(),
+            max: expected,
+        }));
+    }
+    Ok(envelope)
+}
+
+/// Read both inputs of a two-slice call from the host
+///
+/// Counterpart of the host's `WasmInstance::call_raw2` for exports taking
+/// `(ptr1, len1, ptr2, len2)`. Each input is a separate envelope.
+pub fn host_args_pair(
+    ptr1: u32,
+    len1: u32,
+    ptr2: u32,
+    len2: u32,
+) -> Result<(&'static [u8], &'static [u8]), WasmError> {
+    Ok((
+        host_args_envelope(ptr1, len1)?,
+        host_args_envelope(ptr2, len2)?,
+    ))
+}
+
+/// Input read by [`host_args_auto`]
+#[derive(Clone, Copy)]
+pub struct HostInput<'a> {
+    /// The input with any envelope stripped
+    pub payload: &'a [u8],
+    /// Header of the envelope the input came in, or `None` for raw input
+    pub header: Option<EnvelopeHeader>,
+}
+
+impl HostInput<'_> {
+    /// Whether the input came in an envelope
+    pub fn is_enveloped(&self) -> bool {
+        self.header.is_some()
+    }
+}
+
+/// Read input arguments from a host of either protocol generation
+///
+/// Hosts predating the envelope protocol send raw MessagePack. Input
+/// starting with [`MAGIC`](aingle_wasmer_common::MAGIC) is only taken as an
+/// envelope if the whole header validates, including version, checksum and
+/// a length matching `len`; anything else is returned as raw bytes, so raw
+/// payloads that happen to start with the magic bytes pass through intact.
+pub fn host_args_auto(ptr: u32, len: u32) -> Result<HostInput<'static>, WasmError> {
+    Ok(detect_framing(read_bytes(ptr, len)?))
+}
+
+/// Strip the envelope from `bytes` if they hold exactly one valid envelope
+fn detect_framing(bytes: &[u8]) -> HostInput<'_> {
+    let magic = aingle_wasmer_common::MAGIC.to_le_bytes();
+    if bytes.starts_with(&magic) {
+        if let Ok(envelope) = decode_input_envelope(bytes) {
+            return HostInput {
+                payload: envelope.payload,
+                header: Some(envelope.header),
+            };
+        }
+    }
+    HostInput {
+        payload: bytes,
+        header: None,
+    }
+}
+
 /// Read raw bytes from guest memory
-pub fn read_bytes(ptr: u32, len: u32) -> &'static [u8] {
+///
+/// Fails with [`MemoryError::OutOfBounds`] if the range is null or does not
+/// lie within linear memory.
+pub fn read_bytes(ptr: u32, len: u32) -> Result<&'static [u8], WasmError> {
     if len == 0 {
-        return &[];
+        return Ok(&[]);
+    }
+    check_guest_range(ptr, len).map_err(WasmError::Memory)?;
+    Ok(unsafe { core::slice::from_raw_parts(ptr as *const u8, len as usize) })
+}
+
+/// Check that `len` bytes at `ptr` are non-null and lie within linear memory
+pub(crate) fn check_guest_range(ptr: u32, len: u32) -> Result<(), MemoryError> {
+    if len == 0 {
+        return Ok(());
+    }
+
+    let max = memory_len();
+    let end = u64::from(ptr) + u64::from(len);
+    if ptr == 0 || end > max {
+        return Err(MemoryError::OutOfBounds {
+            offset: ptr as usize,
+            len: len as usize,
+            max: max as usize,
+        });
     }
-    unsafe { core::slice::from_raw_parts(ptr as *const u8, len as usize) }
+    Ok(())
+}
+
+/// Current size of linear memory in bytes
+#[cfg(target_arch = "wasm32")]
+fn memory_len() -> u64 {
+    core::arch::wasm32::memory_size(0) as u64 * 65536
+}
+
+/// Natively any 32-bit address is accepted
+#[cfg(not(target_arch = "wasm32"))]
+fn memory_len() -> u64 {
+    1 << 32
+}
+
+/// Encode `data` in an envelope placed directly in an allocation of the
+/// exact size from `region`
+///
+/// Fails with [`MemoryError::ArenaExhausted`](aingle_wasmer_common::MemoryError::ArenaExhausted)
+/// if the arena cannot provide the allocation.
+pub(crate) fn encode_in_arena(
+    data: &[u8],
+    flags: u8,
+    region: ArenaRegion,
+) -> Result<&'static [u8], WasmError> {
+    let len = EnvelopeHeader::SIZE.saturating_add(data.len());
+    let ptr = ARENA
+        .with(|arena| arena.try_alloc_in(region, len))
+        .map_err(WasmError::Memory)?;
+    let region = unsafe { core::slice::from_raw_parts_mut(ptr, len) };
+
+    let written = encode_envelope_with(checksum_kind(), data, flags, region)?;
+    Ok(&region[..written])
 }
 
 /// Return a successful result to the host
+///
+/// The result is encoded straight into the arena, so its size is only
+/// bounded by guest memory.
 pub fn return_ok(data: &[u8]) -> u64 {
-    let mut buffer = [0u8; 4096]; // Max return size
+    match encode_in_arena(data, 0, ArenaRegion::Output) {
+        Ok(envelope) => WasmResult::ok(WasmSlice::new(
+            envelope.as_ptr() as u32,
+            envelope.len() as u32,
+        ))
+        .into_raw(),
+        Err(e) => return_err(error_text(&e).as_ref()),
+    }
+}
 
-    match encode_with_envelope(data, 0, &mut buffer) {
-        Ok(len) => {
-            let ptr = arena_alloc_copy(&buffer[..len]);
-            WasmResult::ok(WasmSlice::new(ptr as u32, len as u32)).into_raw()
-        }
-        Err(_) => return_err(b"encoding error"),
+/// Serialize `value` and return it to the host in an envelope
+///
+/// The serialized value is enveloped straight into an arena allocation of
+/// the exact size. Unit is returned as an empty result, see
+/// [`is_unit`](aingle_wasmer_common::is_unit), and values that cannot be
+/// serialized are returned as an [`ErrorKind::Serialization`] error through
+/// [`return_err_typed`].
+#[cfg(feature = "std")]
+pub fn return_ok_typed<T: Serialize + core::fmt::Debug + ?Sized>(value: &T) -> u64 {
+    if is_unit(value) {
+        return WasmResult::ok_empty().into_raw();
+    }
+    match typed_envelope(value) {
+        Ok(envelope) => WasmResult::ok(WasmSlice::new(
+            envelope.as_ptr() as u32,
+            envelope.len() as u32,
+        ))
+        .into_raw(),
+        Err(error) => return_err_typed(&error),
+    }
+}
+
+/// Serialize `value` into an envelope in the output region
+#[cfg(feature = "std")]
+fn typed_envelope<T: Serialize + core::fmt::Debug + ?Sized>(
+    value: &T,
+) -> Result<&'static [u8], WasmError> {
+    let bytes = aingle_middleware_bytes::encode(&value).map_err(serialization_error::<T>)?;
+    encode_in_arena(&bytes, 0, ArenaRegion::Output)
+}
+
+/// Error for a value of type `T` that could not be serialized
+#[cfg(all(feature = "std", not(feature = "min-size")))]
+fn serialization_error<T: ?Sized>(e: impl core::fmt::Display) -> WasmError {
+    let message = format!("cannot serialize {}: {}", core::any::type_name::<T>(), e);
+    WasmError::GuestStructured(WasmErrorInner::new(ErrorKind::Serialization, &message))
+}
+
+/// Error for a value of type `T` that could not be serialized, naming only
+/// its type
+#[cfg(all(feature = "std", feature = "min-size"))]
+fn serialization_error<T: ?Sized>(_e: impl core::fmt::Display) -> WasmError {
+    let inner = WasmErrorInner::new(ErrorKind::Serialization, core::any::type_name::<T>());
+    WasmError::GuestStructured(inner)
+}
+
+/// Return `error` to the host in an error envelope
+///
+/// The payload is the serialized error understood by the host's
+/// `guest_error_message`, the same as for
+/// [`return_err_ptr`](crate::return_err_ptr), so its kind and message reach
+/// the host intact.
+#[cfg(feature = "std")]
+pub fn return_err_typed(error: &WasmError) -> u64 {
+    match error_envelope(error) {
+        Ok(envelope) => WasmResult::err(WasmSlice::new(
+            envelope.as_ptr() as u32,
+            envelope.len() as u32,
+        ))
+        .into_raw(),
+        Err(_) => fallback_err(error_text(error).as_ref(), true, error.kind()),
+    }
+}
+
+/// Serialize `error` into an error envelope in the output region
+#[cfg(feature = "std")]
+fn error_envelope(error: &WasmError) -> Result<&'static [u8], WasmError> {
+    let payload = crate::compat::error_payload(error);
+    encode_in_arena(&payload, EnvelopeFlags::IsError as u8, ArenaRegion::Output)
+}
+
+/// Render an error for [`return_err`]
+#[cfg(not(feature = "min-size"))]
+pub(crate) fn error_text(error: &WasmError) -> impl AsRef<[u8]> {
+    format_message(format_args!("{}", error))
+}
+
+/// Render an error for [`return_err`] as its kind name and raw message
+///
+/// Unlike `Display` this links in no formatting code.
+#[cfg(feature = "min-size")]
+pub(crate) fn error_text(error: &WasmError) -> Vec<u8> {
+    let (name, message) = (error.kind().name(), error.message());
+    let mut text = Vec::with_capacity(name.len() + 2 + message.len());
+    text.extend_from_slice(name.as_bytes());
+    if !message.is_empty() {
+        text.extend_from_slice(b": ");
+        text.extend_from_slice(message.as_bytes());
     }
+    text
+}
+
+/// Render the error of a failed [`try_result_raw!`] with `Debug`
+#[doc(hidden)]
+#[cfg(not(feature = "min-size"))]
+pub fn __debug_text<E: core::fmt::Debug>(error: &E) -> impl AsRef<[u8]> {
+    format_message(format_args!("{:?}", error))
+}
+
+/// Render the error of a failed [`try_result_raw!`] as its type name
+#[doc(hidden)]
+#[cfg(feature = "min-size")]
+pub fn __debug_text<E: core::fmt::Debug>(_error: &E) -> impl AsRef<[u8]> {
+    core::any::type_name::<E>()
+}
+
+#[cfg(all(feature = "std", not(feature = "min-size")))]
+fn format_message(args: core::fmt::Arguments<'_>) -> alloc::string::String {
+    alloc::fmt::format(args)
+}
+
+/// Without `std` messages are formatted into a fixed buffer and truncated to
+/// fit, instead of allocating a `String`
+#[cfg(all(not(feature = "std"), not(feature = "min-size")))]
+fn format_message(args: core::fmt::Arguments<'_>) -> ErrorMessage {
+    ErrorMessage::format(args)
 }
 
 /// Return an error result to the host
 pub fn return_err(message: &[u8]) -> u64 {
-    use aingle_wasmer_common::EnvelopeFlags;
+    match encode_in_arena(message, EnvelopeFlags::IsError as u8, ArenaRegion::Output) {
+        Ok(envelope) => WasmResult::err(WasmSlice::new(
+            envelope.as_ptr() as u32,
+            envelope.len() as u32,
+        ))
+        .into_raw(),
+        Err(_) => fallback_err(message, true, ErrorKind::Unknown),
+    }
+}
 
-    let mut buffer = [0u8; 256];
-    let flags = EnvelopeFlags::IsError as u8;
+/// Return an error from a static buffer, for when the arena is exhausted
+///
+/// The message is truncated to fit and wrapped in an error envelope if
+/// `enveloped` is set. Should nothing of it remain, the error is returned
+/// without a payload and carries `kind` instead.
+pub(crate) fn fallback_err(message: &[u8], enveloped: bool, kind: ErrorKind) -> u64 {
+    FALLBACK_ERROR.with_borrow_mut(|buffer| {
+        let len = if enveloped {
+            let message = &message[..message.len().min(FALLBACK_ERROR_LEN - EnvelopeHeader::SIZE)];
+            let flags = EnvelopeFlags::IsError as u8;
+            match encode_envelope_with(checksum_kind(), message, flags, buffer) {
+                Ok(len) => len,
+                Err(_) => return WasmResult::err_with_kind(WasmSlice::empty(), kind).into_raw(),
+            }
+        } else {
+            let len = message.len().min(FALLBACK_ERROR_LEN);
+            buffer[..len].copy_from_slice(&message[..len]);
+            len
+        };
+        WasmResult::err_with_kind(WasmSlice::new(buffer.as_ptr() as u32, len as u32), kind)
+            .into_raw()
+    })
+}
 
-    match encode_with_envelope(message, flags, &mut buffer) {
-        Ok(len) => {
-            let ptr = arena_alloc_copy(&buffer[..len]);
-            WasmResult::err(WasmSlice::new(ptr as u32, len as u32)).into_raw()
+/// Try macro for guest functions - returns error to host on failure
+///
+/// The error is returned with [`return_err_ptr`](crate::return_err_ptr), so
+/// its kind and message reach the host intact:
+///
+/// - `try_result!(expr)` converts the error with `Into<WasmError>`
+/// - `try_result!(expr, "message")` replaces it with a located guest error
+/// - `try_result!(expr, ErrorKind::Validation, "context: {}")` formats the
+///   error into a located error of the given kind
+///
+/// The enclosing function may return [`GuestReturn`](crate::GuestReturn) or a
+/// bare `u64`.
+///
+/// Requires the `std` feature; see [`try_result_raw!`] otherwise.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! try_result {
+    ($expr:expr) => {
+        match $expr {
+            Ok(val) => val,
+            Err(e) => {
+                return $crate::return_err_ptr($crate::WasmError::from(e)).into();
+            }
         }
-        Err(_) => {
-            // Last resort: return empty error
-            WasmResult::err(WasmSlice::empty()).into_raw()
+    };
+    ($expr:expr, $msg:literal) => {
+        match $expr {
+            Ok(val) => val,
+            Err(_) => {
+                return $crate::return_err_ptr($crate::WasmError::guest_here($msg)).into();
+            }
         }
-    }
+    };
+    ($expr:expr, $kind:expr, $context:literal) => {
+        match $expr {
+            Ok(val) => val,
+            Err(e) => {
+                let inner = $crate::WasmErrorInner::here($kind, &format!($context, e));
+                return $crate::return_err_ptr($crate::WasmError::GuestStructured(inner)).into();
+            }
+        }
+    };
 }
 
-/// Try macro for guest functions - returns error to host on failure
+/// Try macro returning the `Debug` string of the error with [`return_err`]
+///
+/// Kept for functions not yet migrated to [`try_result!`]. Without `std` the
+/// message is truncated to a fixed length, and with `min-size` it is the type
+/// name of the error.
 #[macro_export]
-macro_rules! try_result {
+macro_rules! try_result_raw {
     ($expr:expr) => {
         match $expr {
             Ok(val) => val,
             Err(e) => {
-                let msg = format!("{:?}", e);
-                return $crate::return_err(msg.as_bytes());
+                let msg = $crate::__debug_text(&e);
+                return $crate::return_err(msg.as_ref());
             }
         }
     };
@@ -87,11 +465,26 @@ macro_rules! try_result {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use aingle_wasmer_codec::{decode_envelope_with, encode_with_envelope};
+    #[cfg(feature = "std")]
+    use aingle_wasmer_common::{DeserializeError, ErrorKind, GuestReturn};
+    use alloc::{vec, vec::Vec};
 
     /// Test that return_ok produces a valid result.
     /// Note: In native (non-WASM) mode, the arena pointer may have bit 31 set,
     /// which when packed can interfere with the error bit. This test verifies
     /// the encoding works and produces non-empty output.
+    #[test]
+    fn test_reserve_scratch() {
+        assert_eq!(reserve_scratch(0), None);
+        let first = reserve_scratch(64).unwrap();
+        // Buffers large enough are kept
+        assert_eq!(reserve_scratch(16), Some(first));
+        let larger = reserve_scratch(4096).unwrap();
+        assert_eq!(reserve_scratch(4096), Some(larger));
+        SCRATCH.with(|scratch| assert_eq!(scratch.borrow().len(), 4096));
+    }
+
     #[test]
     fn test_return_ok() {
         let data = b"test response";
@@ -119,6 +512,301 @@ mod tests {
         assert!(wasm_result.is_err());
     }
 
+    /// Results beyond the former 4 KiB stack buffer decode intact
+    #[test]
+    fn test_large_results_encoded_in_arena() {
+        for size in [64 * 1024, 1024 * 1024] {
+            let data: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+
+            let envelope = encode_in_arena(&data, 0, ArenaRegion::Output).unwrap();
+            assert_eq!(envelope.len(), EnvelopeHeader::SIZE + size);
+            assert_eq!(decode_envelope(envelope).unwrap().payload, &data[..]);
+
+            let envelope =
+                encode_in_arena(&data, EnvelopeFlags::IsError as u8, ArenaRegion::Scratch).unwrap();
+            let decoded = decode_envelope(envelope).unwrap();
+            assert!(decoded.header.is_error());
+            assert_eq!(decoded.payload, &data[..]);
+        }
+    }
+
+    #[test]
+    fn test_selected_checksum() {
+        set_checksum_kind(ChecksumKind::Crc32c);
+        let envelope = encode_in_arena(b"data", 0, ArenaRegion::Output).unwrap();
+        let decoded = decode_envelope_with(ChecksumKind::Crc32c, envelope).unwrap();
+        assert_eq!(decoded.payload, b"data");
+
+        set_checksum_kind(ChecksumKind::Crc32);
+        let envelope = encode_in_arena(b"data", 0, ArenaRegion::Output).unwrap();
+        assert_eq!(
+            decode_envelope(envelope).unwrap().checksum,
+            ChecksumKind::Crc32
+        );
+    }
+
+    #[test]
+    fn test_exhausted_arena_returns_error() {
+        use crate::arena::arena_set_limit;
+
+        arena_set_limit(Some(0));
+        let result = WasmResult::from_raw(return_ok(&[0u8; 64 * 1024]));
+        arena_set_limit(None);
+
+        let expected: &[u8] = if cfg!(feature = "min-size") {
+            b"Memory"
+        } else {
+            b"guest arena exhausted"
+        };
+        assert!(result.is_err());
+        assert!(!result.slice().is_empty());
+        FALLBACK_ERROR.with_borrow(|buffer| {
+            let envelope = decode_envelope(&buffer[..result.slice().len as usize]).unwrap();
+            assert!(envelope.header.is_error());
+            assert_eq!(envelope.payload, expected);
+        });
+    }
+
+    #[test]
+    fn test_guest_range_checks() {
+        assert!(check_guest_range(0, 0).is_ok());
+        assert!(check_guest_range(1024, 16).is_ok());
+
+        // Null pointer with a length
+        assert_eq!(
+            check_guest_range(0, 4),
+            Err(MemoryError::OutOfBounds {
+                offset: 0,
+                len: 4,
+                max: memory_len() as usize,
+            })
+        );
+        assert_eq!(
+            read_bytes(0, 4).map(<[u8]>::len),
+            Err(WasmError::Memory(MemoryError::OutOfBounds {
+                offset: 0,
+                len: 4,
+                max: memory_len() as usize,
+            }))
+        );
+
+        // ptr + len overflowing u32
+        assert!(check_guest_range(u32::MAX - 1, 16).is_err());
+        assert!(host_args_envelope(u32::MAX - 1, 16).is_err());
+    }
+
+    #[test]
+    #[cfg(target_arch = "wasm32")]
+    fn test_guest_range_beyond_memory() {
+        let max = memory_len() as u32;
+        assert!(check_guest_range(max - 16, 16).is_ok());
+        assert!(check_guest_range(max - 16, 17).is_err());
+    }
+
+    #[test]
+    fn test_detect_framing() {
+        // MessagePack for {"a": 1}
+        let payload = [0x81, 0xa1, b'a', 0x01];
+        let mut buffer = vec![0u8; 64];
+        let len = encode_with_envelope(&payload, 0, &mut buffer).unwrap();
+
+        let input = detect_framing(&buffer[..len]);
+        assert!(input.is_enveloped());
+        assert_eq!(input.payload, &payload[..]);
+        assert_eq!(input.header.unwrap().payload_len as usize, payload.len());
+
+        let input = detect_framing(&payload);
+        assert!(!input.is_enveloped());
+        assert_eq!(input.payload, &payload[..]);
+
+        assert!(!detect_framing(&[]).is_enveloped());
+    }
+
+    #[test]
+    fn test_coincidental_magic_is_raw() {
+        // Raw input starting with the magic bytes and a supported version,
+        // but whose "checksum" does not match
+        let mut raw = vec![0x49, 0x41, 0x01, 0x00, 0x04, 0x00, 0x00, 0x00];
+        raw.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef, 1, 2, 3, 4]);
+        let input = detect_framing(&raw);
+        assert!(!input.is_enveloped());
+        assert_eq!(input.payload, &raw[..]);
+
+        // A valid envelope followed by more bytes is not an envelope either
+        let mut buffer = vec![0u8; 64];
+        let len = encode_with_envelope(b"data", 0, &mut buffer).unwrap();
+        assert!(!detect_framing(&buffer[..len + 1]).is_enveloped());
+
+        // Nor is an unsupported version with a valid checksum
+        buffer[2] = aingle_wasmer_common::PROTOCOL_VERSION + 1;
+        assert!(!detect_framing(&buffer[..len]).is_enveloped());
+
+        // Shorter than a header
+        assert!(!detect_framing(&[0x49, 0x41, 0x01]).is_enveloped());
+    }
+
+    #[test]
+    fn test_input_envelope_length_must_match() {
+        let mut buffer = vec![0u8; 64];
+        let len = encode_with_envelope(b"input", 0, &mut buffer).unwrap();
+
+        let envelope = decode_input_envelope(&buffer[..len]).unwrap();
+        assert_eq!(envelope.payload, b"input");
+
+        assert_eq!(
+            decode_input_envelope(&buffer[..len + 8]).map(|e| e.payload),
+            Err(WasmError::Memory(MemoryError::OutOfBounds {
+                offset: 0,
+                len: len + 8,
+                max: len,
+            }))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "min-size")]
+    fn test_min_size_error_text() {
+        let error = WasmError::Memory(MemoryError::ArenaExhausted);
+        assert_eq!(error_text(&error), b"Memory");
+        assert_eq!(error_text(&WasmError::guest("boom")), b"Unknown: boom");
+        assert_eq!(
+            __debug_text(&MemoryError::ArenaExhausted).as_ref(),
+            core::any::type_name::<MemoryError>().as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_host_args_pair_empty() {
+        let (first, second) = host_args_pair(0, 0, 0, 0).unwrap();
+        assert!(first.is_empty());
+        assert!(second.is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    fn parse_count(input: &str) -> GuestReturn {
+        let count: u32 = try_result!(input.parse::<u32>(), ErrorKind::Validation, "context: {}");
+        crate::return_ptr(count)
+    }
+
+    #[cfg(feature = "std")]
+    fn decode_count(input: Result<u32, WasmError>) -> u64 {
+        let count = try_result!(input);
+        crate::return_ptr(count).into()
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_try_result_returns_typed_errors() {
+        assert!(parse_count("many").as_result().is_err());
+
+        let error = WasmError::Deserialize(DeserializeError::InvalidFormat);
+        let result = WasmResult::from_raw(decode_count(Err(error.clone())));
+        let expected = crate::return_err_ptr(error).as_result();
+        assert!(result.is_err());
+        assert_eq!(result.slice().len, expected.slice().len);
+    }
+
+    #[test]
+    #[cfg(all(feature = "std", target_arch = "wasm32"))]
+    fn test_try_result_context_reaches_host() {
+        #[derive(Debug, serde::Deserialize)]
+        struct SerializableError {
+            message: String,
+        }
+
+        let slice = parse_count("many").as_slice();
+        let bytes =
+            unsafe { core::slice::from_raw_parts(slice.ptr as *const u8, slice.len as usize) };
+        let error: SerializableError = aingle_middleware_bytes::decode(bytes).unwrap();
+        assert!(error
+            .message
+            .starts_with("[Validation] context: invalid digit found in string at "));
+        assert!(error.message.contains("memory.rs:"));
+    }
+
+    #[cfg(feature = "std")]
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Point {
+        x: i32,
+        label: String,
+    }
+
+    /// Results are checked through the envelopes, as natively the packed
+    /// pointers are truncated
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_typed_results_roundtrip() {
+        let point = Point {
+            x: -3,
+            label: "origin".to_string(),
+        };
+        let envelope = decode_envelope(typed_envelope(&point).unwrap()).unwrap();
+        assert!(!envelope.header.is_error());
+        let decoded: Point = aingle_middleware_bytes::decode(envelope.payload).unwrap();
+        assert_eq!(decoded, point);
+
+        let raw = WasmResult::from_raw(return_ok_typed(&point));
+        assert_eq!(
+            raw.slice().len as usize,
+            EnvelopeHeader::SIZE + envelope.payload.len()
+        );
+
+        let unit = WasmResult::from_raw(return_ok_typed(&()));
+        assert!(unit.is_ok());
+        assert!(unit.slice().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_typed_errors_roundtrip() {
+        let error = WasmError::GuestStructured(
+            WasmErrorInner::new(ErrorKind::Validation, "bad point").with_location("zome.rs", 7),
+        );
+        let envelope = decode_envelope(error_envelope(&error).unwrap()).unwrap();
+        assert!(envelope.header.is_error());
+        assert_eq!(decoded_error(envelope.payload), error.to_string());
+
+        let raw = WasmResult::from_raw(return_err_typed(&error));
+        assert_eq!(
+            raw.slice().len as usize,
+            EnvelopeHeader::SIZE + envelope.payload.len()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_unserializable_result_is_structured() {
+        #[derive(Debug)]
+        struct Unserializable;
+
+        impl serde::Serialize for Unserializable {
+            fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+                Err(serde::ser::Error::custom("refused"))
+            }
+        }
+
+        let error = typed_envelope(&Unserializable).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Serialization);
+        assert!(error.message().contains("Unserializable"));
+    }
+
+    /// The message the host recovers from an error payload
+    #[cfg(all(feature = "std", not(feature = "min-size")))]
+    fn decoded_error(payload: &[u8]) -> String {
+        #[derive(Debug, serde::Deserialize)]
+        struct SerializableError {
+            message: String,
+        }
+        aingle_middleware_bytes::decode::<_, SerializableError>(payload)
+            .unwrap()
+            .message
+    }
+
+    #[cfg(all(feature = "std", feature = "min-size"))]
+    fn decoded_error(payload: &[u8]) -> String {
+        WasmError::GuestStructured(WasmErrorInner::decode_fixed(payload).unwrap()).to_string()
+    }
+
     /// Test encoding itself works correctly
     #[test]
     fn test_encoding_roundtrip() {
@@ -0,0 +1,410 @@
+//! Native stand-in for the host, for unit tests of guest logic
+//!
+//! With the `mock-host` feature, native builds of functions declared with
+//! [`host_externs!`](crate::host_externs) or from
+//! [`hc_externs`](crate::hc_externs) no longer import anything: they answer
+//! from the [`MockHost`] installed on the calling thread. Arguments and
+//! responses still go through the serialization and envelope checks of the
+//! host call functions, so `cargo test` exercises the same path as a guest
+//! running in the host. [`host_call_with`] calls a mock directly, without
+//! declaring the function. wasm32 builds import the real host functions
+//! whatever the feature.
+//!
+//! ```ignore
+//! aingle_wasmer_guest::host_externs!(greeting_prefix);
+//!
+//! fn greet(name: String) -> Result<String, WasmError> {
+//!     let prefix: String = host_call_enveloped(greeting_prefix, ())?;
+//!     Ok(prefix + &name)
+//! }
+//!
+//! #[test]
+//! fn test_greet() {
+//!     let host = MockHost::new();
+//!     host.register_typed("greeting_prefix", |()| Ok("Hello, ".to_string()));
+//!     let _installed = host.install();
+//!
+//!     assert_eq!(greet("Ada".to_string()).unwrap(), "Hello, Ada");
+//!     assert_eq!(host.call_count("greeting_prefix"), 1);
+//! }
+//! ```
+
+use crate::arena::{ArenaRegion, ARENA};
+use crate::compat::decode_input;
+use crate::host_call::{encode_host_args, enveloped_response};
+use aingle_wasmer_codec::{decode_envelope, encode_with_envelope};
+use aingle_wasmer_common::{
+    EnvelopeFlags, EnvelopeHeader, HostCallError, WasmError, WasmResult, WasmSlice,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::rc::Rc;
+
+/// Stand-in guest address of mock responses, which are read from
+/// [`take_response`] instead
+const MOCK_RESPONSE_PTR: u32 = 8;
+
+type Handler = dyn FnMut(&[u8]) -> Result<Vec<u8>, WasmError>;
+
+thread_local! {
+    /// Host answering the `host_externs!` functions of this thread
+    static ACTIVE: RefCell<Option<MockHost>> = const { RefCell::new(None) };
+    /// Arguments of the host call in progress, and whether they are enveloped
+    static STAGED: Cell<Option<(*const u8, usize, bool)>> = const { Cell::new(None) };
+    /// Response of the last mock host function called
+    static RESPONSE: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+    /// Panic of a handler, rethrown once the host function returned
+    static PANIC: RefCell<Option<Box<dyn Any + Send>>> = const { RefCell::new(None) };
+}
+
+/// Scripted host functions answering host calls in native tests
+///
+/// Every call is recorded with its serialized input, whether a function is
+/// registered for it or not; calls to unregistered functions fail with
+/// [`HostCallError::FunctionNotFound`]. Clones share their functions and
+/// recorded calls.
+#[derive(Clone, Default)]
+pub struct MockHost(Rc<MockHostInner>);
+
+#[derive(Default)]
+struct MockHostInner {
+    handlers: RefCell<HashMap<String, Rc<RefCell<Handler>>>>,
+    calls: RefCell<Vec<(String, Vec<u8>)>>,
+}
+
+impl MockHost {
+    /// A host without functions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer calls to the host function `name` with `handler`
+    ///
+    /// The handler receives the serialized input, without its envelope, and
+    /// returns the serialized output or the error the host reports. Inputs
+    /// and outputs of unit are empty. Replaces an earlier handler of `name`.
+    pub fn register<F>(&self, name: &str, handler: F) -> &Self
+    where
+        F: FnMut(&[u8]) -> Result<Vec<u8>, WasmError> + 'static,
+    {
+        self.0
+            .handlers
+            .borrow_mut()
+            .insert(name.to_string(), Rc::new(RefCell::new(handler)));
+        self
+    }
+
+    /// Answer calls to the host function `name` with a typed `handler`
+    ///
+    /// Inputs and outputs are serialized like those of
+    /// [`host_call_enveloped`](crate::host_call_enveloped).
+    pub fn register_typed<I, O, F>(&self, name: &str, mut handler: F) -> &Self
+    where
+        I: DeserializeOwned + core::fmt::Debug,
+        O: Serialize + core::fmt::Debug,
+        F: FnMut(I) -> Result<O, WasmError> + 'static,
+    {
+        self.register(name, move |input| {
+            encode_host_args(handler(decode_input(input)?)?)
+        })
+    }
+
+    /// Answer the host functions called on this thread until the returned
+    /// guard is dropped
+    pub fn install(&self) -> MockHostGuard {
+        let previous = ACTIVE.with(|active| active.borrow_mut().replace(self.clone()));
+        MockHostGuard { previous }
+    }
+
+    /// Number of calls to the host function `name`
+    pub fn call_count(&self, name: &str) -> usize {
+        self.0
+            .calls
+            .borrow()
+            .iter()
+            .filter(|(called, _)| called == name)
+            .count()
+    }
+
+    /// Serialized inputs of the calls to the host function `name`, in call
+    /// order
+    pub fn calls(&self, name: &str) -> Vec<Vec<u8>> {
+        self.0
+            .calls
+            .borrow()
+            .iter()
+            .filter(|(called, _)| called == name)
+            .map(|(_, input)| input.clone())
+            .collect()
+    }
+
+    /// Inputs of the calls to the host function `name`, deserialized
+    pub fn call_args<T>(&self, name: &str) -> Result<Vec<T>, WasmError>
+    where
+        T: DeserializeOwned + core::fmt::Debug,
+    {
+        self.calls(name)
+            .iter()
+            .map(|input| decode_input(input))
+            .collect()
+    }
+
+    /// Record a call and run the handler of `name` on its input
+    fn answer(&self, name: &str, input: &[u8]) -> Result<Vec<u8>, WasmError> {
+        self.0
+            .calls
+            .borrow_mut()
+            .push((name.to_string(), input.to_vec()));
+
+        // Released before the handler runs, so it may register functions
+        let handler = self.0.handlers.borrow().get(name).cloned();
+        match handler {
+            Some(handler) => (handler.borrow_mut())(input),
+            None => Err(WasmError::HostCall(HostCallError::FunctionNotFound)),
+        }
+    }
+}
+
+/// Keeps a [`MockHost`] installed, reinstalling the previous one on drop
+#[must_use = "the mock host is uninstalled when the guard is dropped"]
+pub struct MockHostGuard {
+    previous: Option<MockHost>,
+}
+
+impl Drop for MockHostGuard {
+    fn drop(&mut self) {
+        ACTIVE.with(|active| *active.borrow_mut() = self.previous.take());
+    }
+}
+
+/// Call the host function `name` of `host` like
+/// [`host_call_enveloped`](crate::host_call_enveloped) calls a declared one
+pub fn host_call_with<I, O>(host: &MockHost, name: &str, input: I) -> Result<O, WasmError>
+where
+    I: Serialize + core::fmt::Debug,
+    O: DeserializeOwned + core::fmt::Debug,
+{
+    let args = encode_host_args(input)?;
+    let (result, response) = respond(host.answer(name, &args), true);
+    enveloped_response(result, &response)
+}
+
+/// The result and response bytes a host sends for `answer`
+///
+/// Errors are serialized [`WasmError`]s; with `enveloped`, non-empty
+/// responses are wrapped in envelopes, flagged as errors for errors.
+fn respond(answer: Result<Vec<u8>, WasmError>, enveloped: bool) -> (WasmResult, Vec<u8>) {
+    let (is_err, payload) = match answer {
+        Ok(output) => (false, output),
+        Err(error) => match aingle_middleware_bytes::encode(&error) {
+            Ok(payload) => (true, payload),
+            Err(_) => (true, error.to_string().into_bytes()),
+        },
+    };
+
+    let response = match (enveloped, payload.is_empty()) {
+        (true, false) => {
+            let flags = if is_err {
+                EnvelopeFlags::IsError as u8
+            } else {
+                0
+            };
+            let mut buffer = vec![0u8; EnvelopeHeader::SIZE + payload.len()];
+            let len = encode_with_envelope(&payload, flags, &mut buffer)
+                .expect("buffer sized for the envelope");
+            buffer.truncate(len);
+            buffer
+        }
+        _ => payload,
+    };
+
+    let slice = match response.len() {
+        0 => WasmSlice::empty(),
+        len => WasmSlice::new(MOCK_RESPONSE_PTR, len as u32),
+    };
+    let result = match is_err {
+        true => WasmResult::err(slice),
+        false => WasmResult::ok(slice),
+    };
+    (result, response)
+}
+
+/// Record the arguments of a host call about to be made
+pub(crate) fn stage_call(args: &[u8], enveloped: bool) {
+    STAGED.with(|staged| staged.set(Some((args.as_ptr(), args.len(), enveloped))));
+    RESPONSE.with(|response| response.borrow_mut().take());
+}
+
+/// Conclude a host call, rethrowing a panic of the handler
+pub(crate) fn finish_call() {
+    STAGED.with(|staged| staged.set(None));
+    if let Some(panic) = PANIC.with(|panic| panic.borrow_mut().take()) {
+        resume_unwind(panic);
+    }
+}
+
+/// The response of the mock host function just called, copied to scratch
+/// memory, or `None` if a real function was called
+pub(crate) fn take_response<'a>() -> Option<&'a [u8]> {
+    let response = RESPONSE.with(|response| response.borrow_mut().take())?;
+    let ptr = ARENA
+        .with(|arena| arena.try_alloc_copy_in(ArenaRegion::Scratch, &response))
+        .expect("arena exhausted copying a mock host response");
+    Some(unsafe { core::slice::from_raw_parts(ptr, response.len()) })
+}
+
+/// Answer a call to the mock host function `name` from the installed
+/// [`MockHost`]
+///
+/// Called by the functions [`host_externs!`](crate::host_externs) declares;
+/// only calls made through the host call functions carry arguments.
+#[doc(hidden)]
+pub fn __dispatch(name: &str) -> u64 {
+    let Some((ptr, len, enveloped)) = STAGED.with(|staged| staged.take()) else {
+        return WasmResult::err(WasmSlice::empty()).into_raw();
+    };
+    // Staged by the host call functions, which keep the arguments alive
+    let args = unsafe { core::slice::from_raw_parts(ptr, len) };
+
+    let answer = catch_unwind(AssertUnwindSafe(|| {
+        let host = ACTIVE
+            .with(|active| active.borrow().clone())
+            .ok_or_else(|| {
+                WasmError::Host(format!("no MockHost is installed to answer `{}`", name))
+            })?;
+        let input = match enveloped {
+            true => decode_envelope(args)?.payload,
+            false => args,
+        };
+        host.answer(name, input)
+    }))
+    .unwrap_or_else(|panic| {
+        // Unwinding out of an `extern "C"` function aborts
+        PANIC.with(|slot| *slot.borrow_mut() = Some(panic));
+        Err(WasmError::Host(format!(
+            "mock host function `{}` panicked",
+            name
+        )))
+    });
+
+    let (result, response) = respond(answer, enveloped);
+    RESPONSE.with(|slot| *slot.borrow_mut() = Some(response));
+    result.into_raw()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{host_call, host_call_enveloped, host_call_raw, ErrorKind, WasmErrorInner};
+
+    crate::host_externs!(mock_lookup, mock_store);
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Entry {
+        key: String,
+        value: u32,
+    }
+
+    #[test]
+    fn test_scripted_response() {
+        let host = MockHost::new();
+        host.register_typed("mock_lookup", |key: String| {
+            Ok(Entry {
+                value: key.len() as u32,
+                key,
+            })
+        });
+        let _installed = host.install();
+
+        let entry: Entry = host_call_enveloped(mock_lookup, "answer".to_string()).unwrap();
+        assert_eq!(
+            entry,
+            Entry {
+                key: "answer".to_string(),
+                value: 6
+            }
+        );
+        // The legacy protocol reaches the same handler
+        let entry: Entry = host_call(mock_lookup, "abc".to_string()).unwrap();
+        assert_eq!(entry.value, 3);
+
+        assert_eq!(host.call_count("mock_lookup"), 2);
+        assert_eq!(
+            host.call_args::<String>("mock_lookup").unwrap(),
+            ["answer", "abc"]
+        );
+    }
+
+    #[test]
+    fn test_host_error() {
+        let host = MockHost::new();
+        host.register("mock_store", |_| {
+            Err(WasmError::GuestStructured(WasmErrorInner::new(
+                ErrorKind::PermissionDenied,
+                "read only",
+            )))
+        });
+        let _installed = host.install();
+
+        let error = host_call_enveloped::<_, ()>(mock_store, 1u8).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+        assert_eq!(host_call::<_, ()>(mock_store, 1u8).unwrap_err(), error);
+        assert_eq!(host.calls("mock_store"), [vec![1u8], vec![1u8]]);
+
+        // Functions the mock does not know
+        assert_eq!(
+            host_call_enveloped::<_, ()>(mock_lookup, ()),
+            Err(WasmError::HostCall(HostCallError::FunctionNotFound))
+        );
+        assert_eq!(host.call_count("mock_lookup"), 1);
+    }
+
+    #[test]
+    fn test_raw_and_unit_calls() {
+        let host = MockHost::new();
+        host.register("mock_store", |input| {
+            Ok(input.iter().rev().copied().collect())
+        });
+        host.register_typed("mock_lookup", |()| Ok(()));
+        let _installed = host.install();
+
+        assert_eq!(host_call_raw(mock_store, b"abc").unwrap(), b"cba");
+        host_call_enveloped::<_, ()>(mock_lookup, ()).unwrap();
+        assert_eq!(host.calls("mock_lookup"), [Vec::<u8>::new()]);
+
+        // Called directly, unit outputs still decode only as unit
+        host_call_with::<_, ()>(&host, "mock_lookup", ()).unwrap();
+        assert!(host_call_with::<_, String>(&host, "mock_lookup", ()).is_err());
+        assert_eq!(host.call_count("mock_lookup"), 3);
+    }
+
+    #[test]
+    fn test_install_scopes() {
+        let outer = MockHost::new();
+        outer.register_typed("mock_lookup", |()| Ok(1u32));
+        let _outer = outer.install();
+        {
+            let inner = MockHost::new();
+            inner.register_typed("mock_lookup", |()| Ok(2u32));
+            let _inner = inner.install();
+            assert_eq!(host_call_enveloped::<_, u32>(mock_lookup, ()), Ok(2));
+        }
+        assert_eq!(host_call_enveloped::<_, u32>(mock_lookup, ()), Ok(1));
+        drop(_outer);
+
+        let error = host_call_enveloped::<_, u32>(mock_lookup, ()).unwrap_err();
+        assert!(error.to_string().contains("no MockHost"), "{}", error);
+    }
+
+    #[test]
+    #[should_panic(expected = "scripted failure")]
+    fn test_handler_panics_reach_the_test() {
+        let host = MockHost::new();
+        host.register("mock_store", |_| panic!("scripted failure"));
+        let _installed = host.install();
+        let _ = host_call_raw(mock_store, b"x");
+    }
+}
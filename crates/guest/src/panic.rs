@@ -0,0 +1,118 @@
+//! Panic capture for guest entry points
+//!
+//! A panicking guest normally ends in an unreachable trap that tells the host
+//! nothing. [`set_panic_hook`] records the panic message and location as a
+//! structured error: entry points wrapped in [`catch_panic`] return it as an
+//! error result, and where the panic aborts instead of unwinding the host
+//! recovers it through the `__aingle_guest_last_panic` export.
+
+use crate::compat::return_err_ptr;
+use crate::memory::return_err_typed;
+use aingle_wasmer_common::{ErrorKind, GuestReturn, WasmError, WasmErrorInner};
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::panic::UnwindSafe;
+use std::sync::Once;
+
+thread_local! {
+    static LAST_PANIC: RefCell<Option<WasmErrorInner>> = const { RefCell::new(None) };
+    static PANIC_RESULT: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Install the panic hook recording guest panics
+///
+/// Only the first call installs the hook, so it is cheap to call at the start
+/// of every entry point. The previously installed hook still runs afterwards.
+pub fn set_panic_hook() {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let mut error =
+                WasmErrorInner::new(ErrorKind::GuestCall, &panic_message(info.payload()));
+            if let Some(location) = info.location() {
+                error = error.with_location(location.file(), location.line());
+            }
+
+            // Published for the host in case the panic aborts
+            PANIC_RESULT.set(return_err_ptr(WasmError::GuestStructured(error.clone())).into_raw());
+            LAST_PANIC.set(Some(error));
+            previous(info);
+        }));
+    });
+}
+
+/// Run an entry point, returning a panic inside it as an error result
+///
+/// The error carries the message and location recorded by the hook from
+/// [`set_panic_hook`], or just the panic message if the hook is not installed.
+/// Targets that abort on panic never return here; see the module docs.
+pub fn catch_panic<F>(f: F) -> GuestReturn
+where
+    F: FnOnce() -> GuestReturn + UnwindSafe,
+{
+    match std::panic::catch_unwind(f) {
+        Ok(result) => result,
+        Err(payload) => {
+            PANIC_RESULT.set(0);
+            let error = take_last_panic().unwrap_or_else(|| {
+                WasmErrorInner::new(ErrorKind::GuestCall, &panic_message(&*payload))
+            });
+            return_err_typed(&WasmError::GuestStructured(error)).into()
+        }
+    }
+}
+
+/// Take the error recorded by the most recent panic, if any
+pub fn take_last_panic() -> Option<WasmErrorInner> {
+    LAST_PANIC.take()
+}
+
+/// Take the error result published by the most recent panic, or 0
+pub(crate) fn take_panic_result() -> u64 {
+    PANIC_RESULT.take()
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "guest panicked".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catch_panic_records_location() {
+        set_panic_hook();
+        set_panic_hook();
+
+        let line = line!() + 1;
+        let result = catch_panic(|| panic!("bad input: {}", 42)).as_result();
+        assert!(result.is_err());
+        assert!(!result.slice().is_empty());
+
+        // catch_panic consumed the recorded panic and the published result
+        assert!(take_last_panic().is_none());
+        assert_eq!(take_panic_result(), 0);
+
+        let result = std::panic::catch_unwind(|| panic!("uncaught"));
+        assert!(result.is_err());
+        let error = take_last_panic().unwrap();
+        assert_eq!(error.message(), "uncaught");
+        assert_eq!(error.file.as_deref(), Some(file!()));
+        assert!(error.line.is_some_and(|l| l > line));
+        assert_ne!(take_panic_result(), 0);
+    }
+
+    #[test]
+    fn test_catch_panic_passes_results_through() {
+        let result = GuestReturn::from_raw(7);
+        assert_eq!(catch_panic(|| result), result);
+    }
+}
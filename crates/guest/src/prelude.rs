@@ -2,46 +2,111 @@
 //!
 //! This module provides all commonly used types and functions
 //! for WASM guest development.
+//!
+//! Call [`set_panic_hook`] once before any guest code can panic, from an init
+//! function or at the top of each entry point, and wrap entry bodies in
+//! [`catch_panic`] so panics reach the host as errors rather than traps.
+
+#[cfg(feature = "macros")]
+pub use crate::aingle_entry;
 
 pub use crate::{
     // Arena
     arena_alloc,
+    arena_alloc_aligned,
     arena_alloc_copy,
+    arena_high_water_mark,
+    arena_region_stats,
+    arena_reserve,
     arena_reset,
+    arena_set_limit,
+    arena_set_max_host_allocation,
+    arena_try_alloc,
+    // Host call cache
+    cached_host_call_raw,
     call_host,
-    // Compatibility layer (for ADK)
-    // Note: SerializedBytes is NOT exported - use from aingle_zome_types
-    host_args,
     // Memory (internal)
+    host_args_auto,
     host_args_envelope,
-    host_call,
+    host_args_pair,
     // Host calls (internal)
+    host_call_cache_set_limits,
+    host_call_cache_stats,
     host_call_raw,
     host_externs,
     read_bytes,
     return_err,
-    return_err_ptr,
     return_ok,
-    return_ptr,
+    // Explained calls
+    trace_step,
     // Macros
-    try_result,
+    try_result_raw,
+    ArenaRegion,
+    CacheLimits,
+    CacheStats,
     GuestArena,
+    HostCallCache,
+    HostInput,
+    // Streaming
+    OutputStream,
+    RegionStats,
+    StreamReader,
+    // Trait and, with `macros`, its derive
+    WasmSafe,
+    ARENA,
+};
+
+#[cfg(feature = "std")]
+pub use crate::{
+    cached_host_call,
+    // Panics
+    catch_panic,
+    // Compatibility layer (for ADK)
+    // Note: SerializedBytes is NOT exported - use from aingle_zome_types
+    host_args,
+    host_args_borrowed,
+    host_args_decode,
+    host_call,
+    // Host calls (recommended)
+    host_call_enveloped,
+    map_extern,
+    register_externs,
+    register_warmup,
+    return_err_ptr,
+    // Typed results (recommended)
+    return_err_typed,
+    return_ok_typed,
+    return_ptr,
+    set_panic_hook,
+    try_result,
     GuestPtr,
     Len,
-    ARENA,
 };
 
+#[allow(deprecated)]
+pub use aingle_wasmer_common::DoubleUSize;
 pub use aingle_wasmer_common::{
+    // Unit convention
+    decode_empty,
     // Macros
+    guest_fn,
+    is_unit,
+    static_assert_wasm_safe,
     wasm_error,
+    ChecksumKind,
     DeserializeError,
-    DoubleUSize,
+    EmptyPayload,
     EnvelopeError,
     EnvelopeFlags,
     // Envelope
     EnvelopeHeader,
     ErrorKind,
+    ErrorMessage,
+    ExternFlags,
+    ExternRecord,
     GuestCallError,
+    GuestFunction,
+    GuestReturn,
     HostCallError,
     MemoryError,
     SerializeError,
@@ -54,17 +119,18 @@ pub use aingle_wasmer_common::{
     WasmPrimitive,
     WasmRef,
     WasmResult,
-    WasmSafe,
     // Types
     WasmSlice,
     // Constants
     MAGIC,
+    MIN_SUPPORTED_PROTOCOL_VERSION,
     PROTOCOL_VERSION,
 };
 
 pub use aingle_wasmer_codec::{
-    compute_checksum, decode_envelope, decode_raw, encode_to_slice, encode_with_envelope,
-    verify_checksum, DecodedEnvelope, Decoder, Encoder,
+    compute_checksum, compute_checksum_with, decode_envelope, decode_envelope_with, decode_raw,
+    encode_envelope_with, encode_to_slice, encode_with_envelope, verify_checksum, DecodedEnvelope,
+    Decoder, Encoder,
 };
 
 // Re-export serde traits for user convenience
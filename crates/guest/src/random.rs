@@ -0,0 +1,99 @@
+//! getrandom backends drawing from the host
+//!
+//! Crates such as `uuid` or `ed25519-dalek` get their randomness from
+//! `getrandom`, which has no source on `wasm32-unknown-unknown`. With the
+//! `getrandom-shim` feature the guest registers custom backends for
+//! getrandom 0.2 and 0.3 that ask the host through the
+//! `env.__aingle_random_bytes` import, so the host decides whether guests get
+//! real or seeded randomness.
+//!
+//! getrandom 0.3 only uses the custom backend when the guest is built with
+//! `--cfg getrandom_backend="custom"`, for instance in `.cargo/config.toml`:
+//!
+//! ```toml
+//! [target.wasm32-unknown-unknown]
+//! rustflags = ['--cfg', 'getrandom_backend="custom"']
+//! ```
+//!
+//! Requests fail with an error instead of blocking when the host provides no
+//! randomness.
+
+use aingle_wasmer_common::RandomStatus;
+
+#[cfg(target_arch = "wasm32")]
+extern "C" {
+    /// Fill guest memory with random bytes
+    fn __aingle_random_bytes(ptr: i32, len: i32) -> i32;
+}
+
+/// Fill `dest` with random bytes from the host
+#[cfg(target_arch = "wasm32")]
+pub fn host_random_bytes(dest: &mut [u8]) -> Result<(), RandomStatus> {
+    if dest.is_empty() {
+        return Ok(());
+    }
+    let code = unsafe { __aingle_random_bytes(dest.as_mut_ptr() as i32, dest.len() as i32) };
+    match RandomStatus::from_code(code) {
+        Some(RandomStatus::Ok) => Ok(()),
+        Some(status) => Err(status),
+        None => Err(RandomStatus::Unavailable),
+    }
+}
+
+/// Natively there is no host to ask
+#[cfg(not(target_arch = "wasm32"))]
+pub fn host_random_bytes(dest: &mut [u8]) -> Result<(), RandomStatus> {
+    if dest.is_empty() {
+        return Ok(());
+    }
+    Err(RandomStatus::Unavailable)
+}
+
+/// getrandom 0.2 backend
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+fn getrandom_02(dest: &mut [u8]) -> Result<(), getrandom02::Error> {
+    host_random_bytes(dest).map_err(|status| {
+        let code = getrandom02::Error::CUSTOM_START + status as u32;
+        core::num::NonZeroU32::new(code)
+            .expect("custom codes are non-zero")
+            .into()
+    })
+}
+
+#[cfg(target_arch = "wasm32")]
+getrandom02::register_custom_getrandom!(getrandom_02);
+
+/// getrandom 0.3 backend
+///
+/// # Safety
+///
+/// `dest` must be valid for writes of `len` bytes.
+#[cfg(target_arch = "wasm32")]
+#[no_mangle]
+unsafe extern "Rust" fn __getrandom_v03_custom(
+    dest: *mut u8,
+    len: usize,
+) -> Result<(), getrandom::Error> {
+    let dest = core::slice::from_raw_parts_mut(dest, len);
+    host_random_bytes(dest).map_err(|status| getrandom::Error::new_custom(status as u16))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_without_host() {
+        assert_eq!(host_random_bytes(&mut []), Ok(()));
+        assert_eq!(
+            host_random_bytes(&mut [0; 16]),
+            Err(RandomStatus::Unavailable)
+        );
+
+        let error = getrandom_02(&mut [0; 16]).unwrap_err();
+        assert_eq!(
+            error.code().get(),
+            getrandom02::Error::CUSTOM_START + RandomStatus::Unavailable as u32
+        );
+    }
+}
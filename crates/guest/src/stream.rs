@@ -0,0 +1,380 @@
+//! Reassembly of inputs streamed from the host in chunks
+//!
+//! The host opens a stream with `__aingle_stream_begin`, copies each chunk
+//! into a small arena buffer and hands it over with `__aingle_stream_write`,
+//! then calls the target function with `(handle, total_len)`. Chunks are kept
+//! separately, so no contiguous region of the full size is ever required.
+//!
+//! Large results go the other way through an [`OutputStream`], which hands
+//! chunks to the host's `__aingle_output_chunk` import as they fill up.
+
+use crate::arena::ArenaRegion;
+use crate::memory::{encode_in_arena, error_text, return_err};
+use aingle_wasmer_common::{StreamStatus, WasmError, WasmResult, WasmSlice};
+use alloc::format;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+struct Stream {
+    total_len: usize,
+    received: usize,
+    chunks: Vec<Vec<u8>>,
+}
+
+thread_local! {
+    /// Open streams, indexed by handle - 1
+    static STREAMS: RefCell<Vec<Option<Stream>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Open a stream expecting `total_len` bytes, returning its handle
+pub fn stream_begin(total_len: usize) -> u32 {
+    STREAMS.with(|streams| {
+        let mut streams = streams.borrow_mut();
+        let stream = Stream {
+            total_len,
+            received: 0,
+            chunks: Vec::new(),
+        };
+
+        match streams.iter().position(Option::is_none) {
+            Some(index) => {
+                streams[index] = Some(stream);
+                index as u32 + 1
+            }
+            None => {
+                streams.push(Some(stream));
+                streams.len() as u32
+            }
+        }
+    })
+}
+
+/// Append a chunk to an open stream
+pub fn stream_write(handle: u32, chunk: &[u8]) -> StreamStatus {
+    STREAMS.with(|streams| {
+        let mut streams = streams.borrow_mut();
+        let Some(stream) = slot(&mut streams, handle).and_then(Option::as_mut) else {
+            return StreamStatus::OutOfOrder;
+        };
+
+        if stream.received + chunk.len() > stream.total_len {
+            return StreamStatus::OverLength;
+        }
+
+        stream.received += chunk.len();
+        stream.chunks.push(chunk.to_vec());
+        StreamStatus::Ok
+    })
+}
+
+fn slot(streams: &mut [Option<Stream>], handle: u32) -> Option<&mut Option<Stream>> {
+    let index = (handle as usize).checked_sub(1)?;
+    streams.get_mut(index)
+}
+
+/// A fully received stream
+pub struct StreamReader {
+    total_len: usize,
+    chunks: Vec<Vec<u8>>,
+}
+
+impl StreamReader {
+    /// Take ownership of the completed stream behind `handle`
+    ///
+    /// Fails if the handle is unknown or not all announced bytes arrived.
+    pub fn open(handle: u32) -> Result<Self, WasmError> {
+        STREAMS.with(|streams| {
+            let mut streams = streams.borrow_mut();
+            let slot = slot(&mut streams, handle)
+                .filter(|slot| slot.is_some())
+                .ok_or_else(|| WasmError::guest(format!("unknown stream handle {}", handle)))?;
+
+            let stream = slot.as_ref().expect("checked above");
+            if stream.received != stream.total_len {
+                return Err(WasmError::guest(format!(
+                    "stream {} incomplete: received {} of {} bytes",
+                    handle, stream.received, stream.total_len
+                )));
+            }
+
+            let stream = slot.take().expect("checked above");
+            Ok(Self {
+                total_len: stream.total_len,
+                chunks: stream.chunks,
+            })
+        })
+    }
+
+    /// Total length of the stream in bytes
+    pub fn len(&self) -> usize {
+        self.total_len
+    }
+
+    /// Whether the stream is empty
+    pub fn is_empty(&self) -> bool {
+        self.total_len == 0
+    }
+
+    /// Iterate over the chunks in the order they were received
+    pub fn chunks(&self) -> impl Iterator<Item = &[u8]> {
+        self.chunks.iter().map(Vec::as_slice)
+    }
+
+    /// Reassemble the stream into one contiguous buffer
+    pub fn into_vec(self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.total_len);
+        for chunk in self.chunks {
+            out.extend_from_slice(&chunk);
+        }
+        out
+    }
+}
+
+/// Open a stream (called by the host)
+#[no_mangle]
+pub extern "C" fn __aingle_stream_begin(total_len: i32) -> i32 {
+    if total_len < 0 {
+        return 0;
+    }
+    stream_begin(total_len as usize) as i32
+}
+
+/// Receive a chunk of an open stream (called by the host)
+#[no_mangle]
+pub extern "C" fn __aingle_stream_write(handle: i32, ptr: i32, len: i32) -> i32 {
+    match crate::read_bytes(ptr as u32, len as u32) {
+        Ok(chunk) => stream_write(handle as u32, chunk) as i32,
+        Err(_) => StreamStatus::OutOfBounds as i32,
+    }
+}
+
+/// Default size of the chunks an [`OutputStream`] hands to the host: 32 KiB
+pub const DEFAULT_OUTPUT_CHUNK_LEN: usize = 32 * 1024;
+
+#[cfg(target_arch = "wasm32")]
+extern "C" {
+    /// Append a chunk to the output of the current call
+    fn __aingle_output_chunk(ptr: i32, len: i32) -> i32;
+}
+
+/// Hand a chunk to the host
+#[cfg(target_arch = "wasm32")]
+fn output_chunk(chunk: &[u8]) -> StreamStatus {
+    let code = unsafe { __aingle_output_chunk(chunk.as_ptr() as i32, chunk.len() as i32) };
+    StreamStatus::from_code(code).unwrap_or(StreamStatus::OutOfOrder)
+}
+
+/// Natively there is no host collecting output
+#[cfg(not(target_arch = "wasm32"))]
+fn output_chunk(_chunk: &[u8]) -> StreamStatus {
+    StreamStatus::OutOfOrder
+}
+
+/// A result streamed to the host in chunks
+///
+/// Writes are buffered up to the chunk length and handed to the host as
+/// each chunk fills, so the guest holds at most one chunk of the result.
+/// Return [`OutputStream::finish`] from the function instead of
+/// [`return_ok`](crate::return_ok); the host then returns the concatenated
+/// chunks as the result of the call.
+pub struct OutputStream {
+    buffer: Vec<u8>,
+    chunk_len: usize,
+    written: u64,
+    sink: fn(&[u8]) -> StreamStatus,
+}
+
+impl OutputStream {
+    /// Create a stream handing [`DEFAULT_OUTPUT_CHUNK_LEN`] bytes at a time
+    /// to the host
+    pub fn new() -> Self {
+        Self::with_chunk_len(DEFAULT_OUTPUT_CHUNK_LEN)
+    }
+
+    /// Create a stream handing `chunk_len` bytes at a time to the host
+    pub fn with_chunk_len(chunk_len: usize) -> Self {
+        Self::with_sink(chunk_len, output_chunk)
+    }
+
+    fn with_sink(chunk_len: usize, sink: fn(&[u8]) -> StreamStatus) -> Self {
+        let chunk_len = chunk_len.max(1);
+        Self {
+            buffer: Vec::with_capacity(chunk_len),
+            chunk_len,
+            written: 0,
+            sink,
+        }
+    }
+
+    /// Bytes handed to the host so far
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Append `bytes` to the result
+    ///
+    /// Fails if the host rejects a chunk, for instance because the result
+    /// exceeds its output limit.
+    pub fn write(&mut self, mut bytes: &[u8]) -> Result<(), WasmError> {
+        while !bytes.is_empty() {
+            // Whole chunks bypass the buffer
+            if self.buffer.is_empty() && bytes.len() >= self.chunk_len {
+                let (chunk, rest) = bytes.split_at(self.chunk_len);
+                self.send(chunk)?;
+                bytes = rest;
+                continue;
+            }
+
+            let take = (self.chunk_len - self.buffer.len()).min(bytes.len());
+            self.buffer.extend_from_slice(&bytes[..take]);
+            bytes = &bytes[take..];
+            if self.buffer.len() == self.chunk_len {
+                self.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Hand the buffered bytes to the host
+    pub fn flush(&mut self) -> Result<(), WasmError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let mut buffer = core::mem::take(&mut self.buffer);
+        let result = self.send(&buffer);
+        // A rejected chunk is kept, so finishing the stream fails as well
+        if result.is_ok() {
+            buffer.clear();
+        }
+        self.buffer = buffer;
+        result
+    }
+
+    fn send(&mut self, chunk: &[u8]) -> Result<(), WasmError> {
+        match (self.sink)(chunk) {
+            StreamStatus::Ok => {
+                self.written += chunk.len() as u64;
+                Ok(())
+            }
+            status => Err(WasmError::guest(format!(
+                "output chunk rejected: {}",
+                status.description()
+            ))),
+        }
+    }
+
+    /// Flush the stream and encode the marker holding its total length
+    ///
+    /// The host reads the result as a marker because chunks were sent, so
+    /// an empty stream sends an empty one.
+    fn marker(&mut self) -> Result<&'static [u8], WasmError> {
+        self.flush()?;
+        if self.written == 0 {
+            self.send(&[])?;
+        }
+        encode_in_arena(&self.written.to_le_bytes(), 0, ArenaRegion::Output)
+    }
+
+    /// Flush the stream and return the result to the host
+    pub fn finish(mut self) -> u64 {
+        match self.marker() {
+            Ok(envelope) => WasmResult::ok(WasmSlice::new(
+                envelope.as_ptr() as u32,
+                envelope.len() as u32,
+            ))
+            .into_raw(),
+            Err(e) => return_err(error_text(&e).as_ref()),
+        }
+    }
+}
+
+impl Default for OutputStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_reassembly() {
+        let handle = stream_begin(6);
+        assert_eq!(stream_write(handle, b"abc"), StreamStatus::Ok);
+        assert_eq!(stream_write(handle, b"def"), StreamStatus::Ok);
+
+        let reader = StreamReader::open(handle).unwrap();
+        assert_eq!(reader.chunks().count(), 2);
+        assert_eq!(reader.into_vec(), b"abcdef");
+
+        // Consumed streams reject further writes
+        assert_eq!(stream_write(handle, b"x"), StreamStatus::OutOfOrder);
+    }
+
+    #[test]
+    fn test_stream_errors() {
+        assert_eq!(stream_write(0, b"x"), StreamStatus::OutOfOrder);
+        assert_eq!(stream_write(999, b"x"), StreamStatus::OutOfOrder);
+
+        let handle = stream_begin(4);
+        assert_eq!(stream_write(handle, b"abc"), StreamStatus::Ok);
+        assert_eq!(stream_write(handle, b"de"), StreamStatus::OverLength);
+        assert!(StreamReader::open(handle).is_err());
+
+        assert_eq!(stream_write(handle, b"d"), StreamStatus::Ok);
+        assert_eq!(StreamReader::open(handle).unwrap().into_vec(), b"abcd");
+        assert!(StreamReader::open(handle).is_err());
+    }
+
+    thread_local! {
+        static CHUNKS: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn collect(chunk: &[u8]) -> StreamStatus {
+        CHUNKS.with(|chunks| chunks.borrow_mut().push(chunk.to_vec()));
+        StreamStatus::Ok
+    }
+
+    fn reject(_chunk: &[u8]) -> StreamStatus {
+        StreamStatus::OverLength
+    }
+
+    #[test]
+    fn test_output_stream_chunks() {
+        let mut stream = OutputStream::with_sink(4, collect);
+        stream.write(b"ab").unwrap();
+        stream.write(b"cdefghijk").unwrap();
+        stream.write(b"").unwrap();
+        stream.write(b"lm").unwrap();
+        assert_eq!(stream.written(), 12);
+
+        let marker = stream.marker().unwrap();
+        let chunks = CHUNKS.with(|chunks| chunks.take());
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 4));
+        assert_eq!(chunks.concat(), b"abcdefghijklm");
+
+        let envelope = crate::decode_envelope(marker).unwrap();
+        assert!(envelope.header.flags_set().is_empty());
+        assert_eq!(envelope.payload, 13u64.to_le_bytes());
+
+        // An empty stream still tells the host it streamed
+        let marker = OutputStream::with_sink(4, collect).marker().unwrap();
+        assert_eq!(CHUNKS.with(|chunks| chunks.take()), [b""]);
+        let envelope = crate::decode_envelope(marker).unwrap();
+        assert_eq!(envelope.payload, 0u64.to_le_bytes());
+    }
+
+    #[test]
+    fn test_output_stream_rejected() {
+        let mut stream = OutputStream::with_sink(4, reject);
+        stream.write(b"abc").unwrap();
+        assert!(stream.write(b"de").is_err());
+        assert!(stream.marker().is_err());
+        assert_eq!(stream.written(), 0);
+
+        // Natively no host collects output
+        let mut stream = OutputStream::with_chunk_len(1);
+        assert!(stream.write(b"x").is_err());
+    }
+}
@@ -0,0 +1,23 @@
+//! Steps the guest reports in the traces of explained calls
+//!
+//! Hosts explaining a call, with `CallOptions::explain` on
+//! `aingle_wasmer_host`, record the messages passed to [`trace_step`] among
+//! their own steps, through the `env.__aingle_trace` import. Other calls
+//! ignore them. Guests calling [`trace_step`] import the function, so hosts
+//! other than `aingle_wasmer_host` must provide it.
+
+#[cfg(target_arch = "wasm32")]
+extern "C" {
+    /// Record a step in the trace of the current call
+    fn __aingle_trace(ptr: i32, len: i32);
+}
+
+/// Report `message` as a step of the current call to the host
+#[cfg(target_arch = "wasm32")]
+pub fn trace_step(message: &str) {
+    unsafe { __aingle_trace(message.as_ptr() as i32, message.len() as i32) }
+}
+
+/// Natively there is no host tracing the call
+#[cfg(not(target_arch = "wasm32"))]
+pub fn trace_step(_message: &str) {}
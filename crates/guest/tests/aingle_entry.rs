@@ -0,0 +1,40 @@
+//! Compile-time checks of `#[aingle_entry]`
+//!
+//! Refresh the expected errors in `tests/ui/aingle_entry` with
+//! `TRYBUILD=overwrite`.
+
+use aingle_wasmer_guest::prelude::*;
+use aingle_wasmer_guest::{GuestPtr, GuestReturn, Len};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Greeting {
+    name: String,
+}
+
+#[aingle_entry]
+fn greet(input: Greeting) -> Result<String, WasmError> {
+    Ok(format!("hello {}", input.name))
+}
+
+#[aingle_entry]
+fn version() -> u32 {
+    1
+}
+
+// Common errors convert into `WasmError`, so `?` works without `map_err`
+#[aingle_entry]
+fn parse_port(input: String) -> Result<u16, WasmError> {
+    Ok(input.trim().parse::<u16>()?)
+}
+
+#[test]
+fn test_aingle_entry_exports() {
+    let exports: [extern "C" fn(GuestPtr, Len) -> GuestReturn; 3] = [greet, version, parse_port];
+    assert_eq!(exports.len(), 3);
+}
+
+#[test]
+fn test_aingle_entry_rejections() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/aingle_entry/*.rs");
+}
@@ -0,0 +1,8 @@
+use aingle_wasmer_guest::prelude::*;
+
+#[aingle_entry]
+async fn later() -> u32 {
+    1
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use aingle_wasmer_guest::prelude::*;
+
+#[aingle_entry]
+fn identity<T: Serialize>(input: T) -> T {
+    input
+}
+
+fn main() {}
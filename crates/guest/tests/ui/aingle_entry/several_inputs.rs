@@ -0,0 +1,8 @@
+use aingle_wasmer_guest::prelude::*;
+
+#[aingle_entry]
+fn add(a: u32, b: u32) -> u32 {
+    a + b
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use aingle_wasmer_guest::WasmSafe;
+
+#[derive(WasmSafe)]
+#[repr(C)]
+struct Owned {
+    len: u32,
+    bytes: Vec<u8>,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use aingle_wasmer_guest::WasmSafe;
+
+#[derive(WasmSafe)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+fn main() {}
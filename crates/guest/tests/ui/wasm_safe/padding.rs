@@ -0,0 +1,10 @@
+use aingle_wasmer_guest::WasmSafe;
+
+#[derive(WasmSafe)]
+#[repr(C)]
+struct Padded {
+    tag: u8,
+    len: u32,
+}
+
+fn main() {}
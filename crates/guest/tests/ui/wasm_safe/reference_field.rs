@@ -0,0 +1,9 @@
+use aingle_wasmer_guest::WasmSafe;
+
+#[derive(WasmSafe)]
+#[repr(C)]
+struct Borrowed<'a> {
+    bytes: &'a [u8],
+}
+
+fn main() {}
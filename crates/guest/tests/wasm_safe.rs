@@ -0,0 +1,41 @@
+//! Compile-time checks of `#[derive(WasmSafe)]`
+//!
+//! Refresh the expected errors in `tests/ui/wasm_safe` with
+//! `TRYBUILD=overwrite`.
+
+use aingle_wasmer_guest::{WasmSafe, WasmSlice};
+
+#[derive(Clone, Copy, WasmSafe)]
+#[repr(C)]
+struct Header {
+    slice: WasmSlice,
+    flags: u32,
+    tag: [u8; 4],
+}
+
+#[derive(Clone, Copy, WasmSafe)]
+#[repr(transparent)]
+struct Id(u64);
+
+#[derive(Clone, Copy, WasmSafe)]
+#[repr(C)]
+struct Pair<T> {
+    first: T,
+    second: T,
+}
+
+fn assert_wasm_safe<T: aingle_wasmer_guest::prelude::WasmSafe>() {}
+
+#[test]
+fn test_derive_wasm_safe() {
+    assert_wasm_safe::<Header>();
+    assert_wasm_safe::<Id>();
+    assert_wasm_safe::<Pair<Id>>();
+    assert_wasm_safe::<[Header; 2]>();
+}
+
+#[test]
+fn test_derive_wasm_safe_rejections() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/wasm_safe/*.rs");
+}
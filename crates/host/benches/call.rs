@@ -0,0 +1,130 @@
+//! Benchmark for guest calls and module caching
+//!
+//! Runs on `fixtures/echo.wat`, so no guest has to be built first.
+
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+mod sys {
+    use aingle_wasmer_host::{call, ExternIO, ImportBuilder, ModuleCache};
+    use criterion::{BenchmarkId, Criterion, Throughput};
+    use std::sync::Arc;
+    use wasmer::{imports, AsStoreMut, Instance, Module, Store};
+
+    fn echo_wasm() -> Vec<u8> {
+        wat::parse_str(include_str!("fixtures/echo.wat")).unwrap()
+    }
+
+    /// Full round trip through `guest::call`: ABI probe, input allocation and
+    /// copy, the guest call, and unwrapping the enveloped result
+    pub fn bench_call_roundtrip(c: &mut Criterion) {
+        let mut store = Store::default();
+        let module = Module::new(&store, echo_wasm()).unwrap();
+        let instance = Arc::new(Instance::new(&mut store, &module, &imports! {}).unwrap());
+
+        let mut group = c.benchmark_group("call_roundtrip");
+
+        for size in [64, 64 * 1024, 4 * 1024 * 1024] {
+            let envelope = ExternIO::new(vec![0xa5u8; size]).to_envelope(0).unwrap();
+            let input = ExternIO::from(envelope);
+            group.throughput(Throughput::Bytes(size as u64));
+            group.bench_with_input(BenchmarkId::from_parameter(size), &input, |b, input| {
+                b.iter(|| {
+                    let output = call(
+                        &mut store.as_store_mut(),
+                        Arc::clone(&instance),
+                        "echo",
+                        input,
+                    )
+                    .unwrap();
+                    assert_eq!(output.len(), size);
+                })
+            });
+        }
+
+        group.finish();
+    }
+
+    /// `Env::move_bytes_to_guest` copying payloads in one go, in 64 KiB
+    /// chunks, and to the scratch buffer instead of a fresh allocation
+    pub fn bench_move_bytes(c: &mut Criterion) {
+        let mut group = c.benchmark_group("move_bytes_to_guest");
+
+        for (mode, chunk, scratch) in [
+            ("whole", None, None),
+            ("chunked", Some(64 * 1024), None),
+            ("scratch", None, Some(4 * 1024 * 1024)),
+        ] {
+            let mut store = Store::default();
+            let module = Module::new(&store, echo_wasm()).unwrap();
+            let mut builder = ImportBuilder::new(&mut store);
+            if let Some(bytes) = chunk {
+                builder = builder.write_chunk(bytes);
+            }
+            if let Some(bytes) = scratch {
+                builder = builder.scratch(bytes);
+            }
+            let function_env = builder.env().clone();
+            builder.instantiate(&mut store, &module).unwrap();
+            let env = function_env.as_ref(&store).clone();
+
+            for size in [4 * 1024, 4 * 1024 * 1024] {
+                let bytes = vec![0xa5u8; size];
+                group.throughput(Throughput::Bytes(size as u64));
+                group.bench_with_input(BenchmarkId::new(mode, size), &bytes, |b, bytes| {
+                    b.iter(|| {
+                        env.move_bytes_to_guest(&mut store.as_store_mut(), bytes)
+                            .unwrap()
+                    })
+                });
+            }
+        }
+
+        group.finish();
+    }
+
+    /// Getting a module from the in-memory cache, from the filesystem cache
+    /// and by compiling it
+    pub fn bench_module_cache(c: &mut Criterion) {
+        let wasm = echo_wasm();
+        let key = [7u8; 32];
+        let mut group = c.benchmark_group("module_cache");
+
+        let cache = ModuleCache::new(None);
+        cache.get(key, &wasm).unwrap();
+        group.bench_function("memory_hit", |b| b.iter(|| cache.get(key, &wasm).unwrap()));
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ModuleCache::new(Some(dir.path().to_path_buf()));
+        cache.get(key, &wasm).unwrap();
+        group.bench_function("filesystem_hit", |b| {
+            b.iter(|| {
+                cache.clear();
+                cache.get(key, &wasm).unwrap()
+            })
+        });
+
+        let cache = ModuleCache::new(None);
+        group.bench_function("compile", |b| {
+            b.iter(|| {
+                cache.clear();
+                cache.get(key, &wasm).unwrap()
+            })
+        });
+
+        group.finish();
+    }
+}
+
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+criterion::criterion_group!(
+    benches,
+    sys::bench_call_roundtrip,
+    sys::bench_move_bytes,
+    sys::bench_module_cache
+);
+
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+criterion::criterion_main!(benches);
+
+/// Guests only run on the sys backends here
+#[cfg(not(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod")))]
+fn main() {}
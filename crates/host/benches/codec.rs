@@ -0,0 +1,98 @@
+//! Benchmark for envelopes and typed payloads on the host
+
+use aingle_wasmer_codec::{decode_envelope_with, encode_envelope_with};
+use aingle_wasmer_common::{ChecksumKind, EnvelopeHeader};
+use aingle_wasmer_host::ExternIO;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde::{Deserialize, Serialize};
+
+/// Shaped like a zome call input: an author, a short action and a batch of
+/// entries with binary content
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Record {
+    author: String,
+    action: String,
+    timestamp: i64,
+    entries: Vec<Entry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Entry {
+    hash: [u8; 32],
+    tags: Vec<String>,
+    #[serde(with = "serde_bytes")]
+    content: Vec<u8>,
+}
+
+fn record() -> Record {
+    Record {
+        author: "uhCAkq3yXVaMcTQ6tRu9m-VtM8i7GkhdU3dVg3pDbrs".to_string(),
+        action: "create_entry".to_string(),
+        timestamp: 1_700_000_000_000_000,
+        entries: (0..16u8)
+            .map(|i| Entry {
+                hash: [i; 32],
+                tags: vec![format!("tag-{}", i), "shared".to_string()],
+                content: vec![i; 512],
+            })
+            .collect(),
+    }
+}
+
+/// Enveloping and unwrapping payloads, with the default CRC32 and CRC32C
+fn bench_envelope(c: &mut Criterion) {
+    let mut group = c.benchmark_group("envelope");
+
+    for (checksum, kind) in [
+        ("crc32", ChecksumKind::Crc32),
+        ("crc32c", ChecksumKind::Crc32c),
+    ] {
+        for size in [64, 64 * 1024, 4 * 1024 * 1024] {
+            let payload = vec![0xa5u8; size];
+            let mut buffer = vec![0u8; EnvelopeHeader::SIZE + size];
+            let len = encode_envelope_with(kind, &payload, 0, &mut buffer).unwrap();
+            group.throughput(Throughput::Bytes(size as u64));
+
+            group.bench_with_input(
+                BenchmarkId::new(format!("encode_{}", checksum), size),
+                &payload,
+                |b, payload| {
+                    b.iter(|| encode_envelope_with(kind, payload, 0, &mut buffer).unwrap())
+                },
+            );
+
+            let envelope = buffer[..len].to_vec();
+            group.bench_with_input(
+                BenchmarkId::new(format!("decode_{}", checksum), size),
+                &envelope,
+                |b, envelope| {
+                    b.iter(|| decode_envelope_with(kind, envelope).unwrap().payload.len())
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+/// Serializing a representative struct into an ExternIO and back
+fn bench_extern_io_typed(c: &mut Criterion) {
+    let record = record();
+    let io = ExternIO::encode(&record).unwrap();
+    let compact = ExternIO::encode_compact(&record).unwrap();
+
+    let mut group = c.benchmark_group("extern_io_typed");
+    group.throughput(Throughput::Bytes(io.len() as u64));
+    group.bench_function("encode", |b| b.iter(|| ExternIO::encode(&record).unwrap()));
+    group.bench_function("decode", |b| b.iter(|| io.decode::<Record>().unwrap()));
+    group.bench_function("encode_compact", |b| {
+        b.iter(|| ExternIO::encode_compact(&record).unwrap())
+    });
+    group.bench_function("decode_compact", |b| {
+        b.iter(|| compact.decode::<Record>().unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_envelope, bench_extern_io_typed);
+criterion_main!(benches);
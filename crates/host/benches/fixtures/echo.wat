@@ -0,0 +1,21 @@
+;; Guest the call benchmarks run on: inputs are written at a fixed offset
+;; with room for 4 MB payloads and returned as they are, and the ABI
+;; handshake advertises envelopes so enveloped results are unwrapped. The
+;; scratch buffer host writes may reuse is the same region.
+(module
+    (memory (export "memory") 80)
+
+    (func (export "__hc__allocate_1") (param i32) (result i32)
+        (i32.const 1024))
+
+    (func (export "__aingle_guest_scratch") (param i32) (result i32)
+        (i32.const 1024))
+
+    ;; Protocol 1, crate 0.0, envelope support
+    (func (export "__aingle_guest_abi") (result i64)
+        (i64.const 0x0000000100000001))
+
+    (func (export "echo") (param $ptr i32) (param $len i32) (result i64)
+        (i64.or
+            (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+            (i64.extend_i32_u (local.get $len)))))
@@ -1,15 +1,121 @@
 //! Benchmark for WASM instance operations
 
-use criterion::{criterion_group, criterion_main, Criterion};
-
-fn bench_placeholder(c: &mut Criterion) {
-    c.bench_function("placeholder", |b| {
-        b.iter(|| {
-            // Placeholder benchmark
-            std::hint::black_box(1 + 1)
-        })
+use aingle_wasmer_codec::encode_with_envelope;
+use aingle_wasmer_common::ChecksumKind;
+use aingle_wasmer_host::backend::{write_envelope_at, BackendMemory};
+use aingle_wasmer_host::{ExternIO, HostError};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+/// Plain byte buffer standing in for guest memory
+struct VecMemory(Vec<u8>);
+
+impl BackendMemory for VecMemory {
+    fn memory_size(&mut self) -> Result<u64, HostError> {
+        Ok(self.0.len() as u64)
+    }
+
+    fn read_memory(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), HostError> {
+        let start = offset as usize;
+        buf.copy_from_slice(&self.0[start..start + buf.len()]);
+        Ok(())
+    }
+
+    fn write_memory(&mut self, offset: u64, data: &[u8]) -> Result<(), HostError> {
+        let start = offset as usize;
+        self.0[start..start + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    fn allocate(&mut self, _len: u32) -> Result<u32, HostError> {
+        Ok(0)
+    }
+}
+
+/// Writing call input into guest memory: buffering the envelope on the host
+/// first versus writing header and payload directly
+fn bench_input_copy(c: &mut Criterion) {
+    let mut group = c.benchmark_group("input_copy");
+
+    for size in [64 * 1024, 10 * 1024 * 1024] {
+        let payload = vec![0xa5u8; size];
+        let mut memory = VecMemory(vec![0u8; size + 2048]);
+        group.throughput(Throughput::Bytes(size as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("buffered", size),
+            &payload,
+            |b, payload| {
+                b.iter(|| {
+                    let mut buffer = vec![0u8; payload.len() + 64];
+                    let len = encode_with_envelope(payload, 0, &mut buffer).unwrap();
+                    memory.write_memory(1024, &buffer[..len]).unwrap();
+                })
+            },
+        );
+
+        group.bench_with_input(BenchmarkId::new("direct", size), &payload, |b, payload| {
+            b.iter(|| {
+                write_envelope_at(&mut memory, 1024, payload, 0, ChecksumKind::Crc32).unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+/// Cloning a payload, as when fanning one input out to several cells
+fn bench_extern_io_clone(c: &mut Criterion) {
+    let mut group = c.benchmark_group("extern_io_clone");
+
+    for size in [1024, 1024 * 1024, 10 * 1024 * 1024] {
+        let io = ExternIO::new(vec![0xa5u8; size]);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &io, |b, io| {
+            b.iter(|| io.clone())
+        });
+    }
+
+    group.finish();
+}
+
+/// Round trip of a large input through a guest echoing it back
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+fn bench_call_raw(c: &mut Criterion) {
+    use aingle_wasmer_host::{EngineConfig, WasmEngine, WasmInstance};
+
+    let wasm = wat::parse_str(
+        r#"
+        (module
+            (memory (export "memory") 256)
+            (func (export "echo") (param $ptr i32) (param $len i32) (result i64)
+                (i64.or
+                    (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+                    (i64.extend_i32_u (local.get $len)))))
+        "#,
+    )
+    .unwrap();
+
+    let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+    let module = engine.compile(&wasm).unwrap();
+    let mut instance = WasmInstance::new(&engine, &module).unwrap();
+    let payload = vec![0xa5u8; 10 * 1024 * 1024];
+
+    let mut group = c.benchmark_group("call_raw");
+    group.throughput(Throughput::Bytes(payload.len() as u64));
+    group.bench_function("echo_10mb", |b| {
+        b.iter(|| instance.call_raw("echo", &payload).unwrap())
     });
+    group.finish();
 }
 
-criterion_group!(benches, bench_placeholder);
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+criterion_group!(
+    benches,
+    bench_input_copy,
+    bench_extern_io_clone,
+    bench_call_raw
+);
+
+#[cfg(not(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod")))]
+criterion_group!(benches, bench_input_copy, bench_extern_io_clone);
+
 criterion_main!(benches);
@@ -0,0 +1,45 @@
+//! Generates the C header of the `ffi` module
+
+fn main() {
+    #[cfg(feature = "cdylib")]
+    ffi::generate();
+}
+
+#[cfg(feature = "cdylib")]
+mod ffi {
+    use std::path::PathBuf;
+
+    pub fn generate() {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+
+        let out = PathBuf::from(std::env::var_os("OUT_DIR").unwrap()).join("aingle_wasmer_host.h");
+        // The module docs state the ownership rules, so they open the header
+        let docs: String = std::fs::read_to_string("src/ffi.rs")
+            .expect("failed to read src/ffi.rs")
+            .lines()
+            .map_while(|line| line.strip_prefix("//!"))
+            .map(|line| format!(" *{}\n", line))
+            .collect();
+        let config = cbindgen::Config {
+            language: cbindgen::Language::C,
+            cpp_compat: true,
+            include_guard: Some("AINGLE_WASMER_HOST_H".to_string()),
+            header: Some(format!(
+                "/*\n * Generated from aingle_wasmer_host's ffi module, do not edit\n *\n{} */",
+                docs
+            )),
+            usize_is_size_t: true,
+            enumeration: cbindgen::EnumConfig {
+                rename_variants: cbindgen::RenameRule::QualifiedScreamingSnakeCase,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src("src/ffi.rs")
+            .generate()
+            .expect("failed to generate the C header")
+            .write_to_file(out);
+    }
+}
@@ -0,0 +1,49 @@
+//! Guest ABI handshake
+//!
+//! Guests report their protocol version and capabilities through the
+//! [`GUEST_ABI_EXPORT`] export; [`Env::guest_abi`](crate::Env::guest_abi)
+//! probes it once and caches the answer, and [`check_protocol`] decides
+//! whether the host accepts the guest's protocol version.
+
+use crate::{check_protocol, HostError};
+use aingle_wasmer_common::{GuestAbi, VersionPolicy, GUEST_ABI_EXPORT};
+use wasmer::{Instance, StoreMut};
+
+/// Probing of the [`GuestAbi`] a guest reports
+pub trait ProbeGuestAbi {
+    /// Ask the guest for its ABI
+    ///
+    /// Guests without the export, or whose export fails, are reported as
+    /// [`GuestAbi::legacy`].
+    fn probe(instance: &Instance, store: &mut StoreMut<'_>) -> GuestAbi;
+
+    /// Ask the guest for its ABI, failing with
+    /// [`HostError::ProtocolMismatch`] unless `policy` accepts its protocol
+    /// version
+    fn probe_checked(
+        instance: &Instance,
+        store: &mut StoreMut<'_>,
+        policy: VersionPolicy,
+    ) -> Result<GuestAbi, HostError> {
+        check_protocol(Self::probe(instance, store), policy)
+    }
+}
+
+impl ProbeGuestAbi for GuestAbi {
+    fn probe(instance: &Instance, store: &mut StoreMut<'_>) -> GuestAbi {
+        let Ok(export) = instance
+            .exports
+            .get_typed_function::<(), i64>(store, GUEST_ABI_EXPORT)
+        else {
+            return GuestAbi::legacy();
+        };
+
+        match export.call(store) {
+            Ok(raw) => GuestAbi::from_raw(raw as u64),
+            Err(e) => {
+                tracing::debug!("Failed to probe guest ABI: {}", e);
+                GuestAbi::legacy()
+            }
+        }
+    }
+}
@@ -0,0 +1,1043 @@
+//! Execution backend abstraction
+//!
+//! The engine, instance and environment types talk to the underlying WASM
+//! runtime exclusively through the traits in this module, so alternative
+//! runtimes can be slotted in without touching the rest of the host crate.
+//! Wasmer is the default implementation behind the `wasmer_sys_*` features,
+//! and the browser's engine behind `wasmer_js`.
+
+use crate::{HostError, HostFunctionRegistry};
+use aingle_wasmer_codec::{compute_checksum_with, decode_envelope};
+use aingle_wasmer_common::{
+    decode_empty, is_unit, ChecksumKind, EnvelopeHeader, StreamStatus, WasmDecode, WasmEncode,
+    WasmRef, WasmSlice, WasmSlice64,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::any::Any;
+use std::cell::RefCell;
+use std::sync::Arc;
+
+/// A WASM runtime capable of compiling and instantiating modules
+pub trait WasmBackend: Send + Sync {
+    /// Short name identifying the backend (e.g. `"wasmer"`)
+    fn name(&self) -> &'static str;
+
+    /// Compile WASM bytes into a backend module
+    fn compile(&self, wasm: &[u8]) -> Result<Arc<dyn BackendModule>, HostError>;
+
+    /// Instantiate a module previously compiled by this backend
+    fn instantiate(
+        &self,
+        module: &dyn BackendModule,
+        imports: &BackendImports,
+    ) -> Result<Box<dyn BackendInstance>, HostError>;
+
+    /// Access the concrete backend for downcasting
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// A compiled module owned by a [`WasmBackend`]
+pub trait BackendModule: Send + Sync {
+    /// All exported functions with their signatures
+    fn function_exports(&self) -> Vec<ExternInfo>;
+
+    /// Exported guest functions, excluding allocator and other infrastructure
+    fn externs(&self) -> Vec<ExternInfo> {
+        self.function_exports()
+            .into_iter()
+            .filter(|info| !is_infrastructure_export(&info.name))
+            .collect()
+    }
+
+    /// Whether the guest follows the 64-bit call convention of memory64
+    /// guests, recognized by exports callable with [`WasmSlice64`]
+    fn is_memory64(&self) -> bool {
+        self.function_exports()
+            .iter()
+            .any(ExternInfo::is_callable64)
+    }
+
+    /// Where the guest memory comes from and which export reaches it
+    ///
+    /// Backends that cannot tell assume the guest exports it as `memory`.
+    fn memory_layout(&self) -> Result<MemoryLayout, HostError> {
+        Ok(MemoryLayout {
+            import: None,
+            export: Some(DEFAULT_MEMORY_EXPORT.to_string()),
+        })
+    }
+
+    /// Access the concrete module for downcasting
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: BackendModule + ?Sized> BackendModule for Arc<T> {
+    fn function_exports(&self) -> Vec<ExternInfo> {
+        (**self).function_exports()
+    }
+
+    fn is_memory64(&self) -> bool {
+        (**self).is_memory64()
+    }
+
+    fn memory_layout(&self) -> Result<MemoryLayout, HostError> {
+        (**self).memory_layout()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        (**self).as_any()
+    }
+}
+
+/// Access to a guest's linear memory and allocator
+pub trait BackendMemory {
+    /// Current size of the guest memory in bytes
+    fn memory_size(&mut self) -> Result<u64, HostError>;
+
+    /// Read `buf.len()` bytes starting at `offset`
+    fn read_memory(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), HostError>;
+
+    /// Write `data` starting at `offset`
+    fn write_memory(&mut self, offset: u64, data: &[u8]) -> Result<(), HostError>;
+
+    /// Allocate `len` bytes using the guest's exported allocator
+    ///
+    /// A 0 pointer, which guests return when they refuse the allocation,
+    /// is reported as [`HostError::MemoryAccess`]; see [`guest_allocation`].
+    fn allocate(&mut self, len: u32) -> Result<u32, HostError>;
+
+    /// Allocate `len` bytes in a 64-bit guest
+    ///
+    /// Uses the guest's `__aingle_guest_allocate64` where the backend
+    /// supports it; by default lengths are passed to [`BackendMemory::allocate`]
+    /// and fail beyond 4 GiB.
+    fn allocate64(&mut self, len: u64) -> Result<u64, HostError> {
+        allocate64_with_32(self, len)
+    }
+}
+
+/// Allocate in a 64-bit guest through its 32-bit allocator
+pub(crate) fn allocate64_with_32<M: BackendMemory + ?Sized>(
+    memory: &mut M,
+    len: u64,
+) -> Result<u64, HostError> {
+    let len = u32::try_from(len).map_err(|_| {
+        HostError::MemoryAccess(format!("allocation of {} bytes exceeds 32-bit memory", len))
+    })?;
+    memory.allocate(len).map(u64::from)
+}
+
+/// A guest accepting inputs through the chunked streaming protocol
+///
+/// See [`aingle_wasmer_common::STREAM_BEGIN_EXPORT`] for the protocol.
+pub trait StreamSink: BackendMemory {
+    /// Open a stream of `total_len` bytes, returning the guest's handle
+    fn stream_begin(&mut self, total_len: u32) -> Result<u32, HostError>;
+
+    /// Hand a chunk already written to guest memory to the stream
+    fn stream_write(&mut self, handle: u32, chunk: WasmSlice) -> Result<i32, HostError>;
+}
+
+/// An instantiated module ready for execution
+pub trait BackendInstance: StreamSink + Send {
+    /// The module this instance was created from
+    fn module(&self) -> &dyn BackendModule;
+
+    /// Call a guest function using the `(ptr, len) -> u64` convention
+    fn call(&mut self, name: &str, input: WasmSlice) -> Result<u64, HostError>;
+
+    /// Call a guest function using the `(ptr1, len1, ptr2, len2) -> u64` convention
+    fn call2(&mut self, name: &str, first: WasmSlice, second: WasmSlice) -> Result<u64, HostError>;
+
+    /// Call a guest function using the `(i64, i64) -> (i64, i64)` convention
+    /// of 64-bit guests, returning the packed
+    /// [`WasmResult64`](aingle_wasmer_common::WasmResult64)
+    fn call64(&mut self, name: &str, _input: WasmSlice64) -> Result<u128, HostError> {
+        Err(HostError::SignatureMismatch(format!(
+            "{}: 64-bit calls are not supported by this backend",
+            name
+        )))
+    }
+
+    /// Call a nullary guest export returning an `i32`, such as the arena
+    /// statistics; `None` if the guest does not export it
+    fn call_stat(&mut self, _name: &str) -> Result<Option<u32>, HostError> {
+        Ok(None)
+    }
+
+    /// Call a nullary guest export returning a packed `i64` result, such as
+    /// the last panic; `None` if the guest does not export it
+    fn call_packed(&mut self, _name: &str) -> Result<Option<u64>, HostError> {
+        Ok(None)
+    }
+
+    /// Call a guest export taking a single `i32` and returning nothing, such
+    /// as the arena limit; `false` if the guest does not export it
+    fn call_setter(&mut self, _name: &str, _value: u32) -> Result<bool, HostError> {
+        Ok(false)
+    }
+
+    /// Remaining metering points, or `None` once the budget is exhausted
+    fn remaining_points(&mut self) -> Option<u64>;
+
+    /// Reset the metering budget to `points`
+    fn set_remaining_points(&mut self, points: u64);
+
+    /// Access the concrete instance for downcasting
+    fn as_any(&self) -> &dyn Any;
+
+    /// Mutably access the concrete instance for downcasting
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+/// WASM value types appearing in export signatures
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueType {
+    /// 32-bit integer
+    I32,
+    /// 64-bit integer
+    I64,
+    /// 32-bit float
+    F32,
+    /// 64-bit float
+    F64,
+    /// 128-bit vector
+    V128,
+    /// Function reference
+    FuncRef,
+    /// External reference
+    ExternRef,
+    /// Any other type not known to the host
+    Other,
+}
+
+/// Description of a function exported by a guest module
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExternInfo {
+    /// Export name
+    pub name: String,
+    /// Parameter types
+    pub params: Vec<ValueType>,
+    /// Result types
+    pub results: Vec<ValueType>,
+    /// Whether the function follows the `(i32, i32) -> i64` call convention
+    pub callable: bool,
+}
+
+impl ExternInfo {
+    /// Describe an exported function
+    pub fn new(name: impl Into<String>, params: Vec<ValueType>, results: Vec<ValueType>) -> Self {
+        let callable = params == [ValueType::I32, ValueType::I32] && results == [ValueType::I64];
+        Self {
+            name: name.into(),
+            params,
+            results,
+            callable,
+        }
+    }
+
+    /// Whether the function follows the `(i64, i64) -> (i64, i64)` call
+    /// convention of 64-bit guests
+    pub fn is_callable64(&self) -> bool {
+        self.params == [ValueType::I64; 2] && self.results == [ValueType::I64; 2]
+    }
+}
+
+/// Export name of the guest memory looked for first
+pub const DEFAULT_MEMORY_EXPORT: &str = "memory";
+
+/// A memory a module imports
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryImport {
+    /// Namespace of the import, `env` for most toolchains
+    pub module: String,
+    /// Name of the import
+    pub name: String,
+    /// Pages the module needs at least
+    pub minimum: u32,
+    /// Pages the module accepts at most, if bounded
+    pub maximum: Option<u32>,
+    /// Whether the memory is shared between threads
+    pub shared: bool,
+}
+
+/// Where the memory of a module comes from, as read from its import and
+/// export types
+///
+/// Rust's `wasm32-unknown-unknown` modules define and export their memory;
+/// modules linked with `--import-memory` import it instead, and may export
+/// it again. Modules with several memories are not supported.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryLayout {
+    /// The memory the host has to create, if the module imports it
+    pub import: Option<MemoryImport>,
+    /// Export the host reaches the memory through, if the module exports it
+    pub export: Option<String>,
+}
+
+impl MemoryLayout {
+    /// The layout of a module importing `imports` and exporting memories
+    /// under `exports`, in export order
+    ///
+    /// The memory is reached through its export whatever its name, so
+    /// modules exporting it other than as [`DEFAULT_MEMORY_EXPORT`] work
+    /// too. Several imported or exported memories fail with
+    /// [`HostError::UnsupportedMemory`] rather than picking one: import and
+    /// export types alone cannot say which memory the guest allocates in.
+    pub fn detect(imports: Vec<MemoryImport>, exports: Vec<String>) -> Result<Self, HostError> {
+        if imports.len() > 1 {
+            let names: Vec<_> = imports
+                .iter()
+                .map(|import| format!("{}.{}", import.module, import.name))
+                .collect();
+            return Err(HostError::UnsupportedMemory(format!(
+                "module imports {} memories: {}",
+                imports.len(),
+                names.join(", ")
+            )));
+        }
+        if exports.len() > 1 {
+            return Err(HostError::UnsupportedMemory(format!(
+                "module exports {} memories: {}",
+                exports.len(),
+                exports.join(", ")
+            )));
+        }
+        Ok(Self {
+            import: imports.into_iter().next(),
+            export: exports.into_iter().next(),
+        })
+    }
+}
+
+/// Check whether an export belongs to the guest runtime rather than user code
+pub fn is_infrastructure_export(name: &str) -> bool {
+    name == "memory"
+        || name == "__hc__allocate_1"
+        || name == "__hc__deallocate_1"
+        || name.starts_with("__aingle_guest_")
+        || name.starts_with("__aingle_stream_")
+        || name.starts_with("__aingle_arena_")
+}
+
+/// Host-provided imports for instantiation
+#[derive(Clone, Debug)]
+pub struct BackendImports {
+    /// Initial pages of the `env.memory` import
+    pub memory_pages: u32,
+    /// Host functions defined as `env` imports under their names
+    pub functions: HostFunctionRegistry,
+}
+
+impl Default for BackendImports {
+    fn default() -> Self {
+        Self {
+            memory_pages: 1,
+            functions: HostFunctionRegistry::new(),
+        }
+    }
+}
+
+/// Read bytes from guest memory with bounds checking
+pub fn read_guest_bytes<M: BackendMemory + ?Sized>(
+    memory: &mut M,
+    guest_ptr: u32,
+    len: u32,
+) -> Result<Vec<u8>, HostError> {
+    let start = guest_ptr as u64;
+    let end = start + len as u64;
+    let size = memory.memory_size()?;
+
+    if end > size {
+        return Err(HostError::out_of_bounds(start, len as u64, size));
+    }
+
+    let mut buffer = vec![0u8; len as usize];
+    memory.read_memory(start, &mut buffer)?;
+    Ok(buffer)
+}
+
+/// Read bytes from guest memory into `out`, reusing its allocation
+///
+/// `out` is cleared first and only grows if its capacity is too small.
+pub fn read_guest_bytes_into<M: BackendMemory + ?Sized>(
+    memory: &mut M,
+    guest_ptr: u32,
+    len: u32,
+    out: &mut Vec<u8>,
+) -> Result<(), HostError> {
+    let start = guest_ptr as u64;
+    let end = start + len as u64;
+    let size = memory.memory_size()?;
+
+    if end > size {
+        return Err(HostError::out_of_bounds(start, len as u64, size));
+    }
+
+    out.clear();
+    out.resize(len as usize, 0);
+    memory.read_memory(start, out)
+}
+
+/// Payloads up to this size are read into a reused per-thread buffer
+pub const SCRATCH_BUFFER_LEN: usize = 16 * 1024;
+
+thread_local! {
+    static SCRATCH: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Read bytes from guest memory and pass them to `f` without keeping them
+///
+/// Payloads up to [`SCRATCH_BUFFER_LEN`] bytes go through a per-thread
+/// buffer, so bursts of small reads do not allocate.
+pub fn with_guest_bytes<M: BackendMemory + ?Sized, R>(
+    memory: &mut M,
+    guest_ptr: u32,
+    len: u32,
+    f: impl FnOnce(&[u8]) -> R,
+) -> Result<R, HostError> {
+    if len as usize > SCRATCH_BUFFER_LEN {
+        return read_guest_bytes(memory, guest_ptr, len).map(|bytes| f(&bytes));
+    }
+
+    // Taken out for the duration of `f`, so a nested read simply allocates
+    let mut buffer = SCRATCH.with(|scratch| std::mem::take(&mut *scratch.borrow_mut()));
+    let result = read_guest_bytes_into(memory, guest_ptr, len, &mut buffer).map(|()| f(&buffer));
+    SCRATCH.with(|scratch| *scratch.borrow_mut() = buffer);
+    result
+}
+
+/// Check a pointer returned by the guest allocator
+///
+/// Guests return 0 when they reject the length or run out of memory, which
+/// must not be mistaken for an allocation at address 0.
+pub fn guest_allocation(ptr: i32) -> Result<u32, HostError> {
+    match ptr {
+        0 => Err(HostError::MemoryAccess(
+            "guest allocation failed".to_string(),
+        )),
+        ptr => Ok(ptr as u32),
+    }
+}
+
+/// Check a pointer returned by a 64-bit guest allocator; see
+/// [`guest_allocation`]
+pub fn guest_allocation64(ptr: i64) -> Result<u64, HostError> {
+    match ptr {
+        0 => Err(HostError::MemoryAccess(
+            "guest allocation failed".to_string(),
+        )),
+        ptr => Ok(ptr as u64),
+    }
+}
+
+/// Allocate space in a 64-bit guest and copy bytes into it
+pub fn write_guest_bytes64<M: BackendMemory + ?Sized>(
+    memory: &mut M,
+    bytes: &[u8],
+) -> Result<WasmSlice64, HostError> {
+    let len = bytes.len() as u64;
+    let ptr = memory.allocate64(len)?;
+    memory.write_memory(ptr, bytes)?;
+    Ok(WasmSlice64::new(ptr, len))
+}
+
+/// Allocate space in the guest and copy bytes into it
+pub fn write_guest_bytes<M: BackendMemory + ?Sized>(
+    memory: &mut M,
+    bytes: &[u8],
+) -> Result<WasmSlice, HostError> {
+    let len = bytes.len() as u32;
+    let ptr = memory.allocate(len)?;
+    memory.write_memory(ptr as u64, bytes)?;
+    Ok(WasmSlice::new(ptr, len))
+}
+
+/// Write `payload` framed in an envelope directly into guest memory at `offset`
+///
+/// The payload is checksummed with `kind`, which the guest accepts once
+/// told through [`WasmInstance::set_checksum`](crate::WasmInstance::set_checksum)
+/// and always for CRC32. The header and payload are copied separately, so
+/// the payload is never buffered on the host.
+pub fn write_envelope_at<M: BackendMemory + ?Sized>(
+    memory: &mut M,
+    offset: u32,
+    payload: &[u8],
+    flags: u8,
+    kind: ChecksumKind,
+) -> Result<WasmSlice, HostError> {
+    let checksum = compute_checksum_with(kind, payload);
+    let header = EnvelopeHeader::new(payload.len() as u32, checksum, flags);
+    memory.write_memory(offset as u64, &header.to_bytes())?;
+    memory.write_memory(offset as u64 + EnvelopeHeader::SIZE as u64, payload)?;
+    Ok(WasmSlice::new(
+        offset,
+        (EnvelopeHeader::SIZE + payload.len()) as u32,
+    ))
+}
+
+/// Allocate space in the guest and write `payload` framed in an envelope,
+/// checksummed with `kind`
+pub fn write_guest_envelope<M: BackendMemory + ?Sized>(
+    memory: &mut M,
+    payload: &[u8],
+    flags: u8,
+    kind: ChecksumKind,
+) -> Result<WasmSlice, HostError> {
+    let ptr = memory.allocate((EnvelopeHeader::SIZE + payload.len()) as u32)?;
+    write_envelope_at(memory, ptr, payload, flags, kind)
+}
+
+/// Stream `data` into the guest in chunks of at most `chunk_size` bytes
+///
+/// A single chunk-sized buffer is allocated in the guest and reused for every
+/// chunk. Returns the stream handle to pass to the target function.
+pub fn stream_to_guest<S: StreamSink + ?Sized>(
+    sink: &mut S,
+    data: &[u8],
+    chunk_size: usize,
+) -> Result<u32, HostError> {
+    if chunk_size == 0 {
+        return Err(HostError::Stream("chunk size must be non-zero".to_string()));
+    }
+    let total_len = u32::try_from(data.len())
+        .map_err(|_| HostError::Stream(format!("{} bytes exceed the stream limit", data.len())))?;
+
+    let handle = sink.stream_begin(total_len)?;
+    if handle == 0 {
+        return Err(HostError::Stream(format!(
+            "guest refused to open a stream of {} bytes",
+            total_len
+        )));
+    }
+    if data.is_empty() {
+        return Ok(handle);
+    }
+
+    let buffer = sink.allocate(chunk_size.min(data.len()) as u32)?;
+    for (index, chunk) in data.chunks(chunk_size).enumerate() {
+        sink.write_memory(buffer as u64, chunk)?;
+        let code = sink.stream_write(handle, WasmSlice::new(buffer, chunk.len() as u32))?;
+
+        match StreamStatus::from_code(code) {
+            Some(StreamStatus::Ok) => {}
+            status => {
+                return Err(HostError::Stream(format!(
+                    "guest rejected chunk at offset {} of stream {}: {}",
+                    index * chunk_size,
+                    handle,
+                    status.map_or("unknown status", StreamStatus::description)
+                )))
+            }
+        }
+    }
+
+    Ok(handle)
+}
+
+/// Deserialize a MessagePack payload, with an empty one standing for unit
+fn decode_payload<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+    match bytes {
+        [] => decode_empty().map_err(|e| e.to_string()),
+        bytes => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+    }
+}
+
+/// Read and deserialize a MessagePack value from guest memory
+///
+/// An empty range decodes only as unit, see [`decode_empty`].
+pub fn read_typed<M: BackendMemory + ?Sized, T: DeserializeOwned>(
+    memory: &mut M,
+    guest_ptr: u32,
+    len: u32,
+) -> Result<T, HostError> {
+    with_guest_bytes(memory, guest_ptr, len, decode_payload)?.map_err(|e| {
+        HostError::Deserialization(format!(
+            "read_typed at {:#x} ({} bytes): {}",
+            guest_ptr, len, e
+        ))
+    })
+}
+
+/// Read an envelope from guest memory and deserialize its payload
+///
+/// The envelope header and checksum are validated before decoding.
+pub fn read_enveloped<M: BackendMemory + ?Sized, T: DeserializeOwned>(
+    memory: &mut M,
+    guest_ptr: u32,
+    len: u32,
+) -> Result<T, HostError> {
+    with_guest_bytes(memory, guest_ptr, len, |bytes| {
+        let envelope = decode_envelope(bytes).map_err(|e| {
+            HostError::Deserialization(format!(
+                "read_enveloped at {:#x} ({} bytes): {}",
+                guest_ptr, len, e
+            ))
+        })?;
+        decode_payload(envelope.payload).map_err(|e| {
+            HostError::Deserialization(format!(
+                "read_enveloped at {:#x} ({} bytes): {}",
+                guest_ptr, len, e
+            ))
+        })
+    })?
+}
+
+/// Read the value a [`WasmRef`] points at in guest memory
+///
+/// Decoding failures are reported as [`HostError::Deserialization`] naming
+/// the offset that was read.
+pub fn read_ref<M: BackendMemory + ?Sized, T: WasmDecode>(
+    memory: &mut M,
+    value: WasmRef<T>,
+) -> Result<T, HostError> {
+    with_guest_bytes(memory, value.ptr(), value.len(), T::decode_from)?.map_err(|e| {
+        HostError::Deserialization(format!(
+            "read_ref at {:#x} ({} bytes): {}",
+            value.ptr(),
+            value.len(),
+            e
+        ))
+    })
+}
+
+/// Encode `value` into the guest memory a [`WasmRef`] covers
+///
+/// Returns the number of bytes written, which may be fewer than the
+/// reference covers.
+pub fn write_ref<M: BackendMemory + ?Sized, T: WasmEncode>(
+    memory: &mut M,
+    target: WasmRef<T>,
+    value: &T,
+) -> Result<usize, HostError> {
+    let start = target.ptr() as u64;
+    let end = start + target.len() as u64;
+    let size = memory.memory_size()?;
+    if end > size {
+        return Err(HostError::out_of_bounds(start, target.len() as u64, size));
+    }
+
+    let mut buffer = vec![0u8; target.len() as usize];
+    let written = value.encode_to(&mut buffer).map_err(|e| {
+        HostError::Serialization(format!(
+            "write_ref at {:#x} ({} bytes): {}",
+            target.ptr(),
+            target.len(),
+            e
+        ))
+    })?;
+    memory.write_memory(start, &buffer[..written])?;
+    Ok(written)
+}
+
+/// Serialize a value, wrap it in an envelope and copy it into the guest
+///
+/// Unit values are sent as an empty payload, see [`is_unit`].
+pub fn write_enveloped<M: BackendMemory + ?Sized, T: Serialize + ?Sized>(
+    memory: &mut M,
+    value: &T,
+) -> Result<WasmSlice, HostError> {
+    if is_unit(value) {
+        return write_guest_envelope(memory, &[], 0, ChecksumKind::Crc32);
+    }
+    let payload = rmp_serde::to_vec_named(value)
+        .map_err(|e| HostError::Serialization(format!("write_enveloped: {}", e)))?;
+    write_guest_envelope(memory, &payload, 0, ChecksumKind::Crc32)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use aingle_wasmer_codec::decode_envelope_with;
+    use aingle_wasmer_common::MemoryError;
+
+    /// Plain byte buffer standing in for guest memory
+    pub(crate) struct VecMemory {
+        pub(crate) data: Vec<u8>,
+        pub(crate) next: u32,
+    }
+
+    impl BackendMemory for VecMemory {
+        fn memory_size(&mut self) -> Result<u64, HostError> {
+            Ok(self.data.len() as u64)
+        }
+
+        fn read_memory(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), HostError> {
+            let start = offset as usize;
+            buf.copy_from_slice(&self.data[start..start + buf.len()]);
+            Ok(())
+        }
+
+        fn write_memory(&mut self, offset: u64, data: &[u8]) -> Result<(), HostError> {
+            let start = offset as usize;
+            self.data[start..start + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn allocate(&mut self, len: u32) -> Result<u32, HostError> {
+            let ptr = self.next;
+            self.next += len;
+            Ok(ptr)
+        }
+    }
+
+    /// Guest side of the streaming protocol enforcing the announced length
+    struct VecStream {
+        memory: VecMemory,
+        total_len: u32,
+        received: Vec<u8>,
+    }
+
+    impl BackendMemory for VecStream {
+        fn memory_size(&mut self) -> Result<u64, HostError> {
+            self.memory.memory_size()
+        }
+
+        fn read_memory(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), HostError> {
+            self.memory.read_memory(offset, buf)
+        }
+
+        fn write_memory(&mut self, offset: u64, data: &[u8]) -> Result<(), HostError> {
+            self.memory.write_memory(offset, data)
+        }
+
+        fn allocate(&mut self, len: u32) -> Result<u32, HostError> {
+            self.memory.allocate(len)
+        }
+    }
+
+    impl StreamSink for VecStream {
+        fn stream_begin(&mut self, total_len: u32) -> Result<u32, HostError> {
+            self.total_len = total_len;
+            Ok(1)
+        }
+
+        fn stream_write(&mut self, handle: u32, chunk: WasmSlice) -> Result<i32, HostError> {
+            if handle != 1 {
+                return Ok(StreamStatus::OutOfOrder as i32);
+            }
+            // Announce one byte less than delivered to provoke an over-length
+            if self.received.len() + chunk.len as usize > self.total_len as usize - 1 {
+                return Ok(StreamStatus::OverLength as i32);
+            }
+            let bytes = read_guest_bytes(self, chunk.ptr, chunk.len)?;
+            self.received.extend_from_slice(&bytes);
+            Ok(StreamStatus::Ok as i32)
+        }
+    }
+
+    #[test]
+    fn test_stream_over_length() {
+        let mut sink = VecStream {
+            memory: VecMemory {
+                data: vec![0u8; 64],
+                next: 0,
+            },
+            total_len: 0,
+            received: Vec::new(),
+        };
+
+        let err = stream_to_guest(&mut sink, b"abcdefgh", 4).unwrap_err();
+        match err {
+            HostError::Stream(msg) => {
+                assert!(msg.contains("offset 4"), "{}", msg);
+                assert!(msg.contains("past the announced stream length"), "{}", msg);
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert_eq!(sink.received, b"abcd");
+
+        let err = stream_to_guest(&mut sink, b"abc", 0).unwrap_err();
+        assert!(matches!(err, HostError::Stream(_)));
+    }
+
+    #[test]
+    fn test_guest_bytes_roundtrip() {
+        let mut memory = VecMemory {
+            data: vec![0u8; 64],
+            next: 8,
+        };
+
+        let slice = write_guest_bytes(&mut memory, b"hello").unwrap();
+        assert_eq!(slice, WasmSlice::new(8, 5));
+
+        let bytes = read_guest_bytes(&mut memory, slice.ptr, slice.len).unwrap();
+        assert_eq!(bytes, b"hello");
+    }
+
+    #[test]
+    fn test_extern_info_callable() {
+        let zome_fn = ExternInfo::new(
+            "create_entry",
+            vec![ValueType::I32, ValueType::I32],
+            vec![ValueType::I64],
+        );
+        assert!(zome_fn.callable);
+
+        let other = ExternInfo::new("helper", vec![ValueType::I32], vec![ValueType::I32]);
+        assert!(!other.callable);
+        assert!(!zome_fn.is_callable64());
+
+        let zome_fn64 = ExternInfo::new(
+            "create_entry",
+            vec![ValueType::I64, ValueType::I64],
+            vec![ValueType::I64, ValueType::I64],
+        );
+        assert!(!zome_fn64.callable);
+        assert!(zome_fn64.is_callable64());
+    }
+
+    #[test]
+    fn test_guest_bytes64() {
+        let mut memory = VecMemory {
+            data: vec![0u8; 64],
+            next: 8,
+        };
+        let slice = write_guest_bytes64(&mut memory, b"large").unwrap();
+        assert_eq!(slice, WasmSlice64::new(8, 5));
+        assert_eq!(&memory.data[8..13], b"large");
+
+        // Backends without a 64-bit allocator stop at 4 GiB
+        assert!(matches!(
+            memory.allocate64(1 << 32),
+            Err(HostError::MemoryAccess(_))
+        ));
+        assert_eq!(memory.allocate64(16).unwrap(), 13);
+
+        assert!(guest_allocation64(0).is_err());
+        assert_eq!(guest_allocation64(1 << 40).unwrap(), 1 << 40);
+    }
+
+    #[test]
+    fn test_infrastructure_exports() {
+        assert!(is_infrastructure_export("memory"));
+        assert!(is_infrastructure_export("__hc__allocate_1"));
+        assert!(is_infrastructure_export("__aingle_guest_reset_arena"));
+        assert!(is_infrastructure_export("__aingle_stream_begin"));
+        assert!(is_infrastructure_export("__aingle_arena_push"));
+        assert!(!is_infrastructure_export("create_entry"));
+    }
+
+    #[test]
+    fn test_memory_layout() {
+        let import = |name: &str| MemoryImport {
+            module: "env".to_string(),
+            name: name.to_string(),
+            minimum: 1,
+            maximum: None,
+            shared: false,
+        };
+        let exports = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+
+        let exported = MemoryLayout::detect(vec![], exports(&["mem"])).unwrap();
+        assert_eq!(exported.import, None);
+        assert_eq!(exported.export.as_deref(), Some("mem"));
+
+        let imported = MemoryLayout::detect(vec![import("memory")], vec![]).unwrap();
+        assert_eq!(imported.import, Some(import("memory")));
+        assert_eq!(imported.export, None);
+
+        let reexported = MemoryLayout::detect(vec![import("memory")], exports(&["memory"]));
+        assert_eq!(reexported.unwrap().export.as_deref(), Some("memory"));
+
+        assert_eq!(
+            MemoryLayout::detect(vec![], vec![]).unwrap(),
+            MemoryLayout::default()
+        );
+
+        for (imports, exported) in [
+            (vec![import("a"), import("b")], vec![]),
+            (vec![], exports(&["memory", "scratch"])),
+        ] {
+            assert!(matches!(
+                MemoryLayout::detect(imports, exported),
+                Err(HostError::UnsupportedMemory(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_typed_roundtrip() {
+        let mut memory = VecMemory {
+            data: vec![0u8; 256],
+            next: 16,
+        };
+
+        let bytes = rmp_serde::to_vec_named(&("entry", 42u32)).unwrap();
+        let slice = write_guest_bytes(&mut memory, &bytes).unwrap();
+        let value: (String, u32) = read_typed(&mut memory, slice.ptr, slice.len).unwrap();
+        assert_eq!(value, ("entry".to_string(), 42));
+
+        let slice = write_enveloped(&mut memory, &vec![1u8, 2, 3]).unwrap();
+        let value: Vec<u8> = read_enveloped(&mut memory, slice.ptr, slice.len).unwrap();
+        assert_eq!(value, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_read_typed_deserialize_error() {
+        let mut memory = VecMemory {
+            data: vec![0u8; 64],
+            next: 32,
+        };
+        // A msgpack string where an integer is expected
+        let slice = write_guest_bytes(&mut memory, &[0xa1, b'x']).unwrap();
+
+        let err = read_typed::<_, u32>(&mut memory, slice.ptr, slice.len).unwrap_err();
+        match err {
+            HostError::Deserialization(msg) => {
+                assert!(msg.starts_with("read_typed at 0x20 (2 bytes)"), "{}", msg)
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_read_enveloped_rejects_raw_bytes() {
+        let mut memory = VecMemory {
+            data: vec![0u8; 64],
+            next: 8,
+        };
+        let bytes = rmp_serde::to_vec_named(&7u32).unwrap();
+        let slice = write_guest_bytes(&mut memory, &bytes).unwrap();
+
+        let err = read_enveloped::<_, u32>(&mut memory, slice.ptr, slice.len).unwrap_err();
+        match err {
+            HostError::Deserialization(msg) => {
+                assert!(msg.starts_with("read_enveloped at 0x8"), "{}", msg)
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_enveloped_unit() {
+        let mut memory = VecMemory {
+            data: vec![0u8; 64],
+            next: 8,
+        };
+
+        let slice = write_enveloped(&mut memory, &()).unwrap();
+        assert_eq!(slice.len as usize, EnvelopeHeader::SIZE);
+        read_enveloped::<_, ()>(&mut memory, slice.ptr, slice.len).unwrap();
+        read_typed::<_, ()>(&mut memory, 0, 0).unwrap();
+
+        let err = read_enveloped::<_, u32>(&mut memory, slice.ptr, slice.len).unwrap_err();
+        assert!(err.to_string().contains("expected `u32`"), "{}", err);
+    }
+
+    #[test]
+    fn test_write_envelope_at() {
+        let mut memory = VecMemory {
+            data: vec![0u8; 64],
+            next: 0,
+        };
+
+        for kind in [ChecksumKind::Crc32, ChecksumKind::Crc32c] {
+            let slice = write_envelope_at(&mut memory, 4, b"payload", 0, kind).unwrap();
+            assert_eq!(slice, WasmSlice::new(4, EnvelopeHeader::SIZE as u32 + 7));
+
+            let bytes = read_guest_bytes(&mut memory, slice.ptr, slice.len).unwrap();
+            let envelope = decode_envelope_with(kind, &bytes).unwrap();
+            assert_eq!(envelope.payload, b"payload");
+        }
+    }
+
+    #[test]
+    fn test_ref_roundtrip() {
+        let mut memory = VecMemory {
+            data: vec![0u8; 16],
+            next: 0,
+        };
+
+        let value = WasmRef::<f32>::new(WasmSlice::new(8, 4));
+        assert_eq!(write_ref(&mut memory, value, &2.5).unwrap(), 4);
+        assert_eq!(memory.data[8..12], 2.5f32.to_le_bytes());
+        assert_eq!(read_ref(&mut memory, value).unwrap(), 2.5);
+
+        let past_end = WasmRef::<u64>::new(WasmSlice::new(12, 8));
+        assert!(matches!(
+            read_ref(&mut memory, past_end),
+            Err(HostError::Memory(MemoryError::OutOfBounds {
+                offset: 12,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            write_ref(&mut memory, past_end, &1),
+            Err(HostError::Memory(MemoryError::OutOfBounds {
+                offset: 12,
+                ..
+            }))
+        ));
+
+        let short = WasmRef::<u64>::new(WasmSlice::new(4, 4));
+        let err = read_ref(&mut memory, short).unwrap_err();
+        assert!(
+            err.to_string().contains("read_ref at 0x4 (4 bytes)"),
+            "{}",
+            err
+        );
+        assert!(matches!(
+            write_ref(&mut memory, short, &1),
+            Err(HostError::Serialization(_))
+        ));
+        assert_eq!(memory.data[4..8], [0; 4]);
+    }
+
+    #[test]
+    fn test_read_out_of_bounds() {
+        let mut memory = VecMemory {
+            data: vec![0u8; 16],
+            next: 0,
+        };
+
+        let result = read_guest_bytes(&mut memory, 10, 10);
+        assert!(matches!(
+            result,
+            Err(HostError::Memory(MemoryError::OutOfBounds {
+                offset: 10,
+                len: 10,
+                max: 16
+            }))
+        ));
+
+        let mut out = Vec::new();
+        let result = read_guest_bytes_into(&mut memory, u32::MAX, 2, &mut out);
+        assert!(matches!(
+            result,
+            Err(HostError::Memory(MemoryError::OutOfBounds {
+                offset: 0xffff_ffff,
+                len: 2,
+                max: 16
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_read_guest_bytes_into_reuses_buffer() {
+        let mut memory = VecMemory {
+            data: (0..64).collect(),
+            next: 0,
+        };
+        let mut out = Vec::with_capacity(32);
+        let capacity = out.capacity();
+
+        read_guest_bytes_into(&mut memory, 8, 4, &mut out).unwrap();
+        assert_eq!(out, [8, 9, 10, 11]);
+        read_guest_bytes_into(&mut memory, 60, 2, &mut out).unwrap();
+        assert_eq!(out, [60, 61]);
+        assert_eq!(out.capacity(), capacity);
+
+        assert!(read_guest_bytes_into(&mut memory, 62, 4, &mut out).is_err());
+    }
+
+    #[test]
+    fn test_guest_allocation_sentinel() {
+        assert_eq!(guest_allocation(1024).unwrap(), 1024);
+        assert_eq!(guest_allocation(-8).unwrap(), 0xffff_fff8);
+        assert!(matches!(
+            guest_allocation(0),
+            Err(HostError::MemoryAccess(message)) if message == "guest allocation failed"
+        ));
+    }
+}
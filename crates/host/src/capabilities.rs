@@ -0,0 +1,276 @@
+//! What a [`WasmEngine`](crate::WasmEngine) can do, for status reporting
+
+use crate::backend::WasmBackend;
+use crate::EngineConfig;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "wasmer")]
+use crate::wasmer_backend::WasmerBackend;
+
+/// Compiler turning guests into machine code
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompilerKind {
+    /// Wasmer's Cranelift compiler, of the `wasmer_sys_dev` feature
+    Cranelift,
+    /// Wasmer's LLVM compiler, of the `wasmer_sys_prod` feature
+    Llvm,
+    /// The JavaScript engine of the browser, of the `wasmer_js` feature
+    JavaScript,
+    /// A backend other than Wasmer
+    Unknown,
+}
+
+/// Proposals beyond WebAssembly 1.0 the engine compiles modules using
+///
+/// Detected by compiling a minimal module per proposal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WasmFeatures {
+    /// 128-bit SIMD
+    pub simd: bool,
+    /// Shared memories and atomics
+    pub threads: bool,
+    /// Bulk memory operations
+    pub bulk_memory: bool,
+    /// Reference types
+    pub reference_types: bool,
+    /// Functions returning multiple values
+    pub multi_value: bool,
+    /// 64-bit memories
+    pub memory64: bool,
+    /// Several memories per module
+    pub multi_memory: bool,
+}
+
+/// Header of the probe modules, the magic and version 1
+const PROBE_HEADER: &[u8] = b"\0asm\x01\0\0\0";
+
+/// `(func (result v128) (v128.const i64x2 0 0))`
+const SIMD_PROBE: &[u8] = &[
+    0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7b, // type () -> v128
+    0x03, 0x02, 0x01, 0x00, // func of type 0
+    0x0a, 0x16, 0x01, 0x14, 0x00, 0xfd, 0x0c, // body: v128.const
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x0b,
+];
+
+/// `(memory 1 1 shared)`
+const THREADS_PROBE: &[u8] = &[0x05, 0x04, 0x01, 0x03, 0x01, 0x01];
+
+/// `(memory 1) (func (memory.fill (i32.const 0) (i32.const 0) (i32.const 0)))`
+const BULK_MEMORY_PROBE: &[u8] = &[
+    0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type () -> ()
+    0x03, 0x02, 0x01, 0x00, // func of type 0
+    0x05, 0x03, 0x01, 0x00, 0x01, // memory 1
+    0x0a, 0x0d, 0x01, 0x0b, 0x00, // body:
+    0x41, 0x00, 0x41, 0x00, 0x41, 0x00, 0xfc, 0x0b, 0x00, 0x0b, // memory.fill
+];
+
+/// `(table 1 externref)`
+const REFERENCE_TYPES_PROBE: &[u8] = &[0x04, 0x04, 0x01, 0x6f, 0x00, 0x01];
+
+/// `(func (result i32 i32) (i32.const 0) (i32.const 0))`
+const MULTI_VALUE_PROBE: &[u8] = &[
+    0x01, 0x06, 0x01, 0x60, 0x00, 0x02, 0x7f, 0x7f, // type () -> (i32, i32)
+    0x03, 0x02, 0x01, 0x00, // func of type 0
+    0x0a, 0x08, 0x01, 0x06, 0x00, 0x41, 0x00, 0x41, 0x00, 0x0b, // body
+];
+
+/// `(memory i64 1)`
+const MEMORY64_PROBE: &[u8] = &[0x05, 0x03, 0x01, 0x04, 0x01];
+
+/// `(memory 1) (memory 1)`
+const MULTI_MEMORY_PROBE: &[u8] = &[0x05, 0x05, 0x02, 0x00, 0x01, 0x00, 0x01];
+
+impl WasmFeatures {
+    /// The proposals `backend` compiles the probe modules of
+    pub fn detect(backend: &dyn WasmBackend) -> Self {
+        let compiles = |sections: &[u8]| backend.compile(&probe(sections)).is_ok();
+        Self {
+            simd: compiles(SIMD_PROBE),
+            threads: compiles(THREADS_PROBE),
+            bulk_memory: compiles(BULK_MEMORY_PROBE),
+            reference_types: compiles(REFERENCE_TYPES_PROBE),
+            multi_value: compiles(MULTI_VALUE_PROBE),
+            memory64: compiles(MEMORY64_PROBE),
+            multi_memory: compiles(MULTI_MEMORY_PROBE),
+        }
+    }
+}
+
+/// A probe module made of `sections`
+fn probe(sections: &[u8]) -> Vec<u8> {
+    [PROBE_HEADER, sections].concat()
+}
+
+/// What an engine compiles with and enforces, as reported by
+/// [`WasmEngine::capabilities`](crate::WasmEngine::capabilities)
+///
+/// Serializable for conductors to publish in their status endpoints; it is
+/// also recorded in the artifacts the engine writes to the disk cache.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EngineCapabilities {
+    /// Name of the backend, see [`WasmBackend::name`]
+    pub backend: String,
+    /// Compiler of the backend
+    pub compiler: CompilerKind,
+    /// Version of the Wasmer crate, `None` on other backends
+    pub wasmer_version: Option<String>,
+    /// Version of this crate
+    pub host_version: String,
+    /// Whether guests are metered, which `wasmer_js` cannot do
+    pub metering: bool,
+    /// Points a call may spend, enforced only with `metering`
+    pub metering_limit: u64,
+    /// Whether NaNs are canonicalized, which only the native compilers do
+    pub canonicalize_nans: bool,
+    /// Static memory bound configured for the native tunables
+    pub static_memory_bound: u32,
+    /// Proposals beyond WebAssembly 1.0 the backend compiles
+    pub features: WasmFeatures,
+}
+
+impl EngineCapabilities {
+    /// The capabilities of an engine on `backend` with `config`
+    pub(crate) fn detect(backend: &dyn WasmBackend, config: &EngineConfig, metering: bool) -> Self {
+        #[cfg(feature = "wasmer")]
+        let (compiler, wasmer_version) = if backend.as_any().is::<WasmerBackend>() {
+            (WASMER_COMPILER, Some(WASMER_VERSION.to_string()))
+        } else {
+            (CompilerKind::Unknown, None)
+        };
+        #[cfg(not(feature = "wasmer"))]
+        let (compiler, wasmer_version) = (CompilerKind::Unknown, None);
+
+        // Probes are kept from the middlewares of a configured engine
+        #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+        let features = match backend.as_any().downcast_ref::<WasmerBackend>() {
+            Some(wasmer) if wasmer.metering_limit().is_some() => {
+                WasmFeatures::detect(&WasmerBackend::unmetered(config))
+            }
+            _ => WasmFeatures::detect(backend),
+        };
+        #[cfg(not(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod")))]
+        let features = WasmFeatures::detect(backend);
+
+        let native = matches!(compiler, CompilerKind::Cranelift | CompilerKind::Llvm);
+        Self {
+            backend: backend.name().to_string(),
+            compiler,
+            wasmer_version,
+            host_version: env!("CARGO_PKG_VERSION").to_string(),
+            metering,
+            metering_limit: config.metering_limit,
+            canonicalize_nans: native && config.canonicalize_nans,
+            static_memory_bound: config.static_memory_bound,
+            features,
+        }
+    }
+}
+
+/// Version of Wasmer in this build, as wasmer exports none
+///
+/// Wasmer's crates are released in lockstep and wasmer requires its exact
+/// version of wasmer-types, so the version of wasmer-types resolved for the
+/// build is the one of wasmer.
+#[cfg(feature = "wasmer")]
+const WASMER_VERSION: &str = wasmer_types::VERSION;
+
+/// Compiler of the Wasmer backend in this build
+#[cfg(feature = "wasmer_sys_dev")]
+const WASMER_COMPILER: CompilerKind = CompilerKind::Cranelift;
+
+#[cfg(all(feature = "wasmer_sys_prod", not(feature = "wasmer_sys_dev")))]
+const WASMER_COMPILER: CompilerKind = CompilerKind::Llvm;
+
+#[cfg(all(
+    feature = "wasmer",
+    not(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))
+))]
+const WASMER_COMPILER: CompilerKind = CompilerKind::JavaScript;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probes_match_their_text() {
+        for (sections, text) in [
+            (
+                SIMD_PROBE,
+                "(module (func (result v128) (v128.const i64x2 0 0)))",
+            ),
+            (THREADS_PROBE, "(module (memory 1 1 shared))"),
+            (
+                BULK_MEMORY_PROBE,
+                "(module (memory 1) (func (memory.fill (i32.const 0) (i32.const 0) (i32.const 0))))",
+            ),
+            (REFERENCE_TYPES_PROBE, "(module (table 1 externref))"),
+            (
+                MULTI_VALUE_PROBE,
+                "(module (func (result i32 i32) (i32.const 0) (i32.const 0)))",
+            ),
+            (MEMORY64_PROBE, "(module (memory i64 1))"),
+            (MULTI_MEMORY_PROBE, "(module (memory 1) (memory 1))"),
+        ] {
+            assert_eq!(probe(sections), wat::parse_str(text).unwrap(), "{}", text);
+        }
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_detect_wasmer() {
+        use crate::WasmerBackend;
+
+        let config = EngineConfig::default();
+        let backend = WasmerBackend::new(&config).unwrap();
+        let capabilities = EngineCapabilities::detect(&backend, &config, true);
+        assert_eq!(capabilities.compiler, WASMER_COMPILER);
+        assert_eq!(
+            capabilities.wasmer_version.as_deref(),
+            Some(wasmer_types::VERSION)
+        );
+        assert_eq!(
+            capabilities.host_version,
+            env!("CARGO_PKG_VERSION").to_string()
+        );
+    }
+
+    #[test]
+    fn test_detect_without_features() {
+        use crate::backend::{BackendImports, BackendInstance, BackendModule};
+        use crate::HostError;
+        use std::any::Any;
+        use std::sync::Arc;
+
+        /// Compiles nothing
+        struct Refusing;
+
+        impl WasmBackend for Refusing {
+            fn name(&self) -> &'static str {
+                "refusing"
+            }
+
+            fn compile(&self, _: &[u8]) -> Result<Arc<dyn BackendModule>, HostError> {
+                Err(HostError::Compilation("refused".into()))
+            }
+
+            fn instantiate(
+                &self,
+                _: &dyn BackendModule,
+                _: &BackendImports,
+            ) -> Result<Box<dyn BackendInstance>, HostError> {
+                Err(HostError::Instantiation("refused".into()))
+            }
+
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+        }
+
+        let capabilities = EngineCapabilities::detect(&Refusing, &EngineConfig::default(), false);
+        assert_eq!(capabilities.backend, "refusing");
+        assert_eq!(capabilities.compiler, CompilerKind::Unknown);
+        assert_eq!(capabilities.wasmer_version, None);
+        assert!(!capabilities.canonicalize_nans);
+        assert_eq!(capabilities.features, WasmFeatures::default());
+    }
+}
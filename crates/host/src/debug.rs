@@ -0,0 +1,167 @@
+//! Guest memory inspection for debugging
+//!
+//! Only available with the `debug-tools` feature, which must never be enabled
+//! in production builds.
+
+use crate::backend::{read_guest_bytes, BackendMemory};
+use crate::HostError;
+use aingle_wasmer_common::{EnvelopeError, EnvelopeHeader};
+use std::fmt;
+
+#[cfg(feature = "wasmer_sys_prod")]
+compile_error!("the `debug-tools` feature must not be combined with `wasmer_sys_prod`");
+
+/// Bytes captured from a guest memory region
+#[derive(Clone)]
+pub struct MemoryDump {
+    /// Guest pointer the dump starts at
+    pub ptr: u32,
+    /// Captured bytes
+    pub bytes: Vec<u8>,
+    /// Envelope header parsed from the start of the region, if long enough
+    pub header: Option<EnvelopeHeader>,
+    /// Validation error of the parsed header
+    pub header_error: Option<EnvelopeError>,
+}
+
+impl MemoryDump {
+    /// Build a dump from bytes already read from guest memory
+    pub fn new(ptr: u32, bytes: Vec<u8>) -> Self {
+        let header = bytes
+            .get(..EnvelopeHeader::SIZE)
+            .and_then(|b| b.try_into().ok())
+            .map(EnvelopeHeader::from_bytes);
+        let header_error = match &header {
+            Some(header) => header.validate(None).err(),
+            None => Some(EnvelopeError::BufferTooSmall {
+                needed: EnvelopeHeader::SIZE,
+                available: bytes.len(),
+            }),
+        };
+
+        Self {
+            ptr,
+            bytes,
+            header,
+            header_error,
+        }
+    }
+
+    /// Read `len` bytes at `ptr` from guest memory
+    pub fn capture<M: BackendMemory + ?Sized>(
+        memory: &mut M,
+        ptr: u32,
+        len: u32,
+    ) -> Result<Self, HostError> {
+        Ok(Self::new(ptr, read_guest_bytes(memory, ptr, len)?))
+    }
+
+    /// Hex and ASCII rendering, 16 bytes per line prefixed with guest addresses
+    pub fn hexdump(&self) -> String {
+        let mut out = String::new();
+        for (i, chunk) in self.bytes.chunks(16).enumerate() {
+            let addr = self.ptr as usize + i * 16;
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            out.push_str(&format!(
+                "{:08x}  {:<47}  |{}|\n",
+                addr,
+                hex.join(" "),
+                ascii
+            ));
+        }
+        out
+    }
+}
+
+impl fmt::Debug for MemoryDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryDump")
+            .field("ptr", &self.ptr)
+            .field("len", &self.bytes.len())
+            .field("header_error", &self.header_error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for MemoryDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} bytes at {:#x}", self.bytes.len(), self.ptr)?;
+
+        if let Some(header) = self.header {
+            // Copy fields out of the packed header before formatting
+            let (magic, version, flags, payload_len, checksum) = (
+                header.magic,
+                header.version,
+                header.flags,
+                header.payload_len,
+                header.checksum,
+            );
+            writeln!(
+                f,
+                "envelope: magic={:#06x} version={} flags={:#04x} payload_len={} checksum={:#010x}",
+                magic, version, flags, payload_len, checksum
+            )?;
+        }
+        if let Some(error) = self.header_error {
+            writeln!(f, "envelope invalid: {}", error)?;
+        }
+
+        f.write_str(&self.hexdump())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aingle_wasmer_codec::encode_with_envelope;
+    use aingle_wasmer_common::{EnvelopeFlags, MAGIC, PROTOCOL_VERSION};
+
+    #[test]
+    fn test_dump_identifies_envelope() {
+        let mut buffer = [0u8; 64];
+        let len =
+            encode_with_envelope(b"hello", EnvelopeFlags::IsError as u8, &mut buffer).unwrap();
+
+        let dump = MemoryDump::new(1024, buffer[..len].to_vec());
+        assert!(dump.header_error.is_none());
+
+        let header = dump.header.unwrap();
+        let (magic, version, flags, payload_len) = (
+            header.magic,
+            header.version,
+            header.flags,
+            header.payload_len,
+        );
+        assert_eq!(magic, MAGIC);
+        assert_eq!(version, PROTOCOL_VERSION);
+        assert_eq!(flags, EnvelopeFlags::IsError as u8);
+        assert_eq!(payload_len, 5);
+
+        let rendered = dump.to_string();
+        assert!(rendered.contains("payload_len=5"));
+        assert!(rendered.contains("00000400"));
+    }
+
+    #[test]
+    fn test_dump_reports_invalid_header() {
+        let dump = MemoryDump::new(0, vec![0xff; 16]);
+        assert_eq!(dump.header_error, Some(EnvelopeError::InvalidMagic(0xffff)));
+
+        let short = MemoryDump::new(0, vec![0x41, 0x49]);
+        assert!(short.header.is_none());
+        assert!(matches!(
+            short.header_error,
+            Some(EnvelopeError::BufferTooSmall { .. })
+        ));
+    }
+}
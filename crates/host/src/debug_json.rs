@@ -0,0 +1,246 @@
+//! JSON rendering of MessagePack payloads for logs
+//!
+//! Only available with the `debug-tools` feature. Values of map fields named
+//! in the redaction list are replaced before anything is rendered, so
+//! secrets a payload carries never reach the logs.
+
+use crate::{ExternIO, HostError};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use parking_lot::RwLock;
+use rmpv::Value;
+use serde_json::{Map, Number, Value as Json};
+use std::collections::BTreeMap;
+
+/// Fields redacted until [`set_redacted_fields`] replaces the list
+pub const DEFAULT_REDACTED_FIELDS: &[&str] = &[
+    "passphrase",
+    "password",
+    "private_key",
+    "secret",
+    "seed",
+    "token",
+];
+
+/// Replacement for the values of redacted fields
+pub const REDACTED: &str = "[redacted]";
+
+static REDACTED_FIELDS: RwLock<Option<Vec<String>>> = parking_lot::const_rwlock(None);
+
+/// Replace the field names whose values [`transcode_msgpack_to_json`]
+/// redacts
+///
+/// Names match map keys case-insensitively. The list is process wide and
+/// replaces [`DEFAULT_REDACTED_FIELDS`], which callers wanting to extend the
+/// defaults must include themselves.
+pub fn set_redacted_fields<I, S>(fields: I)
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    *REDACTED_FIELDS.write() = Some(fields.into_iter().map(Into::into).collect());
+}
+
+/// Render a MessagePack value as compact JSON
+///
+/// Object keys come out sorted. Binary values are rendered as base64
+/// strings, extension values as `{"ext": type, "data": base64}` and map
+/// keys that are not strings as their JSON rendering. Values of fields in
+/// the redaction list, see [`set_redacted_fields`], are replaced with
+/// [`REDACTED`].
+pub fn transcode_msgpack_to_json(bytes: &[u8]) -> Result<String, HostError> {
+    let redacted = REDACTED_FIELDS.read();
+    match redacted.as_deref() {
+        Some(fields) => transcode_redacting(bytes, fields),
+        None => transcode_redacting(bytes, DEFAULT_REDACTED_FIELDS),
+    }
+}
+
+fn transcode_redacting<S: AsRef<str>>(bytes: &[u8], redacted: &[S]) -> Result<String, HostError> {
+    let mut rest = bytes;
+    let value = rmpv::decode::read_value(&mut rest)
+        .map_err(|e| HostError::Deserialization(e.to_string()))?;
+    if !rest.is_empty() {
+        return Err(HostError::Deserialization(format!(
+            "{} trailing bytes after the MessagePack value",
+            rest.len()
+        )));
+    }
+
+    serde_json::to_string(&to_json(value, redacted))
+        .map_err(|e| HostError::Serialization(e.to_string()))
+}
+
+fn to_json<S: AsRef<str>>(value: Value, redacted: &[S]) -> Json {
+    match value {
+        Value::Nil => Json::Null,
+        Value::Boolean(b) => Json::Bool(b),
+        Value::Integer(i) => match (i.as_u64(), i.as_i64()) {
+            (Some(u), _) => Json::from(u),
+            (None, Some(i)) => Json::from(i),
+            (None, None) => Json::Null,
+        },
+        Value::F32(f) => float(f64::from(f)),
+        Value::F64(f) => float(f),
+        Value::String(s) => match s.into_str() {
+            Some(s) => Json::String(s),
+            None => Json::Null,
+        },
+        Value::Binary(bytes) => Json::String(STANDARD.encode(bytes)),
+        Value::Array(items) => Json::Array(
+            items
+                .into_iter()
+                .map(|item| to_json(item, redacted))
+                .collect(),
+        ),
+        // Keys are sorted here, as other crates in the build may turn on
+        // serde_json's `preserve_order`
+        Value::Map(entries) => {
+            let mut object = BTreeMap::new();
+            for (key, value) in entries {
+                let key = match key {
+                    Value::String(s) if s.is_str() => s.into_str().unwrap_or_default(),
+                    other => to_json(other, redacted).to_string(),
+                };
+                let value = if is_redacted(&key, redacted) {
+                    Json::String(REDACTED.to_string())
+                } else {
+                    to_json(value, redacted)
+                };
+                object.insert(key, value);
+            }
+            Json::Object(object.into_iter().collect())
+        }
+        Value::Ext(kind, data) => {
+            let mut object = Map::new();
+            object.insert("data".to_string(), Json::String(STANDARD.encode(data)));
+            object.insert("ext".to_string(), Json::from(kind));
+            Json::Object(object)
+        }
+    }
+}
+
+/// JSON has no NaN or infinities
+fn float(f: f64) -> Json {
+    Number::from_f64(f).map_or(Json::Null, Json::Number)
+}
+
+fn is_redacted<S: AsRef<str>>(key: &str, redacted: &[S]) -> bool {
+    redacted
+        .iter()
+        .any(|field| field.as_ref().eq_ignore_ascii_case(key))
+}
+
+/// Render `bytes` for a log line, without their content if they do not hold
+/// a MessagePack value
+#[cfg_attr(not(feature = "wasmer"), allow(dead_code))]
+pub(crate) fn payload_for_log(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return "<empty>".to_string();
+    }
+    transcode_msgpack_to_json(bytes)
+        .unwrap_or_else(|e| format!("<{} bytes, not MessagePack: {}>", bytes.len(), e))
+}
+
+impl ExternIO {
+    /// The payload as JSON, see [`transcode_msgpack_to_json`]
+    pub fn to_debug_json(&self) -> Result<String, HostError> {
+        transcode_msgpack_to_json(self.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_transcode_nested() {
+        #[derive(Serialize)]
+        struct Entry {
+            id: u32,
+            tags: Vec<&'static str>,
+            links: BTreeMap<&'static str, Vec<i64>>,
+            parent: Option<u8>,
+            weight: f64,
+        }
+
+        let entry = Entry {
+            id: 7,
+            tags: vec!["a", "b"],
+            links: BTreeMap::from([("next", vec![1, -2])]),
+            parent: None,
+            weight: 0.5,
+        };
+        let io = ExternIO::encode(&entry).unwrap();
+        assert_eq!(
+            io.to_debug_json().unwrap(),
+            r#"{"id":7,"links":{"next":[1,-2]},"parent":null,"tags":["a","b"],"weight":0.5}"#
+        );
+
+        let compact = ExternIO::encode_compact(&entry).unwrap();
+        assert_eq!(
+            compact.to_debug_json().unwrap(),
+            r#"[7,["a","b"],{"next":[1,-2]},null,0.5]"#
+        );
+    }
+
+    #[test]
+    fn test_transcode_binary_as_base64() {
+        let mut bytes = Vec::new();
+        let value = Value::Map(vec![
+            (
+                Value::from("hash"),
+                Value::Binary(vec![0xde, 0xad, 0xbe, 0xef]),
+            ),
+            (Value::from(1), Value::Ext(2, b"hi".to_vec())),
+        ]);
+        rmpv::encode::write_value(&mut bytes, &value).unwrap();
+
+        assert_eq!(
+            transcode_msgpack_to_json(&bytes).unwrap(),
+            r#"{"1":{"data":"aGk=","ext":2},"hash":"3q2+7w=="}"#
+        );
+    }
+
+    #[test]
+    fn test_transcode_rejects_malformed() {
+        assert!(matches!(
+            transcode_msgpack_to_json(&[0x92, 0x01]),
+            Err(HostError::Deserialization(_))
+        ));
+        assert!(matches!(
+            transcode_msgpack_to_json(&[0x01, 0x02]),
+            Err(HostError::Deserialization(_))
+        ));
+        // A five byte string holding one byte
+        assert!(payload_for_log(&[0xa5, b'h']).starts_with("<2 bytes, not MessagePack"));
+        assert_eq!(payload_for_log(&[]), "<empty>");
+    }
+
+    #[test]
+    fn test_redaction() {
+        #[derive(Serialize)]
+        struct Login {
+            user: &'static str,
+            password: &'static str,
+            keys: BTreeMap<&'static str, &'static str>,
+        }
+
+        let login = Login {
+            user: "alice",
+            password: "hunter2",
+            keys: BTreeMap::from([("Private_Key", "abc"), ("public_key", "def")]),
+        };
+        let bytes = ExternIO::encode(&login).unwrap().into_vec();
+        assert_eq!(
+            transcode_redacting(&bytes, DEFAULT_REDACTED_FIELDS).unwrap(),
+            r#"{"keys":{"Private_Key":"[redacted]","public_key":"def"},"password":"[redacted]","user":"alice"}"#
+        );
+        assert_eq!(
+            transcode_redacting(&bytes, &["user", "keys"]).unwrap(),
+            r#"{"keys":"[redacted]","password":"hunter2","user":"[redacted]"}"#
+        );
+    }
+}
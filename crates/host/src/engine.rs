@@ -1,25 +1,35 @@
 //! WASM engine configuration and management
 
-use crate::module::ModuleCache;
-use crate::{HostError, DEFAULT_METERING_LIMIT};
-use std::sync::Arc;
+use crate::backend::{BackendModule, WasmBackend};
+use crate::capabilities::EngineCapabilities;
+use crate::failure::FailureTracker;
+use crate::metering::MeteringLedger;
+use crate::module::{CacheGcPolicy, ModuleCache};
+use crate::{
+    ExternEncoding, HostError, DEFAULT_MAX_INPUT_LEN, DEFAULT_MAX_OUTPUT_LEN,
+    DEFAULT_METERING_LIMIT,
+};
+use aingle_wasmer_codec::compute_checksum;
+use aingle_wasmer_common::{
+    ChecksumKind, GuestAbi, SliceOrder, VersionPolicy, GUEST_WARMUP_EXPORT,
+};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
-#[cfg(feature = "wasmer_sys_dev")]
-use wasmer::sys::Cranelift;
-
-#[cfg(feature = "wasmer_sys_prod")]
-use wasmer::sys::LLVM;
-
-#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+#[cfg(feature = "wasmer")]
+use crate::wasmer_backend::WasmerBackend;
+#[cfg(feature = "wasmer")]
 use wasmer::{Engine, Module};
 
 #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
-use wasmer_middlewares::Metering;
+use wasmer::sys::ModuleMiddleware;
 
 /// Configuration for the WASM engine
 #[derive(Clone, Debug)]
 pub struct EngineConfig {
     /// Maximum operations before timeout
+    ///
+    /// Not enforced with the `wasmer_js` backend, which cannot meter guests.
     pub metering_limit: u64,
     /// Enable NaN canonicalization for determinism
     pub canonicalize_nans: bool,
@@ -27,6 +37,61 @@ pub struct EngineConfig {
     pub cache_path: Option<std::path::PathBuf>,
     /// Static memory bound (for iOS compatibility)
     pub static_memory_bound: u32,
+    /// Clean up the disk cache with this policy when the engine is created
+    pub cache_gc: Option<CacheGcPolicy>,
+    /// Maximum input size in bytes passed to a guest call
+    pub max_input_len: usize,
+    /// Maximum output size in bytes read back from a guest call
+    pub max_output_len: usize,
+    /// Bytes the guest arena makes room for right after instantiation, so
+    /// the first call does not grow memory
+    pub guest_prealloc_bytes: Option<u32>,
+    /// Bytes [`Env::move_bytes_to_guest`](crate::Env::move_bytes_to_guest)
+    /// and the other host writes to guest memory copy per memory view
+    ///
+    /// `None` copies payloads in one go. Chunks bound how long a single copy
+    /// holds the store for multi-megabyte payloads, at the cost of a view
+    /// and a bounds check per chunk; the `move_bytes_to_guest` group of the
+    /// `call` benchmark compares both.
+    pub guest_write_chunk_bytes: Option<usize>,
+    /// Size of the buffer host writes reuse in place of allocating in the
+    /// guest, reserved through its `__aingle_guest_scratch` export after
+    /// instantiation
+    ///
+    /// Saves a call into the guest allocator per write that fits, but data
+    /// written there is only valid until the next write, so guests must
+    /// consume host responses before making another host call, as the guest
+    /// crate does. Guests without the export allocate as usual.
+    pub guest_scratch_bytes: Option<u32>,
+    /// Checksum of the envelopes instances exchange with their guest
+    ///
+    /// Guests that do not advertise CRC32C support stay on CRC32, see
+    /// [`WasmInstance::set_checksum`](crate::WasmInstance::set_checksum).
+    pub checksum: ChecksumKind,
+    /// Charge the time guests spend in host calls against their metering
+    /// budget, which otherwise only counts WASM instructions
+    ///
+    /// Applies to the standard imports and the functions of a
+    /// [`HostFunctionRegistry`](crate::HostFunctionRegistry); imports added
+    /// with [`ImportBuilder::define`](crate::ImportBuilder::define) are not
+    /// measured. Not enforced with the `wasmer_js` backend.
+    pub charge_host_calls: Option<HostCallCost>,
+    /// Export instances call with an empty payload right after
+    /// instantiation, if the guest has it, so the first real call does not
+    /// pay for the guest's lazy initialization
+    ///
+    /// Failures are returned as [`HostError::WarmupFailed`] instead of an
+    /// instance. `None` skips warm-up.
+    pub warmup_export: Option<String>,
+    /// Points the warm-up call may spend, on top of the
+    /// [`EngineConfig::metering_limit`] left to the calls that follow
+    pub warmup_metering_limit: u64,
+    /// Additional compiler middlewares, built for every module compiled
+    ///
+    /// Metering is always pushed first; these follow in vector order, so each
+    /// sees the operators already rewritten by the ones before it.
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    pub extra_middlewares: Vec<MiddlewareFactory>,
 }
 
 impl Default for EngineConfig {
@@ -36,71 +101,279 @@ impl Default for EngineConfig {
             canonicalize_nans: true,
             cache_path: None,
             static_memory_bound: 0x4000,
+            cache_gc: None,
+            max_input_len: DEFAULT_MAX_INPUT_LEN,
+            max_output_len: DEFAULT_MAX_OUTPUT_LEN,
+            guest_prealloc_bytes: None,
+            guest_write_chunk_bytes: None,
+            guest_scratch_bytes: None,
+            checksum: ChecksumKind::Crc32,
+            charge_host_calls: None,
+            warmup_export: Some(GUEST_WARMUP_EXPORT.to_string()),
+            warmup_metering_limit: DEFAULT_METERING_LIMIT,
+            #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+            extra_middlewares: Vec::new(),
+        }
+    }
+}
+
+impl EngineConfig {
+    /// Hash of the settings compiled into modules: the metering limit, NaN
+    /// canonicalization, the static memory bound and the extra middlewares,
+    /// by their `Debug` output
+    ///
+    /// Modules compiled under different hashes run differently, so the
+    /// cache of a [`WasmEngine`] does not load the artifacts of one under
+    /// another, see [`ModuleCache::with_engine`].
+    pub fn artifact_hash(&self) -> u32 {
+        #[allow(unused_mut)]
+        let mut settings = format!(
+            "metering_limit={};canonicalize_nans={};static_memory_bound={}",
+            self.metering_limit, self.canonicalize_nans, self.static_memory_bound
+        );
+        #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+        for middleware in &self.extra_middlewares {
+            settings.push_str(&format!(";{:?}", middleware));
+        }
+        compute_checksum(settings.as_bytes())
+    }
+}
+
+/// Builds the compiler middleware of [`EngineConfig::extra_middlewares`]
+/// for each module an engine compiles
+///
+/// Middlewares keeping state about the module they instrument, as Wasmer's
+/// `Metering` does, can only be used for one module; a fresh one is built
+/// for every module instead.
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+#[derive(Clone)]
+pub struct MiddlewareFactory(Arc<dyn Fn() -> Arc<dyn ModuleMiddleware> + Send + Sync>);
+
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+impl MiddlewareFactory {
+    /// Build middlewares with `build`
+    pub fn new(build: impl Fn() -> Arc<dyn ModuleMiddleware> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(build))
+    }
+
+    /// Build the middleware for a module
+    pub fn build(&self) -> Arc<dyn ModuleMiddleware> {
+        (self.0)()
+    }
+}
+
+/// Shows a freshly built middleware, by which
+/// [`EngineConfig::artifact_hash`] tells middlewares apart
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+impl std::fmt::Debug for MiddlewareFactory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("MiddlewareFactory")
+            .field(&self.build())
+            .finish()
+    }
+}
+
+/// Metering points a host call costs the guest, see
+/// [`EngineConfig::charge_host_calls`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostCallCost {
+    /// The same points for every call
+    PerCall(u64),
+    /// Points per microsecond spent in the call
+    PerMicrosecond(u64),
+}
+
+impl HostCallCost {
+    /// Points charged for a host call that took `elapsed`
+    pub fn points(&self, elapsed: Duration) -> u64 {
+        match *self {
+            Self::PerCall(points) => points,
+            Self::PerMicrosecond(points) => {
+                let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+                points.saturating_mul(micros)
+            }
         }
     }
 }
 
+/// Per-call overrides for engine-level settings
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CallOptions {
+    /// Override for [`EngineConfig::max_input_len`]
+    pub max_input_len: Option<usize>,
+    /// Override for [`EngineConfig::max_output_len`]
+    pub max_output_len: Option<usize>,
+    /// ABI of the guest, as cached by `Env::guest_abi`; probed by
+    /// `guest::call` on every call when unset
+    pub abi: Option<GuestAbi>,
+    /// Guest protocol versions `guest::call` accepts, older supported ones
+    /// when unset
+    pub version_policy: Option<VersionPolicy>,
+    /// Encoding of the input of typed calls, named when unset; compact
+    /// only reaches guests reading it, see [`ExternEncoding::negotiate`]
+    pub encoding: Option<ExternEncoding>,
+    /// Order of the packed results of legacy guests, detected from their
+    /// results when unset
+    pub slice_order: Option<SliceOrder>,
+    /// Bytes the functions of a [`HostFunctionRegistry`](crate::HostFunctionRegistry)
+    /// may exchange with the guest during the call, arguments and responses
+    /// together; host calls past it fail with
+    /// [`ErrorKind::ResourceExhausted`](aingle_wasmer_common::ErrorKind::ResourceExhausted).
+    /// Unlimited when unset.
+    pub max_host_io_bytes: Option<u64>,
+    /// The caller's assertion that the function is pure, letting
+    /// [`WasmInstance`](crate::WasmInstance) calls and `guest::call_cached`
+    /// answer it from a `ResultCache` with the `result-cache` feature;
+    /// ignored otherwise
+    pub cacheable: Option<PurityAssertion>,
+    /// Record each step of the call in a [`CallTrace`](crate::CallTrace),
+    /// returned by `guest::call_explained`
+    pub explain: bool,
+    /// Let a host function call back into the instance from within a call
+    /// through [`Env::call_guest`](crate::Env::call_guest), instead of
+    /// failing with [`HostError::ReentrantCall`]
+    ///
+    /// The nested call runs on an arena of its own when the guest exports
+    /// `__aingle_arena_push` and `__aingle_arena_pop`, as guests built on
+    /// the guest crate do; others share their allocator with the call they
+    /// are nested in.
+    pub allow_reentrancy: bool,
+}
+
+/// Assertion that a guest function is deterministic and read-only: its
+/// output depends on its input bytes alone, and calling it has no effect
+///
+/// Required by [`CallOptions::cacheable`], so outputs are only cached where
+/// the caller vouched for it; caching any other function serves stale
+/// outputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PurityAssertion(());
+
+impl PurityAssertion {
+    /// Assert that the function called is pure
+    pub const fn assert_pure() -> Self {
+        Self(())
+    }
+}
+
 /// WASM execution engine
+///
+/// A thin wrapper over a [`WasmBackend`]; Wasmer is used by default.
 pub struct WasmEngine {
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
-    inner: Engine,
+    backend: Arc<dyn WasmBackend>,
     config: EngineConfig,
     cache: Arc<ModuleCache>,
+    ledger: Arc<MeteringLedger>,
+    failures: Option<Arc<FailureTracker>>,
+    capabilities: OnceLock<EngineCapabilities>,
 }
 
 impl WasmEngine {
     /// Create a new WASM engine with the given configuration
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    #[cfg(feature = "wasmer")]
     pub fn new(config: EngineConfig) -> Result<Self, HostError> {
-        use std::sync::Arc as StdArc;
-        use wasmer::sys::{BaseTunables, CompilerConfig, NativeEngineExt};
-
-        let cost_function = |_: &wasmer::wasmparser::Operator| -> u64 { 1 };
-        let metering = StdArc::new(Metering::new(config.metering_limit, cost_function));
-
-        #[cfg(feature = "wasmer_sys_dev")]
-        let mut compiler = Cranelift::default();
+        let backend = WasmerBackend::new(&config)?;
+        Ok(Self::with_backend(config, Arc::new(backend)))
+    }
 
-        #[cfg(feature = "wasmer_sys_prod")]
-        let mut compiler = LLVM::default();
+    /// Create a new WASM engine running on the given backend
+    ///
+    /// On a Wasmer backend, [`WasmEngine::compile_cached`] compiles with the
+    /// same engine as [`WasmEngine::compile`], metering included.
+    pub fn with_backend(config: EngineConfig, backend: Arc<dyn WasmBackend>) -> Self {
+        let cache = Arc::new(module_cache(&config, backend.as_ref()));
 
-        if config.canonicalize_nans {
-            compiler.canonicalize_nans(true);
+        if let Some(policy) = config.cache_gc {
+            match cache.gc_disk(policy.max_age, policy.max_total_bytes) {
+                Ok(report) => tracing::debug!(
+                    "Module cache cleanup removed {} files ({} bytes)",
+                    report.files_removed,
+                    report.bytes_removed
+                ),
+                Err(e) => tracing::warn!("Module cache cleanup failed: {}", e),
+            }
         }
-        compiler.push_middleware(metering);
 
-        let mut engine = Engine::from(compiler);
-
-        // iOS compatibility tunables
-        engine.set_tunables(BaseTunables {
-            static_memory_bound: config.static_memory_bound.into(),
-            static_memory_offset_guard_size: 0x1_0000,
-            dynamic_memory_offset_guard_size: 0x1_0000,
-        });
+        Self {
+            backend,
+            config,
+            cache,
+            ledger: Arc::new(MeteringLedger::default()),
+            failures: None,
+            capabilities: OnceLock::new(),
+        }
+    }
 
-        Ok(Self {
-            inner: engine,
-            config: config.clone(),
-            cache: Arc::new(ModuleCache::new(config.cache_path.clone())),
-        })
+    /// Quarantine modules that keep failing, as tracked by `tracker`
+    ///
+    /// Applies to instances created afterwards and attributed to a module key.
+    pub fn with_failure_tracker(mut self, tracker: Arc<FailureTracker>) -> Self {
+        self.failures = Some(tracker);
+        self
     }
 
     /// Compile WASM bytes into a module
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
-    pub fn compile(&self, wasm: &[u8]) -> Result<Module, HostError> {
-        Module::new(&self.inner, wasm).map_err(|e| HostError::Compilation(e.to_string()))
+    pub fn compile(&self, wasm: &[u8]) -> Result<Arc<dyn BackendModule>, HostError> {
+        self.backend.compile(wasm)
     }
 
     /// Compile with caching using a 32-byte key
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    ///
+    /// Artifacts written to the disk cache record the
+    /// [capabilities](WasmEngine::capabilities) of this engine.
+    #[cfg(feature = "wasmer")]
     pub fn compile_cached(&self, key: [u8; 32], wasm: &[u8]) -> Result<Arc<Module>, HostError> {
+        if self.cache.cache_path().is_some() {
+            self.cache.record_capabilities(self.capabilities());
+        }
         self.cache.get(key, wasm)
     }
 
+    /// Get the module cache behind [`WasmEngine::compile_cached`]
+    pub fn module_cache(&self) -> &Arc<ModuleCache> {
+        &self.cache
+    }
+
+    /// Get a reference to the backend
+    pub fn backend(&self) -> &dyn WasmBackend {
+        self.backend.as_ref()
+    }
+
     /// Get a reference to the inner Wasmer engine
+    ///
+    /// Returns `None` when the engine runs on a different backend.
+    #[cfg(feature = "wasmer")]
+    pub fn inner(&self) -> Option<&Engine> {
+        self.backend
+            .as_any()
+            .downcast_ref::<WasmerBackend>()
+            .map(WasmerBackend::engine)
+    }
+
+    /// Get the metering limit compiled into every module
+    ///
+    /// Each module is instrumented by a metering middleware of its own, so
+    /// points are read and reset per instance, through
+    /// [`BackendInstance::remaining_points`](crate::BackendInstance::remaining_points)
+    /// and [`BackendInstance::set_remaining_points`](crate::BackendInstance::set_remaining_points).
+    /// Returns `None` when the engine runs on a different backend or on a
+    /// wrapped engine.
     #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
-    pub fn inner(&self) -> &Engine {
-        &self.inner
+    pub fn metering_limit(&self) -> Option<u64> {
+        self.backend
+            .as_any()
+            .downcast_ref::<WasmerBackend>()
+            .and_then(WasmerBackend::metering_limit)
+    }
+
+    /// Get the ledger accumulating metering points per module
+    pub fn ledger(&self) -> &Arc<MeteringLedger> {
+        &self.ledger
+    }
+
+    /// Get the failure tracker, if one is attached
+    pub fn failure_tracker(&self) -> Option<&Arc<FailureTracker>> {
+        self.failures.as_ref()
     }
 
     /// Get the configuration
@@ -108,13 +381,56 @@ impl WasmEngine {
         &self.config
     }
 
+    /// Report what this engine compiles with and enforces
+    ///
+    /// The supported proposals are detected by compiling a probe module for
+    /// each on the first call; later calls return the same report.
+    pub fn capabilities(&self) -> &EngineCapabilities {
+        self.capabilities.get_or_init(|| {
+            #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+            let metering = self.metering_limit().is_some();
+            #[cfg(not(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod")))]
+            let metering = false;
+
+            EngineCapabilities::detect(self.backend.as_ref(), &self.config, metering)
+        })
+    }
+
     /// Clear the module cache
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    #[cfg(feature = "wasmer")]
     pub fn clear_cache(&self) {
         self.cache.clear();
     }
 }
 
+/// The module cache of an engine on `backend`
+///
+/// Wasmer backends share their engine with the cache. Engines wrapped with
+/// [`WasmerBackend::from_engine`] cache in memory only, as nothing tells
+/// their artifacts apart from those of other settings.
+fn module_cache(config: &EngineConfig, backend: &dyn WasmBackend) -> ModuleCache {
+    #[cfg(feature = "wasmer")]
+    if let Some(backend) = backend.as_any().downcast_ref::<WasmerBackend>() {
+        let engine = backend.engine().clone();
+        return match backend.artifact_hash() {
+            Some(hash) => ModuleCache::with_engine(config.cache_path.clone(), engine, hash),
+            None => {
+                if let Some(path) = &config.cache_path {
+                    tracing::warn!(
+                        "Caching the modules of a wrapped engine in memory only, not in {}",
+                        path.display()
+                    );
+                }
+                // No artifact is written, so the hash is never recorded
+                ModuleCache::with_engine(None, engine, 0)
+            }
+        };
+    }
+    #[cfg(not(feature = "wasmer"))]
+    let _ = backend;
+    ModuleCache::new(config.cache_path.clone())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -125,5 +441,254 @@ mod tests {
         let config = EngineConfig::default();
         let engine = WasmEngine::new(config).unwrap();
         assert!(engine.config().canonicalize_nans);
+        assert_eq!(engine.backend().name(), "wasmer");
+        assert!(engine.inner().is_some());
+        assert_eq!(engine.metering_limit(), Some(DEFAULT_METERING_LIMIT));
+    }
+
+    #[test]
+    fn test_artifact_hash() {
+        let hash = EngineConfig::default().artifact_hash();
+        assert_eq!(hash, EngineConfig::default().artifact_hash());
+
+        // Settings compiled into modules change it, the others do not
+        for config in [
+            EngineConfig {
+                metering_limit: 1,
+                ..EngineConfig::default()
+            },
+            EngineConfig {
+                canonicalize_nans: false,
+                ..EngineConfig::default()
+            },
+            EngineConfig {
+                static_memory_bound: 0x8000,
+                ..EngineConfig::default()
+            },
+        ] {
+            assert_ne!(config.artifact_hash(), hash, "{:?}", config);
+        }
+        let runtime_only = EngineConfig {
+            cache_path: Some("/modules".into()),
+            max_input_len: 1,
+            warmup_export: None,
+            ..EngineConfig::default()
+        };
+        assert_eq!(runtime_only.artifact_hash(), hash);
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_compile_cached_is_metered() {
+        use crate::{ImportBuilder, WasmInstance};
+        use wasmer::Store;
+        use wasmer_middlewares::metering::{get_remaining_points, MeteringPoints};
+
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (memory (export "memory") 1)
+                (func (export "spin") (param i32 i32) (result i64)
+                    (loop $forever (br $forever))
+                    (i64.const 0)))
+            "#,
+        )
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let engine = WasmEngine::new(EngineConfig {
+            metering_limit: 1_000_000,
+            cache_path: Some(dir.path().to_path_buf()),
+            warmup_export: None,
+            ..EngineConfig::default()
+        })
+        .unwrap();
+        assert!(engine
+            .module_cache()
+            .compiler()
+            .ends_with(&format!("config {:08x}", engine.config().artifact_hash())));
+
+        // Through the backend
+        let module = engine.compile(&wasm).unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+        assert!(matches!(
+            instance.call_raw("spin", b""),
+            Err(HostError::MeteringExceeded)
+        ));
+
+        // Through the cache, compiled and then loaded from disk
+        for _ in 0..2 {
+            let module = engine.compile_cached([3; 32], &wasm).unwrap();
+            let mut store = Store::new(engine.inner().unwrap().clone());
+            let instance = ImportBuilder::new(&mut store)
+                .instantiate(&mut store, &module)
+                .unwrap();
+            let spin = instance.exports.get_function("spin").unwrap();
+            assert!(spin.call(&mut store, &[0.into(), 0.into()]).is_err());
+            assert!(matches!(
+                get_remaining_points(&mut store, &instance),
+                MeteringPoints::Exhausted
+            ));
+            engine.clear_cache();
+        }
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_compile_many_modules() {
+        use crate::WasmInstance;
+
+        let spin = |export: &str| {
+            wat::parse_str(format!(
+                r#"
+                (module
+                    (memory (export "memory") 1)
+                    (func (export "{}") (param i32 i32) (result i64)
+                        (loop $forever (br $forever))
+                        (i64.const 0)))
+                "#,
+                export
+            ))
+            .unwrap()
+        };
+        let wasms = [spin("spin"), spin("whirl"), spin("twirl")];
+        let engine = WasmEngine::new(EngineConfig {
+            metering_limit: 1_000_000,
+            warmup_export: None,
+            ..EngineConfig::default()
+        })
+        .unwrap();
+
+        // Each module is metered, whether compiled by the backend or the cache
+        for (i, (wasm, export)) in wasms.iter().zip(["spin", "whirl", "twirl"]).enumerate() {
+            let compiled = engine.compile(wasm).unwrap();
+            let mut instance = WasmInstance::new(&engine, compiled.as_ref()).unwrap();
+            assert!(matches!(
+                instance.call_raw(export, b""),
+                Err(HostError::MeteringExceeded)
+            ));
+
+            let cached = engine.compile_cached([i as u8; 32], wasm).unwrap();
+            let mut instance = WasmInstance::new(&engine, cached.as_ref()).unwrap();
+            assert!(matches!(
+                instance.call_raw(export, b""),
+                Err(HostError::MeteringExceeded)
+            ));
+        }
+        assert_eq!(engine.module_cache().len(), wasms.len());
+    }
+
+    #[test]
+    fn test_host_call_cost() {
+        let elapsed = Duration::from_micros(250);
+        assert_eq!(HostCallCost::PerCall(40).points(elapsed), 40);
+        assert_eq!(HostCallCost::PerMicrosecond(3).points(elapsed), 750);
+        assert_eq!(
+            HostCallCost::PerMicrosecond(2).points(Duration::MAX),
+            u64::MAX
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "wasmer_sys_dev")]
+    fn test_capabilities() {
+        use crate::CompilerKind;
+
+        let engine = WasmEngine::new(EngineConfig {
+            metering_limit: 1234,
+            ..EngineConfig::default()
+        })
+        .unwrap();
+        let capabilities = engine.capabilities();
+        assert_eq!(capabilities.backend, "wasmer");
+        assert_eq!(capabilities.compiler, CompilerKind::Cranelift);
+        assert!(capabilities.wasmer_version.is_some());
+        assert!(capabilities.metering);
+        assert_eq!(capabilities.metering_limit, 1234);
+        assert!(capabilities.canonicalize_nans);
+        assert_eq!(capabilities.static_memory_bound, 0x4000);
+        assert!(capabilities.features.bulk_memory);
+        assert!(capabilities.features.multi_value);
+        assert!(std::ptr::eq(capabilities, engine.capabilities()));
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_extra_middleware() {
+        use crate::WasmInstance;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use wasmer::sys::{
+            FunctionMiddleware, MiddlewareError, MiddlewareReaderState, ModuleMiddleware,
+        };
+        use wasmer::wasmparser::Operator;
+        use wasmer::LocalFunctionIndex;
+
+        /// Counts every operator fed through the compiler
+        #[derive(Debug, Default)]
+        struct OperatorCounter(Arc<AtomicUsize>);
+
+        impl ModuleMiddleware for OperatorCounter {
+            fn generate_function_middleware(
+                &self,
+                _: LocalFunctionIndex,
+            ) -> Box<dyn FunctionMiddleware> {
+                Box::new(FunctionCounter(self.0.clone()))
+            }
+        }
+
+        #[derive(Debug)]
+        struct FunctionCounter(Arc<AtomicUsize>);
+
+        impl FunctionMiddleware for FunctionCounter {
+            fn feed<'a>(
+                &mut self,
+                operator: Operator<'a>,
+                state: &mut MiddlewareReaderState<'a>,
+            ) -> Result<(), MiddlewareError> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                state.push_operator(operator);
+                Ok(())
+            }
+        }
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let built = Arc::new(AtomicUsize::new(0));
+        let factory = {
+            let (count, built) = (count.clone(), built.clone());
+            MiddlewareFactory::new(move || {
+                built.fetch_add(1, Ordering::SeqCst);
+                Arc::new(OperatorCounter(count.clone()))
+            })
+        };
+        let config = EngineConfig {
+            extra_middlewares: vec![factory],
+            ..Default::default()
+        };
+        let engine = WasmEngine::new(config).unwrap();
+
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (memory (export "memory") 1)
+                (func (export "echo") (param $ptr i32) (param $len i32) (result i64)
+                    (i64.or
+                        (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+                        (i64.extend_i32_u (local.get $len)))))
+            "#,
+        )
+        .unwrap();
+        let module = engine.compile(&wasm).unwrap();
+        // The 8 operators of `echo`, `end` included, and any metering adds
+        let fed = count.load(Ordering::SeqCst);
+        assert!(fed >= 8, "{}", fed);
+
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+        assert_eq!(instance.call_raw("echo", b"ping").unwrap(), b"ping");
+        assert_eq!(count.load(Ordering::SeqCst), fed);
+
+        // Every module gets a middleware of its own
+        let built_before = built.load(Ordering::SeqCst);
+        engine.compile(&wasm).unwrap();
+        assert_eq!(built.load(Ordering::SeqCst), built_before + 1);
+        assert_eq!(count.load(Ordering::SeqCst), 2 * fed);
     }
 }
@@ -3,15 +3,33 @@
 //! Provides the execution environment for WASM guest code, including
 //! memory management and data transfer between host and guest.
 
-use crate::HostError;
-use aingle_wasmer_common::WasmSlice;
+use crate::backend::{
+    allocate64_with_32, guest_allocation, guest_allocation64, read_enveloped, read_guest_bytes,
+    read_guest_bytes_into, read_ref, read_typed, stream_to_guest, with_guest_bytes,
+    write_enveloped, write_guest_bytes64, write_ref, BackendMemory, StreamSink,
+    DEFAULT_MEMORY_EXPORT,
+};
+use crate::random::RandomStream;
+use crate::{CallOptions, HostCallCost, HostError, ProbeGuestAbi};
+use aingle_wasmer_common::{
+    decode_empty, is_unit, GuestAbi, SliceOrder, WasmDecode, WasmEncode, WasmRef, WasmSlice,
+    GUEST_ALLOCATE64_EXPORT, GUEST_ARENA_POP_EXPORT, GUEST_ARENA_PUSH_EXPORT, GUEST_SCRATCH_EXPORT,
+    STREAM_BEGIN_EXPORT, STREAM_WRITE_EXPORT,
+};
+use parking_lot::Mutex;
 use serde::{de::DeserializeOwned, Serialize};
+use std::any::Any;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
-#[cfg(feature = "wasmer_sys_dev")]
-use wasmer::{Memory, StoreMut, TypedFunction};
-
-#[cfg(feature = "wasmer_sys_prod")]
-use wasmer::{Memory, StoreMut, TypedFunction};
+#[cfg(feature = "wasmer")]
+use crate::MemoryAccessFailure;
+#[cfg(feature = "wasmer")]
+use wasmer::{
+    AsStoreMut, AsStoreRef, Exports, Global, Instance, Memory, StoreMut, TypedFunction, Value,
+};
 
 /// Guest pointer type
 pub type GuestPtr = u32;
@@ -29,8 +47,79 @@ pub struct Env {
     pub memory: Option<Memory>,
     /// Function to allocate memory in the guest
     pub allocate: Option<TypedFunction<i32, i32>>,
+    /// Function to allocate memory in a 64-bit guest
+    pub allocate64: Option<TypedFunction<i64, i64>>,
     /// Function to deallocate memory in the guest
     pub deallocate: Option<TypedFunction<(i32, i32), ()>>,
+    /// Function opening a chunked input stream in the guest
+    pub stream_begin: Option<TypedFunction<i32, i32>>,
+    /// Function handing a chunk to an open stream in the guest
+    pub stream_write: Option<TypedFunction<(i32, i32, i32), i32>>,
+    /// Host-owned guest allocations not freed yet, shared between clones
+    outstanding: Arc<Mutex<Vec<(GuestPtr, Len)>>>,
+    /// ABI reported by the guest, probed on first use
+    abi: Arc<OnceLock<GuestAbi>>,
+    /// Order of the guest's packed results, once detected from a legacy
+    /// guest's result
+    slice_order: Arc<OnceLock<SliceOrder>>,
+    /// Source of `env.__aingle_random_bytes`, none if guests get no randomness
+    pub(crate) random: Option<Arc<Mutex<RandomStream>>>,
+    /// State of the embedder for its host functions, see [`Env::context`]
+    context: Option<Arc<dyn Any + Send + Sync>>,
+    /// Cost of host calls charged against the metering budget, if any
+    pub(crate) host_call_cost: Option<HostCallCost>,
+    /// Metering globals of the guest: remaining points and exhaustion flag
+    metering: Option<(Global, Global)>,
+    /// Points charged for host calls and not taken yet, shared between
+    /// clones
+    import_points: Arc<AtomicU64>,
+    /// Bytes host functions exchanged with the guest in the current call,
+    /// shared between clones
+    host_io: Arc<Mutex<HostIoTally>>,
+    /// Bytes host writes copy per memory view, all at once if unset
+    pub(crate) write_chunk: Option<usize>,
+    /// Buffer the guest reserved for host writes, see [`Env::scratch`]
+    scratch: Option<WasmSlice>,
+    /// Export of the guest memory, see [`Env::set_memory_export`]
+    memory_export: Option<String>,
+    /// Guest calls of the instance in progress, shared between clones
+    calls: Arc<AtomicU32>,
+}
+
+/// A guest call counted by [`Env::enter_call`], until dropped
+pub(crate) struct EnteredCall {
+    calls: Arc<AtomicU32>,
+    /// Whether the call is nested in another call of the instance
+    pub(crate) nested: bool,
+}
+
+impl Drop for EnteredCall {
+    fn drop(&mut self) {
+        self.calls.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Bytes guests exchanged with host functions during a call
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HostIo {
+    /// Bytes of arguments read from the guest
+    pub bytes_in: u64,
+    /// Bytes of responses written to the guest
+    pub bytes_out: u64,
+}
+
+impl HostIo {
+    /// Bytes in both directions
+    pub fn total(&self) -> u64 {
+        self.bytes_in.saturating_add(self.bytes_out)
+    }
+}
+
+/// [`HostIo`] of the current call with the quota it runs under
+#[derive(Default)]
+struct HostIoTally {
+    io: HostIo,
+    max_bytes: Option<u64>,
 }
 
 impl Env {
@@ -39,11 +128,265 @@ impl Env {
         Self::default()
     }
 
+    /// Take the memory and the guest exports from an instance
+    ///
+    /// A memory set before is kept if the instance exports none, as for
+    /// guests importing the memory created by the host.
+    pub fn attach(&mut self, instance: &Instance, store: &impl AsStoreRef) {
+        let exports = &instance.exports;
+        if let Some(memory) = exported_memory(exports, self.memory_export.as_deref()) {
+            self.memory = Some(memory.clone());
+        }
+        self.allocate = exports.get_typed_function(store, "__hc__allocate_1").ok();
+        self.allocate64 = exports
+            .get_typed_function(store, GUEST_ALLOCATE64_EXPORT)
+            .ok();
+        self.deallocate = exports.get_typed_function(store, "__hc__deallocate_1").ok();
+        self.stream_begin = exports.get_typed_function(store, STREAM_BEGIN_EXPORT).ok();
+        self.stream_write = exports.get_typed_function(store, STREAM_WRITE_EXPORT).ok();
+        self.metering = exports
+            .get_global("wasmer_metering_remaining_points")
+            .ok()
+            .cloned()
+            .zip(
+                exports
+                    .get_global("wasmer_metering_points_exhausted")
+                    .ok()
+                    .cloned(),
+            );
+    }
+
+    /// Take the memory from the export `name` on [`Env::attach`]
+    ///
+    /// Unset, the export `memory` is taken, or else the first memory
+    /// exported.
+    pub fn set_memory_export(&mut self, name: impl Into<String>) {
+        self.memory_export = Some(name.into());
+    }
+
+    /// Set the state host functions of this instance read with
+    /// [`Env::context`], such as the agent key or database handle of a cell
+    ///
+    /// Replaces any context set before. The context is dropped with the
+    /// environment: set on the [`FunctionEnv`](wasmer::FunctionEnv) of an
+    /// [`ImportBuilder`](crate::ImportBuilder), it lives as long as the
+    /// instance, in the store its functions are owned by. Clones of the
+    /// environment share it.
+    pub fn set_context(&mut self, context: Arc<dyn Any + Send + Sync>) {
+        self.context = Some(context);
+    }
+
+    /// The context set with [`Env::set_context`], if there is one of type
+    /// `T`
+    pub fn context<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        self.context.clone()?.downcast().ok()
+    }
+
+    /// Charge a host call that took `elapsed` against the guest's metering
+    /// budget, if host calls are charged and the guest is metered
+    ///
+    /// Fails with [`HostError::MeteringExceeded`] if the charge is more than
+    /// the budget holds, leaving it exhausted; host calls return the error
+    /// to abort the guest.
+    pub fn charge_host_call(
+        &self,
+        store: &mut impl AsStoreMut,
+        elapsed: Duration,
+    ) -> Result<(), HostError> {
+        let (Some(cost), Some((remaining, exhausted))) =
+            (self.host_call_cost, self.metering.as_ref())
+        else {
+            return Ok(());
+        };
+
+        let points = cost.points(elapsed);
+        let left = remaining.get(store).i64().unwrap_or(0) as u64;
+        self.import_points
+            .fetch_add(points.min(left), Ordering::Relaxed);
+        match left.checked_sub(points) {
+            Some(left) => remaining.set(store, Value::I64(left as i64))?,
+            None => {
+                remaining.set(store, Value::I64(0))?;
+                exhausted.set(store, Value::I32(1))?;
+                return Err(HostError::MeteringExceeded);
+            }
+        }
+        Ok(())
+    }
+
+    /// Start counting the bytes host functions exchange with the guest for
+    /// a new call, refusing host calls past `max_bytes`
+    ///
+    /// Done by [`Env::call_guest`] and [`WasmInstance`](crate::WasmInstance)
+    /// calls with [`CallOptions::max_host_io_bytes`].
+    pub fn start_host_io(&self, max_bytes: Option<u64>) {
+        *self.host_io.lock() = HostIoTally {
+            io: HostIo::default(),
+            max_bytes,
+        };
+    }
+
+    /// Bytes host functions exchanged with the guest since
+    /// [`Env::start_host_io`]
+    pub fn host_io(&self) -> HostIo {
+        self.host_io.lock().io
+    }
+
+    /// Count `bytes_in` and `bytes_out` of a host call, unless they would
+    /// exceed the quota of the current call
+    pub(crate) fn admit_host_io(&self, bytes_in: u64, bytes_out: u64) -> bool {
+        let mut tally = self.host_io.lock();
+        let io = HostIo {
+            bytes_in: tally.io.bytes_in.saturating_add(bytes_in),
+            bytes_out: tally.io.bytes_out.saturating_add(bytes_out),
+        };
+        if tally.max_bytes.is_some_and(|max| io.total() > max) {
+            return false;
+        }
+        tally.io = io;
+        true
+    }
+
+    /// Reserve a buffer of `bytes` through the guest's
+    /// `__aingle_guest_scratch` export, for [`Env::move_bytes_to_guest`] to
+    /// write to in place of allocating
+    ///
+    /// Done on instantiation with
+    /// [`EngineConfig::guest_scratch_bytes`](crate::EngineConfig::guest_scratch_bytes).
+    /// Returns whether the guest reserved it; guests without the export keep
+    /// allocating.
+    pub fn reserve_scratch(
+        &mut self,
+        store: &mut impl AsStoreMut,
+        instance: &Instance,
+        bytes: u32,
+    ) -> Result<bool, HostError> {
+        let Ok(reserve) = instance
+            .exports
+            .get_typed_function::<i32, i32>(&*store, GUEST_SCRATCH_EXPORT)
+        else {
+            return Ok(false);
+        };
+        let ptr = reserve
+            .call(store, bytes as i32)
+            .map_err(|e| HostError::MemoryAccess(format!("Failed to reserve scratch: {}", e)))?;
+        self.scratch = (ptr != 0).then(|| WasmSlice::new(ptr as u32, bytes));
+        Ok(self.scratch.is_some())
+    }
+
+    /// Buffer the guest reserved with [`Env::reserve_scratch`], if any
+    pub fn scratch(&self) -> Option<WasmSlice> {
+        self.scratch
+    }
+
+    /// Take the points charged for host calls since they were last taken
+    pub fn take_import_points(&self) -> u64 {
+        self.import_points.swap(0, Ordering::Relaxed)
+    }
+
     /// Check if the environment is initialized
     pub fn is_initialized(&self) -> bool {
         self.memory.is_some() && self.allocate.is_some() && self.deallocate.is_some()
     }
 
+    /// ABI reported by the guest, probed on first use and cached
+    ///
+    /// Pass it in [`CallOptions::abi`](crate::CallOptions::abi) to spare
+    /// `guest::call` probing the guest on every call.
+    pub fn guest_abi(&self, instance: &Instance, store: &mut StoreMut<'_>) -> GuestAbi {
+        *self.abi.get_or_init(|| GuestAbi::probe(instance, store))
+    }
+
+    /// Order a legacy guest packs its results in, once detected
+    ///
+    /// Set by [`Env::call_guest`] from the first result that tells; `None`
+    /// until then and for guests speaking the handshake.
+    pub fn slice_order(&self) -> Option<SliceOrder> {
+        self.slice_order.get().copied()
+    }
+
+    /// Call a guest function with the ABI and result order cached here
+    ///
+    /// Like [`guest::call_with_options`](crate::guest::call_with_options),
+    /// but the guest ABI is probed once, and the order of a legacy guest's
+    /// results is detected once and reused for the instance's later calls.
+    /// Values set in `options` take precedence.
+    ///
+    /// Host functions calling back into the instance while it is in a call
+    /// made here or through [`WasmInstance`](crate::WasmInstance) fail with
+    /// [`HostError::ReentrantCall`], unless
+    /// [`CallOptions::allow_reentrancy`] is set. Nested calls then run
+    /// between `__aingle_arena_push` and `__aingle_arena_pop` when the guest
+    /// exports them, and count against the host I/O quota of the outer
+    /// call.
+    pub fn call_guest(
+        &self,
+        store: &mut StoreMut<'_>,
+        instance: Arc<Instance>,
+        name: &str,
+        input: impl AsRef<[u8]>,
+        options: &CallOptions,
+    ) -> Result<Vec<u8>, wasmer::RuntimeError> {
+        let call = self
+            .enter_call(options.allow_reentrancy)
+            .map_err(|e| wasmer::RuntimeError::user(Box::new(e)))?;
+        let abi = match options.abi {
+            Some(abi) => abi,
+            None => self.guest_abi(&instance, store),
+        };
+        let options = CallOptions {
+            abi: Some(abi),
+            ..*options
+        };
+        if !call.nested {
+            self.start_host_io(options.max_host_io_bytes);
+            return crate::guest::call_detecting_order(
+                store,
+                instance,
+                name,
+                input,
+                &options,
+                &self.slice_order,
+            );
+        }
+
+        arena_frame(store, &instance, GUEST_ARENA_PUSH_EXPORT)?;
+        let result = crate::guest::call_detecting_order(
+            store,
+            instance.clone(),
+            name,
+            input,
+            &options,
+            &self.slice_order,
+        );
+        let popped = arena_frame(store, &instance, GUEST_ARENA_POP_EXPORT);
+        let output = result?;
+        popped?;
+        Ok(output)
+    }
+
+    /// Guest calls of the instance in progress, nested calls included
+    pub fn calls_in_progress(&self) -> u32 {
+        self.calls.load(Ordering::Acquire)
+    }
+
+    /// Count a guest call of the instance as in progress until the
+    /// returned guard drops
+    ///
+    /// Fails with [`HostError::ReentrantCall`] if a call is in progress
+    /// already, unless `allow_reentrancy` is set.
+    pub(crate) fn enter_call(&self, allow_reentrancy: bool) -> Result<EnteredCall, HostError> {
+        let outer = self.calls.fetch_add(1, Ordering::AcqRel);
+        let call = EnteredCall {
+            calls: self.calls.clone(),
+            nested: outer > 0,
+        };
+        if call.nested && !allow_reentrancy {
+            return Err(HostError::ReentrantCall);
+        }
+        Ok(call)
+    }
+
     /// Consume and deserialize input from guest memory
     ///
     /// Reads bytes from guest memory and deserializes them into the expected type.
@@ -57,6 +400,9 @@ impl Env {
     /// * `guest_ptr` - Pointer to the start of the data in guest memory
     /// * `len` - Number of bytes to read
     ///
+    /// An empty input decodes only as unit, see
+    /// [`decode_empty`](aingle_wasmer_common::decode_empty).
+    ///
     /// # Returns
     /// * `Ok(T)` - The deserialized value
     /// * `Err(HostError)` - If memory access or deserialization fails
@@ -66,10 +412,16 @@ impl Env {
         guest_ptr: GuestPtr,
         len: Len,
     ) -> Result<T, HostError> {
-        let bytes = self.consume_bytes_from_guest(store, guest_ptr, len)?;
+        if len == 0 {
+            return decode_empty().map_err(|e| {
+                HostError::Serialization(format!("Failed to deserialize input: {}", e))
+            });
+        }
         // Use aingle_middleware_bytes for consistent serialization format
-        aingle_middleware_bytes::decode(&bytes)
-            .map_err(|e| HostError::Serialization(format!("Failed to deserialize input: {}", e)))
+        self.consume_with(store, guest_ptr, len, |bytes| {
+            aingle_middleware_bytes::decode::<_, T>(bytes)
+        })?
+        .map_err(|e| HostError::Serialization(format!("Failed to deserialize input: {}", e)))
     }
 
     /// Consume bytes from guest memory
@@ -91,35 +443,41 @@ impl Env {
         guest_ptr: GuestPtr,
         len: Len,
     ) -> Result<Vec<u8>, HostError> {
-        let memory = self
-            .memory
-            .as_ref()
-            .ok_or_else(|| HostError::MemoryAccess("Memory not initialized".to_string()))?;
-
-        let view = memory.view(store);
-        let start = guest_ptr as u64;
-        let end = start + len as u64;
-
-        if end > view.data_size() {
-            return Err(HostError::MemoryAccess(format!(
-                "Out of bounds: {}..{} > {}",
-                start,
-                end,
-                view.data_size()
-            )));
-        }
+        read_guest_bytes(&mut self.bind(store), guest_ptr, len)
+    }
 
-        let mut buffer = vec![0u8; len as usize];
-        view.read(start, &mut buffer)
-            .map_err(|e| HostError::MemoryAccess(format!("Failed to read memory: {}", e)))?;
+    /// Consume bytes from guest memory into a caller-owned buffer
+    ///
+    /// `out` is cleared and reused, so repeated reads avoid allocating.
+    pub fn consume_bytes_into(
+        &self,
+        store: &mut StoreMut<'_>,
+        guest_ptr: GuestPtr,
+        len: Len,
+        out: &mut Vec<u8>,
+    ) -> Result<(), HostError> {
+        read_guest_bytes_into(&mut self.bind(store), guest_ptr, len, out)
+    }
 
-        Ok(buffer)
+    /// Consume bytes from guest memory and pass them to `f`
+    ///
+    /// Small payloads are read into a reused per-thread buffer instead of a
+    /// fresh allocation; use this when the bytes are decoded and dropped.
+    pub fn consume_with<R>(
+        &self,
+        store: &mut StoreMut<'_>,
+        guest_ptr: GuestPtr,
+        len: Len,
+        f: impl FnOnce(&[u8]) -> R,
+    ) -> Result<R, HostError> {
+        with_guest_bytes(&mut self.bind(store), guest_ptr, len, f)
     }
 
     /// Move data to guest memory
     ///
     /// Serializes the data and writes it to guest memory, returning the pointer/length.
     /// This handles allocation in the guest and returns a combined u64 value.
+    /// Unit data is returned as an empty slice without touching the guest.
     ///
     /// # Type Parameters
     /// * `T` - The type to serialize (must implement Serialize)
@@ -136,6 +494,9 @@ impl Env {
         store: &mut StoreMut<'_>,
         data: T,
     ) -> Result<u64, HostError> {
+        if is_unit(&data) {
+            return Ok(WasmSlice::empty().pack());
+        }
         // Use aingle_middleware_bytes for consistent serialization format
         let bytes = aingle_middleware_bytes::encode(&data)
             .map_err(|e| HostError::Serialization(format!("Failed to serialize: {}", e)))?;
@@ -159,29 +520,117 @@ impl Env {
         store: &mut StoreMut<'_>,
         bytes: &[u8],
     ) -> Result<u64, HostError> {
-        let memory = self
-            .memory
-            .as_ref()
-            .ok_or_else(|| HostError::MemoryAccess("Memory not initialized".to_string()))?;
-        let allocate = self.allocate.as_ref().ok_or_else(|| {
-            HostError::MemoryAccess("Allocate function not initialized".to_string())
-        })?;
+        let len = bytes.len() as Len;
+        if let Some(scratch) = self.scratch.filter(|scratch| len <= scratch.len) {
+            self.bind(store).write_memory(scratch.ptr as u64, bytes)?;
+            return Ok(WasmSlice::new(scratch.ptr, len).pack());
+        }
 
-        let len = bytes.len() as i32;
+        let ptr = self.bind(store).allocate(len)?;
+        if let Err(e) = self.bind(store).write_memory(ptr as u64, bytes) {
+            // A write failing halfway leaves nothing the guest could use
+            let _ = self.deallocate_untracked(store, ptr, len);
+            return Err(e);
+        }
 
-        // Allocate memory in the guest
-        let ptr = allocate
-            .call(store, len)
-            .map_err(|e| HostError::MemoryAccess(format!("Failed to allocate: {}", e)))?;
+        // Return combined pointer/length
+        Ok(WasmSlice::new(ptr, len).pack())
+    }
+
+    /// Move raw bytes to the memory of a 64-bit guest
+    ///
+    /// Counterpart of [`Env::move_bytes_to_guest`] for guests exporting
+    /// `__aingle_guest_allocate64`; others are limited to 4 GiB.
+    ///
+    /// # Returns
+    /// * `Ok(u128)` - Combined pointer/length value (ptr << 64 | len)
+    /// * `Err(HostError)` - If allocation or memory write fails
+    pub fn move_bytes_to_guest64(
+        &self,
+        store: &mut StoreMut<'_>,
+        bytes: &[u8],
+    ) -> Result<u128, HostError> {
+        Ok(write_guest_bytes64(&mut self.bind(store), bytes)?.pack())
+    }
 
-        // Write bytes to guest memory
-        let view = memory.view(store);
-        view.write(ptr as u64, bytes)
-            .map_err(|e| HostError::MemoryAccess(format!("Failed to write to memory: {}", e)))?;
+    /// Read and deserialize a MessagePack value from guest memory
+    ///
+    /// Deserialization failures are reported as [`HostError::Deserialization`]
+    /// naming the offset that was read.
+    pub fn read_typed<T: DeserializeOwned>(
+        &self,
+        store: &mut StoreMut<'_>,
+        guest_ptr: GuestPtr,
+        len: Len,
+    ) -> Result<T, HostError> {
+        read_typed(&mut self.bind(store), guest_ptr, len)
+    }
 
-        // Return combined pointer/length
-        let slice = WasmSlice::new(ptr as u32, len as u32);
-        Ok(slice.pack())
+    /// Serialize a value and move it to guest memory
+    ///
+    /// Returns the combined pointer/length like [`Env::move_data_to_guest`].
+    pub fn write_typed<T: Serialize + std::fmt::Debug>(
+        &self,
+        store: &mut StoreMut<'_>,
+        data: &T,
+    ) -> Result<u64, HostError> {
+        self.move_data_to_guest(store, data)
+    }
+
+    /// Read an envelope from guest memory and deserialize its payload
+    pub fn read_enveloped<T: DeserializeOwned>(
+        &self,
+        store: &mut StoreMut<'_>,
+        guest_ptr: GuestPtr,
+        len: Len,
+    ) -> Result<T, HostError> {
+        read_enveloped(&mut self.bind(store), guest_ptr, len)
+    }
+
+    /// Serialize a value into an envelope and move it to guest memory
+    ///
+    /// Returns the combined pointer/length of the envelope.
+    pub fn write_enveloped<T: Serialize>(
+        &self,
+        store: &mut StoreMut<'_>,
+        data: &T,
+    ) -> Result<u64, HostError> {
+        Ok(write_enveloped(&mut self.bind(store), data)?.pack())
+    }
+
+    /// Read the value a [`WasmRef`] points at in guest memory
+    pub fn read_ref<T: WasmDecode>(
+        &self,
+        store: &mut StoreMut<'_>,
+        value: WasmRef<T>,
+    ) -> Result<T, HostError> {
+        read_ref(&mut self.bind(store), value)
+    }
+
+    /// Encode a value into the guest memory a [`WasmRef`] covers
+    ///
+    /// Returns the number of bytes written.
+    pub fn write_ref<T: WasmEncode>(
+        &self,
+        store: &mut StoreMut<'_>,
+        target: WasmRef<T>,
+        value: &T,
+    ) -> Result<usize, HostError> {
+        write_ref(&mut self.bind(store), target, value)
+    }
+
+    /// Stream bytes into the guest in chunks of at most `chunk_size` bytes
+    ///
+    /// Avoids a single allocation of the full input size in the guest.
+    /// Returns the stream handle to pass to the target function together
+    /// with the total length.
+    pub fn stream_bytes_to_guest(
+        &self,
+        store: &mut StoreMut<'_>,
+        data: &[u8],
+        chunk_size: usize,
+    ) -> Result<u32, HostError> {
+        stream_to_guest(&mut self.bind(store), data, chunk_size)
     }
 
     /// Deallocate memory in the guest
@@ -195,6 +644,50 @@ impl Env {
         store: &mut StoreMut<'_>,
         ptr: GuestPtr,
         len: Len,
+    ) -> Result<(), HostError> {
+        {
+            let mut outstanding = self.outstanding.lock();
+            if let Some(pos) = outstanding.iter().position(|&a| a == (ptr, len)) {
+                outstanding.swap_remove(pos);
+            }
+        }
+        self.deallocate_untracked(store, ptr, len)
+    }
+
+    /// Remember a guest allocation the host owns so it can be freed later
+    ///
+    /// Memory handed over to the guest must not be tracked, as the guest is
+    /// responsible for freeing it.
+    pub fn track_allocation(&self, ptr: GuestPtr, len: Len) {
+        self.outstanding.lock().push((ptr, len));
+    }
+
+    /// Number of tracked allocations not freed yet
+    pub fn outstanding_allocations(&self) -> usize {
+        self.outstanding.lock().len()
+    }
+
+    /// Free every tracked allocation
+    ///
+    /// All allocations are released even if some fail; the first failure is
+    /// returned. Without a guest deallocator the tracking is simply dropped.
+    pub fn free_outstanding(&self, store: &mut StoreMut<'_>) -> Result<(), HostError> {
+        let outstanding = std::mem::take(&mut *self.outstanding.lock());
+
+        let mut result = Ok(());
+        for (ptr, len) in outstanding {
+            if let Err(e) = self.deallocate_untracked(store, ptr, len) {
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+
+    fn deallocate_untracked(
+        &self,
+        store: &mut StoreMut<'_>,
+        ptr: GuestPtr,
+        len: Len,
     ) -> Result<(), HostError> {
         if let Some(deallocate) = self.deallocate.as_ref() {
             deallocate
@@ -203,6 +696,218 @@ impl Env {
         }
         Ok(())
     }
+
+    /// Capture a region of guest memory for inspection
+    #[cfg(feature = "debug-tools")]
+    pub fn dump_memory(
+        &self,
+        store: &mut StoreMut<'_>,
+        ptr: GuestPtr,
+        len: Len,
+    ) -> Result<crate::MemoryDump, HostError> {
+        crate::MemoryDump::capture(&mut self.bind(store), ptr, len)
+    }
+
+    /// Bind the environment to a store for backend-neutral memory access
+    pub fn bind<'a, 'b>(&'a self, store: &'a mut StoreMut<'b>) -> EnvMemory<'a, 'b> {
+        EnvMemory { env: self, store }
+    }
+}
+
+/// The memory `exports` offer under `name`
+///
+/// Without a name the export [`DEFAULT_MEMORY_EXPORT`] is taken, or else
+/// the first memory exported, for toolchains naming it otherwise.
+pub(crate) fn exported_memory<'a>(exports: &'a Exports, name: Option<&str>) -> Option<&'a Memory> {
+    match name {
+        Some(name) => exports.get_memory(name).ok(),
+        None => exports
+            .get_memory(DEFAULT_MEMORY_EXPORT)
+            .ok()
+            .or_else(|| exports.iter().memories().next().map(|(_, memory)| memory)),
+    }
+}
+
+/// Call the arena export `name` of a guest around a nested call, if the
+/// guest has it
+fn arena_frame(
+    store: &mut StoreMut<'_>,
+    instance: &Instance,
+    name: &str,
+) -> Result<(), wasmer::RuntimeError> {
+    match instance
+        .exports
+        .get_typed_function::<(), i32>(&*store, name)
+    {
+        Ok(frame) => frame.call(store).map(drop),
+        Err(_) => Ok(()),
+    }
+}
+
+/// An [`Env`] with a context of type `T`, for host functions defined on a
+/// `FunctionEnv<EnvWith<T>>`
+///
+/// The typed alternative to [`Env::context`], at the cost of an environment
+/// apart from the one of the [`ImportBuilder`](crate::ImportBuilder): the
+/// instance has to be attached to it by hand. It derefs to its [`Env`].
+///
+/// ```ignore
+/// let env = FunctionEnv::new(&mut store, EnvWith::new(Cell { agent }));
+/// let agent_info = Function::new_typed_with_env(&mut store, &env, agent_info);
+/// let instance = ImportBuilder::new(&mut store)
+///     .define("env", "__hc__agent_info_1", agent_info)
+///     .instantiate(&mut store, &module)?;
+/// let mut attached = env.as_ref(&store).clone();
+/// attached.attach(&instance, &store);
+/// *env.as_mut(&mut store) = attached;
+/// ```
+pub struct EnvWith<T> {
+    /// The environment
+    pub env: Env,
+    /// State of the embedder for its host functions
+    pub context: Arc<T>,
+}
+
+impl<T> EnvWith<T> {
+    /// Create an empty environment with `context`
+    pub fn new(context: T) -> Self {
+        Self {
+            env: Env::new(),
+            context: Arc::new(context),
+        }
+    }
+}
+
+impl<T> Clone for EnvWith<T> {
+    fn clone(&self) -> Self {
+        Self {
+            env: self.env.clone(),
+            context: self.context.clone(),
+        }
+    }
+}
+
+impl<T> Deref for EnvWith<T> {
+    type Target = Env;
+
+    fn deref(&self) -> &Env {
+        &self.env
+    }
+}
+
+impl<T> DerefMut for EnvWith<T> {
+    fn deref_mut(&mut self) -> &mut Env {
+        &mut self.env
+    }
+}
+
+/// An [`Env`] bound to a store, usable wherever a [`BackendMemory`] is expected
+pub struct EnvMemory<'a, 'b> {
+    env: &'a Env,
+    store: &'a mut StoreMut<'b>,
+}
+
+impl EnvMemory<'_, '_> {
+    fn memory(&self) -> Result<&Memory, HostError> {
+        self.env
+            .memory
+            .as_ref()
+            .ok_or_else(|| HostError::MemoryAccess("Memory not initialized".to_string()))
+    }
+
+    /// Write `data` at `offset` through one view of the memory
+    fn write_view(&mut self, offset: u64, data: &[u8]) -> Result<(), HostError> {
+        let view = self.memory()?.view(&*self.store);
+        view.write(offset, data).map_err(|error| {
+            HostError::from(MemoryAccessFailure {
+                error,
+                offset,
+                len: data.len() as u64,
+                memory_size: view.data_size(),
+            })
+        })
+    }
+}
+
+impl BackendMemory for EnvMemory<'_, '_> {
+    fn memory_size(&mut self) -> Result<u64, HostError> {
+        Ok(self.memory()?.view(&*self.store).data_size())
+    }
+
+    fn read_memory(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), HostError> {
+        let view = self.memory()?.view(&*self.store);
+        view.read(offset, buf).map_err(|error| {
+            HostError::from(MemoryAccessFailure {
+                error,
+                offset,
+                len: buf.len() as u64,
+                memory_size: view.data_size(),
+            })
+        })
+    }
+
+    fn write_memory(&mut self, offset: u64, data: &[u8]) -> Result<(), HostError> {
+        match self.env.write_chunk {
+            // A view per chunk, so no single copy holds the store for long
+            Some(chunk) if chunk > 0 && data.len() > chunk => {
+                for (i, part) in data.chunks(chunk).enumerate() {
+                    self.write_view(offset + (i * chunk) as u64, part)?;
+                }
+                Ok(())
+            }
+            _ => self.write_view(offset, data),
+        }
+    }
+
+    fn allocate(&mut self, len: u32) -> Result<u32, HostError> {
+        let allocate = self.env.allocate.as_ref().ok_or_else(|| {
+            HostError::MemoryAccess("Allocate function not initialized".to_string())
+        })?;
+
+        let ptr = allocate
+            .call(self.store, len as i32)
+            .map_err(|e| HostError::MemoryAccess(format!("Failed to allocate: {}", e)))?;
+        guest_allocation(ptr)
+    }
+
+    fn allocate64(&mut self, len: u64) -> Result<u64, HostError> {
+        let Some(allocate) = self.env.allocate64.as_ref() else {
+            return allocate64_with_32(self, len);
+        };
+
+        let ptr = allocate
+            .call(self.store, len as i64)
+            .map_err(|e| HostError::MemoryAccess(format!("Failed to allocate: {}", e)))?;
+        guest_allocation64(ptr)
+    }
+}
+
+impl StreamSink for EnvMemory<'_, '_> {
+    fn stream_begin(&mut self, total_len: u32) -> Result<u32, HostError> {
+        let begin = self.env.stream_begin.as_ref().ok_or_else(|| {
+            HostError::Stream("Stream begin function not initialized".to_string())
+        })?;
+
+        begin
+            .call(self.store, total_len as i32)
+            .map(|handle| handle.max(0) as u32)
+            .map_err(|e| HostError::Stream(format!("Failed to begin stream: {}", e)))
+    }
+
+    fn stream_write(&mut self, handle: u32, chunk: WasmSlice) -> Result<i32, HostError> {
+        let write = self.env.stream_write.as_ref().ok_or_else(|| {
+            HostError::Stream("Stream write function not initialized".to_string())
+        })?;
+
+        write
+            .call(
+                self.store,
+                handle as i32,
+                chunk.ptr as i32,
+                chunk.len as i32,
+            )
+            .map_err(|e| HostError::Stream(format!("Failed to write stream: {}", e)))
+    }
 }
 
 #[cfg(test)]
@@ -215,6 +920,243 @@ mod tests {
         assert!(!env.is_initialized());
         assert!(env.memory.is_none());
         assert!(env.allocate.is_none());
+        assert!(env.allocate64.is_none());
         assert!(env.deallocate.is_none());
+        assert_eq!(env.outstanding_allocations(), 0);
+    }
+
+    #[test]
+    fn test_context() {
+        let mut env = Env::new();
+        assert!(env.context::<u32>().is_none());
+
+        env.set_context(Arc::new(7u32));
+        assert_eq!(env.context::<u32>().as_deref(), Some(&7));
+        assert!(env.context::<String>().is_none());
+        assert_eq!(env.clone().context::<u32>().as_deref(), Some(&7));
+
+        let typed = EnvWith::new(String::from("agent"));
+        assert_eq!(*typed.context, "agent");
+        assert!(!typed.is_initialized());
+    }
+
+    #[test]
+    fn test_host_io_quota() {
+        let env = Env::new();
+        assert!(env.admit_host_io(10, 20));
+        assert_eq!(
+            env.host_io(),
+            HostIo {
+                bytes_in: 10,
+                bytes_out: 20
+            }
+        );
+
+        env.clone().start_host_io(Some(50));
+        assert_eq!(env.host_io(), HostIo::default());
+        assert!(env.admit_host_io(30, 0));
+        // Refused calls are not counted
+        assert!(!env.admit_host_io(0, 21));
+        assert!(env.admit_host_io(0, 20));
+        assert!(!env.admit_host_io(1, 0));
+        assert_eq!(env.host_io().total(), 50);
+    }
+
+    #[test]
+    fn test_track_allocation_shared_between_clones() {
+        let env = Env::new();
+        let clone = env.clone();
+        clone.track_allocation(1024, 16);
+        assert_eq!(env.outstanding_allocations(), 1);
+    }
+
+    #[test]
+    fn test_enter_call() {
+        let env = Env::new();
+        let outer = env.enter_call(false).unwrap();
+        assert!(!outer.nested);
+        assert!(matches!(
+            env.clone().enter_call(false),
+            Err(HostError::ReentrantCall)
+        ));
+        assert_eq!(env.calls_in_progress(), 1);
+
+        let nested = env.enter_call(true).unwrap();
+        assert!(nested.nested);
+        assert_eq!(env.calls_in_progress(), 2);
+        drop((nested, outer));
+        assert_eq!(env.calls_in_progress(), 0);
+        assert!(!env.enter_call(false).unwrap().nested);
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_reentrant_calls() {
+        use crate::ImportBuilder;
+        use wasmer::{AsStoreMut, Function, FunctionEnvMut, Module, Store};
+
+        /// Where `env.reenter` calls `inner` of the instance it runs in
+        struct Reentry {
+            instance: OnceLock<Arc<Instance>>,
+            options: CallOptions,
+            result: Mutex<Option<Result<Vec<u8>, String>>>,
+        }
+
+        // Echoes its input from `outer` after re-entering, and from `inner`;
+        // the arena frame exports rewind a bump allocator
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "env" "reenter" (func $reenter))
+                (memory (export "memory") 1)
+                (global $bump (export "bump") (mut i32) (i32.const 1024))
+                (global $saved (mut i32) (i32.const 0))
+                (global $frames (export "frames") (mut i32) (i32.const 0))
+                (func $pack (param i32 i32) (result i64)
+                    (i64.or
+                        (i64.shl (i64.extend_i32_u (local.get 0)) (i64.const 32))
+                        (i64.extend_i32_u (local.get 1))))
+                (func (export "__hc__allocate_1") (param i32) (result i32)
+                    (global.get $bump)
+                    (global.set $bump (i32.add (global.get $bump) (local.get 0))))
+                (func (export "__aingle_arena_push") (result i32)
+                    (global.set $saved (global.get $bump))
+                    (global.set $frames (i32.add (global.get $frames) (i32.const 1)))
+                    (global.get $frames))
+                (func (export "__aingle_arena_pop") (result i32)
+                    (global.set $bump (global.get $saved))
+                    (global.set $frames (i32.sub (global.get $frames) (i32.const 1)))
+                    (global.get $frames))
+                (func (export "outer") (param i32 i32) (result i64)
+                    (call $reenter)
+                    (call $pack (local.get 0) (local.get 1)))
+                (func (export "inner") (param i32 i32) (result i64)
+                    (call $pack (local.get 0) (local.get 1))))
+            "#,
+        )
+        .unwrap();
+
+        for allow_reentrancy in [false, true] {
+            let options = CallOptions {
+                abi: Some(GuestAbi::current(0, 1, 0)),
+                allow_reentrancy,
+                ..Default::default()
+            };
+            let reentry = Arc::new(Reentry {
+                instance: OnceLock::new(),
+                options,
+                result: Mutex::new(None),
+            });
+            let mut store = Store::default();
+            let module = Module::new(&store, &wasm).unwrap();
+            let builder = ImportBuilder::new(&mut store);
+            let reenter = Function::new_typed_with_env(
+                &mut store,
+                builder.env(),
+                |mut env: FunctionEnvMut<'_, Env>| {
+                    let (env, mut store) = env.data_and_store_mut();
+                    let reentry = env.context::<Reentry>().unwrap();
+                    let instance = reentry.instance.get().unwrap().clone();
+                    let result = env.call_guest(
+                        &mut store,
+                        instance,
+                        "inner",
+                        b"inner input",
+                        &reentry.options,
+                    );
+                    *reentry.result.lock() = Some(result.map_err(|e| e.to_string()));
+                },
+            );
+            let function_env = builder.env().clone();
+            let instance = Arc::new(
+                builder
+                    .context(reentry.clone())
+                    .define("env", "reenter", reenter)
+                    .instantiate(&mut store, &module)
+                    .unwrap(),
+            );
+            assert!(reentry.instance.set(instance.clone()).is_ok());
+            let env = function_env.as_ref(&store).clone();
+            let bump = instance.exports.get_global("bump").unwrap().clone();
+            let frames = instance.exports.get_global("frames").unwrap().clone();
+            let mut store = store.as_store_mut();
+
+            let output = env
+                .call_guest(
+                    &mut store,
+                    instance.clone(),
+                    "outer",
+                    b"outer input",
+                    &options,
+                )
+                .unwrap();
+            assert_eq!(output, b"outer input");
+            assert_eq!(env.calls_in_progress(), 0);
+            let nested = reentry.result.lock().take().unwrap();
+            if allow_reentrancy {
+                assert_eq!(nested.unwrap(), b"inner input");
+                // The nested input was allocated in a frame popped since
+                assert_eq!(bump.get(&mut store).i32(), Some(1024 + 11));
+                assert_eq!(frames.get(&mut store).i32(), Some(0));
+            } else {
+                let message = nested.unwrap_err();
+                assert!(message.contains("re-entrant"), "{}", message);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_move_bytes_to_guest() {
+        use crate::ImportBuilder;
+        use wasmer::{AsStoreMut, Module, Store};
+
+        // Allocates 1 KiB before the end of its only page, records the
+        // length of frees and reserves scratch at 1024
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (memory (export "memory") 1)
+                (global $freed (export "freed") (mut i32) (i32.const 0))
+                (func (export "__hc__allocate_1") (param i32) (result i32)
+                    (i32.const 64512))
+                (func (export "__hc__deallocate_1") (param i32 i32)
+                    (global.set $freed (local.get 1)))
+                (func (export "__aingle_guest_scratch") (param i32) (result i32)
+                    (i32.const 1024)))
+            "#,
+        )
+        .unwrap();
+        let bytes: Vec<u8> = (0..=255).cycle().take(5000).collect();
+
+        for scratch in [None, Some(4096)] {
+            let mut store = Store::default();
+            let module = Module::new(&store, &wasm).unwrap();
+            let mut builder = ImportBuilder::new(&mut store).write_chunk(256);
+            if let Some(bytes) = scratch {
+                builder = builder.scratch(bytes);
+            }
+            let function_env = builder.env().clone();
+            let instance = builder.instantiate(&mut store, &module).unwrap();
+            let env = function_env.as_ref(&store).clone();
+            let freed = instance.exports.get_global("freed").unwrap().clone();
+            let mut store = store.as_store_mut();
+            assert_eq!(env.scratch(), scratch.map(|len| WasmSlice::new(1024, len)));
+
+            // Written in chunks, to scratch when there is room
+            let slice =
+                WasmSlice::unpack(env.move_bytes_to_guest(&mut store, &bytes[..1000]).unwrap());
+            let ptr = if scratch.is_some() { 1024 } else { 64512 };
+            assert_eq!(slice, WasmSlice::new(ptr, 1000));
+            assert_eq!(
+                read_guest_bytes(&mut env.bind(&mut store), slice.ptr, slice.len).unwrap(),
+                &bytes[..1000]
+            );
+
+            // Running out of memory after the first chunks frees the
+            // allocation
+            assert!(env.move_bytes_to_guest(&mut store, &bytes).is_err());
+            assert_eq!(freed.get(&mut store).i32(), Some(5000));
+        }
     }
 }
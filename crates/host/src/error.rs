@@ -1,5 +1,6 @@
 //! Host-side error types
 
+use aingle_wasmer_common::MemoryError;
 use thiserror::Error;
 
 /// Errors that can occur on the host side
@@ -21,18 +22,58 @@ pub enum HostError {
     #[error("memory not found in exports")]
     MemoryNotFound,
 
-    /// Memory access error
+    /// The module uses its memories in a way the host does not support,
+    /// such as several memories
+    #[error("unsupported memory layout: {0}")]
+    UnsupportedMemory(String),
+
+    /// Memory access error the runtime reports without details
     #[error("memory access error: {0}")]
     MemoryAccess(String),
 
-    /// Runtime error during execution
+    /// Guest memory access failed for a known reason, such as bounds
+    #[error("memory error: {0}")]
+    Memory(MemoryError),
+
+    /// Runtime error during execution that is neither a trap nor a host error
     #[error("runtime error: {0}")]
     Runtime(String),
 
+    /// The guest trapped
+    #[error("trap ({kind:?}): {message}")]
+    Trap {
+        /// Category of the trap
+        kind: TrapKind,
+        /// Message reported by the runtime
+        message: String,
+    },
+
+    /// Guest export does not match the expected signature
+    #[error("signature mismatch: {0}")]
+    SignatureMismatch(String),
+
+    /// Chunked streaming into the guest failed
+    #[error("stream error: {0}")]
+    Stream(String),
+
     /// Invalid return value from guest
     #[error("invalid return value from guest")]
     InvalidReturn,
 
+    /// The guest speaks a protocol version the host does not accept
+    #[error("protocol mismatch: guest speaks version {guest}, host speaks version {host}")]
+    ProtocolMismatch {
+        /// Protocol version reported by the guest
+        guest: u8,
+        /// Protocol version of the host
+        host: u8,
+    },
+
+    /// A host function called back into the instance while it was in a
+    /// call, see [`CallOptions::allow_reentrancy`](crate::CallOptions::allow_reentrancy)
+    #[error("re-entrant call of a guest already in a call")]
+    ReentrantCall,
+
     /// Guest returned an error
     #[error("guest error: {0}")]
     GuestError(String),
@@ -49,9 +90,122 @@ pub enum HostError {
     #[error("metering limit exceeded")]
     MeteringExceeded,
 
+    /// The warm-up export failed right after instantiation, see
+    /// [`EngineConfig::warmup_export`](crate::EngineConfig::warmup_export)
+    #[error("warm-up export {export} failed: {source}")]
+    WarmupFailed {
+        /// Name of the export
+        export: String,
+        /// Error the call failed with
+        source: Box<HostError>,
+    },
+
+    /// Module is quarantined after repeated failures
+    #[error("module {0:02x?} is quarantined after repeated failures")]
+    Quarantined([u8; 32]),
+
     /// Cache error
     #[error("cache error: {0}")]
     Cache(String),
+
+    /// Input exceeds the configured size limit
+    #[error("input too large: {len} bytes exceeds limit of {max}")]
+    InputTooLarge {
+        /// Size of the rejected input in bytes
+        len: usize,
+        /// Configured limit in bytes
+        max: usize,
+    },
+
+    /// Guest output exceeds the configured size limit
+    #[error("output too large: {len} bytes exceeds limit of {max}")]
+    OutputTooLarge {
+        /// Size claimed by the guest result in bytes
+        len: usize,
+        /// Configured limit in bytes
+        max: usize,
+    },
+}
+
+/// Category of a guest trap, mirroring the runtime's trap codes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TrapKind {
+    /// The call stack was exhausted
+    StackOverflow,
+    /// A load or store was outside linear memory
+    MemoryOutOfBounds,
+    /// A memory access was not aligned as required
+    MisalignedAccess,
+    /// A table access was out of bounds
+    TableOutOfBounds,
+    /// An indirect call went through a null table entry
+    IndirectCallNull,
+    /// An indirect call hit a function of the wrong signature
+    BadSignature,
+    /// An integer arithmetic operation overflowed
+    IntegerOverflow,
+    /// An integer division by zero
+    IntegerDivisionByZero,
+    /// A float could not be converted to an integer
+    BadConversionToInteger,
+    /// An `unreachable` instruction was executed
+    UnreachableCodeReached,
+    /// Any trap the runtime reports without a known category
+    Other,
+}
+
+impl HostError {
+    /// An access of `len` bytes at `offset` past the end of a memory of
+    /// `memory_size` bytes
+    ///
+    /// Values a `usize` cannot hold are saturated.
+    pub fn out_of_bounds(offset: u64, len: u64, memory_size: u64) -> Self {
+        let saturate = |value: u64| usize::try_from(value).unwrap_or(usize::MAX);
+        HostError::Memory(MemoryError::OutOfBounds {
+            offset: saturate(offset),
+            len: saturate(len),
+            max: saturate(memory_size),
+        })
+    }
+}
+
+/// A failed access to a wasmer memory, with the range it attempted
+///
+/// [`wasmer::MemoryAccessError`] does not say where the access went, so
+/// readers and writers attach it before converting into a [`HostError`].
+#[cfg(feature = "wasmer")]
+#[derive(Debug)]
+pub struct MemoryAccessFailure {
+    /// Error reported by the memory view
+    pub error: wasmer::MemoryAccessError,
+    /// Offset of the attempted access
+    pub offset: u64,
+    /// Length of the attempted access in bytes
+    pub len: u64,
+    /// Size of the memory at the time of the access in bytes
+    pub memory_size: u64,
+}
+
+/// Bounds failures become [`MemoryError::OutOfBounds`], any other failure
+/// stays a [`HostError::MemoryAccess`] message
+#[cfg(feature = "wasmer")]
+impl From<MemoryAccessFailure> for HostError {
+    fn from(failure: MemoryAccessFailure) -> Self {
+        match failure.error {
+            wasmer::MemoryAccessError::HeapOutOfBounds | wasmer::MemoryAccessError::Overflow => {
+                HostError::out_of_bounds(failure.offset, failure.len, failure.memory_size)
+            }
+            error => HostError::MemoryAccess(error.to_string()),
+        }
+    }
+}
+
+/// Without the attempted range an access error can only be described
+#[cfg(feature = "wasmer")]
+impl From<wasmer::MemoryAccessError> for HostError {
+    fn from(error: wasmer::MemoryAccessError) -> Self {
+        HostError::MemoryAccess(error.to_string())
+    }
 }
 
 impl From<HostError> for aingle_wasmer_common::WasmError {
@@ -68,9 +222,10 @@ impl From<HostError> for aingle_wasmer_common::WasmError {
             HostError::MeteringExceeded => {
                 aingle_wasmer_common::WasmError::GuestCall(GuestCallError::MeteringExceeded)
             }
-            HostError::GuestError(_) => {
+            HostError::GuestError(_) | HostError::Trap { .. } => {
                 aingle_wasmer_common::WasmError::GuestCall(GuestCallError::Panic)
             }
+            HostError::Memory(error) => aingle_wasmer_common::WasmError::Memory(error),
             _ => aingle_wasmer_common::WasmError::HostCall(HostCallError::HostError(0)),
         }
     }
@@ -84,5 +239,83 @@ mod tests {
     fn test_error_display() {
         let err = HostError::FunctionNotFound("test_fn".to_string());
         assert!(err.to_string().contains("test_fn"));
+
+        let err = HostError::OutputTooLarge { len: 100, max: 10 };
+        assert_eq!(
+            err.to_string(),
+            "output too large: 100 bytes exceeds limit of 10"
+        );
+
+        let err = HostError::ProtocolMismatch { guest: 2, host: 1 };
+        assert_eq!(
+            err.to_string(),
+            "protocol mismatch: guest speaks version 2, host speaks version 1"
+        );
+    }
+
+    #[test]
+    fn test_trap_maps_to_panic() {
+        use aingle_wasmer_common::{GuestCallError, WasmError};
+
+        let err = HostError::Trap {
+            kind: TrapKind::MemoryOutOfBounds,
+            message: "out of bounds memory access".to_string(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "trap (MemoryOutOfBounds): out of bounds memory access"
+        );
+        assert!(matches!(
+            WasmError::from(err),
+            WasmError::GuestCall(GuestCallError::Panic)
+        ));
+        assert!(matches!(
+            WasmError::from(HostError::MeteringExceeded),
+            WasmError::GuestCall(GuestCallError::MeteringExceeded)
+        ));
+    }
+
+    #[test]
+    fn test_memory_error_maps_to_memory() {
+        use aingle_wasmer_common::WasmError;
+
+        let err = HostError::out_of_bounds(65530, 16, 65536);
+        assert_eq!(
+            err.to_string(),
+            "memory error: memory access out of bounds: tried to read 16 bytes at offset 65530 \
+             but memory is 65536 bytes"
+        );
+        assert_eq!(
+            WasmError::from(err),
+            WasmError::Memory(MemoryError::OutOfBounds {
+                offset: 65530,
+                len: 16,
+                max: 65536
+            })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "wasmer")]
+    fn test_memory_access_failure() {
+        let failure = |error| MemoryAccessFailure {
+            error,
+            offset: 70000,
+            len: 8,
+            memory_size: 65536,
+        };
+
+        assert!(matches!(
+            HostError::from(failure(wasmer::MemoryAccessError::HeapOutOfBounds)),
+            HostError::Memory(MemoryError::OutOfBounds {
+                offset: 70000,
+                len: 8,
+                max: 65536
+            })
+        ));
+        assert!(matches!(
+            HostError::from(failure(wasmer::MemoryAccessError::NonUtf8String)),
+            HostError::MemoryAccess(_)
+        ));
     }
 }
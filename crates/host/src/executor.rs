@@ -0,0 +1,349 @@
+//! Bounded execution of guest calls
+//!
+//! [`CallExecutor`] caps the number of guest calls running at once, globally
+//! and optionally per module, queueing everything beyond the limits.
+
+use crate::backend::BackendModule;
+use crate::{ExternIO, HostError, HostFunctionRegistry, WasmEngine, WasmInstance};
+use parking_lot::{Condvar, Mutex, RwLock};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+/// Receives queueing and timing events from a [`CallExecutor`]
+pub trait CallObserver: Send + Sync {
+    /// A call entered the queue; `queue_depth` includes it
+    fn on_queued(&self, _module_key: &[u8; 32], _queue_depth: usize) {}
+
+    /// A call left the queue after waiting `wait`
+    fn on_started(&self, _module_key: &[u8; 32], _wait: Duration) {}
+
+    /// A call finished after running for `elapsed`
+    fn on_finished(&self, _module_key: &[u8; 32], _elapsed: Duration) {}
+
+    /// A [`WasmInstance`] observed by this was finalized or dropped
+    fn on_instance_drop(&self, _module_key: Option<&[u8; 32]>) {}
+}
+
+/// Configuration for a [`CallExecutor`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExecutorConfig {
+    /// Maximum number of calls executing at once
+    pub max_concurrent_calls: usize,
+    /// Maximum number of calls executing at once for a single module
+    pub per_module_limit: Option<usize>,
+}
+
+impl Default for ExecutorConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_calls: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4),
+            per_module_limit: None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct GateState {
+    running: usize,
+    queued: usize,
+    per_module: HashMap<[u8; 32], usize>,
+}
+
+/// Executes guest calls with bounded concurrency
+///
+/// Calls block the submitting thread until a slot is free.
+pub struct CallExecutor {
+    engine: Arc<WasmEngine>,
+    config: ExecutorConfig,
+    modules: RwLock<HashMap<[u8; 32], Arc<dyn BackendModule>>>,
+    state: Mutex<GateState>,
+    slot_freed: Condvar,
+    observer: Option<Arc<dyn CallObserver>>,
+    functions: HostFunctionRegistry,
+}
+
+impl CallExecutor {
+    /// Create an executor running calls on the given engine
+    pub fn new(engine: Arc<WasmEngine>, config: ExecutorConfig) -> Self {
+        Self {
+            engine,
+            config,
+            modules: RwLock::new(HashMap::new()),
+            state: Mutex::new(GateState::default()),
+            slot_freed: Condvar::new(),
+            observer: None,
+            functions: HostFunctionRegistry::new(),
+        }
+    }
+
+    /// Report queue depth and wait times to `observer`
+    pub fn with_observer(mut self, observer: Arc<dyn CallObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Import the functions of `registry` into every submitted call
+    pub fn with_functions(mut self, registry: HostFunctionRegistry) -> Self {
+        self.functions = registry;
+        self
+    }
+
+    /// Make a compiled module callable under `key`
+    pub fn register(&self, key: [u8; 32], module: Arc<dyn BackendModule>) {
+        self.modules.write().insert(key, module);
+    }
+
+    /// Call `fn_name` on a fresh instance of the module registered as `key`
+    ///
+    /// Blocks while the concurrency limits are reached.
+    pub fn submit(
+        &self,
+        key: [u8; 32],
+        fn_name: &str,
+        input: impl AsRef<[u8]>,
+    ) -> Result<ExternIO, HostError> {
+        let module = self.modules.read().get(&key).cloned().ok_or_else(|| {
+            HostError::Instantiation(format!("no module registered for key {:02x?}", &key[..4]))
+        })?;
+
+        self.run(key, || {
+            let mut instance =
+                WasmInstance::with_functions(&self.engine, &module, &self.functions)?
+                    .with_module_key(key);
+            if let Some(observer) = &self.observer {
+                instance = instance.with_observer(observer.clone());
+            }
+            instance.call_raw(fn_name, input).map(ExternIO::new)
+        })
+    }
+
+    /// Run `f` once a slot for `key` is free
+    pub fn run<R>(&self, key: [u8; 32], f: impl FnOnce() -> R) -> R {
+        let queued_at = Instant::now();
+        self.acquire(key);
+        if let Some(observer) = &self.observer {
+            observer.on_started(&key, queued_at.elapsed());
+        }
+
+        let _slot = Slot {
+            executor: self,
+            key,
+        };
+        let started_at = Instant::now();
+        let result = f();
+        if let Some(observer) = &self.observer {
+            observer.on_finished(&key, started_at.elapsed());
+        }
+        result
+    }
+
+    /// Number of calls currently waiting for a slot
+    pub fn queue_depth(&self) -> usize {
+        self.state.lock().queued
+    }
+
+    /// Number of calls currently executing
+    pub fn running(&self) -> usize {
+        self.state.lock().running
+    }
+
+    /// Get the configuration
+    pub fn config(&self) -> &ExecutorConfig {
+        &self.config
+    }
+
+    fn acquire(&self, key: [u8; 32]) {
+        let mut state = self.state.lock();
+        state.queued += 1;
+        if let Some(observer) = &self.observer {
+            observer.on_queued(&key, state.queued);
+        }
+
+        while !self.has_slot(&state, &key) {
+            self.slot_freed.wait(&mut state);
+        }
+
+        state.queued -= 1;
+        state.running += 1;
+        *state.per_module.entry(key).or_default() += 1;
+    }
+
+    fn has_slot(&self, state: &GateState, key: &[u8; 32]) -> bool {
+        let module_running = state.per_module.get(key).copied().unwrap_or(0);
+        state.running < self.config.max_concurrent_calls.max(1)
+            && self
+                .config
+                .per_module_limit
+                .is_none_or(|limit| module_running < limit.max(1))
+    }
+
+    fn release(&self, key: &[u8; 32]) {
+        let mut state = self.state.lock();
+        state.running -= 1;
+        if let Some(count) = state.per_module.get_mut(key) {
+            *count -= 1;
+            if *count == 0 {
+                state.per_module.remove(key);
+            }
+        }
+        drop(state);
+        self.slot_freed.notify_all();
+    }
+}
+
+/// Releases an executor slot when dropped, including on panic
+struct Slot<'a> {
+    executor: &'a CallExecutor,
+    key: [u8; 32],
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.executor.release(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EngineConfig;
+    use aingle_wasmer_common::{HostFunction, WasmError};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct CountingObserver {
+        started: AtomicUsize,
+    }
+
+    impl CallObserver for CountingObserver {
+        fn on_started(&self, _module_key: &[u8; 32], _wait: Duration) {
+            self.started.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Holds the calling guest for its argument in milliseconds, tracking
+    /// how many guests it holds at once
+    #[derive(Clone, Default)]
+    struct Hold {
+        active: Arc<AtomicUsize>,
+        high_water: Arc<AtomicUsize>,
+    }
+
+    impl HostFunction<u64, ()> for Hold {
+        const NAME: &'static str = "hold";
+
+        fn call(&self, millis: u64) -> Result<(), WasmError> {
+            let now = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+            self.high_water.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(millis));
+            self.active.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    /// Calls `hold` with its input and echoes it; responses are bump
+    /// allocated from 4096
+    const HOLD_WAT: &str = r#"
+        (module
+            (import "env" "hold" (func $hold (param i32 i32) (result i64)))
+            (memory (export "memory") 1)
+            (global $next (mut i32) (i32.const 4096))
+            (func (export "__hc__allocate_1") (param $len i32) (result i32)
+                (global.get $next)
+                (global.set $next (i32.add (global.get $next) (local.get $len))))
+            (func (export "__hc__deallocate_1") (param i32 i32))
+            (func (export "hold") (param $ptr i32) (param $len i32) (result i64)
+                (drop (call $hold (local.get $ptr) (local.get $len)))
+                (i64.or
+                    (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+                    (i64.extend_i32_u (local.get $len)))))
+    "#;
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_concurrency_limit() {
+        let engine = Arc::new(WasmEngine::new(EngineConfig::default()).unwrap());
+        let module = engine.compile(&wat::parse_str(HOLD_WAT).unwrap()).unwrap();
+        let hold = Hold::default();
+        let observer = Arc::new(CountingObserver::default());
+        let executor = CallExecutor::new(
+            engine,
+            ExecutorConfig {
+                max_concurrent_calls: 8,
+                per_module_limit: None,
+            },
+        )
+        .with_functions(HostFunctionRegistry::new().register(hold.clone()))
+        .with_observer(observer.clone());
+        for key in 0..4u8 {
+            executor.register([key; 32], module.clone());
+        }
+
+        let input = rmp_serde::to_vec(&2u64).unwrap();
+        std::thread::scope(|scope| {
+            for i in 0..200u8 {
+                let (executor, input) = (&executor, &input);
+                scope.spawn(move || {
+                    let output = executor.submit([i % 4; 32], "hold", input).unwrap();
+                    assert_eq!(output.as_ref(), &input[..]);
+                });
+            }
+        });
+
+        let high_water = hold.high_water.load(Ordering::SeqCst);
+        assert!(high_water > 0 && high_water <= 8, "{}", high_water);
+        assert_eq!(observer.started.load(Ordering::SeqCst), 200);
+        assert_eq!(executor.running(), 0);
+        assert_eq!(executor.queue_depth(), 0);
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_per_module_limit() {
+        let engine = Arc::new(WasmEngine::new(EngineConfig::default()).unwrap());
+        let executor = CallExecutor::new(
+            engine,
+            ExecutorConfig {
+                max_concurrent_calls: 8,
+                per_module_limit: Some(2),
+            },
+        );
+
+        let active = AtomicUsize::new(0);
+        let high_water = AtomicUsize::new(0);
+
+        std::thread::scope(|scope| {
+            for _ in 0..20 {
+                let (executor, active, high_water) = (&executor, &active, &high_water);
+                scope.spawn(move || {
+                    executor.run([1; 32], || {
+                        let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                        high_water.fetch_max(now, Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_millis(2));
+                        active.fetch_sub(1, Ordering::SeqCst);
+                    })
+                });
+            }
+        });
+
+        assert!(high_water.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_submit_unregistered() {
+        let engine = Arc::new(WasmEngine::new(EngineConfig::default()).unwrap());
+        let executor = CallExecutor::new(engine, ExecutorConfig::default());
+
+        let result = executor.submit([9; 32], "echo", ExternIO::new(vec![]));
+        assert!(matches!(result, Err(HostError::Instantiation(_))));
+    }
+}
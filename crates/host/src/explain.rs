@@ -0,0 +1,205 @@
+//! Step-by-step traces of single guest calls
+//!
+//! Framing and memory bugs show in the details of a call: where the input
+//! was allocated, what the guest returned packed, whether the result was an
+//! envelope. With [`CallOptions::explain`](crate::CallOptions::explain),
+//! `guest::call_explained` records each of these steps in a [`CallTrace`]
+//! returned next to the result, without a tracing subscriber. Traces
+//! serialize, to be attached to bug reports.
+
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fmt::Write;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+/// Bytes of data a [`TraceStep`] previews in hex
+pub const TRACE_PREVIEW_LEN: usize = 64;
+
+/// What a [`TraceStep`] did
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TraceStepKind {
+    /// Took the ABI of the guest, probed or from the options
+    Abi,
+    /// Allocated guest memory for the input
+    Allocate,
+    /// Wrote the input to guest memory
+    Write,
+    /// Ran the guest function
+    Call,
+    /// Unpacked the result the guest returned into a slice
+    Unpack,
+    /// Read the result from guest memory
+    Read,
+    /// Unwrapped the result from its envelope, or kept it raw
+    Decode,
+    /// Reported by the guest through `env.__aingle_trace`
+    Guest,
+}
+
+/// One step of a [`CallTrace`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceStep {
+    /// What the step did
+    pub kind: TraceStepKind,
+    /// Offset in guest memory the step worked on
+    pub offset: Option<u64>,
+    /// Bytes the step worked on
+    pub len: Option<u64>,
+    /// Hex of the first [`TRACE_PREVIEW_LEN`] of those bytes
+    pub preview: Option<String>,
+    /// What happened, in words
+    pub detail: String,
+    /// Microseconds from the start of the call to the end of the step
+    pub at_micros: u64,
+}
+
+/// The steps of one guest call, in order
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallTrace {
+    /// Function called
+    pub function: String,
+    /// Steps taken, in order
+    pub steps: Vec<TraceStep>,
+}
+
+impl CallTrace {
+    /// The kinds of the steps, in order
+    pub fn kinds(&self) -> Vec<TraceStepKind> {
+        self.steps.iter().map(|step| step.kind).collect()
+    }
+}
+
+/// `bytes` in hex, cut after [`TRACE_PREVIEW_LEN`] bytes
+pub fn hex_preview(bytes: &[u8]) -> String {
+    let shown = &bytes[..bytes.len().min(TRACE_PREVIEW_LEN)];
+    let mut hex = String::with_capacity(shown.len() * 2 + 3);
+    for byte in shown {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    if bytes.len() > shown.len() {
+        hex.push_str("...");
+    }
+    hex
+}
+
+thread_local! {
+    /// Trace of the call being explained on this thread
+    static TRACE: RefCell<Option<Recorder>> = const { RefCell::new(None) };
+}
+
+/// A trace being recorded
+struct Recorder {
+    started: Instant,
+    trace: CallTrace,
+}
+
+/// Run `call` while recording the steps of calls to `function` it makes
+///
+/// A trace recorded by an enclosing call is set aside meanwhile, like the
+/// output of `collect_output`.
+#[cfg_attr(not(feature = "wasmer"), allow(dead_code))]
+pub(crate) fn explain<R>(function: &str, call: impl FnOnce() -> R) -> (R, CallTrace) {
+    struct Restore(Option<Recorder>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            TRACE.with(|trace| *trace.borrow_mut() = self.0.take());
+        }
+    }
+
+    let recorder = Recorder {
+        started: Instant::now(),
+        trace: CallTrace {
+            function: function.to_string(),
+            steps: Vec::new(),
+        },
+    };
+    let outer = TRACE.with(|trace| trace.borrow_mut().replace(recorder));
+    let restore = Restore(outer);
+
+    let result = call();
+    let trace = TRACE
+        .with(|trace| trace.borrow_mut().take())
+        .map(|recorder| recorder.trace)
+        .unwrap_or_default();
+    drop(restore);
+    (result, trace)
+}
+
+/// Whether a call is being explained on this thread
+#[cfg_attr(not(feature = "wasmer"), allow(dead_code))]
+pub(crate) fn explaining() -> bool {
+    TRACE.with(|trace| trace.borrow().is_some())
+}
+
+/// Record a step of the call being explained on this thread, if any
+///
+/// `region` is the offset and length the step worked on, `data` the bytes
+/// to preview; `detail` is only formatted while explaining.
+#[cfg_attr(not(feature = "wasmer"), allow(dead_code))]
+pub(crate) fn record(
+    kind: TraceStepKind,
+    region: Option<(u64, u64)>,
+    data: Option<&[u8]>,
+    detail: impl FnOnce() -> String,
+) {
+    TRACE.with(|trace| {
+        let mut trace = trace.borrow_mut();
+        let Some(recorder) = trace.as_mut() else {
+            return;
+        };
+        let at_micros = u64::try_from(recorder.started.elapsed().as_micros()).unwrap_or(u64::MAX);
+        recorder.trace.steps.push(TraceStep {
+            kind,
+            offset: region.map(|(offset, _)| offset),
+            len: region.map(|(_, len)| len),
+            preview: data.map(hex_preview),
+            detail: detail(),
+            at_micros,
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_preview() {
+        assert_eq!(hex_preview(b""), "");
+        assert_eq!(hex_preview(&[0x00, 0xab, 0x7f]), "00ab7f");
+        let long = hex_preview(&[0xff; 100]);
+        assert_eq!(long.len(), TRACE_PREVIEW_LEN * 2 + 3);
+        assert!(long.ends_with("ff..."));
+    }
+
+    #[test]
+    fn test_explain_records_only_while_active() {
+        record(TraceStepKind::Call, None, None, || unreachable!());
+        assert!(!explaining());
+
+        let ((), trace) = explain("outer", || {
+            record(TraceStepKind::Write, Some((1024, 3)), Some(b"abc"), || {
+                "input".to_string()
+            });
+            let ((), inner) = explain("inner", || {
+                record(TraceStepKind::Call, None, None, String::new);
+            });
+            assert_eq!(inner.kinds(), [TraceStepKind::Call]);
+            record(TraceStepKind::Read, None, None, String::new);
+        });
+        assert!(!explaining());
+
+        assert_eq!(trace.function, "outer");
+        assert_eq!(trace.kinds(), [TraceStepKind::Write, TraceStepKind::Read]);
+        let write = &trace.steps[0];
+        assert_eq!((write.offset, write.len), (Some(1024), Some(3)));
+        assert_eq!(write.preview.as_deref(), Some("616263"));
+        assert_eq!(write.detail, "input");
+        assert!(trace.steps[1].at_micros >= write.at_micros);
+    }
+}
@@ -0,0 +1,225 @@
+//! Quarantine for repeatedly failing modules
+//!
+//! The [`FailureTracker`] counts consecutive trap-class failures per module
+//! key. Past the configured threshold the module is quarantined and calls
+//! fail fast with [`HostError::Quarantined`] instead of running the guest.
+
+use crate::HostError;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+/// When to quarantine a module and for how long
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FailurePolicy {
+    /// Consecutive trap-class failures that put a module in quarantine
+    pub threshold: u32,
+    /// Time after which a quarantined module is allowed to run again
+    pub cool_down: Duration,
+}
+
+impl Default for FailurePolicy {
+    fn default() -> Self {
+        Self {
+            threshold: 5,
+            cool_down: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Default)]
+struct FailureState {
+    consecutive: u32,
+    quarantined_at: Option<Instant>,
+    last_warned: Option<Instant>,
+}
+
+/// Consecutive failure counts and quarantine state keyed by module hash
+#[derive(Default)]
+pub struct FailureTracker {
+    policy: FailurePolicy,
+    states: Mutex<HashMap<[u8; 32], FailureState>>,
+}
+
+impl FailureTracker {
+    /// Create a tracker applying the given policy
+    pub fn new(policy: FailurePolicy) -> Self {
+        Self {
+            policy,
+            states: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Fail with [`HostError::Quarantined`] if `key` may not run
+    ///
+    /// A quarantine whose cool-down has elapsed is lifted here. Rejections are
+    /// logged at most once per cool-down.
+    pub fn check(&self, key: &[u8; 32]) -> Result<(), HostError> {
+        let mut states = self.states.lock();
+        let Some(state) = states.get_mut(key) else {
+            return Ok(());
+        };
+        let Some(quarantined_at) = state.quarantined_at else {
+            return Ok(());
+        };
+
+        if quarantined_at.elapsed() >= self.policy.cool_down {
+            states.remove(key);
+            return Ok(());
+        }
+
+        if state
+            .last_warned
+            .is_none_or(|warned| warned.elapsed() >= self.policy.cool_down)
+        {
+            state.last_warned = Some(Instant::now());
+            tracing::warn!(
+                "Rejecting call to quarantined module {:02x?} after {} consecutive failures",
+                &key[..4],
+                state.consecutive
+            );
+        }
+        Err(HostError::Quarantined(*key))
+    }
+
+    /// Record the outcome of a call to `key`
+    ///
+    /// Successes reset the failure count; only trap-class errors count
+    /// towards quarantine.
+    pub fn record<T>(&self, key: &[u8; 32], result: &Result<T, HostError>) {
+        match result {
+            Ok(_) => self.record_success(key),
+            Err(error) if is_trap_class(error) => self.record_failure(key),
+            Err(_) => {}
+        }
+    }
+
+    /// Reset the failure count of `key`
+    pub fn record_success(&self, key: &[u8; 32]) {
+        self.states.lock().remove(key);
+    }
+
+    /// Count a trap-class failure of `key`, quarantining it past the threshold
+    pub fn record_failure(&self, key: &[u8; 32]) {
+        let mut states = self.states.lock();
+        let state = states.entry(*key).or_default();
+        state.consecutive += 1;
+
+        if state.quarantined_at.is_none() && state.consecutive >= self.policy.threshold.max(1) {
+            state.quarantined_at = Some(Instant::now());
+            state.last_warned = Some(Instant::now());
+            tracing::warn!(
+                "Quarantining module {:02x?} for {:?} after {} consecutive failures",
+                &key[..4],
+                self.policy.cool_down,
+                state.consecutive
+            );
+        }
+    }
+
+    /// Lift the quarantine of `key` and reset its failure count
+    pub fn quarantine_reset(&self, key: &[u8; 32]) {
+        self.states.lock().remove(key);
+    }
+
+    /// Whether `key` is currently quarantined
+    pub fn is_quarantined(&self, key: &[u8; 32]) -> bool {
+        self.states
+            .lock()
+            .get(key)
+            .and_then(|state| state.quarantined_at)
+            .is_some_and(|at| at.elapsed() < self.policy.cool_down)
+    }
+
+    /// Number of consecutive trap-class failures recorded for `key`
+    pub fn consecutive_failures(&self, key: &[u8; 32]) -> u32 {
+        self.states
+            .lock()
+            .get(key)
+            .map_or(0, |state| state.consecutive)
+    }
+
+    /// Get the policy
+    pub fn policy(&self) -> &FailurePolicy {
+        &self.policy
+    }
+}
+
+/// Errors raised by the guest itself rather than by the host or the caller
+fn is_trap_class(error: &HostError) -> bool {
+    matches!(
+        error,
+        HostError::Trap { .. } | HostError::MeteringExceeded | HostError::Runtime(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TrapKind;
+
+    fn trap() -> Result<(), HostError> {
+        Err(HostError::Trap {
+            kind: TrapKind::UnreachableCodeReached,
+            message: "unreachable".to_string(),
+        })
+    }
+
+    #[test]
+    fn test_quarantine_after_threshold() {
+        let tracker = FailureTracker::new(FailurePolicy {
+            threshold: 3,
+            cool_down: Duration::from_secs(3600),
+        });
+        let key = [1u8; 32];
+
+        for _ in 0..2 {
+            assert!(tracker.check(&key).is_ok());
+            tracker.record(&key, &trap());
+        }
+        assert_eq!(tracker.consecutive_failures(&key), 2);
+
+        // Non-trap errors neither count nor reset
+        tracker.record(&key, &Err::<(), _>(HostError::FunctionNotFound("f".into())));
+        assert_eq!(tracker.consecutive_failures(&key), 2);
+
+        tracker.record(&key, &trap());
+        assert!(tracker.is_quarantined(&key));
+        assert!(matches!(
+            tracker.check(&key),
+            Err(HostError::Quarantined(k)) if k == key
+        ));
+        assert!(tracker.check(&[2u8; 32]).is_ok());
+
+        tracker.quarantine_reset(&key);
+        assert!(tracker.check(&key).is_ok());
+        assert_eq!(tracker.consecutive_failures(&key), 0);
+    }
+
+    #[test]
+    fn test_success_resets_and_cool_down_lifts() {
+        let tracker = FailureTracker::new(FailurePolicy {
+            threshold: 2,
+            cool_down: Duration::from_millis(20),
+        });
+        let key = [1u8; 32];
+
+        tracker.record(&key, &trap());
+        tracker.record(&key, &Ok(()));
+        tracker.record(&key, &trap());
+        assert!(tracker.check(&key).is_ok());
+
+        tracker.record(&key, &trap());
+        assert!(tracker.check(&key).is_err());
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(!tracker.is_quarantined(&key));
+        assert!(tracker.check(&key).is_ok());
+        assert_eq!(tracker.consecutive_failures(&key), 0);
+    }
+}
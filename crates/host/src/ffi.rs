@@ -0,0 +1,450 @@
+//! C ABI for embedding the host in conductors written in other languages
+//!
+//! A small `extern "C"` surface over [`WasmEngine`], compiled modules and
+//! [`WasmInstance`], with the header generated by cbindgen available as
+//! [`HEADER`]. Build the shared library with
+//! `cargo rustc -p aingle_wasmer_host --features cdylib --crate-type cdylib`.
+//!
+//! # Ownership
+//!
+//! Every object a function returns is owned by the caller and released with
+//! its free function: `aingle_engine_free`, `aingle_module_free`,
+//! `aingle_instance_free`, `aingle_bytes_free` and `aingle_string_free`.
+//! Free functions accept NULL. Instances keep what they need of their
+//! engine and module, which may be freed first. Engines and modules may be
+//! used from several threads at once; an instance from one thread at a time.
+//!
+//! # Errors
+//!
+//! Functions returning a pointer return NULL on failure, [`aingle_call`] a
+//! status other than `AINGLE_STATUS_OK`. The message of the last failure on
+//! the calling thread is returned by [`aingle_last_error`]. Panics are
+//! caught at the boundary and reported as failures.
+
+use crate::{BackendModule, EngineConfig, HostError, WasmEngine, WasmInstance};
+use aingle_wasmer_common::ChecksumKind;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// `aingle_wasmer_host.h`, generated from this module
+///
+/// cbindgen:ignore
+pub const HEADER: &str = include_str!(concat!(env!("OUT_DIR"), "/aingle_wasmer_host.h"));
+
+/// Outcome of [`aingle_call`]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AingleStatus {
+    /// The call succeeded and the output was written
+    Ok = 0,
+    /// The guest returned an error
+    GuestError = 1,
+    /// The host failed to run the call
+    Error = 2,
+    /// A pointer was NULL or a string not valid UTF-8
+    InvalidArgument = 3,
+    /// The host panicked; the instance should not be used again
+    Panic = 4,
+}
+
+/// An engine compiling and running guests
+pub struct AingleEngine {
+    engine: WasmEngine,
+}
+
+/// A compiled guest module
+pub struct AingleModule {
+    module: Arc<dyn BackendModule>,
+    key: [u8; 32],
+}
+
+/// An instance of a guest module
+pub struct AingleInstance {
+    instance: WasmInstance,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Failure at the boundary, with the status [`aingle_call`] reports it as
+struct FfiError {
+    status: AingleStatus,
+    message: String,
+}
+
+impl FfiError {
+    fn invalid(message: &str) -> Self {
+        Self {
+            status: AingleStatus::InvalidArgument,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<HostError> for FfiError {
+    fn from(error: HostError) -> Self {
+        let status = match error {
+            HostError::GuestError(_) => AingleStatus::GuestError,
+            _ => AingleStatus::Error,
+        };
+        Self {
+            status,
+            message: error.to_string(),
+        }
+    }
+}
+
+fn set_last_error(message: String) {
+    // Interior NULs would end the C string early
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Run `f`, recording its error or panic as the last error
+fn boundary<T>(f: impl FnOnce() -> Result<T, FfiError>) -> Result<T, AingleStatus> {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(error)) => {
+            set_last_error(error.message);
+            Err(error.status)
+        }
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            set_last_error(format!("panic: {}", message));
+            Err(AingleStatus::Panic)
+        }
+    }
+}
+
+/// Read a C string argument, NULL allowed
+///
+/// # Safety
+///
+/// `ptr` must be NULL or point at a NUL-terminated string.
+unsafe fn optional_str<'a>(ptr: *const c_char, what: &str) -> Result<Option<&'a str>, FfiError> {
+    if ptr.is_null() {
+        return Ok(None);
+    }
+    // SAFETY: the caller passes a NUL-terminated string
+    unsafe { CStr::from_ptr(ptr) }
+        .to_str()
+        .map(Some)
+        .map_err(|_| FfiError::invalid(&format!("{} is not valid UTF-8", what)))
+}
+
+/// Borrow the `len` bytes at `ptr`, which may be NULL when `len` is 0
+///
+/// # Safety
+///
+/// `ptr` must be valid for reads of `len` bytes.
+unsafe fn byte_slice<'a>(ptr: *const u8, len: usize, what: &str) -> Result<&'a [u8], FfiError> {
+    match (ptr.is_null(), len) {
+        (_, 0) => Ok(&[]),
+        (true, _) => Err(FfiError::invalid(&format!("{} is NULL", what))),
+        // SAFETY: the caller passes `len` readable bytes
+        (false, len) => Ok(unsafe { std::slice::from_raw_parts(ptr, len) }),
+    }
+}
+
+/// Engine settings read from the JSON passed to [`aingle_engine_new`]
+#[derive(serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigJson {
+    metering_limit: u64,
+    canonicalize_nans: bool,
+    cache_path: Option<PathBuf>,
+    max_input_len: usize,
+    max_output_len: usize,
+    guest_prealloc_bytes: Option<u32>,
+    checksum: ChecksumKind,
+}
+
+impl Default for ConfigJson {
+    fn default() -> Self {
+        let config = EngineConfig::default();
+        Self {
+            metering_limit: config.metering_limit,
+            canonicalize_nans: config.canonicalize_nans,
+            cache_path: config.cache_path,
+            max_input_len: config.max_input_len,
+            max_output_len: config.max_output_len,
+            guest_prealloc_bytes: config.guest_prealloc_bytes,
+            checksum: config.checksum,
+        }
+    }
+}
+
+impl From<ConfigJson> for EngineConfig {
+    fn from(json: ConfigJson) -> Self {
+        Self {
+            metering_limit: json.metering_limit,
+            canonicalize_nans: json.canonicalize_nans,
+            cache_path: json.cache_path,
+            max_input_len: json.max_input_len,
+            max_output_len: json.max_output_len,
+            guest_prealloc_bytes: json.guest_prealloc_bytes,
+            checksum: json.checksum,
+            ..Self::default()
+        }
+    }
+}
+
+/// Create an engine
+///
+/// `config_json` is NULL for the defaults, or a JSON object with any of
+/// `metering_limit`, `canonicalize_nans`, `cache_path`, `max_input_len`,
+/// `max_output_len`, `guest_prealloc_bytes` and `checksum` (`"Crc32"` or
+/// `"Crc32c"`). Returns NULL on failure. Free with [`aingle_engine_free`].
+///
+/// # Safety
+///
+/// `config_json` must be NULL or point at a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn aingle_engine_new(config_json: *const c_char) -> *mut AingleEngine {
+    boundary(|| {
+        // SAFETY: forwarded from the caller
+        let config = match unsafe { optional_str(config_json, "config_json") }? {
+            Some(json) => serde_json::from_str::<ConfigJson>(json)
+                .map_err(|e| FfiError::invalid(&format!("invalid config_json: {}", e)))?,
+            None => ConfigJson::default(),
+        };
+        let engine = WasmEngine::new(config.into())?;
+        Ok(Box::into_raw(Box::new(AingleEngine { engine })))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Free an engine
+///
+/// # Safety
+///
+/// `engine` must be NULL or returned by [`aingle_engine_new`] and not freed.
+#[no_mangle]
+pub unsafe extern "C" fn aingle_engine_free(engine: *mut AingleEngine) {
+    if !engine.is_null() {
+        // SAFETY: the caller passes an engine it owns
+        drop(unsafe { Box::from_raw(engine) });
+    }
+}
+
+/// Compile the `len` bytes of WebAssembly at `bytes`
+///
+/// The module key, the BLAKE3 hash of the bytes, is written to the 32 bytes
+/// at `key_out` unless it is NULL; calls on instances of the module are
+/// attributed to it in the engine's metering ledger. Returns NULL on
+/// failure. Free with [`aingle_module_free`].
+///
+/// # Safety
+///
+/// `engine` must be a live engine, `bytes` valid for reads of `len` bytes
+/// and `key_out` NULL or valid for writes of 32 bytes.
+#[no_mangle]
+pub unsafe extern "C" fn aingle_module_compile(
+    engine: *const AingleEngine,
+    bytes: *const u8,
+    len: usize,
+    key_out: *mut u8,
+) -> *mut AingleModule {
+    boundary(|| {
+        // SAFETY: the caller passes a live engine
+        let engine =
+            unsafe { engine.as_ref() }.ok_or_else(|| FfiError::invalid("engine is NULL"))?;
+        // SAFETY: forwarded from the caller
+        let wasm = unsafe { byte_slice(bytes, len, "bytes") }?;
+        let key = *blake3::hash(wasm).as_bytes();
+        let module = engine.engine.compile(wasm)?;
+        if !key_out.is_null() {
+            // SAFETY: the caller provides 32 writable bytes
+            unsafe { std::ptr::copy_nonoverlapping(key.as_ptr(), key_out, key.len()) };
+        }
+        Ok(Box::into_raw(Box::new(AingleModule { module, key })))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Free a module
+///
+/// # Safety
+///
+/// `module` must be NULL or returned by [`aingle_module_compile`] and not
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn aingle_module_free(module: *mut AingleModule) {
+    if !module.is_null() {
+        // SAFETY: the caller passes a module it owns
+        drop(unsafe { Box::from_raw(module) });
+    }
+}
+
+/// Instantiate a module
+///
+/// Returns NULL on failure. Free with [`aingle_instance_free`].
+///
+/// # Safety
+///
+/// `engine` and `module` must be live, and `module` compiled by `engine`.
+#[no_mangle]
+pub unsafe extern "C" fn aingle_instance_new(
+    engine: *const AingleEngine,
+    module: *const AingleModule,
+) -> *mut AingleInstance {
+    boundary(|| {
+        // SAFETY: the caller passes a live engine and module
+        let (engine, module) = unsafe { (engine.as_ref(), module.as_ref()) };
+        let engine = engine.ok_or_else(|| FfiError::invalid("engine is NULL"))?;
+        let module = module.ok_or_else(|| FfiError::invalid("module is NULL"))?;
+        let instance =
+            WasmInstance::new(&engine.engine, module.module.as_ref())?.with_module_key(module.key);
+        Ok(Box::into_raw(Box::new(AingleInstance { instance })))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Free an instance
+///
+/// # Safety
+///
+/// `instance` must be NULL or returned by [`aingle_instance_new`] and not
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn aingle_instance_free(instance: *mut AingleInstance) {
+    if !instance.is_null() {
+        // SAFETY: the caller passes an instance it owns
+        drop(unsafe { Box::from_raw(instance) });
+    }
+}
+
+/// Call the guest function `name` with the `in_len` bytes at `in_ptr`
+///
+/// On success the output is written to `*out_ptr` and `*out_len`, and
+/// owned by the caller, who frees it with [`aingle_bytes_free`], even when
+/// empty. Nothing is written on failure.
+///
+/// # Safety
+///
+/// `instance` must be live and not used by another thread, `name` a
+/// NUL-terminated string, `in_ptr` valid for reads of `in_len` bytes, and
+/// `out_ptr` and `out_len` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn aingle_call(
+    instance: *mut AingleInstance,
+    name: *const c_char,
+    in_ptr: *const u8,
+    in_len: usize,
+    out_ptr: *mut *mut u8,
+    out_len: *mut usize,
+) -> AingleStatus {
+    let result = boundary(|| {
+        // SAFETY: the caller passes a live instance
+        let instance =
+            unsafe { instance.as_mut() }.ok_or_else(|| FfiError::invalid("instance is NULL"))?;
+        // SAFETY: forwarded from the caller
+        let name = unsafe { optional_str(name, "name") }?
+            .ok_or_else(|| FfiError::invalid("name is NULL"))?;
+        // SAFETY: forwarded from the caller
+        let input = unsafe { byte_slice(in_ptr, in_len, "in_ptr") }?;
+        if out_ptr.is_null() || out_len.is_null() {
+            return Err(FfiError::invalid("out_ptr or out_len is NULL"));
+        }
+
+        let output = instance.instance.call_raw(name, input)?.into_boxed_slice();
+        let len = output.len();
+        // SAFETY: the caller provides writable outputs
+        unsafe {
+            out_len.write(len);
+            out_ptr.write(Box::into_raw(output).cast::<u8>());
+        }
+        Ok(())
+    });
+    match result {
+        Ok(()) => AingleStatus::Ok,
+        Err(status) => status,
+    }
+}
+
+/// Free the output of [`aingle_call`]
+///
+/// # Safety
+///
+/// `ptr` and `len` must be NULL or an output of [`aingle_call`] not freed.
+#[no_mangle]
+pub unsafe extern "C" fn aingle_bytes_free(ptr: *mut u8, len: usize) {
+    if !ptr.is_null() {
+        // SAFETY: the caller passes an output it owns, with its length
+        drop(unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len)) });
+    }
+}
+
+/// Message of the last failure on the calling thread, or NULL if none
+///
+/// Free with [`aingle_string_free`].
+#[no_mangle]
+pub extern "C" fn aingle_last_error() -> *mut c_char {
+    LAST_ERROR
+        .with(|last| last.borrow().clone())
+        .map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+/// Free a string returned by [`aingle_last_error`]
+///
+/// # Safety
+///
+/// `string` must be NULL or returned by [`aingle_last_error`] and not freed.
+#[no_mangle]
+pub unsafe extern "C" fn aingle_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        // SAFETY: the caller passes a string it owns
+        drop(unsafe { CString::from_raw(string) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> String {
+        let message = aingle_last_error();
+        assert!(!message.is_null());
+        let text = unsafe { CStr::from_ptr(message) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        unsafe { aingle_string_free(message) };
+        text
+    }
+
+    #[test]
+    fn test_boundary_catches_panics() {
+        let result: Result<(), _> = boundary(|| panic!("guest table corrupted"));
+        assert_eq!(result, Err(AingleStatus::Panic));
+        assert_eq!(last_error(), "panic: guest table corrupted");
+
+        let result: Result<(), _> =
+            boundary(|| Err(HostError::GuestError("bad\0input".into()).into()));
+        assert_eq!(result, Err(AingleStatus::GuestError));
+        assert_eq!(last_error(), "guest error: bad input");
+    }
+
+    #[test]
+    fn test_config_json() {
+        let config: EngineConfig =
+            serde_json::from_str::<ConfigJson>(r#"{"max_input_len": 1024, "checksum": "Crc32c"}"#)
+                .unwrap()
+                .into();
+        assert_eq!(config.max_input_len, 1024);
+        assert_eq!(config.checksum, ChecksumKind::Crc32c);
+        assert_eq!(
+            config.metering_limit,
+            EngineConfig::default().metering_limit
+        );
+        assert!(serde_json::from_str::<ConfigJson>(r#"{"metering": 1}"#).is_err());
+    }
+}
@@ -2,51 +2,164 @@
 //!
 //! Functions for calling guest WASM functions and transferring data.
 
-use crate::HostError;
-use aingle_wasmer_common::{WasmResult, WasmSlice};
+use crate::{CallOptions, HostError, DEFAULT_MAX_INPUT_LEN, DEFAULT_MAX_OUTPUT_LEN};
+use aingle_wasmer_common::{
+    decode_empty, is_unit, GuestAbi, GuestCapability, VersionPolicy, WasmError, WasmErrorInner,
+    WasmResult, WasmSlice, PROTOCOL_VERSION,
+};
+use bytes::Bytes;
 use serde::{de::DeserializeOwned, Serialize};
 use std::sync::Arc;
 
-#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
-use wasmer::{Instance, StoreMut, Value};
+#[cfg(feature = "wasmer")]
+use crate::backend::guest_allocation;
+#[cfg(feature = "wasmer")]
+use crate::env::exported_memory;
+#[cfg(feature = "wasmer")]
+use crate::explain::{explain, record, TraceStepKind};
+#[cfg(feature = "wasmer")]
+use crate::imports::collect_output;
+#[cfg(feature = "wasmer")]
+use crate::{CallTrace, FailureTracker, MeteringLedger, ProbeGuestAbi};
+#[cfg(all(feature = "wasmer", feature = "result-cache"))]
+use crate::{ResultCache, ResultKey};
+#[cfg(feature = "wasmer")]
+use aingle_wasmer_codec::{decode_envelope, ChecksumHasher, DecodedEnvelope, EnvelopeHeaderInfo};
+#[cfg(feature = "wasmer")]
+use aingle_wasmer_common::{ChecksumKind, EnvelopeHeader, GuestFunction, GuestReturn, SliceOrder};
+#[cfg(feature = "wasmer")]
+use std::io;
+#[cfg(feature = "wasmer")]
+use std::sync::OnceLock;
+#[cfg(feature = "wasmer")]
+use wasmer::{Instance, Memory, MemoryView, StoreMut, Value};
+
+/// MessagePack flavour of typed payloads
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExternEncoding {
+    /// Structs as maps keyed by field name, readable by every peer
+    #[default]
+    Named,
+    /// Structs as arrays of their fields in declaration order
+    ///
+    /// Saves the field names, often close to half of a small struct. Fields
+    /// skipped with `skip_serializing_if` shift the ones after them, so
+    /// types using it must stay named.
+    Compact,
+}
+
+impl ExternEncoding {
+    /// Encoding to send to a guest of `abi`
+    ///
+    /// Compact falls back to named unless the guest advertises
+    /// [`GuestCapability::CompactEncoding`].
+    pub fn negotiate(self, abi: GuestAbi) -> Self {
+        match self {
+            Self::Compact if !abi.has(GuestCapability::CompactEncoding) => Self::Named,
+            encoding => encoding,
+        }
+    }
+}
 
 /// ExternIO compatible type for host-guest communication
 ///
 /// This wraps serialized bytes and provides encode/decode methods
-/// compatible with aingle's ExternIO.
+/// compatible with aingle's ExternIO. The bytes are reference-counted, so
+/// cloning is cheap; the wire format is the same plain byte string as before.
 #[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 #[serde(transparent)]
 #[repr(transparent)]
-pub struct ExternIO(#[serde(with = "serde_bytes")] pub Vec<u8>);
+pub struct ExternIO(#[serde(with = "serde_shared_bytes")] pub Bytes);
 
 impl ExternIO {
     /// Create a new ExternIO from bytes
-    pub fn new(bytes: Vec<u8>) -> Self {
-        Self(bytes)
+    pub fn new(bytes: impl Into<Bytes>) -> Self {
+        Self(bytes.into())
+    }
+
+    /// Create an ExternIO over a constant payload without copying it
+    pub const fn from_static(bytes: &'static [u8]) -> Self {
+        Self(Bytes::from_static(bytes))
     }
 
     /// Encode a value to ExternIO
+    ///
+    /// Unit values encode as an empty payload, see
+    /// [`is_unit`](aingle_wasmer_common::is_unit).
     pub fn encode<T: Serialize>(value: T) -> Result<Self, HostError> {
-        let bytes = rmp_serde::to_vec_named(&value)
-            .map_err(|e| HostError::Serialization(format!("Failed to encode: {}", e)))?;
-        Ok(Self(bytes))
+        Self::encode_with(value, ExternEncoding::Named)
+    }
+
+    /// Encode a value to ExternIO with structs as positional arrays
+    ///
+    /// Only for peers reading compact payloads, see
+    /// [`ExternEncoding::Compact`].
+    pub fn encode_compact<T: Serialize>(value: T) -> Result<Self, HostError> {
+        Self::encode_with(value, ExternEncoding::Compact)
+    }
+
+    /// Encode a value to ExternIO with the given encoding
+    pub fn encode_with<T: Serialize>(
+        value: T,
+        encoding: ExternEncoding,
+    ) -> Result<Self, HostError> {
+        if is_unit(&value) {
+            return Ok(Self(Bytes::new()));
+        }
+        let bytes = match encoding {
+            ExternEncoding::Named => rmp_serde::to_vec_named(&value),
+            ExternEncoding::Compact => rmp_serde::to_vec(&value),
+        }
+        .map_err(|e| HostError::Serialization(format!("Failed to encode: {}", e)))?;
+        Ok(Self(bytes.into()))
     }
 
     /// Decode from ExternIO
+    ///
+    /// Structs are read from either encoding. An empty payload decodes only
+    /// as unit.
     pub fn decode<T: DeserializeOwned>(&self) -> Result<T, HostError> {
+        if self.0.is_empty() {
+            return decode_empty()
+                .map_err(|e| HostError::Serialization(format!("Failed to decode: {}", e)));
+        }
         rmp_serde::from_slice(&self.0)
             .map_err(|e| HostError::Serialization(format!("Failed to decode: {}", e)))
     }
 
     /// Get inner bytes
+    ///
+    /// Only copies if the bytes are shared with another clone.
     pub fn into_vec(self) -> Vec<u8> {
-        self.0
+        self.0.into()
     }
 
     /// Get bytes as slice
     pub fn as_bytes(&self) -> &[u8] {
         &self.0
     }
+
+    /// Wrap the payload in an envelope with the given flags
+    pub fn to_envelope(&self, flags: u8) -> Result<Vec<u8>, HostError> {
+        let mut buffer = vec![0u8; aingle_wasmer_common::EnvelopeHeader::SIZE + self.0.len()];
+        let len = aingle_wasmer_codec::encode_with_envelope(&self.0, flags, &mut buffer)
+            .map_err(|e| HostError::Serialization(e.to_string()))?;
+        buffer.truncate(len);
+        Ok(buffer)
+    }
+
+    /// Unwrap the payload of an envelope, verifying its checksum
+    ///
+    /// Envelopes flagged as errors fail with [`HostError::GuestError`], see
+    /// [`guest_error_message`].
+    pub fn from_envelope(bytes: &[u8]) -> Result<Self, HostError> {
+        let envelope = aingle_wasmer_codec::decode_envelope(bytes)
+            .map_err(|e| HostError::Deserialization(e.to_string()))?;
+        if envelope.header.is_error() {
+            return Err(HostError::GuestError(guest_error_message(envelope.payload)));
+        }
+        Ok(Self(Bytes::copy_from_slice(envelope.payload)))
+    }
 }
 
 impl AsRef<[u8]> for ExternIO {
@@ -55,20 +168,95 @@ impl AsRef<[u8]> for ExternIO {
     }
 }
 
+impl std::ops::Deref for ExternIO {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
 impl From<Vec<u8>> for ExternIO {
     fn from(v: Vec<u8>) -> Self {
-        Self(v)
+        Self(v.into())
+    }
+}
+
+impl From<Bytes> for ExternIO {
+    fn from(bytes: Bytes) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<&[u8]> for ExternIO {
+    fn from(bytes: &[u8]) -> Self {
+        Self(Bytes::copy_from_slice(bytes))
+    }
+}
+
+impl<const N: usize> From<&[u8; N]> for ExternIO {
+    fn from(bytes: &[u8; N]) -> Self {
+        Self::from(&bytes[..])
+    }
+}
+
+impl From<&Vec<u8>> for ExternIO {
+    fn from(bytes: &Vec<u8>) -> Self {
+        Self::from(bytes.as_slice())
+    }
+}
+
+/// Shares the bytes rather than copying them
+impl From<&ExternIO> for ExternIO {
+    fn from(io: &ExternIO) -> Self {
+        io.clone()
+    }
+}
+
+/// The wrapper of `aingle_zome_types` holds a `Vec<u8>`, taken over without
+/// a copy
+#[cfg(feature = "zome_types")]
+impl From<aingle_zome_types::ExternIO> for ExternIO {
+    fn from(io: aingle_zome_types::ExternIO) -> Self {
+        Self(io.0.into())
+    }
+}
+
+/// Copies only if the bytes are shared with another clone
+#[cfg(feature = "zome_types")]
+impl From<ExternIO> for aingle_zome_types::ExternIO {
+    fn from(io: ExternIO) -> Self {
+        Self(io.into_vec())
     }
 }
 
 impl From<ExternIO> for Vec<u8> {
     fn from(io: ExternIO) -> Self {
-        io.0
+        io.into_vec()
+    }
+}
+
+/// Serializes [`Bytes`] exactly like `serde_bytes` does a `Vec<u8>`
+mod serde_shared_bytes {
+    use bytes::Bytes;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+        serde_bytes::serialize(bytes.as_ref(), serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+        serde_bytes::ByteBuf::deserialize(deserializer).map(|buf| buf.into_vec().into())
     }
 }
 
 /// Call a guest function
 ///
+/// The input is anything converting into an [`ExternIO`], including
+/// `aingle_zome_types::ExternIO` with the `zome_types` feature. Owned bytes
+/// and `&ExternIO` are passed without an intermediate copy; borrowed slices
+/// are copied once.
+///
 /// This function:
 /// 1. Allocates memory in the guest for the input
 /// 2. Copies the input bytes to guest memory
@@ -88,40 +276,511 @@ impl From<ExternIO> for Vec<u8> {
 /// # Example
 /// ```ignore
 /// // With ExternIO
-/// let result_bytes = call(&mut store, instance, "my_fn", &extern_io.0)?;
-/// let result = ExternIO(result_bytes);
+/// let result_bytes = call(&mut store, instance, "my_fn", &extern_io)?;
+/// let result = ExternIO::new(result_bytes);
 ///
 /// // With raw bytes
-/// let result_bytes = call(&mut store, instance, "my_fn", &input_bytes)?;
+/// let result_bytes = call(&mut store, instance, "my_fn", input_bytes)?;
 /// ```
-#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+#[cfg(feature = "wasmer")]
 pub fn call(
+    store: &mut StoreMut<'_>,
+    instance: Arc<Instance>,
+    name: &str,
+    input: impl Into<ExternIO>,
+) -> Result<Vec<u8>, wasmer::RuntimeError> {
+    call_with_options(store, instance, name, input, &CallOptions::default())
+}
+
+/// Call the guest function a [`GuestFunction`] marker declares
+///
+/// Like [`call`], with the input and output serialized with the declared
+/// types; decoding failures are reported as a user error wrapping
+/// [`HostError::Serialization`].
+#[cfg(feature = "wasmer")]
+pub fn call_fn<F: GuestFunction>(
+    store: &mut StoreMut<'_>,
+    instance: Arc<Instance>,
+    input: &F::Input,
+) -> Result<F::Output, wasmer::RuntimeError> {
+    call_fn_with_options::<F>(store, instance, input, &CallOptions::default())
+}
+
+/// Call the guest function a [`GuestFunction`] marker declares, with
+/// per-call overrides
+///
+/// Like [`call_with_options`], with the input encoded as
+/// `options.encoding` negotiates with the guest, see
+/// [`ExternEncoding::negotiate`].
+#[cfg(feature = "wasmer")]
+pub fn call_fn_with_options<F: GuestFunction>(
+    store: &mut StoreMut<'_>,
+    instance: Arc<Instance>,
+    input: &F::Input,
+    options: &CallOptions,
+) -> Result<F::Output, wasmer::RuntimeError> {
+    let user_error = |e: HostError| wasmer::RuntimeError::user(Box::new(e));
+    let abi = match options.abi {
+        Some(abi) => abi,
+        None => GuestAbi::probe(&instance, store),
+    };
+    let encoding = options.encoding.unwrap_or_default().negotiate(abi);
+    let input = ExternIO::encode_with(input, encoding).map_err(user_error)?;
+    let options = CallOptions {
+        abi: Some(abi),
+        ..*options
+    };
+    let output = call_with_options(store, instance, F::NAME, input, &options)?;
+    ExternIO::from(output).decode().map_err(user_error)
+}
+
+/// Call a guest function with per-call size limits
+///
+/// Limits not set in `options` fall back to [`DEFAULT_MAX_INPUT_LEN`] and
+/// [`DEFAULT_MAX_OUTPUT_LEN`]. Violations are reported as a user error
+/// wrapping [`HostError::InputTooLarge`] or [`HostError::OutputTooLarge`].
+///
+/// Results of guests reporting [`GuestCapability::EnvelopeIo`] are unwrapped
+/// when they are envelopes; for the marker envelope of a streamed result the
+/// output the guest sent through `__aingle_output_chunk` is returned, see
+/// [`ImportBuilder`](crate::ImportBuilder). The guest ABI is taken from `options.abi`, or
+/// probed for each call when unset; cache it with `Env::guest_abi`. Guests
+/// on a protocol version `options.version_policy` rejects fail with
+/// [`HostError::ProtocolMismatch`].
+///
+/// Legacy guests may pack their results with the length in the high half,
+/// see [`SliceOrder`]. The order is taken from `options.slice_order`, or
+/// detected from each result when unset; `Env::call_guest` detects it once
+/// per instance.
+#[cfg(feature = "wasmer")]
+pub fn call_with_options(
+    store: &mut StoreMut<'_>,
+    instance: Arc<Instance>,
+    name: &str,
+    input: impl Into<ExternIO>,
+    options: &CallOptions,
+) -> Result<Vec<u8>, wasmer::RuntimeError> {
+    let input = input.into();
+    call_detecting_order(store, instance, name, input, options, &OnceLock::new())
+}
+
+/// Call a guest function like [`call_with_options`], answering calls
+/// asserted pure with [`CallOptions::cacheable`] from `cache`
+///
+/// Outputs are cached under `module_key`, which must identify the module of
+/// `instance`: entries are never served across keys. Errors are not cached.
+#[cfg(all(feature = "wasmer", feature = "result-cache"))]
+pub fn call_cached(
+    store: &mut StoreMut<'_>,
+    instance: Arc<Instance>,
+    cache: &ResultCache,
+    module_key: [u8; 32],
+    name: &str,
+    input: impl Into<ExternIO>,
+    options: &CallOptions,
+) -> Result<Vec<u8>, wasmer::RuntimeError> {
+    let input = input.into();
+    if options.cacheable.is_none() {
+        return call_with_options(store, instance, name, input, options);
+    }
+    let key = ResultKey::new(module_key, name, &input);
+    if let Some(output) = cache.get(&key) {
+        return Ok(output.into_vec());
+    }
+    let output = call_with_options(store, instance, name, input, options)?;
+    cache.insert(key, ExternIO::new(output.clone()));
+    Ok(output)
+}
+
+/// Call a guest function like [`call_with_options`], returning the
+/// [`CallTrace`] of the call next to its result when
+/// [`CallOptions::explain`] is set
+///
+/// The trace holds the steps of the host, from the ABI to decoding the
+/// result, and those the guest reports through `env.__aingle_trace`, in
+/// order. It is returned for failed calls too, up to the failing step.
+#[cfg(feature = "wasmer")]
+pub fn call_explained(
+    store: &mut StoreMut<'_>,
+    instance: Arc<Instance>,
+    name: &str,
+    input: impl Into<ExternIO>,
+    options: &CallOptions,
+) -> (Result<Vec<u8>, wasmer::RuntimeError>, Option<CallTrace>) {
+    if !options.explain {
+        return (
+            call_with_options(store, instance, name, input, options),
+            None,
+        );
+    }
+    let (result, trace) = explain(name, || {
+        call_with_options(store, instance, name, input, options)
+    });
+    (result, Some(trace))
+}
+
+/// [`call_with_options`] recording the result order of a legacy guest in
+/// `order` once a result tells it
+#[cfg(feature = "wasmer")]
+pub(crate) fn call_detecting_order(
     store: &mut StoreMut<'_>,
     instance: Arc<Instance>,
     name: &str,
     input: impl AsRef<[u8]>,
+    options: &CallOptions,
+    order: &OnceLock<SliceOrder>,
 ) -> Result<Vec<u8>, wasmer::RuntimeError> {
-    // Get the memory and allocate function from the instance
-    let memory = instance
-        .exports
-        .get_memory("memory")
-        .map_err(|e| wasmer::RuntimeError::new(format!("Failed to get memory: {}", e)))?;
+    let input_bytes = input.as_ref();
+    let Returned {
+        memory,
+        abi,
+        result: wasm_result,
+        streamed,
+        max_output,
+    } = run_guest(store, instance, name, input_bytes, options, order)?;
+    let slice = wasm_result.slice();
+
+    if slice.is_empty() {
+        record(TraceStepKind::Decode, None, None, || {
+            "empty result".to_string()
+        });
+        if wasm_result.is_err() {
+            return Err(guest_error(
+                name,
+                input_bytes,
+                &[],
+                payloadless_error_message(wasm_result),
+            ));
+        }
+        return Ok(Vec::new());
+    }
+
+    if slice.len as usize > max_output {
+        return Err(wasmer::RuntimeError::user(Box::new(
+            HostError::OutputTooLarge {
+                len: slice.len as usize,
+                max: max_output,
+            },
+        )));
+    }
+
+    // Read the result from guest memory
+    let view = memory.view(store);
+    let mut result_bytes = vec![0u8; slice.len as usize];
+    view.read(slice.ptr as u64, &mut result_bytes)
+        .map_err(|e| wasmer::RuntimeError::new(format!("Failed to read result: {}", e)))?;
+    record(
+        TraceStepKind::Read,
+        Some((slice.ptr as u64, slice.len as u64)),
+        Some(&result_bytes),
+        || format!("{} bytes of result", slice.len),
+    );
+
+    if abi.has(GuestCapability::EnvelopeIo) {
+        if let Some(envelope) = whole_envelope(&result_bytes) {
+            record(TraceStepKind::Decode, None, Some(envelope.payload), || {
+                format!("envelope {:?}", envelope.header.info())
+            });
+            if wasm_result.is_err() || envelope.header.is_error() {
+                return Err(guest_error(
+                    name,
+                    input_bytes,
+                    envelope.payload,
+                    guest_error_message(envelope.payload),
+                ));
+            }
+            if let Some(streamed) = streamed {
+                return streamed_result(envelope.payload, streamed, max_output)
+                    .map_err(|e| wasmer::RuntimeError::user(Box::new(e)));
+            }
+            return Ok(envelope.payload.to_vec());
+        }
+    }
+    record(TraceStepKind::Decode, None, None, || {
+        "raw result".to_string()
+    });
+
+    if wasm_result.is_err() {
+        return Err(guest_error(
+            name,
+            input_bytes,
+            &result_bytes,
+            guest_error_message(&result_bytes),
+        ));
+    }
+
+    Ok(result_bytes)
+}
+
+/// Bytes [`call_into`] copies out of guest memory at a time
+#[cfg(feature = "wasmer")]
+pub const FORWARD_CHUNK_LEN: usize = 64 * 1024;
+
+/// What [`call_into`] wrote to its sink
+#[cfg(feature = "wasmer")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ForwardedResult {
+    /// Header of the result envelope, `None` for raw results
+    pub header: Option<EnvelopeHeaderInfo>,
+    /// Bytes written to the sink
+    pub bytes: u64,
+}
+
+/// Call a guest function like [`call`], writing its output to `sink`
+/// instead of returning it
+#[cfg(feature = "wasmer")]
+pub fn call_into(
+    store: &mut StoreMut<'_>,
+    instance: Arc<Instance>,
+    name: &str,
+    input: impl Into<ExternIO>,
+    sink: &mut dyn io::Write,
+) -> Result<ForwardedResult, wasmer::RuntimeError> {
+    call_into_with_options(store, instance, name, input, sink, &CallOptions::default())
+}
+
+/// Call a guest function like [`call_with_options`], writing its output to
+/// `sink` instead of returning it
+///
+/// The result is never copied whole onto the host heap: envelopes are
+/// checked in place, their checksum computed over guest memory, and the
+/// payload is then copied to `sink` [`FORWARD_CHUNK_LEN`] bytes at a time.
+/// Output the guest streamed during the call is collected by the host
+/// anyway, and written at once; error payloads are read to build the
+/// [`HostError::GuestError`]. The sink is not flushed, and holds whatever
+/// was written before a failing write.
+#[cfg(feature = "wasmer")]
+pub fn call_into_with_options(
+    store: &mut StoreMut<'_>,
+    instance: Arc<Instance>,
+    name: &str,
+    input: impl Into<ExternIO>,
+    sink: &mut dyn io::Write,
+    options: &CallOptions,
+) -> Result<ForwardedResult, wasmer::RuntimeError> {
+    let input = input.into();
+    let user_error = |e: HostError| wasmer::RuntimeError::user(Box::new(e));
+    let Returned {
+        memory,
+        abi,
+        result: wasm_result,
+        streamed,
+        max_output,
+    } = run_guest(store, instance, name, &input, options, &OnceLock::new())?;
+    let slice = wasm_result.slice();
+
+    if slice.is_empty() {
+        if wasm_result.is_err() {
+            return Err(guest_error(
+                name,
+                &input,
+                &[],
+                payloadless_error_message(wasm_result),
+            ));
+        }
+        return Ok(ForwardedResult {
+            header: None,
+            bytes: 0,
+        });
+    }
+
+    if slice.len as usize > max_output {
+        return Err(user_error(HostError::OutputTooLarge {
+            len: slice.len as usize,
+            max: max_output,
+        }));
+    }
+
+    let view = memory.view(store);
+    let envelope = if abi.has(GuestCapability::EnvelopeIo) {
+        envelope_in_place(&view, slice).map_err(user_error)?
+    } else {
+        None
+    };
+    let (header, ptr, len) = match envelope {
+        Some(header) => (
+            Some(header),
+            slice.ptr as u64 + EnvelopeHeader::SIZE as u64,
+            u64::from(header.payload_len),
+        ),
+        None => (None, slice.ptr as u64, u64::from(slice.len)),
+    };
+
+    if wasm_result.is_err() || header.is_some_and(|header| header.is_error()) {
+        let payload = read_guest(&view, ptr, len).map_err(user_error)?;
+        return Err(guest_error(
+            name,
+            &input,
+            &payload,
+            guest_error_message(&payload),
+        ));
+    }
+
+    if let (Some(header), Some(streamed)) = (header, streamed) {
+        let marker = read_guest(&view, ptr, len).map_err(user_error)?;
+        let streamed = streamed_result(&marker, streamed, max_output).map_err(user_error)?;
+        sink.write_all(&streamed)
+            .map_err(|e| user_error(write_error(e)))?;
+        return Ok(ForwardedResult {
+            header: Some(header.info()),
+            bytes: streamed.len() as u64,
+        });
+    }
+
+    for_each_chunk(&view, ptr, len, |chunk| {
+        sink.write_all(chunk).map_err(write_error)
+    })
+    .map_err(user_error)?;
+    Ok(ForwardedResult {
+        header: header.map(|header| header.info()),
+        bytes: len,
+    })
+}
+
+/// The header of the result `slice` if it is exactly one valid envelope,
+/// checked without copying it out of `view`
+///
+/// The counterpart of [`whole_envelope`] for results left in guest memory.
+#[cfg(feature = "wasmer")]
+fn envelope_in_place(
+    view: &MemoryView<'_>,
+    slice: WasmSlice,
+) -> Result<Option<EnvelopeHeader>, HostError> {
+    let mut bytes = [0u8; EnvelopeHeader::SIZE];
+    if (slice.len as usize) < bytes.len() {
+        return Ok(None);
+    }
+    view.read(slice.ptr as u64, &mut bytes)
+        .map_err(read_error)?;
+    let header = EnvelopeHeader::from_bytes(&bytes);
+    if header.validate(None).is_err() || header.envelope_len() != Some(slice.len as usize) {
+        return Ok(None);
+    }
+
+    // Either algorithm is accepted, as by `decode_envelope`
+    let mut hashers = [ChecksumKind::Crc32, ChecksumKind::Crc32c].map(ChecksumHasher::new);
+    for_each_chunk(
+        view,
+        slice.ptr as u64 + bytes.len() as u64,
+        u64::from(header.payload_len),
+        |chunk| {
+            hashers.iter_mut().for_each(|hasher| hasher.update(chunk));
+            Ok(())
+        },
+    )?;
+    let matches = hashers.map(|hasher| hasher.finalize() == header.checksum);
+    Ok(matches.contains(&true).then_some(header))
+}
+
+/// Pass the `len` bytes of `view` at `ptr` to `f`, [`FORWARD_CHUNK_LEN`]
+/// bytes at a time
+#[cfg(feature = "wasmer")]
+fn for_each_chunk(
+    view: &MemoryView<'_>,
+    ptr: u64,
+    len: u64,
+    mut f: impl FnMut(&[u8]) -> Result<(), HostError>,
+) -> Result<(), HostError> {
+    let mut buffer = vec![0u8; len.min(FORWARD_CHUNK_LEN as u64) as usize];
+    let mut offset = 0;
+    while offset < len {
+        let chunk = &mut buffer[..(len - offset).min(FORWARD_CHUNK_LEN as u64) as usize];
+        view.read(ptr + offset, chunk).map_err(read_error)?;
+        f(chunk)?;
+        offset += chunk.len() as u64;
+    }
+    Ok(())
+}
+
+/// The `len` bytes of `view` at `ptr`
+#[cfg(feature = "wasmer")]
+fn read_guest(view: &MemoryView<'_>, ptr: u64, len: u64) -> Result<Vec<u8>, HostError> {
+    let mut bytes = vec![0u8; len as usize];
+    view.read(ptr, &mut bytes).map_err(read_error)?;
+    Ok(bytes)
+}
+
+#[cfg(feature = "wasmer")]
+fn read_error(e: wasmer::MemoryAccessError) -> HostError {
+    HostError::MemoryAccess(format!("Failed to read result: {}", e))
+}
+
+#[cfg(feature = "wasmer")]
+fn write_error(e: io::Error) -> HostError {
+    HostError::Runtime(format!("Failed to write result: {}", e))
+}
+
+/// A guest call that returned, with what it returned
+#[cfg(feature = "wasmer")]
+struct Returned {
+    memory: Memory,
+    abi: GuestAbi,
+    /// The result, validated and well-formed
+    result: WasmResult,
+    /// Output the guest streamed during the call, `None` unless it called
+    /// the output chunk import; its result is then the streamed marker
+    streamed: Option<Vec<u8>>,
+    max_output: usize,
+}
+
+/// Write the input of a call to the guest and run it, up to the result
+#[cfg(feature = "wasmer")]
+fn run_guest(
+    store: &mut StoreMut<'_>,
+    instance: Arc<Instance>,
+    name: &str,
+    input_bytes: &[u8],
+    options: &CallOptions,
+    order: &OnceLock<SliceOrder>,
+) -> Result<Returned, wasmer::RuntimeError> {
+    let max_input = options.max_input_len.unwrap_or(DEFAULT_MAX_INPUT_LEN);
+    if input_bytes.len() > max_input {
+        return Err(wasmer::RuntimeError::user(Box::new(
+            HostError::InputTooLarge {
+                len: input_bytes.len(),
+                max: max_input,
+            },
+        )));
+    }
+
+    let abi = match options.abi {
+        Some(abi) => abi,
+        None => GuestAbi::probe(&instance, store),
+    };
+    check_protocol(abi, options.version_policy.unwrap_or_default())
+        .map_err(|e| wasmer::RuntimeError::user(Box::new(e)))?;
+    record(TraceStepKind::Abi, None, None, || format!("{:?}", abi));
+
+    // Get the memory and allocate function from the instance; guests
+    // importing their memory have to export it again to be called here
+    let memory = exported_memory(&instance.exports, None)
+        .ok_or_else(|| wasmer::RuntimeError::new("Failed to get memory: no memory exported"))?;
 
     let allocate = instance
         .exports
         .get_typed_function::<i32, i32>(store, "__hc__allocate_1")
         .map_err(|e| wasmer::RuntimeError::new(format!("Failed to get allocate: {}", e)))?;
 
-    let input_bytes = input.as_ref();
     let input_len = input_bytes.len() as i32;
 
     // Allocate memory for input in guest
     let input_ptr = allocate.call(store, input_len)?;
+    guest_allocation(input_ptr).map_err(|e| wasmer::RuntimeError::user(Box::new(e)))?;
+    let input_region = Some((input_ptr as u32 as u64, input_bytes.len() as u64));
+    record(TraceStepKind::Allocate, input_region, None, || {
+        "__hc__allocate_1".to_string()
+    });
 
     // Write input to guest memory
     let view = memory.view(store);
     view.write(input_ptr as u64, input_bytes)
         .map_err(|e| wasmer::RuntimeError::new(format!("Failed to write input: {}", e)))?;
+    record(
+        TraceStepKind::Write,
+        input_region,
+        Some(input_bytes),
+        || format!("{} bytes of input", input_bytes.len()),
+    );
 
     // Get the target function
     let func = instance
@@ -129,8 +788,21 @@ pub fn call(
         .get_function(name)
         .map_err(|e| wasmer::RuntimeError::new(format!("Function '{}' not found: {}", name, e)))?;
 
-    // Call the function
-    let results = func.call(store, &[Value::I32(input_ptr), Value::I32(input_len)])?;
+    // Call the function, collecting any output it streams
+    let max_output = options.max_output_len.unwrap_or(DEFAULT_MAX_OUTPUT_LEN);
+    let (results, streamed) = collect_output(max_output, || {
+        func.call(store, &[Value::I32(input_ptr), Value::I32(input_len)])
+    });
+    record(TraceStepKind::Call, None, None, || {
+        match (&results, &streamed) {
+            (Ok(_), None) => format!("{} returned", name),
+            (Ok(_), Some(streamed)) => {
+                format!("{} returned, streaming {} bytes", name, streamed.len())
+            }
+            (Err(e), _) => format!("{} failed: {}", name, e),
+        }
+    });
+    let results = results?;
 
     // Parse the result (returns i64 containing pointer and length)
     let result_packed = results
@@ -138,26 +810,274 @@ pub fn call(
         .and_then(|v| v.i64())
         .ok_or_else(|| wasmer::RuntimeError::new("Invalid return type from guest"))?;
 
-    let wasm_result = WasmResult::from_raw(result_packed as u64);
+    let raw = result_packed as u64;
+    let order = match (abi.is_legacy(), options.slice_order) {
+        (false, _) => SliceOrder::PtrLen,
+        (true, Some(order)) => order,
+        (true, None) => match order.get() {
+            Some(order) => *order,
+            None => detect_result_order(store, memory, raw, max_output)
+                .map_or(SliceOrder::PtrLen, |detected| {
+                    *order.get_or_init(|| detected)
+                }),
+        },
+    };
+    let wasm_result = match order {
+        SliceOrder::PtrLen => GuestReturn::from_raw(raw).as_result(),
+        // Legacy results carry no error bit
+        SliceOrder::LenPtr => WasmResult::ok(order.unpack(raw)),
+    };
     let slice = wasm_result.slice();
+    record(
+        TraceStepKind::Unpack,
+        Some((slice.ptr as u64, slice.len as u64)),
+        None,
+        || {
+            let status = if wasm_result.is_err() { "error" } else { "ok" };
+            format!("{:#018x} read {:?}, {}", raw, order, status)
+        },
+    );
+    slice
+        .validate()
+        .map_err(|e| wasmer::RuntimeError::user(Box::new(HostError::Memory(e))))?;
+    if !wasm_result.is_well_formed() {
+        return Err(wasmer::RuntimeError::user(Box::new(
+            HostError::InvalidReturn,
+        )));
+    }
 
-    if slice.is_empty() {
-        return Ok(Vec::new());
+    Ok(Returned {
+        memory: memory.clone(),
+        abi,
+        result: wasm_result,
+        streamed,
+        max_output,
+    })
+}
+
+/// A [`HostError::GuestError`] returned by the guest function `name`
+///
+/// With the `debug-tools` feature, the input and error payloads are logged
+/// as JSON at debug level, redacted as
+/// [`transcode_msgpack_to_json`](crate::transcode_msgpack_to_json) does.
+#[cfg(feature = "wasmer")]
+fn guest_error(name: &str, input: &[u8], payload: &[u8], message: String) -> wasmer::RuntimeError {
+    #[cfg(feature = "debug-tools")]
+    if tracing::enabled!(tracing::Level::DEBUG) {
+        use crate::debug_json::payload_for_log;
+
+        let _span = tracing::debug_span!(
+            "guest_error",
+            function = name,
+            input = %payload_for_log(input),
+            payload = %payload_for_log(payload)
+        )
+        .entered();
+        tracing::debug!("Guest returned an error: {}", message);
     }
+    #[cfg(not(feature = "debug-tools"))]
+    let _ = (name, input, payload);
 
-    // Read the result from guest memory
+    wasmer::RuntimeError::user(Box::new(HostError::GuestError(message)))
+}
+
+/// The order a legacy guest packed its result `raw` in, if the result tells
+///
+/// Legacy guests return plain MessagePack, so a reading is plausible if it
+/// holds exactly one MessagePack value.
+#[cfg(feature = "wasmer")]
+fn detect_result_order(
+    store: &StoreMut<'_>,
+    memory: &Memory,
+    raw: u64,
+    max_output: usize,
+) -> Option<SliceOrder> {
     let view = memory.view(store);
-    let mut result_bytes = vec![0u8; slice.len as usize];
-    view.read(slice.ptr as u64, &mut result_bytes)
-        .map_err(|e| wasmer::RuntimeError::new(format!("Failed to read result: {}", e)))?;
+    SliceOrder::detect(raw, view.data_size(), |slice| {
+        if slice.len as usize > max_output {
+            return false;
+        }
+        let mut bytes = vec![0u8; slice.len as usize];
+        view.read(slice.ptr as u64, &mut bytes).is_ok() && is_msgpack_value(&bytes)
+    })
+}
 
-    Ok(result_bytes)
+/// Whether `bytes` hold exactly one MessagePack value
+#[cfg(feature = "wasmer")]
+fn is_msgpack_value(bytes: &[u8]) -> bool {
+    let mut rest = bytes;
+    let parsed = <serde::de::IgnoredAny as serde::Deserialize>::deserialize(
+        &mut rmp_serde::Deserializer::new(&mut rest),
+    )
+    .is_ok();
+    parsed && rest.is_empty()
+}
+
+/// Decode `bytes` if they hold exactly one valid envelope
+///
+/// Guests supporting envelopes still return raw MessagePack from functions
+/// written against the compatibility layer, so results are only unwrapped
+/// when they are an envelope.
+#[cfg(feature = "wasmer")]
+fn whole_envelope(bytes: &[u8]) -> Option<DecodedEnvelope<'_>> {
+    decode_envelope(bytes)
+        .ok()
+        .filter(|envelope| EnvelopeHeader::SIZE + envelope.payload.len() == bytes.len())
+}
+
+/// Result of a call that streamed its output
+///
+/// `marker` is the payload of the envelope the guest returned after
+/// streaming, the total length it streamed, which must match what was
+/// collected.
+#[cfg(feature = "wasmer")]
+fn streamed_result(marker: &[u8], streamed: Vec<u8>, max: usize) -> Result<Vec<u8>, HostError> {
+    let announced = <[u8; 8]>::try_from(marker)
+        .map(u64::from_le_bytes)
+        .map_err(|_| HostError::Stream("malformed streamed output marker".to_string()))?;
+    if announced > max as u64 {
+        return Err(HostError::OutputTooLarge {
+            len: usize::try_from(announced).unwrap_or(usize::MAX),
+            max,
+        });
+    }
+    if announced != streamed.len() as u64 {
+        return Err(HostError::Stream(format!(
+            "guest announced {} bytes of streamed output but sent {}",
+            announced,
+            streamed.len()
+        )));
+    }
+    Ok(streamed)
+}
+
+/// Check that the host accepts the protocol version of a guest reporting
+/// `abi` under `policy`
+///
+/// Fails with [`HostError::ProtocolMismatch`] naming both versions.
+pub fn check_protocol(abi: GuestAbi, policy: VersionPolicy) -> Result<GuestAbi, HostError> {
+    match policy.accepts(abi.compatibility()) {
+        true => Ok(abi),
+        false => Err(HostError::ProtocolMismatch {
+            guest: abi.protocol_version,
+            host: PROTOCOL_VERSION,
+        }),
+    }
+}
+
+/// Message for an error result without a payload
+///
+/// Names the kind the guest recorded with
+/// [`WasmResult::err_with_kind`], if any.
+pub fn payloadless_error_message(result: WasmResult) -> String {
+    match result.kind() {
+        Some(kind) => WasmError::without_payload(kind).to_string(),
+        None => "empty error".to_string(),
+    }
+}
+
+/// Extract the message from an error returned by `return_err_ptr`
+///
+/// Guests send a serialized `{ error_type, message }` struct, or the fixed
+/// layout of [`encode_fixed_error`](aingle_wasmer_common::encode_fixed_error)
+/// when built with the guest crate's `min-size` feature, falling back to the
+/// plain UTF-8 message when the error cannot be serialized.
+pub fn guest_error_message(bytes: &[u8]) -> String {
+    if let Some(inner) = WasmErrorInner::decode_fixed(bytes) {
+        return WasmError::GuestStructured(inner).to_string();
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct SerializableError {
+        #[allow(dead_code)]
+        error_type: String,
+        message: String,
+    }
+
+    match aingle_middleware_bytes::decode::<_, SerializableError>(bytes) {
+        Ok(error) => error.message,
+        Err(_) => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Call a guest function and attribute the points it consumed to `key`
+///
+/// Instances compiled without metering are recorded as consuming no points.
+#[cfg(feature = "wasmer")]
+pub fn call_metered(
+    store: &mut StoreMut<'_>,
+    instance: Arc<Instance>,
+    name: &str,
+    input: impl Into<ExternIO>,
+    ledger: &MeteringLedger,
+    key: [u8; 32],
+) -> Result<Vec<u8>, wasmer::RuntimeError> {
+    let before = remaining_points(store, &instance);
+    let result = call(store, instance.clone(), name, input);
+    let after = remaining_points(store, &instance);
+
+    ledger.record(key, before.saturating_sub(after));
+    result
+}
+
+/// Call a guest function unless the module `key` is quarantined
+///
+/// Fails fast with [`HostError::Quarantined`] while `tracker` holds the module
+/// in quarantine, and records the outcome of calls that do run.
+#[cfg(feature = "wasmer")]
+pub fn call_tracked(
+    store: &mut StoreMut<'_>,
+    instance: Arc<Instance>,
+    name: &str,
+    input: impl Into<ExternIO>,
+    tracker: &FailureTracker,
+    key: [u8; 32],
+) -> Result<Vec<u8>, wasmer::RuntimeError> {
+    tracker
+        .check(&key)
+        .map_err(|e| wasmer::RuntimeError::user(Box::new(e)))?;
+
+    let result = call(store, instance, name, input);
+    let outcome = result
+        .as_ref()
+        .map(|_| ())
+        .map_err(|e| HostError::from(e.clone()));
+    tracker.record(&key, &outcome);
+    result
+}
+
+/// Remaining metering points, or zero for unmetered or exhausted instances
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+fn remaining_points(store: &mut StoreMut<'_>, instance: &Instance) -> u64 {
+    use wasmer_middlewares::metering::{get_remaining_points, MeteringPoints};
+
+    if instance
+        .exports
+        .get_global("wasmer_metering_remaining_points")
+        .is_err()
+    {
+        return 0;
+    }
+
+    match get_remaining_points(store, instance) {
+        MeteringPoints::Remaining(points) => points,
+        MeteringPoints::Exhausted => 0,
+    }
+}
+
+/// Guests on the JavaScript engine are never metered
+#[cfg(all(
+    feature = "wasmer_js",
+    not(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))
+))]
+fn remaining_points(_store: &mut StoreMut<'_>, _instance: &Instance) -> u64 {
+    0
 }
 
 /// Call a guest function with raw bytes (legacy alias for call)
 ///
 /// This is now an alias for `call` since `call` already accepts `&[u8]`.
-#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+#[cfg(feature = "wasmer")]
 #[deprecated(since = "0.0.2", note = "Use call() directly, it now accepts &[u8]")]
 pub fn call_raw(
     store: &mut StoreMut<'_>,
@@ -168,23 +1088,46 @@ pub fn call_raw(
     call(store, instance, name, input)
 }
 
+/// Range of `len` bytes at `ptr` within `memory`
+///
+/// Bounds are compared as `u64`, so `ptr + len` cannot wrap on 32-bit hosts.
+fn guest_range(memory: &[u8], ptr: u32, len: u64) -> Result<std::ops::Range<usize>, HostError> {
+    let start = ptr as u64;
+    let end = start + len;
+    if end > memory.len() as u64 {
+        return Err(HostError::out_of_bounds(start, len, memory.len() as u64));
+    }
+    Ok(start as usize..end as usize)
+}
+
 /// Consume bytes from guest memory
 ///
 /// This is a helper function that reads bytes directly from guest memory.
 pub fn consume_bytes_from_guest(memory: &[u8], ptr: u32, len: u32) -> Result<Vec<u8>, HostError> {
-    let start = ptr as usize;
-    let end = start + len as usize;
-
-    if end > memory.len() {
-        return Err(HostError::MemoryAccess(format!(
-            "out of bounds: {}..{} > {}",
-            start,
-            end,
-            memory.len()
-        )));
-    }
+    Ok(memory[guest_range(memory, ptr, len as u64)?].to_vec())
+}
 
-    Ok(memory[start..end].to_vec())
+/// Consume bytes from guest memory into a caller-owned buffer
+///
+/// `out` is cleared and reused, so repeated reads avoid allocating.
+pub fn consume_bytes_into(
+    memory: &[u8],
+    ptr: u32,
+    len: u32,
+    out: &mut Vec<u8>,
+) -> Result<(), HostError> {
+    out.clear();
+    consume_with(memory, ptr, len, |bytes| out.extend_from_slice(bytes))
+}
+
+/// Pass bytes from guest memory to `f` without copying them
+pub fn consume_with<R>(
+    memory: &[u8],
+    ptr: u32,
+    len: u32,
+    f: impl FnOnce(&[u8]) -> R,
+) -> Result<R, HostError> {
+    Ok(f(&memory[guest_range(memory, ptr, len as u64)?]))
 }
 
 /// Move data to guest memory
@@ -195,19 +1138,8 @@ pub fn move_data_to_guest(
     ptr: u32,
     data: &[u8],
 ) -> Result<WasmSlice, HostError> {
-    let start = ptr as usize;
-    let end = start + data.len();
-
-    if end > memory.len() {
-        return Err(HostError::MemoryAccess(format!(
-            "out of bounds: {}..{} > {}",
-            start,
-            end,
-            memory.len()
-        )));
-    }
-
-    memory[start..end].copy_from_slice(data);
+    let range = guest_range(memory, ptr, data.len() as u64)?;
+    memory[range].copy_from_slice(data);
     Ok(WasmSlice::new(ptr, data.len() as u32))
 }
 
@@ -224,7 +1156,7 @@ pub fn build_guest_result(data: &[u8], is_error: bool) -> Result<Vec<u8>, HostEr
 
     let mut buffer = vec![0u8; data.len() + 64];
     let len = encode_with_envelope(data, flags, &mut buffer)
-        .map_err(|e| HostError::Serialization(format!("{:?}", e)))?;
+        .map_err(|e| HostError::Serialization(e.to_string()))?;
 
     buffer.truncate(len);
     Ok(buffer)
@@ -233,6 +1165,7 @@ pub fn build_guest_result(data: &[u8], is_error: bool) -> Result<Vec<u8>, HostEr
 #[cfg(test)]
 mod tests {
     use super::*;
+    use aingle_wasmer_common::{ErrorKind, MemoryError};
 
     #[test]
     fn test_extern_io_roundtrip() {
@@ -253,11 +1186,771 @@ mod tests {
         assert_eq!(original, decoded);
     }
 
+    #[test]
+    fn test_extern_io_compact() {
+        #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+        struct Reading {
+            id: u16,
+            temp: i16,
+            humidity: u8,
+            pressure: u16,
+            battery: u8,
+            rssi: i8,
+            seq: u32,
+            ts: u32,
+            ok: bool,
+            unit: char,
+        }
+
+        let reading = Reading {
+            id: 7,
+            temp: -4,
+            humidity: 61,
+            pressure: 1013,
+            battery: 88,
+            rssi: -70,
+            seq: 1,
+            ts: 1_700_000_000,
+            ok: true,
+            unit: 'C',
+        };
+        let named = ExternIO::encode(&reading).unwrap();
+        let compact = ExternIO::encode_compact(&reading).unwrap();
+        assert_eq!((named.len(), compact.len()), (73, 19));
+        assert!(compact.len() * 10 <= named.len() * 6);
+
+        // Either encoding decodes, here and in the middleware guests use
+        assert_eq!(named.decode::<Reading>().unwrap(), reading);
+        assert_eq!(compact.decode::<Reading>().unwrap(), reading);
+        let guest: Reading = aingle_middleware_bytes::decode(compact.as_bytes()).unwrap();
+        assert_eq!(guest, reading);
+        let host = ExternIO::new(aingle_middleware_bytes::encode(&reading).unwrap());
+        assert_eq!(host, named);
+
+        // Values other than structs encode the same either way
+        let value = (7u32, "seven".to_string(), vec![1u8, 2]);
+        assert_eq!(
+            ExternIO::encode(&value).unwrap(),
+            ExternIO::encode_compact(&value).unwrap()
+        );
+        assert!(ExternIO::encode_compact(()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_extern_encoding_negotiate() {
+        let compact = GuestAbi::current(0, 1, GuestCapability::CompactEncoding as u32);
+        let named = GuestAbi::current(0, 1, GuestCapability::EnvelopeIo as u32);
+
+        assert_eq!(
+            ExternEncoding::Compact.negotiate(compact),
+            ExternEncoding::Compact
+        );
+        assert_eq!(
+            ExternEncoding::Compact.negotiate(named),
+            ExternEncoding::Named
+        );
+        assert_eq!(
+            ExternEncoding::Compact.negotiate(GuestAbi::legacy()),
+            ExternEncoding::Named
+        );
+        assert_eq!(
+            ExternEncoding::Named.negotiate(compact),
+            ExternEncoding::Named
+        );
+        assert_eq!(CallOptions::default().encoding, None);
+    }
+
+    #[test]
+    fn test_extern_io_wire_format() {
+        #[derive(serde::Serialize)]
+        struct Legacy(#[serde(with = "serde_bytes")] Vec<u8>);
+
+        let payload = vec![0xc1, 0x00, 0x7f, 0xff];
+        let io = ExternIO::new(payload.clone());
+
+        let wire = rmp_serde::to_vec(&io).unwrap();
+        assert_eq!(wire, rmp_serde::to_vec(&Legacy(payload.clone())).unwrap());
+        assert_eq!(wire, [0xc4, 4, 0xc1, 0x00, 0x7f, 0xff]);
+
+        let back: ExternIO = rmp_serde::from_slice(&wire).unwrap();
+        assert_eq!(back, io);
+        assert_eq!(back.into_vec(), payload);
+    }
+
+    #[test]
+    fn test_extern_io_unit() {
+        #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+        struct Ack;
+
+        // unit -> unit
+        let unit = ExternIO::encode(()).unwrap();
+        assert!(unit.is_empty());
+        unit.decode::<()>().unwrap();
+        assert_eq!(ExternIO::encode(Ack).unwrap().decode::<Ack>().unwrap(), Ack);
+
+        // unit -> T
+        assert_eq!(
+            unit.decode::<u32>().unwrap_err().to_string(),
+            "serialization error: Failed to decode: UnexpectedEof: expected `u32` but the \
+             payload is empty"
+        );
+
+        // T -> unit, with the result of an older peer serializing unit as nil
+        assert_eq!(ExternIO::encode(7u32).unwrap().decode::<u32>().unwrap(), 7);
+        ExternIO::new(vec![0xc0]).decode::<()>().unwrap();
+    }
+
+    #[test]
+    fn test_extern_io_clone_shares_bytes() {
+        let io = ExternIO::new(vec![7u8; 1024]);
+        let clone = io.clone();
+        assert_eq!(io.as_bytes().as_ptr(), clone.as_bytes().as_ptr());
+
+        const PING: ExternIO = ExternIO::from_static(b"ping");
+        assert_eq!(PING.as_bytes(), b"ping");
+        assert_eq!(Vec::from(PING), b"ping");
+    }
+
+    #[test]
+    fn test_extern_io_conversions() {
+        let io = ExternIO::from(b"ping");
+        assert_eq!(io.as_bytes(), b"ping");
+        assert_eq!(ExternIO::from(&b"ping"[..]), io);
+        assert_eq!(ExternIO::from(&b"ping".to_vec()), io);
+
+        let shared = ExternIO::from(&io);
+        assert_eq!(shared.as_bytes().as_ptr(), io.as_bytes().as_ptr());
+    }
+
+    #[test]
+    #[cfg(feature = "zome_types")]
+    fn test_zome_types_extern_io() {
+        let zome = aingle_zome_types::ExternIO(b"ping".to_vec());
+        let ptr = zome.0.as_ptr();
+        let io = ExternIO::from(zome);
+        assert_eq!(io.as_bytes().as_ptr(), ptr);
+
+        let zome = aingle_zome_types::ExternIO::from(io);
+        assert_eq!(zome.0.as_ptr(), ptr);
+        assert_eq!(zome.0, b"ping");
+
+        let reading = ("sensor", 7u32);
+        let zome = aingle_zome_types::ExternIO::encode(reading).unwrap();
+        assert_eq!(
+            ExternIO::from(zome).decode::<(String, u32)>().unwrap(),
+            ("sensor".to_string(), 7)
+        );
+    }
+
+    #[test]
+    fn test_extern_io_envelope() {
+        use aingle_wasmer_common::{ChecksumKind, EnvelopeFlags, EnvelopeHeader};
+
+        let io = ExternIO::encode(("ping", 7u32)).unwrap();
+        let wire = io.to_envelope(0).unwrap();
+        assert_eq!(wire.len(), EnvelopeHeader::SIZE + io.len());
+        assert_eq!(ExternIO::from_envelope(&wire).unwrap(), io);
+        let mut wire = vec![0u8; EnvelopeHeader::SIZE + io.len()];
+        aingle_wasmer_codec::encode_envelope_with(ChecksumKind::Crc32c, &io, 0, &mut wire).unwrap();
+        assert_eq!(ExternIO::from_envelope(&wire).unwrap(), io);
+
+        let mut corrupt = wire.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            ExternIO::from_envelope(&corrupt),
+            Err(HostError::Deserialization(_))
+        ));
+
+        let error = ExternIO::new(b"no greeting today".to_vec());
+        let wire = error.to_envelope(EnvelopeFlags::IsError as u8).unwrap();
+        match ExternIO::from_envelope(&wire) {
+            Err(HostError::GuestError(message)) => assert_eq!(message, "no greeting today"),
+            other => panic!("expected a guest error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_output_too_large() {
+        use wasmer::{imports, AsStoreMut, Module, Store};
+
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (memory (export "memory") 1)
+                (func (export "__hc__allocate_1") (param i32) (result i32)
+                    (i32.const 1024))
+                (func (export "huge") (param i32 i32) (result i64)
+                    (i64.const 0x0000040010000000)))
+            "#,
+        )
+        .unwrap();
+
+        let mut store = Store::default();
+        let module = Module::new(&store, wasm).unwrap();
+        let instance = Instance::new(&mut store, &module, &imports! {}).unwrap();
+
+        let err = call(&mut store.as_store_mut(), Arc::new(instance), "huge", b"x").unwrap_err();
+        assert!(matches!(
+            err.downcast::<HostError>(),
+            Ok(HostError::OutputTooLarge {
+                len: 0x1000_0000,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_rejects_failed_allocation() {
+        use wasmer::{imports, AsStoreMut, Module, Store};
+
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (memory (export "memory") 1)
+                (func (export "__hc__allocate_1") (param i32) (result i32)
+                    (i32.const 0))
+                (func (export "echo") (param i32 i32) (result i64)
+                    (i64.const 0)))
+            "#,
+        )
+        .unwrap();
+
+        let mut store = Store::default();
+        let module = Module::new(&store, wasm).unwrap();
+        let instance = Instance::new(&mut store, &module, &imports! {}).unwrap();
+
+        let err = call(&mut store.as_store_mut(), Arc::new(instance), "echo", b"x").unwrap_err();
+        assert!(matches!(
+            err.downcast::<HostError>(),
+            Ok(HostError::MemoryAccess(message)) if message == "guest allocation failed"
+        ));
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_streamed_output() {
+        use crate::ImportBuilder;
+        use aingle_wasmer_codec::encode_with_envelope;
+        use wasmer::{AsStoreMut, Module, Store};
+
+        const CHUNK: usize = 32 * 1024;
+        const CHUNKS: usize = 128;
+
+        let mut marker = [0u8; EnvelopeHeader::SIZE + 8];
+        let total = (CHUNK * CHUNKS) as u64;
+        encode_with_envelope(&total.to_le_bytes(), 0, &mut marker).unwrap();
+        let marker: String = marker.iter().map(|b| format!("\\{:02x}", b)).collect();
+
+        // Streams chunk i filled with byte i, trapping if a chunk is rejected
+        let wasm = wat::parse_str(format!(
+            r#"
+            (module
+                (import "env" "__aingle_output_chunk" (func $chunk (param i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 40000) "{marker}")
+                (func (export "__hc__allocate_1") (param i32) (result i32)
+                    (i32.const 48000))
+                (func (export "stream") (param i32 i32) (result i64)
+                    (local $i i32)
+                    (loop $next
+                        (memory.fill (i32.const 1024) (local.get $i) (i32.const {CHUNK}))
+                        (if (call $chunk (i32.const 1024) (i32.const {CHUNK}))
+                            (then unreachable))
+                        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                        (br_if $next (i32.lt_u (local.get $i) (i32.const {CHUNKS}))))
+                    (i64.or
+                        (i64.shl (i64.const 40000) (i64.const 32))
+                        (i64.const {marker_len}))))
+            "#,
+            marker_len = EnvelopeHeader::SIZE + 8,
+        ))
+        .unwrap();
+
+        let mut store = Store::default();
+        let module = Module::new(&store, wasm).unwrap();
+        let instance = Arc::new(
+            ImportBuilder::new(&mut store)
+                .instantiate(&mut store, &module)
+                .unwrap(),
+        );
+        let mut options = CallOptions {
+            abi: Some(GuestAbi::current(0, 1, GuestCapability::EnvelopeIo as u32)),
+            ..Default::default()
+        };
+
+        let mut store = store.as_store_mut();
+        let output =
+            call_with_options(&mut store, instance.clone(), "stream", b"x", &options).unwrap();
+        assert_eq!(output.len(), CHUNK * CHUNKS);
+        for (i, chunk) in output.chunks(CHUNK).enumerate() {
+            assert!(chunk.iter().all(|&b| b == i as u8));
+        }
+
+        // Chunks past the output limit are rejected
+        options.max_output_len = Some(CHUNK * CHUNKS / 2);
+        assert!(call_with_options(&mut store, instance, "stream", b"x", &options).is_err());
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_into() {
+        use aingle_wasmer_codec::encode_with_envelope;
+        use aingle_wasmer_common::EnvelopeFlags;
+        use std::io::{Read, Seek};
+        use wasmer::{imports, AsStoreMut, Module, Store};
+
+        // Echoes its input raw, empty results at offset 0, and returns the
+        // envelope the test writes at 64 KiB, of the length given to `set_len`
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (memory (export "memory") 8)
+                (global $len (mut i32) (i32.const 0))
+                (func (export "set_len") (param i32)
+                    (global.set $len (local.get 0)))
+                (func (export "__hc__allocate_1") (param i32) (result i32)
+                    (i32.const 1024))
+                (func (export "echo") (param i32 i32) (result i64)
+                    (i64.or
+                        (i64.shl
+                            (i64.extend_i32_u
+                                (select (local.get 0) (i32.const 0) (local.get 1)))
+                            (i64.const 32))
+                        (i64.extend_i32_u (local.get 1))))
+                (func (export "wrapped") (param i32 i32) (result i64)
+                    (i64.or
+                        (i64.const 0x0001000000000000)
+                        (i64.extend_i32_u (global.get $len)))))
+            "#,
+        )
+        .unwrap();
+
+        let mut store = Store::default();
+        let module = Module::new(&store, wasm).unwrap();
+        let instance = Arc::new(Instance::new(&mut store, &module, &imports! {}).unwrap());
+        let memory = instance.exports.get_memory("memory").unwrap().clone();
+        let set_len = instance
+            .exports
+            .get_typed_function::<i32, ()>(&store, "set_len")
+            .unwrap();
+        let mut store = store.as_store_mut();
+        let options = CallOptions {
+            abi: Some(GuestAbi::current(0, 1, GuestCapability::EnvelopeIo as u32)),
+            ..Default::default()
+        };
+
+        // Both sinks receive what the conventional path returns
+        let forward = |store: &mut StoreMut<'_>, name: &str, input: &[u8]| {
+            let expected =
+                call_with_options(store, instance.clone(), name, input, &options).unwrap();
+
+            let mut vec = Vec::new();
+            let forwarded =
+                call_into_with_options(store, instance.clone(), name, input, &mut vec, &options)
+                    .unwrap();
+            assert_eq!(vec, expected);
+            assert_eq!(forwarded.bytes, expected.len() as u64);
+
+            let mut file = tempfile::tempfile().unwrap();
+            let to_file =
+                call_into_with_options(store, instance.clone(), name, input, &mut file, &options)
+                    .unwrap();
+            assert_eq!(to_file, forwarded);
+            let mut written = Vec::new();
+            file.rewind().unwrap();
+            file.read_to_end(&mut written).unwrap();
+            assert_eq!(written, expected);
+            forwarded
+        };
+
+        // Raw results, over several chunks
+        let input: Vec<u8> = (0..=255).cycle().take(3 * FORWARD_CHUNK_LEN + 5).collect();
+        assert_eq!(forward(&mut store, "echo", &input).header, None);
+        assert_eq!(forward(&mut store, "echo", b"").bytes, 0);
+
+        // An envelope, unwrapped
+        let payload: Vec<u8> = (0..=250).cycle().take(2 * FORWARD_CHUNK_LEN + 7).collect();
+        let mut envelope = vec![0u8; EnvelopeHeader::SIZE + payload.len()];
+        encode_with_envelope(&payload, 0, &mut envelope).unwrap();
+        let write_envelope = |store: &mut StoreMut<'_>, envelope: &[u8]| {
+            memory.view(store).write(0x1_0000, envelope).unwrap();
+            set_len.call(store, envelope.len() as i32).unwrap();
+        };
+        write_envelope(&mut store, &envelope);
+        let forwarded = forward(&mut store, "wrapped", b"x");
+        assert_eq!(forwarded.bytes, payload.len() as u64);
+        let header = forwarded.header.unwrap();
+        assert_eq!(header.payload_len as usize, payload.len());
+
+        // A checksum mismatch leaves the result raw, header included
+        let mut corrupt = envelope.clone();
+        *corrupt.last_mut().unwrap() ^= 0xff;
+        write_envelope(&mut store, &corrupt);
+        let forwarded = forward(&mut store, "wrapped", b"x");
+        assert_eq!(forwarded.header, None);
+        assert_eq!(forwarded.bytes, corrupt.len() as u64);
+
+        // Error envelopes fail without writing
+        let mut failed = vec![0u8; EnvelopeHeader::SIZE + 4];
+        encode_with_envelope(b"oops", EnvelopeFlags::IsError as u8, &mut failed).unwrap();
+        write_envelope(&mut store, &failed);
+        let mut sink = Vec::new();
+        let err = call_into_with_options(
+            &mut store,
+            instance.clone(),
+            "wrapped",
+            b"x",
+            &mut sink,
+            &options,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast::<HostError>(),
+            Ok(HostError::GuestError(message)) if message == "oops"
+        ));
+        assert!(sink.is_empty());
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_explained() {
+        use crate::ImportBuilder;
+        use crate::TraceStepKind as Step;
+        use wasmer::{AsStoreMut, Module, Store};
+
+        // Echoes its input after reporting a step of its own
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "env" "__aingle_trace" (func $trace (param i32 i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 16) "checked input")
+                (func (export "__hc__allocate_1") (param i32) (result i32)
+                    (i32.const 1024))
+                (func (export "echo") (param i32 i32) (result i64)
+                    (call $trace (i32.const 16) (i32.const 13))
+                    (i64.or
+                        (i64.shl (i64.extend_i32_u (local.get 0)) (i64.const 32))
+                        (i64.extend_i32_u (local.get 1)))))
+            "#,
+        )
+        .unwrap();
+
+        let mut store = Store::default();
+        let module = Module::new(&store, wasm).unwrap();
+        let instance = Arc::new(
+            ImportBuilder::new(&mut store)
+                .instantiate(&mut store, &module)
+                .unwrap(),
+        );
+        let mut store = store.as_store_mut();
+        let mut options = CallOptions {
+            abi: Some(GuestAbi::current(0, 1, 0)),
+            ..Default::default()
+        };
+
+        let (output, trace) =
+            call_explained(&mut store, instance.clone(), "echo", b"ping", &options);
+        assert_eq!(output.unwrap(), b"ping");
+        assert_eq!(trace, None);
+
+        options.explain = true;
+        let (output, trace) =
+            call_explained(&mut store, instance.clone(), "echo", b"ping", &options);
+        assert_eq!(output.unwrap(), b"ping");
+        let trace = trace.unwrap();
+        assert_eq!(trace.function, "echo");
+        assert_eq!(
+            trace.kinds(),
+            [
+                Step::Abi,
+                Step::Allocate,
+                Step::Write,
+                Step::Guest,
+                Step::Call,
+                Step::Unpack,
+                Step::Read,
+                Step::Decode
+            ]
+        );
+        let write = &trace.steps[2];
+        assert_eq!((write.offset, write.len), (Some(1024), Some(4)));
+        assert_eq!(write.preview.as_deref(), Some("70696e67"));
+        assert_eq!(trace.steps[3].detail, "checked input");
+        assert_eq!(trace.steps[7].detail, "raw result");
+
+        // Failed calls keep the steps up to the failure
+        let (output, trace) = call_explained(&mut store, instance, "missing", b"ping", &options);
+        assert!(output.is_err());
+        assert_eq!(
+            trace.unwrap().kinds(),
+            [Step::Abi, Step::Allocate, Step::Write]
+        );
+    }
+
+    #[test]
+    fn test_payloadless_error_message() {
+        let empty = WasmResult::err(WasmSlice::empty());
+        assert_eq!(payloadless_error_message(empty), "empty error");
+
+        let timeout = WasmResult::err_with_kind(WasmSlice::empty(), ErrorKind::Timeout);
+        assert_eq!(
+            payloadless_error_message(timeout),
+            "[Timeout] error without payload"
+        );
+    }
+
+    #[test]
+    fn test_check_protocol() {
+        let abi = |protocol_version| GuestAbi {
+            protocol_version,
+            ..GuestAbi::current(0, 1, 0)
+        };
+        let accepted = |version, policy| check_protocol(abi(version), policy).is_ok();
+
+        assert!(accepted(0, VersionPolicy::AcceptOlder));
+        assert!(accepted(1, VersionPolicy::AcceptOlder));
+        assert!(!accepted(2, VersionPolicy::AcceptOlder));
+        assert!(!accepted(0, VersionPolicy::RequireCurrent));
+        assert!(accepted(1, VersionPolicy::RequireCurrent));
+        assert!(!accepted(2, VersionPolicy::RequireCurrent));
+
+        assert!(matches!(
+            check_protocol(abi(2), VersionPolicy::default()),
+            Err(HostError::ProtocolMismatch { guest: 2, host: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_guest_error_message() {
+        #[derive(Debug, serde::Serialize)]
+        struct SerializableError {
+            error_type: String,
+            message: String,
+        }
+
+        let structured = aingle_middleware_bytes::encode(&SerializableError {
+            error_type: "Discriminant(0)".to_string(),
+            message: "serialization failed: unsupported type".to_string(),
+        })
+        .unwrap();
+        assert_eq!(
+            guest_error_message(&structured),
+            "serialization failed: unsupported type"
+        );
+        assert_eq!(guest_error_message(b"plain message"), "plain message");
+
+        // Guests built with `min-size`
+        let fixed = aingle_wasmer_common::encode_fixed_error(
+            aingle_wasmer_common::ErrorKind::Validation,
+            Some(("lib.rs", 7)),
+            b"count must be positive",
+        );
+        assert_eq!(
+            guest_error_message(&fixed),
+            "[Validation] count must be positive at lib.rs:7"
+        );
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_surfaces_guest_error() {
+        use wasmer::{imports, AsStoreMut, Module, Store};
+
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (memory (export "memory") 1)
+                (data (i32.const 16) "serialization error: bad field")
+                (func (export "__hc__allocate_1") (param i32) (result i32)
+                    (i32.const 1024))
+                (func (export "fails") (param i32 i32) (result i64)
+                    (i64.const 0x800000100000001e))
+                (func (export "fails_empty") (param i32 i32) (result i64)
+                    (i64.const 0x8000000000000000)))
+            "#,
+        )
+        .unwrap();
+
+        let mut store = Store::default();
+        let module = Module::new(&store, wasm).unwrap();
+        let instance = Arc::new(Instance::new(&mut store, &module, &imports! {}).unwrap());
+
+        let err = call(&mut store.as_store_mut(), instance.clone(), "fails", b"x").unwrap_err();
+        assert!(matches!(
+            err.downcast::<HostError>(),
+            Ok(HostError::GuestError(message)) if message == "serialization error: bad field"
+        ));
+
+        let err = call(&mut store.as_store_mut(), instance, "fails_empty", b"x").unwrap_err();
+        assert!(matches!(
+            err.downcast::<HostError>(),
+            Ok(HostError::GuestError(_))
+        ));
+    }
+
+    /// Returns the envelope the test writes at offset 64, optionally
+    /// reporting an ABI with envelope support
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn abi_fixture(with_abi: bool) -> String {
+        let abi = if with_abi {
+            r#"(func (export "__aingle_guest_abi") (result i64)
+                (i64.const 0x0000000300010001))"#
+        } else {
+            ""
+        };
+        format!(
+            r#"
+            (module
+                (memory (export "memory") 1)
+                (global $len (mut i32) (i32.const 0))
+                (func (export "set_len") (param i32)
+                    (global.set $len (local.get 0)))
+                (func (export "__hc__allocate_1") (param i32) (result i32)
+                    (i32.const 1024))
+                (func (export "wrapped") (param i32 i32) (result i64)
+                    (i64.or
+                        (i64.const 0x0000004000000000)
+                        (i64.extend_i32_u (global.get $len))))
+                {abi})
+            "#
+        )
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_guest_abi_handshake() {
+        use crate::Env;
+        use wasmer::{imports, AsStoreMut, Module, Store};
+
+        let envelope = build_guest_result(b"pong", false).unwrap();
+        for with_abi in [true, false] {
+            let mut store = Store::default();
+            let wasm = wat::parse_str(abi_fixture(with_abi)).unwrap();
+            let module = Module::new(&store, wasm).unwrap();
+            let instance = Arc::new(Instance::new(&mut store, &module, &imports! {}).unwrap());
+
+            let memory = instance.exports.get_memory("memory").unwrap();
+            memory.view(&store).write(64, &envelope).unwrap();
+            instance
+                .exports
+                .get_typed_function::<i32, ()>(&store, "set_len")
+                .unwrap()
+                .call(&mut store, envelope.len() as i32)
+                .unwrap();
+
+            let mut store = store.as_store_mut();
+            let env = Env::new();
+            let abi = env.guest_abi(&instance, &mut store);
+            assert_eq!(env.guest_abi(&instance, &mut store), abi);
+
+            let result = call(&mut store, instance.clone(), "wrapped", b"x").unwrap();
+            if with_abi {
+                assert_eq!(abi.protocol_version, 1);
+                assert_eq!((abi.crate_major, abi.crate_minor), (0, 1));
+                assert!(abi.has(GuestCapability::EnvelopeIo));
+                assert!(abi.has(GuestCapability::ArenaReset));
+                assert!(!abi.has(GuestCapability::Streaming));
+                assert_eq!(result, b"pong");
+            } else {
+                assert_eq!(abi, GuestAbi::legacy());
+                assert_eq!(result, envelope);
+            }
+
+            // A cached legacy ABI skips the probe and keeps the raw framing
+            let options = CallOptions {
+                abi: Some(GuestAbi::legacy()),
+                ..Default::default()
+            };
+            let raw = call_with_options(&mut store, instance, "wrapped", b"x", &options).unwrap();
+            assert_eq!(raw, envelope);
+        }
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_legacy_slice_order() {
+        use crate::Env;
+        use wasmer::{imports, AsStoreMut, Module, Store};
+
+        // Legacy guest returning a MessagePack string with its length first
+        let wat = r#"
+            (module
+                (memory (export "memory") 1)
+                (data (i32.const 64) "\a4pong")
+                (func (export "__hc__allocate_1") (param i32) (result i32)
+                    (i32.const 1024))
+                (func (export "legacy") (param i32 i32) (result i64)
+                    (i64.const 0x0000000500000040)))
+        "#;
+        let mut store = Store::default();
+        let module = Module::new(&store, wat::parse_str(wat).unwrap()).unwrap();
+        let instance = Arc::new(Instance::new(&mut store, &module, &imports! {}).unwrap());
+        let mut store = store.as_store_mut();
+        let pong = rmp_serde::to_vec("pong").unwrap();
+
+        // Detected from each result
+        assert_eq!(
+            call(&mut store, instance.clone(), "legacy", b"x").unwrap(),
+            pong
+        );
+
+        // Detected once per instance through the environment
+        let env = Env::new();
+        assert_eq!(env.slice_order(), None);
+        for _ in 0..2 {
+            let output = env
+                .call_guest(
+                    &mut store,
+                    instance.clone(),
+                    "legacy",
+                    b"x",
+                    &Default::default(),
+                )
+                .unwrap();
+            assert_eq!(output, pong);
+            assert_eq!(env.slice_order(), Some(SliceOrder::LenPtr));
+        }
+
+        // Forcing the current order reads the zeroes before the payload
+        let options = CallOptions {
+            slice_order: Some(SliceOrder::PtrLen),
+            ..Default::default()
+        };
+        let output = call_with_options(&mut store, instance, "legacy", b"x", &options).unwrap();
+        assert_eq!(output.len(), 64);
+
+        assert!(is_msgpack_value(&pong));
+        assert!(!is_msgpack_value(&output));
+        assert!(!is_msgpack_value(&[]));
+    }
+
     #[test]
     fn test_consume_bytes() {
         let memory = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
         let bytes = consume_bytes_from_guest(&memory, 2, 4).unwrap();
         assert_eq!(bytes, vec![2, 3, 4, 5]);
+
+        let mut out = vec![9; 16];
+        consume_bytes_into(&memory, 6, 3, &mut out).unwrap();
+        assert_eq!(out, [6, 7, 8]);
+        assert_eq!(consume_with(&memory, 0, 2, |b| b.to_vec()).unwrap(), [0, 1]);
+        assert!(consume_with(&memory, 8, 4, |_| ()).is_err());
+
+        assert!(matches!(
+            consume_bytes_from_guest(&memory, 8, 4),
+            Err(HostError::Memory(MemoryError::OutOfBounds {
+                offset: 8,
+                len: 4,
+                max: 10
+            }))
+        ));
+
+        // Ranges past u32::MAX are out of bounds rather than wrapping
+        assert!(consume_bytes_from_guest(&memory, u32::MAX, u32::MAX).is_err());
+        assert!(consume_with(&memory, 2, u32::MAX, |_| ()).is_err());
     }
 
     #[test]
@@ -269,5 +1962,7 @@ mod tests {
         assert_eq!(slice.ptr, 10);
         assert_eq!(slice.len, 5);
         assert_eq!(&memory[10..15], data);
+
+        assert!(move_data_to_guest(&mut memory, u32::MAX, data).is_err());
     }
 }
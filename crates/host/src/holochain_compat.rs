@@ -0,0 +1,293 @@
+//! Adapters with the names and signatures of `holochain_wasmer_host`
+//!
+//! Conductors moving over from `holochain_wasmer_host` can swap their
+//! imports for this module and keep most call sites as they are:
+//!
+//! ```ignore
+//! use aingle_wasmer_host::holochain_compat::{guest, ModuleCacheCompat, WasmError};
+//!
+//! let cache = ModuleCacheCompat::new(None);
+//! let module = cache.get(key, &wasm)?;
+//! let output: String = guest::call(&mut store, instance, "greet", input)?;
+//! ```
+//!
+//! Errors come back as [`WasmError`], whose [`WasmErrorInner`] has the
+//! variants of `holochain_wasmer_common` this crate has a counterpart for,
+//! built from [`HostError`] and [`aingle_wasmer_common::WasmError`].
+//!
+//! Semantics intentionally differ in two places:
+//!
+//! - Arena reset: guests built with the guest crate allocate from an arena
+//!   the host resets after each call, instead of having the host free each
+//!   allocation with `__hc__deallocate_1`. Pointers into guest memory do
+//!   not outlive the call that produced them.
+//! - Envelope framing: results of guests supporting envelopes are unwrapped
+//!   and checksummed before decoding, and their errors arrive as
+//!   [`WasmErrorInner::Guest`] with the guest's message instead of a
+//!   serialized `WasmError`. Inputs stay plain MessagePack.
+//!
+//! The names clash with the crate's own [`WasmError`](crate::WasmError)
+//! and [`WasmErrorInner`](crate::WasmErrorInner), so import this module or
+//! the [`prelude`](crate::prelude), not both.
+
+use crate::HostError;
+use serde::{Deserialize, Serialize};
+use std::panic::Location;
+
+#[cfg(feature = "wasmer")]
+use crate::ModuleCache;
+#[cfg(feature = "wasmer")]
+use std::path::PathBuf;
+#[cfg(feature = "wasmer")]
+use std::sync::Arc;
+#[cfg(feature = "wasmer")]
+use wasmer::{Module, RuntimeError};
+
+pub use aingle_middleware_bytes::SerializedBytesError;
+
+/// Key of a cached module, typically the hash of its WASM
+pub type CacheKey = [u8; 32];
+
+/// Error in the shape of `holochain_wasmer_common::WasmError`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+#[error("{error} at {file}:{line}")]
+pub struct WasmError {
+    /// File the error was raised in
+    pub file: String,
+    /// Line the error was raised at
+    pub line: u32,
+    /// What went wrong
+    pub error: WasmErrorInner,
+}
+
+/// The variants of `holochain_wasmer_common::WasmErrorInner` this crate
+/// has errors for
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+pub enum WasmErrorInner {
+    /// Guest memory could not be reached or allocated
+    #[error("memory error")]
+    Memory,
+    /// The guest reported an error
+    #[error("guest error: {0}")]
+    Guest(String),
+    /// A payload could not be serialized or deserialized
+    #[error("serialization error: {0}")]
+    Serialize(SerializedBytesError),
+    /// The module could not be compiled or loaded from the cache
+    #[error("compile error: {0}")]
+    Compile(String),
+    /// The call did not run to completion
+    #[error("call error: {0}")]
+    CallError(String),
+    /// The host reported an error
+    #[error("host error: {0}")]
+    Host(String),
+}
+
+impl WasmError {
+    /// Error located at the caller
+    #[track_caller]
+    pub fn here(error: WasmErrorInner) -> Self {
+        let location = Location::caller();
+        Self {
+            file: location.file().to_string(),
+            line: location.line(),
+            error,
+        }
+    }
+}
+
+impl From<HostError> for WasmError {
+    #[track_caller]
+    fn from(error: HostError) -> Self {
+        let inner = match error {
+            HostError::Compilation(message) | HostError::Cache(message) => {
+                WasmErrorInner::Compile(message)
+            }
+            HostError::MemoryNotFound
+            | HostError::UnsupportedMemory(_)
+            | HostError::MemoryAccess(_)
+            | HostError::Memory(_) => WasmErrorInner::Memory,
+            HostError::GuestError(message) => WasmErrorInner::Guest(message),
+            HostError::Serialization(message) => {
+                WasmErrorInner::Serialize(SerializedBytesError::Serialize(message))
+            }
+            HostError::Deserialization(message) => {
+                WasmErrorInner::Serialize(SerializedBytesError::Deserialize(message))
+            }
+            error => WasmErrorInner::CallError(error.to_string()),
+        };
+        Self::here(inner)
+    }
+}
+
+impl From<aingle_wasmer_common::WasmError> for WasmError {
+    #[track_caller]
+    fn from(error: aingle_wasmer_common::WasmError) -> Self {
+        use aingle_wasmer_common::WasmError as Native;
+
+        let inner = match &error {
+            Native::Serialize(_) => {
+                WasmErrorInner::Serialize(SerializedBytesError::Serialize(error.to_string()))
+            }
+            Native::Deserialize(_) => {
+                WasmErrorInner::Serialize(SerializedBytesError::Deserialize(error.to_string()))
+            }
+            Native::Memory(_) => WasmErrorInner::Memory,
+            Native::HostCall(_) => WasmErrorInner::Host(error.to_string()),
+            Native::GuestCall(_) => WasmErrorInner::CallError(error.to_string()),
+            Native::Guest(message) => WasmErrorInner::Guest(message.clone()),
+            Native::Host(message) => WasmErrorInner::Host(message.clone()),
+            Native::GuestStructured(inner) => {
+                let guest = WasmErrorInner::Guest(inner.message().to_string());
+                if let (Some(file), Some(line)) = (&inner.file, inner.line) {
+                    return Self {
+                        file: file.clone(),
+                        line,
+                        error: guest,
+                    };
+                }
+                guest
+            }
+        };
+        Self::here(inner)
+    }
+}
+
+/// [`ModuleCache`] with the signatures of `holochain_wasmer_host`'s
+///
+/// Failures are reported as a [`RuntimeError`] wrapping a [`WasmError`].
+#[cfg(feature = "wasmer")]
+pub struct ModuleCacheCompat(ModuleCache);
+
+#[cfg(feature = "wasmer")]
+impl ModuleCacheCompat {
+    /// Create a cache, persisting compiled modules in `maybe_fs_dir` if set
+    pub fn new(maybe_fs_dir: Option<PathBuf>) -> Self {
+        Self(ModuleCache::new(maybe_fs_dir))
+    }
+
+    /// Get the module cached under `key`, compiling `wasm` on a miss
+    pub fn get(&self, key: CacheKey, wasm: &[u8]) -> Result<Arc<Module>, RuntimeError> {
+        self.0.get(key, wasm).map_err(runtime_error)
+    }
+
+    /// The underlying cache
+    pub fn inner(&self) -> &ModuleCache {
+        &self.0
+    }
+}
+
+#[cfg(feature = "wasmer")]
+impl From<ModuleCache> for ModuleCacheCompat {
+    fn from(cache: ModuleCache) -> Self {
+        Self(cache)
+    }
+}
+
+/// Guest calls in the shape of `holochain_wasmer_host::guest`
+#[cfg(feature = "wasmer")]
+pub mod guest {
+    use super::runtime_error;
+    use crate::{ExternIO, HostError};
+    use serde::{de::DeserializeOwned, Serialize};
+    use std::sync::Arc;
+    use wasmer::{Instance, RuntimeError, StoreMut};
+
+    /// Call the guest function `f` with `input`, decoding its output as `O`
+    ///
+    /// Takes its arguments in the order of `holochain_wasmer_host`'s
+    /// `guest::call` and serializes both ways like it does; errors of this
+    /// crate are reported as a [`RuntimeError`] wrapping a
+    /// [`WasmError`](super::WasmError), traps of the guest as they are.
+    pub fn call<I, O>(
+        store_mut: &mut StoreMut<'_>,
+        instance: Arc<Instance>,
+        f: &str,
+        input: I,
+    ) -> Result<O, RuntimeError>
+    where
+        I: Serialize + std::fmt::Debug,
+        O: DeserializeOwned + std::fmt::Debug,
+    {
+        let input = ExternIO::encode(input).map_err(runtime_error)?;
+        let output = crate::guest::call(store_mut, instance, f, input).map_err(|error| {
+            match error.downcast::<HostError>() {
+                Ok(error) => runtime_error(error),
+                Err(error) => error,
+            }
+        })?;
+        ExternIO::from(output).decode().map_err(runtime_error)
+    }
+}
+
+/// Report `error` as a [`RuntimeError`] wrapping a [`WasmError`]
+#[cfg(feature = "wasmer")]
+fn runtime_error(error: HostError) -> RuntimeError {
+    RuntimeError::user(Box::new(WasmError::from(error)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aingle_wasmer_common::{ErrorKind, MemoryError};
+
+    #[test]
+    fn test_host_error_mapping() {
+        let cases = [
+            (
+                HostError::Compilation("bad magic".to_string()),
+                WasmErrorInner::Compile("bad magic".to_string()),
+            ),
+            (HostError::MemoryNotFound, WasmErrorInner::Memory),
+            (
+                HostError::GuestError("denied".to_string()),
+                WasmErrorInner::Guest("denied".to_string()),
+            ),
+            (
+                HostError::Deserialization("eof".to_string()),
+                WasmErrorInner::Serialize(SerializedBytesError::Deserialize("eof".to_string())),
+            ),
+            (
+                HostError::MeteringExceeded,
+                WasmErrorInner::CallError("metering limit exceeded".to_string()),
+            ),
+        ];
+        for (error, expected) in cases {
+            let line = line!() + 1;
+            let error = WasmError::from(error);
+            assert_eq!(error.error, expected);
+            assert_eq!((error.file.as_str(), error.line), (file!(), line));
+        }
+    }
+
+    #[test]
+    fn test_wasm_error_mapping() {
+        use aingle_wasmer_common::WasmError as Native;
+
+        let located = Native::GuestStructured(
+            aingle_wasmer_common::WasmErrorInner::new(ErrorKind::Validation, "too short")
+                .with_location("zome.rs", 12),
+        );
+        assert_eq!(
+            WasmError::from(located),
+            WasmError {
+                file: "zome.rs".to_string(),
+                line: 12,
+                error: WasmErrorInner::Guest("too short".to_string()),
+            }
+        );
+
+        let error = WasmError::from(Native::Memory(MemoryError::ArenaExhausted));
+        assert_eq!(error.error, WasmErrorInner::Memory);
+        assert_eq!(error.file, file!());
+        assert_eq!(
+            WasmError::from(Native::Host("gone".to_string())).error,
+            WasmErrorInner::Host("gone".to_string())
+        );
+
+        // Survives the trip through MessagePack like holochain's
+        let bytes = rmp_serde::to_vec_named(&error).unwrap();
+        assert_eq!(rmp_serde::from_slice::<WasmError>(&bytes).unwrap(), error);
+    }
+}
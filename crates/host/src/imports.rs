@@ -0,0 +1,774 @@
+//! Host imports provided to guests
+//!
+//! [`ImportBuilder`] starts out with the standard imports every guest may
+//! use, `env.__aingle_output_chunk` for streamed output,
+//! `env.__aingle_random_bytes` for randomness and `env.__aingle_trace` for
+//! the steps of explained calls, and takes the embedder's own imports on
+//! top, including the functions of a [`HostFunctionRegistry`].
+
+use crate::backend::{BackendMemory, DEFAULT_MEMORY_EXPORT};
+use crate::explain::{explaining, record, TraceStepKind};
+use crate::random::{RandomSource, RandomStream};
+use crate::registry::Dispatch;
+use crate::{Env, HostCallCost, HostError, HostFunctionRegistry};
+use aingle_wasmer_common::{
+    ErrorKind, RandomStatus, StreamStatus, WasmResult, WasmSlice, HC_EXTERN_NAMES,
+    OUTPUT_CHUNK_IMPORT, RANDOM_BYTES_IMPORT, TRACE_IMPORT,
+};
+use parking_lot::Mutex;
+use std::any::Any;
+use std::cell::RefCell;
+use std::sync::Arc;
+use wasmer::{
+    AsStoreMut, Extern, Function, FunctionEnv, FunctionEnvMut, Imports, Instance, Memory, Module,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+thread_local! {
+    /// Output streamed by the guest call running on this thread
+    static STREAMED_OUTPUT: RefCell<Option<StreamedOutput>> = const { RefCell::new(None) };
+}
+
+/// Chunks collected during one guest call
+struct StreamedOutput {
+    bytes: Vec<u8>,
+    max: usize,
+    /// Whether a chunk was accepted, if only an empty one
+    started: bool,
+}
+
+/// Run `call` while collecting the output the guest streams, up to `max`
+/// bytes
+///
+/// The output is `None` if the guest accepted no chunk, not even an empty
+/// one. Output collected by an enclosing call is set aside meanwhile, so
+/// nested calls each receive their own chunks.
+pub(crate) fn collect_output<R>(max: usize, call: impl FnOnce() -> R) -> (R, Option<Vec<u8>>) {
+    struct Restore(Option<StreamedOutput>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            STREAMED_OUTPUT.with(|output| *output.borrow_mut() = self.0.take());
+        }
+    }
+
+    let collecting = StreamedOutput {
+        bytes: Vec::new(),
+        max,
+        started: false,
+    };
+    let outer = STREAMED_OUTPUT.with(|output| output.borrow_mut().replace(collecting));
+    let restore = Restore(outer);
+
+    let result = call();
+    let bytes = STREAMED_OUTPUT
+        .with(|output| output.borrow_mut().take())
+        .filter(|output| output.started)
+        .map(|output| output.bytes);
+    drop(restore);
+    (result, bytes)
+}
+
+/// Run the handler of a host call, then charge the time it took against
+/// the guest's metering budget
+///
+/// Fails with [`HostError::MeteringExceeded`], which aborts the guest, once
+/// the budget is spent.
+fn charged<R>(
+    mut env: FunctionEnvMut<'_, Env>,
+    handler: impl FnOnce(&mut FunctionEnvMut<'_, Env>) -> R,
+) -> Result<R, HostError> {
+    let started = Instant::now();
+    let result = handler(&mut env);
+    let (env, mut store) = env.data_and_store_mut();
+    env.charge_host_call(&mut store, started.elapsed())?;
+    Ok(result)
+}
+
+/// Dispatch a host call to a registry function, within the host I/O quota
+/// of the guest call, see [`Env::start_host_io`]
+///
+/// Calls past the quota fail with a payload-less
+/// [`ErrorKind::ResourceExhausted`] result. Their arguments are not read and
+/// their response, already written, is zeroed; no other guest memory is
+/// touched, so the envelope of the guest call stays intact.
+fn dispatch_within_quota(
+    env: &Env,
+    memory: &mut dyn BackendMemory,
+    dispatch: &Dispatch,
+    ptr: u32,
+    len: u32,
+) -> WasmResult {
+    let exhausted = WasmResult::err_with_kind(WasmSlice::empty(), ErrorKind::ResourceExhausted);
+    if !env.admit_host_io(u64::from(len), 0) {
+        return exhausted;
+    }
+    let result = dispatch(&mut *memory, ptr, len);
+    let response = result.slice();
+    if env.admit_host_io(0, u64::from(response.len)) {
+        return result;
+    }
+    // Best effort: the guest never reads the response either way
+    let _ = memory.write_memory(u64::from(response.ptr), &vec![0; response.len as usize]);
+    exhausted
+}
+
+/// Handler of `env.__aingle_output_chunk`: `(ptr: i32, len: i32) -> i32`
+fn output_chunk(env: FunctionEnvMut<'_, Env>, ptr: i32, len: i32) -> Result<i32, HostError> {
+    charged(env, |env| output_chunk_status(env, ptr, len))
+}
+
+fn output_chunk_status(env: &mut FunctionEnvMut<'_, Env>, ptr: i32, len: i32) -> i32 {
+    let (env, store) = env.data_and_store_mut();
+    let Some(memory) = env.memory.as_ref() else {
+        return StreamStatus::OutOfBounds as i32;
+    };
+    let view = memory.view(&store);
+
+    let status = STREAMED_OUTPUT.with(|output| {
+        let mut output = output.borrow_mut();
+        let Some(output) = output.as_mut() else {
+            return StreamStatus::OutOfOrder;
+        };
+
+        let start = output.bytes.len();
+        let len = len as u32 as usize;
+        if start.saturating_add(len) > output.max {
+            return StreamStatus::OverLength;
+        }
+        output.bytes.resize(start + len, 0);
+        if view
+            .read(ptr as u32 as u64, &mut output.bytes[start..])
+            .is_err()
+        {
+            output.bytes.truncate(start);
+            return StreamStatus::OutOfBounds;
+        }
+        output.started = true;
+        StreamStatus::Ok
+    });
+    status as i32
+}
+
+/// Longest guest trace message kept, in bytes
+const MAX_TRACE_MESSAGE_LEN: u32 = 4096;
+
+/// Handler of `env.__aingle_trace`: `(ptr: i32, len: i32) -> ()`
+///
+/// Records the message as a [`TraceStepKind::Guest`] step of the call being
+/// explained; messages are not even read otherwise.
+fn trace(env: FunctionEnvMut<'_, Env>, ptr: i32, len: i32) -> Result<(), HostError> {
+    charged(env, |env| {
+        if !explaining() {
+            return;
+        }
+        let (env, store) = env.data_and_store_mut();
+        let Some(memory) = env.memory.as_ref() else {
+            return;
+        };
+        let (ptr, len) = (ptr as u32, len as u32);
+        let mut message = vec![0; len.min(MAX_TRACE_MESSAGE_LEN) as usize];
+        let read = memory.view(&store).read(u64::from(ptr), &mut message);
+        record(
+            TraceStepKind::Guest,
+            Some((u64::from(ptr), u64::from(len))),
+            None,
+            || match read {
+                Ok(()) => String::from_utf8_lossy(&message).into_owned(),
+                Err(e) => format!("unreadable message: {}", e),
+            },
+        );
+    })
+}
+
+/// Handler of `env.__aingle_random_bytes`: `(ptr: i32, len: i32) -> i32`
+fn random_bytes(env: FunctionEnvMut<'_, Env>, ptr: i32, len: i32) -> Result<i32, HostError> {
+    charged(env, |env| random_bytes_status(env, ptr, len))
+}
+
+fn random_bytes_status(env: &mut FunctionEnvMut<'_, Env>, ptr: i32, len: i32) -> i32 {
+    let (env, store) = env.data_and_store_mut();
+    let (Some(memory), Some(random)) = (env.memory.as_ref(), env.random.as_ref()) else {
+        return RandomStatus::Unavailable as i32;
+    };
+    let view = memory.view(&store);
+
+    let (ptr, len) = (ptr as u32 as u64, len as u32 as u64);
+    if ptr + len > view.data_size() {
+        return RandomStatus::OutOfBounds as i32;
+    }
+    let mut bytes = vec![0u8; len as usize];
+    if random.lock().fill(&mut bytes).is_err() {
+        return RandomStatus::Unavailable as i32;
+    }
+    match view.write(ptr, &bytes) {
+        Ok(()) => RandomStatus::Ok as i32,
+        Err(_) => RandomStatus::OutOfBounds as i32,
+    }
+}
+
+/// Builder for the imports of a guest instance
+///
+/// The standard imports are registered by [`ImportBuilder::new`]; their
+/// handlers share an [`Env`] which [`ImportBuilder::instantiate`] attaches to
+/// the new instance. Guests asking for randomness get
+/// [`RandomStatus::Unavailable`] unless a source is set with
+/// [`ImportBuilder::random`]. Host functions defined on
+/// [`ImportBuilder::env`] read the state of the embedder set with
+/// [`ImportBuilder::context`] from their environment.
+///
+/// ```ignore
+/// let my_host_fn = Function::new_typed_with_env(&mut store, builder.env(), my_host_fn);
+/// let instance = builder
+///     .memory(memory)
+///     .random(RandomSource::Os)
+///     .context(Arc::new(cell_state))
+///     .define("env", "my_host_fn", my_host_fn)
+///     .instantiate(&mut store, &module)?;
+/// ```
+pub struct ImportBuilder {
+    env: FunctionEnv<Env>,
+    imports: Imports,
+    memory: Option<Memory>,
+    memory_export: Option<String>,
+    random: Option<RandomSource>,
+    context: Option<Arc<dyn Any + Send + Sync>>,
+    host_call_cost: Option<HostCallCost>,
+    write_chunk: Option<usize>,
+    scratch_bytes: Option<u32>,
+}
+
+impl ImportBuilder {
+    /// Create a builder holding the standard imports
+    pub fn new(store: &mut impl AsStoreMut) -> Self {
+        let env = FunctionEnv::new(store, Env::new());
+        let mut imports = Imports::new();
+        imports.define(
+            "env",
+            OUTPUT_CHUNK_IMPORT,
+            Function::new_typed_with_env(store, &env, output_chunk),
+        );
+        imports.define(
+            "env",
+            RANDOM_BYTES_IMPORT,
+            Function::new_typed_with_env(store, &env, random_bytes),
+        );
+        imports.define(
+            "env",
+            TRACE_IMPORT,
+            Function::new_typed_with_env(store, &env, trace),
+        );
+        Self {
+            env,
+            imports,
+            memory: None,
+            memory_export: None,
+            random: None,
+            context: None,
+            host_call_cost: None,
+            write_chunk: None,
+            scratch_bytes: None,
+        }
+    }
+
+    /// Provide `memory` as `env.memory` to guests importing their memory
+    pub fn memory(self, memory: Memory) -> Self {
+        self.memory_import("env", DEFAULT_MEMORY_EXPORT, memory)
+    }
+
+    /// Provide `memory` as `namespace.name` to guests importing their
+    /// memory under another name, see [`MemoryLayout`](crate::MemoryLayout)
+    pub fn memory_import(mut self, namespace: &str, name: &str, memory: Memory) -> Self {
+        self.imports.define(namespace, name, memory.clone());
+        self.memory = Some(memory);
+        self
+    }
+
+    /// Reach the guest memory through the export `name`, see
+    /// [`Env::set_memory_export`]
+    pub fn memory_export(mut self, name: impl Into<String>) -> Self {
+        self.memory_export = Some(name.into());
+        self
+    }
+
+    /// Answer `env.__aingle_random_bytes` from `source`
+    pub fn random(mut self, source: RandomSource) -> Self {
+        self.random = Some(source);
+        self
+    }
+
+    /// Set the context of the instance, see [`Env::set_context`]
+    pub fn context(mut self, context: Arc<dyn Any + Send + Sync>) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    /// Charge the time guests spend in the standard imports and the
+    /// functions of registries against their metering budget, see
+    /// [`EngineConfig::charge_host_calls`](crate::EngineConfig::charge_host_calls)
+    pub fn charge_host_calls(mut self, cost: HostCallCost) -> Self {
+        self.host_call_cost = Some(cost);
+        self
+    }
+
+    /// Copy host writes to guest memory `bytes` at a time, see
+    /// [`EngineConfig::guest_write_chunk_bytes`](crate::EngineConfig::guest_write_chunk_bytes)
+    pub fn write_chunk(mut self, bytes: usize) -> Self {
+        self.write_chunk = Some(bytes);
+        self
+    }
+
+    /// Reserve a guest buffer of `bytes` for host writes on instantiation,
+    /// see [`Env::reserve_scratch`]
+    pub fn scratch(mut self, bytes: u32) -> Self {
+        self.scratch_bytes = Some(bytes);
+        self
+    }
+
+    /// Add an import, replacing any registered under the same name
+    pub fn define(mut self, namespace: &str, name: &str, import: impl Into<Extern>) -> Self {
+        self.imports.define(namespace, name, import);
+        self
+    }
+
+    /// Add the functions of `registry` as `env` imports under their names
+    ///
+    /// They share the environment of the standard imports, so they reach
+    /// guest memory once [`ImportBuilder::instantiate`] has attached it. The
+    /// bytes they exchange with the guest are counted against the quota of
+    /// the guest call, see
+    /// [`CallOptions::max_host_io_bytes`](crate::CallOptions::max_host_io_bytes);
+    /// streamed output and randomness are not, the former being bounded by
+    /// the output limits of the call instead.
+    pub fn functions(
+        mut self,
+        store: &mut impl AsStoreMut,
+        registry: &HostFunctionRegistry,
+    ) -> Self {
+        for (name, dispatch) in registry.entries() {
+            let function = Function::new_typed_with_env(
+                store,
+                &self.env,
+                move |env: FunctionEnvMut<'_, Env>, ptr: i32, len: i32| {
+                    charged(env, |env| {
+                        let (env, mut store) = env.data_and_store_mut();
+                        let result = dispatch_within_quota(
+                            env,
+                            &mut env.bind(&mut store),
+                            &*dispatch,
+                            ptr as u32,
+                            len as u32,
+                        );
+                        result.into_raw() as i64
+                    })
+                },
+            );
+            self.imports.define("env", name, function);
+        }
+        self
+    }
+
+    /// Standard holochain host functions not defined in the `env` namespace
+    ///
+    /// See [`HC_EXTERN_NAMES`].
+    pub fn missing_hc_externs(&self) -> Vec<&'static str> {
+        HC_EXTERN_NAMES
+            .iter()
+            .copied()
+            .filter(|name| self.imports.get_export("env", name).is_none())
+            .collect()
+    }
+
+    /// Fail with [`HostError::Instantiation`] naming the standard holochain
+    /// host functions not defined, if any
+    pub fn require_hc_externs(self) -> Result<Self, HostError> {
+        let missing = self.missing_hc_externs();
+        if !missing.is_empty() {
+            return Err(HostError::Instantiation(format!(
+                "missing host functions: {}",
+                missing.join(", ")
+            )));
+        }
+        Ok(self)
+    }
+
+    /// Define the standard holochain host functions not defined yet as
+    /// stubs denying every call
+    ///
+    /// The stubs return an error result without payload carrying
+    /// [`ErrorKind::PermissionDenied`], so guests importing host functions
+    /// the embedder does not provide still instantiate. Functions defined
+    /// afterwards replace the stubs.
+    pub fn stub_hc_externs(mut self, store: &mut impl AsStoreMut) -> Self {
+        for name in self.missing_hc_externs() {
+            let stub = Function::new_typed(store, |_ptr: i32, _len: i32| -> i64 {
+                WasmResult::err_with_kind(WasmSlice::empty(), ErrorKind::PermissionDenied)
+                    .into_raw() as i64
+            });
+            self.imports.define("env", name, stub);
+        }
+        self
+    }
+
+    /// Environment shared by the standard import handlers
+    pub fn env(&self) -> &FunctionEnv<Env> {
+        &self.env
+    }
+
+    /// The imports, for instantiating the module by hand
+    ///
+    /// The standard handlers then need the instance attached to
+    /// [`ImportBuilder::env`] with [`Env::attach`], and get no randomness
+    /// and no context, nor are host calls charged.
+    pub fn build(self) -> Imports {
+        self.imports
+    }
+
+    /// Instantiate `module` with the imports and attach the instance to the
+    /// standard handlers
+    pub fn instantiate(
+        self,
+        store: &mut impl AsStoreMut,
+        module: &Module,
+    ) -> Result<Instance, HostError> {
+        let instance = Instance::new(store, module, &self.imports)
+            .map_err(|e| HostError::Instantiation(e.to_string()))?;
+
+        let mut env = self.env.as_ref(&*store).clone();
+        env.memory = self.memory;
+        if let Some(name) = self.memory_export {
+            env.set_memory_export(name);
+        }
+        env.random = self
+            .random
+            .map(|source| Arc::new(Mutex::new(RandomStream::new(source))));
+        if let Some(context) = self.context {
+            env.set_context(context);
+        }
+        env.host_call_cost = self.host_call_cost;
+        env.write_chunk = self.write_chunk;
+        env.attach(&instance, &*store);
+        if let Some(bytes) = self.scratch_bytes {
+            env.reserve_scratch(store, &instance, bytes)?;
+        }
+        *self.env.as_mut(store) = env;
+        Ok(instance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_output_nests() {
+        let push = |bytes: &[u8]| {
+            STREAMED_OUTPUT.with(|output| {
+                let mut output = output.borrow_mut();
+                let output = output.as_mut().unwrap();
+                output.bytes.extend_from_slice(bytes);
+                output.started = true;
+            })
+        };
+
+        let (((), inner), outer) = collect_output(16, || {
+            push(b"outer ");
+            let inner = collect_output(16, || push(b"inner"));
+            push(b"again");
+            inner
+        });
+        assert_eq!(inner.as_deref(), Some(&b"inner"[..]));
+        assert_eq!(outer.as_deref(), Some(&b"outer again"[..]));
+
+        // Calls streaming nothing are told from those streaming empty output
+        assert_eq!(collect_output(16, || ()).1, None);
+        assert_eq!(collect_output(16, || push(b"")).1, Some(Vec::new()));
+        assert!(STREAMED_OUTPUT.with(|output| output.borrow().is_none()));
+    }
+
+    #[test]
+    fn test_context_per_instance() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use wasmer::Store;
+
+        /// State of a cell, counting the calls of its guest
+        struct Counter(AtomicU32);
+
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "env" "bump" (func $bump (result i32)))
+                (memory (export "memory") 1)
+                (func (export "bump") (result i32) (call $bump)))
+            "#,
+        )
+        .unwrap();
+        let mut store = Store::default();
+        let module = Module::new(&store, wasm).unwrap();
+
+        let instantiate = |store: &mut Store, counter: &Arc<Counter>| {
+            let builder = ImportBuilder::new(store);
+            let bump = Function::new_typed_with_env(
+                store,
+                builder.env(),
+                |env: FunctionEnvMut<'_, Env>| -> i32 {
+                    let counter = env.data().context::<Counter>().unwrap();
+                    counter.0.fetch_add(1, Ordering::SeqCst) as i32 + 1
+                },
+            );
+            let instance = builder
+                .context(counter.clone())
+                .define("env", "bump", bump)
+                .instantiate(store, &module)
+                .unwrap();
+            instance
+                .exports
+                .get_typed_function::<(), i32>(&*store, "bump")
+                .unwrap()
+        };
+        let (first, second) = (
+            Arc::new(Counter(AtomicU32::new(0))),
+            Arc::new(Counter(AtomicU32::new(100))),
+        );
+        let bump_first = instantiate(&mut store, &first);
+        let bump_second = instantiate(&mut store, &second);
+
+        assert_eq!(bump_first.call(&mut store).unwrap(), 1);
+        assert_eq!(bump_first.call(&mut store).unwrap(), 2);
+        assert_eq!(bump_second.call(&mut store).unwrap(), 101);
+        assert_eq!(first.0.load(Ordering::SeqCst), 2);
+        assert_eq!(second.0.load(Ordering::SeqCst), 101);
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_host_calls_charged() {
+        use crate::{EngineConfig, WasmEngine};
+        use aingle_wasmer_codec::encode_with_envelope;
+        use aingle_wasmer_common::{HostFunction, WasmError};
+        use std::time::Duration;
+        use wasmer::Store;
+        use wasmer_middlewares::metering::{get_remaining_points, MeteringPoints};
+
+        /// Sleeps for its argument in milliseconds
+        struct Sleep;
+
+        impl HostFunction<u64, ()> for Sleep {
+            const NAME: &'static str = "sleep";
+
+            fn call(&self, millis: u64) -> Result<(), WasmError> {
+                std::thread::sleep(Duration::from_millis(millis));
+                Ok(())
+            }
+        }
+
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "env" "sleep" (func $sleep (param i32 i32) (result i64)))
+                (memory (export "memory") 1)
+                (func (export "sleep") (param $ptr i32) (param $len i32) (result i64)
+                    (call $sleep (local.get $ptr) (local.get $len))))
+            "#,
+        )
+        .unwrap();
+        let engine = WasmEngine::new(EngineConfig {
+            metering_limit: 50_000,
+            ..EngineConfig::default()
+        })
+        .unwrap();
+        let mut store = Store::new(engine.inner().unwrap().clone());
+        let module = Module::new(&store, wasm).unwrap();
+        let registry = HostFunctionRegistry::new().register(Sleep);
+        let instance = ImportBuilder::new(&mut store)
+            .charge_host_calls(HostCallCost::PerMicrosecond(1))
+            .functions(&mut store, &registry)
+            .instantiate(&mut store, &module)
+            .unwrap();
+
+        let memory = instance.exports.get_memory("memory").unwrap().clone();
+        let sleep = instance
+            .exports
+            .get_typed_function::<(i32, i32), i64>(&store, "sleep")
+            .unwrap();
+        let sleep_for = |store: &mut Store, millis: u64| {
+            let mut args = [0u8; 64];
+            let payload = rmp_serde::to_vec(&millis).unwrap();
+            let len = encode_with_envelope(&payload, 0, &mut args).unwrap();
+            memory.view(&*store).write(1024, &args[..len]).unwrap();
+            sleep.call(store, 1024, len as i32)
+        };
+
+        // A short sleep fits the budget, but costs at least its duration
+        sleep_for(&mut store, 1).unwrap();
+        let MeteringPoints::Remaining(left) = get_remaining_points(&mut store, &instance) else {
+            panic!("budget exhausted by a short sleep");
+        };
+        assert!(left <= 49_000, "{}", left);
+
+        // A long one exhausts it inside the import
+        let error = sleep_for(&mut store, 100).unwrap_err();
+        assert!(
+            matches!(HostError::from(error), HostError::MeteringExceeded),
+            "the call did not fail with exhaustion"
+        );
+        assert!(matches!(
+            get_remaining_points(&mut store, &instance),
+            MeteringPoints::Exhausted
+        ));
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_host_io_quota() {
+        use aingle_wasmer_codec::encode_with_envelope;
+        use aingle_wasmer_common::{HostFunction, WasmError};
+        use wasmer::Store;
+
+        struct Echo;
+
+        impl HostFunction<Vec<u8>, Vec<u8>> for Echo {
+            const NAME: &'static str = "echo";
+
+            fn call(&self, args: Vec<u8>) -> Result<Vec<u8>, WasmError> {
+                Ok(args)
+            }
+        }
+
+        // Calls `echo` `times` over with the same arguments, returning the
+        // last result; responses are bump allocated from 4096
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "env" "echo" (func $echo (param i32 i32) (result i64)))
+                (memory (export "memory") 1)
+                (global $next (mut i32) (i32.const 4096))
+                (func (export "__hc__allocate_1") (param $len i32) (result i32)
+                    (global.get $next)
+                    (global.set $next (i32.add (global.get $next) (local.get $len))))
+                (func (export "__hc__deallocate_1") (param i32 i32))
+                (func (export "chatty") (param $ptr i32) (param $len i32) (param $times i32)
+                    (result i64)
+                    (local $result i64)
+                    (block $done
+                        (loop $again
+                            (br_if $done (i32.eqz (local.get $times)))
+                            (local.set $result (call $echo (local.get $ptr) (local.get $len)))
+                            (local.set $times (i32.sub (local.get $times) (i32.const 1)))
+                            (br $again)))
+                    (local.get $result)))
+            "#,
+        )
+        .unwrap();
+        let mut store = Store::default();
+        let module = Module::new(&store, wasm).unwrap();
+        let builder = ImportBuilder::new(&mut store);
+        let env = builder.env().as_ref(&store).clone();
+        let registry = HostFunctionRegistry::new().register(Echo);
+        let instance = builder
+            .functions(&mut store, &registry)
+            .instantiate(&mut store, &module)
+            .unwrap();
+
+        let memory = instance.exports.get_memory("memory").unwrap().clone();
+        let chatty = instance
+            .exports
+            .get_typed_function::<(i32, i32, i32), i64>(&store, "chatty")
+            .unwrap();
+        let mut args = [0u8; 256];
+        let payload = rmp_serde::to_vec(&vec![7u8; 100]).unwrap();
+        let len = encode_with_envelope(&payload, 0, &mut args).unwrap();
+        memory.view(&store).write(1024, &args[..len]).unwrap();
+        let chatty = |store: &mut Store, times: i32| {
+            let raw = chatty.call(store, 1024, len as i32, times).unwrap();
+            WasmResult::from_raw(raw as u64)
+        };
+
+        // Unlimited by default
+        assert!(!chatty(&mut store, 4).is_err());
+        assert!(env.host_io().bytes_in >= 4 * len as u64);
+        assert!(env.host_io().bytes_out > 0);
+
+        // Echoes cost about twice their arguments, so three fit the quota
+        let quota = 8 * len as u64;
+        env.start_host_io(Some(quota));
+        assert!(!chatty(&mut store, 3).is_err());
+        let result = chatty(&mut store, 10);
+        assert!(result.is_err());
+        assert_eq!(result.kind(), Some(ErrorKind::ResourceExhausted));
+        assert!(result.slice().is_empty());
+        let io = env.host_io();
+        assert!(io.total() <= quota, "{:?}", io);
+        assert!(io.bytes_in >= 3 * len as u64, "{:?}", io);
+
+        // The arguments of the guest call are left as they were
+        let mut after = vec![0u8; len];
+        memory.view(&store).read(1024, &mut after).unwrap();
+        assert_eq!(after, args[..len]);
+
+        // A new call starts from nothing
+        env.start_host_io(Some(quota));
+        assert!(!chatty(&mut store, 1).is_err());
+    }
+
+    #[test]
+    fn test_hc_extern_stubs() {
+        use wasmer::Store;
+
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "env" "__hc__get_1" (func $get (param i32 i32) (result i64)))
+                (import "env" "__hc__agent_info_1" (func $agent_info (param i32 i32) (result i64)))
+                (import "env" "__hc__create_1" (func $create (param i32 i32) (result i64)))
+                (memory (export "memory") 1)
+                (func (export "get") (result i64)
+                    (call $get (i32.const 0) (i32.const 0)))
+                (func (export "agent_info") (result i64)
+                    (call $agent_info (i32.const 0) (i32.const 0))))
+            "#,
+        )
+        .unwrap();
+        let mut store = Store::default();
+        let module = Module::new(&store, wasm).unwrap();
+
+        let builder = ImportBuilder::new(&mut store);
+        assert_eq!(builder.missing_hc_externs(), HC_EXTERN_NAMES);
+        let error = ImportBuilder::new(&mut store)
+            .require_hc_externs()
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("__hc__zome_info_1"), "{}", error);
+
+        // Functions the embedder defines are kept, the rest stubbed
+        let agent_info = Function::new_typed(&mut store, |_ptr: i32, _len: i32| -> i64 {
+            WasmResult::ok_empty().into_raw() as i64
+        });
+        let builder = builder
+            .define("env", "__hc__agent_info_1", agent_info)
+            .stub_hc_externs(&mut store);
+        assert!(builder.missing_hc_externs().is_empty());
+        let instance = builder
+            .require_hc_externs()
+            .unwrap()
+            .instantiate(&mut store, &module)
+            .unwrap();
+
+        let call = |store: &mut Store, name: &str| {
+            let function = instance
+                .exports
+                .get_typed_function::<(), i64>(&*store, name)
+                .unwrap();
+            WasmResult::from_raw(function.call(store).unwrap() as u64)
+        };
+        let denied = call(&mut store, "get");
+        assert!(denied.is_err());
+        assert!(denied.slice().is_empty());
+        assert_eq!(denied.kind(), Some(ErrorKind::PermissionDenied));
+        assert!(!call(&mut store, "agent_info").is_err());
+    }
+}
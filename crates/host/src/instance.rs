@@ -1,144 +1,1745 @@
 //! WASM instance management
 
-use crate::{Env, HostError, WasmEngine};
-use aingle_wasmer_codec::{decode_envelope, encode_with_envelope};
-use aingle_wasmer_common::WasmResult;
-#[allow(unused_imports)]
-use aingle_wasmer_common::WasmSlice;
+use crate::backend::{
+    stream_to_guest, write_envelope_at, write_guest_envelope, BackendImports, BackendInstance,
+    BackendModule, ExternInfo, ValueType,
+};
+use crate::failure::FailureTracker;
+use crate::metering::MeteringLedger;
+use crate::{
+    CallObserver, CallOptions, EngineConfig, Env, ExternEncoding, ExternIO, HostError,
+    HostFunctionRegistry, HostIo, TrapKind, WasmEngine,
+};
+use aingle_wasmer_codec::decode_envelope_with;
+use aingle_wasmer_common::{
+    ChecksumKind, GuestAbi, GuestCapability, GuestFunction, GuestReturn, MemoryError, WasmResult,
+    WasmResult64, WasmSlice, WasmSlice64, GUEST_ABI_EXPORT, GUEST_ALLOCATED_BYTES_EXPORT,
+    GUEST_HIGH_WATER_EXPORT, GUEST_LAST_PANIC_EXPORT, GUEST_PREALLOC_EXPORT,
+    GUEST_SET_ARENA_LIMIT_EXPORT, GUEST_SET_CHECKSUM_EXPORT,
+};
+use std::sync::Arc;
 
-#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
-use wasmer::{imports, Instance, Memory, MemoryType, Module, Store};
+#[cfg(feature = "result-cache")]
+use crate::{ResultCache, ResultKey};
+
+#[cfg(feature = "wasmer")]
+use crate::wasmer_backend::WasmerInstance;
+
+#[cfg(feature = "wasmer")]
+use wasmer::{AsStoreMut, Store};
+
+/// Statistics gathered from the guest after a call
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CallReport {
+    /// Bytes held by the guest arena, if the guest exports them
+    pub guest_allocated_bytes: Option<u32>,
+    /// Guest arena high-water mark, if the guest exports it
+    pub guest_high_water: Option<u32>,
+    /// Metering points spent running WASM instructions, if the guest is
+    /// metered
+    pub wasm_points: Option<u64>,
+    /// Metering points charged for host calls, if the guest is metered, see
+    /// [`EngineConfig::charge_host_calls`](crate::EngineConfig::charge_host_calls)
+    pub import_points: Option<u64>,
+    /// Bytes exchanged with host functions, see
+    /// [`CallOptions::max_host_io_bytes`]
+    pub host_io: HostIo,
+}
 
 /// A WASM instance ready for execution
 pub struct WasmInstance {
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
-    instance: Instance,
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
-    store: Store,
-    #[allow(dead_code)]
+    inner: Box<dyn BackendInstance>,
     env: Env,
+    max_input_len: usize,
+    max_output_len: usize,
+    ledger: Arc<MeteringLedger>,
+    failures: Option<Arc<FailureTracker>>,
+    module_key: Option<[u8; 32]>,
+    observer: Option<Arc<dyn CallObserver>>,
+    last_report: Option<CallReport>,
+    finalized: bool,
+    /// Whether calls use the 64-bit convention of memory64 guests
+    memory64: bool,
+    /// Checksum of the envelopes exchanged with the guest
+    checksum: ChecksumKind,
+    #[cfg(feature = "result-cache")]
+    result_cache: Option<ResultCache>,
 }
 
 impl WasmInstance {
     /// Create a new instance from a module
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
-    pub fn new(engine: &WasmEngine, module: &Module) -> Result<Self, HostError> {
-        let mut store = Store::new(engine.inner().clone());
-        let env = Env::new();
+    pub fn new(engine: &WasmEngine, module: &dyn BackendModule) -> Result<Self, HostError> {
+        Self::with_functions(engine, module, &HostFunctionRegistry::new())
+    }
 
-        // Create memory
-        let memory = Memory::new(&mut store, MemoryType::new(1, None, false))
-            .map_err(|e| HostError::Instantiation(e.to_string()))?;
+    /// Create a new instance from a module importing the functions of
+    /// `registry`
+    ///
+    /// The bytes they exchange with the guest are reported in
+    /// [`CallReport::host_io`], within [`CallOptions::max_host_io_bytes`].
+    pub fn with_functions(
+        engine: &WasmEngine,
+        module: &dyn BackendModule,
+        registry: &HostFunctionRegistry,
+    ) -> Result<Self, HostError> {
+        let imports = BackendImports {
+            functions: registry.clone(),
+            ..BackendImports::default()
+        };
+        let inner = engine.backend().instantiate(module, &imports)?;
 
-        // Build minimal imports
-        let import_object = imports! {
-            "env" => {
-                "memory" => memory,
-            },
+        let mut instance = Self {
+            env: env_for(inner.as_ref()),
+            inner,
+            max_input_len: engine.config().max_input_len,
+            max_output_len: engine.config().max_output_len,
+            ledger: engine.ledger().clone(),
+            failures: engine.failure_tracker().cloned(),
+            module_key: None,
+            observer: None,
+            last_report: None,
+            finalized: false,
+            memory64: module.is_memory64(),
+            checksum: ChecksumKind::Crc32,
+            #[cfg(feature = "result-cache")]
+            result_cache: None,
         };
+        if let Some(bytes) = engine.config().guest_prealloc_bytes {
+            instance.prealloc_guest_arena(bytes)?;
+        }
+        if engine.config().checksum != ChecksumKind::Crc32 {
+            instance.set_checksum(engine.config().checksum)?;
+        }
+        if let Some(export) = engine.config().warmup_export.as_deref() {
+            if module
+                .function_exports()
+                .iter()
+                .any(|function| function.name == export)
+            {
+                instance.warm_up(export, engine.config())?;
+            }
+        }
+        Ok(instance)
+    }
 
-        let instance = Instance::new(&mut store, module, &import_object)
-            .map_err(|e| HostError::Instantiation(e.to_string()))?;
+    /// Attribute the points consumed by calls to `key` in the engine's ledger
+    ///
+    /// Calls are also subject to the engine's [`FailureTracker`], if any.
+    pub fn with_module_key(mut self, key: [u8; 32]) -> Self {
+        self.module_key = Some(key);
+        self
+    }
 
-        Ok(Self {
-            instance,
-            store,
-            env,
-        })
+    /// Answer calls made with [`CallOptions::cacheable`] from `cache`
+    ///
+    /// Outputs are cached under the key set with
+    /// [`WasmInstance::with_module_key`]; instances without one always run
+    /// the guest.
+    #[cfg(feature = "result-cache")]
+    pub fn with_result_cache(mut self, cache: ResultCache) -> Self {
+        self.result_cache = Some(cache);
+        self
+    }
+
+    /// Notify `observer` when the instance is finalized or dropped
+    pub fn with_observer(mut self, observer: Arc<dyn CallObserver>) -> Self {
+        self.observer = Some(observer);
+        self
     }
 
     /// Call a function on the instance
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
-    pub fn call_raw(&mut self, name: &str, args: &[u8]) -> Result<Vec<u8>, HostError> {
-        // Get the function
-        let func = self
-            .instance
-            .exports
-            .get_function(name)
-            .map_err(|_| HostError::FunctionNotFound(name.to_string()))?;
-
-        // Encode args with envelope
-        let mut buffer = vec![0u8; args.len() + 64];
-        let len = encode_with_envelope(args, 0, &mut buffer)
-            .map_err(|e| HostError::Serialization(format!("{:?}", e)))?;
-
-        // Get memory for writing
-        let memory = self
-            .instance
-            .exports
-            .get_memory("memory")
-            .map_err(|_| HostError::MemoryNotFound)?;
-
-        // Write to guest memory at fixed offset
-        let ptr: u32 = 1024;
-        {
-            let view = memory.view(&self.store);
-            view.write(ptr as u64, &buffer[..len])
-                .map_err(|e| HostError::MemoryAccess(e.to_string()))?;
+    ///
+    /// The input is borrowed and copied straight into guest memory.
+    pub fn call_raw(&mut self, name: &str, args: impl AsRef<[u8]>) -> Result<Vec<u8>, HostError> {
+        self.call_raw_with_options(name, args, &CallOptions::default())
+    }
+
+    /// Call the guest function a [`GuestFunction`] marker declares
+    ///
+    /// The input and output are serialized with the declared types, so the
+    /// call is checked against the interface the guest exports.
+    pub fn call_fn<F: GuestFunction>(&mut self, input: &F::Input) -> Result<F::Output, HostError> {
+        self.call_fn_with_options::<F>(input, &CallOptions::default())
+    }
+
+    /// Call the guest function a [`GuestFunction`] marker declares, with
+    /// per-call overrides
+    ///
+    /// The input is encoded as `options.encoding` negotiates with the guest,
+    /// see [`ExternEncoding::negotiate`]; the guest ABI is taken from
+    /// `options.abi`, or asked from the guest when compact encoding is
+    /// requested without it.
+    pub fn call_fn_with_options<F: GuestFunction>(
+        &mut self,
+        input: &F::Input,
+        options: &CallOptions,
+    ) -> Result<F::Output, HostError> {
+        let encoding = match (options.encoding.unwrap_or_default(), options.abi) {
+            (ExternEncoding::Named, _) => ExternEncoding::Named,
+            (encoding, Some(abi)) => encoding.negotiate(abi),
+            (encoding, None) => encoding.negotiate(self.guest_abi()?),
+        };
+        let input = ExternIO::encode_with(input, encoding)?;
+        ExternIO::from(self.call_raw_with_options(F::NAME, input, options)?).decode()
+    }
+
+    /// Call a function on the instance with per-call overrides
+    ///
+    /// Guests following the 64-bit convention, see
+    /// [`BackendModule::is_memory64`], are called with a [`WasmSlice64`] and
+    /// return a [`WasmResult64`].
+    ///
+    /// With the `result-cache` feature, calls asserted pure with
+    /// [`CallOptions::cacheable`] are answered from the cache set with
+    /// `WasmInstance::with_result_cache` when they can; hits do not run the
+    /// guest, and clear [`WasmInstance::last_call_report`].
+    pub fn call_raw_with_options(
+        &mut self,
+        name: &str,
+        args: impl AsRef<[u8]>,
+        options: &CallOptions,
+    ) -> Result<Vec<u8>, HostError> {
+        let args = args.as_ref();
+        #[cfg(feature = "result-cache")]
+        if let (Some(_), Some(key), Some(cache)) = (
+            options.cacheable,
+            self.module_key,
+            self.result_cache.clone(),
+        ) {
+            let key = ResultKey::new(key, name, args);
+            if let Some(output) = cache.get(&key) {
+                self.last_report = None;
+                return Ok(output.into_vec());
+            }
+            let output = self.call_uncached(name, args, options)?;
+            cache.insert(key, ExternIO::new(output.clone()));
+            return Ok(output);
+        }
+        self.call_uncached(name, args, options)
+    }
+
+    /// Run a call of [`WasmInstance::call_raw_with_options`] on the guest,
+    /// bypassing any result cache
+    fn call_uncached(
+        &mut self,
+        name: &str,
+        args: &[u8],
+        options: &CallOptions,
+    ) -> Result<Vec<u8>, HostError> {
+        let max_input = options.max_input_len.unwrap_or(self.max_input_len);
+        if args.len() > max_input {
+            return Err(HostError::InputTooLarge {
+                len: args.len(),
+                max: max_input,
+            });
+        }
+
+        let before = self.begin_call(options)?;
+
+        // Write args with envelope to guest memory at fixed offset
+        let input = write_envelope_at(self.inner.as_mut(), 1024, args, 0, self.checksum)?;
+
+        if self.memory64 {
+            let result = self.inner.call64(name, input.into());
+            self.finish_call(before, &result);
+            let result = WasmResult64::from_raw(result.map_err(|e| self.recover_panic(e))?);
+            return self.read_result_slice(result.is_err(), result.slice(), options);
         }
 
         // Call the function
-        let result = func
-            .call(
-                &mut self.store,
-                &[
-                    wasmer::Value::I32(ptr as i32),
-                    wasmer::Value::I32(len as i32),
-                ],
-            )
-            .map_err(|e| HostError::Runtime(e.to_string()))?;
-
-        // Parse result
-        let result_packed = match result.first() {
-            Some(wasmer::Value::I64(v)) => *v as u64,
-            _ => return Err(HostError::InvalidReturn),
+        let result = self.inner.call(name, input);
+        self.finish_call(before, &result);
+        let result_packed = result.map_err(|e| self.recover_panic(e))?;
+
+        self.read_result(result_packed.into(), options)
+    }
+
+    /// Call a function taking two independent inputs
+    ///
+    /// The export must follow the `(ptr1, len1, ptr2, len2) -> u64` convention.
+    /// Both inputs are envelope-encoded and written into memory obtained from
+    /// the guest allocator; the input size limit applies to their sum.
+    pub fn call_raw2(
+        &mut self,
+        name: &str,
+        a: impl AsRef<[u8]>,
+        b: impl AsRef<[u8]>,
+    ) -> Result<Vec<u8>, HostError> {
+        let (a, b) = (a.as_ref(), b.as_ref());
+        let options = CallOptions::default();
+        let max_input = options.max_input_len.unwrap_or(self.max_input_len);
+        if a.len() + b.len() > max_input {
+            return Err(HostError::InputTooLarge {
+                len: a.len() + b.len(),
+                max: max_input,
+            });
+        }
+
+        let info = self
+            .inner
+            .module()
+            .function_exports()
+            .into_iter()
+            .find(|info| info.name == name)
+            .ok_or_else(|| HostError::FunctionNotFound(name.to_string()))?;
+
+        if info.params != [ValueType::I32; 4] || info.results != [ValueType::I64] {
+            return Err(HostError::SignatureMismatch(format!(
+                "{} does not take (ptr1, len1, ptr2, len2) -> i64, found {:?} -> {:?}",
+                name, info.params, info.results
+            )));
+        }
+
+        let before = self.begin_call(&options)?;
+        let first = write_guest_envelope(self.inner.as_mut(), a, 0, self.checksum)?;
+        let second = write_guest_envelope(self.inner.as_mut(), b, 0, self.checksum)?;
+        self.env.track_allocation(first.ptr, first.len);
+        self.env.track_allocation(second.ptr, second.len);
+
+        let result = self.inner.call2(name, first, second);
+        self.finish_call(before, &result);
+        let result_packed = result.map_err(|e| self.recover_panic(e))?;
+
+        self.read_result(result_packed.into(), &options)
+    }
+
+    /// Stream a large input into the guest in chunks and call `name` on it
+    ///
+    /// The export receives `(handle, total_len)` in place of `(ptr, len)` and
+    /// reads the input back with the guest's `StreamReader`. The guest never
+    /// needs a contiguous region larger than `chunk_size` for the transfer.
+    pub fn call_streamed(
+        &mut self,
+        name: &str,
+        data: impl AsRef<[u8]>,
+        chunk_size: usize,
+    ) -> Result<Vec<u8>, HostError> {
+        let data = data.as_ref();
+        if data.len() > self.max_input_len {
+            return Err(HostError::InputTooLarge {
+                len: data.len(),
+                max: self.max_input_len,
+            });
+        }
+
+        let before = self.begin_call(&CallOptions::default())?;
+        let result = stream_to_guest(self.inner.as_mut(), data, chunk_size).and_then(|handle| {
+            self.inner
+                .call(name, WasmSlice::new(handle, data.len() as u32))
+        });
+        self.finish_call(before, &result);
+        let result_packed = result.map_err(|e| self.recover_panic(e))?;
+
+        self.read_result(result_packed.into(), &CallOptions::default())
+    }
+
+    /// Call the warm-up export `name` with an empty payload, on the warm-up
+    /// budget of `config` and leaving the full call budget to later calls
+    fn warm_up(&mut self, name: &str, config: &EngineConfig) -> Result<(), HostError> {
+        let metered = self.inner.remaining_points().is_some();
+        if metered {
+            self.inner
+                .set_remaining_points(config.warmup_metering_limit);
+        }
+        let result = self.call_empty(name);
+        if metered {
+            self.inner.set_remaining_points(config.metering_limit);
+        }
+        result.map_err(|source| HostError::WarmupFailed {
+            export: name.to_string(),
+            source: Box::new(source),
+        })
+    }
+
+    /// Call `name` with an empty payload, ignoring its output
+    fn call_empty(&mut self, name: &str) -> Result<(), HostError> {
+        let options = CallOptions::default();
+        if self.memory64 {
+            let result = self.inner.call64(name, WasmSlice64::empty());
+            let result = WasmResult64::from_raw(result.map_err(|e| self.recover_panic(e))?);
+            return self
+                .read_result_slice(result.is_err(), result.slice(), &options)
+                .map(drop);
+        }
+        let result = self.inner.call(name, WasmSlice::empty());
+        let result_packed = result.map_err(|e| self.recover_panic(e))?;
+        self.read_result(result_packed.into(), &options).map(drop)
+    }
+
+    /// Fail fast for quarantined modules, otherwise return the remaining
+    /// points ahead of a call, `None` for unmetered guests
+    fn begin_call(&mut self, options: &CallOptions) -> Result<Option<u64>, HostError> {
+        if let (Some(key), Some(failures)) = (self.module_key, &self.failures) {
+            failures.check(&key)?;
+        }
+        self.env.take_import_points();
+        self.env.start_host_io(options.max_host_io_bytes);
+        Ok(self.inner.remaining_points())
+    }
+
+    /// Record the points consumed since `before` and the call outcome if the
+    /// call is attributed to a module, then gather the guest's statistics
+    fn finish_call<T>(&mut self, before: Option<u64>, result: &Result<T, HostError>) {
+        let spent =
+            before.map(|before| before.saturating_sub(self.inner.remaining_points().unwrap_or(0)));
+        let import_points = self.env.take_import_points();
+        if let Some(key) = self.module_key {
+            self.ledger.record(key, spent.unwrap_or(0));
+            if let Some(failures) = &self.failures {
+                failures.record(&key, result);
+            }
+        }
+
+        self.last_report = Some(CallReport {
+            guest_allocated_bytes: self.read_stat(GUEST_ALLOCATED_BYTES_EXPORT),
+            guest_high_water: self.read_stat(GUEST_HIGH_WATER_EXPORT),
+            wasm_points: spent.map(|spent| spent.saturating_sub(import_points)),
+            import_points: spent.map(|_| import_points),
+            host_io: self.env.host_io(),
+        });
+    }
+
+    /// Replace an unreachable trap with the panic the guest published, if any
+    fn recover_panic(&mut self, error: HostError) -> HostError {
+        if !matches!(
+            error,
+            HostError::Trap {
+                kind: TrapKind::UnreachableCodeReached,
+                ..
+            }
+        ) {
+            return error;
+        }
+
+        let Ok(Some(raw)) = self.inner.call_packed(GUEST_LAST_PANIC_EXPORT) else {
+            return error;
+        };
+        let result = GuestReturn::from_raw(raw).as_result();
+        let slice = result.slice();
+        if !result.is_err() || slice.is_empty() || slice.len as usize > self.max_output_len {
+            return error;
+        }
+
+        let mut bytes = vec![0u8; slice.len as usize];
+        match self.inner.read_memory(slice.ptr as u64, &mut bytes) {
+            Ok(()) => HostError::GuestError(crate::guest_error_message(&bytes)),
+            Err(_) => error,
+        }
+    }
+
+    fn read_stat(&mut self, name: &str) -> Option<u32> {
+        self.inner.call_stat(name).unwrap_or_else(|e| {
+            tracing::debug!("Failed to read guest statistic {}: {}", name, e);
+            None
+        })
+    }
+
+    /// Limit the bytes the guest arena may hold, or lift the limit with `None`
+    ///
+    /// Guest allocations past the limit are returned as
+    /// `MemoryError::ArenaExhausted` errors instead of growing memory.
+    /// Returns `false` if the guest does not support a limit.
+    pub fn set_guest_arena_limit(&mut self, bytes: Option<u32>) -> Result<bool, HostError> {
+        self.inner
+            .call_setter(GUEST_SET_ARENA_LIMIT_EXPORT, bytes.unwrap_or(0))
+    }
+
+    /// Have the guest arena make room for `bytes` ahead of the next call
+    ///
+    /// Done on instantiation when
+    /// [`EngineConfig::guest_prealloc_bytes`](crate::EngineConfig::guest_prealloc_bytes)
+    /// is set. The room counts against the guest's arena limit, and the
+    /// guest skips the reservation if it would exceed it. Returns `false` if
+    /// the guest does not support preallocation.
+    pub fn prealloc_guest_arena(&mut self, bytes: u32) -> Result<bool, HostError> {
+        self.inner.call_setter(GUEST_PREALLOC_EXPORT, bytes)
+    }
+
+    /// Checksum the envelopes exchanged with the guest with `kind`
+    ///
+    /// Done on instantiation with
+    /// [`EngineConfig::checksum`](crate::EngineConfig::checksum). CRC32C is
+    /// only selected if the guest advertises
+    /// [`GuestCapability::Crc32c`]; the guest is told through its
+    /// [`GUEST_SET_CHECKSUM_EXPORT`] export, so both sides send envelopes
+    /// checksummed the same way. Returns the algorithm in use afterwards.
+    pub fn set_checksum(&mut self, kind: ChecksumKind) -> Result<ChecksumKind, HostError> {
+        let supported = match kind {
+            ChecksumKind::Crc32 => true,
+            ChecksumKind::Crc32c => self.guest_abi()?.has(GuestCapability::Crc32c),
         };
+        if supported {
+            // Guests without the export only ever send CRC32
+            let selected = self
+                .inner
+                .call_setter(GUEST_SET_CHECKSUM_EXPORT, kind as u32)?;
+            self.checksum = if selected { kind } else { ChecksumKind::Crc32 };
+        }
+        Ok(self.checksum)
+    }
 
-        let wasm_result = WasmResult::from_raw(result_packed);
-        let slice = wasm_result.slice();
+    /// Checksum of the envelopes exchanged with the guest
+    pub fn checksum(&self) -> ChecksumKind {
+        self.checksum
+    }
 
+    /// ABI the guest reports, legacy for guests without the export
+    fn guest_abi(&mut self) -> Result<GuestAbi, HostError> {
+        Ok(self
+            .inner
+            .call_packed(GUEST_ABI_EXPORT)?
+            .map_or(GuestAbi::legacy(), GuestAbi::from_raw))
+    }
+
+    /// Statistics gathered after the most recent call that reached the guest
+    pub fn last_call_report(&self) -> Option<&CallReport> {
+        self.last_report.as_ref()
+    }
+
+    /// Call a nullary guest export and decode the envelope it returns
+    ///
+    /// Returns `None` if the guest does not export `name`.
+    pub(crate) fn call_nullary(&mut self, name: &str) -> Result<Option<Vec<u8>>, HostError> {
+        let Some(raw) = self.inner.call_packed(name)? else {
+            return Ok(None);
+        };
+        self.read_result(raw.into(), &CallOptions::default())
+            .map(Some)
+    }
+
+    /// Read and decode the envelope a packed guest result points at
+    fn read_result(
+        &mut self,
+        result: GuestReturn,
+        options: &CallOptions,
+    ) -> Result<Vec<u8>, HostError> {
+        let wasm_result = WasmResult::from(result);
+        wasm_result.slice().validate().map_err(HostError::Memory)?;
+        if !wasm_result.is_well_formed() {
+            return Err(HostError::InvalidReturn);
+        }
+        if wasm_result.is_err() && wasm_result.slice().is_empty() {
+            return Err(HostError::GuestError(crate::payloadless_error_message(
+                wasm_result,
+            )));
+        }
+        self.read_result_slice(wasm_result.is_err(), wasm_result.slice().into(), options)
+    }
+
+    /// Read and decode the envelope a guest result slice points at
+    fn read_result_slice(
+        &mut self,
+        is_err: bool,
+        slice: WasmSlice64,
+        options: &CallOptions,
+    ) -> Result<Vec<u8>, HostError> {
         if slice.is_empty() {
-            if wasm_result.is_err() {
+            if is_err {
                 return Err(HostError::GuestError("empty error".to_string()));
             }
             return Ok(vec![]);
         }
 
-        // Read response from guest memory
-        let mut response = vec![0u8; slice.len as usize];
-        {
-            let view = memory.view(&self.store);
-            view.read(slice.ptr as u64, &mut response)
-                .map_err(|e| HostError::MemoryAccess(e.to_string()))?;
+        if slice.checked_end().is_none() {
+            return Err(HostError::Memory(MemoryError::OutOfBounds {
+                offset: usize::try_from(slice.ptr).unwrap_or(usize::MAX),
+                len: usize::try_from(slice.len).unwrap_or(usize::MAX),
+                max: usize::MAX,
+            }));
         }
 
-        // Decode envelope
-        let envelope = decode_envelope(&response)
-            .map_err(|e| HostError::Deserialization(format!("{:?}", e)))?;
+        let max_output = options.max_output_len.unwrap_or(self.max_output_len);
+        let len = usize::try_from(slice.len).unwrap_or(usize::MAX);
+        if len > max_output {
+            return Err(HostError::OutputTooLarge {
+                len,
+                max: max_output,
+            });
+        }
 
-        if wasm_result.is_err() || envelope.header.is_error() {
-            return Err(HostError::GuestError(
-                String::from_utf8_lossy(envelope.payload).to_string(),
-            ));
+        // Read response from guest memory
+        let mut response = vec![0u8; len];
+        self.inner.read_memory(slice.ptr, &mut response)?;
+
+        // Decode the envelope, checksummed as agreed with the guest
+        let envelope = decode_envelope_with(self.checksum, &response)
+            .map_err(|e| HostError::Deserialization(e.to_string()))?;
+
+        if is_err || envelope.header.is_error() {
+            return Err(HostError::GuestError(crate::guest_error_message(
+                envelope.payload,
+            )));
         }
 
         Ok(envelope.payload.to_vec())
     }
 
+    /// Capture the guest memory region a packed return value points at
+    #[cfg(feature = "debug-tools")]
+    pub fn dump_result_region(
+        &mut self,
+        result: &WasmResult,
+    ) -> Result<crate::MemoryDump, HostError> {
+        let slice = result.slice();
+        crate::MemoryDump::capture(self.inner.as_mut(), slice.ptr, slice.len)
+    }
+
+    /// List the guest functions exported by this instance
+    ///
+    /// Allocator, memory and other infrastructure exports are filtered out.
+    /// Use [`BackendModule::externs`] for the same information before
+    /// instantiation.
+    pub fn externs(&self) -> Vec<ExternInfo> {
+        self.inner.module().externs()
+    }
+
+    /// Number of host-made guest allocations not freed yet
+    pub fn outstanding_allocations(&self) -> usize {
+        self.env.outstanding_allocations()
+    }
+
+    /// Release the instance's resources, reporting deallocation failures
+    ///
+    /// Frees outstanding host-made allocations and notifies the observer.
+    /// Metering points are recorded in the ledger after every call, so there
+    /// is nothing left to flush. Dropping the instance finalizes it as well,
+    /// ignoring errors; calling this more than once is a no-op.
+    pub fn finalize(&mut self) -> Result<(), HostError> {
+        if self.finalized {
+            return Ok(());
+        }
+        self.finalized = true;
+
+        let result = self.free_outstanding();
+        if let Some(observer) = &self.observer {
+            observer.on_instance_drop(self.module_key.as_ref());
+        }
+        result
+    }
+
+    #[cfg(feature = "wasmer")]
+    fn free_outstanding(&mut self) -> Result<(), HostError> {
+        let env = self.env.clone();
+        match self.store_mut() {
+            Some(store) => env.free_outstanding(&mut store.as_store_mut()),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(not(feature = "wasmer"))]
+    fn free_outstanding(&mut self) -> Result<(), HostError> {
+        Ok(())
+    }
+
+    /// Get the backend instance
+    pub fn backend_instance(&mut self) -> &mut dyn BackendInstance {
+        self.inner.as_mut()
+    }
+
     /// Get reference to the store
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
-    pub fn store(&self) -> &Store {
-        &self.store
+    ///
+    /// Returns `None` when the instance runs on a different backend.
+    #[cfg(feature = "wasmer")]
+    pub fn store(&self) -> Option<&Store> {
+        self.inner
+            .as_any()
+            .downcast_ref::<WasmerInstance>()
+            .map(WasmerInstance::store)
     }
 
     /// Get mutable reference to the store
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
-    pub fn store_mut(&mut self) -> &mut Store {
-        &mut self.store
+    ///
+    /// Returns `None` when the instance runs on a different backend.
+    #[cfg(feature = "wasmer")]
+    pub fn store_mut(&mut self) -> Option<&mut Store> {
+        self.inner
+            .as_any_mut()
+            .downcast_mut::<WasmerInstance>()
+            .map(WasmerInstance::store_mut)
+    }
+}
+
+impl Drop for WasmInstance {
+    fn drop(&mut self) {
+        if let Err(e) = self.finalize() {
+            tracing::debug!("Failed to finalize instance: {}", e);
+        }
     }
 }
 
+/// Environment bound to the guest's memory and allocator exports
+///
+/// Shared with the standard imports of Wasmer instances, whose charges for
+/// host calls it reports.
+#[cfg(feature = "wasmer")]
+fn env_for(inner: &dyn BackendInstance) -> Env {
+    match inner.as_any().downcast_ref::<WasmerInstance>() {
+        Some(wasmer) => wasmer.env().as_ref(wasmer.store()).clone(),
+        None => Env::new(),
+    }
+}
+
+#[cfg(not(feature = "wasmer"))]
+fn env_for(_inner: &dyn BackendInstance) -> Env {
+    Env::new()
+}
+
 #[cfg(test)]
 mod tests {
-    // Instance tests require actual WASM modules
+    use super::*;
+    use aingle_wasmer_common::MemoryError;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Echoes the input envelope back as the result slice
+    const ECHO_WAT: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (func (export "echo") (param $ptr i32) (param $len i32) (result i64)
+                (i64.or
+                    (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+                    (i64.extend_i32_u (local.get $len)))))
+    "#;
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_raw_through_backend() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine.compile(&wat::parse_str(ECHO_WAT).unwrap()).unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+
+        let output = instance.call_raw("echo", b"ping").unwrap();
+        assert_eq!(output, b"ping");
+
+        let io = crate::ExternIO::new(b"pong".to_vec());
+        assert_eq!(instance.call_raw("echo", &io).unwrap(), b"pong");
+        assert!(instance.store().is_some());
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_fn_uses_declared_types() {
+        crate::guest_fn! {
+            struct Echo => fn echo(Vec<String>) -> Vec<String>;
+            struct EchoCount => fn echo(Vec<String>) -> u32;
+        }
+
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine.compile(&wat::parse_str(ECHO_WAT).unwrap()).unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+
+        let input = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(instance.call_fn::<Echo>(&input).unwrap(), input);
+        assert!(matches!(
+            instance.call_fn::<EchoCount>(&input),
+            Err(HostError::Serialization(_))
+        ));
+    }
+
+    /// Returns a result slice claiming 256 MiB at offset 1024
+    const HUGE_RESULT_WAT: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (func (export "huge") (param i32 i32) (result i64)
+                (i64.const 0x0000040010000000)))
+    "#;
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_raw_output_too_large() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine
+            .compile(&wat::parse_str(HUGE_RESULT_WAT).unwrap())
+            .unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+
+        let result = instance.call_raw("huge", b"x");
+        assert!(matches!(
+            result,
+            Err(HostError::OutputTooLarge {
+                len: 0x1000_0000,
+                max: crate::DEFAULT_MAX_OUTPUT_LEN
+            })
+        ));
+    }
+
+    /// Returns a result slice whose end wraps past 4 GiB
+    const WRAPPING_RESULT_WAT: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (func (export "wrapping") (param i32 i32) (result i64)
+                (i64.const 0x7ffffff080000020)))
+    "#;
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_raw_rejects_wrapping_result() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine
+            .compile(&wat::parse_str(WRAPPING_RESULT_WAT).unwrap())
+            .unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+
+        assert!(matches!(
+            instance.call_raw("wrapping", b"x"),
+            Err(HostError::Memory(MemoryError::OutOfBounds { .. }))
+        ));
+    }
+
+    /// Returns the empty result, an empty payload at offset 64, and 8 bytes
+    /// at offset 0
+    const EMPTY_RESULTS_WAT: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (func (export "empty") (param i32 i32) (result i64)
+                (i64.const 0))
+            (func (export "zero_len") (param i32 i32) (result i64)
+                (i64.const 0x0000004000000000))
+            (func (export "null_data") (param i32 i32) (result i64)
+                (i64.const 0x0000000000000008)))
+    "#;
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_raw_rejects_malformed_empty_results() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine
+            .compile(&wat::parse_str(EMPTY_RESULTS_WAT).unwrap())
+            .unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+
+        assert_eq!(instance.call_raw("empty", b"x").unwrap(), b"");
+        for name in ["zero_len", "null_data"] {
+            assert!(
+                matches!(instance.call_raw(name, b"x"), Err(HostError::InvalidReturn)),
+                "{} accepted",
+                name
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_raw_records_ledger() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine.compile(&wat::parse_str(ECHO_WAT).unwrap()).unwrap();
+        let key_a = [0xaa; 32];
+        let key_b = [0xbb; 32];
+
+        let mut a = WasmInstance::new(&engine, &module)
+            .unwrap()
+            .with_module_key(key_a);
+        let mut b = WasmInstance::new(&engine, &module)
+            .unwrap()
+            .with_module_key(key_b);
+
+        for _ in 0..3 {
+            a.call_raw("echo", b"ping").unwrap();
+        }
+        for _ in 0..5 {
+            b.call_raw("echo", b"ping").unwrap();
+        }
+
+        let entry_a = engine.ledger().get(&key_a).unwrap();
+        let entry_b = engine.ledger().get(&key_b).unwrap();
+        assert_eq!(entry_a.calls, 3);
+        assert_eq!(entry_b.calls, 5);
+        assert!(entry_a.total_points > 0);
+        // Identical calls cost the same, so totals scale with call counts
+        assert_eq!(entry_a.total_points * 5, entry_b.total_points * 3);
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_raw_input_limit_override() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine.compile(&wat::parse_str(ECHO_WAT).unwrap()).unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+
+        let options = CallOptions {
+            max_input_len: Some(2),
+            ..Default::default()
+        };
+        let result = instance.call_raw_with_options("echo", b"ping", &options);
+        assert!(matches!(
+            result,
+            Err(HostError::InputTooLarge { len: 4, max: 2 })
+        ));
+    }
+
+    /// Two zome functions next to the guest allocator
+    const ZOME_WAT: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (func (export "__hc__allocate_1") (param i32) (result i32)
+                (i32.const 1024))
+            (func (export "__aingle_guest_reset_arena"))
+            (func (export "create_entry") (param i32 i32) (result i64)
+                (i64.const 0))
+            (func (export "get_count") (result i32)
+                (i32.const 0)))
+    "#;
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_externs() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine.compile(&wat::parse_str(ZOME_WAT).unwrap()).unwrap();
+
+        // Available on the module before instantiation
+        let names: Vec<_> = module.externs().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, vec!["create_entry", "get_count"]);
+
+        let instance = WasmInstance::new(&engine, &module).unwrap();
+        let externs = instance.externs();
+        assert_eq!(externs.len(), 2);
+
+        let create_entry = externs.iter().find(|e| e.name == "create_entry").unwrap();
+        assert!(create_entry.callable);
+
+        let get_count = externs.iter().find(|e| e.name == "get_count").unwrap();
+        assert!(!get_count.callable);
+        assert!(get_count.params.is_empty());
+    }
+
+    /// Bump allocator growing memory on demand, echoing either of two inputs
+    const PAIR_WAT: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (global $next (mut i32) (i32.const 1024))
+            (func (export "__hc__allocate_1") (param $len i32) (result i32)
+                (local $ptr i32)
+                (local.set $ptr (global.get $next))
+                (global.set $next (i32.add (local.get $ptr) (local.get $len)))
+                (block $done
+                    (loop $grow
+                        (br_if $done
+                            (i32.le_u (global.get $next)
+                                (i32.mul (memory.size) (i32.const 65536))))
+                        (if (i32.eq (memory.grow (i32.const 16)) (i32.const -1))
+                            (then unreachable))
+                        (br $grow)))
+                (local.get $ptr))
+            (func (export "first") (param $p1 i32) (param $l1 i32) (param $p2 i32) (param $l2 i32)
+                (result i64)
+                (i64.or
+                    (i64.shl (i64.extend_i32_u (local.get $p1)) (i64.const 32))
+                    (i64.extend_i32_u (local.get $l1))))
+            (func (export "second") (param $p1 i32) (param $l1 i32) (param $p2 i32) (param $l2 i32)
+                (result i64)
+                (i64.or
+                    (i64.shl (i64.extend_i32_u (local.get $p2)) (i64.const 32))
+                    (i64.extend_i32_u (local.get $l2))))
+            (func (export "single") (param i32 i32) (result i64)
+                (i64.const 0)))
+    "#;
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_raw2_roundtrip() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine.compile(&wat::parse_str(PAIR_WAT).unwrap()).unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+
+        let small = [7u8; 16];
+        let large: Vec<u8> = (0..2 * 1024 * 1024).map(|i| i as u8).collect();
+
+        let first = instance.call_raw2("first", small, &large).unwrap();
+        assert_eq!(first, small);
+
+        let second = instance.call_raw2("second", small, &large).unwrap();
+        assert_eq!(second, large);
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_raw2_signature_mismatch() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine.compile(&wat::parse_str(PAIR_WAT).unwrap()).unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+
+        let result = instance.call_raw2("single", b"a", b"b");
+        assert!(matches!(result, Err(HostError::SignatureMismatch(_))));
+    }
+
+    /// Hashes a streamed input incrementally (FNV-1a) and returns the hash in
+    /// an envelope, without ever holding the whole input in memory
+    const STREAM_HASH_WAT: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (global $next (mut i32) (i32.const 1024))
+            (global $total (mut i32) (i32.const 0))
+            (global $received (mut i32) (i32.const 0))
+            (global $hash (mut i32) (i32.const 0))
+            (global $open (mut i32) (i32.const 0))
+            (func (export "__hc__allocate_1") (param $len i32) (result i32)
+                (local $ptr i32)
+                (local.set $ptr (global.get $next))
+                (global.set $next (i32.add (local.get $ptr) (local.get $len)))
+                (block $done
+                    (loop $grow
+                        (br_if $done
+                            (i32.le_u (global.get $next)
+                                (i32.mul (memory.size) (i32.const 65536))))
+                        (if (i32.eq (memory.grow (i32.const 1)) (i32.const -1))
+                            (then unreachable))
+                        (br $grow)))
+                (local.get $ptr))
+            (func (export "__aingle_stream_begin") (param $total i32) (result i32)
+                (global.set $total (local.get $total))
+                (global.set $received (i32.const 0))
+                (global.set $hash (i32.const 0x811c9dc5))
+                (global.set $open (i32.const 1))
+                (i32.const 1))
+            (func (export "__aingle_stream_write")
+                (param $handle i32) (param $ptr i32) (param $len i32) (result i32)
+                (local $end i32)
+                (if (i32.or (i32.ne (local.get $handle) (i32.const 1))
+                            (i32.eqz (global.get $open)))
+                    (then (return (i32.const 1))))
+                (if (i32.gt_u (i32.add (global.get $received) (local.get $len))
+                              (global.get $total))
+                    (then (return (i32.const 2))))
+                (local.set $end (i32.add (local.get $ptr) (local.get $len)))
+                (block $done
+                    (loop $bytes
+                        (br_if $done (i32.ge_u (local.get $ptr) (local.get $end)))
+                        (global.set $hash
+                            (i32.mul
+                                (i32.xor (global.get $hash) (i32.load8_u (local.get $ptr)))
+                                (i32.const 0x01000193)))
+                        (local.set $ptr (i32.add (local.get $ptr) (i32.const 1)))
+                        (br $bytes)))
+                (global.set $received (i32.add (global.get $received) (local.get $len)))
+                (i32.const 0))
+            (func (export "hash") (param $handle i32) (param $len i32) (result i64)
+                (local $crc i32) (local $i i32) (local $bit i32)
+                (if (i32.or (i32.ne (local.get $handle) (i32.const 1))
+                            (i32.ne (global.get $received) (local.get $len)))
+                    (then unreachable))
+                (global.set $open (i32.const 0))
+                (i32.store (i32.const 28) (global.get $hash))
+                ;; CRC-32 of the four payload bytes
+                (local.set $crc (i32.const -1))
+                (block $done
+                    (loop $bytes
+                        (br_if $done (i32.ge_u (local.get $i) (i32.const 4)))
+                        (local.set $crc
+                            (i32.xor (local.get $crc)
+                                (i32.load8_u (i32.add (i32.const 28) (local.get $i)))))
+                        (local.set $bit (i32.const 0))
+                        (block $bits_done
+                            (loop $bits
+                                (br_if $bits_done (i32.ge_u (local.get $bit) (i32.const 8)))
+                                (local.set $crc
+                                    (i32.xor
+                                        (i32.shr_u (local.get $crc) (i32.const 1))
+                                        (i32.and (i32.const 0xedb88320)
+                                            (i32.sub (i32.const 0)
+                                                (i32.and (local.get $crc) (i32.const 1))))))
+                                (local.set $bit (i32.add (local.get $bit) (i32.const 1)))
+                                (br $bits)))
+                        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                        (br $bytes)))
+                ;; Envelope header: magic, version 1, no flags, 4 byte payload
+                (i32.store (i32.const 16) (i32.const 0x00014149))
+                (i32.store (i32.const 20) (i32.const 4))
+                (i32.store (i32.const 24) (i32.xor (local.get $crc) (i32.const -1)))
+                (i64.const 0x0000001000000010)))
+    "#;
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_streamed_hash() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine
+            .compile(&wat::parse_str(STREAM_HASH_WAT).unwrap())
+            .unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+
+        let data: Vec<u8> = (0..8 * 1024 * 1024u32)
+            .map(|i| (i.wrapping_mul(31) ^ (i >> 8)) as u8)
+            .collect();
+        let expected = data.iter().fold(0x811c9dc5u32, |hash, &b| {
+            (hash ^ b as u32).wrapping_mul(0x01000193)
+        });
+
+        let output = instance.call_streamed("hash", &data, 64 * 1024).unwrap();
+        assert_eq!(output, expected.to_le_bytes());
+
+        // Only a single chunk buffer was ever allocated in the guest
+        let size = instance.backend_instance().memory_size().unwrap();
+        assert!(size <= 3 * 64 * 1024, "guest memory grew to {} bytes", size);
+    }
+
+    #[derive(Default)]
+    struct DropObserver {
+        drops: AtomicUsize,
+    }
+
+    impl CallObserver for DropObserver {
+        fn on_instance_drop(&self, module_key: Option<&[u8; 32]>) {
+            assert_eq!(module_key, Some(&[0x11; 32]));
+            self.drops.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_finalize_frees_allocations() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine.compile(&wat::parse_str(PAIR_WAT).unwrap()).unwrap();
+        let observer = Arc::new(DropObserver::default());
+        let mut instance = WasmInstance::new(&engine, &module)
+            .unwrap()
+            .with_module_key([0x11; 32])
+            .with_observer(observer.clone());
+
+        instance.call_raw2("first", b"a", b"b").unwrap();
+        assert_eq!(instance.outstanding_allocations(), 2);
+
+        instance.finalize().unwrap();
+        assert_eq!(instance.outstanding_allocations(), 0);
+        assert_eq!(observer.drops.load(Ordering::SeqCst), 1);
+
+        // Dropping after finalize does not notify again
+        drop(instance);
+        assert_eq!(observer.drops.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_drop_notifies_observer() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine.compile(&wat::parse_str(ECHO_WAT).unwrap()).unwrap();
+        let observer = Arc::new(DropObserver::default());
+
+        let instance = WasmInstance::new(&engine, &module)
+            .unwrap()
+            .with_module_key([0x11; 32])
+            .with_observer(observer.clone());
+        drop(instance);
+
+        assert_eq!(observer.drops.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_raw_missing_function() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine.compile(&wat::parse_str(ECHO_WAT).unwrap()).unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+
+        let result = instance.call_raw("missing", b"ping");
+        assert!(matches!(result, Err(HostError::FunctionNotFound(_))));
+    }
+
+    /// Exports that trap in different ways
+    const TRAP_WAT: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (func (export "oob") (param i32 i32) (result i64)
+                (drop (i32.load (i32.const 0x7ffffff0)))
+                (i64.const 0))
+            (func (export "crash") (param i32 i32) (result i64)
+                unreachable)
+            (func (export "spin") (param i32 i32) (result i64)
+                (loop $forever (br $forever))
+                (i64.const 0)))
+    "#;
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_traps_are_categorized() {
+        let engine = WasmEngine::new(EngineConfig {
+            metering_limit: 100_000,
+            ..EngineConfig::default()
+        })
+        .unwrap();
+        let module = engine.compile(&wat::parse_str(TRAP_WAT).unwrap()).unwrap();
+
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+        assert!(matches!(
+            instance.call_raw("oob", b""),
+            Err(HostError::Trap {
+                kind: TrapKind::MemoryOutOfBounds,
+                ..
+            })
+        ));
+        assert!(matches!(
+            instance.call_raw("crash", b""),
+            Err(HostError::Trap {
+                kind: TrapKind::UnreachableCodeReached,
+                ..
+            })
+        ));
+
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+        assert!(matches!(
+            instance.call_raw("spin", b""),
+            Err(HostError::MeteringExceeded)
+        ));
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_failing_module_quarantined() {
+        use crate::{FailurePolicy, FailureTracker};
+
+        let tracker = Arc::new(FailureTracker::new(FailurePolicy {
+            threshold: 2,
+            ..FailurePolicy::default()
+        }));
+        let engine = WasmEngine::new(EngineConfig::default())
+            .unwrap()
+            .with_failure_tracker(tracker.clone());
+        let module = engine.compile(&wat::parse_str(TRAP_WAT).unwrap()).unwrap();
+        let key = [4u8; 32];
+
+        for _ in 0..2 {
+            let mut instance = WasmInstance::new(&engine, &module)
+                .unwrap()
+                .with_module_key(key);
+            assert!(matches!(
+                instance.call_raw("crash", b""),
+                Err(HostError::Trap { .. })
+            ));
+        }
+
+        let mut instance = WasmInstance::new(&engine, &module)
+            .unwrap()
+            .with_module_key(key);
+        assert!(matches!(
+            instance.call_raw("crash", b""),
+            Err(HostError::Quarantined(k)) if k == key
+        ));
+
+        tracker.quarantine_reset(&key);
+        assert!(matches!(
+            instance.call_raw("crash", b""),
+            Err(HostError::Trap { .. })
+        ));
+    }
+
+    /// Reports arena statistics the way the guest crate exports them
+    const STATS_WAT: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (global $allocated (mut i32) (i32.const 0))
+            (global $high (mut i32) (i32.const 0))
+            (func (export "__aingle_guest_allocated_bytes") (result i32)
+                (global.get $allocated))
+            (func (export "__aingle_guest_high_water") (result i32)
+                (global.get $high))
+            (func (export "work") (param i32 i32) (result i64)
+                (global.set $allocated (i32.const 4096))
+                (global.set $high (i32.const 65536))
+                (i64.const 0)))
+    "#;
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_report_reads_arena_stats() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine.compile(&wat::parse_str(STATS_WAT).unwrap()).unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+        assert!(instance.last_call_report().is_none());
+
+        instance.call_raw("work", b"").unwrap();
+        let report = instance.last_call_report().unwrap();
+        assert_eq!(report.guest_allocated_bytes, Some(4096));
+        assert_eq!(report.guest_high_water, Some(65536));
+
+        // Guests without the exports report nothing
+        let module = engine.compile(&wat::parse_str(ECHO_WAT).unwrap()).unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+        instance.call_raw("echo", b"ping").unwrap();
+        let report = instance.last_call_report().unwrap();
+        assert_eq!(report.guest_allocated_bytes, None);
+        assert_eq!(report.guest_high_water, None);
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_report_splits_points() {
+        use crate::HostCallCost;
+
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "env" "__aingle_random_bytes" (func $random (param i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                (func (export "work") (param i32 i32) (result i64)
+                    (drop (call $random (i32.const 0) (i32.const 8)))
+                    (drop (call $random (i32.const 0) (i32.const 8)))
+                    (i64.const 0)))
+            "#,
+        )
+        .unwrap();
+        let engine = WasmEngine::new(EngineConfig {
+            charge_host_calls: Some(HostCallCost::PerCall(1000)),
+            ..EngineConfig::default()
+        })
+        .unwrap();
+        let module = engine.compile(&wasm).unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+
+        instance.call_raw("work", b"").unwrap();
+        let report = instance.last_call_report().unwrap();
+        assert_eq!(report.import_points, Some(2000));
+        let wasm_points = report.wasm_points.unwrap();
+        assert!(wasm_points > 0 && wasm_points < 1000, "{}", wasm_points);
+
+        // Charges do not carry over to the next call
+        instance.call_raw("work", b"").unwrap();
+        assert_eq!(
+            instance.last_call_report().unwrap().import_points,
+            Some(2000)
+        );
+    }
+
+    /// Returns the error envelope at offset 64 once an arena limit is set,
+    /// like a guest whose limited arena is exhausted
+    const ARENA_LIMIT_WAT: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (global $limit (mut i32) (i32.const 0))
+            (func (export "__aingle_guest_set_arena_limit") (param i32)
+                (global.set $limit (local.get 0)))
+            (func (export "exhausted") (param i32 i32) (result i64)
+                (if (i32.eqz (global.get $limit)) (then (unreachable)))
+                (i64.const 0x8000004000000021)))
+    "#;
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_guest_arena_limit() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine
+            .compile(&wat::parse_str(ARENA_LIMIT_WAT).unwrap())
+            .unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+
+        let error = crate::build_guest_result(b"guest arena exhausted", true).unwrap();
+        assert_eq!(error.len(), 0x21);
+        instance
+            .backend_instance()
+            .write_memory(64, &error)
+            .unwrap();
+
+        assert!(instance.set_guest_arena_limit(Some(4096)).unwrap());
+        assert!(matches!(
+            instance.call_raw("exhausted", b""),
+            Err(HostError::GuestError(message)) if message.contains("arena exhausted")
+        ));
+
+        // Guests without the export cannot be limited
+        let module = engine.compile(&wat::parse_str(ECHO_WAT).unwrap()).unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+        assert!(!instance.set_guest_arena_limit(None).unwrap());
+    }
+
+    /// Follows the 64-bit convention, returning its input as the result,
+    /// or a result slice whose end wraps past 2^64
+    const ECHO64_WAT: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (func (export "echo64") (param i64 i64) (result i64 i64)
+                (local.get 0)
+                (local.get 1))
+            (func (export "wrapping64") (param i64 i64) (result i64 i64)
+                (i64.const 0x7ffffffffffffff0)
+                (i64.const -16)))
+    "#;
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_call_memory64_convention() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine
+            .compile(&wat::parse_str(ECHO64_WAT).unwrap())
+            .unwrap();
+        assert!(module.is_memory64());
+
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+        assert_eq!(instance.call_raw("echo64", b"ping").unwrap(), b"ping");
+        assert!(matches!(
+            instance.call_raw("wrapping64", b"x"),
+            Err(HostError::Memory(MemoryError::OutOfBounds { .. }))
+        ));
+
+        let module = engine.compile(&wat::parse_str(ECHO_WAT).unwrap()).unwrap();
+        assert!(!module.is_memory64());
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_memory_layouts() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let echo = |memory: &str| {
+            wat::parse_str(format!(
+                r#"
+                (module
+                    {}
+                    (func (export "__hc__allocate_1") (param i32) (result i32)
+                        (i32.const 1024))
+                    (func (export "echo") (param $ptr i32) (param $len i32) (result i64)
+                        (i64.or
+                            (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+                            (i64.extend_i32_u (local.get $len)))))
+                "#,
+                memory
+            ))
+            .unwrap()
+        };
+
+        for (memory, import, export) in [
+            // As linked with `--import-memory`, needing more than a page
+            (
+                r#"(memory (import "env" "memory") 2)"#,
+                Some("memory"),
+                None,
+            ),
+            (r#"(memory (import "js" "heap") 2 4)"#, Some("heap"), None),
+            (
+                r#"(memory (import "env" "memory") 2) (export "memory" (memory 0))"#,
+                Some("memory"),
+                Some("memory"),
+            ),
+            (r#"(memory (export "mem") 2)"#, None, Some("mem")),
+        ] {
+            let module = engine.compile(&echo(memory)).unwrap();
+            let layout = module.memory_layout().unwrap();
+            assert_eq!(
+                layout.import.as_ref().map(|import| import.name.as_str()),
+                import,
+                "{}",
+                memory
+            );
+            assert_eq!(layout.export.as_deref(), export, "{}", memory);
+
+            let mut instance = WasmInstance::new(&engine, &module).unwrap();
+            assert_eq!(
+                instance.call_raw("echo", b"ping").unwrap(),
+                b"ping",
+                "{}",
+                memory
+            );
+            let size = instance.backend_instance().memory_size().unwrap();
+            assert_eq!(size, 2 * 64 * 1024, "{}", memory);
+        }
+
+        // Refused rather than guessing which memory the guest allocates in,
+        // if the engine compiles multi-memory modules at all
+        let wasm = echo(r#"(memory (import "env" "a") 1) (memory (import "env" "b") 1)"#);
+        if let Ok(module) = engine.compile(&wasm) {
+            assert!(matches!(
+                module.memory_layout(),
+                Err(HostError::UnsupportedMemory(_))
+            ));
+            assert!(matches!(
+                WasmInstance::new(&engine, &module),
+                Err(HostError::UnsupportedMemory(_))
+            ));
+        }
+    }
+
+    /// Reports the bytes it was asked to preallocate as its arena size
+    const PREALLOC_WAT: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (global $reserved (mut i32) (i32.const 0))
+            (func (export "__aingle_guest_prealloc") (param i32)
+                (global.set $reserved (local.get 0)))
+            (func (export "__aingle_guest_allocated_bytes") (result i32)
+                (global.get $reserved))
+            (func (export "noop") (param i32 i32) (result i64)
+                (i64.const 0)))
+    "#;
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_guest_prealloc() {
+        let engine = WasmEngine::new(EngineConfig {
+            guest_prealloc_bytes: Some(64 * 1024),
+            ..EngineConfig::default()
+        })
+        .unwrap();
+        let module = engine
+            .compile(&wat::parse_str(PREALLOC_WAT).unwrap())
+            .unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+        instance.call_raw("noop", b"").unwrap();
+        assert_eq!(
+            instance.last_call_report().unwrap().guest_allocated_bytes,
+            Some(64 * 1024)
+        );
+
+        // Guests without the export are instantiated all the same
+        let module = engine.compile(&wat::parse_str(ECHO_WAT).unwrap()).unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+        assert!(!instance.prealloc_guest_arena(1024).unwrap());
+    }
+
+    /// Echoes its input like [`ECHO_WAT`], counting its calls as `calls`
+    #[cfg(feature = "result-cache")]
+    const COUNTING_ECHO_WAT: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (global $calls (mut i64) (i64.const 0))
+            (func (export "echo") (param $ptr i32) (param $len i32) (result i64)
+                (global.set $calls (i64.add (global.get $calls) (i64.const 1)))
+                (i64.or
+                    (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+                    (i64.extend_i32_u (local.get $len))))
+            (func (export "calls") (result i64)
+                (global.get $calls)))
+    "#;
+
+    #[test]
+    #[cfg(all(
+        feature = "result-cache",
+        any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod")
+    ))]
+    fn test_result_cache() {
+        use crate::{PurityAssertion, ResultCache};
+
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine
+            .compile(&wat::parse_str(COUNTING_ECHO_WAT).unwrap())
+            .unwrap();
+        let cache = ResultCache::default();
+        let instantiate = |key: [u8; 32]| {
+            WasmInstance::new(&engine, &module)
+                .unwrap()
+                .with_module_key(key)
+                .with_result_cache(cache.clone())
+        };
+        let calls =
+            |instance: &mut WasmInstance| instance.backend_instance().call_packed("calls").unwrap();
+        let pure = CallOptions {
+            cacheable: Some(PurityAssertion::assert_pure()),
+            ..CallOptions::default()
+        };
+
+        // Two identical calls run the guest once
+        let mut instance = instantiate([1; 32]);
+        assert_eq!(
+            instance
+                .call_raw_with_options("echo", b"op", &pure)
+                .unwrap(),
+            b"op"
+        );
+        assert!(instance.last_call_report().is_some());
+        assert_eq!(
+            instance
+                .call_raw_with_options("echo", b"op", &pure)
+                .unwrap(),
+            b"op"
+        );
+        assert!(instance.last_call_report().is_none());
+        assert_eq!(calls(&mut instance), Some(1));
+
+        // One byte of input apart misses
+        assert_eq!(
+            instance
+                .call_raw_with_options("echo", b"oq", &pure)
+                .unwrap(),
+            b"oq"
+        );
+        assert_eq!(calls(&mut instance), Some(2));
+
+        // Calls not asserted pure always run
+        instance.call_raw("echo", b"op").unwrap();
+        assert_eq!(calls(&mut instance), Some(3));
+
+        // Nor are outputs shared with other modules
+        let mut other = instantiate([2; 32]);
+        other.call_raw_with_options("echo", b"op", &pure).unwrap();
+        assert_eq!(calls(&mut other), Some(1));
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.bytes_saved), (1, 3, 2));
+    }
+
+    /// Counts to 1000 on warm-up, exporting the count as `warm`
+    const WARMUP_WAT: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (global $warm (mut i32) (i32.const 0))
+            (func (export "__aingle_warmup") (param i32 i32) (result i64)
+                (local $i i32)
+                (loop $again
+                    (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                    (br_if $again (i32.lt_u (local.get $i) (i32.const 1000))))
+                (global.set $warm (local.get $i))
+                (i64.const 0))
+            (func (export "warm") (result i64)
+                (i64.extend_i32_u (global.get $warm))))
+    "#;
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_warmup() {
+        let wasm = wat::parse_str(WARMUP_WAT).unwrap();
+        let warm = |config: EngineConfig| {
+            let engine = WasmEngine::new(config).unwrap();
+            let module = engine.compile(&wasm).unwrap();
+            WasmInstance::new(&engine, &module).map(|mut instance| {
+                let inner = instance.backend_instance();
+                let remaining = inner.remaining_points();
+                (inner.call_packed("warm").unwrap(), remaining)
+            })
+        };
+
+        // Warm-up spends its own budget, not the one of later calls
+        let config = EngineConfig {
+            metering_limit: 1_000_000,
+            ..EngineConfig::default()
+        };
+        assert_eq!(warm(config.clone()).unwrap(), (Some(1000), Some(1_000_000)));
+
+        let skipped = EngineConfig {
+            warmup_export: None,
+            ..config.clone()
+        };
+        assert_eq!(warm(skipped).unwrap(), (Some(0), Some(1_000_000)));
+
+        let starved = EngineConfig {
+            warmup_metering_limit: 100,
+            ..config
+        };
+        match warm(starved) {
+            Err(HostError::WarmupFailed { export, source }) => {
+                assert_eq!(export, "__aingle_warmup");
+                assert!(matches!(*source, HostError::MeteringExceeded), "{}", source);
+            }
+            other => panic!("expected a warm-up failure, got {:?}", other),
+        }
+
+        // Guests without the export are instantiated all the same
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine.compile(&wat::parse_str(ECHO_WAT).unwrap()).unwrap();
+        assert!(WasmInstance::new(&engine, &module).is_ok());
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_warmup_trap() {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (memory (export "memory") 1)
+                (func (export "__aingle_warmup") (param i32 i32) (result i64)
+                    unreachable))
+            "#,
+        )
+        .unwrap();
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine.compile(&wasm).unwrap();
+        assert!(matches!(
+            WasmInstance::new(&engine, &module),
+            Err(HostError::WarmupFailed { source, .. }) if matches!(*source, HostError::Trap { .. })
+        ));
+    }
+
+    /// Echoes its input like [`ECHO_WAT`], advertising CRC32C support
+    const CRC32C_WAT: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (global $checksum (mut i32) (i32.const 0))
+            (func (export "__aingle_guest_abi") (result i64)
+                (i64.const 0x0000001000000001))
+            (func (export "__aingle_guest_set_checksum") (param i32)
+                (global.set $checksum (local.get 0)))
+            (func (export "checksum") (result i64)
+                (i64.extend_i32_u (global.get $checksum)))
+            (func (export "echo") (param $ptr i32) (param $len i32) (result i64)
+                (i64.or
+                    (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+                    (i64.extend_i32_u (local.get $len)))))
+    "#;
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_checksum_negotiation() {
+        let engine = WasmEngine::new(EngineConfig {
+            checksum: ChecksumKind::Crc32c,
+            ..EngineConfig::default()
+        })
+        .unwrap();
+
+        // The guest is told, and the echoed CRC32C envelope decodes
+        let module = engine
+            .compile(&wat::parse_str(CRC32C_WAT).unwrap())
+            .unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+        assert_eq!(instance.checksum(), ChecksumKind::Crc32c);
+        assert_eq!(instance.call_raw("echo", b"ping").unwrap(), b"ping");
+        assert_eq!(
+            instance.inner.call_packed("checksum").unwrap(),
+            Some(ChecksumKind::Crc32c as u64)
+        );
+
+        // Guests not advertising it stay on CRC32
+        let module = engine.compile(&wat::parse_str(ECHO_WAT).unwrap()).unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+        assert_eq!(instance.checksum(), ChecksumKind::Crc32);
+        assert_eq!(instance.call_raw("echo", b"ping").unwrap(), b"ping");
+    }
+
+    /// Traps like a guest whose panic aborted, publishing the error at
+    /// offset 64
+    const PANIC_WAT: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (global $len (mut i32) (i32.const 0))
+            (func (export "set_panic_len") (param i32)
+                (global.set $len (local.get 0)))
+            (func (export "__aingle_guest_last_panic") (result i64)
+                (i64.or
+                    (i64.const 0x8000004000000000)
+                    (i64.extend_i32_u (global.get $len))))
+            (func (export "explode") (param i32 i32) (result i64)
+                (unreachable)))
+    "#;
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_panic_recovered_as_guest_error() {
+        #[derive(Debug, serde::Serialize)]
+        struct SerializableError {
+            error_type: String,
+            message: String,
+        }
+
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine.compile(&wat::parse_str(PANIC_WAT).unwrap()).unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+
+        // Without a published panic the trap is reported as is
+        assert!(matches!(
+            instance.call_raw("explode", b""),
+            Err(HostError::Trap {
+                kind: TrapKind::UnreachableCodeReached,
+                ..
+            })
+        ));
+
+        let error = aingle_middleware_bytes::encode(&SerializableError {
+            error_type: "GuestStructured".to_string(),
+            message: "[GuestCall] bad input: 42 at src/lib.rs:7".to_string(),
+        })
+        .unwrap();
+        let inner = instance.backend_instance();
+        inner.write_memory(64, &error).unwrap();
+        inner
+            .call_setter("set_panic_len", error.len() as u32)
+            .unwrap();
+
+        assert!(matches!(
+            instance.call_raw("explode", b""),
+            Err(HostError::GuestError(message))
+                if message == "[GuestCall] bad input: 42 at src/lib.rs:7"
+        ));
+    }
 }
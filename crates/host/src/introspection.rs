@@ -0,0 +1,126 @@
+//! Extern tables registered by guests
+//!
+//! Export names alone cannot say which input a function expects or whether
+//! it is a callback. Guests that declare their externs with
+//! `register_externs!` export a table carrying this metadata, which
+//! [`ModuleIntrospection`] reads back.
+
+use crate::{HostError, WasmInstance};
+use aingle_wasmer_common::{ExternRecord, GUEST_LIST_EXTERNS_EXPORT};
+
+/// The extern table registered by a guest
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ModuleIntrospection {
+    externs: Vec<ExternRecord>,
+}
+
+impl ModuleIntrospection {
+    /// Read the extern table of an instance
+    ///
+    /// Guests that register no externs yield an empty table.
+    pub fn from_instance(instance: &mut WasmInstance) -> Result<Self, HostError> {
+        let Some(payload) = instance.call_nullary(GUEST_LIST_EXTERNS_EXPORT)? else {
+            return Ok(Self::default());
+        };
+        let externs = aingle_middleware_bytes::decode(&payload)
+            .map_err(|e| HostError::Deserialization(format!("extern table: {}", e)))?;
+        Ok(Self { externs })
+    }
+
+    /// All registered externs, in registration order
+    pub fn externs(&self) -> &[ExternRecord] {
+        &self.externs
+    }
+
+    /// Get the record of the extern exported as `name`
+    pub fn get(&self, name: &str) -> Option<&ExternRecord> {
+        self.externs.iter().find(|record| record.name == name)
+    }
+
+    /// Registered externs flagged as callbacks
+    pub fn callbacks(&self) -> impl Iterator<Item = &ExternRecord> {
+        self.externs.iter().filter(|record| record.is_callback())
+    }
+
+    /// Whether the guest registered no externs
+    pub fn is_empty(&self) -> bool {
+        self.externs.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{build_guest_result, EngineConfig, WasmEngine};
+    use aingle_wasmer_common::ExternFlags;
+
+    const EXTERNS_WAT: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (global $len (mut i32) (i32.const 0))
+            (func (export "set_table_len") (param i32)
+                (global.set $len (local.get 0)))
+            (func (export "__aingle_list_externs") (result i64)
+                (i64.or
+                    (i64.const 0x0000004000000000)
+                    (i64.extend_i32_u (global.get $len))))
+            (func (export "greet") (param i32 i32) (result i64)
+                (i64.const 0))
+            (func (export "on_event") (param i32 i32) (result i64)
+                (i64.const 0)))
+    "#;
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_read_registered_externs() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let module = engine
+            .compile(&wat::parse_str(EXTERNS_WAT).unwrap())
+            .unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+
+        let table = vec![
+            ExternRecord {
+                name: "greet".to_string(),
+                schema_id: 1,
+                flags: ExternFlags::ReadOnly as u32,
+            },
+            ExternRecord {
+                name: "on_event".to_string(),
+                schema_id: 2,
+                flags: ExternFlags::Callback as u32,
+            },
+        ];
+        let envelope =
+            build_guest_result(&aingle_middleware_bytes::encode(&table).unwrap(), false).unwrap();
+        let inner = instance.backend_instance();
+        inner.write_memory(64, &envelope).unwrap();
+        inner
+            .call_setter("set_table_len", envelope.len() as u32)
+            .unwrap();
+
+        let introspection = ModuleIntrospection::from_instance(&mut instance).unwrap();
+        assert_eq!(introspection.externs(), &table[..]);
+        assert!(introspection
+            .get("greet")
+            .unwrap()
+            .has_flag(ExternFlags::ReadOnly));
+        assert_eq!(introspection.get("on_event").unwrap().schema_id, 2);
+        assert!(introspection.get("missing").is_none());
+
+        let callbacks: Vec<_> = introspection.callbacks().map(|r| r.name.as_str()).collect();
+        assert_eq!(callbacks, ["on_event"]);
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_unregistered_guest_has_empty_table() {
+        let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+        let wasm = wat::parse_str(r#"(module (memory (export "memory") 1))"#).unwrap();
+        let module = engine.compile(&wasm).unwrap();
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+
+        let introspection = ModuleIntrospection::from_instance(&mut instance).unwrap();
+        assert!(introspection.is_empty());
+    }
+}
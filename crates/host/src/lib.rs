@@ -8,6 +8,14 @@
 //! - Sandboxed execution
 //! - Zero-copy data transfer where possible
 //!
+//! ## Backends
+//!
+//! - `wasmer_sys_dev` (default): Wasmer with Cranelift
+//! - `wasmer_sys_prod`: Wasmer with LLVM
+//! - `wasmer_js`: Wasmer's js API for `wasm32-unknown-unknown`, running
+//!   guests on the browser's engine. Metering is a no-op there and modules
+//!   are not persisted to disk.
+//!
 //! ## Example
 //!
 //! ```ignore
@@ -19,33 +27,101 @@
 
 #![warn(missing_docs)]
 
+#[cfg(feature = "wasmer")]
+mod abi;
+/// Execution backend abstraction
+pub mod backend;
+mod capabilities;
+#[cfg(feature = "debug-tools")]
+mod debug;
+#[cfg(feature = "debug-tools")]
+mod debug_json;
 mod engine;
 mod env;
 mod error;
+mod executor;
+mod explain;
+mod failure;
+/// C ABI for embedding the host in non-Rust conductors
+#[cfg(all(feature = "cdylib", feature = "wasmer"))]
+pub mod ffi;
 /// Guest interaction utilities
 pub mod guest;
+/// Adapters with the `holochain_wasmer_host` API
+pub mod holochain_compat;
+#[cfg(feature = "wasmer")]
+mod imports;
 mod instance;
+mod introspection;
+mod metering;
+mod random;
+mod registry;
+#[cfg(feature = "result-cache")]
+mod result_cache;
+/// Soak testing of long-running instance reuse
+#[cfg(all(feature = "stress", not(target_arch = "wasm32")))]
+pub mod stress;
+#[cfg(feature = "wasmer")]
+mod wasmer_backend;
 
 /// Module caching with filesystem support
 pub mod module;
 
 pub mod prelude;
 
+#[cfg(feature = "wasmer")]
+pub use abi::ProbeGuestAbi;
+pub use backend::{
+    BackendInstance, BackendMemory, BackendModule, ExternInfo, MemoryImport, MemoryLayout,
+    StreamSink, ValueType, WasmBackend, DEFAULT_MEMORY_EXPORT,
+};
+pub use capabilities::{CompilerKind, EngineCapabilities, WasmFeatures};
+#[cfg(feature = "debug-tools")]
+pub use debug::MemoryDump;
+#[cfg(feature = "debug-tools")]
+pub use debug_json::{
+    set_redacted_fields, transcode_msgpack_to_json, DEFAULT_REDACTED_FIELDS, REDACTED,
+};
 pub use engine::*;
 pub use env::*;
 pub use error::*;
+pub use executor::{CallExecutor, CallObserver, ExecutorConfig};
+pub use explain::{hex_preview, CallTrace, TraceStep, TraceStepKind, TRACE_PREVIEW_LEN};
+pub use failure::{FailurePolicy, FailureTracker};
 pub use guest::*;
+#[cfg(feature = "wasmer")]
+pub use imports::ImportBuilder;
 pub use instance::*;
-pub use module::ModuleCache;
+pub use introspection::ModuleIntrospection;
+pub use metering::{LedgerEntry, MeteringLedger, DEFAULT_LEDGER_WINDOW};
+pub use module::{CacheEntry, CacheGcPolicy, GcReport, ModuleCache, ARTIFACT_COMPILER};
+pub use random::RandomSource;
+pub use registry::HostFunctionRegistry;
+#[cfg(feature = "result-cache")]
+pub use result_cache::{ResultCache, ResultCacheStats, ResultKey, DEFAULT_RESULT_CACHE_BYTES};
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+pub use wasmer_backend::MeteringCostFn;
+#[cfg(feature = "wasmer")]
+pub use wasmer_backend::{WasmerBackend, WasmerInstance};
 
+#[allow(deprecated)]
+pub use aingle_wasmer_common::DoubleUSize;
 pub use aingle_wasmer_common::{
-    DeserializeError, DoubleUSize, GuestCallError, HostCallError, SerializeError, WasmDecode,
-    WasmEncode, WasmError, WasmErrorInner, WasmResult, WasmSlice,
+    guest_fn, ChecksumKind, Compatibility, DeserializeError, ExternFlags, ExternRecord, GuestAbi,
+    GuestCallError, GuestCapability, GuestFunction, GuestReturn, HostCallError, HostFunction,
+    ProtocolCompat, SerializeError, VersionPolicy, WasmDecode, WasmEncode, WasmError,
+    WasmErrorInner, WasmResult, WasmSlice,
 };
 
 /// Default metering limit: 100 billion operations
 pub const DEFAULT_METERING_LIMIT: u64 = 100_000_000_000;
 
+/// Default maximum input size accepted by guest calls: 64 MiB
+pub const DEFAULT_MAX_INPUT_LEN: usize = 64 * 1024 * 1024;
+
+/// Default maximum output size read back from guest calls: 64 MiB
+pub const DEFAULT_MAX_OUTPUT_LEN: usize = 64 * 1024 * 1024;
+
 /// Test metering limit: 10 million operations
 #[cfg(test)]
 pub const TEST_METERING_LIMIT: u64 = 10_000_000;
@@ -0,0 +1,190 @@
+//! Cumulative metering accounting per module
+//!
+//! The [`MeteringLedger`] attributes the points consumed by every guest call
+//! to the module it ran, so hosts can track compute per DNA across calls.
+
+use parking_lot::{Mutex, RwLock};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+// std's clock panics on wasm32-unknown-unknown
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+/// Default width of the rolling rate window
+pub const DEFAULT_LEDGER_WINDOW: Duration = Duration::from_secs(60);
+
+/// Snapshot of the metering totals for one module
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LedgerEntry {
+    /// Total points consumed since the last reset
+    pub total_points: u64,
+    /// Number of calls recorded since the last reset
+    pub calls: u64,
+    /// Average points per second over the rolling window
+    pub points_per_sec: f64,
+}
+
+/// Per-module counters
+///
+/// Totals are atomics; only the rolling window takes the per-key lock.
+#[derive(Default)]
+struct LedgerSlot {
+    total_points: AtomicU64,
+    calls: AtomicU64,
+    /// Points per elapsed second since the ledger was created
+    window: Mutex<VecDeque<(u64, u64)>>,
+}
+
+/// Cumulative metering totals keyed by module hash
+pub struct MeteringLedger {
+    slots: RwLock<HashMap<[u8; 32], Arc<LedgerSlot>>>,
+    window: Duration,
+    started: Instant,
+}
+
+impl Default for MeteringLedger {
+    fn default() -> Self {
+        Self::new(DEFAULT_LEDGER_WINDOW)
+    }
+}
+
+impl MeteringLedger {
+    /// Create a ledger computing rates over the given window
+    pub fn new(window: Duration) -> Self {
+        Self {
+            slots: RwLock::new(HashMap::new()),
+            window: window.max(Duration::from_secs(1)),
+            started: Instant::now(),
+        }
+    }
+
+    /// Record the points consumed by one call of a module
+    pub fn record(&self, key: [u8; 32], points: u64) {
+        let slot = self.slot(key);
+        slot.total_points.fetch_add(points, Ordering::Relaxed);
+        slot.calls.fetch_add(1, Ordering::Relaxed);
+
+        let now = self.now_secs();
+        let mut window = slot.window.lock();
+        match window.back_mut() {
+            Some((sec, bucket)) if *sec == now => *bucket += points,
+            _ => window.push_back((now, points)),
+        }
+        self.expire(&mut window, now);
+    }
+
+    /// Get the totals for a module
+    pub fn get(&self, key: &[u8; 32]) -> Option<LedgerEntry> {
+        let slot = self.slots.read().get(key).cloned()?;
+        Some(self.entry(&slot))
+    }
+
+    /// Clear the totals for a module
+    pub fn reset(&self, key: &[u8; 32]) {
+        self.slots.write().remove(key);
+    }
+
+    /// Clear the totals for all modules
+    pub fn reset_all(&self) {
+        self.slots.write().clear();
+    }
+
+    /// Snapshot the totals of every module for export
+    pub fn snapshot(&self) -> HashMap<[u8; 32], LedgerEntry> {
+        let slots: Vec<_> = self
+            .slots
+            .read()
+            .iter()
+            .map(|(key, slot)| (*key, slot.clone()))
+            .collect();
+
+        slots
+            .into_iter()
+            .map(|(key, slot)| (key, self.entry(&slot)))
+            .collect()
+    }
+
+    /// Width of the rolling rate window
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    fn slot(&self, key: [u8; 32]) -> Arc<LedgerSlot> {
+        if let Some(slot) = self.slots.read().get(&key) {
+            return slot.clone();
+        }
+        self.slots.write().entry(key).or_default().clone()
+    }
+
+    fn entry(&self, slot: &LedgerSlot) -> LedgerEntry {
+        let now = self.now_secs();
+        let mut window = slot.window.lock();
+        self.expire(&mut window, now);
+        let windowed: u64 = window.iter().map(|(_, points)| points).sum();
+
+        LedgerEntry {
+            total_points: slot.total_points.load(Ordering::Relaxed),
+            calls: slot.calls.load(Ordering::Relaxed),
+            points_per_sec: windowed as f64 / self.window.as_secs_f64(),
+        }
+    }
+
+    fn expire(&self, window: &mut VecDeque<(u64, u64)>, now: u64) {
+        let oldest = now.saturating_sub(self.window.as_secs().saturating_sub(1));
+        while matches!(window.front(), Some((sec, _)) if *sec < oldest) {
+            window.pop_front();
+        }
+    }
+
+    fn now_secs(&self) -> u64 {
+        self.started.elapsed().as_secs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_attributes_keys() {
+        let ledger = MeteringLedger::default();
+        let a = [1u8; 32];
+        let b = [2u8; 32];
+
+        for _ in 0..3 {
+            ledger.record(a, 10);
+        }
+        ledger.record(b, 7);
+
+        let entry = ledger.get(&a).unwrap();
+        assert_eq!(entry.total_points, 30);
+        assert_eq!(entry.calls, 3);
+        assert_eq!(entry.points_per_sec, 30.0 / 60.0);
+
+        let entry = ledger.get(&b).unwrap();
+        assert_eq!(entry.total_points, 7);
+        assert_eq!(entry.calls, 1);
+
+        assert!(ledger.get(&[3u8; 32]).is_none());
+    }
+
+    #[test]
+    fn test_reset_and_snapshot() {
+        let ledger = MeteringLedger::default();
+        ledger.record([1u8; 32], 5);
+        ledger.record([2u8; 32], 6);
+
+        ledger.reset(&[1u8; 32]);
+        let snapshot = ledger.snapshot();
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[&[2u8; 32]].total_points, 6);
+
+        ledger.reset_all();
+        assert!(ledger.snapshot().is_empty());
+    }
+}
@@ -3,53 +3,209 @@
 //! Provides efficient caching of compiled WASM modules with optional
 //! filesystem persistence.
 
-use crate::HostError;
-use parking_lot::RwLock;
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::sync::Arc;
+use crate::{EngineCapabilities, HostError};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
-#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+#[cfg(feature = "wasmer")]
+use parking_lot::{Mutex, RwLock};
+#[cfg(feature = "wasmer")]
+use std::collections::HashMap;
+#[cfg(feature = "wasmer")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "wasmer")]
+use std::sync::{Arc, OnceLock};
+#[cfg(feature = "wasmer")]
 use wasmer::{Engine, Module};
 
+/// Number of independently locked shards of the in-memory cache
+#[cfg(feature = "wasmer")]
+const SHARDS: usize = 16;
+
+#[cfg(feature = "wasmer")]
+type Shard = RwLock<HashMap<[u8; 32], Arc<Module>>>;
+
+/// Policy for cleaning up the on-disk module cache
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheGcPolicy {
+    /// Artifacts not used for longer than this are deleted
+    pub max_age: Duration,
+    /// Upper bound for the total size of the cache directory
+    pub max_total_bytes: u64,
+}
+
+/// Summary of a disk cache cleanup
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// Number of files removed
+    pub files_removed: usize,
+    /// Total size of the removed files in bytes
+    pub bytes_removed: u64,
+}
+
+/// A compiled module in the on-disk cache
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheEntry {
+    /// Key the module is cached under
+    pub key: [u8; 32],
+    /// Path of the artifact, in the sharded or the legacy flat layout
+    pub path: PathBuf,
+    /// Size of the artifact in bytes
+    pub size: u64,
+    /// Last use of the artifact, its mtime
+    pub last_used: SystemTime,
+    /// Compiler the artifact was produced by, `None` for artifacts written
+    /// before compilers were recorded
+    pub compiler: Option<String>,
+    /// Capabilities of the engine that wrote the artifact, `None` for
+    /// artifacts written before they were recorded or by a cache used
+    /// without an engine
+    pub capabilities: Option<EngineCapabilities>,
+}
+
+/// Name of the compiler wasmer's default engine uses in this build
+#[cfg(feature = "wasmer_sys_dev")]
+macro_rules! compiler_name {
+    () => {
+        "cranelift"
+    };
+}
+
+#[cfg(all(feature = "wasmer_sys_prod", not(feature = "wasmer_sys_dev")))]
+macro_rules! compiler_name {
+    () => {
+        "llvm"
+    };
+}
+
+#[cfg(not(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod")))]
+macro_rules! compiler_name {
+    () => {
+        "wasmer"
+    };
+}
+
+/// Compiler of the artifacts this build writes to the disk cache
+///
+/// Artifacts recording a different compiler are recompiled rather than
+/// loaded.
+pub const ARTIFACT_COMPILER: &str = concat!(
+    compiler_name!(),
+    ", aingle_wasmer_host ",
+    env!("CARGO_PKG_VERSION")
+);
+
+/// Magic opening artifacts that record their compiler
+///
+/// It is followed by the length of the compiler name in one byte, the name
+/// and the serialized module.
+const ARTIFACT_MAGIC: &[u8; 8] = b"\0aimod\x01\0";
+
+/// Magic opening artifacts that also record the capabilities of the engine
+/// that wrote them
+///
+/// The compiler is recorded as after [`ARTIFACT_MAGIC`], followed by the
+/// length of the capabilities in two little-endian bytes, the capabilities
+/// as named MessagePack and the serialized module.
+const CAPABILITIES_MAGIC: &[u8; 8] = b"\0aimod\x02\0";
+
+/// Longest artifact header, read to list artifacts
+const MAX_HEADER_LEN: usize = 8 + 1 + u8::MAX as usize + 2 + u16::MAX as usize;
+
 /// Cache for compiled WASM modules
 ///
 /// Stores compiled modules in memory and optionally on disk for
-/// faster subsequent loads. Thread-safe for concurrent access.
+/// faster subsequent loads. Thread-safe for concurrent access: the in-memory
+/// cache is sharded by the first key byte, and concurrent requests for the
+/// same uncached key compile it only once.
 pub struct ModuleCache {
     /// In-memory cache of compiled modules
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
-    modules: RwLock<HashMap<[u8; 32], Arc<Module>>>,
+    #[cfg(feature = "wasmer")]
+    shards: [Shard; SHARDS],
+
+    /// Per-key locks held while a module is loaded or compiled
+    #[cfg(feature = "wasmer")]
+    in_flight: Mutex<HashMap<[u8; 32], Arc<Mutex<()>>>>,
+
+    /// Number of modules compiled from WASM bytes
+    #[cfg(feature = "wasmer")]
+    compiled: AtomicUsize,
 
     /// Optional filesystem cache directory
     cache_path: Option<PathBuf>,
 
     /// Wasmer engine for compilation
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    #[cfg(feature = "wasmer")]
     engine: Engine,
+
+    /// Compiler recorded in the artifacts this cache writes and loads
+    #[cfg(feature = "wasmer")]
+    compiler: String,
+
+    /// Capabilities of the engine using this cache, as recorded in the
+    /// artifacts it writes
+    #[cfg(feature = "wasmer")]
+    capabilities: OnceLock<Vec<u8>>,
 }
 
 impl ModuleCache {
-    /// Create a new module cache
+    /// Create a new module cache compiling with Wasmer's default engine
+    ///
+    /// The default engine meters nothing and has default tunables; the
+    /// cache of a [`WasmEngine`](crate::WasmEngine) compiles with its engine
+    /// instead, see [`ModuleCache::with_engine`].
     ///
     /// # Arguments
-    /// * `cache_path` - Optional filesystem path for persistent caching
+    /// * `cache_path` - Optional filesystem path for persistent caching,
+    ///   ignored on wasm32 targets, which cache in memory only
     pub fn new(cache_path: Option<PathBuf>) -> Self {
-        #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+        // Browsers have no filesystem to persist compiled modules in
+        #[cfg(target_arch = "wasm32")]
+        let cache_path = {
+            if let Some(path) = cache_path {
+                tracing::warn!(
+                    "Ignoring module cache path {} on wasm32, modules are cached in memory only",
+                    path.display()
+                );
+            }
+            None
+        };
+
+        #[cfg(feature = "wasmer")]
         {
             Self {
-                modules: RwLock::new(HashMap::new()),
+                shards: std::array::from_fn(|_| RwLock::new(HashMap::new())),
+                in_flight: Mutex::new(HashMap::new()),
+                compiled: AtomicUsize::new(0),
                 cache_path,
                 engine: Engine::default(),
+                compiler: ARTIFACT_COMPILER.to_string(),
+                capabilities: OnceLock::new(),
             }
         }
 
-        #[cfg(not(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod")))]
+        #[cfg(not(feature = "wasmer"))]
         {
             Self { cache_path }
         }
     }
 
+    /// Create a module cache compiling with `engine`
+    ///
+    /// `config_hash` identifies the settings compiled into the modules of
+    /// `engine`, as [`EngineConfig::artifact_hash`](crate::EngineConfig::artifact_hash)
+    /// does. It is recorded with the compiler in the artifacts written, and
+    /// artifacts recording another, or none, are recompiled rather than
+    /// loaded, so modules metered differently never mix in one directory.
+    #[cfg(feature = "wasmer")]
+    pub fn with_engine(cache_path: Option<PathBuf>, engine: Engine, config_hash: u32) -> Self {
+        Self {
+            engine,
+            compiler: format!("{}, config {:08x}", ARTIFACT_COMPILER, config_hash),
+            ..Self::new(cache_path)
+        }
+    }
+
     /// Get or compile a module
     ///
     /// If the module is cached (in memory or on disk), returns the cached version.
@@ -62,104 +218,355 @@ impl ModuleCache {
     /// # Returns
     /// * `Ok(Arc<Module>)` - The compiled module
     /// * `Err(HostError)` - If compilation fails
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    #[cfg(feature = "wasmer")]
     pub fn get(&self, key: [u8; 32], wasm_bytes: &[u8]) -> Result<Arc<Module>, HostError> {
         // Check in-memory cache first
+        if let Some(module) = self.cached(&key) {
+            return Ok(module);
+        }
+
+        // Only one thread loads or compiles a given key; the others wait here
+        // and then find it cached. No shard lock is held meanwhile.
+        let key_lock = self.in_flight.lock().entry(key).or_default().clone();
+        let _guard = key_lock.lock();
+
+        let result = self.cached(&key).map(Ok).unwrap_or_else(|| {
+            let module = self.load_or_compile(&key, wasm_bytes)?;
+            self.shard(&key).write().insert(key, Arc::clone(&module));
+            Ok(module)
+        });
+
+        // A getter that arrived after an earlier removal holds a lock of its
+        // own, which stays for the threads waiting on it
+        let mut in_flight = self.in_flight.lock();
+        if in_flight
+            .get(&key)
+            .is_some_and(|lock| Arc::ptr_eq(lock, &key_lock))
         {
-            let cache = self.modules.read();
-            if let Some(module) = cache.get(&key) {
-                return Ok(Arc::clone(module));
-            }
+            in_flight.remove(&key);
         }
+        result
+    }
+
+    /// Look up a module in the in-memory cache
+    #[cfg(feature = "wasmer")]
+    fn cached(&self, key: &[u8; 32]) -> Option<Arc<Module>> {
+        self.shard(key).read().get(key).cloned()
+    }
 
+    /// Load a module from the filesystem cache or compile it
+    #[cfg(feature = "wasmer")]
+    fn load_or_compile(&self, key: &[u8; 32], wasm_bytes: &[u8]) -> Result<Arc<Module>, HostError> {
         // Try to load from filesystem cache
-        if let Some(module) = self.load_from_disk(&key) {
-            let arc_module = Arc::new(module);
-            let mut cache = self.modules.write();
-            cache.insert(key, Arc::clone(&arc_module));
-            return Ok(arc_module);
+        if let Some(module) = self.load_from_disk(key) {
+            return Ok(Arc::new(module));
         }
 
         // Compile the module
         let module = Module::new(&self.engine, wasm_bytes)
             .map_err(|e| HostError::Compilation(format!("Failed to compile WASM: {}", e)))?;
+        self.compiled.fetch_add(1, Ordering::Relaxed);
 
         // Save to disk if path is configured
-        self.save_to_disk(&key, &module);
+        self.save_to_disk(key, &module);
 
-        // Cache in memory
-        let arc_module = Arc::new(module);
-        {
-            let mut cache = self.modules.write();
-            cache.insert(key, Arc::clone(&arc_module));
-        }
+        Ok(Arc::new(module))
+    }
 
-        Ok(arc_module)
+    #[cfg(feature = "wasmer")]
+    fn shard(&self, key: &[u8; 32]) -> &Shard {
+        &self.shards[key[0] as usize % SHARDS]
+    }
+
+    /// Record `capabilities` in the artifacts written from now on
+    ///
+    /// Only the first engine to record its capabilities is recorded.
+    #[cfg(feature = "wasmer")]
+    pub fn record_capabilities(&self, capabilities: &EngineCapabilities) {
+        if self.capabilities.get().is_some() {
+            return;
+        }
+        match rmp_serde::to_vec_named(capabilities) {
+            Ok(bytes) if bytes.len() <= u16::MAX as usize => {
+                let _ = self.capabilities.set(bytes);
+            }
+            Ok(_) => tracing::warn!("Engine capabilities too long to record"),
+            Err(e) => tracing::warn!("Failed to serialize engine capabilities: {}", e),
+        }
     }
 
     /// Load a module from the filesystem cache
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    ///
+    /// Artifacts of another compiler are left to be recompiled.
+    #[cfg(all(feature = "wasmer", not(target_arch = "wasm32")))]
     fn load_from_disk(&self, key: &[u8; 32]) -> Option<Module> {
+        let bytes = self.read_artifact(key)?;
+        let artifact = split_artifact(&bytes);
+        if !self.compiled_here(artifact.compiler) {
+            tracing::debug!(
+                "Recompiling cached module built by {}",
+                artifact.compiler.unwrap_or(ARTIFACT_COMPILER)
+            );
+            return None;
+        }
+
+        // Deserialize the module
+        // Note: This is unsafe as it loads pre-compiled code
+        unsafe { Module::deserialize(&self.engine, artifact.module).ok() }
+    }
+
+    /// Save a module to the filesystem cache
+    #[cfg(all(feature = "wasmer", not(target_arch = "wasm32")))]
+    fn save_to_disk(&self, key: &[u8; 32], module: &Module) {
+        let capabilities = self.capabilities.get().map(Vec::as_slice);
+        match module.serialize() {
+            Ok(bytes) => {
+                self.store_artifact(key, &with_header(&bytes, &self.compiler, capabilities))
+            }
+            Err(e) => {
+                tracing::warn!("Failed to serialize module: {}", e);
+            }
+        }
+    }
+
+    /// Whether an artifact recording `compiler` was written by a cache
+    /// compiling like this one
+    ///
+    /// Artifacts written before compilers were recorded are taken for the
+    /// default engine's.
+    #[cfg(feature = "wasmer")]
+    fn compiled_here(&self, compiler: Option<&str>) -> bool {
+        compiler.unwrap_or(ARTIFACT_COMPILER) == self.compiler
+    }
+
+    /// Compiler recorded in the artifacts this cache writes:
+    /// [`ARTIFACT_COMPILER`], followed by the configuration hash of caches
+    /// created with [`ModuleCache::with_engine`]
+    #[cfg(feature = "wasmer")]
+    pub fn compiler(&self) -> &str {
+        &self.compiler
+    }
+
+    /// Modules are not persisted on wasm32
+    #[cfg(all(feature = "wasmer", target_arch = "wasm32"))]
+    fn load_from_disk(&self, _key: &[u8; 32]) -> Option<Module> {
+        None
+    }
+
+    /// Modules are not persisted on wasm32
+    #[cfg(all(feature = "wasmer", target_arch = "wasm32"))]
+    fn save_to_disk(&self, _key: &[u8; 32], _module: &Module) {}
+
+    /// Path of the on-disk artifact for `key`, if a cache path is configured
+    ///
+    /// Artifacts are sharded by their first key byte, as
+    /// `<cache_path>/ab/cdef...` for a key starting with `0xab 0xcd 0xef`.
+    pub fn module_cache_file_path(&self, key: &[u8; 32]) -> Option<PathBuf> {
         let path = self.cache_path.as_ref()?;
-        let file_path = path.join(hex::encode(key));
+        Some(
+            path.join(hex::encode(&key[..1]))
+                .join(hex::encode(&key[1..])),
+        )
+    }
+
+    /// Path of an artifact in the old flat layout, `<cache_path>/abcdef...`
+    ///
+    /// Still read and migrated on access; to be removed in a future release.
+    fn legacy_file_path(&self, key: &[u8; 32]) -> Option<PathBuf> {
+        Some(self.cache_path.as_ref()?.join(hex::encode(key)))
+    }
+
+    /// Read the serialized artifact for `key`
+    ///
+    /// An artifact found in the legacy flat layout is moved into the sharded
+    /// layout first.
+    #[cfg(all(feature = "wasmer", not(target_arch = "wasm32")))]
+    fn read_artifact(&self, key: &[u8; 32]) -> Option<Vec<u8>> {
+        let file_path = self.module_cache_file_path(key)?;
 
         if !file_path.exists() {
-            return None;
+            let legacy_path = self.legacy_file_path(key)?;
+            if !legacy_path.exists() {
+                return None;
+            }
+            migrate_artifact(&legacy_path, &file_path)?;
         }
 
         // Try to load the serialized module
         let bytes = std::fs::read(&file_path).ok()?;
 
-        // Deserialize the module
-        // Note: This is unsafe as it loads pre-compiled code
-        unsafe { Module::deserialize(&self.engine, &bytes).ok() }
+        // Record the hit so disk cleanup treats the artifact as recently used
+        touch(&file_path);
+
+        Some(bytes)
     }
 
-    /// Save a module to the filesystem cache
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
-    fn save_to_disk(&self, key: &[u8; 32], module: &Module) {
-        let Some(path) = self.cache_path.as_ref() else {
+    /// Write the serialized artifact for `key`
+    #[cfg(all(feature = "wasmer", not(target_arch = "wasm32")))]
+    fn store_artifact(&self, key: &[u8; 32], bytes: &[u8]) {
+        let Some(file_path) = self.module_cache_file_path(key) else {
             return;
         };
 
-        // Create cache directory if needed
-        if let Err(e) = std::fs::create_dir_all(path) {
-            tracing::warn!("Failed to create cache directory: {}", e);
-            return;
+        // Create the cache and shard directories if needed
+        if let Some(shard_dir) = file_path.parent() {
+            if let Err(e) = std::fs::create_dir_all(shard_dir) {
+                tracing::warn!("Failed to create cache directory: {}", e);
+                return;
+            }
         }
 
-        let file_path = path.join(hex::encode(key));
+        if let Err(e) = std::fs::write(&file_path, bytes) {
+            tracing::warn!("Failed to write module to cache: {}", e);
+        }
+    }
 
-        // Serialize and save
-        match module.serialize() {
-            Ok(bytes) => {
-                if let Err(e) = std::fs::write(&file_path, bytes) {
-                    tracing::warn!("Failed to write module to cache: {}", e);
+    /// Clean up the on-disk cache
+    ///
+    /// Deletes artifacts whose last use (file mtime) is older than `max_age`,
+    /// then removes least-recently-used artifacts until the directory holds
+    /// at most `max_total_bytes`. Both the sharded and the legacy flat layout
+    /// are covered. The in-memory cache is left untouched.
+    pub fn gc_disk(&self, max_age: Duration, max_total_bytes: u64) -> Result<GcReport, HostError> {
+        let mut report = GcReport::default();
+        let Some(path) = self.cache_path.as_ref() else {
+            return Ok(report);
+        };
+
+        let now = SystemTime::now();
+        let mut files = Vec::new();
+
+        for (file_path, metadata) in artifact_files(path)? {
+            let last_used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            let age = now.duration_since(last_used).unwrap_or_default();
+
+            if age > max_age {
+                if remove_artifact(&file_path) {
+                    report.files_removed += 1;
+                    report.bytes_removed += metadata.len();
                 }
+            } else {
+                files.push((last_used, metadata.len(), file_path));
             }
-            Err(e) => {
-                tracing::warn!("Failed to serialize module: {}", e);
+        }
+
+        // Evict least-recently-used artifacts until under the size budget
+        let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+        files.sort_by_key(|(last_used, _, _)| *last_used);
+
+        for (_, len, file_path) in files {
+            if total <= max_total_bytes {
+                break;
+            }
+            if remove_artifact(&file_path) {
+                report.files_removed += 1;
+                report.bytes_removed += len;
+                total -= len;
             }
         }
+
+        Ok(report)
+    }
+
+    /// The modules in the on-disk cache, ordered by key
+    ///
+    /// Both the sharded and the legacy flat layout are listed; files whose
+    /// names are not module keys are skipped. Reading the list does not
+    /// count as a use of the artifacts.
+    pub fn disk_entries(&self) -> Result<Vec<CacheEntry>, HostError> {
+        let Some(path) = self.cache_path.as_ref() else {
+            return Ok(Vec::new());
+        };
+
+        let mut entries: Vec<CacheEntry> = artifact_files(path)?
+            .into_iter()
+            .filter_map(|(file_path, metadata)| {
+                let key = key_of(path, &file_path)?;
+                let (compiler, capabilities) = read_header(&file_path);
+                Some(CacheEntry {
+                    key,
+                    size: metadata.len(),
+                    last_used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                    compiler,
+                    capabilities,
+                    path: file_path,
+                })
+            })
+            .collect();
+        entries.sort_by_key(|entry| entry.key);
+        Ok(entries)
+    }
+
+    /// Check that the artifact of `entry` loads with this cache's engine
+    ///
+    /// Fails with [`HostError::Cache`] if the artifact cannot be read, was
+    /// produced by another compiler than [`ModuleCache::compiler`] or does
+    /// not deserialize. Like [`ModuleCache::disk_entries`], it does not count as
+    /// a use of the artifact.
+    #[cfg(all(feature = "wasmer", not(target_arch = "wasm32")))]
+    pub fn verify_artifact(&self, entry: &CacheEntry) -> Result<(), HostError> {
+        let bytes = std::fs::read(&entry.path).map_err(|e| {
+            HostError::Cache(format!("Failed to read {}: {}", entry.path.display(), e))
+        })?;
+        let artifact = split_artifact(&bytes);
+        if !self.compiled_here(artifact.compiler) {
+            return Err(HostError::Cache(format!(
+                "compiled by {}, this cache uses {}",
+                artifact.compiler.unwrap_or(ARTIFACT_COMPILER),
+                self.compiler
+            )));
+        }
+
+        unsafe { Module::deserialize(&self.engine, artifact.module) }
+            .map(drop)
+            .map_err(|e| HostError::Cache(format!("Failed to deserialize module: {}", e)))
+    }
+
+    /// Remove the module cached under `key`, from memory and disk
+    ///
+    /// Returns whether an artifact was deleted from disk.
+    pub fn remove(&self, key: &[u8; 32]) -> Result<bool, HostError> {
+        #[cfg(feature = "wasmer")]
+        self.shard(key).write().remove(key);
+
+        let mut removed = false;
+        for file_path in [self.module_cache_file_path(key), self.legacy_file_path(key)]
+            .into_iter()
+            .flatten()
+        {
+            match std::fs::remove_file(&file_path) {
+                Ok(()) => removed = true,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(HostError::Cache(format!(
+                        "Failed to remove {}: {}",
+                        file_path.display(),
+                        e
+                    )))
+                }
+            }
+        }
+        Ok(removed)
     }
 
     /// Clear the in-memory cache
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    #[cfg(feature = "wasmer")]
     pub fn clear(&self) {
-        let mut cache = self.modules.write();
-        cache.clear();
+        for shard in &self.shards {
+            shard.write().clear();
+        }
     }
 
     /// Get the number of cached modules
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    #[cfg(feature = "wasmer")]
     pub fn len(&self) -> usize {
-        self.modules.read().len()
+        self.shards.iter().map(|shard| shard.read().len()).sum()
     }
 
     /// Check if cache is empty
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    #[cfg(feature = "wasmer")]
     pub fn is_empty(&self) -> bool {
-        self.modules.read().is_empty()
+        self.shards.iter().all(|shard| shard.read().is_empty())
     }
 
     /// Get the cache path
@@ -172,7 +579,7 @@ impl ModuleCache {
     /// This is necessary to create a Store that is compatible with
     /// the compiled modules. In Wasmer 6.0+, modules must be instantiated
     /// with a Store that uses the same Engine that compiled them.
-    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    #[cfg(feature = "wasmer")]
     pub fn engine(&self) -> &Engine {
         &self.engine
     }
@@ -184,11 +591,214 @@ impl Default for ModuleCache {
     }
 }
 
-/// Helper to convert bytes to hex string
+/// The files in a cache directory with their metadata
+///
+/// Shard directories hold one level of artifacts; legacy artifacts sit
+/// directly in the cache directory. A missing directory holds none.
+fn artifact_files(path: &Path) -> Result<Vec<(PathBuf, std::fs::Metadata)>, HostError> {
+    let entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(HostError::Cache(format!(
+                "Failed to read cache directory: {}",
+                e
+            )))
+        }
+    };
+
+    let mut artifacts = Vec::new();
+    for entry in entries.flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_file() {
+            artifacts.push((entry.path(), metadata));
+        } else if metadata.is_dir() {
+            let Ok(shard) = std::fs::read_dir(entry.path()) else {
+                continue;
+            };
+            for entry in shard.flatten() {
+                if let Ok(metadata) = entry.metadata() {
+                    if metadata.is_file() {
+                        artifacts.push((entry.path(), metadata));
+                    }
+                }
+            }
+        }
+    }
+    Ok(artifacts)
+}
+
+/// The key of the artifact at `file_path` in the cache at `cache_path`,
+/// from its shard directory and file name or its legacy flat name
+fn key_of(cache_path: &Path, file_path: &Path) -> Option<[u8; 32]> {
+    let relative = file_path.strip_prefix(cache_path).ok()?;
+    let name: String = relative
+        .iter()
+        .map(|part| part.to_str())
+        .collect::<Option<Vec<_>>>()?
+        .concat();
+    hex::decode(&name)?.try_into().ok()
+}
+
+/// The compiler and engine capabilities recorded in the artifact at
+/// `path`, reading only its header
+fn read_header(path: &Path) -> (Option<String>, Option<EngineCapabilities>) {
+    use std::io::Read;
+
+    let mut header = Vec::new();
+    let read = std::fs::File::open(path)
+        .and_then(|file| file.take(MAX_HEADER_LEN as u64).read_to_end(&mut header));
+    if read.is_err() {
+        return (None, None);
+    }
+
+    let artifact = split_artifact(&header);
+    (
+        artifact.compiler.map(str::to_string),
+        artifact
+            .capabilities
+            .and_then(|bytes| rmp_serde::from_slice(bytes).ok()),
+    )
+}
+
+/// An artifact split into its header and the serialized module
+#[derive(Debug, PartialEq, Eq)]
+struct Artifact<'a> {
+    /// Compiler recorded in the header
+    compiler: Option<&'a str>,
+    /// Engine capabilities recorded in the header, as named MessagePack
+    capabilities: Option<&'a [u8]>,
+    /// The serialized module
+    module: &'a [u8],
+}
+
+/// Split an artifact into the compiler and capabilities it records and the
+/// serialized module
+///
+/// Artifacts written before compilers were recorded are all module.
+fn split_artifact(bytes: &[u8]) -> Artifact<'_> {
+    fn compiler(rest: &[u8]) -> Option<(&str, &[u8])> {
+        let (&len, rest) = rest.split_first()?;
+        let (name, rest) = rest.split_at_checked(len as usize)?;
+        Some((std::str::from_utf8(name).ok()?, rest))
+    }
+
+    fn capabilities(rest: &[u8]) -> Option<(&[u8], &[u8])> {
+        let (len, rest) = rest.split_first_chunk::<2>()?;
+        rest.split_at_checked(u16::from_le_bytes(*len) as usize)
+    }
+
+    let recorded = if let Some(rest) = bytes.strip_prefix(ARTIFACT_MAGIC) {
+        compiler(rest).map(|(compiler, module)| (compiler, None, module))
+    } else if let Some(rest) = bytes.strip_prefix(CAPABILITIES_MAGIC) {
+        compiler(rest).and_then(|(compiler, rest)| {
+            let (capabilities, module) = capabilities(rest)?;
+            Some((compiler, Some(capabilities), module))
+        })
+    } else {
+        None
+    };
+
+    match recorded {
+        Some((compiler, capabilities, module)) => Artifact {
+            compiler: Some(compiler),
+            capabilities,
+            module,
+        },
+        None => Artifact {
+            compiler: None,
+            capabilities: None,
+            module: bytes,
+        },
+    }
+}
+
+/// Prefix a serialized module with `compiler` and, if recorded, the
+/// capabilities of the engine
+#[cfg(all(feature = "wasmer", not(target_arch = "wasm32")))]
+fn with_header(module: &[u8], compiler: &str, capabilities: Option<&[u8]>) -> Vec<u8> {
+    let capabilities_len = capabilities.map_or(0, |bytes| 2 + bytes.len());
+    let mut artifact = Vec::with_capacity(
+        ARTIFACT_MAGIC.len() + 1 + compiler.len() + capabilities_len + module.len(),
+    );
+    artifact.extend_from_slice(if capabilities.is_some() {
+        CAPABILITIES_MAGIC
+    } else {
+        ARTIFACT_MAGIC
+    });
+    artifact.push(compiler.len() as u8);
+    artifact.extend_from_slice(compiler.as_bytes());
+    if let Some(bytes) = capabilities {
+        artifact.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+        artifact.extend_from_slice(bytes);
+    }
+    artifact.extend_from_slice(module);
+    artifact
+}
+
+/// Move a legacy flat-layout artifact to its sharded location
+#[cfg(all(feature = "wasmer", not(target_arch = "wasm32")))]
+fn migrate_artifact(legacy_path: &Path, file_path: &Path) -> Option<()> {
+    let result = file_path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| std::fs::rename(legacy_path, file_path));
+
+    match result {
+        Ok(()) => Some(()),
+        Err(e) => {
+            tracing::warn!(
+                "Failed to migrate cached module {}: {}",
+                legacy_path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Bump the mtime of a cache artifact to now
+#[cfg(all(feature = "wasmer", not(target_arch = "wasm32")))]
+fn touch(path: &Path) {
+    let result = std::fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(SystemTime::now()));
+
+    if let Err(e) = result {
+        tracing::debug!("Failed to update cache artifact mtime: {}", e);
+    }
+}
+
+/// Remove a cache artifact, returning whether it was deleted
+fn remove_artifact(path: &Path) -> bool {
+    match std::fs::remove_file(path) {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::warn!("Failed to remove cached module {}: {}", path.display(), e);
+            false
+        }
+    }
+}
+
+/// Helpers to convert between bytes and hex strings
 mod hex {
     pub fn encode(bytes: &[u8]) -> String {
         bytes.iter().map(|b| format!("{:02x}", b)).collect()
     }
+
+    /// Bytes of an even-length hex string, `None` if it is not one
+    pub fn decode(text: &str) -> Option<Vec<u8>> {
+        if !text.len().is_multiple_of(2) || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -208,8 +818,358 @@ mod tests {
         assert_eq!(cache.cache_path(), Some(&path));
     }
 
+    /// Write a cache artifact with a backdated mtime
+    fn write_artifact(dir: &Path, name: &str, len: usize, age: Duration) {
+        let path = dir.join(name);
+        std::fs::write(&path, vec![0u8; len]).unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    #[test]
+    fn test_gc_disk_removes_expired() {
+        let dir = tempfile::tempdir().unwrap();
+        write_artifact(dir.path(), "old", 100, Duration::from_secs(7200));
+        write_artifact(dir.path(), "fresh", 100, Duration::from_secs(10));
+
+        let cache = ModuleCache::new(Some(dir.path().to_path_buf()));
+        let report = cache.gc_disk(Duration::from_secs(3600), u64::MAX).unwrap();
+
+        assert_eq!(report.files_removed, 1);
+        assert_eq!(report.bytes_removed, 100);
+        assert!(!dir.path().join("old").exists());
+        assert!(dir.path().join("fresh").exists());
+    }
+
+    #[test]
+    fn test_gc_disk_enforces_size_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        write_artifact(dir.path(), "a", 100, Duration::from_secs(300));
+        write_artifact(dir.path(), "b", 100, Duration::from_secs(200));
+        write_artifact(dir.path(), "c", 100, Duration::from_secs(100));
+
+        let cache = ModuleCache::new(Some(dir.path().to_path_buf()));
+        let report = cache.gc_disk(Duration::from_secs(3600), 150).unwrap();
+
+        // The two least recently used artifacts go first
+        assert_eq!(report.files_removed, 2);
+        assert_eq!(report.bytes_removed, 200);
+        assert!(!dir.path().join("a").exists());
+        assert!(!dir.path().join("b").exists());
+        assert!(dir.path().join("c").exists());
+    }
+
+    #[test]
+    fn test_gc_disk_covers_shards() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("ab")).unwrap();
+        write_artifact(&dir.path().join("ab"), "cd", 100, Duration::from_secs(7200));
+        write_artifact(dir.path(), "legacy", 100, Duration::from_secs(7200));
+
+        let cache = ModuleCache::new(Some(dir.path().to_path_buf()));
+        let report = cache.gc_disk(Duration::from_secs(3600), u64::MAX).unwrap();
+
+        assert_eq!(report.files_removed, 2);
+        assert!(!dir.path().join("ab").join("cd").exists());
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_sharded_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ModuleCache::new(Some(dir.path().to_path_buf()));
+        let mut key = [0x11u8; 32];
+        key[0] = 0xab;
+
+        let file_path = cache.module_cache_file_path(&key).unwrap();
+        assert_eq!(file_path, dir.path().join("ab").join("11".repeat(31)));
+
+        cache.store_artifact(&key, b"artifact");
+        assert!(file_path.exists());
+        assert_eq!(cache.read_artifact(&key).unwrap(), b"artifact");
+        assert!(cache.read_artifact(&[0u8; 32]).is_none());
+
+        assert!(ModuleCache::new(None)
+            .module_cache_file_path(&key)
+            .is_none());
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_legacy_layout_migrated() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ModuleCache::new(Some(dir.path().to_path_buf()));
+        let key = [0xcdu8; 32];
+
+        let legacy_path = dir.path().join(hex::encode(&key));
+        std::fs::write(&legacy_path, b"old artifact").unwrap();
+
+        assert_eq!(cache.read_artifact(&key).unwrap(), b"old artifact");
+        assert!(!legacy_path.exists());
+        assert!(cache.module_cache_file_path(&key).unwrap().exists());
+
+        // Later reads hit the sharded layout directly
+        assert_eq!(cache.read_artifact(&key).unwrap(), b"old artifact");
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_artifacts_kept_apart_by_config_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let wasm = wat::parse_str("(module (func (export \"id\")))").unwrap();
+        let key = [9; 32];
+        let cache =
+            |hash| ModuleCache::with_engine(Some(dir.path().into()), Engine::default(), hash);
+
+        let first = cache(1);
+        first.get(key, &wasm).unwrap();
+        assert_eq!(first.compiled.load(Ordering::Relaxed), 1);
+        let entry = &first.disk_entries().unwrap()[0];
+        assert_eq!(entry.compiler.as_deref(), Some(first.compiler()));
+        first.verify_artifact(entry).unwrap();
+
+        // Another configuration recompiles, and the same loads
+        let second = cache(2);
+        assert!(second.verify_artifact(entry).is_err());
+        second.get(key, &wasm).unwrap();
+        assert_eq!(second.compiled.load(Ordering::Relaxed), 1);
+        let third = cache(2);
+        third.get(key, &wasm).unwrap();
+        assert_eq!(third.compiled.load(Ordering::Relaxed), 0);
+
+        // Neither loads artifacts of the default engine
+        let default = ModuleCache::new(Some(dir.path().into()));
+        default.get(key, &wasm).unwrap();
+        assert_eq!(default.compiled.load(Ordering::Relaxed), 1);
+        assert_eq!(default.compiler(), ARTIFACT_COMPILER);
+        let after_default = cache(2);
+        after_default.get(key, &wasm).unwrap();
+        assert_eq!(after_default.compiled.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_gc_disk_without_path() {
+        let cache = ModuleCache::new(None);
+        let report = cache.gc_disk(Duration::ZERO, 0).unwrap();
+        assert_eq!(report, GcReport::default());
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_concurrent_get_compiles_once_per_key() {
+        let cache = ModuleCache::new(None);
+        let modules: Vec<([u8; 32], Vec<u8>)> = (0..4u8)
+            .map(|i| {
+                let wat = format!(
+                    "(module (func (export \"id\") (result i32) (i32.const {})))",
+                    i
+                );
+                ([i; 32], wat::parse_str(wat).unwrap())
+            })
+            .collect();
+
+        std::thread::scope(|scope| {
+            for t in 0..16 {
+                let (cache, modules) = (&cache, &modules);
+                scope.spawn(move || {
+                    for i in 0..modules.len() {
+                        let (key, wasm) = &modules[(t + i) % modules.len()];
+                        cache.get(*key, wasm).unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(cache.compiled.load(Ordering::Relaxed), 4);
+        assert_eq!(cache.len(), 4);
+        assert!(cache.in_flight.lock().is_empty());
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_concurrent_get_failing_compile() {
+        let cache = ModuleCache::new(None);
+        let key = [9; 32];
+
+        std::thread::scope(|scope| {
+            for _ in 0..16 {
+                let cache = &cache;
+                scope.spawn(move || {
+                    for _ in 0..8 {
+                        assert!(matches!(
+                            cache.get(key, b"not wasm"),
+                            Err(HostError::Compilation(_))
+                        ));
+                    }
+                });
+            }
+        });
+
+        assert!(cache.is_empty());
+        assert!(cache.in_flight.lock().is_empty());
+
+        // The key compiles once it has valid bytes
+        let wasm = wat::parse_str("(module)").unwrap();
+        cache.get(key, &wasm).unwrap();
+        assert_eq!(cache.compiled.load(Ordering::Relaxed), 1);
+        assert!(cache.in_flight.lock().is_empty());
+    }
+
     #[test]
     fn test_hex_encode() {
         assert_eq!(hex::encode(&[0xde, 0xad, 0xbe, 0xef]), "deadbeef");
+        assert_eq!(hex::decode("DEADbeef"), Some(vec![0xde, 0xad, 0xbe, 0xef]));
+        assert_eq!(hex::decode("abc"), None);
+        assert_eq!(hex::decode("+f"), None);
+    }
+
+    /// An artifact recording `compiler`
+    fn recorded(compiler: &str, module: &[u8]) -> Vec<u8> {
+        let mut artifact = ARTIFACT_MAGIC.to_vec();
+        artifact.push(compiler.len() as u8);
+        artifact.extend_from_slice(compiler.as_bytes());
+        artifact.extend_from_slice(module);
+        artifact
+    }
+
+    /// An artifact recording `compiler` and `capabilities`
+    fn recorded_with(compiler: &str, capabilities: &[u8], module: &[u8]) -> Vec<u8> {
+        let mut artifact = CAPABILITIES_MAGIC.to_vec();
+        artifact.push(compiler.len() as u8);
+        artifact.extend_from_slice(compiler.as_bytes());
+        artifact.extend_from_slice(&(capabilities.len() as u16).to_le_bytes());
+        artifact.extend_from_slice(capabilities);
+        artifact.extend_from_slice(module);
+        artifact
+    }
+
+    fn capabilities() -> EngineCapabilities {
+        EngineCapabilities {
+            backend: "wasmer".to_string(),
+            compiler: crate::CompilerKind::Llvm,
+            wasmer_version: Some("6.0.0".to_string()),
+            host_version: "0.1.0".to_string(),
+            metering: true,
+            metering_limit: 1234,
+            canonicalize_nans: true,
+            static_memory_bound: 0x4000,
+            features: crate::WasmFeatures::default(),
+        }
+    }
+
+    #[test]
+    fn test_split_artifact() {
+        let artifact = recorded("cranelift, test", b"module");
+        assert_eq!(
+            split_artifact(&artifact),
+            Artifact {
+                compiler: Some("cranelift, test"),
+                capabilities: None,
+                module: b"module",
+            }
+        );
+        let unrecorded = Artifact {
+            compiler: None,
+            capabilities: None,
+            module: b"old module",
+        };
+        assert_eq!(split_artifact(b"old module"), unrecorded);
+
+        // A header cut short reads as an unrecorded artifact
+        let short = &artifact[..ARTIFACT_MAGIC.len() + 4];
+        assert_eq!(split_artifact(short).module, short);
+        assert_eq!(split_artifact(short).compiler, None);
+
+        let artifact = recorded_with("cranelift, test", b"caps", b"module");
+        assert_eq!(
+            split_artifact(&artifact),
+            Artifact {
+                compiler: Some("cranelift, test"),
+                capabilities: Some(b"caps"),
+                module: b"module",
+            }
+        );
+        let short = &artifact[..artifact.len() - b"capsmodule".len() + 2];
+        assert_eq!(split_artifact(short).capabilities, None);
+        assert_eq!(split_artifact(short).module, short);
+    }
+
+    #[test]
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn test_with_header() {
+        let module = b"module";
+        assert_eq!(
+            with_header(module, ARTIFACT_COMPILER, None),
+            recorded(ARTIFACT_COMPILER, module)
+        );
+
+        let cache = ModuleCache::new(None);
+        cache.record_capabilities(&capabilities());
+        let recorded_caps = cache.capabilities.get().unwrap();
+        let artifact = with_header(module, ARTIFACT_COMPILER, Some(recorded_caps));
+        assert_eq!(
+            artifact,
+            recorded_with(ARTIFACT_COMPILER, recorded_caps, module)
+        );
+
+        // The first engine recording its capabilities stays recorded
+        cache.record_capabilities(&EngineCapabilities {
+            metering_limit: 1,
+            ..capabilities()
+        });
+        assert_eq!(cache.capabilities.get().unwrap(), recorded_caps);
+    }
+
+    #[test]
+    fn test_disk_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ModuleCache::new(Some(dir.path().to_path_buf()));
+        let (sharded, legacy) = ([0xabu8; 32], [0x01u8; 32]);
+
+        let sharded_path = cache.module_cache_file_path(&sharded).unwrap();
+        std::fs::create_dir_all(sharded_path.parent().unwrap()).unwrap();
+        std::fs::write(&sharded_path, recorded("llvm, test", b"module")).unwrap();
+        std::fs::write(dir.path().join(hex::encode(&legacy)), b"old module").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), b"not a module").unwrap();
+        let attributed = [0xcdu8; 32];
+        let attributed_path = cache.module_cache_file_path(&attributed).unwrap();
+        std::fs::create_dir_all(attributed_path.parent().unwrap()).unwrap();
+        let recorded_caps = rmp_serde::to_vec_named(&capabilities()).unwrap();
+        std::fs::write(
+            &attributed_path,
+            recorded_with("llvm, test", &recorded_caps, b"module"),
+        )
+        .unwrap();
+
+        let entries = cache.disk_entries().unwrap();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| (entry.key, entry.size, entry.compiler.as_deref()))
+                .collect::<Vec<_>>(),
+            [
+                (legacy, 10, None),
+                (sharded, 25, Some("llvm, test")),
+                (
+                    attributed,
+                    27 + recorded_caps.len() as u64,
+                    Some("llvm, test")
+                )
+            ]
+        );
+        assert_eq!(entries[1].path, sharded_path);
+        assert_eq!(entries[1].capabilities, None);
+        assert_eq!(entries[2].capabilities, Some(capabilities()));
+
+        assert!(cache.remove(&sharded).unwrap());
+        assert!(cache.remove(&legacy).unwrap());
+        assert!(cache.remove(&attributed).unwrap());
+        assert!(!cache.remove(&legacy).unwrap());
+        assert!(cache.disk_entries().unwrap().is_empty());
+
+        assert!(ModuleCache::new(None).disk_entries().unwrap().is_empty());
     }
 }
@@ -5,46 +5,98 @@
 
 pub use crate::{
     build_guest_result,
+    check_protocol,
     consume_bytes_from_guest,
+    consume_bytes_into,
+    consume_with,
+    guest_error_message,
     move_data_to_guest,
+    // Backend
+    BackendModule,
+    // Execution
+    CallExecutor,
+    CallObserver,
+    CallOptions,
+    CallReport,
     EngineConfig,
     // Cache (legacy)
     // ModuleCache from cache module - using module::ModuleCache instead
     // Environment
     Env,
+    EnvWith,
+    ExecutorConfig,
+    ExternEncoding,
+    ExternInfo,
+    // Failure quarantine
+    FailurePolicy,
+    FailureTracker,
     // Guest utilities
-    // Note: ExternIO intentionally NOT exported to avoid conflict with aingle_zome_types::ExternIO
     GuestPtr,
     // Errors
     HostError,
+    // Host functions
+    HostFunctionRegistry,
+    // Metering
+    LedgerEntry,
     Len,
+    MeteringLedger,
+    // Introspection
+    ModuleIntrospection,
+    // Guest randomness
+    RandomSource,
+    // Streaming
+    StreamSink,
+    WasmBackend,
     // Engine
     WasmEngine,
     // Instance
     WasmInstance,
     // Constants
+    DEFAULT_MAX_INPUT_LEN,
+    DEFAULT_MAX_OUTPUT_LEN,
     DEFAULT_METERING_LIMIT,
 };
 
+// Renamed so glob imports next to aingle_zome_types' prelude do not conflict
+pub use crate::ExternIO as HostExternIO;
+
 // Module cache from the new module
-pub use crate::module::ModuleCache;
+pub use crate::module::{CacheGcPolicy, GcReport, ModuleCache};
 
 // Conditionally export call function when wasmer is enabled
-#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
-pub use crate::guest::call;
+#[cfg(feature = "wasmer")]
+pub use crate::guest::{call, call_fn, call_metered, call_with_options};
+#[cfg(feature = "wasmer")]
+pub use crate::{ImportBuilder, ProbeGuestAbi};
 
+#[allow(deprecated)]
+pub use aingle_wasmer_common::DoubleUSize;
 pub use aingle_wasmer_common::{
+    // Macros
+    guest_fn,
+    static_assert_wasm_safe,
+    ChecksumKind,
+    Compatibility,
     DeserializeError,
-    DoubleUSize,
     EnvelopeError,
     EnvelopeFlags,
     // Envelope
     EnvelopeHeader,
     ErrorKind,
+    ExternFlags,
+    ExternRecord,
+    // Handshake
+    GuestAbi,
     GuestCallError,
+    GuestCapability,
+    GuestFunction,
+    GuestReturn,
     HostCallError,
+    HostFunction,
     MemoryError,
+    ProtocolCompat,
     SerializeError,
+    VersionPolicy,
     WasmDecode,
     // Traits
     WasmEncode,
@@ -59,12 +111,14 @@ pub use aingle_wasmer_common::{
     WasmSlice,
     // Constants
     MAGIC,
+    MIN_SUPPORTED_PROTOCOL_VERSION,
     PROTOCOL_VERSION,
 };
 
 pub use aingle_wasmer_codec::{
-    compute_checksum, decode_envelope, decode_raw, encode_to_slice, encode_with_envelope,
-    verify_checksum, DecodedEnvelope, Decoder, Encoder,
+    compute_checksum, compute_checksum_with, decode_envelope, decode_envelope_with, decode_raw,
+    encode_envelope_with, encode_to_slice, encode_with_envelope, verify_checksum, DecodedEnvelope,
+    Decoder, Encoder,
 };
 
 // Re-export serde for user convenience
@@ -0,0 +1,257 @@
+//! Registry of typed host functions
+//!
+//! [`HostFunctionRegistry`] collects [`HostFunction`] implementations and
+//! serves them over the protocol of the guest's `host_call_enveloped`: the
+//! guest passes an envelope holding the serialized arguments, and gets back
+//! an envelope holding the serialized result, or an error envelope holding
+//! the serialized [`WasmError`]. With Wasmer the registry becomes `env`
+//! imports through [`ImportBuilder::functions`](crate::ImportBuilder::functions).
+
+use crate::backend::{read_enveloped, write_enveloped, write_guest_envelope, BackendMemory};
+use crate::HostError;
+use aingle_wasmer_common::{
+    ChecksumKind, EnvelopeFlags, ErrorKind, HostFunction, WasmError, WasmErrorInner, WasmResult,
+    WasmSlice,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::sync::Arc;
+
+/// A registered host function with its argument and result types erased
+pub(crate) type Dispatch = dyn Fn(&mut dyn BackendMemory, u32, u32) -> WasmResult + Send + Sync;
+
+/// Host functions callable by guests, by name
+///
+/// ```ignore
+/// struct Double;
+///
+/// impl HostFunction<u32, u64> for Double {
+///     const NAME: &'static str = "double";
+///
+///     fn call(&self, args: u32) -> Result<u64, WasmError> {
+///         Ok(u64::from(args) * 2)
+///     }
+/// }
+///
+/// let registry = HostFunctionRegistry::new().register(Double);
+/// let instance = ImportBuilder::new(&mut store)
+///     .functions(&mut store, &registry)
+///     .instantiate(&mut store, &module)?;
+/// ```
+#[derive(Clone, Default)]
+pub struct HostFunctionRegistry {
+    functions: Vec<(&'static str, Arc<Dispatch>)>,
+}
+
+impl HostFunctionRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `function` under [`HostFunction::NAME`], replacing any function
+    /// registered under the same name
+    pub fn register<F, Args, Ret>(mut self, function: F) -> Self
+    where
+        F: HostFunction<Args, Ret> + Send + Sync + 'static,
+        Args: DeserializeOwned,
+        Ret: Serialize,
+    {
+        let dispatch: Arc<Dispatch> =
+            Arc::new(move |memory, ptr, len| invoke(&function, memory, ptr, len));
+        match self.functions.iter_mut().find(|(name, _)| *name == F::NAME) {
+            Some(entry) => entry.1 = dispatch,
+            None => self.functions.push((F::NAME, dispatch)),
+        }
+        self
+    }
+
+    /// Names of the registered functions, in order of registration
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.functions.iter().map(|(name, _)| *name)
+    }
+
+    /// Whether a function is registered as `name`
+    pub fn contains(&self, name: &str) -> bool {
+        self.names().any(|registered| registered == name)
+    }
+
+    /// Call the function registered as `name` with the arguments envelope at
+    /// `ptr` and `len` in `memory`
+    ///
+    /// Returns the result to hand back to the guest, whose slice points at
+    /// the response envelope written to guest memory.
+    pub fn dispatch(
+        &self,
+        name: &str,
+        memory: &mut dyn BackendMemory,
+        ptr: u32,
+        len: u32,
+    ) -> Result<WasmResult, HostError> {
+        let (_, dispatch) = self
+            .functions
+            .iter()
+            .find(|(registered, _)| *registered == name)
+            .ok_or_else(|| HostError::FunctionNotFound(name.to_string()))?;
+        Ok(dispatch(memory, ptr, len))
+    }
+
+    #[cfg(feature = "wasmer")]
+    pub(crate) fn entries(&self) -> impl Iterator<Item = (&'static str, Arc<Dispatch>)> + '_ {
+        self.functions
+            .iter()
+            .map(|(name, dispatch)| (*name, dispatch.clone()))
+    }
+}
+
+impl std::fmt::Debug for HostFunctionRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+/// Decode the arguments, call `function` and write its response
+///
+/// A response that cannot be written to guest memory leaves an error result
+/// without payload, carrying the kind of the failure.
+fn invoke<F, Args, Ret>(
+    function: &F,
+    memory: &mut dyn BackendMemory,
+    ptr: u32,
+    len: u32,
+) -> WasmResult
+where
+    F: HostFunction<Args, Ret>,
+    Args: DeserializeOwned,
+    Ret: Serialize,
+{
+    let result = read_enveloped::<_, Args>(memory, ptr, len)
+        .map_err(|e| {
+            WasmError::GuestStructured(WasmErrorInner::new(
+                ErrorKind::Deserialization,
+                &format!("{}: {}", F::NAME, e),
+            ))
+        })
+        .and_then(|args| function.call(args));
+
+    match result {
+        Ok(ret) => match write_enveloped(memory, &ret) {
+            Ok(slice) => WasmResult::ok(slice),
+            Err(_) => WasmResult::err_with_kind(WasmSlice::empty(), ErrorKind::Serialization),
+        },
+        Err(error) => match write_error(memory, &error) {
+            Ok(slice) => WasmResult::err(slice),
+            Err(_) => WasmResult::err_with_kind(WasmSlice::empty(), error.kind()),
+        },
+    }
+}
+
+/// Write `error` to guest memory in an error envelope
+fn write_error(memory: &mut dyn BackendMemory, error: &WasmError) -> Result<WasmSlice, HostError> {
+    let payload = rmp_serde::to_vec_named(error)
+        .map_err(|e| HostError::Serialization(format!("host function error: {}", e)))?;
+    write_guest_envelope(
+        memory,
+        &payload,
+        EnvelopeFlags::IsError as u8,
+        ChecksumKind::Crc32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::tests::VecMemory;
+    use aingle_wasmer_codec::decode_envelope;
+
+    struct Double;
+
+    impl HostFunction<u32, u64> for Double {
+        const NAME: &'static str = "double";
+
+        fn call(&self, args: u32) -> Result<u64, WasmError> {
+            match args {
+                0 => Err(WasmError::GuestStructured(WasmErrorInner::new(
+                    ErrorKind::InvalidInput,
+                    "nothing to double",
+                ))),
+                args => Ok(u64::from(args) * 2),
+            }
+        }
+    }
+
+    struct Tripler;
+
+    impl HostFunction<u32, u64> for Tripler {
+        const NAME: &'static str = "double";
+
+        fn call(&self, args: u32) -> Result<u64, WasmError> {
+            Ok(u64::from(args) * 3)
+        }
+    }
+
+    /// Call `name` with `args` as the guest would, returning the result and
+    /// the response envelope
+    fn call(registry: &HostFunctionRegistry, name: &str, args: &[u8]) -> (WasmResult, Vec<u8>) {
+        let mut memory = VecMemory {
+            data: vec![0u8; 256],
+            next: 0,
+        };
+        let input = write_guest_envelope(&mut memory, args, 0, ChecksumKind::Crc32).unwrap();
+        let result = registry
+            .dispatch(name, &mut memory, input.ptr, input.len)
+            .unwrap();
+        let slice = result.slice();
+        let start = slice.ptr as usize;
+        let response = memory.data[start..start + slice.len as usize].to_vec();
+        (result, response)
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let registry = HostFunctionRegistry::new().register(Double);
+        assert!(registry.contains("double"));
+
+        let (result, response) = call(&registry, "double", &rmp_serde::to_vec(&21u32).unwrap());
+        assert!(result.is_ok());
+        let envelope = decode_envelope(&response).unwrap();
+        assert!(!envelope.header.is_error());
+        assert_eq!(rmp_serde::from_slice::<u64>(envelope.payload).unwrap(), 42);
+
+        // Errors of the function come back serialized in an error envelope
+        let (result, response) = call(&registry, "double", &rmp_serde::to_vec(&0u32).unwrap());
+        assert!(result.is_err());
+        let envelope = decode_envelope(&response).unwrap();
+        assert!(envelope.header.is_error());
+        let error: WasmError = rmp_serde::from_slice(envelope.payload).unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+
+        // So do arguments of the wrong type
+        let (result, response) = call(&registry, "double", &rmp_serde::to_vec("x").unwrap());
+        assert!(result.is_err());
+        let error: WasmError =
+            rmp_serde::from_slice(decode_envelope(&response).unwrap().payload).unwrap();
+        assert_eq!(error.kind(), ErrorKind::Deserialization);
+        assert!(error.message().starts_with("double: "), "{}", error);
+    }
+
+    #[test]
+    fn test_register_replaces() {
+        let registry = HostFunctionRegistry::new()
+            .register(Double)
+            .register(Tripler);
+        assert_eq!(registry.names().collect::<Vec<_>>(), ["double"]);
+
+        let (_, response) = call(&registry, "double", &rmp_serde::to_vec(&2u32).unwrap());
+        let envelope = decode_envelope(&response).unwrap();
+        assert_eq!(rmp_serde::from_slice::<u64>(envelope.payload).unwrap(), 6);
+
+        let mut memory = VecMemory {
+            data: vec![0u8; 16],
+            next: 0,
+        };
+        assert!(matches!(
+            registry.dispatch("triple", &mut memory, 0, 0),
+            Err(HostError::FunctionNotFound(name)) if name == "triple"
+        ));
+    }
+}
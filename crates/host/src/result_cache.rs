@@ -0,0 +1,356 @@
+//! Cache of the outputs of pure guest functions
+//!
+//! Validation functions are deterministic and read-only: the same input
+//! bytes always give the same output. Once the caller asserts as much with
+//! [`CallOptions::cacheable`](crate::CallOptions::cacheable), their outputs
+//! are kept in a [`ResultCache`] keyed by the module, the function and the
+//! BLAKE3 hash of the input, and later calls are answered without running
+//! the guest. Entries of one module are never served to another.
+
+use crate::ExternIO;
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Default byte budget of a [`ResultCache`]: 64 MiB
+pub const DEFAULT_RESULT_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Identity of a call: the module, the function and the hash of its input
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ResultKey {
+    module_key: [u8; 32],
+    function: String,
+    input: [u8; 32],
+}
+
+impl ResultKey {
+    /// The key of calling `function` of the module `module_key` with `input`
+    pub fn new(module_key: [u8; 32], function: &str, input: &[u8]) -> Self {
+        Self {
+            module_key,
+            function: function.to_string(),
+            input: *blake3::hash(input).as_bytes(),
+        }
+    }
+
+    /// Key of the module the call runs
+    pub fn module_key(&self) -> &[u8; 32] {
+        &self.module_key
+    }
+}
+
+/// Snapshot of the counters of a [`ResultCache`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResultCacheStats {
+    /// Calls answered from the cache
+    pub hits: u64,
+    /// Calls the cache could not answer
+    pub misses: u64,
+    /// Output bytes served from the cache instead of guests
+    pub bytes_saved: u64,
+    /// Entries dropped to stay within the byte budget
+    pub evictions: u64,
+    /// Entries held
+    pub entries: usize,
+    /// Bytes held, outputs and function names
+    pub bytes: usize,
+}
+
+/// A cached output with its size and last use
+struct Slot {
+    output: ExternIO,
+    bytes: usize,
+    used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    slots: HashMap<ResultKey, Slot>,
+    /// Keys by last use, least recent first
+    recency: BTreeMap<u64, ResultKey>,
+    bytes: usize,
+    /// Use counter ordering `recency`
+    tick: u64,
+    /// Modules neither read nor written
+    bypassed: HashSet<[u8; 32]>,
+}
+
+impl Entries {
+    fn touch(&mut self, key: &ResultKey) -> Option<ExternIO> {
+        self.tick += 1;
+        let tick = self.tick;
+        let slot = self.slots.get_mut(key)?;
+        let key = self.recency.remove(&slot.used)?;
+        slot.used = tick;
+        self.recency.insert(tick, key);
+        Some(slot.output.clone())
+    }
+
+    fn remove(&mut self, key: &ResultKey) {
+        if let Some(slot) = self.slots.remove(key) {
+            self.recency.remove(&slot.used);
+            self.bytes -= slot.bytes;
+        }
+    }
+
+    /// Drop the least recently used entries until `bytes` more fit in
+    /// `max_bytes`, returning how many were dropped
+    fn make_room(&mut self, bytes: usize, max_bytes: usize) -> u64 {
+        let mut evicted = 0;
+        while self.bytes + bytes > max_bytes {
+            let Some((_, key)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some(slot) = self.slots.remove(&key) {
+                self.bytes -= slot.bytes;
+            }
+            evicted += 1;
+        }
+        evicted
+    }
+}
+
+struct Shared {
+    entries: Mutex<Entries>,
+    max_bytes: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    bytes_saved: AtomicU64,
+    evictions: AtomicU64,
+}
+
+/// Outputs of pure guest functions, least recently used first out of a
+/// byte budget
+///
+/// Shared between clones, so instances of the same modules can use one
+/// cache. Only successful outputs are kept; the caller is responsible for
+/// caching functions whose output depends on their input bytes alone.
+///
+/// ```ignore
+/// let cache = ResultCache::new(16 * 1024 * 1024);
+/// let mut instance = WasmInstance::new(&engine, &module)?
+///     .with_module_key(module_key)
+///     .with_result_cache(cache.clone());
+/// let options = CallOptions {
+///     cacheable: Some(PurityAssertion::assert_pure()),
+///     ..CallOptions::default()
+/// };
+/// let output = instance.call_raw_with_options("validate", &op, &options)?;
+/// ```
+#[derive(Clone)]
+pub struct ResultCache {
+    shared: Arc<Shared>,
+}
+
+impl Default for ResultCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_RESULT_CACHE_BYTES)
+    }
+}
+
+impl std::fmt::Debug for ResultCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResultCache")
+            .field("max_bytes", &self.shared.max_bytes)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl ResultCache {
+    /// Create a cache holding up to `max_bytes` of outputs
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                entries: Mutex::new(Entries::default()),
+                max_bytes,
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                bytes_saved: AtomicU64::new(0),
+                evictions: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// The output cached for `key`, counted as a hit or a miss
+    ///
+    /// Calls of bypassed modules are neither.
+    pub fn get(&self, key: &ResultKey) -> Option<ExternIO> {
+        let mut entries = self.shared.entries.lock();
+        if entries.bypassed.contains(&key.module_key) {
+            return None;
+        }
+        let Some(output) = entries.touch(key) else {
+            self.shared.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        self.shared.hits.fetch_add(1, Ordering::Relaxed);
+        self.shared
+            .bytes_saved
+            .fetch_add(output.len() as u64, Ordering::Relaxed);
+        Some(output)
+    }
+
+    /// Cache `output` for `key`, evicting older entries as needed
+    ///
+    /// Outputs of bypassed modules and outputs larger than the whole budget
+    /// are not kept.
+    pub fn insert(&self, key: ResultKey, output: ExternIO) {
+        let bytes = output.len() + key.function.len();
+        if bytes > self.shared.max_bytes {
+            return;
+        }
+        let mut entries = self.shared.entries.lock();
+        if entries.bypassed.contains(&key.module_key) {
+            return;
+        }
+        entries.remove(&key);
+        let evicted = entries.make_room(bytes, self.shared.max_bytes);
+        self.shared.evictions.fetch_add(evicted, Ordering::Relaxed);
+
+        entries.tick += 1;
+        let used = entries.tick;
+        entries.recency.insert(used, key.clone());
+        entries.bytes += bytes;
+        entries.slots.insert(
+            key,
+            Slot {
+                output,
+                bytes,
+                used,
+            },
+        );
+    }
+
+    /// Drop the entries of the module `module_key`, after its code or the
+    /// state its functions read changed
+    pub fn invalidate(&self, module_key: &[u8; 32]) {
+        let mut entries = self.shared.entries.lock();
+        let keys: Vec<_> = entries
+            .slots
+            .keys()
+            .filter(|key| key.module_key == *module_key)
+            .cloned()
+            .collect();
+        for key in &keys {
+            entries.remove(key);
+        }
+    }
+
+    /// Stop or resume caching the calls of the module `module_key`
+    ///
+    /// Bypassing a module drops its entries.
+    pub fn set_bypass(&self, module_key: [u8; 32], bypass: bool) {
+        if bypass {
+            self.invalidate(&module_key);
+            self.shared.entries.lock().bypassed.insert(module_key);
+        } else {
+            self.shared.entries.lock().bypassed.remove(&module_key);
+        }
+    }
+
+    /// Drop every entry
+    pub fn clear(&self) {
+        let mut entries = self.shared.entries.lock();
+        entries.slots.clear();
+        entries.recency.clear();
+        entries.bytes = 0;
+    }
+
+    /// Byte budget of the cache
+    pub fn max_bytes(&self) -> usize {
+        self.shared.max_bytes
+    }
+
+    /// Snapshot the counters
+    pub fn stats(&self) -> ResultCacheStats {
+        let entries = self.shared.entries.lock();
+        ResultCacheStats {
+            hits: self.shared.hits.load(Ordering::Relaxed),
+            misses: self.shared.misses.load(Ordering::Relaxed),
+            bytes_saved: self.shared.bytes_saved.load(Ordering::Relaxed),
+            evictions: self.shared.evictions.load(Ordering::Relaxed),
+            entries: entries.slots.len(),
+            bytes: entries.bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(bytes: &[u8]) -> ExternIO {
+        ExternIO::new(bytes.to_vec())
+    }
+
+    #[test]
+    fn test_hit_and_miss() {
+        let cache = ResultCache::default();
+        let key = ResultKey::new([1; 32], "validate", b"op");
+        assert_eq!(cache.get(&key), None);
+        cache.insert(key.clone(), output(b"valid"));
+        assert_eq!(cache.get(&key), Some(output(b"valid")));
+
+        // One byte of input apart
+        assert_eq!(cache.get(&ResultKey::new([1; 32], "validate", b"oq")), None);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.bytes_saved), (1, 2, 5));
+        assert_eq!((stats.entries, stats.bytes), (1, 13));
+    }
+
+    #[test]
+    fn test_modules_kept_apart() {
+        let cache = ResultCache::default();
+        cache.insert(ResultKey::new([1; 32], "validate", b"op"), output(b"valid"));
+        assert_eq!(cache.get(&ResultKey::new([2; 32], "validate", b"op")), None);
+        assert_eq!(cache.get(&ResultKey::new([1; 32], "other", b"op")), None);
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        // Room for two entries of 8 + 1 bytes
+        let cache = ResultCache::new(20);
+        let key = |input: &[u8]| ResultKey::new([1; 32], "f", input);
+        cache.insert(key(b"a"), output(b"aaaaaaaa"));
+        cache.insert(key(b"b"), output(b"bbbbbbbb"));
+        cache.get(&key(b"a")).unwrap();
+        cache.insert(key(b"c"), output(b"cccccccc"));
+
+        assert!(cache.get(&key(b"a")).is_some());
+        assert!(cache.get(&key(b"b")).is_none());
+        assert!(cache.get(&key(b"c")).is_some());
+        let stats = cache.stats();
+        assert_eq!((stats.evictions, stats.entries, stats.bytes), (1, 2, 18));
+
+        // Outputs over the budget are not kept
+        cache.insert(key(b"d"), output(&[0; 20]));
+        assert!(cache.get(&key(b"d")).is_none());
+        assert_eq!(cache.stats().entries, 2);
+    }
+
+    #[test]
+    fn test_invalidate_and_bypass() {
+        let cache = ResultCache::default();
+        let first = ResultKey::new([1; 32], "f", b"x");
+        let second = ResultKey::new([2; 32], "f", b"x");
+        cache.insert(first.clone(), output(b"1"));
+        cache.insert(second.clone(), output(b"2"));
+
+        cache.invalidate(&[1; 32]);
+        assert!(cache.get(&first).is_none());
+        assert!(cache.get(&second).is_some());
+
+        cache.set_bypass([2; 32], true);
+        assert!(cache.get(&second).is_none());
+        cache.insert(second.clone(), output(b"2"));
+        assert_eq!(cache.stats().entries, 0);
+
+        cache.set_bypass([2; 32], false);
+        cache.insert(second.clone(), output(b"2"));
+        assert!(cache.get(&second).is_some());
+        cache.clear();
+        assert_eq!(cache.stats().bytes, 0);
+    }
+}
@@ -0,0 +1,506 @@
+//! Soak testing of long-running guest instances
+//!
+//! [`run_soak`] drives a module through many calls as a [`SoakProfile`]
+//! describes and returns a [`SoakReport`] tracing memory over the run along
+//! with failures by kind and call latencies. Reports serialize, so runs can
+//! be stored and compared over time.
+
+use crate::backend::BackendModule;
+use crate::{HostError, WasmEngine, WasmInstance};
+use parking_lot::{Condvar, Mutex};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// Size of a WASM memory page
+const WASM_PAGE_SIZE: u64 = 64 * 1024;
+
+/// Sizes of the payloads sent during a soak
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum PayloadSizes {
+    /// Every payload has this many bytes
+    Fixed(usize),
+    /// Sizes drawn uniformly between two bounds
+    Uniform {
+        /// Smallest size, inclusive
+        min: usize,
+        /// Largest size, inclusive
+        max: usize,
+    },
+    /// Sizes drawn from `(size, weight)` pairs
+    Weighted(Vec<(usize, u32)>),
+}
+
+impl PayloadSizes {
+    /// Size of the payload for a draw of `random`
+    fn pick(&self, random: u64) -> usize {
+        match self {
+            PayloadSizes::Fixed(size) => *size,
+            PayloadSizes::Uniform { min, max } => {
+                let span = max.saturating_sub(*min) as u64 + 1;
+                min + (random % span) as usize
+            }
+            PayloadSizes::Weighted(sizes) => {
+                let total: u64 = sizes.iter().map(|&(_, weight)| u64::from(weight)).sum();
+                if total == 0 {
+                    return 0;
+                }
+                let mut draw = random % total;
+                for &(size, weight) in sizes {
+                    if draw < u64::from(weight) {
+                        return size;
+                    }
+                    draw -= u64::from(weight);
+                }
+                unreachable!("draw is below the total weight")
+            }
+        }
+    }
+}
+
+/// Which instances the calls of a soak run on
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum InstanceMode {
+    /// A new instance for every call
+    Fresh,
+    /// One instance per worker, reused for all of its calls
+    Reuse,
+    /// Instances shared by all workers, taken for each call and put back
+    Pool(usize),
+}
+
+/// What a soak does
+///
+/// Reused and pooled instances are kept after failed calls, as long-lived
+/// conductors keep them, so failures that corrupt an instance show up in
+/// the calls after them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SoakProfile {
+    /// Export to call, following the `(ptr, len) -> u64` convention
+    pub function: String,
+    /// Number of calls in total
+    pub calls: usize,
+    /// Sizes of the payloads passed to the calls
+    pub payload: PayloadSizes,
+    /// Number of threads making calls
+    pub concurrency: usize,
+    /// Which instances the calls run on
+    pub instances: InstanceMode,
+    /// Take a memory sample after every this many calls
+    pub sample_every: usize,
+    /// Seed of the payload sizes and contents, so runs can be repeated
+    pub seed: u64,
+}
+
+impl SoakProfile {
+    /// Ten thousand 1 KiB calls of `function` on one reused instance
+    pub fn new(function: impl Into<String>) -> Self {
+        Self {
+            function: function.into(),
+            calls: 10_000,
+            payload: PayloadSizes::Fixed(1024),
+            concurrency: 1,
+            instances: InstanceMode::Reuse,
+            sample_every: 1_000,
+            seed: 0,
+        }
+    }
+}
+
+/// Memory of the process and a guest after some number of calls
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct SoakSample {
+    /// Calls completed when the sample was taken
+    pub calls: usize,
+    /// Milliseconds since the soak started
+    pub elapsed_ms: u64,
+    /// Resident set size of the process, where the platform reports it
+    pub rss_bytes: Option<u64>,
+    /// Pages of memory of the instance that made the sampled call
+    pub memory_pages: u64,
+    /// Bytes held by the guest arena, if the guest exports them
+    pub guest_allocated_bytes: Option<u32>,
+    /// Guest arena high-water mark, if the guest exports it
+    pub guest_high_water: Option<u32>,
+}
+
+/// Latency percentiles of the calls of a soak, in microseconds
+///
+/// Includes instantiating for [`InstanceMode::Fresh`] and waiting for an
+/// instance for [`InstanceMode::Pool`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct LatencyPercentiles {
+    /// Median
+    pub p50_us: u64,
+    /// 90th percentile
+    pub p90_us: u64,
+    /// 99th percentile
+    pub p99_us: u64,
+    /// Slowest call
+    pub max_us: u64,
+}
+
+impl LatencyPercentiles {
+    /// Percentiles of `latencies`, by the nearest-rank method
+    fn of(mut latencies: Vec<u64>) -> Self {
+        if latencies.is_empty() {
+            return Self::default();
+        }
+        latencies.sort_unstable();
+        let rank = |percent: usize| {
+            let index = (latencies.len() * percent).div_ceil(100).max(1) - 1;
+            latencies[index]
+        };
+        Self {
+            p50_us: rank(50),
+            p90_us: rank(90),
+            p99_us: rank(99),
+            max_us: latencies[latencies.len() - 1],
+        }
+    }
+}
+
+/// Outcome of a soak
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SoakReport {
+    /// The profile the soak ran
+    pub profile: SoakProfile,
+    /// Calls made
+    pub calls: usize,
+    /// Calls that failed, including failures to instantiate
+    pub failures: usize,
+    /// Failures by kind of [`HostError`]
+    pub errors: BTreeMap<String, usize>,
+    /// Latencies of all calls
+    pub latency: LatencyPercentiles,
+    /// Memory samples in call order
+    pub samples: Vec<SoakSample>,
+    /// Milliseconds the soak took
+    pub elapsed_ms: u64,
+}
+
+impl SoakReport {
+    /// Pages of guest memory gained from the first sample to the last
+    ///
+    /// Guests that keep growing over a soak usually leak.
+    pub fn memory_growth_pages(&self) -> u64 {
+        match (self.samples.first(), self.samples.last()) {
+            (Some(first), Some(last)) => last.memory_pages.saturating_sub(first.memory_pages),
+            _ => 0,
+        }
+    }
+}
+
+/// State shared by the workers of a soak
+struct Soak<'a> {
+    engine: &'a WasmEngine,
+    module: &'a dyn BackendModule,
+    profile: &'a SoakProfile,
+    started: Instant,
+    next: AtomicUsize,
+    completed: AtomicUsize,
+    pool: Mutex<Vec<WasmInstance>>,
+    returned: Condvar,
+    outcome: Mutex<Outcome>,
+}
+
+/// What the workers gathered
+#[derive(Default)]
+struct Outcome {
+    failures: usize,
+    errors: BTreeMap<String, usize>,
+    latencies: Vec<u64>,
+    samples: Vec<SoakSample>,
+}
+
+/// Run the soak `profile` describes against `module`
+///
+/// Fails only if the instances of an [`InstanceMode::Pool`] cannot be
+/// created up front; failures of calls are counted in the report.
+pub fn run_soak(
+    engine: &WasmEngine,
+    module: &dyn BackendModule,
+    profile: &SoakProfile,
+) -> Result<SoakReport, HostError> {
+    let pool = match profile.instances {
+        InstanceMode::Pool(size) => (0..size.max(1))
+            .map(|_| WasmInstance::new(engine, module))
+            .collect::<Result<_, _>>()?,
+        InstanceMode::Fresh | InstanceMode::Reuse => Vec::new(),
+    };
+    let soak = Soak {
+        engine,
+        module,
+        profile,
+        started: Instant::now(),
+        next: AtomicUsize::new(0),
+        completed: AtomicUsize::new(0),
+        pool: Mutex::new(pool),
+        returned: Condvar::new(),
+        outcome: Mutex::new(Outcome::default()),
+    };
+
+    std::thread::scope(|scope| {
+        for _ in 0..profile.concurrency.max(1) {
+            scope.spawn(|| soak.work());
+        }
+    });
+
+    let elapsed_ms = soak.started.elapsed().as_millis() as u64;
+    let mut outcome = soak.outcome.into_inner();
+    outcome.samples.sort_by_key(|sample| sample.calls);
+    Ok(SoakReport {
+        profile: profile.clone(),
+        calls: outcome.latencies.len(),
+        failures: outcome.failures,
+        errors: outcome.errors,
+        latency: LatencyPercentiles::of(outcome.latencies),
+        samples: outcome.samples,
+        elapsed_ms,
+    })
+}
+
+impl Soak<'_> {
+    /// Make calls until the profile's are used up
+    fn work(&self) {
+        let mut reused = None;
+        let mut latencies = Vec::new();
+        let mut errors = Vec::new();
+
+        loop {
+            let index = self.next.fetch_add(1, Ordering::Relaxed);
+            if index >= self.profile.calls {
+                break;
+            }
+            let payload = self.payload(index);
+
+            let started = Instant::now();
+            let result = match self.profile.instances {
+                InstanceMode::Fresh => WasmInstance::new(self.engine, self.module)
+                    .and_then(|mut instance| self.call(&mut instance, &payload)),
+                InstanceMode::Reuse => match reused
+                    .take()
+                    .map_or_else(|| WasmInstance::new(self.engine, self.module), Ok)
+                {
+                    Ok(mut instance) => {
+                        let result = self.call(&mut instance, &payload);
+                        reused = Some(instance);
+                        result
+                    }
+                    Err(e) => Err(e),
+                },
+                InstanceMode::Pool(_) => {
+                    let mut instance = self.take();
+                    let result = self.call(&mut instance, &payload);
+                    self.put_back(instance);
+                    result
+                }
+            };
+            latencies.push(started.elapsed().as_micros() as u64);
+            if let Err(e) = result {
+                errors.push(error_kind(&e));
+            }
+        }
+
+        let mut outcome = self.outcome.lock();
+        outcome.failures += errors.len();
+        for kind in errors {
+            *outcome.errors.entry(kind).or_default() += 1;
+        }
+        outcome.latencies.extend(latencies);
+    }
+
+    /// The payload of call `index`, the same on every run with one seed
+    fn payload(&self, index: usize) -> Vec<u8> {
+        let mut state = self.profile.seed ^ (index as u64).wrapping_mul(0xa076_1d64_78bd_642f);
+        let size = self.profile.payload.pick(splitmix64(&mut state));
+        let mut payload = Vec::with_capacity(size);
+        while payload.len() < size {
+            let bytes = splitmix64(&mut state).to_le_bytes();
+            payload.extend_from_slice(&bytes[..(size - payload.len()).min(8)]);
+        }
+        payload
+    }
+
+    /// Make a call on `instance`, sampling it if the call completes a
+    /// sampling period
+    fn call(&self, instance: &mut WasmInstance, payload: &[u8]) -> Result<(), HostError> {
+        let result = instance.call_raw(&self.profile.function, payload);
+        let calls = self.completed.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.profile.sample_every > 0 && calls.is_multiple_of(self.profile.sample_every) {
+            self.sample(instance, calls, result.is_ok());
+        }
+        result.map(|_| ())
+    }
+
+    fn sample(&self, instance: &mut WasmInstance, calls: usize, succeeded: bool) {
+        // A failed call does not refresh the arena statistics
+        let report = instance
+            .last_call_report()
+            .filter(|_| succeeded)
+            .copied()
+            .unwrap_or_default();
+        let memory_pages = instance
+            .backend_instance()
+            .memory_size()
+            .map_or(0, |bytes| bytes / WASM_PAGE_SIZE);
+        let sample = SoakSample {
+            calls,
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+            rss_bytes: resident_set_size(),
+            memory_pages,
+            guest_allocated_bytes: report.guest_allocated_bytes,
+            guest_high_water: report.guest_high_water,
+        };
+        self.outcome.lock().samples.push(sample);
+    }
+
+    /// Take an instance from the pool, waiting for one to be put back
+    fn take(&self) -> WasmInstance {
+        let mut pool = self.pool.lock();
+        loop {
+            if let Some(instance) = pool.pop() {
+                return instance;
+            }
+            self.returned.wait(&mut pool);
+        }
+    }
+
+    fn put_back(&self, instance: WasmInstance) {
+        self.pool.lock().push(instance);
+        self.returned.notify_one();
+    }
+}
+
+/// Name of the variant of `error`, with the kind of traps
+fn error_kind(error: &HostError) -> String {
+    let kind = match error {
+        HostError::Compilation(_) => "Compilation",
+        HostError::Instantiation(_) => "Instantiation",
+        HostError::FunctionNotFound(_) => "FunctionNotFound",
+        HostError::MemoryNotFound => "MemoryNotFound",
+        HostError::UnsupportedMemory(_) => "UnsupportedMemory",
+        HostError::MemoryAccess(_) => "MemoryAccess",
+        HostError::Memory(_) => "Memory",
+        HostError::Runtime(_) => "Runtime",
+        HostError::Trap { kind, .. } => return format!("Trap({:?})", kind),
+        HostError::SignatureMismatch(_) => "SignatureMismatch",
+        HostError::Stream(_) => "Stream",
+        HostError::InvalidReturn => "InvalidReturn",
+        HostError::ProtocolMismatch { .. } => "ProtocolMismatch",
+        HostError::ReentrantCall => "ReentrantCall",
+        HostError::GuestError(_) => "GuestError",
+        HostError::Serialization(_) => "Serialization",
+        HostError::Deserialization(_) => "Deserialization",
+        HostError::MeteringExceeded => "MeteringExceeded",
+        HostError::WarmupFailed { .. } => "WarmupFailed",
+        HostError::Quarantined(_) => "Quarantined",
+        HostError::Cache(_) => "Cache",
+        HostError::InputTooLarge { .. } => "InputTooLarge",
+        HostError::OutputTooLarge { .. } => "OutputTooLarge",
+    };
+    kind.to_string()
+}
+
+/// Resident set size of this process, from `/proc` on Linux
+fn resident_set_size() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// Step of the splitmix64 generator
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TrapKind;
+
+    #[test]
+    fn test_payload_sizes() {
+        assert_eq!(PayloadSizes::Fixed(7).pick(12345), 7);
+
+        let uniform = PayloadSizes::Uniform { min: 10, max: 12 };
+        let mut state = 1;
+        for _ in 0..100 {
+            assert!((10..=12).contains(&uniform.pick(splitmix64(&mut state))));
+        }
+
+        let weighted = PayloadSizes::Weighted(vec![(1, 1), (2, 0), (3, 3)]);
+        assert_eq!(weighted.pick(0), 1);
+        assert_eq!(weighted.pick(1), 3);
+        assert_eq!(weighted.pick(3), 3);
+        assert_eq!(weighted.pick(4), 1);
+        assert_eq!(PayloadSizes::Weighted(Vec::new()).pick(9), 0);
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        assert_eq!(
+            LatencyPercentiles::of(Vec::new()),
+            LatencyPercentiles::default()
+        );
+        assert_eq!(
+            LatencyPercentiles::of((1..=100).rev().collect()),
+            LatencyPercentiles {
+                p50_us: 50,
+                p90_us: 90,
+                p99_us: 99,
+                max_us: 100,
+            }
+        );
+        assert_eq!(LatencyPercentiles::of(vec![5]).p50_us, 5);
+    }
+
+    #[test]
+    fn test_error_kind() {
+        assert_eq!(error_kind(&HostError::MeteringExceeded), "MeteringExceeded");
+        assert_eq!(
+            error_kind(&HostError::Trap {
+                kind: TrapKind::StackOverflow,
+                message: "deep".to_string(),
+            }),
+            "Trap(StackOverflow)"
+        );
+    }
+
+    #[test]
+    fn test_report_serializes() {
+        let report = SoakReport {
+            profile: SoakProfile::new("echo"),
+            calls: 2,
+            failures: 1,
+            errors: BTreeMap::from([("MeteringExceeded".to_string(), 1)]),
+            latency: LatencyPercentiles::of(vec![3, 9]),
+            samples: vec![SoakSample {
+                calls: 2,
+                elapsed_ms: 1,
+                rss_bytes: None,
+                memory_pages: 17,
+                guest_allocated_bytes: Some(64),
+                guest_high_water: None,
+            }],
+            elapsed_ms: 1,
+        };
+        let bytes = rmp_serde::to_vec_named(&report).unwrap();
+        for field in ["profile", "memory_pages", "p99_us", "MeteringExceeded"] {
+            assert!(
+                bytes
+                    .windows(field.len())
+                    .any(|window| window == field.as_bytes()),
+                "{}",
+                field
+            );
+        }
+        assert_eq!(report.memory_growth_pages(), 0);
+    }
+}
@@ -0,0 +1,700 @@
+//! Wasmer implementation of the execution backend
+//!
+//! Natively guests are compiled with Cranelift or LLVM and metered; with
+//! the `wasmer_js` feature they are handed to the JavaScript engine, which
+//! offers no compiler middlewares, so they run unmetered.
+
+use crate::backend::{
+    allocate64_with_32, guest_allocation, guest_allocation64, BackendImports, BackendInstance,
+    BackendMemory, BackendModule, ExternInfo, MemoryImport, MemoryLayout, StreamSink, ValueType,
+    WasmBackend,
+};
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+use crate::MiddlewareFactory;
+use crate::{
+    EngineConfig, Env, HostCallCost, HostError, ImportBuilder, MemoryAccessFailure, TrapKind,
+};
+use aingle_wasmer_common::{
+    WasmResult64, WasmSlice, WasmSlice64, GUEST_ALLOCATE64_EXPORT, STREAM_BEGIN_EXPORT,
+    STREAM_WRITE_EXPORT,
+};
+use std::any::Any;
+use std::sync::Arc;
+
+#[cfg(feature = "wasmer_sys_dev")]
+use wasmer::sys::Cranelift;
+
+#[cfg(feature = "wasmer_sys_prod")]
+use wasmer::sys::LLVM;
+
+use wasmer::{
+    Engine, ExternType, FunctionEnv, Instance, Memory, MemoryType, Module, RuntimeError, Store,
+    Type, Value,
+};
+
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+use wasmer::sys::vm::TrapCode;
+
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+use wasmer::sys::{FunctionMiddleware, MiddlewareError, ModuleMiddleware};
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+use wasmer::wasmparser::Operator;
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+use wasmer::LocalFunctionIndex;
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+use wasmer_middlewares::metering::{get_remaining_points, set_remaining_points, MeteringPoints};
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+use wasmer_middlewares::Metering;
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+use wasmer_types::ModuleInfo;
+
+/// Cost function used by the metering middleware
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+pub type MeteringCostFn = fn(&Operator) -> u64;
+
+/// Every operator costs one point
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+fn unit_cost(_: &Operator) -> u64 {
+    1
+}
+
+/// A compiler middleware built afresh for every module an engine compiles
+///
+/// Middlewares such as [`Metering`] record the globals they add to the one
+/// module they instrument and panic when handed another. Wasmer compiles
+/// the modules of an engine one at a time, holding the engine from
+/// `transform_module_info` to the last function, so the functions of a
+/// module always see the middleware built for it.
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+#[derive(Debug)]
+struct PerModule {
+    factory: MiddlewareFactory,
+    current: parking_lot::Mutex<Option<Arc<dyn ModuleMiddleware>>>,
+}
+
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+impl PerModule {
+    fn new(factory: MiddlewareFactory) -> Self {
+        Self {
+            factory,
+            current: parking_lot::Mutex::new(None),
+        }
+    }
+}
+
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+impl ModuleMiddleware for PerModule {
+    fn generate_function_middleware(
+        &self,
+        local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        self.current
+            .lock()
+            .as_ref()
+            .expect("functions are compiled after their module info is transformed")
+            .generate_function_middleware(local_function_index)
+    }
+
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) -> Result<(), MiddlewareError> {
+        let middleware = self.factory.build();
+        middleware.transform_module_info(module_info)?;
+        *self.current.lock() = Some(middleware);
+        Ok(())
+    }
+}
+
+/// Backend running guests on Wasmer
+pub struct WasmerBackend {
+    engine: Engine,
+    /// Points every module compiled starts its calls with, if metered
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    metering_limit: Option<u64>,
+    /// Cost of host calls charged against the metering budget, if any
+    host_call_cost: Option<HostCallCost>,
+    /// Bytes host writes copy per memory view, all at once if unset
+    write_chunk: Option<usize>,
+    /// Size of the guest buffer host writes reuse, if any
+    scratch_bytes: Option<u32>,
+    /// Hash of the settings the engine compiles with, unknown for wrapped
+    /// engines
+    artifact_hash: Option<u32>,
+}
+
+impl WasmerBackend {
+    /// Create a Wasmer backend with metering and tunables from the config
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    pub fn new(config: &EngineConfig) -> Result<Self, HostError> {
+        use wasmer::sys::{BaseTunables, CompilerConfig, NativeEngineExt};
+
+        let metering_limit = config.metering_limit;
+        let metering = MiddlewareFactory::new(move || {
+            Arc::new(Metering::new(metering_limit, unit_cost as MeteringCostFn))
+        });
+
+        #[cfg(feature = "wasmer_sys_dev")]
+        let mut compiler = Cranelift::default();
+
+        #[cfg(feature = "wasmer_sys_prod")]
+        let mut compiler = LLVM::default();
+
+        if config.canonicalize_nans {
+            compiler.canonicalize_nans(true);
+        }
+        // Metering first, then extra middlewares in configured order, each
+        // built anew for every module
+        compiler.push_middleware(Arc::new(PerModule::new(metering)));
+        for factory in &config.extra_middlewares {
+            compiler.push_middleware(Arc::new(PerModule::new(factory.clone())));
+        }
+
+        let mut engine = Engine::from(compiler);
+
+        // iOS compatibility tunables
+        engine.set_tunables(BaseTunables {
+            static_memory_bound: config.static_memory_bound.into(),
+            static_memory_offset_guard_size: 0x1_0000,
+            dynamic_memory_offset_guard_size: 0x1_0000,
+        });
+
+        Ok(Self {
+            engine,
+            metering_limit: Some(metering_limit),
+            host_call_cost: config.charge_host_calls,
+            write_chunk: config.guest_write_chunk_bytes,
+            scratch_bytes: config.guest_scratch_bytes,
+            artifact_hash: Some(config.artifact_hash()),
+        })
+    }
+
+    /// A backend compiling like [`WasmerBackend::new`] without the
+    /// metering and extra middlewares
+    ///
+    /// Modules compiled besides the guests, such as feature probes, are
+    /// compiled here, out of sight of the middlewares an embedder
+    /// configured.
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    pub(crate) fn unmetered(config: &EngineConfig) -> Self {
+        #[cfg(feature = "wasmer_sys_dev")]
+        let mut compiler = Cranelift::default();
+
+        #[cfg(feature = "wasmer_sys_prod")]
+        let mut compiler = LLVM::default();
+
+        if config.canonicalize_nans {
+            compiler.canonicalize_nans(true);
+        }
+        Self::from_engine(Engine::from(compiler))
+    }
+
+    /// Create a Wasmer backend on the JavaScript engine
+    ///
+    /// The JavaScript engine compiles guests itself, so the compiler
+    /// settings of the config do not apply: guests run unmetered, whatever
+    /// `metering_limit` says, and a call only ends when the guest returns.
+    #[cfg(all(
+        feature = "wasmer_js",
+        not(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))
+    ))]
+    pub fn new(config: &EngineConfig) -> Result<Self, HostError> {
+        tracing::warn!(
+            "The wasmer_js backend cannot meter guests; the limit of {} points is not enforced",
+            config.metering_limit
+        );
+        Ok(Self {
+            write_chunk: config.guest_write_chunk_bytes,
+            scratch_bytes: config.guest_scratch_bytes,
+            ..Self::from_engine(Engine::default())
+        })
+    }
+
+    /// Wrap an existing Wasmer engine
+    pub fn from_engine(engine: Engine) -> Self {
+        Self {
+            engine,
+            #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+            metering_limit: None,
+            host_call_cost: None,
+            write_chunk: None,
+            scratch_bytes: None,
+            artifact_hash: None,
+        }
+    }
+
+    /// Get a reference to the Wasmer engine
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    /// Hash of the settings compiled into modules, see
+    /// [`EngineConfig::artifact_hash`]; `None` for engines wrapped with
+    /// [`WasmerBackend::from_engine`]
+    pub fn artifact_hash(&self) -> Option<u32> {
+        self.artifact_hash
+    }
+
+    /// Get the metering limit compiled into every module, if this backend
+    /// meters guests
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    pub fn metering_limit(&self) -> Option<u64> {
+        self.metering_limit
+    }
+}
+
+impl WasmBackend for WasmerBackend {
+    fn name(&self) -> &'static str {
+        "wasmer"
+    }
+
+    fn compile(&self, wasm: &[u8]) -> Result<Arc<dyn BackendModule>, HostError> {
+        let module =
+            Module::new(&self.engine, wasm).map_err(|e| HostError::Compilation(e.to_string()))?;
+        Ok(Arc::new(module))
+    }
+
+    fn instantiate(
+        &self,
+        module: &dyn BackendModule,
+        imports: &BackendImports,
+    ) -> Result<Box<dyn BackendInstance>, HostError> {
+        let module = module.as_any().downcast_ref::<Module>().ok_or_else(|| {
+            HostError::Instantiation("module was not compiled by the wasmer backend".to_string())
+        })?;
+
+        let layout = module.memory_layout()?;
+        let mut store = Store::new(self.engine.clone());
+
+        let mut builder = ImportBuilder::new(&mut store);
+        // Only modules importing their memory get one from the host
+        if let Some(import) = &layout.import {
+            let pages = imports.memory_pages.max(import.minimum);
+            let pages = import.maximum.map_or(pages, |maximum| pages.min(maximum));
+            let memory = Memory::new(
+                &mut store,
+                MemoryType::new(pages, import.maximum, import.shared),
+            )
+            .map_err(|e| HostError::Instantiation(e.to_string()))?;
+            builder = builder.memory_import(&import.module, &import.name, memory);
+        }
+        if let Some(export) = layout.export {
+            builder = builder.memory_export(export);
+        }
+        let mut builder = builder.functions(&mut store, &imports.functions);
+        if let Some(cost) = self.host_call_cost {
+            builder = builder.charge_host_calls(cost);
+        }
+        if let Some(bytes) = self.write_chunk {
+            builder = builder.write_chunk(bytes);
+        }
+        if let Some(bytes) = self.scratch_bytes {
+            builder = builder.scratch(bytes);
+        }
+        let env = builder.env().clone();
+        let instance = builder.instantiate(&mut store, module)?;
+        let memory = env.as_ref(&store).memory.clone();
+
+        Ok(Box::new(WasmerInstance {
+            instance,
+            store,
+            env,
+            memory,
+        }))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl BackendModule for Module {
+    fn function_exports(&self) -> Vec<ExternInfo> {
+        self.exports()
+            .filter_map(|export| match export.ty() {
+                ExternType::Function(ty) => Some(ExternInfo::new(
+                    export.name(),
+                    ty.params().iter().map(value_type).collect(),
+                    ty.results().iter().map(value_type).collect(),
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn memory_layout(&self) -> Result<MemoryLayout, HostError> {
+        let imports = self
+            .imports()
+            .filter_map(|import| match import.ty() {
+                ExternType::Memory(ty) => Some(MemoryImport {
+                    module: import.module().to_string(),
+                    name: import.name().to_string(),
+                    minimum: ty.minimum.0,
+                    maximum: ty.maximum.map(|pages| pages.0),
+                    shared: ty.shared,
+                }),
+                _ => None,
+            })
+            .collect();
+        let exports = self
+            .exports()
+            .filter(|export| matches!(export.ty(), ExternType::Memory(_)))
+            .map(|export| export.name().to_string())
+            .collect();
+        MemoryLayout::detect(imports, exports)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+fn value_type(ty: &Type) -> ValueType {
+    match ty {
+        Type::I32 => ValueType::I32,
+        Type::I64 => ValueType::I64,
+        Type::F32 => ValueType::F32,
+        Type::F64 => ValueType::F64,
+        Type::V128 => ValueType::V128,
+        Type::FuncRef => ValueType::FuncRef,
+        Type::ExternRef => ValueType::ExternRef,
+        #[allow(unreachable_patterns)]
+        _ => ValueType::Other,
+    }
+}
+
+impl From<RuntimeError> for HostError {
+    /// Recover host errors raised inside host calls and categorize traps
+    fn from(error: RuntimeError) -> Self {
+        let error = match error.downcast::<HostError>() {
+            Ok(host_error) => return host_error,
+            Err(error) => error,
+        };
+
+        let message = error.message();
+        match error.to_trap() {
+            Some(code) => HostError::Trap {
+                kind: trap_kind(code),
+                message,
+            },
+            None => HostError::Runtime(message),
+        }
+    }
+}
+
+#[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+fn trap_kind(code: TrapCode) -> TrapKind {
+    match code {
+        TrapCode::StackOverflow => TrapKind::StackOverflow,
+        TrapCode::HeapAccessOutOfBounds => TrapKind::MemoryOutOfBounds,
+        TrapCode::HeapMisaligned | TrapCode::UnalignedAtomic => TrapKind::MisalignedAccess,
+        TrapCode::TableAccessOutOfBounds => TrapKind::TableOutOfBounds,
+        TrapCode::IndirectCallToNull => TrapKind::IndirectCallNull,
+        TrapCode::BadSignature => TrapKind::BadSignature,
+        TrapCode::IntegerOverflow => TrapKind::IntegerOverflow,
+        TrapCode::IntegerDivisionByZero => TrapKind::IntegerDivisionByZero,
+        TrapCode::BadConversionToInteger => TrapKind::BadConversionToInteger,
+        TrapCode::UnreachableCodeReached => TrapKind::UnreachableCodeReached,
+        #[allow(unreachable_patterns)]
+        _ => TrapKind::Other,
+    }
+}
+
+/// Trap codes are not exported on the JavaScript engine
+#[cfg(all(
+    feature = "wasmer_js",
+    not(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))
+))]
+fn trap_kind<T>(_code: T) -> TrapKind {
+    TrapKind::Other
+}
+
+/// A Wasmer instance together with the store that owns it
+pub struct WasmerInstance {
+    instance: Instance,
+    store: Store,
+    env: FunctionEnv<Env>,
+    /// Memory of the guest, imported or exported
+    memory: Option<Memory>,
+}
+
+impl WasmerInstance {
+    /// Get the environment of the standard imports
+    pub fn env(&self) -> &FunctionEnv<Env> {
+        &self.env
+    }
+
+    /// Get a reference to the Wasmer instance
+    pub fn instance(&self) -> &Instance {
+        &self.instance
+    }
+
+    /// Get a reference to the store
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Get mutable reference to the store
+    pub fn store_mut(&mut self) -> &mut Store {
+        &mut self.store
+    }
+
+    fn call_with_params(&mut self, name: &str, params: &[Value]) -> Result<u64, HostError> {
+        match self.call_with_results(name, params)?.first() {
+            Some(Value::I64(v)) => Ok(*v as u64),
+            _ => Err(HostError::InvalidReturn),
+        }
+    }
+
+    fn call_with_results(
+        &mut self,
+        name: &str,
+        params: &[Value],
+    ) -> Result<Box<[Value]>, HostError> {
+        let func = self
+            .instance
+            .exports
+            .get_function(name)
+            .map_err(|_| HostError::FunctionNotFound(name.to_string()))?;
+        // Host functions calling back into the instance see the call
+        let _call = self.env.as_ref(&self.store).enter_call(false)?;
+
+        // Metering aborts a call with an `unreachable` trap once exhausted
+        func.call(&mut self.store, params).map_err(|e| {
+            if self.metering_exhausted() {
+                HostError::MeteringExceeded
+            } else {
+                HostError::from(e)
+            }
+        })
+    }
+
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn metering_exhausted(&mut self) -> bool {
+        self.instance
+            .exports
+            .get_global("wasmer_metering_remaining_points")
+            .is_ok()
+            && matches!(
+                get_remaining_points(&mut self.store, &self.instance),
+                MeteringPoints::Exhausted
+            )
+    }
+
+    /// Guests on the JavaScript engine are not metered
+    #[cfg(all(
+        feature = "wasmer_js",
+        not(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))
+    ))]
+    fn metering_exhausted(&mut self) -> bool {
+        false
+    }
+
+    fn memory(&self) -> Result<&Memory, HostError> {
+        self.memory.as_ref().ok_or(HostError::MemoryNotFound)
+    }
+}
+
+impl BackendMemory for WasmerInstance {
+    fn memory_size(&mut self) -> Result<u64, HostError> {
+        Ok(self.memory()?.view(&self.store).data_size())
+    }
+
+    fn read_memory(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), HostError> {
+        let view = self.memory()?.view(&self.store);
+        view.read(offset, buf).map_err(|error| {
+            HostError::from(MemoryAccessFailure {
+                error,
+                offset,
+                len: buf.len() as u64,
+                memory_size: view.data_size(),
+            })
+        })
+    }
+
+    fn write_memory(&mut self, offset: u64, data: &[u8]) -> Result<(), HostError> {
+        let view = self.memory()?.view(&self.store);
+        view.write(offset, data).map_err(|error| {
+            HostError::from(MemoryAccessFailure {
+                error,
+                offset,
+                len: data.len() as u64,
+                memory_size: view.data_size(),
+            })
+        })
+    }
+
+    fn allocate(&mut self, len: u32) -> Result<u32, HostError> {
+        let allocate = self
+            .instance
+            .exports
+            .get_typed_function::<i32, i32>(&self.store, "__hc__allocate_1")
+            .map_err(|_| HostError::FunctionNotFound("__hc__allocate_1".to_string()))?;
+
+        let ptr = allocate
+            .call(&mut self.store, len as i32)
+            .map_err(|e| HostError::MemoryAccess(format!("Failed to allocate: {}", e)))?;
+        guest_allocation(ptr)
+    }
+
+    fn allocate64(&mut self, len: u64) -> Result<u64, HostError> {
+        let Ok(allocate) = self
+            .instance
+            .exports
+            .get_typed_function::<i64, i64>(&self.store, GUEST_ALLOCATE64_EXPORT)
+        else {
+            return allocate64_with_32(self, len);
+        };
+
+        let ptr = allocate
+            .call(&mut self.store, len as i64)
+            .map_err(|e| HostError::MemoryAccess(format!("Failed to allocate: {}", e)))?;
+        guest_allocation64(ptr)
+    }
+}
+
+impl StreamSink for WasmerInstance {
+    fn stream_begin(&mut self, total_len: u32) -> Result<u32, HostError> {
+        let begin = self
+            .instance
+            .exports
+            .get_typed_function::<i32, i32>(&self.store, STREAM_BEGIN_EXPORT)
+            .map_err(|_| HostError::FunctionNotFound(STREAM_BEGIN_EXPORT.to_string()))?;
+
+        begin
+            .call(&mut self.store, total_len as i32)
+            .map(|handle| handle.max(0) as u32)
+            .map_err(|e| HostError::Stream(format!("Failed to begin stream: {}", e)))
+    }
+
+    fn stream_write(&mut self, handle: u32, chunk: WasmSlice) -> Result<i32, HostError> {
+        let write = self
+            .instance
+            .exports
+            .get_typed_function::<(i32, i32, i32), i32>(&self.store, STREAM_WRITE_EXPORT)
+            .map_err(|_| HostError::FunctionNotFound(STREAM_WRITE_EXPORT.to_string()))?;
+
+        write
+            .call(
+                &mut self.store,
+                handle as i32,
+                chunk.ptr as i32,
+                chunk.len as i32,
+            )
+            .map_err(|e| HostError::Stream(format!("Failed to write stream: {}", e)))
+    }
+}
+
+impl BackendInstance for WasmerInstance {
+    fn module(&self) -> &dyn BackendModule {
+        self.instance.module()
+    }
+
+    fn call(&mut self, name: &str, input: WasmSlice) -> Result<u64, HostError> {
+        self.call_with_params(
+            name,
+            &[Value::I32(input.ptr as i32), Value::I32(input.len as i32)],
+        )
+    }
+
+    fn call2(&mut self, name: &str, first: WasmSlice, second: WasmSlice) -> Result<u64, HostError> {
+        self.call_with_params(
+            name,
+            &[
+                Value::I32(first.ptr as i32),
+                Value::I32(first.len as i32),
+                Value::I32(second.ptr as i32),
+                Value::I32(second.len as i32),
+            ],
+        )
+    }
+
+    fn call64(&mut self, name: &str, input: WasmSlice64) -> Result<u128, HostError> {
+        let results = self.call_with_results(
+            name,
+            &[Value::I64(input.ptr as i64), Value::I64(input.len as i64)],
+        )?;
+        match *results {
+            [Value::I64(high), Value::I64(low)] => {
+                Ok(WasmResult64::from_parts(high as u64, low as u64).into_raw())
+            }
+            _ => Err(HostError::InvalidReturn),
+        }
+    }
+
+    fn call_stat(&mut self, name: &str) -> Result<Option<u32>, HostError> {
+        let Ok(stat) = self
+            .instance
+            .exports
+            .get_typed_function::<(), i32>(&self.store, name)
+        else {
+            return Ok(None);
+        };
+
+        let value = stat.call(&mut self.store)?;
+        Ok(Some(value as u32))
+    }
+
+    fn call_packed(&mut self, name: &str) -> Result<Option<u64>, HostError> {
+        let Ok(export) = self
+            .instance
+            .exports
+            .get_typed_function::<(), i64>(&self.store, name)
+        else {
+            return Ok(None);
+        };
+
+        let value = export.call(&mut self.store)?;
+        Ok(Some(value as u64))
+    }
+
+    fn call_setter(&mut self, name: &str, value: u32) -> Result<bool, HostError> {
+        let Ok(setter) = self
+            .instance
+            .exports
+            .get_typed_function::<i32, ()>(&self.store, name)
+        else {
+            return Ok(false);
+        };
+
+        setter.call(&mut self.store, value as i32)?;
+        Ok(true)
+    }
+
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn remaining_points(&mut self) -> Option<u64> {
+        // Engines wrapped with `from_engine` may compile without metering
+        self.instance
+            .exports
+            .get_global("wasmer_metering_remaining_points")
+            .ok()?;
+        match get_remaining_points(&mut self.store, &self.instance) {
+            MeteringPoints::Remaining(points) => Some(points),
+            MeteringPoints::Exhausted => None,
+        }
+    }
+
+    #[cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+    fn set_remaining_points(&mut self, points: u64) {
+        set_remaining_points(&mut self.store, &self.instance, points);
+    }
+
+    #[cfg(all(
+        feature = "wasmer_js",
+        not(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))
+    ))]
+    fn remaining_points(&mut self) -> Option<u64> {
+        None
+    }
+
+    #[cfg(all(
+        feature = "wasmer_js",
+        not(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))
+    ))]
+    fn set_remaining_points(&mut self, _points: u64) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
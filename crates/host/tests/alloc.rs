@@ -0,0 +1,85 @@
+//! Heap allocations of the typed read path
+//!
+//! Counting them takes a global allocator, which is kept out of the unit
+//! tests so it doesn't sit under every other test of the crate.
+
+use aingle_wasmer_host::backend::{read_guest_bytes, read_typed, BackendMemory};
+use aingle_wasmer_host::HostError;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// Counts heap allocations made by the current thread
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+/// Plain byte buffer standing in for guest memory
+struct VecMemory(Vec<u8>);
+
+impl BackendMemory for VecMemory {
+    fn memory_size(&mut self) -> Result<u64, HostError> {
+        Ok(self.0.len() as u64)
+    }
+
+    fn read_memory(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), HostError> {
+        let start = offset as usize;
+        buf.copy_from_slice(&self.0[start..start + buf.len()]);
+        Ok(())
+    }
+
+    fn write_memory(&mut self, offset: u64, data: &[u8]) -> Result<(), HostError> {
+        let start = offset as usize;
+        self.0[start..start + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    fn allocate(&mut self, _len: u32) -> Result<u32, HostError> {
+        unreachable!("reads never allocate in the guest")
+    }
+}
+
+#[test]
+fn test_small_typed_reads_do_not_allocate() {
+    let payload = rmp_serde::to_vec(&(7u32, true)).unwrap();
+    let len = payload.len() as u32;
+    let mut memory = VecMemory(payload);
+
+    // Warm up the per-thread buffer
+    let _: (u32, bool) = read_typed(&mut memory, 0, len).unwrap();
+
+    let before = allocations();
+    for _ in 0..1000 {
+        let bytes = read_guest_bytes(&mut memory, 0, len).unwrap();
+        let _: (u32, bool) = rmp_serde::from_slice(&bytes).unwrap();
+    }
+    let copying = allocations() - before;
+
+    let before = allocations();
+    for _ in 0..1000 {
+        let _: (u32, bool) = read_typed(&mut memory, 0, len).unwrap();
+    }
+    let reusing = allocations() - before;
+
+    assert!(copying >= 1000, "{} allocations", copying);
+    assert_eq!(reusing, 0);
+}
@@ -0,0 +1,89 @@
+//! A guest written in C on the support layer in `examples/c-guest`, built
+//! against the header generated by the common crate's `capi` feature
+//!
+//! Needs clang with the wasm32 target and wasm-ld; skipped without them.
+//! The compiler is taken from `$CLANG`, defaulting to `clang`.
+
+#![cfg(any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod"))]
+
+use aingle_wasmer_host::{EngineConfig, HostError, WasmEngine, WasmInstance};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+const FLAGS: &[&str] = &[
+    "--target=wasm32-unknown-unknown",
+    "-nostdlib",
+    "-fno-builtin",
+    "-O2",
+    "-Wl,--no-entry",
+    "-Wl,--stack-first",
+    "-Wl,-z,stack-size=1048576",
+];
+
+fn clang() -> Command {
+    Command::new(std::env::var_os("CLANG").unwrap_or_else(|| "clang".into()))
+}
+
+/// Whether clang can link an empty wasm32 module
+fn wasm_toolchain(dir: &Path) -> bool {
+    clang()
+        .args(FLAGS)
+        .args(["-x", "c", "-", "-o"])
+        .arg(dir.join("probe.wasm"))
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Build `echo.c`, or return `None` without a wasm32 toolchain
+fn build() -> Option<Vec<u8>> {
+    let dir = tempfile::tempdir().unwrap();
+    if !wasm_toolchain(dir.path()) {
+        eprintln!("skipping: clang cannot build wasm32 modules");
+        return None;
+    }
+
+    std::fs::write(
+        dir.path().join("aingle_wasmer.h"),
+        aingle_wasmer_common::capi::HEADER,
+    )
+    .unwrap();
+    let examples = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../examples/c-guest");
+    let wasm = dir.path().join("echo.wasm");
+    let status = clang()
+        .args(FLAGS)
+        .args(["-Wall", "-Werror", "-I"])
+        .arg(dir.path())
+        .arg(examples.join("aingle_guest.c"))
+        .arg(examples.join("echo.c"))
+        .arg("-o")
+        .arg(&wasm)
+        .status()
+        .unwrap();
+    assert!(status.success(), "building the C guest failed");
+    Some(std::fs::read(wasm).unwrap())
+}
+
+#[test]
+fn test_c_guest() {
+    let Some(wasm) = build() else {
+        return;
+    };
+    let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+    let module = engine.compile(&wasm).unwrap();
+    let mut instance = WasmInstance::new(&engine, &module).unwrap();
+
+    assert_eq!(instance.call_raw("echo", b"ping").unwrap(), b"ping");
+    assert_eq!(instance.call_raw("echo", b"").unwrap(), b"");
+    // Results are reclaimed by the next call
+    let large = vec![7u8; 48 * 1024];
+    for _ in 0..4 {
+        assert_eq!(instance.call_raw("echo", &large).unwrap(), large);
+    }
+
+    match instance.call_raw("fail", b"sensor offline") {
+        Err(HostError::GuestError(message)) => assert_eq!(message, "sensor offline"),
+        other => panic!("expected a guest error, got {:?}", other),
+    }
+}
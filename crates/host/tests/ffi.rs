@@ -0,0 +1,183 @@
+//! The C ABI of the `cdylib` feature, called through extern declarations
+//! matching the generated header, as a C conductor would
+
+#![cfg(all(
+    feature = "cdylib",
+    any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod")
+))]
+
+use aingle_wasmer_host::ffi::{AingleStatus, HEADER};
+use std::ffi::{c_char, CStr, CString};
+use std::ptr::{null, null_mut};
+
+/// Opaque handles, as the header declares them
+#[repr(C)]
+struct AingleEngine {
+    _private: [u8; 0],
+}
+#[repr(C)]
+struct AingleModule {
+    _private: [u8; 0],
+}
+#[repr(C)]
+struct AingleInstance {
+    _private: [u8; 0],
+}
+
+extern "C" {
+    fn aingle_engine_new(config_json: *const c_char) -> *mut AingleEngine;
+    fn aingle_engine_free(engine: *mut AingleEngine);
+    fn aingle_module_compile(
+        engine: *const AingleEngine,
+        bytes: *const u8,
+        len: usize,
+        key_out: *mut u8,
+    ) -> *mut AingleModule;
+    fn aingle_module_free(module: *mut AingleModule);
+    fn aingle_instance_new(
+        engine: *const AingleEngine,
+        module: *const AingleModule,
+    ) -> *mut AingleInstance;
+    fn aingle_instance_free(instance: *mut AingleInstance);
+    fn aingle_call(
+        instance: *mut AingleInstance,
+        name: *const c_char,
+        in_ptr: *const u8,
+        in_len: usize,
+        out_ptr: *mut *mut u8,
+        out_len: *mut usize,
+    ) -> AingleStatus;
+    fn aingle_bytes_free(ptr: *mut u8, len: usize);
+    fn aingle_last_error() -> *mut c_char;
+    fn aingle_string_free(string: *mut c_char);
+}
+
+/// `echo` returns the input envelope, `fail` returns it as an error
+const GUEST_WAT: &str = r#"
+    (module
+        (memory (export "memory") 1)
+        (func $slice (param $ptr i32) (param $len i32) (result i64)
+            (i64.or
+                (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+                (i64.extend_i32_u (local.get $len))))
+        (func (export "echo") (param $ptr i32) (param $len i32) (result i64)
+            (call $slice (local.get $ptr) (local.get $len)))
+        (func (export "fail") (param $ptr i32) (param $len i32) (result i64)
+            (i64.or
+                (call $slice (local.get $ptr) (local.get $len))
+                (i64.const 0x8000000000000000))))
+"#;
+
+fn last_error() -> String {
+    unsafe {
+        let message = aingle_last_error();
+        assert!(!message.is_null());
+        let text = CStr::from_ptr(message).to_str().unwrap().to_string();
+        aingle_string_free(message);
+        text
+    }
+}
+
+/// Call `name`, returning the status and the output on success
+fn call(instance: *mut AingleInstance, name: &str, input: &[u8]) -> (AingleStatus, Vec<u8>) {
+    let name = CString::new(name).unwrap();
+    let (mut out_ptr, mut out_len) = (null_mut(), 0);
+    unsafe {
+        let status = aingle_call(
+            instance,
+            name.as_ptr(),
+            input.as_ptr(),
+            input.len(),
+            &mut out_ptr,
+            &mut out_len,
+        );
+        if status != AingleStatus::Ok {
+            return (status, Vec::new());
+        }
+        let output = std::slice::from_raw_parts(out_ptr, out_len).to_vec();
+        aingle_bytes_free(out_ptr, out_len);
+        (status, output)
+    }
+}
+
+#[test]
+fn test_c_abi() {
+    let wasm = wat::parse_str(GUEST_WAT).unwrap();
+    unsafe {
+        let config = CString::new(r#"{"max_input_len": 1024}"#).unwrap();
+        let engine = aingle_engine_new(config.as_ptr());
+        assert!(!engine.is_null());
+
+        let mut key = [0u8; 32];
+        let module = aingle_module_compile(engine, wasm.as_ptr(), wasm.len(), key.as_mut_ptr());
+        assert!(!module.is_null());
+        assert_eq!(key, *blake3::hash(&wasm).as_bytes());
+
+        let instance = aingle_instance_new(engine, module);
+        assert!(!instance.is_null());
+        // Instances outlive their engine and module
+        aingle_module_free(module);
+        aingle_engine_free(engine);
+
+        assert_eq!(
+            call(instance, "echo", b"ping"),
+            (AingleStatus::Ok, b"ping".to_vec())
+        );
+        assert_eq!(call(instance, "echo", b""), (AingleStatus::Ok, Vec::new()));
+
+        assert_eq!(
+            call(instance, "fail", b"no sensor").0,
+            AingleStatus::GuestError
+        );
+        assert_eq!(last_error(), "guest error: no sensor");
+        assert_eq!(call(instance, "missing", b"").0, AingleStatus::Error);
+        assert!(last_error().contains("missing"));
+        assert_eq!(call(instance, "echo", &[0; 2048]).0, AingleStatus::Error);
+
+        aingle_instance_free(instance);
+    }
+}
+
+#[test]
+fn test_c_abi_invalid_arguments() {
+    unsafe {
+        let config = CString::new(r#"{"metering": 1}"#).unwrap();
+        assert!(aingle_engine_new(config.as_ptr()).is_null());
+        assert!(last_error().starts_with("invalid config_json"));
+
+        let engine = aingle_engine_new(null());
+        assert!(!engine.is_null());
+        assert!(aingle_module_compile(engine, null(), 8, null_mut()).is_null());
+        assert_eq!(last_error(), "bytes is NULL");
+        assert!(aingle_module_compile(engine, b"not wasm".as_ptr(), 8, null_mut()).is_null());
+        assert!(aingle_instance_new(engine, null()).is_null());
+        assert_eq!(
+            call(null_mut(), "echo", b"").0,
+            AingleStatus::InvalidArgument
+        );
+
+        // Free functions accept NULL
+        aingle_engine_free(engine);
+        aingle_engine_free(null_mut());
+        aingle_module_free(null_mut());
+        aingle_instance_free(null_mut());
+        aingle_bytes_free(null_mut(), 0);
+        aingle_string_free(null_mut());
+    }
+}
+
+#[test]
+fn test_c_abi_header() {
+    for name in [
+        "aingle_engine_new",
+        "aingle_module_compile",
+        "aingle_instance_new",
+        "aingle_call",
+        "aingle_bytes_free",
+        "aingle_last_error",
+    ] {
+        assert!(HEADER.contains(&format!("{}(", name)), "{}", name);
+    }
+    assert!(HEADER.contains("AINGLE_STATUS_GUEST_ERROR = 1"));
+    assert!(HEADER.contains("# Ownership"));
+}
@@ -0,0 +1,382 @@
+//! End to end tests on the guests of `aingle_wasmer_test_fixtures`, one
+//! per fixture
+//!
+//! The fixtures are built for wasm32-unknown-unknown when this compiles:
+//!
+//! ```text
+//! cargo test -p aingle_wasmer_host --features fixtures --test fixtures
+//! ```
+
+#![cfg(all(
+    feature = "fixtures",
+    any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod")
+))]
+
+use aingle_wasmer_common::{guest_fn, ErrorKind};
+use aingle_wasmer_host::{
+    guest, CallOptions, CallTrace, EngineConfig, ExternIO, HostError, HostFunction,
+    HostFunctionRegistry, ImportBuilder, TraceStepKind, WasmEngine, WasmError, WasmInstance,
+};
+use aingle_wasmer_test_fixtures::{
+    fixture, FIXTURES, GUEST, GUEST_HOST_CALLS, GUEST_IMPORTED_MEMORY, HOST_ECHO,
+};
+use std::sync::Arc;
+use std::time::Instant;
+use wasmer::{AsStoreMut, Module, Store};
+
+guest_fn! {
+    /// The `#[aingle_entry]` fixture
+    struct Greet => fn greet(String) -> String;
+    /// The `map_extern!` fixture
+    struct Sum => fn sum(Vec<u32>) -> u64;
+}
+
+fn instantiate(config: EngineConfig) -> WasmInstance {
+    let engine = WasmEngine::new(config).unwrap();
+    let module = engine.compile(GUEST).unwrap();
+    WasmInstance::new(&engine, &module).unwrap()
+}
+
+fn guest_error(result: Result<Vec<u8>, HostError>) -> String {
+    match result {
+        Err(HostError::GuestError(message)) => message,
+        other => panic!("expected a guest error, got {:?}", other),
+    }
+}
+
+/// `count` as the 4-byte prefix some fixtures take, followed by `rest`
+fn counted(count: u32, rest: &[u8]) -> Vec<u8> {
+    [&count.to_le_bytes(), rest].concat()
+}
+
+#[test]
+fn test_manifest() {
+    let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+    for (name, wasm) in FIXTURES {
+        let module = engine.compile(wasm).unwrap();
+        assert!(
+            module
+                .function_exports()
+                .iter()
+                .any(|export| export.name == *name),
+            "{} is not exported by its module",
+            name
+        );
+    }
+    assert_eq!(fixture("call_host_echo"), Some(GUEST_HOST_CALLS));
+    assert_eq!(fixture("missing"), None);
+}
+
+#[test]
+fn test_echo() {
+    let mut instance = instantiate(EngineConfig::default());
+    assert_eq!(instance.call_raw("echo", b"ping").unwrap(), b"ping");
+    assert_eq!(instance.call_raw("echo", b"").unwrap(), b"");
+    let payload: Vec<u8> = (0..=255).cycle().take(64 * 1024).collect();
+    assert_eq!(instance.call_raw("echo", &payload).unwrap(), payload);
+}
+
+#[test]
+fn test_memory_layouts() {
+    let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+    for (wasm, imported) in [(GUEST, false), (GUEST_IMPORTED_MEMORY, true)] {
+        let module = engine.compile(wasm).unwrap();
+        let layout = module.memory_layout().unwrap();
+        assert_eq!(layout.import.is_some(), imported, "{:?}", layout);
+        assert_eq!(layout.export.is_none(), imported, "{:?}", layout);
+
+        let mut instance = WasmInstance::new(&engine, &module).unwrap();
+        assert_eq!(instance.call_raw("echo", b"ping").unwrap(), b"ping");
+        let chunk: Vec<u8> = (0..=255).collect();
+        let output = instance
+            .call_raw("echo_large", counted(1024, &chunk))
+            .unwrap();
+        assert_eq!(output.len(), 256 * 1024);
+    }
+}
+
+#[test]
+fn test_echo_large() {
+    let mut instance = instantiate(EngineConfig::default());
+    let chunk: Vec<u8> = (0..=255).collect();
+    let output = instance
+        .call_raw("echo_large", counted(16 * 1024, &chunk))
+        .unwrap();
+    assert_eq!(output.len(), 4 * 1024 * 1024);
+    assert!(output.chunks(chunk.len()).all(|part| part == chunk));
+
+    let message = guest_error(instance.call_raw("echo_large", b"ab"));
+    assert!(message.starts_with("[InvalidInput]"), "{}", message);
+}
+
+#[test]
+fn test_loop_forever() {
+    let mut instance = instantiate(EngineConfig {
+        metering_limit: 1_000_000,
+        ..EngineConfig::default()
+    });
+    assert!(matches!(
+        instance.call_raw("loop_forever", b""),
+        Err(HostError::MeteringExceeded)
+    ));
+}
+
+#[test]
+fn test_loop_forever_cached() {
+    use wasmer_middlewares::metering::{get_remaining_points, MeteringPoints};
+
+    let engine = WasmEngine::new(EngineConfig {
+        metering_limit: 1_000_000,
+        ..EngineConfig::default()
+    })
+    .unwrap();
+
+    // Compiled by the backend
+    let module = engine.compile(GUEST).unwrap();
+    let mut instance = WasmInstance::new(&engine, &module).unwrap();
+    assert!(matches!(
+        instance.call_raw("loop_forever", b""),
+        Err(HostError::MeteringExceeded)
+    ));
+
+    // Compiled by the module cache, with the same engine
+    let module = engine.compile_cached([5; 32], GUEST).unwrap();
+    let mut store = Store::new(engine.inner().unwrap().clone());
+    let instance = ImportBuilder::new(&mut store)
+        .instantiate(&mut store, &module)
+        .unwrap();
+    let result = guest::call(
+        &mut store.as_store_mut(),
+        Arc::new(instance.clone()),
+        "loop_forever",
+        ExternIO::new(Vec::new()),
+    );
+    assert!(result.is_err());
+    assert!(matches!(
+        get_remaining_points(&mut store, &instance),
+        MeteringPoints::Exhausted
+    ));
+}
+
+#[test]
+fn test_panic_with_message() {
+    let mut instance = instantiate(EngineConfig::default());
+    let message = guest_error(instance.call_raw("panic_with_message", b"sensor offline"));
+    assert!(
+        message.starts_with("[GuestCall] sensor offline"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn test_alloc_n_bytes() {
+    let mut instance = instantiate(EngineConfig::default());
+    let pages = |instance: &mut WasmInstance| {
+        instance.backend_instance().memory_size().unwrap() / (64 * 1024)
+    };
+    let before = pages(&mut instance);
+
+    let bytes = 8 * 1024 * 1024;
+    let output = instance
+        .call_raw("alloc_n_bytes", counted(bytes, b""))
+        .unwrap();
+    let high_water = u64::from_le_bytes(output.try_into().unwrap());
+    assert!(high_water >= u64::from(bytes), "{}", high_water);
+    assert!(pages(&mut instance) > before);
+    let report = instance.last_call_report().unwrap();
+    assert!(report.guest_high_water >= Some(bytes), "{:?}", report);
+}
+
+struct HostEcho;
+
+impl HostFunction<Vec<u8>, Vec<u8>> for HostEcho {
+    const NAME: &'static str = HOST_ECHO;
+
+    fn call(&self, args: Vec<u8>) -> Result<Vec<u8>, WasmError> {
+        Ok(args)
+    }
+}
+
+#[test]
+fn test_call_host_echo() {
+    // Instances without host functions cannot satisfy the import
+    let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+    let module = engine.compile(GUEST_HOST_CALLS).unwrap();
+    assert!(WasmInstance::new(&engine, &module).is_err());
+
+    let mut store = Store::new(engine.inner().unwrap().clone());
+    let module = Module::new(&store, GUEST_HOST_CALLS).unwrap();
+    let registry = HostFunctionRegistry::new().register(HostEcho);
+    let instance = ImportBuilder::new(&mut store)
+        .functions(&mut store, &registry)
+        .instantiate(&mut store, &module)
+        .unwrap();
+
+    let input = ExternIO::new(b"through the host".to_vec())
+        .to_envelope(0)
+        .unwrap();
+    let output = guest::call(
+        &mut store.as_store_mut(),
+        Arc::new(instance),
+        "call_host_echo",
+        ExternIO::from(input),
+    )
+    .unwrap();
+    assert_eq!(output, b"through the host");
+}
+
+#[test]
+fn test_explain() {
+    let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+    let mut store = Store::new(engine.inner().unwrap().clone());
+    let module = Module::new(&store, GUEST).unwrap();
+    let instance = ImportBuilder::new(&mut store)
+        .instantiate(&mut store, &module)
+        .unwrap();
+    let input = ExternIO::new(b"explained".to_vec()).to_envelope(0).unwrap();
+    let options = CallOptions {
+        explain: true,
+        ..CallOptions::default()
+    };
+
+    let (output, trace) = guest::call_explained(
+        &mut store.as_store_mut(),
+        Arc::new(instance),
+        "echo",
+        &input,
+        &options,
+    );
+    assert_eq!(output.unwrap(), b"explained");
+    let trace = trace.unwrap();
+    let kinds: Vec<_> = trace
+        .kinds()
+        .into_iter()
+        .filter(|kind| {
+            matches!(
+                kind,
+                TraceStepKind::Allocate
+                    | TraceStepKind::Write
+                    | TraceStepKind::Call
+                    | TraceStepKind::Read
+                    | TraceStepKind::Decode
+            )
+        })
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            TraceStepKind::Allocate,
+            TraceStepKind::Write,
+            TraceStepKind::Call,
+            TraceStepKind::Read,
+            TraceStepKind::Decode
+        ]
+    );
+    let json = serde_json::to_string(&trace).unwrap();
+    assert_eq!(serde_json::from_str::<CallTrace>(&json).unwrap(), trace);
+}
+
+#[test]
+fn test_lazy_table() {
+    let first_call = |warmup_export: Option<String>| {
+        let mut instance = instantiate(EngineConfig {
+            warmup_export,
+            ..EngineConfig::default()
+        });
+        let started = Instant::now();
+        let output = instance.call_raw("lazy_table", b"").unwrap();
+        (output, started.elapsed())
+    };
+    let (cold, cold_latency) = first_call(None);
+    let (warm, warm_latency) = first_call(EngineConfig::default().warmup_export);
+    assert_eq!(cold, warm);
+    assert!(
+        warm_latency < cold_latency,
+        "warm {:?}, cold {:?}",
+        warm_latency,
+        cold_latency
+    );
+}
+
+#[test]
+fn test_chatty() {
+    let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+    let module = engine.compile(GUEST_HOST_CALLS).unwrap();
+    let registry = HostFunctionRegistry::new().register(HostEcho);
+    let mut instance = WasmInstance::with_functions(&engine, &module, &registry).unwrap();
+    let message = vec![0x5a; 1000];
+
+    let output = instance.call_raw("chatty", counted(10, &message)).unwrap();
+    assert_eq!(output, 10u32.to_le_bytes());
+    let unlimited = instance.last_call_report().unwrap().host_io;
+    assert!(unlimited.bytes_in >= 10 * 1000, "{:?}", unlimited);
+    assert!(unlimited.bytes_out >= 10 * 1000, "{:?}", unlimited);
+
+    // A quota of a third of that stops the guest within its fourth call
+    let options = CallOptions {
+        max_host_io_bytes: Some(unlimited.total() / 3),
+        ..CallOptions::default()
+    };
+    let message =
+        guest_error(instance.call_raw_with_options("chatty", counted(10, &message), &options));
+    assert!(message.starts_with("[ResourceExhausted]"), "{}", message);
+    let report = instance.last_call_report().unwrap();
+    assert!(
+        report.host_io.total() <= unlimited.total() / 3,
+        "{:?}",
+        report
+    );
+    assert!(report.host_io.bytes_in >= 3 * 1000, "{:?}", report);
+
+    // The instance is fine for the next call
+    let output = instance.call_raw("chatty", counted(1, b"again")).unwrap();
+    assert_eq!(output, 1u32.to_le_bytes());
+}
+
+#[test]
+fn test_return_error_kind() {
+    let mut instance = instantiate(EngineConfig::default());
+    for &kind in ErrorKind::ALL {
+        let message = guest_error(instance.call_raw("return_error_kind", [kind as u8]));
+        assert!(
+            message.starts_with(&format!("[{:?}] error of the requested kind", kind)),
+            "{}",
+            message
+        );
+    }
+}
+
+#[test]
+fn test_greet() {
+    let mut instance = instantiate(EngineConfig::default());
+    let greeting = instance.call_fn::<Greet>(&"wasm".to_string()).unwrap();
+    assert_eq!(greeting, "hello wasm");
+
+    let message = match instance.call_fn::<Greet>(&String::new()) {
+        Err(HostError::GuestError(message)) => message,
+        other => panic!("expected a guest error, got {:?}", other),
+    };
+    assert!(
+        message.starts_with("[InvalidInput] nobody to greet"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn test_sum() {
+    let mut instance = instantiate(EngineConfig::default());
+    let total = instance.call_fn::<Sum>(&vec![1, 2, u32::MAX]).unwrap();
+    assert_eq!(total, 3 + u64::from(u32::MAX));
+
+    let message = match instance.call_fn::<Sum>(&Vec::new()) {
+        Err(HostError::GuestError(message)) => message,
+        other => panic!("expected a guest error, got {:?}", other),
+    };
+    assert!(
+        message.starts_with("[InvalidInput] nothing to add up"),
+        "{}",
+        message
+    );
+}
@@ -0,0 +1,51 @@
+//! The host on wasmer's js API, as it runs in a browser
+//!
+//! Needs `wasm-bindgen-test-runner` as the runner of the wasm32 target and
+//! runs under Node.js:
+//!
+//! ```text
+//! CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUNNER=wasm-bindgen-test-runner \
+//!     cargo test -p aingle_wasmer_host --target wasm32-unknown-unknown \
+//!     --no-default-features --features wasmer_js --test js
+//! ```
+
+#![cfg(all(target_arch = "wasm32", feature = "wasmer_js"))]
+
+use aingle_wasmer_host::{EngineConfig, ExternIO, ModuleCache, WasmEngine, WasmInstance};
+use std::path::PathBuf;
+use std::sync::Arc;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+/// Echoes the input envelope back as the result slice
+const ECHO_WAT: &str = r#"
+    (module
+        (memory (export "memory") 1)
+        (func (export "echo") (param $ptr i32) (param $len i32) (result i64)
+            (i64.or
+                (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+                (i64.extend_i32_u (local.get $len)))))
+"#;
+
+#[wasm_bindgen_test]
+fn test_echo() {
+    let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+    assert_eq!(engine.backend().name(), "wasmer");
+    let module = engine.compile(&wat::parse_str(ECHO_WAT).unwrap()).unwrap();
+    let mut instance = WasmInstance::new(&engine, &module).unwrap();
+
+    assert_eq!(instance.call_raw("echo", b"ping").unwrap(), b"ping");
+    let io = ExternIO::new(b"pong".to_vec());
+    assert_eq!(instance.call_raw("echo", &io).unwrap(), b"pong");
+}
+
+#[wasm_bindgen_test]
+fn test_module_cache_in_memory() {
+    // There is no filesystem to persist modules in
+    let cache = ModuleCache::new(Some(PathBuf::from("/modules")));
+    assert_eq!(cache.cache_path(), None);
+
+    let wasm = wat::parse_str(ECHO_WAT).unwrap();
+    let module = cache.get([1; 32], &wasm).unwrap();
+    assert!(Arc::ptr_eq(&module, &cache.get([1; 32], &wasm).unwrap()));
+    assert_eq!(cache.len(), 1);
+}
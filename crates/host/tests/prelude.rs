@@ -0,0 +1,34 @@
+//! The prelude glob-imported next to `aingle_zome_types`' prelude, as zome
+//! hosts do
+
+use aingle_wasmer_host::prelude::*;
+
+/// Stands in for `aingle_zome_types::prelude`
+mod zome_types {
+    #[derive(Debug, PartialEq)]
+    pub struct ExternIO(pub Vec<u8>);
+}
+use zome_types::*;
+
+#[test]
+fn test_prelude_glob_import() {
+    // Ambiguous if the prelude exported its own ExternIO
+    let zome = ExternIO(b"ping".to_vec());
+    let host = HostExternIO::new(zome.0.clone());
+    assert_eq!(host.as_bytes(), zome.0);
+}
+
+#[cfg(feature = "zome_types")]
+mod zome_types_prelude {
+    use aingle_wasmer_host::prelude::*;
+    use aingle_zome_types::prelude::*;
+
+    #[test]
+    fn test_prelude_with_zome_types() {
+        let zome = ExternIO::encode(7u32).unwrap();
+        let host = HostExternIO::from(zome);
+        assert_eq!(host.decode::<u32>().unwrap(), 7);
+        let zome: ExternIO = host.into();
+        assert_eq!(zome.decode::<u32>().unwrap(), 7);
+    }
+}
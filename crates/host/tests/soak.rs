@@ -0,0 +1,116 @@
+//! Soaks of the echo guest the call benchmarks run on
+//!
+//! The long soaks are ignored; run them with
+//! `cargo test -p aingle_wasmer_host --features stress -- --ignored soak`
+//! and set `SOAK_REPORT` to a path to keep the report of each as JSON
+//! lines for trend tracking.
+
+#![cfg(all(
+    feature = "stress",
+    any(feature = "wasmer_sys_dev", feature = "wasmer_sys_prod")
+))]
+
+use aingle_wasmer_host::stress::{run_soak, InstanceMode, PayloadSizes, SoakProfile, SoakReport};
+use aingle_wasmer_host::{EngineConfig, WasmEngine};
+use std::io::Write;
+
+fn soak(profile: &SoakProfile) -> SoakReport {
+    let wasm = wat::parse_str(include_str!("../benches/fixtures/echo.wat")).unwrap();
+    let engine = WasmEngine::new(EngineConfig::default()).unwrap();
+    let module = engine.compile(&wasm).unwrap();
+    let report = run_soak(&engine, &module, profile).unwrap();
+
+    if let Ok(path) = std::env::var("SOAK_REPORT") {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        writeln!(file, "{}", serde_json::to_string(&report).unwrap()).unwrap();
+    }
+    report
+}
+
+/// Small, medium and the occasional large payload
+fn mixed_payloads() -> PayloadSizes {
+    PayloadSizes::Weighted(vec![(64, 16), (4 * 1024, 6), (256 * 1024, 1)])
+}
+
+/// Checks shared by every soak: all calls succeed and the echo guest, which
+/// never grows its memory, ends the run with the memory it started with
+fn assert_steady(report: &SoakReport) {
+    assert_eq!(report.calls, report.profile.calls, "{:?}", report);
+    assert_eq!(report.failures, 0, "{:?}", report.errors);
+    assert_eq!(
+        report.samples.len(),
+        report.profile.calls / report.profile.sample_every
+    );
+    assert_eq!(report.memory_growth_pages(), 0, "{:?}", report.samples);
+    assert!(report.latency.p50_us <= report.latency.p99_us);
+    assert!(report.latency.p99_us <= report.latency.max_us);
+}
+
+#[test]
+fn test_short_soak() {
+    let profile = SoakProfile {
+        calls: 200,
+        payload: PayloadSizes::Uniform { min: 0, max: 4096 },
+        sample_every: 50,
+        ..SoakProfile::new("echo")
+    };
+    let report = soak(&profile);
+    assert_steady(&report);
+    assert_eq!(report.samples[0].calls, 50);
+    assert_eq!(report.samples[0].memory_pages, 80);
+}
+
+#[test]
+fn test_soak_counts_failures_by_kind() {
+    let profile = SoakProfile {
+        calls: 10,
+        sample_every: 5,
+        ..SoakProfile::new("missing")
+    };
+    let report = soak(&profile);
+    assert_eq!(report.failures, 10);
+    assert_eq!(report.errors.len(), 1, "{:?}", report.errors);
+}
+
+#[test]
+#[ignore = "long-running soak"]
+fn soak_reused_instance() {
+    let profile = SoakProfile {
+        calls: 1_000_000,
+        payload: mixed_payloads(),
+        sample_every: 10_000,
+        ..SoakProfile::new("echo")
+    };
+    assert_steady(&soak(&profile));
+}
+
+#[test]
+#[ignore = "long-running soak"]
+fn soak_fresh_instances() {
+    let profile = SoakProfile {
+        calls: 50_000,
+        payload: mixed_payloads(),
+        concurrency: 4,
+        instances: InstanceMode::Fresh,
+        ..SoakProfile::new("echo")
+    };
+    assert_steady(&soak(&profile));
+}
+
+#[test]
+#[ignore = "long-running soak"]
+fn soak_pooled_instances() {
+    let profile = SoakProfile {
+        calls: 1_000_000,
+        payload: mixed_payloads(),
+        concurrency: 8,
+        instances: InstanceMode::Pool(4),
+        sample_every: 10_000,
+        ..SoakProfile::new("echo")
+    };
+    assert_steady(&soak(&profile));
+}
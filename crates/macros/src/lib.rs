@@ -0,0 +1,356 @@
+//! # AIngle WASM Macros
+//!
+//! Procedural macros for WASM guests, re-exported by `aingle_wasmer_guest`.
+
+#![warn(missing_docs)]
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{
+    parse_macro_input, parse_quote, Data, DeriveInput, Error, Fields, FnArg, GenericArgument,
+    ItemFn, LitStr, Path, PathArguments, ReturnType, Type,
+};
+
+/// Export a function as a guest entry point
+///
+/// Generates the `#[no_mangle] extern "C" fn(ptr, len) -> u64` wrapper the
+/// host calls: the input is read with `host_args_auto`, which accepts it
+/// enveloped or raw, and deserialized, the function is invoked, and its
+/// result is returned with `return_ok_typed` or `return_err_typed`. With the
+/// guest's `panic-hook` feature, panics are returned as errors too.
+///
+/// The function takes at most one input, and returns either
+/// `Result<T, E>` with `WasmError: From<E>` or a plain `T`; methods,
+/// generic and async functions are rejected. The guest crate's
+/// `tests/aingle_entry.rs` covers what is accepted and refused.
+///
+/// ```ignore
+/// use aingle_wasmer_guest::prelude::*;
+///
+/// #[derive(Debug, Serialize, Deserialize)]
+/// struct Greeting {
+///     name: String,
+/// }
+///
+/// #[aingle_entry]
+/// fn greet(input: Greeting) -> Result<String, WasmError> {
+///     Ok(format!("hello {}", input.name))
+/// }
+///
+/// #[aingle_entry]
+/// fn version() -> u32 {
+///     1
+/// }
+/// ```
+///
+/// Common errors such as parse, UTF-8 and MessagePack errors convert into
+/// `WasmError`, so `?` works without `map_err`:
+///
+/// ```ignore
+/// use aingle_wasmer_guest::prelude::*;
+///
+/// #[aingle_entry]
+/// fn parse_port(input: String) -> Result<u16, WasmError> {
+///     Ok(input.trim().parse::<u16>()?)
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn aingle_entry(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let func = parse_macro_input!(item as ItemFn);
+    let expanded = if attr.is_empty() {
+        expand_entry(func)
+    } else {
+        Err(Error::new(
+            Span::call_site(),
+            "#[aingle_entry] does not take arguments",
+        ))
+    };
+    expanded.unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand_entry(func: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    check_signature(&func)?;
+
+    let name = &func.sig.ident;
+    let vis = &func.vis;
+    let docs = func.attrs.iter().filter(|attr| attr.path().is_ident("doc"));
+    let input = match func.sig.inputs.first() {
+        Some(FnArg::Typed(input)) => Some(input),
+        Some(FnArg::Receiver(receiver)) => {
+            return Err(Error::new_spanned(
+                receiver,
+                "#[aingle_entry] cannot be used on methods",
+            ))
+        }
+        None => None,
+    };
+
+    let call = match (input, returns_result(&func.sig.output)) {
+        (Some(input), true) => {
+            let ty = &input.ty;
+            quote!(|input: #ty| #name(input).map_err(::aingle_wasmer_guest::WasmError::from))
+        }
+        (Some(input), false) => {
+            let ty = &input.ty;
+            quote!(|input: #ty| Ok(#name(input)))
+        }
+        (None, true) => quote!(|| #name().map_err(::aingle_wasmer_guest::WasmError::from)),
+        (None, false) => quote!(|| Ok(#name())),
+    };
+    let dispatch = if input.is_some() {
+        quote!(::aingle_wasmer_guest::__entry(guest_ptr, len, #call))
+    } else {
+        quote!(::aingle_wasmer_guest::__entry_unit(#call))
+    };
+
+    Ok(quote! {
+        #(#docs)*
+        #[no_mangle]
+        #vis extern "C" fn #name(
+            guest_ptr: ::aingle_wasmer_guest::GuestPtr,
+            len: ::aingle_wasmer_guest::Len,
+        ) -> ::aingle_wasmer_guest::GuestReturn {
+            #func
+
+            #dispatch
+        }
+    })
+}
+
+/// Reject signatures the generated wrapper cannot call
+fn check_signature(func: &ItemFn) -> syn::Result<()> {
+    let sig = &func.sig;
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(Error::new_spanned(
+            asyncness,
+            "#[aingle_entry] functions cannot be async",
+        ));
+    }
+    if let Some(unsafety) = &sig.unsafety {
+        return Err(Error::new_spanned(
+            unsafety,
+            "#[aingle_entry] functions cannot be unsafe",
+        ));
+    }
+    if let Some(abi) = &sig.abi {
+        return Err(Error::new_spanned(
+            abi,
+            "#[aingle_entry] generates the extern wrapper itself",
+        ));
+    }
+    if !sig.generics.params.is_empty() || sig.generics.where_clause.is_some() {
+        return Err(Error::new_spanned(
+            &sig.generics,
+            "#[aingle_entry] functions cannot be generic",
+        ));
+    }
+    if let Some(variadic) = &sig.variadic {
+        return Err(Error::new_spanned(
+            variadic,
+            "#[aingle_entry] functions cannot be variadic",
+        ));
+    }
+    if sig.inputs.len() > 1 {
+        return Err(Error::new_spanned(
+            &sig.inputs,
+            "#[aingle_entry] functions take at most one input; use a struct for several values",
+        ));
+    }
+    Ok(())
+}
+
+/// Whether the return type is spelled as a `Result`
+fn returns_result(output: &ReturnType) -> bool {
+    let ReturnType::Type(_, ty) = output else {
+        return false;
+    };
+    let Type::Path(path) = ty.as_ref() else {
+        return false;
+    };
+    path.path
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "Result")
+}
+
+/// Derive `WasmSafe` for a `#[repr(C)]` or `#[repr(transparent)]` struct
+///
+/// Every field must be `WasmSafe` itself, and fields holding references,
+/// raw pointers, tuples or heap types such as `Box` and `Vec` are refused.
+/// For structs without generics a compile-time assertion also checks that
+/// the fields fill the struct without padding; the cases refused are
+/// covered by the guest crate's `tests/ui/wasm_safe`.
+///
+/// ```ignore
+/// use aingle_wasmer_guest::prelude::*;
+///
+/// #[derive(Clone, Copy, WasmSafe)]
+/// #[repr(C)]
+/// struct Point {
+///     x: i32,
+///     y: i32,
+///     tag: [u8; 8],
+/// }
+/// ```
+///
+/// The generated code names `::aingle_wasmer_guest`; crates depending only
+/// on `aingle_wasmer_common` point the derive there:
+///
+/// ```ignore
+/// #[derive(WasmSafe)]
+/// #[wasm_safe(crate = "aingle_wasmer_common")]
+/// #[repr(C)]
+/// struct Point {
+///     x: i32,
+///     y: i32,
+/// }
+/// ```
+#[proc_macro_derive(WasmSafe, attributes(wasm_safe))]
+pub fn derive_wasm_safe(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    expand_wasm_safe(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_wasm_safe(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let krate = crate_path(&input)?;
+    check_repr(&input)?;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.iter().collect(),
+            Fields::Unnamed(fields) => fields.unnamed.iter().collect(),
+            Fields::Unit => Vec::new(),
+        },
+        Data::Enum(data) => {
+            return Err(Error::new_spanned(
+                data.enum_token,
+                "#[derive(WasmSafe)] only supports structs",
+            ))
+        }
+        Data::Union(data) => {
+            return Err(Error::new_spanned(
+                data.union_token,
+                "#[derive(WasmSafe)] only supports structs",
+            ))
+        }
+    };
+    let field_types: Vec<&Type> = fields.iter().map(|field| &field.ty).collect();
+    for ty in &field_types {
+        check_field_type(ty)?;
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    if input.generics.params.is_empty() {
+        return Ok(quote! {
+            unsafe impl #krate::WasmSafe for #name {}
+            #krate::static_assert_wasm_safe!(#name #(, #field_types)*);
+        });
+    }
+
+    // The layout of generic structs depends on their parameters, so only
+    // the fields are checked, through the bounds of the impl
+    let mut where_clause = where_clause.cloned().unwrap_or_else(|| parse_quote!(where));
+    for ty in &field_types {
+        where_clause
+            .predicates
+            .push(parse_quote!(#ty: #krate::WasmSafe));
+    }
+    Ok(quote! {
+        unsafe impl #impl_generics #krate::WasmSafe for #name #ty_generics #where_clause {}
+    })
+}
+
+/// Path of the crate exporting `WasmSafe`, from `#[wasm_safe(crate = "..")]`
+fn crate_path(input: &DeriveInput) -> syn::Result<Path> {
+    let mut krate = parse_quote!(::aingle_wasmer_guest);
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("wasm_safe"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("crate") {
+                krate = meta.value()?.parse::<LitStr>()?.parse()?;
+                Ok(())
+            } else {
+                Err(meta.error("unknown #[wasm_safe] option"))
+            }
+        })?;
+    }
+    Ok(krate)
+}
+
+/// Require `#[repr(C)]` or `#[repr(transparent)]`, the layouts shared with
+/// the other side
+fn check_repr(input: &DeriveInput) -> syn::Result<()> {
+    let mut stable = false;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("repr"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("C") || meta.path.is_ident("transparent") {
+                stable = true;
+            }
+            // Skip the arguments of `align(..)` and `packed(..)`
+            if meta.input.peek(syn::token::Paren) {
+                let _ = meta.input.parse::<proc_macro2::Group>()?;
+            }
+            Ok(())
+        })?;
+    }
+    if stable {
+        Ok(())
+    } else {
+        Err(Error::new_spanned(
+            &input.ident,
+            "#[derive(WasmSafe)] requires #[repr(C)] or #[repr(transparent)]",
+        ))
+    }
+}
+
+/// Refuse field types that point outside the value or have no stable layout
+fn check_field_type(ty: &Type) -> syn::Result<()> {
+    match ty {
+        Type::Reference(_) => Err(Error::new_spanned(
+            ty,
+            "WasmSafe types cannot hold references",
+        )),
+        Type::Ptr(_) => Err(Error::new_spanned(
+            ty,
+            "WasmSafe types cannot hold raw pointers",
+        )),
+        Type::Tuple(tuple) if !tuple.elems.is_empty() => Err(Error::new_spanned(
+            ty,
+            "tuples have no stable layout; use a #[repr(C)] struct",
+        )),
+        Type::Array(array) => check_field_type(&array.elem),
+        Type::Group(group) => check_field_type(&group.elem),
+        Type::Paren(paren) => check_field_type(&paren.elem),
+        Type::Path(path) => {
+            let Some(segment) = path.path.segments.last() else {
+                return Ok(());
+            };
+            const HEAP_TYPES: [&str; 5] = ["Box", "Vec", "String", "Rc", "Arc"];
+            if HEAP_TYPES.iter().any(|heap| segment.ident == heap) {
+                return Err(Error::new_spanned(
+                    ty,
+                    format!("WasmSafe types cannot hold a `{}`", segment.ident),
+                ));
+            }
+            if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                for arg in &args.args {
+                    if let GenericArgument::Type(ty) = arg {
+                        check_field_type(ty)?;
+                    }
+                }
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
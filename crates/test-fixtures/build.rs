@@ -0,0 +1,67 @@
+//! Builds the guest modules of the manifest with the `fixtures` feature
+
+fn main() {
+    #[cfg(feature = "fixtures")]
+    if std::env::var("CARGO_CFG_TARGET_ARCH").as_deref() != Ok("wasm32") {
+        guest::build();
+    }
+}
+
+#[cfg(feature = "fixtures")]
+mod guest {
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    const TARGET: &str = "wasm32-unknown-unknown";
+
+    /// Build this crate for wasm32 once per module, copying each to
+    /// `$OUT_DIR/<module>.wasm`
+    pub fn build() {
+        let manifest_dir = PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap());
+        for dir in ["src", "../guest/src", "../common/src"] {
+            println!(
+                "cargo:rerun-if-changed={}",
+                manifest_dir.join(dir).display()
+            );
+        }
+
+        let out_dir = PathBuf::from(std::env::var_os("OUT_DIR").unwrap());
+        // A target directory of its own, as the outer build holds the lock
+        // on the workspace's
+        let target_dir = out_dir.join("guest");
+        for (module, features, rustflags) in [
+            ("guest", "", ""),
+            ("guest_host_calls", "host-calls", ""),
+            // Linked to import its memory rather than export it
+            ("guest_imported_memory", "", "-Clink-arg=--import-memory"),
+        ] {
+            build_module(&manifest_dir, &target_dir, features, rustflags);
+            let wasm = target_dir
+                .join(TARGET)
+                .join("release")
+                .join("aingle_wasmer_test_fixtures.wasm");
+            std::fs::copy(&wasm, out_dir.join(format!("{}.wasm", module)))
+                .unwrap_or_else(|e| panic!("failed to copy {}: {}", wasm.display(), e));
+        }
+    }
+
+    fn build_module(manifest_dir: &Path, target_dir: &Path, features: &str, rustflags: &str) {
+        let status = Command::new(std::env::var_os("CARGO").unwrap())
+            .args(["build", "--release", "--target", TARGET])
+            .arg("--manifest-path")
+            .arg(manifest_dir.join("Cargo.toml"))
+            .args(["--features", features])
+            .arg("--target-dir")
+            .arg(target_dir)
+            // Flags of the outer build are meant for the host target
+            .env_remove("CARGO_ENCODED_RUSTFLAGS")
+            .env("RUSTFLAGS", rustflags)
+            .status()
+            .expect("failed to run cargo");
+        assert!(
+            status.success(),
+            "building the fixture guest failed; is the {} target installed?",
+            TARGET
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! The exports of the fixture guest
+
+use aingle_wasmer_guest::{
+    aingle_entry, arena_high_water_mark, arena_try_alloc, host_args_envelope, register_warmup,
+    return_err_typed, return_ok, set_panic_hook, try_result_raw, ErrorKind, WasmError,
+    WasmErrorInner,
+};
+use std::sync::OnceLock;
+
+/// Table `lazy_table` reads, slow to build on purpose
+static TABLE: OnceLock<[u64; 256]> = OnceLock::new();
+
+/// Rounds of mixing per entry of [`TABLE`], tens of milliseconds in all
+const TABLE_ROUNDS: u32 = 100_000;
+
+fn build_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    for (index, entry) in table.iter_mut().enumerate() {
+        let mut state = index as u64;
+        for _ in 0..TABLE_ROUNDS {
+            // splitmix64
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut mixed = state;
+            mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            state ^= mixed ^ (mixed >> 31);
+        }
+        *entry = state;
+    }
+    table
+}
+
+fn warm_up() -> Result<(), WasmError> {
+    TABLE.get_or_init(build_table);
+    Ok(())
+}
+
+register_warmup!(warm_up);
+
+/// A 4-byte little-endian count at the start of `input`, with the rest
+fn split_count(input: &[u8]) -> Result<(u32, &[u8]), WasmError> {
+    match input.split_first_chunk::<4>() {
+        Some((count, rest)) => Ok((u32::from_le_bytes(*count), rest)),
+        None => Err(invalid_input("input must start with a 4-byte count")),
+    }
+}
+
+fn invalid_input(message: &str) -> WasmError {
+    WasmError::GuestStructured(WasmErrorInner::new(ErrorKind::InvalidInput, message))
+}
+
+/// Return the input
+#[no_mangle]
+pub extern "C" fn echo(ptr: u32, len: u32) -> u64 {
+    let input = try_result_raw!(host_args_envelope(ptr, len));
+    return_ok(input)
+}
+
+/// Return the input after a 4-byte count, repeated count times
+///
+/// Small inputs give large results, without the host writing large inputs.
+#[no_mangle]
+pub extern "C" fn echo_large(ptr: u32, len: u32) -> u64 {
+    let input = try_result_raw!(host_args_envelope(ptr, len));
+    match split_count(input) {
+        Ok((count, chunk)) => return_ok(&chunk.repeat(count as usize)),
+        Err(error) => return_err_typed(&error),
+    }
+}
+
+/// Spin until metering stops the guest
+#[no_mangle]
+pub extern "C" fn loop_forever(_ptr: u32, _len: u32) -> u64 {
+    let mut turns = 0u64;
+    loop {
+        turns = core::hint::black_box(turns.wrapping_add(1));
+    }
+}
+
+/// Panic with the input as the message
+///
+/// The panic hook publishes the message, so the host recovers it from the
+/// trap the panic ends in.
+#[no_mangle]
+pub extern "C" fn panic_with_message(ptr: u32, len: u32) -> u64 {
+    set_panic_hook();
+    let input = try_result_raw!(host_args_envelope(ptr, len));
+    panic!("{}", String::from_utf8_lossy(input));
+}
+
+/// Allocate the 4-byte count of the input in bytes from the arena and fill
+/// them, returning the arena high-water mark as 8 little-endian bytes
+#[no_mangle]
+pub extern "C" fn alloc_n_bytes(ptr: u32, len: u32) -> u64 {
+    let input = try_result_raw!(host_args_envelope(ptr, len));
+    let (count, _) = match split_count(input) {
+        Ok(split) => split,
+        Err(error) => return return_err_typed(&error),
+    };
+    match arena_try_alloc(count as usize) {
+        Ok(allocation) => {
+            // Touch the memory, so the guest's memory grows for real
+            unsafe { core::ptr::write_bytes(allocation, 0xa5, count as usize) };
+            return_ok(&(arena_high_water_mark() as u64).to_le_bytes())
+        }
+        Err(error) => return_err_typed(&WasmError::Memory(error)),
+    }
+}
+
+/// Return the xor of the entries of the lazily built table, as 8
+/// little-endian bytes
+///
+/// The warm-up builds the table, unless the host skips it, in which case
+/// the first call does.
+#[no_mangle]
+pub extern "C" fn lazy_table(_ptr: u32, _len: u32) -> u64 {
+    let table = TABLE.get_or_init(build_table);
+    let folded = table.iter().fold(0, |acc, entry| acc ^ entry);
+    return_ok(&folded.to_le_bytes())
+}
+
+/// Fail with the error kind the single byte of input names
+#[no_mangle]
+pub extern "C" fn return_error_kind(ptr: u32, len: u32) -> u64 {
+    let input = try_result_raw!(host_args_envelope(ptr, len));
+    let error = match input {
+        [kind] => WasmError::GuestStructured(WasmErrorInner::new(
+            ErrorKind::from_u8(*kind),
+            "error of the requested kind",
+        )),
+        _ => invalid_input("input must be a single error kind byte"),
+    };
+    return_err_typed(&error)
+}
+
+/// Greet the name given as a MessagePack string, failing on an empty one
+#[aingle_entry]
+fn greet(name: String) -> Result<String, WasmError> {
+    if name.is_empty() {
+        return Err(invalid_input("nobody to greet"));
+    }
+    Ok(format!("hello {}", name))
+}
+
+/// Add up the MessagePack array of numbers given, failing on an empty one
+fn add_up(numbers: Vec<u32>) -> Result<u64, WasmError> {
+    if numbers.is_empty() {
+        return Err(invalid_input("nothing to add up"));
+    }
+    Ok(numbers.into_iter().map(u64::from).sum())
+}
+
+aingle_wasmer_guest::map_extern!(sum, add_up, Vec<u32>, u64);
+
+#[cfg(feature = "host-calls")]
+mod host_calls {
+    use super::split_count;
+    use aingle_wasmer_guest::{
+        host_args_envelope, host_call_enveloped, return_err_typed, return_ok, try_result_raw,
+    };
+
+    aingle_wasmer_guest::host_externs!(host_echo);
+
+    /// Send the input to the `host_echo` host function and return its
+    /// response
+    #[no_mangle]
+    pub extern "C" fn call_host_echo(ptr: u32, len: u32) -> u64 {
+        let input = try_result_raw!(host_args_envelope(ptr, len));
+        match host_call_enveloped::<_, Vec<u8>>(host_echo, input.to_vec()) {
+            Ok(response) => return_ok(&response),
+            Err(error) => return_err_typed(&error),
+        }
+    }
+
+    /// Send the input after a 4-byte count to `host_echo` count times,
+    /// failing with the first error, and return the number of calls as 4
+    /// little-endian bytes
+    #[no_mangle]
+    pub extern "C" fn chatty(ptr: u32, len: u32) -> u64 {
+        let input = try_result_raw!(host_args_envelope(ptr, len));
+        let (count, message) = match split_count(input) {
+            Ok(split) => split,
+            Err(error) => return return_err_typed(&error),
+        };
+        for _ in 0..count {
+            if let Err(error) = host_call_enveloped::<_, Vec<u8>>(host_echo, message.to_vec()) {
+                return return_err_typed(&error);
+            }
+        }
+        return_ok(&count.to_le_bytes())
+    }
+}
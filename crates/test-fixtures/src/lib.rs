@@ -0,0 +1,84 @@
+//! # AIngle WASM Test Fixtures
+//!
+//! A guest built on `aingle_wasmer_guest` exporting the functions host
+//! integration tests run on, so they call real Rust guests instead of hand
+//! written WAT:
+//!
+//! | Fixture | Behavior |
+//! |---|---|
+//! | `echo` | returns its input |
+//! | `echo_large` | returns its input after a 4-byte little-endian count, that many times over |
+//! | `loop_forever` | never returns, until metering stops it |
+//! | `panic_with_message` | panics with its input as the message |
+//! | `alloc_n_bytes` | allocates a 4-byte little-endian count of bytes in the arena |
+//! | `call_host_echo` | returns what the `host_echo` host function returns for its input |
+//! | `return_error_kind` | fails with the [`ErrorKind`](aingle_wasmer_guest::ErrorKind) its one byte of input names |
+//! | `lazy_table` | returns 8 bytes read from a table the first call builds, unless the warm-up did |
+//! | `chatty` | sends its input after a 4-byte little-endian count to `host_echo`, that many times over |
+//! | `greet` | greets the MessagePack string it takes, through `#[aingle_entry]` |
+//! | `sum` | adds up the MessagePack array of numbers it takes, through `map_extern!` |
+//!
+//! `call_host_echo` and `chatty` are built into a module of their own, as
+//! they import `env.host_echo` and the rest instantiate without imports.
+//! Both modules export the `__aingle_warmup` building the table of
+//! `lazy_table`. The rest are built once more linked with
+//! `--import-memory`, importing `env.memory` instead of exporting their
+//! memory.
+//!
+//! Built for the host with the `fixtures` feature, the crate builds itself
+//! for wasm32-unknown-unknown and carries the modules in [`FIXTURES`]:
+//!
+//! ```text
+//! rustup target add wasm32-unknown-unknown
+//! cargo test -p aingle_wasmer_host --features fixtures --test fixtures
+//! ```
+
+#![warn(missing_docs)]
+
+#[cfg(target_arch = "wasm32")]
+mod guest;
+
+/// The guest module without host imports
+#[cfg(all(feature = "fixtures", not(target_arch = "wasm32")))]
+pub const GUEST: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/guest.wasm"));
+
+/// The guest module built with the `host-calls` feature, importing
+/// `env.host_echo`
+#[cfg(all(feature = "fixtures", not(target_arch = "wasm32")))]
+pub const GUEST_HOST_CALLS: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/guest_host_calls.wasm"));
+
+/// The guest module without host imports, linked to import its memory as
+/// `env.memory` instead of exporting it
+#[cfg(all(feature = "fixtures", not(target_arch = "wasm32")))]
+pub const GUEST_IMPORTED_MEMORY: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/guest_imported_memory.wasm"));
+
+/// Every fixture with the module exporting it, by export name
+#[cfg(all(feature = "fixtures", not(target_arch = "wasm32")))]
+pub const FIXTURES: &[(&str, &[u8])] = &[
+    ("echo", GUEST),
+    ("echo_large", GUEST),
+    ("loop_forever", GUEST),
+    ("panic_with_message", GUEST),
+    ("alloc_n_bytes", GUEST),
+    ("call_host_echo", GUEST_HOST_CALLS),
+    ("return_error_kind", GUEST),
+    ("lazy_table", GUEST),
+    ("chatty", GUEST_HOST_CALLS),
+    ("greet", GUEST),
+    ("sum", GUEST),
+];
+
+/// The module exporting the fixture `name`
+#[cfg(all(feature = "fixtures", not(target_arch = "wasm32")))]
+pub fn fixture(name: &str) -> Option<&'static [u8]> {
+    FIXTURES
+        .iter()
+        .find(|(fixture, _)| *fixture == name)
+        .map(|(_, wasm)| *wasm)
+}
+
+/// Name of the host function `call_host_echo` and `chatty` call, taking
+/// and returning a `Vec<u8>`
+pub const HOST_ECHO: &str = "host_echo";
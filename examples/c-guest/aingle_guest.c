@@ -0,0 +1,167 @@
+/* Guest support layer for guests written in C, see aingle_guest.h */
+
+#include "aingle_guest.h"
+
+static _Alignas(8) uint8_t arena[AINGLE_ARENA_SIZE];
+static uint32_t arena_used;
+static bool call_returned;
+
+void *aingle_alloc(uint32_t len) {
+    if (call_returned) {
+        arena_used = 0;
+        call_returned = false;
+    }
+    uint32_t start = (arena_used + 7) & ~7u;
+    if (len > AINGLE_ARENA_SIZE - start) {
+        return 0;
+    }
+    arena_used = start + len;
+    return &arena[start];
+}
+
+/* Allocator exports the host writes inputs through */
+
+AINGLE_EXPORT("__hc__allocate_1")
+int32_t hc_allocate(int32_t len) {
+    if (len < 0) {
+        return 0;
+    }
+    return (int32_t)(uintptr_t)aingle_alloc((uint32_t)len);
+}
+
+/* Memory is reclaimed when the arena is reset */
+AINGLE_EXPORT("__hc__deallocate_1")
+void hc_deallocate(int32_t ptr, int32_t len) {
+    (void)ptr;
+    (void)len;
+}
+
+uint32_t aingle_crc32(const uint8_t *data, uint32_t len) {
+    /* Bitwise rather than table driven, to keep the module small */
+    uint32_t crc = 0xffffffffu;
+    for (uint32_t i = 0; i < len; i++) {
+        crc ^= data[i];
+        for (int bit = 0; bit < 8; bit++) {
+            crc = (crc >> 1) ^ (0xedb88320u & (0u - (crc & 1)));
+        }
+    }
+    return ~crc;
+}
+
+static uint32_t read_u32(const uint8_t *bytes) {
+    return (uint32_t)bytes[0] | (uint32_t)bytes[1] << 8 | (uint32_t)bytes[2] << 16 |
+           (uint32_t)bytes[3] << 24;
+}
+
+static void write_u32(uint8_t *bytes, uint32_t value) {
+    for (int i = 0; i < 4; i++) {
+        bytes[i] = (uint8_t)(value >> (8 * i));
+    }
+}
+
+/* The functions declared by aingle_wasmer.h */
+
+uint64_t aingle_result_ok(uint32_t ptr, uint32_t len) {
+    return (uint64_t)ptr << 32 | len;
+}
+
+uint64_t aingle_result_err(uint32_t ptr, uint32_t len) {
+    return aingle_result_ok(ptr, len) | AINGLE_RESULT_ERROR_BIT;
+}
+
+bool aingle_result_is_err(uint64_t result) {
+    return (result & AINGLE_RESULT_ERROR_BIT) != 0;
+}
+
+uint32_t aingle_result_ptr(uint64_t result) {
+    uint32_t ptr = (uint32_t)(result >> 32) & 0x7fffffffu;
+    /* Errors without payload may carry their kind in bits 24-30 */
+    if (aingle_result_is_err(result) && aingle_result_len(result) == 0) {
+        ptr &= 0x00ffffffu;
+    }
+    return ptr;
+}
+
+uint32_t aingle_result_len(uint64_t result) {
+    return (uint32_t)result;
+}
+
+void aingle_envelope_header_encode(uint32_t payload_len, uint32_t checksum, uint8_t flags,
+                                   uint8_t *out) {
+    out[AINGLE_ENVELOPE_MAGIC_OFFSET] = AINGLE_MAGIC & 0xff;
+    out[AINGLE_ENVELOPE_MAGIC_OFFSET + 1] = AINGLE_MAGIC >> 8;
+    out[AINGLE_ENVELOPE_VERSION_OFFSET] = AINGLE_PROTOCOL_VERSION;
+    out[AINGLE_ENVELOPE_FLAGS_OFFSET] = flags;
+    write_u32(out + AINGLE_ENVELOPE_PAYLOAD_LEN_OFFSET, payload_len);
+    write_u32(out + AINGLE_ENVELOPE_CHECKSUM_OFFSET, checksum);
+}
+
+bool aingle_envelope_header_decode(const uint8_t *bytes, uint32_t len,
+                                   struct AingleEnvelopeHeader *out) {
+    if (len < AINGLE_ENVELOPE_HEADER_SIZE) {
+        return false;
+    }
+    uint16_t magic = (uint16_t)(bytes[AINGLE_ENVELOPE_MAGIC_OFFSET] |
+                                bytes[AINGLE_ENVELOPE_MAGIC_OFFSET + 1] << 8);
+    uint8_t version = bytes[AINGLE_ENVELOPE_VERSION_OFFSET];
+    if (magic != AINGLE_MAGIC || version != AINGLE_PROTOCOL_VERSION) {
+        return false;
+    }
+    out->magic = magic;
+    out->version = version;
+    out->flags = bytes[AINGLE_ENVELOPE_FLAGS_OFFSET];
+    out->payload_len = read_u32(bytes + AINGLE_ENVELOPE_PAYLOAD_LEN_OFFSET);
+    out->checksum = read_u32(bytes + AINGLE_ENVELOPE_CHECKSUM_OFFSET);
+    return true;
+}
+
+/* Input and results */
+
+bool aingle_input(uint32_t ptr, uint32_t len, const uint8_t **payload, uint32_t *payload_len) {
+    const uint8_t *bytes = (const uint8_t *)(uintptr_t)ptr;
+    struct AingleEnvelopeHeader header;
+    if (!aingle_envelope_header_decode(bytes, len, &header) ||
+        header.payload_len > len - AINGLE_ENVELOPE_HEADER_SIZE) {
+        return false;
+    }
+    const uint8_t *data = bytes + AINGLE_ENVELOPE_HEADER_SIZE;
+    if (aingle_crc32(data, header.payload_len) != header.checksum) {
+        return false;
+    }
+    *payload = data;
+    *payload_len = header.payload_len;
+    return true;
+}
+
+static uint64_t frame(const uint8_t *payload, uint32_t len, uint8_t flags) {
+    uint8_t *out = 0;
+    if (len <= AINGLE_ARENA_SIZE - AINGLE_ENVELOPE_HEADER_SIZE) {
+        out = aingle_alloc(AINGLE_ENVELOPE_HEADER_SIZE + len);
+    }
+    if (!out) {
+        /* An error without payload */
+        call_returned = true;
+        return AINGLE_RESULT_ERROR_BIT;
+    }
+    aingle_envelope_header_encode(len, aingle_crc32(payload, len), flags, out);
+    for (uint32_t i = 0; i < len; i++) {
+        out[AINGLE_ENVELOPE_HEADER_SIZE + i] = payload[i];
+    }
+    call_returned = true;
+    uint32_t ptr = (uint32_t)(uintptr_t)out;
+    uint32_t total = AINGLE_ENVELOPE_HEADER_SIZE + len;
+    return (flags & AINGLE_FLAG_IS_ERROR) != 0 ? aingle_result_err(ptr, total)
+                                                : aingle_result_ok(ptr, total);
+}
+
+uint64_t aingle_return_ok(const uint8_t *payload, uint32_t len) {
+    return frame(payload, len, 0);
+}
+
+uint64_t aingle_return_err(const char *message) {
+    uint32_t len = 0;
+    while (message[len] != '\0') {
+        len++;
+    }
+    return frame((const uint8_t *)message, len, AINGLE_FLAG_IS_ERROR);
+}
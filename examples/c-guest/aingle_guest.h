@@ -0,0 +1,74 @@
+/*
+ * Guest support layer for guests written in C
+ *
+ * Implements the allocator exports the host calls and the envelope framing
+ * of inputs and results, on top of aingle_wasmer.h, the header generated by
+ * the `capi` feature of aingle_wasmer_common. The aingle_result_* and
+ * aingle_envelope_header_* functions that header declares are implemented
+ * in aingle_guest.c, so guests need no Rust toolchain.
+ *
+ * Guest functions take the (ptr, len) of their input envelope and return a
+ * packed result:
+ *
+ *     AINGLE_EXPORT("echo")
+ *     uint64_t echo(uint32_t ptr, uint32_t len) {
+ *         const uint8_t *payload;
+ *         uint32_t payload_len;
+ *         if (!aingle_input(ptr, len, &payload, &payload_len))
+ *             return aingle_return_err("malformed input");
+ *         return aingle_return_ok(payload, payload_len);
+ *     }
+ *
+ * Hosts write the input of plain calls at offset 1024, so the stack goes
+ * first in memory with the size Rust guests have, and the input takes its
+ * far end:
+ *
+ *     clang --target=wasm32-unknown-unknown -nostdlib -fno-builtin -O2 \
+ *         -Wl,--no-entry -Wl,--stack-first -Wl,-z,stack-size=1048576 \
+ *         -I <dir of aingle_wasmer.h> aingle_guest.c guest.c -o guest.wasm
+ *
+ * -fno-builtin keeps clang from calling a memcpy there is no libc for.
+ */
+
+#ifndef AINGLE_GUEST_H
+#define AINGLE_GUEST_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#include "aingle_wasmer.h"
+
+/* Bytes of the arena backing guest and host allocations */
+#ifndef AINGLE_ARENA_SIZE
+#define AINGLE_ARENA_SIZE (64 * 1024)
+#endif
+
+/* Export a guest function under `name` */
+#define AINGLE_EXPORT(name) __attribute__((export_name(name)))
+
+/*
+ * Allocate `len` bytes from the arena, or return NULL once it is exhausted
+ *
+ * The arena is reset by the first allocation after a result was returned,
+ * which the host has read by then.
+ */
+void *aingle_alloc(uint32_t len);
+
+/* IEEE CRC32 of `len` bytes, the default envelope checksum */
+uint32_t aingle_crc32(const uint8_t *data, uint32_t len);
+
+/*
+ * Check the input envelope at (ptr, len) and point `payload` at its payload
+ *
+ * Returns false for malformed envelopes and checksum mismatches. Envelopes
+ * are checked as CRC32; hosts only send CRC32C to guests asking for it.
+ */
+bool aingle_input(uint32_t ptr, uint32_t len, const uint8_t **payload, uint32_t *payload_len);
+
+/* Return `len` bytes at `payload` as the result, framed in an envelope */
+uint64_t aingle_return_ok(const uint8_t *payload, uint32_t len);
+
+/* Return `message` as an error, read by the host as a guest error */
+uint64_t aingle_return_err(const char *message);
+
+#endif /* AINGLE_GUEST_H */
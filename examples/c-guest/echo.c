@@ -0,0 +1,30 @@
+/* Guest written in C, called by the host's c_guest test */
+
+#include "aingle_guest.h"
+
+/* Return the input as it is */
+AINGLE_EXPORT("echo")
+uint64_t echo(uint32_t ptr, uint32_t len) {
+    const uint8_t *payload;
+    uint32_t payload_len;
+    if (!aingle_input(ptr, len, &payload, &payload_len)) {
+        return aingle_return_err("malformed input");
+    }
+    return aingle_return_ok(payload, payload_len);
+}
+
+/* Fail with the input as the message */
+AINGLE_EXPORT("fail")
+uint64_t fail(uint32_t ptr, uint32_t len) {
+    const uint8_t *payload;
+    uint32_t payload_len;
+    if (!aingle_input(ptr, len, &payload, &payload_len) || payload_len > 255) {
+        return aingle_return_err("malformed input");
+    }
+    char message[256];
+    for (uint32_t i = 0; i < payload_len; i++) {
+        message[i] = (char)payload[i];
+    }
+    message[payload_len] = '\0';
+    return aingle_return_err(message);
+}
@@ -0,0 +1,14 @@
+#! /usr/bin/env bash
+
+# Size of the guest crate built for wasm32 with the default allocator, with
+# arena-global-alloc and with min-size; per-call allocation cost is covered by
+# `cargo bench -p aingle_wasmer_guest --features arena-global-alloc`, and the
+# example guest's `size` test compares min-size builds
+set -euo pipefail
+
+wasm=target/wasm32-unknown-unknown/release/aingle_wasmer_guest.wasm
+
+for features in "" "arena-global-alloc" "min-size"; do
+  cargo build -p aingle_wasmer_guest --release --target wasm32-unknown-unknown --features "$features"
+  echo "${features:-default allocator}: $(wc -c < "$wasm") bytes"
+done
@@ -0,0 +1,11 @@
+#!/usr/bin/env bash
+set -euxo pipefail
+
+export RUST_BACKTRACE=full
+
+# the host builds for the browser
+cargo build -p aingle_wasmer_host --target wasm32-unknown-unknown --no-default-features --features wasmer_js
+
+# runs the host tests on wasmer's js API under Node.js
+CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUNNER=wasm-bindgen-test-runner \
+  cargo test -p aingle_wasmer_host --target wasm32-unknown-unknown --no-default-features --features wasmer_js --test js ${1-}
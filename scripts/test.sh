@@ -2,4 +2,5 @@
 
 ./scripts/test-wasmer_sys_dev.sh
 ./scripts/test-wasmer_sys_prod.sh
-./scripts/test-wasmer_wamr.sh
\ No newline at end of file
+./scripts/test-wasmer_wamr.sh
+./scripts/test-wasmer_js.sh
\ No newline at end of file